@@ -0,0 +1,167 @@
+use budlum_core::account::AccountState;
+use budlum_core::consensus::{ConsensusEngine, ConsensusError};
+use budlum_core::crypto::KeyPair;
+use budlum_core::{Block, Blockchain, Transaction};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::sync::Arc;
+
+const TXS_PER_BLOCK: usize = 4;
+const SYNC_BLOCKS: usize = 10_000;
+
+struct BenchEngine;
+
+impl ConsensusEngine for BenchEngine {
+    fn prepare_block(
+        &self,
+        block: &mut Block,
+        _state: &AccountState,
+    ) -> Result<(), ConsensusError> {
+        block.hash = block.calculate_hash();
+        Ok(())
+    }
+    fn validate_block(
+        &self,
+        _block: &Block,
+        _chain: &[Block],
+        _state: &AccountState,
+    ) -> Result<(), ConsensusError> {
+        Ok(())
+    }
+    fn full_validate(
+        &self,
+        _block: &Block,
+        _chain: &[Block],
+        _state: &AccountState,
+        _now: u128,
+    ) -> Result<(), ConsensusError> {
+        Ok(())
+    }
+    fn consensus_type(&self) -> &'static str {
+        "Bench"
+    }
+    fn info(&self) -> String {
+        "Bench engine (no validation)".to_string()
+    }
+}
+
+fn new_chain(senders: &[KeyPair]) -> Blockchain {
+    let mut chain = Blockchain::new(Arc::new(BenchEngine), None, 1337, None);
+    for kp in senders {
+        chain.init_genesis_account(&kp.public_key_hex());
+    }
+    chain
+}
+
+fn signed_transfer(kp: &KeyPair, nonce: u64) -> Transaction {
+    let mut tx = Transaction::new_with_fee(
+        kp.public_key_hex(),
+        "recipient".to_string(),
+        10,
+        1,
+        nonce,
+        vec![],
+    );
+    tx.sign(kp);
+    tx
+}
+
+fn build_chain(senders: &[KeyPair], blocks: usize) -> Vec<Block> {
+    let mut source = new_chain(senders);
+    for height in 0..blocks {
+        for kp in senders {
+            source
+                .add_transaction(signed_transfer(kp, height as u64))
+                .expect("bench tx rejected");
+        }
+        source.produce_block("producer".to_string());
+    }
+    source.chain.split_off(1)
+}
+
+fn senders(count: usize) -> Vec<KeyPair> {
+    (0..count).map(|_| KeyPair::generate().unwrap()).collect()
+}
+
+fn bench_tx_verify(c: &mut Criterion) {
+    let kp = KeyPair::generate().unwrap();
+    let tx = signed_transfer(&kp, 0);
+    c.bench_function("tx_signature_verify", |b| b.iter(|| assert!(tx.verify())));
+}
+
+fn bench_apply_transaction(c: &mut Criterion) {
+    let kp = KeyPair::generate().unwrap();
+    let tx = signed_transfer(&kp, 0);
+    let mut state = AccountState::new();
+    state.add_balance(&kp.public_key_hex(), 1_000_000);
+    c.bench_function("apply_transaction", |b| {
+        b.iter_batched(
+            || state.clone(),
+            |mut s| s.apply_transaction(&tx).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_state_root(c: &mut Criterion) {
+    let mut state = AccountState::new();
+    for i in 0..10_000u64 {
+        state.add_balance(&format!("{:064x}", i), i + 1);
+    }
+    c.bench_function("state_root_10k_accounts", |b| {
+        b.iter(|| state.calculate_state_root())
+    });
+}
+
+fn bench_block_serialization(c: &mut Criterion) {
+    let kps = senders(TXS_PER_BLOCK);
+    let block = build_chain(&kps, 1).remove(0);
+    let bytes = serde_json::to_vec(&block).unwrap();
+    c.bench_function("block_serialize", |b| {
+        b.iter(|| serde_json::to_vec(&block).unwrap())
+    });
+    c.bench_function("block_deserialize", |b| {
+        b.iter(|| serde_json::from_slice::<Block>(&bytes).unwrap())
+    });
+}
+
+fn bench_block_import(c: &mut Criterion) {
+    let kps = senders(TXS_PER_BLOCK);
+    let block = build_chain(&kps, 1).remove(0);
+    c.bench_function("block_import", |b| {
+        b.iter_batched(
+            || new_chain(&kps),
+            |mut chain| chain.validate_and_add_block(block.clone()).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_sync(c: &mut Criterion) {
+    let kps = senders(TXS_PER_BLOCK);
+    let blocks = build_chain(&kps, SYNC_BLOCKS);
+    let mut group = c.benchmark_group("sync");
+    group.sample_size(10);
+    group.bench_function("sync_10k_blocks", |b| {
+        b.iter_batched(
+            || (new_chain(&kps), blocks.clone()),
+            |(mut chain, blocks)| {
+                for block in blocks {
+                    chain.validate_and_add_block(block).unwrap();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_tx_verify,
+    bench_apply_transaction,
+    bench_state_root,
+    bench_block_serialization,
+    bench_block_import,
+    bench_sync
+);
+criterion_main!(benches);
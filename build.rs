@@ -1,7 +1,41 @@
 fn main() {
     println!("cargo:rerun-if-changed=proto/protocol.proto");
+    println!("cargo:rerun-if-changed=proto/rpc.proto");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    // HEAD only changes on checkout; a commit moves the branch ref, which is
+    // either a loose file or an entry in packed-refs. Paths that don't exist
+    // are skipped, since cargo would otherwise rerun this script every build.
+    let branch_ref = std::fs::read_to_string(".git/HEAD")
+        .ok()
+        .and_then(|head| head.strip_prefix("ref: ").map(|r| r.trim().to_string()));
+    for path in branch_ref
+        .map(|r| format!(".git/{}", r))
+        .into_iter()
+        .chain([".git/packed-refs".to_string()])
+    {
+        if std::path::Path::new(&path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    let commit = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=BUDLUM_GIT_COMMIT={}", commit);
 
     prost_build::Config::new()
         .compile_protos(&["proto/protocol.proto"], &["proto/"])
         .expect("Failed to compile Protobuf schemas");
+
+    #[cfg(feature = "rpc")]
+    tonic_prost_build::configure()
+        .extern_path(".budlum.network", "crate::network::proto_conversions::pb")
+        .compile_protos(&["proto/rpc.proto"], &["proto/"])
+        .expect("Failed to compile gRPC service definitions");
 }
@@ -1,11 +1,24 @@
-use crate::consensus::pos::SlashingEvidence;
+use crate::blockchain::EPOCH_LENGTH;
+use crate::bridge::{ForeignChain, SignedInbound, BRIDGE_ESCROW};
+use crate::chain_config::{ChainLimits, ForkSchedule};
+use crate::consensus::finality::{FinalityCert, ValidatorEntry, ValidatorSetSnapshot};
+use crate::consensus::pos::{SlashingEvidence, EVIDENCE_MAX_AGE_EPOCHS};
+use crate::dust::{sweep_root, DustProof, DustSweepConfig, SweptAccount};
+use crate::genesis::{GenesisConfig, RewardConfig, TreasuryConfig};
+use crate::hash::{hash_fields, hash_pair, merkle_branches, merkle_root, merkle_root_from_branch};
+use crate::light_client::{LightClient, LightClientMessage};
+use crate::randao::{mix_reveal, verify_reveal};
+use crate::slashing::SlashingEvidence as VoteEvidence;
 use crate::storage::Storage;
-use crate::transaction::{Transaction, TransactionType};
+use crate::transaction::{AuthorityVote, Transaction, TransactionType};
+use crate::validator_stats::{ValidatorInfo, ValidatorPerformance, EXPECTED_BLOCK_UNITS};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 pub const MIN_TX_FEE: u64 = 1;
 pub const GENESIS_BALANCE: u64 = 1_000_000_000;
 pub const UNBONDING_EPOCHS: u64 = 7;
+pub const REPORTER_REWARD_PERCENT: u64 = 10;
+pub const MAX_REDELEGATIONS: usize = 7;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnbondingEntry {
     pub address: String,
@@ -13,11 +26,73 @@ pub struct UnbondingEntry {
     pub release_epoch: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Stays open until `completion_epoch` so the source validator's offences
+// from before the move still reach the redelegated stake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedelegationEntry {
+    pub delegator: String,
+    pub source: String,
+    pub destination: String,
+    pub amount: u64,
+    pub completion_epoch: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochRewardReceipt {
+    pub epoch: u64,
+    pub minted: u64,
+    pub rewards: Vec<(String, u64)>,
+    pub treasury: u64,
+}
+
+// Whether the validator set the chain is running on can still finalize:
+// enough of its members must be neither slashed nor jailed to reach
+// `min_validators` and to hold a finality quorum of the set's stake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidatorSetSafety {
+    pub epoch: u64,
+    pub validators: usize,
+    pub eligible_validators: usize,
+    pub min_validators: usize,
+    pub eligible_stake: u64,
+    pub quorum_stake: u64,
+    pub safe: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreasurySource {
+    Fees,
+    Rewards,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryCredit {
+    pub source: TreasurySource,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakingRules {
+    pub min_stake: u64,
+    pub max_validators: usize,
+}
+impl StakingRules {
+    pub fn is_unrestricted(&self) -> bool {
+        self.min_stake == 0 && self.max_validators == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
     pub public_key: String,
     pub balance: u64,
     pub nonce: u64,
+    // Stake this account has bonded, by the validator it backs (its own
+    // address for self-stake). These coins left `balance` when they were
+    // staked and only come back through the unbonding queue.
+    #[serde(default)]
+    pub locked: BTreeMap<String, u64>,
 }
 impl Account {
     pub fn new(public_key: String) -> Self {
@@ -25,6 +100,7 @@ impl Account {
             public_key,
             balance: 0,
             nonce: 0,
+            locked: BTreeMap::new(),
         }
     }
     pub fn with_balance(public_key: String, balance: u64) -> Self {
@@ -32,10 +108,14 @@ impl Account {
             public_key,
             balance,
             nonce: 0,
+            locked: BTreeMap::new(),
         }
     }
+    pub fn total_locked(&self) -> u64 {
+        self.locked.values().sum()
+    }
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Validator {
     pub address: String,
     pub stake: u64,
@@ -47,6 +127,26 @@ pub struct Validator {
     pub votes_for: u64,
     pub votes_against: u64,
     pub vrf_public_key: Vec<u8>,
+    #[serde(default)]
+    pub accrued_rewards: u64,
+    #[serde(default)]
+    pub missed_slots: u64,
+    // Set for a double sign. Unlike a downtime jail this is permanent: the
+    // validator is never released or unjailed and can only withdraw.
+    #[serde(default)]
+    pub tombstoned: bool,
+    // Hash-onion layer the validator's next RANDAO reveal must hash to.
+    #[serde(default)]
+    pub randao_commitment: Vec<u8>,
+    // Which of the validator's hash onions the commitment belongs to.
+    #[serde(default)]
+    pub randao_generation: u64,
+    // Finality vote key and its proof of possession; certificates only
+    // count signers that have one.
+    #[serde(default)]
+    pub bls_public_key: Vec<u8>,
+    #[serde(default)]
+    pub bls_pop: Vec<u8>,
 }
 
 impl Validator {
@@ -62,20 +162,70 @@ impl Validator {
             votes_for: 0,
             votes_against: 0,
             vrf_public_key: Vec::new(),
+            accrued_rewards: 0,
+            missed_slots: 0,
+            tombstoned: false,
+            randao_commitment: Vec::new(),
+            randao_generation: 0,
+            bls_public_key: Vec::new(),
+            bls_pop: Vec::new(),
         }
     }
     pub fn effective_stake(&self) -> u64 {
-        if self.slashed || self.jailed {
+        if self.slashed || self.jailed || self.tombstoned {
             0
         } else {
             self.stake
         }
     }
     pub fn is_eligible(&self, current_block: u64) -> bool {
-        self.active && !self.slashed && (!self.jailed || current_block >= self.jail_until)
+        self.active
+            && !self.slashed
+            && !self.tombstoned
+            && (!self.jailed || current_block >= self.jail_until)
+    }
+    fn tombstone(&mut self) {
+        self.tombstoned = true;
+        self.jailed = true;
+        self.jail_until = u64::MAX;
+        self.active = false;
     }
 }
 
+pub const STATE_CHECKPOINT_KEY: &str = "STATE_CHECKPOINT";
+
+// Everything block execution evolves, written at finalized checkpoints so a
+// restart only replays the blocks after `height`. Staking rules, rewards,
+// treasury, dust sweeping, limits and forks come from the chain spec and
+// are not stored.
+#[derive(Serialize, Deserialize)]
+struct StateCheckpoint {
+    height: u64,
+    block_hash: String,
+    accounts: HashMap<String, Account>,
+    validators: HashMap<String, Validator>,
+    unbonding_queue: Vec<UnbondingEntry>,
+    epoch_index: u64,
+    last_epoch_time: u64,
+    frozen_validator_set: Option<ValidatorSetSnapshot>,
+    authority_votes: BTreeMap<AuthorityVote, BTreeSet<String>>,
+    last_block_timestamp: u128,
+    total_minted: u64,
+    total_burned: u64,
+    allowances: BTreeMap<String, BTreeMap<String, u64>>,
+    redelegations: Vec<RedelegationEntry>,
+    bridge_messages: BTreeSet<String>,
+    light_clients: BTreeMap<u64, LightClient>,
+    last_active: HashMap<String, u64>,
+    randao_mix: [u8; 32],
+    randao_seed: [u8; 32],
+    dust_sweeps: BTreeMap<u64, String>,
+    swept_balance: u64,
+    restored_dust: BTreeSet<String>,
+    performance: BTreeMap<String, ValidatorPerformance>,
+    processed_evidence: BTreeMap<String, u64>,
+}
+
 #[derive(Clone)]
 pub struct AccountState {
     pub accounts: HashMap<String, Account>,
@@ -84,6 +234,47 @@ pub struct AccountState {
     storage: Option<Storage>,
     pub epoch_index: u64,
     pub last_epoch_time: u64,
+    pub frozen_validator_set: Option<ValidatorSetSnapshot>,
+    pub staking_rules: StakingRules,
+    pub authority_votes: BTreeMap<AuthorityVote, BTreeSet<String>>,
+    pub last_block_timestamp: u128,
+    pub reward_config: RewardConfig,
+    pub total_minted: u64,
+    pub total_burned: u64,
+    pub allowances: BTreeMap<String, BTreeMap<String, u64>>,
+    pub redelegations: Vec<RedelegationEntry>,
+    // Ids of inbound bridge messages already executed.
+    pub bridge_messages: BTreeSet<String>,
+    // Remote Budlum chains followed through their finality certificates.
+    pub light_clients: BTreeMap<u64, LightClient>,
+    // Running mix of every RANDAO reveal so far, and its value at the start
+    // of the current epoch, which is what leader election reads.
+    pub randao_mix: [u8; 32],
+    pub randao_seed: [u8; 32],
+    pub treasury: TreasuryConfig,
+    pub treasury_credits: Vec<TreasuryCredit>,
+    pub dust_sweep: DustSweepConfig,
+    // Epoch in which each account was last created, debited or credited.
+    pub last_active: HashMap<String, u64>,
+    // Merkle root of the accounts swept at each epoch boundary.
+    pub dust_sweeps: BTreeMap<u64, String>,
+    // Coins held by swept accounts that have not been restored.
+    pub swept_balance: u64,
+    // Leaves of swept accounts already restored.
+    pub restored_dust: BTreeSet<String>,
+    // Proposal, vote and slash history of every validator seen so far,
+    // kept after a validator leaves the set.
+    pub performance: BTreeMap<String, ValidatorPerformance>,
+    // Keys of evidence already slashed, with the epoch of the offence. Kept
+    // until blocks would reject the evidence as expired anyway.
+    pub processed_evidence: BTreeMap<String, u64>,
+    // Accounts swept by the last epoch advance, for the chain to archive.
+    pub swept_dust: Vec<(u64, Vec<SweptAccount>)>,
+    // Every account written since `clear_touched`, with its value before
+    // the first write, so a block's state diff never copies the rest.
+    touched: HashMap<String, Option<Account>>,
+    pub limits: ChainLimits,
+    pub forks: ForkSchedule,
 }
 impl AccountState {
     pub fn new() -> Self {
@@ -94,6 +285,32 @@ impl AccountState {
             storage: None,
             epoch_index: 0,
             last_epoch_time: 0,
+            frozen_validator_set: None,
+            staking_rules: StakingRules::default(),
+            authority_votes: BTreeMap::new(),
+            last_block_timestamp: 0,
+            reward_config: RewardConfig::default(),
+            total_minted: 0,
+            total_burned: 0,
+            allowances: BTreeMap::new(),
+            redelegations: Vec::new(),
+            bridge_messages: BTreeSet::new(),
+            light_clients: BTreeMap::new(),
+            randao_mix: [0u8; 32],
+            randao_seed: [0u8; 32],
+            treasury: TreasuryConfig::default(),
+            treasury_credits: Vec::new(),
+            dust_sweep: DustSweepConfig::default(),
+            last_active: HashMap::new(),
+            dust_sweeps: BTreeMap::new(),
+            swept_balance: 0,
+            restored_dust: BTreeSet::new(),
+            performance: BTreeMap::new(),
+            processed_evidence: BTreeMap::new(),
+            swept_dust: Vec::new(),
+            touched: HashMap::new(),
+            limits: ChainLimits::default(),
+            forks: ForkSchedule::default(),
         }
     }
     pub fn with_storage(storage: Storage) -> Self {
@@ -104,6 +321,32 @@ impl AccountState {
             storage: Some(storage),
             epoch_index: 0,
             last_epoch_time: 0,
+            frozen_validator_set: None,
+            staking_rules: StakingRules::default(),
+            authority_votes: BTreeMap::new(),
+            last_block_timestamp: 0,
+            reward_config: RewardConfig::default(),
+            total_minted: 0,
+            total_burned: 0,
+            allowances: BTreeMap::new(),
+            redelegations: Vec::new(),
+            bridge_messages: BTreeSet::new(),
+            light_clients: BTreeMap::new(),
+            randao_mix: [0u8; 32],
+            randao_seed: [0u8; 32],
+            treasury: TreasuryConfig::default(),
+            treasury_credits: Vec::new(),
+            dust_sweep: DustSweepConfig::default(),
+            last_active: HashMap::new(),
+            dust_sweeps: BTreeMap::new(),
+            swept_balance: 0,
+            restored_dust: BTreeSet::new(),
+            performance: BTreeMap::new(),
+            processed_evidence: BTreeMap::new(),
+            swept_dust: Vec::new(),
+            touched: HashMap::new(),
+            limits: ChainLimits::default(),
+            forks: ForkSchedule::default(),
         };
         if let Err(e) = state.load_from_storage() {
             println!("Could not load account state: {}", e);
@@ -137,31 +380,377 @@ impl AccountState {
 
         crate::hash::calculate_hash(&prefix_bytes)
     }
+    // The state every replay starts from: the chain spec's parameters and
+    // its genesis allocations.
+    pub fn from_genesis(spec: &GenesisConfig) -> Self {
+        let mut state = AccountState::new();
+        state.apply_spec(spec);
+        for (address, amount) in &spec.allocations {
+            state.add_balance(address, *amount);
+        }
+        state
+    }
+    pub fn apply_spec(&mut self, spec: &GenesisConfig) {
+        self.reward_config = spec.staking_rewards;
+        self.treasury = spec.treasury.clone();
+        self.limits = spec.limits;
+        self.dust_sweep = spec.dust_sweep;
+        self.staking_rules = spec.staking;
+        // Specs are validated before a chain is opened.
+        self.forks = ForkSchedule::new(&spec.forks).unwrap_or_default();
+    }
     pub fn init_genesis(&mut self, genesis_pubkey: &str) {
         let account = Account::with_balance(genesis_pubkey.to_string(), GENESIS_BALANCE);
         self.accounts.insert(genesis_pubkey.to_string(), account);
+        self.total_minted += GENESIS_BALANCE;
         println!("Genesis account created: {} coins", GENESIS_BALANCE);
     }
+    pub fn total_supply(&self) -> u64 {
+        self.total_minted.saturating_sub(self.total_burned)
+    }
+    // Every coin the ledger holds: spendable balances, stake locked by
+    // accounts, unbonding stake, rewards not yet claimed and swept dust
+    // awaiting restore.
+    pub fn accounted_supply(&self) -> u64 {
+        let held: u64 = self
+            .accounts
+            .values()
+            .map(|a| a.balance + a.total_locked())
+            .sum();
+        let rewards: u64 = self.validators.values().map(|v| v.accrued_rewards).sum();
+        let unbonding: u64 = self.unbonding_queue.iter().map(|e| e.amount).sum();
+        held + rewards + unbonding + self.swept_balance
+    }
+    // Each validator's stake must be exactly what accounts have locked with
+    // it.
+    pub fn check_stake_locks(&self) -> Result<(), String> {
+        let mut locked: HashMap<&str, u64> = HashMap::new();
+        for account in self.accounts.values() {
+            for (validator, amount) in &account.locked {
+                *locked.entry(validator.as_str()).or_default() += amount;
+            }
+        }
+        for validator in self.validators.values() {
+            let total = locked.remove(validator.address.as_str()).unwrap_or(0);
+            if total != validator.stake {
+                return Err(format!(
+                    "Validator {} has stake {} but accounts locked {}",
+                    validator.address, validator.stake, total
+                ));
+            }
+        }
+        match locked.into_iter().find(|(_, amount)| *amount > 0) {
+            Some((validator, amount)) => Err(format!(
+                "{} locked with {}, which is not a validator",
+                amount, validator
+            )),
+            None => Ok(()),
+        }
+    }
+    // How far the ledger is from the minted minus burned supply. Blocks never
+    // change it; it is only non-zero when balances were seeded directly, as
+    // in tests.
+    pub fn supply_drift(&self) -> i128 {
+        self.accounted_supply() as i128 - self.total_minted as i128 + self.total_burned as i128
+    }
+    // Seeds a validator outside any transaction, e.g. from a checkpoint's
+    // validator set. Stake not already locked by delegators is locked as the
+    // validator's own.
     pub fn add_validator(&mut self, address: String, stake: u64) {
         let validator = Validator::new(address.clone(), stake);
-        self.validators.insert(address, validator);
+        self.validators.insert(address.clone(), validator);
+        let own = stake.saturating_sub(self.delegated_to(&address));
+        if own > 0 || self.locked_stake(&address, &address) > 0 {
+            let account = self.get_or_create(&address);
+            account.locked.remove(&address);
+            if own > 0 {
+                account.locked.insert(address.clone(), own);
+            }
+        }
+    }
+    // Stake locked with validators that are not in the state, e.g. ones left
+    // out of the validator set a checkpoint carried, comes back as inactive
+    // validators so the stake stays accounted for.
+    pub fn adopt_locked_validators(&mut self) {
+        let mut locked: BTreeMap<String, u64> = BTreeMap::new();
+        for account in self.accounts.values() {
+            for (validator, amount) in &account.locked {
+                if !self.validators.contains_key(validator) {
+                    *locked.entry(validator.clone()).or_default() += amount;
+                }
+            }
+        }
+        for (address, stake) in locked {
+            let mut validator = Validator::new(address.clone(), stake);
+            validator.active = false;
+            self.validators.insert(address, validator);
+        }
     }
     pub fn get_total_stake(&self) -> u64 {
-        self.validators
-            .values()
-            .filter(|v| v.active && !v.slashed)
-            .map(|v| v.stake)
-            .sum()
+        self.get_active_validators().iter().map(|v| v.stake).sum()
     }
     pub fn get_active_validators(&self) -> Vec<&Validator> {
         let mut validators: Vec<&Validator> = self
             .validators
             .values()
-            .filter(|v| v.active && !v.slashed)
+            .filter(|v| {
+                v.active && !v.slashed && !v.tombstoned && v.stake >= self.staking_rules.min_stake
+            })
             .collect();
         validators.sort_by(|a, b| a.address.cmp(&b.address));
         validators
     }
+    fn has_validator_slot(&self) -> bool {
+        self.staking_rules.max_validators == 0
+            || self.get_active_validators().len() < self.staking_rules.max_validators
+    }
+    // Runs at epoch boundaries: the largest stakes above the minimum fill
+    // the capped set, which brings back validators evicted earlier once
+    // there is room for them again.
+    pub fn enforce_staking_rules(&mut self) -> Vec<String> {
+        if self.staking_rules.is_unrestricted() {
+            return Vec::new();
+        }
+        let min_stake = self.staking_rules.min_stake;
+        let mut candidates: Vec<&Validator> = self
+            .validators
+            .values()
+            .filter(|v| {
+                !v.slashed && !v.tombstoned && !v.jailed && v.stake > 0 && v.stake >= min_stake
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.stake.cmp(&a.stake).then(a.address.cmp(&b.address)));
+        if self.staking_rules.max_validators > 0 {
+            candidates.truncate(self.staking_rules.max_validators);
+        }
+        let keep: std::collections::HashSet<String> =
+            candidates.into_iter().map(|v| v.address.clone()).collect();
+        let mut deactivated = Vec::new();
+        for validator in self.validators.values_mut() {
+            let active = keep.contains(&validator.address);
+            if validator.active && !active && !validator.slashed {
+                deactivated.push(validator.address.clone());
+            }
+            validator.active = active;
+        }
+        deactivated.sort();
+        for address in &deactivated {
+            println!(
+                "Validator {} deactivated: outside staking limits (min stake {}, max validators {})",
+                &address[..16.min(address.len())],
+                self.staking_rules.min_stake,
+                self.staking_rules.max_validators
+            );
+        }
+        deactivated
+    }
+    pub fn validator_set_safety(&self, min_validators: usize) -> ValidatorSetSafety {
+        let set = self
+            .frozen_validator_set
+            .clone()
+            .filter(|set| !set.validators.is_empty())
+            .unwrap_or_else(|| self.active_validator_snapshot(self.epoch_index));
+        let eligible: Vec<u64> = set
+            .validators
+            .iter()
+            .filter_map(|entry| self.validators.get(&entry.address).map(|v| (entry, v)))
+            .filter(|(_, v)| !v.slashed && !v.jailed && !v.tombstoned)
+            .map(|(entry, _)| entry.stake)
+            .collect();
+        let eligible_stake = eligible.iter().sum();
+        let quorum_stake = set.quorum_stake();
+        ValidatorSetSafety {
+            epoch: set.epoch,
+            validators: set.validators.len(),
+            eligible_validators: eligible.len(),
+            min_validators,
+            eligible_stake,
+            quorum_stake,
+            safe: eligible.len() >= min_validators.max(1) && eligible_stake >= quorum_stake,
+        }
+    }
+    // Sets a validator's finality key once its proof of possession checks.
+    pub fn register_bls_key(
+        &mut self,
+        address: &str,
+        public_key: Vec<u8>,
+        pop: Vec<u8>,
+        chain_id: u64,
+    ) -> Result<(), String> {
+        let entry = ValidatorEntry {
+            address: address.to_string(),
+            stake: 0,
+            bls_public_key: public_key,
+            pop_signature: pop,
+        };
+        if !crate::consensus::finality::verify_pop(&entry, chain_id) {
+            return Err(format!("Invalid BLS proof of possession for {}", address));
+        }
+        let validator = self
+            .validators
+            .get_mut(address)
+            .ok_or_else(|| format!("{} is not a validator", address))?;
+        validator.bls_public_key = entry.bls_public_key;
+        validator.bls_pop = entry.pop_signature;
+        Ok(())
+    }
+    pub fn active_validator_snapshot(&self, epoch: u64) -> ValidatorSetSnapshot {
+        let entries = self
+            .get_active_validators()
+            .into_iter()
+            .map(|v| ValidatorEntry {
+                address: v.address.clone(),
+                stake: v.stake,
+                bls_public_key: v.bls_public_key.clone(),
+                pop_signature: v.bls_pop.clone(),
+            })
+            .collect();
+        ValidatorSetSnapshot::new(epoch, entries)
+    }
+    pub fn is_epoch_start(height: u64) -> bool {
+        height > 0 && height.is_multiple_of(EPOCH_LENGTH)
+    }
+    pub fn validator_set_for_block(&self, height: u64) -> ValidatorSetSnapshot {
+        if !Self::is_epoch_start(height) {
+            if let Some(ref frozen) = self.frozen_validator_set {
+                if !frozen.validators.is_empty() {
+                    return frozen.clone();
+                }
+            }
+        }
+        self.active_validator_snapshot(height / EPOCH_LENGTH)
+    }
+    pub fn validators_for_block(&self, height: u64) -> Vec<&Validator> {
+        self.validator_set_for_block(height)
+            .validators
+            .iter()
+            .filter_map(|entry| self.validators.get(&entry.address))
+            .filter(|v| !v.slashed)
+            .collect()
+    }
+    pub fn freeze_validator_set_at(&mut self, height: u64) {
+        if Self::is_epoch_start(height) {
+            let snapshot = self.active_validator_snapshot(height / EPOCH_LENGTH);
+            println!(
+                "Froze validator set for epoch {}: {} validators, hash {}",
+                snapshot.epoch,
+                snapshot.validators.len(),
+                &snapshot.set_hash[..16]
+            );
+            self.frozen_validator_set = Some(snapshot);
+        }
+    }
+    // Consensus checks reveals before a block is accepted; one that does
+    // not open the producer's commitment is ignored here rather than mixed.
+    pub fn apply_randao_reveal(&mut self, producer: Option<&str>, reveal: &[u8]) {
+        if reveal.is_empty() {
+            return;
+        }
+        let Some(validator) = producer.and_then(|p| self.validators.get_mut(p)) else {
+            return;
+        };
+        if !verify_reveal(&validator.randao_commitment, reveal) {
+            return;
+        }
+        validator.randao_commitment = reveal.to_vec();
+        self.randao_mix = mix_reveal(&self.randao_mix, reveal);
+    }
+    pub fn record_missed_slots(&mut self, producer: Option<&str>, missed: &[String], limit: u64) {
+        if let Some(validator) = producer.and_then(|p| self.validators.get_mut(p)) {
+            validator.missed_slots = 0;
+        }
+        for address in missed {
+            if let Some(validator) = self.validators.get_mut(address) {
+                validator.missed_slots += 1;
+                if limit > 0 && validator.missed_slots >= limit && !validator.jailed {
+                    validator.jailed = true;
+                    validator.active = false;
+                    validator.jail_until = u64::MAX;
+                    println!(
+                        "Validator {} jailed after missing {} consecutive slots",
+                        &address[..16.min(address.len())],
+                        validator.missed_slots
+                    );
+                }
+            }
+        }
+    }
+    // Credits every validator in the block's set with its stake share of one
+    // expected proposal, and the producer with the proposal it made.
+    pub fn record_proposal(&mut self, height: u64, producer: Option<&str>) {
+        if height == 0 {
+            return;
+        }
+        let shares: Vec<(String, u64)> = self
+            .validators_for_block(height)
+            .iter()
+            .map(|v| (v.address.clone(), v.stake))
+            .collect();
+        if shares.is_empty() {
+            return;
+        }
+        let total: u128 = shares.iter().map(|(_, stake)| *stake as u128).sum();
+        for (address, stake) in shares {
+            let units = (stake as u128 * EXPECTED_BLOCK_UNITS as u128)
+                .checked_div(total)
+                .unwrap_or(0) as u64;
+            self.performance.entry(address).or_default().expected_units += units;
+        }
+        if let Some(producer) = producer {
+            if let Some(validator) = self.validators.get_mut(producer) {
+                validator.last_proposed_block = Some(height);
+                self.performance
+                    .entry(producer.to_string())
+                    .or_default()
+                    .blocks_proposed += 1;
+            }
+        }
+    }
+    pub fn record_finality_votes(&mut self, cert: &FinalityCert, snapshot: &ValidatorSetSnapshot) {
+        for (idx, entry) in snapshot.validators.iter().enumerate() {
+            let performance = self.performance.entry(entry.address.clone()).or_default();
+            performance.checkpoints_eligible += 1;
+            if cert.signed_by(idx) {
+                performance.checkpoints_signed += 1;
+            }
+        }
+    }
+    // Votes are not part of any block, so a state rebuilt from blocks takes
+    // them over from the state it replaces.
+    pub fn carry_finality_votes(&mut self, previous: &AccountState) {
+        for (address, old) in &previous.performance {
+            let performance = self.performance.entry(address.clone()).or_default();
+            performance.checkpoints_signed = old.checkpoints_signed;
+            performance.checkpoints_eligible = old.checkpoints_eligible;
+        }
+    }
+    pub fn validator_info(&self, address: &str) -> Option<ValidatorInfo> {
+        let validator = self.validators.get(address);
+        let performance = self.performance.get(address);
+        if validator.is_none() && performance.is_none() {
+            return None;
+        }
+        let performance = performance.cloned().unwrap_or_default();
+        Some(ValidatorInfo {
+            address: address.to_string(),
+            stake: validator.map_or(0, |v| v.stake),
+            delegated_stake: self.delegated_to(address),
+            active: validator.is_some_and(|v| v.active),
+            jailed: validator.is_some_and(|v| v.jailed),
+            tombstoned: validator.is_some_and(|v| v.tombstoned),
+            slashed: validator.is_some_and(|v| v.slashed),
+            missed_slots: validator.map_or(0, |v| v.missed_slots),
+            accrued_rewards: validator.map_or(0, |v| v.accrued_rewards),
+            last_proposed_block: validator.and_then(|v| v.last_proposed_block),
+            blocks_proposed: performance.blocks_proposed,
+            blocks_expected: performance.blocks_expected(),
+            proposal_rate: performance.proposal_rate(),
+            votes_signed: performance.checkpoints_signed,
+            votes_expected: performance.checkpoints_eligible,
+            uptime: performance.uptime(),
+            slashes: performance.slashes,
+        })
+    }
     pub fn get_validator(&self, address: &str) -> Option<&Validator> {
         self.validators.get(address)
     }
@@ -169,16 +758,101 @@ impl AccountState {
         self.validators.get_mut(address)
     }
 
+    pub fn locked_stake(&self, staker: &str, validator: &str) -> u64 {
+        self.accounts
+            .get(staker)
+            .and_then(|account| account.locked.get(validator))
+            .copied()
+            .unwrap_or(0)
+    }
+    pub fn get_delegation(&self, delegator: &str, validator: &str) -> u64 {
+        if delegator == validator {
+            return 0;
+        }
+        self.locked_stake(delegator, validator)
+    }
+    pub fn delegated_to(&self, validator: &str) -> u64 {
+        self.accounts
+            .iter()
+            .filter(|(address, _)| address.as_str() != validator)
+            .filter_map(|(_, account)| account.locked.get(validator))
+            .sum()
+    }
+    // Stake `staker` can move or withdraw from `validator`: what it locked
+    // there, which for the operator excludes what others delegated.
+    pub fn bonded_by(&self, staker: &str, validator: &str) -> u64 {
+        self.locked_stake(staker, validator)
+    }
+    // Moves stake between `staker`'s locked bucket for `validator` and the
+    // validator's stake. Balances are settled by the caller.
+    fn lock_stake(&mut self, staker: &str, validator: &str, amount: u64) {
+        let account = self.get_or_create(staker);
+        *account.locked.entry(validator.to_string()).or_default() += amount;
+        if let Some(v) = self.validators.get_mut(validator) {
+            v.stake += amount;
+        }
+    }
+    fn unlock_stake(&mut self, staker: &str, validator: &str, amount: u64) -> Result<(), String> {
+        let locked = self.locked_stake(staker, validator);
+        if locked < amount {
+            return Err(format!("Insufficient stake: {} < {}", locked, amount));
+        }
+        self.cut_lock(staker, validator, amount);
+        if let Some(v) = self.validators.get_mut(validator) {
+            v.stake = v.stake.saturating_sub(amount);
+        }
+        Ok(())
+    }
+    fn cut_lock(&mut self, staker: &str, validator: &str, amount: u64) {
+        self.touch(staker);
+        if let Some(account) = self.accounts.get_mut(staker) {
+            if let Some(locked) = account.locked.get_mut(validator) {
+                *locked = locked.saturating_sub(amount);
+                if *locked == 0 {
+                    account.locked.remove(validator);
+                }
+            }
+        }
+    }
+    pub fn get_pending_rewards(&self, address: &str) -> u64 {
+        self.validators
+            .get(address)
+            .map(|v| v.accrued_rewards)
+            .unwrap_or(0)
+    }
     pub fn get_balance(&self, public_key: &str) -> u64 {
         self.accounts
             .get(public_key)
             .map(|a| a.balance)
             .unwrap_or(0)
     }
+    pub fn get_allowance(&self, owner: &str, spender: &str) -> u64 {
+        self.allowances
+            .get(owner)
+            .and_then(|spenders| spenders.get(spender))
+            .copied()
+            .unwrap_or(0)
+    }
     pub fn get_nonce(&self, public_key: &str) -> u64 {
         self.accounts.get(public_key).map(|a| a.nonce).unwrap_or(0)
     }
+    fn touch(&mut self, address: &str) {
+        if !self.touched.contains_key(address) {
+            let before = self.accounts.get(address).cloned();
+            self.touched.insert(address.to_string(), before);
+        }
+    }
+    pub fn clear_touched(&mut self) {
+        self.touched.clear();
+    }
+    // Accounts written since `clear_touched` and their values before that.
+    pub fn touched_accounts(&self) -> &HashMap<String, Option<Account>> {
+        &self.touched
+    }
     pub fn get_or_create(&mut self, public_key: &str) -> &mut Account {
+        self.touch(public_key);
+        self.last_active
+            .insert(public_key.to_string(), self.epoch_index);
         if !self.accounts.contains_key(public_key) {
             self.accounts
                 .insert(public_key.to_string(), Account::new(public_key.to_string()));
@@ -186,16 +860,23 @@ impl AccountState {
         self.accounts.get_mut(public_key).unwrap()
     }
     pub fn validate_transaction(&self, tx: &Transaction) -> Result<(), String> {
+        self.validate_transaction_at_nonce(tx, self.get_nonce(&tx.from))
+    }
+    pub fn validate_transaction_at_nonce(
+        &self,
+        tx: &Transaction,
+        expected_nonce: u64,
+    ) -> Result<(), String> {
         if tx.from == "genesis" {
             return Ok(());
         }
         if !tx.verify() {
             return Err("Invalid signature".into());
         }
-        if tx.fee < MIN_TX_FEE {
-            return Err(format!("Fee too low: {} < {}", tx.fee, MIN_TX_FEE));
+        let min_fee = MIN_TX_FEE * tx.fee_units();
+        if tx.fee < min_fee {
+            return Err(format!("Fee too low: {} < {}", tx.fee, min_fee));
         }
-        let expected_nonce = self.get_nonce(&tx.from);
         if tx.nonce != expected_nonce {
             return Err(format!(
                 "Invalid nonce: expected {}, got {}",
@@ -221,13 +902,35 @@ impl AccountState {
                 if tx.amount == 0 {
                     return Err("Stake amount must be > 0".into());
                 }
+                let current = self.validators.get(&tx.from).map(|v| v.stake).unwrap_or(0);
+                let total = current
+                    .checked_add(tx.amount)
+                    .ok_or("Stake overflows the validator's total")?;
+                let min_stake = self.staking_rules.min_stake;
+                if total < min_stake {
+                    return Err(format!("Stake below minimum: {} < {}", total, min_stake));
+                }
+            }
+            TransactionType::Unstake if !tx.to.is_empty() && tx.to != tx.from => {
+                let delegated = self.get_delegation(&tx.from, &tx.to);
+                if delegated < tx.amount {
+                    return Err(format!(
+                        "Insufficient delegation: {} < {}",
+                        delegated, tx.amount
+                    ));
+                }
             }
             TransactionType::Unstake => {
                 if let Some(validator) = self.validators.get(&tx.from) {
-                    if validator.stake < tx.amount {
+                    let bonded = self.bonded_by(&tx.from, &tx.from);
+                    if bonded < tx.amount {
+                        return Err(format!("Insufficient stake: {} < {}", bonded, tx.amount));
+                    }
+                    let remaining = validator.stake - tx.amount;
+                    if remaining > 0 && remaining < self.staking_rules.min_stake {
                         return Err(format!(
-                            "Insufficient stake: {} < {}",
-                            validator.stake, tx.amount
+                            "Remaining stake {} below minimum {}; unstake everything or keep at least the minimum",
+                            remaining, self.staking_rules.min_stake
                         ));
                     }
                 } else {
@@ -235,35 +938,248 @@ impl AccountState {
                 }
             }
             TransactionType::Vote => {
-                if !self.validators.contains_key(&tx.from) {
-                    return Err("Only validators can vote".into());
+                let vote = AuthorityVote::decode(&tx.data)?;
+                let active = self.get_active_validators();
+                if !active.iter().any(|v| v.address == tx.from) {
+                    return Err("Only active validators can vote".into());
+                }
+                let target_active = active.iter().any(|v| v.address == vote.target());
+                match vote {
+                    AuthorityVote::Add(_) if target_active => {
+                        return Err("Candidate is already an authority".into());
+                    }
+                    AuthorityVote::Remove(_) if !target_active => {
+                        return Err("Target is not an authority".into());
+                    }
+                    _ => {}
+                }
+                if self
+                    .authority_votes
+                    .get(&vote)
+                    .is_some_and(|voters| voters.contains(&tx.from))
+                {
+                    return Err("Already voted on this proposal".into());
+                }
+            }
+            TransactionType::ClaimRewards => match self.validators.get(&tx.from) {
+                Some(validator) if validator.accrued_rewards > 0 => {}
+                Some(_) => return Err("No rewards to claim".into()),
+                None => return Err("Not a validator".into()),
+            },
+            TransactionType::Unjail => match self.validators.get(&tx.from) {
+                Some(validator) if validator.tombstoned => {
+                    return Err("Tombstoned validators cannot unjail".into())
+                }
+                Some(validator) if validator.slashed => {
+                    return Err("Slashed validators cannot unjail".into())
+                }
+                Some(validator) if validator.jailed => {}
+                Some(_) => return Err("Validator is not jailed".into()),
+                None => return Err("Not a validator".into()),
+            },
+            TransactionType::Burn => {
+                if tx.amount == 0 {
+                    return Err("Burn amount must be > 0".into());
+                }
+            }
+            TransactionType::Approve => {
+                if tx.to.is_empty() || tx.to == tx.from {
+                    return Err("Approve needs a spender other than the owner".into());
                 }
             }
+            TransactionType::TransferFrom => {
+                let owner = tx.transfer_from_owner()?;
+                let allowance = self.get_allowance(&owner, &tx.from);
+                if allowance < tx.amount {
+                    return Err(format!("Allowance exceeded: {} < {}", allowance, tx.amount));
+                }
+                let owner_balance = self.get_balance(&owner);
+                if owner_balance < tx.amount {
+                    return Err(format!(
+                        "Owner balance too low: {} < {}",
+                        owner_balance, tx.amount
+                    ));
+                }
+            }
+            TransactionType::Batch => {
+                tx.batch_outputs()?;
+            }
+            TransactionType::Redelegate => {
+                let source = tx.redelegate_source()?;
+                if tx.amount == 0 || source == tx.to {
+                    return Err("Redelegate needs an amount and a different destination".into());
+                }
+                match self.validators.get(&tx.to) {
+                    Some(destination) if !destination.slashed && !destination.jailed => {}
+                    Some(_) => return Err("Destination validator is slashed or jailed".into()),
+                    None => return Err("Destination is not a validator".into()),
+                }
+                let bonded = self.bonded_by(&tx.from, &source);
+                if bonded < tx.amount {
+                    return Err(format!("Insufficient stake: {} < {}", bonded, tx.amount));
+                }
+                if source == tx.from {
+                    let remaining = self.validators.get(&source).map_or(0, |v| v.stake) - tx.amount;
+                    if remaining > 0 && remaining < self.staking_rules.min_stake {
+                        return Err(format!(
+                            "Remaining stake {} below minimum {}",
+                            remaining, self.staking_rules.min_stake
+                        ));
+                    }
+                }
+                let in_flight: Vec<&RedelegationEntry> = self
+                    .redelegations
+                    .iter()
+                    .filter(|e| e.delegator == tx.from && e.completion_epoch > self.epoch_index)
+                    .collect();
+                if in_flight.len() >= MAX_REDELEGATIONS {
+                    return Err(format!(
+                        "Too many redelegations in progress (max {})",
+                        MAX_REDELEGATIONS
+                    ));
+                }
+                if in_flight.iter().any(|e| e.destination == source) {
+                    return Err("Stake redelegated to the source has not matured yet".into());
+                }
+            }
+            TransactionType::BridgeLock | TransactionType::BridgeBurn => {
+                if tx.amount == 0 {
+                    return Err("Bridge transfer amount must be > 0".into());
+                }
+                tx.bridge_transfer()?;
+            }
+            TransactionType::BridgeMint | TransactionType::BridgeRelease => {
+                self.check_bridge_inbound(tx)?;
+            }
+            TransactionType::LightClientUpdate => {
+                self.check_light_client_update(tx)?;
+            }
+            TransactionType::RestoreAccount => {
+                self.check_dust_restore(tx)?;
+            }
+            TransactionType::RandaoCommit => {
+                self.check_randao_commit(tx)?;
+            }
         }
 
         Ok(())
     }
 
-    pub fn apply_slashing(&mut self, evidences: &[SlashingEvidence], slash_ratio: f64) {
+    // Anyone relaying the evidence could rewrite its unsigned `reporter`, so
+    // the reward goes to the producer that included it.
+    pub fn apply_slashing(
+        &mut self,
+        evidences: &[SlashingEvidence],
+        slash_ratio: f64,
+        block_producer: Option<&str>,
+    ) {
         for evidence in evidences {
+            self.processed_evidence
+                .insert(evidence.key(), evidence.header1.index / EPOCH_LENGTH);
             if let Some(producer) = &evidence.header1.producer {
-                if let Some(validator) = self.validators.get_mut(producer) {
-                    if !validator.slashed {
-                        let penalty = (validator.stake as f64 * slash_ratio) as u64;
-                        validator.stake = validator.stake.saturating_sub(penalty);
-                        validator.slashed = true;
-                        validator.active = false;
-                        let jail_duration = 3600 * 24;
-
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs();
-                        validator.jail_until = now + jail_duration;
-                        println!("Slashed validator {} for {} stake", producer, penalty);
-                    }
-                }
+                self.slash_offender(producer, slash_ratio, block_producer.unwrap_or_default());
+            }
+        }
+    }
+
+    // The evidence's `reporter` is not signed, so the reward goes to the
+    // producer that included the evidence.
+    pub fn apply_vote_slashing(
+        &mut self,
+        evidences: &[VoteEvidence],
+        slash_ratio: f64,
+        block_producer: Option<&str>,
+    ) {
+        for evidence in evidences {
+            self.processed_evidence
+                .insert(evidence.key(), evidence.epoch);
+            self.slash_offender(
+                &evidence.validator,
+                slash_ratio,
+                block_producer.unwrap_or_default(),
+            );
+        }
+    }
+
+    pub fn evidence_processed(&self, key: &str) -> bool {
+        self.processed_evidence.contains_key(key)
+    }
+
+    fn slash_offender(&mut self, offender: &str, slash_ratio: f64, reporter: &str) {
+        let mut penalty = 0;
+        if let Some(validator) = self.validators.get_mut(offender) {
+            if !validator.slashed {
+                penalty = (validator.stake as f64 * slash_ratio) as u64;
+                validator.stake = validator.stake.saturating_sub(penalty);
+                validator.slashed = true;
+                validator.tombstone();
+                println!(
+                    "Slashed and tombstoned validator {} for {} stake",
+                    offender, penalty
+                );
+                self.performance
+                    .entry(offender.to_string())
+                    .or_default()
+                    .slashes += 1;
+            }
+        }
+        let reward = if reporter.is_empty() || reporter == offender {
+            0
+        } else {
+            penalty * REPORTER_REWARD_PERCENT / 100
+        };
+        if reward > 0 {
+            self.get_or_create(reporter).balance += reward;
+            println!("Reporter {} rewarded {} for evidence", reporter, reward);
+        }
+        self.total_burned += penalty - reward;
+        if penalty > 0 {
+            self.slash_delegations(offender, slash_ratio, penalty);
+        }
+    }
+
+    // Delegations to the offender share its penalty, and so does stake that was
+    // redelegated away from it while the offence could still be reported. The
+    // operator's own lock takes the rest, so the locks still add up to the
+    // reduced stake.
+    fn slash_delegations(&mut self, offender: &str, slash_ratio: f64, penalty: u64) {
+        let delegators: Vec<(String, u64)> = self
+            .accounts
+            .iter()
+            .filter(|(address, _)| address.as_str() != offender)
+            .filter_map(|(delegator, account)| {
+                account
+                    .locked
+                    .get(offender)
+                    .map(|amount| (delegator.clone(), *amount))
+            })
+            .collect();
+        let mut delegated_cut = 0;
+        for (delegator, amount) in delegators {
+            let cut = (amount as f64 * slash_ratio) as u64;
+            self.cut_lock(&delegator, offender, cut);
+            delegated_cut += cut;
+        }
+        self.cut_lock(offender, offender, penalty.saturating_sub(delegated_cut));
+
+        let open: Vec<RedelegationEntry> = self
+            .redelegations
+            .iter()
+            .filter(|e| e.source == offender && e.completion_epoch > self.epoch_index)
+            .cloned()
+            .collect();
+        for entry in open {
+            let held = self.bonded_by(&entry.delegator, &entry.destination);
+            let cut = ((entry.amount as f64 * slash_ratio) as u64).min(held);
+            if cut == 0 {
+                continue;
             }
+            let _ = self.unlock_stake(&entry.delegator, &entry.destination, cut);
+            self.total_burned += cut;
+            println!(
+                "Slashed {} of stake redelegated by {} from {} to {}",
+                cut, entry.delegator, offender, entry.destination
+            );
         }
     }
 
@@ -289,17 +1205,135 @@ impl AccountState {
         }
     }
 
-    pub fn advance_epoch(&mut self, current_timestamp: u128) {
+    fn credit_treasury(&mut self, source: TreasurySource, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        let address = self.treasury.address.clone();
+        self.get_or_create(&address).balance += amount;
+        self.treasury_credits
+            .push(TreasuryCredit { source, amount });
+    }
+
+    pub fn distribute_epoch_rewards(&mut self) -> EpochRewardReceipt {
+        let mut payouts: Vec<(String, u64)> = self
+            .get_active_validators()
+            .into_iter()
+            .map(|v| {
+                (
+                    v.address.clone(),
+                    self.reward_config.epoch_reward(v.effective_stake()),
+                )
+            })
+            .filter(|(_, reward)| *reward > 0)
+            .collect();
+        let mut minted = 0u64;
+        let mut treasury = 0u64;
+        for (address, reward) in payouts.iter_mut() {
+            if let Some(validator) = self.validators.get_mut(address.as_str()) {
+                let cut = self.treasury.share(*reward, self.treasury.reward_bps);
+                minted += *reward;
+                treasury += cut;
+                *reward -= cut;
+                validator.accrued_rewards += *reward;
+            }
+        }
+        self.credit_treasury(TreasurySource::Rewards, treasury);
+        self.total_minted += minted;
+        if minted > 0 {
+            println!(
+                "Epoch {} rewards: minted {} across {} validators",
+                self.epoch_index,
+                minted,
+                payouts.len()
+            );
+        }
+        EpochRewardReceipt {
+            epoch: self.epoch_index,
+            minted,
+            rewards: payouts,
+            treasury,
+        }
+    }
+
+    // Only accounts that never sent a transaction are swept: a restored
+    // account starts again at nonce 0, so an account with history could
+    // have its old transactions replayed. Anything that validates, stakes,
+    // delegates, holds allowances or belongs to the protocol is kept.
+    fn is_sweepable(&self, address: &str, account: &Account) -> bool {
+        let inactive_since = self.last_active.get(address).copied().unwrap_or(0);
+        account.nonce == 0
+            && account.balance < self.dust_sweep.threshold
+            && self.epoch_index.saturating_sub(inactive_since) >= self.dust_sweep.inactive_epochs
+            && address != BRIDGE_ESCROW
+            && address != self.treasury.address
+            && !self.validators.contains_key(address)
+            && account.locked.is_empty()
+            && !self.allowances.contains_key(address)
+            && !self.allowances.values().any(|a| a.contains_key(address))
+            && !self.unbonding_queue.iter().any(|e| e.address == address)
+    }
+
+    pub fn sweep_dust(&mut self) {
+        if !self.dust_sweep.is_enabled() {
+            return;
+        }
+        let mut addresses: Vec<String> = self
+            .accounts
+            .iter()
+            .filter(|(address, account)| self.is_sweepable(address, account))
+            .map(|(address, _)| address.clone())
+            .collect();
+        if addresses.is_empty() {
+            return;
+        }
+        addresses.sort();
+        let mut swept = Vec::new();
+        for address in &addresses {
+            self.last_active.remove(address);
+            self.touch(address);
+            if let Some(account) = self.accounts.remove(address) {
+                if account.balance > 0 {
+                    swept.push(SweptAccount {
+                        address: address.clone(),
+                        balance: account.balance,
+                    });
+                }
+            }
+        }
+        println!(
+            "Epoch {}: swept {} inactive dust accounts",
+            self.epoch_index,
+            addresses.len()
+        );
+        if swept.is_empty() {
+            return;
+        }
+        self.swept_balance += swept.iter().map(|a| a.balance).sum::<u64>();
+        self.dust_sweeps
+            .insert(self.epoch_index, sweep_root(self.epoch_index, &swept));
+        self.swept_dust.push((self.epoch_index, swept));
+    }
+
+    pub fn advance_epoch(&mut self, current_timestamp: u128) -> EpochRewardReceipt {
+        let receipt = self.distribute_epoch_rewards();
         self.epoch_index += 1;
         self.last_epoch_time = current_timestamp as u64;
+        self.randao_seed = self.randao_mix;
         println!("Epoch advanced to {}", self.epoch_index);
 
         self.process_unbonding();
+        let epoch = self.epoch_index;
+        self.redelegations.retain(|e| e.completion_epoch > epoch);
+        self.processed_evidence
+            .retain(|_, offence| epoch.saturating_sub(*offence) <= EVIDENCE_MAX_AGE_EPOCHS);
+        self.sweep_dust();
 
         let current_time_sec = (current_timestamp / 1000) as u64;
 
         for (addr, validator) in self.validators.iter_mut() {
-            if validator.jailed && validator.jail_until <= current_time_sec {
+            if validator.jailed && !validator.tombstoned && validator.jail_until <= current_time_sec
+            {
                 println!("Validator {} released from jail", addr);
                 validator.jailed = false;
                 if validator.stake > 0 {
@@ -307,6 +1341,8 @@ impl AccountState {
                 }
             }
         }
+        self.enforce_staking_rules();
+        receipt
     }
 
     pub fn apply_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
@@ -337,38 +1373,66 @@ impl AccountState {
                 sender.balance -= total_cost;
                 sender.nonce += 1;
 
-                let stake_amount = tx.amount;
-                let validator = self
-                    .validators
+                self.validators
                     .entry(tx.from.clone())
-                    .or_insert_with(|| Validator::new(tx.from.clone(), 0));
-                validator.stake += stake_amount;
-                validator.active = true;
+                    .or_insert_with(|| Validator {
+                        active: false,
+                        ..Validator::new(tx.from.clone(), 0)
+                    });
+                self.lock_stake(&tx.from, &tx.from, tx.amount);
+                let min_stake = self.staking_rules.min_stake;
+                // A full set is only re-ranked at the next epoch boundary.
+                let has_slot = self.has_validator_slot();
+                let validator = self.validators.get_mut(&tx.from).unwrap();
+                if !validator.active {
+                    validator.active =
+                        has_slot && !validator.tombstoned && validator.stake >= min_stake;
+                }
                 println!("Stake added: {} now has {}", tx.from, validator.stake);
             }
-            TransactionType::Unstake => {
-                let sender_start_balance = self.get_balance(&tx.from);
-                if sender_start_balance < tx.fee {
-                    return Err("Insufficient balance for fee".into());
+            TransactionType::Unstake if !tx.to.is_empty() && tx.to != tx.from => {
+                if self.get_delegation(&tx.from, &tx.to) < tx.amount {
+                    return Err("Insufficient delegation".into());
                 }
-
-                if let Some(validator) = self.validators.get_mut(&tx.from) {
-                    if validator.stake < tx.amount {
-                        return Err("Insufficient stake".into());
-                    }
-                    validator.stake -= tx.amount;
-                    if validator.stake == 0 {
+                self.unlock_stake(&tx.from, &tx.to, tx.amount)?;
+                if let Some(validator) = self.validators.get_mut(&tx.to) {
+                    if validator.stake < self.staking_rules.min_stake {
                         validator.active = false;
                     }
-                    println!(
-                        "Unstake queued: {} amount {} releases at epoch {}",
-                        tx.from,
-                        tx.amount,
-                        self.epoch_index + UNBONDING_EPOCHS
-                    );
-                } else {
+                }
+                self.unbonding_queue.push(UnbondingEntry {
+                    address: tx.from.clone(),
+                    amount: tx.amount,
+                    release_epoch: self.epoch_index + UNBONDING_EPOCHS,
+                });
+
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+                println!("Undelegated {} from {} by {}", tx.amount, tx.to, tx.from);
+            }
+            TransactionType::Unstake => {
+                let sender_start_balance = self.get_balance(&tx.from);
+                if sender_start_balance < tx.fee {
+                    return Err("Insufficient balance for fee".into());
+                }
+
+                if !self.validators.contains_key(&tx.from) {
                     return Err("Not a validator".into());
                 }
+                self.unlock_stake(&tx.from, &tx.from, tx.amount)?;
+                let min_stake = self.staking_rules.min_stake;
+                if let Some(validator) = self.validators.get_mut(&tx.from) {
+                    if validator.stake == 0 || validator.stake < min_stake {
+                        validator.active = false;
+                    }
+                }
+                println!(
+                    "Unstake queued: {} amount {} releases at epoch {}",
+                    tx.from,
+                    tx.amount,
+                    self.epoch_index + UNBONDING_EPOCHS
+                );
 
                 self.unbonding_queue.push(UnbondingEntry {
                     address: tx.from.clone(),
@@ -381,16 +1445,360 @@ impl AccountState {
                 sender.nonce += 1;
             }
             TransactionType::Vote => {
+                let vote = AuthorityVote::decode(&tx.data)?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+
+                self.tally_authority_vote(&tx.from, vote);
+            }
+            TransactionType::ClaimRewards => {
+                let rewards = match self.validators.get_mut(&tx.from) {
+                    Some(validator) => std::mem::take(&mut validator.accrued_rewards),
+                    None => return Err("Not a validator".into()),
+                };
+                let sender = self.get_or_create(&tx.from);
+                sender.balance = sender.balance - tx.fee + rewards;
+                sender.nonce += 1;
+                println!("Rewards claimed: {} withdrew {}", tx.from, rewards);
+            }
+            TransactionType::Unjail => {
+                let has_slot = self.has_validator_slot();
+                match self.validators.get_mut(&tx.from) {
+                    Some(validator)
+                        if validator.jailed && !validator.slashed && !validator.tombstoned =>
+                    {
+                        validator.jailed = false;
+                        validator.jail_until = 0;
+                        validator.missed_slots = 0;
+                        validator.active = (validator.active || has_slot)
+                            && validator.stake >= self.staking_rules.min_stake;
+                    }
+                    _ => return Err("Validator is not jailed".into()),
+                }
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+                println!("Validator {} unjailed", tx.from);
+            }
+            TransactionType::Burn => {
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+                self.total_burned += tx.amount;
+                println!("Burned {} from {}", tx.amount, tx.from);
+            }
+            TransactionType::Approve => {
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+
+                let spenders = self.allowances.entry(tx.from.clone()).or_default();
+                if tx.amount == 0 {
+                    spenders.remove(&tx.to);
+                    if spenders.is_empty() {
+                        self.allowances.remove(&tx.from);
+                    }
+                } else {
+                    spenders.insert(tx.to.clone(), tx.amount);
+                }
+                println!(
+                    "Allowance set: {} may spend {} of {}",
+                    tx.to, tx.amount, tx.from
+                );
+            }
+            TransactionType::TransferFrom => {
+                let owner = tx.transfer_from_owner()?;
+                if self.get_allowance(&owner, &tx.from) < tx.amount {
+                    return Err("Allowance exceeded".into());
+                }
+                if self.get_balance(&owner) < tx.amount {
+                    return Err("Insufficient owner balance".into());
+                }
+                if let Some(spenders) = self.allowances.get_mut(&owner) {
+                    let remaining = spenders[&tx.from] - tx.amount;
+                    if remaining == 0 {
+                        spenders.remove(&tx.from);
+                    } else {
+                        spenders.insert(tx.from.clone(), remaining);
+                    }
+                    if spenders.is_empty() {
+                        self.allowances.remove(&owner);
+                    }
+                }
+
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+
+                self.get_or_create(&owner).balance -= tx.amount;
+                self.get_or_create(&tx.to).balance += tx.amount;
+            }
+            TransactionType::Batch => {
+                let outputs = tx.batch_outputs()?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+
+                for output in outputs {
+                    self.get_or_create(&output.to).balance += output.amount;
+                }
+            }
+            TransactionType::Redelegate => {
+                let source = tx.redelegate_source()?;
+                if self.bonded_by(&tx.from, &source) < tx.amount {
+                    return Err("Insufficient stake".into());
+                }
+                if !self.validators.contains_key(&tx.to) {
+                    return Err("Destination is not a validator".into());
+                }
+                let min_stake = self.staking_rules.min_stake;
+                self.unlock_stake(&tx.from, &source, tx.amount)?;
+                if let Some(validator) = self.validators.get_mut(&source) {
+                    if validator.stake < min_stake {
+                        validator.active = false;
+                    }
+                }
+                self.lock_stake(&tx.from, &tx.to, tx.amount);
+                let has_slot = self.has_validator_slot();
+                if let Some(validator) = self.validators.get_mut(&tx.to) {
+                    if !validator.active {
+                        validator.active = has_slot && validator.stake >= min_stake;
+                    }
+                }
+                self.redelegations.push(RedelegationEntry {
+                    delegator: tx.from.clone(),
+                    source: source.clone(),
+                    destination: tx.to.clone(),
+                    amount: tx.amount,
+                    completion_epoch: self.epoch_index + UNBONDING_EPOCHS,
+                });
+
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+                println!(
+                    "Redelegated {} from {} to {} for {}",
+                    tx.amount, source, tx.to, tx.from
+                );
+            }
+            TransactionType::BridgeLock => {
+                let transfer = tx.bridge_transfer()?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+                self.get_or_create(BRIDGE_ESCROW).balance += tx.amount;
+                println!(
+                    "Bridge lock: {} from {} for {} on {:?}",
+                    tx.amount, tx.from, transfer.recipient, transfer.destination
+                );
+            }
+            TransactionType::BridgeBurn => {
+                let transfer = tx.bridge_transfer()?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= total_cost;
+                sender.nonce += 1;
+                self.total_burned += tx.amount;
+                println!(
+                    "Bridge burn: {} from {} for {} on {:?}",
+                    tx.amount, tx.from, transfer.recipient, transfer.destination
+                );
+            }
+            TransactionType::BridgeMint | TransactionType::BridgeRelease => {
+                let inbound = self.check_bridge_inbound(tx)?;
                 let sender = self.get_or_create(&tx.from);
                 sender.balance -= tx.fee;
                 sender.nonce += 1;
 
-                println!("Vote TX processed from {}", tx.from);
+                if tx.tx_type == TransactionType::BridgeMint {
+                    self.total_minted += tx.amount;
+                } else {
+                    self.get_or_create(BRIDGE_ESCROW).balance -= tx.amount;
+                }
+                self.get_or_create(&tx.to).balance += tx.amount;
+                self.bridge_messages.insert(inbound.message.id());
+                println!(
+                    "Bridge {:?}: {} to {} from {:?}",
+                    inbound.message.kind, tx.amount, tx.to, inbound.message.source
+                );
+            }
+            TransactionType::LightClientUpdate => {
+                let client = self.check_light_client_update(tx)?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+                println!(
+                    "Light client for chain {} at height {}",
+                    client.chain_id, client.latest_height
+                );
+                self.light_clients.insert(client.chain_id, client);
+            }
+            TransactionType::RestoreAccount => {
+                let proof = self.check_dust_restore(tx)?;
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+
+                self.get_or_create(&tx.to).balance += proof.account.balance;
+                self.swept_balance -= proof.account.balance;
+                self.restored_dust.insert(proof.leaf());
+                println!(
+                    "Restored {} swept in epoch {} to {}",
+                    proof.account.balance, proof.epoch, tx.to
+                );
+            }
+            TransactionType::RandaoCommit => {
+                let (generation, commitment) = self.check_randao_commit(tx)?;
+                if let Some(validator) = self.validators.get_mut(&tx.from) {
+                    validator.randao_commitment = commitment.to_vec();
+                    validator.randao_generation = generation;
+                }
+                let sender = self.get_or_create(&tx.from);
+                sender.balance -= tx.fee;
+                sender.nonce += 1;
+                println!("RANDAO generation {} committed by {}", generation, tx.from);
             }
         }
 
         Ok(())
     }
+    // Block import applies transactions without the mempool checks, so the
+    // attestations are verified on both paths.
+    fn check_bridge_inbound(&self, tx: &Transaction) -> Result<SignedInbound, String> {
+        let inbound = tx.bridge_inbound()?;
+        if self.bridge_messages.contains(&inbound.message.id()) {
+            return Err("Bridge message already processed".into());
+        }
+        match &inbound.proof {
+            Some(proof) => {
+                inbound.verify_relayer()?;
+                let ForeignChain::Budlum(source) = inbound.message.source else {
+                    return Err("Only Budlum chains can prove bridge transfers".into());
+                };
+                self.light_clients
+                    .get(&source)
+                    .ok_or_else(|| format!("No light client for chain {}", source))?
+                    .verify_transfer(&inbound.message, proof, tx.chain_id)?;
+            }
+            None => inbound.verify(&self.get_active_validators())?,
+        }
+        if tx.tx_type == TransactionType::BridgeRelease {
+            let escrow = self.get_balance(BRIDGE_ESCROW);
+            if escrow < tx.amount {
+                return Err(format!("Bridge escrow too low: {} < {}", escrow, tx.amount));
+            }
+        }
+        Ok(inbound)
+    }
+    // A used onion cannot be reused, so a re-commit must move to a later
+    // generation; only a validator with no commitment may keep its own.
+    fn check_randao_commit(&self, tx: &Transaction) -> Result<(u64, [u8; 32]), String> {
+        let (generation, commitment) = tx.randao_commit()?;
+        let validator = self.validators.get(&tx.from).ok_or("Not a validator")?;
+        let fresh = validator.randao_commitment.is_empty();
+        if generation < validator.randao_generation
+            || (generation == validator.randao_generation && !fresh)
+        {
+            return Err(format!(
+                "RANDAO generation {} is not after {}",
+                generation, validator.randao_generation
+            ));
+        }
+        Ok((generation, commitment))
+    }
+    fn check_dust_restore(&self, tx: &Transaction) -> Result<DustProof, String> {
+        let proof = tx.dust_proof()?;
+        if proof.account.address != tx.to {
+            return Err("Dust proof is for a different account".into());
+        }
+        if self.dust_sweeps.get(&proof.epoch) != Some(&proof.root()) {
+            return Err(format!(
+                "Dust proof does not match the sweep of epoch {}",
+                proof.epoch
+            ));
+        }
+        if self.restored_dust.contains(&proof.leaf()) {
+            return Err("Swept account already restored".into());
+        }
+        Ok(proof)
+    }
+    // Returns the client as it is after the update. Only active validators
+    // may choose which remote validator set a new client starts from.
+    fn check_light_client_update(&self, tx: &Transaction) -> Result<LightClient, String> {
+        match tx.light_client_message()? {
+            LightClientMessage::Create { chain_id, update } => {
+                if self.light_clients.contains_key(&chain_id) {
+                    return Err(format!(
+                        "Light client for chain {} already exists",
+                        chain_id
+                    ));
+                }
+                if !self
+                    .get_active_validators()
+                    .iter()
+                    .any(|v| v.address == tx.from)
+                {
+                    return Err("Only active validators can create light clients".into());
+                }
+                LightClient::create(chain_id, &update)
+            }
+            LightClientMessage::Update { chain_id, update } => {
+                let mut client = self
+                    .light_clients
+                    .get(&chain_id)
+                    .cloned()
+                    .ok_or_else(|| format!("No light client for chain {}", chain_id))?;
+                client.apply_update(&update)?;
+                Ok(client)
+            }
+        }
+    }
+    fn tally_authority_vote(&mut self, voter: &str, vote: AuthorityVote) {
+        let active: BTreeSet<String> = self
+            .get_active_validators()
+            .into_iter()
+            .map(|v| v.address.clone())
+            .collect();
+        let voters = self.authority_votes.entry(vote.clone()).or_default();
+        voters.insert(voter.to_string());
+        voters.retain(|v| active.contains(v));
+        let tally = voters.len();
+        println!(
+            "Authority vote {:?} from {}: {}/{}",
+            vote,
+            &voter[..16.min(voter.len())],
+            tally,
+            active.len()
+        );
+        if tally * 2 <= active.len() {
+            return;
+        }
+
+        self.authority_votes.remove(&vote);
+        match vote {
+            AuthorityVote::Add(address) => {
+                let validator = self
+                    .validators
+                    .entry(address.clone())
+                    .or_insert_with(|| Validator::new(address.clone(), 0));
+                validator.active = !validator.tombstoned;
+                println!("Authority added: {}", address);
+            }
+            AuthorityVote::Remove(address) => {
+                if let Some(validator) = self.validators.get_mut(&address) {
+                    validator.active = false;
+                }
+                for voters in self.authority_votes.values_mut() {
+                    voters.remove(&address);
+                }
+                self.authority_votes.retain(|_, voters| !voters.is_empty());
+                println!("Authority removed: {}", address);
+            }
+        }
+    }
+    // Supply is tracked by the running minted and burned totals, which the
+    // state root commits. Debug builds also check them against a scan of
+    // every account after each block.
     pub fn apply_block(
         &mut self,
         transactions: &[Transaction],
@@ -406,8 +1814,21 @@ impl AccountState {
             }
             total_fees += tx.fee;
         }
+        let treasury_cut = self.treasury.share(total_fees, self.treasury.fee_bps);
+        self.credit_treasury(TreasurySource::Fees, treasury_cut);
+        total_fees -= treasury_cut;
         if let Some(producer) = block_producer {
             if total_fees > 0 {
+                if let Some(validator) = self.validators.get_mut(producer) {
+                    validator.accrued_rewards += total_fees;
+                    println!(
+                        "Validator {} accrued {} in fees (unclaimed: {})",
+                        &producer[..16.min(producer.len())],
+                        total_fees,
+                        validator.accrued_rewards
+                    );
+                    return Ok(());
+                }
                 let producer_account = self.get_or_create(producer);
                 producer_account.balance += total_fees;
                 println!(
@@ -416,12 +1837,89 @@ impl AccountState {
                     total_fees
                 );
             }
+        } else {
+            self.total_burned += total_fees;
         }
         Ok(())
     }
     pub fn add_balance(&mut self, public_key: &str, amount: u64) {
         let account = self.get_or_create(public_key);
         account.balance += amount;
+        self.total_minted += amount;
+    }
+    pub fn save_checkpoint(
+        &self,
+        storage: &Storage,
+        height: u64,
+        block_hash: &str,
+    ) -> Result<(), String> {
+        let checkpoint = StateCheckpoint {
+            height,
+            block_hash: block_hash.to_string(),
+            accounts: self.accounts.clone(),
+            validators: self.validators.clone(),
+            unbonding_queue: self.unbonding_queue.clone(),
+            epoch_index: self.epoch_index,
+            last_epoch_time: self.last_epoch_time,
+            frozen_validator_set: self.frozen_validator_set.clone(),
+            authority_votes: self.authority_votes.clone(),
+            last_block_timestamp: self.last_block_timestamp,
+            total_minted: self.total_minted,
+            total_burned: self.total_burned,
+            allowances: self.allowances.clone(),
+            redelegations: self.redelegations.clone(),
+            bridge_messages: self.bridge_messages.clone(),
+            light_clients: self.light_clients.clone(),
+            last_active: self.last_active.clone(),
+            randao_mix: self.randao_mix,
+            randao_seed: self.randao_seed,
+            dust_sweeps: self.dust_sweeps.clone(),
+            swept_balance: self.swept_balance,
+            restored_dust: self.restored_dust.clone(),
+            performance: self.performance.clone(),
+            processed_evidence: self.processed_evidence.clone(),
+        };
+        let data =
+            bincode::serialize(&checkpoint).map_err(|e| format!("Serialization error: {}", e))?;
+        storage
+            .put_meta(STATE_CHECKPOINT_KEY, &data)
+            .map_err(|e| format!("Storage error: {}", e))
+    }
+    // Returns the stored state with the height and block hash it was taken at.
+    pub fn load_checkpoint(storage: &Storage) -> Result<Option<(u64, String, Self)>, String> {
+        let data = match storage
+            .get_meta(STATE_CHECKPOINT_KEY)
+            .map_err(|e| format!("Storage error: {}", e))?
+        {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let checkpoint: StateCheckpoint =
+            bincode::deserialize(&data).map_err(|e| format!("Deserialization error: {}", e))?;
+        let mut state = AccountState::new();
+        state.accounts = checkpoint.accounts;
+        state.validators = checkpoint.validators;
+        state.unbonding_queue = checkpoint.unbonding_queue;
+        state.epoch_index = checkpoint.epoch_index;
+        state.last_epoch_time = checkpoint.last_epoch_time;
+        state.frozen_validator_set = checkpoint.frozen_validator_set;
+        state.authority_votes = checkpoint.authority_votes;
+        state.last_block_timestamp = checkpoint.last_block_timestamp;
+        state.total_minted = checkpoint.total_minted;
+        state.total_burned = checkpoint.total_burned;
+        state.allowances = checkpoint.allowances;
+        state.redelegations = checkpoint.redelegations;
+        state.bridge_messages = checkpoint.bridge_messages;
+        state.light_clients = checkpoint.light_clients;
+        state.last_active = checkpoint.last_active;
+        state.randao_mix = checkpoint.randao_mix;
+        state.randao_seed = checkpoint.randao_seed;
+        state.dust_sweeps = checkpoint.dust_sweeps;
+        state.swept_balance = checkpoint.swept_balance;
+        state.restored_dust = checkpoint.restored_dust;
+        state.performance = checkpoint.performance;
+        state.processed_evidence = checkpoint.processed_evidence;
+        Ok(Some((checkpoint.height, checkpoint.block_hash, state)))
     }
     pub fn save_to_storage(&self) -> Result<(), String> {
         let storage = match &self.storage {
@@ -431,13 +1929,8 @@ impl AccountState {
         let data = serde_json::to_vec(&self.accounts)
             .map_err(|e| format!("Serialization error: {}", e))?;
         storage
-            .db()
-            .insert("ACCOUNT_STATE", data)
+            .put_meta("ACCOUNT_STATE", &data)
             .map_err(|e| format!("Storage error: {}", e))?;
-        storage
-            .db()
-            .flush()
-            .map_err(|e| format!("Flush error: {}", e))?;
         Ok(())
     }
     fn load_from_storage(&mut self) -> Result<(), String> {
@@ -445,7 +1938,7 @@ impl AccountState {
             Some(s) => s,
             None => return Ok(()),
         };
-        if let Ok(Some(data)) = storage.db().get("ACCOUNT_STATE") {
+        if let Ok(Some(data)) = storage.get_meta("ACCOUNT_STATE") {
             let accounts: HashMap<String, Account> = serde_json::from_slice(&data)
                 .map_err(|e| format!("Deserialization error: {}", e))?;
             self.accounts = accounts;
@@ -480,25 +1973,253 @@ impl AccountState {
             .map(|(k, v)| (k.clone(), v.nonce))
             .collect()
     }
+    pub fn get_all_locked(&self) -> HashMap<String, BTreeMap<String, u64>> {
+        self.accounts
+            .iter()
+            .filter(|(_, v)| !v.locked.is_empty())
+            .map(|(k, v)| (k.clone(), v.locked.clone()))
+            .collect()
+    }
 
     pub fn calculate_state_root(&self) -> String {
-        use sha2::{Digest, Sha256};
+        state_root_of(&self.state_root_entries())
+    }
+    // Proofs for `keys` against this state's root. A missing key is covered
+    // by the entries either side of where it would sort.
+    pub fn state_proofs(&self, keys: &[String]) -> Vec<StateProof> {
+        let entries = self.state_root_entries();
+        let mut indices = std::collections::BTreeSet::new();
+        for key in keys {
+            match entries.binary_search_by(|e| e.key.as_str().cmp(key)) {
+                Ok(i) => {
+                    indices.insert(i);
+                }
+                Err(i) => {
+                    indices.extend(i.checked_sub(1));
+                    indices.extend((i < entries.len()).then_some(i));
+                }
+            }
+        }
+        let indices: Vec<usize> = indices.into_iter().collect();
+        let leaves: Vec<String> = entries.iter().map(StateRootEntry::leaf).collect();
+        indices
+            .iter()
+            .zip(merkle_branches(&leaves, &indices))
+            .map(|(&i, branch)| StateProof {
+                entry: entries[i].clone(),
+                index: i as u64,
+                branch,
+            })
+            .collect()
+    }
+    // Every leaf of the state root, sorted by key: one per account,
+    // validator and allowance owner, plus the chain-wide records.
+    pub fn state_root_entries(&self) -> Vec<StateRootEntry> {
+        let mut entries: Vec<StateRootEntry> = self
+            .accounts
+            .iter()
+            .map(|(address, account)| StateRootEntry::new(format!("account:{}", address), account))
+            .chain(self.validators.iter().map(|(address, validator)| {
+                StateRootEntry::new(format!("validator:{}", address), validator)
+            }))
+            .chain(self.allowances.iter().map(|(owner, allowances)| {
+                StateRootEntry::new(format!("allowance:{}", owner), allowances)
+            }))
+            .chain(
+                self.last_active.iter().map(|(address, epoch)| {
+                    StateRootEntry::new(format!("active:{}", address), epoch)
+                }),
+            )
+            .collect();
+        let votes: Vec<_> = self.authority_votes.iter().collect();
+        entries.extend([
+            StateRootEntry::new(
+                "chain:supply".into(),
+                &SupplyLeaf {
+                    total_minted: self.total_minted,
+                    total_burned: self.total_burned,
+                    swept_balance: self.swept_balance,
+                },
+            ),
+            StateRootEntry::new(
+                "chain:epoch".into(),
+                &EpochLeaf {
+                    epoch_index: self.epoch_index,
+                    last_epoch_time: self.last_epoch_time,
+                },
+            ),
+            StateRootEntry::new("chain:unbonding".into(), &self.unbonding_queue),
+            StateRootEntry::new("chain:redelegations".into(), &self.redelegations),
+            StateRootEntry::new(
+                "chain:frozen_validator_set".into(),
+                &self.frozen_validator_set,
+            ),
+            StateRootEntry::new("chain:authority_votes".into(), &votes),
+            StateRootEntry::new("chain:bridge_messages".into(), &self.bridge_messages),
+            StateRootEntry::new("chain:light_clients".into(), &self.light_clients),
+            StateRootEntry::new("chain:dust_sweeps".into(), &self.dust_sweeps),
+            StateRootEntry::new("chain:restored_dust".into(), &self.restored_dust),
+            StateRootEntry::new("chain:processed_evidence".into(), &self.processed_evidence),
+            StateRootEntry::new(
+                "chain:randao".into(),
+                &RandaoLeaf {
+                    mix: self.randao_mix,
+                    seed: self.randao_seed,
+                },
+            ),
+        ]);
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+    // Rebuilds the committed parts of a state from its root entries, e.g.
+    // the chain-wide entries a snapshot carries.
+    pub fn restore_entries(&mut self, entries: &[StateRootEntry]) -> Result<(), String> {
+        for entry in entries {
+            match entry.key.split_once(':') {
+                Some(("account", address)) => {
+                    self.accounts.insert(address.to_string(), entry.parse()?);
+                }
+                Some(("validator", address)) => {
+                    self.validators.insert(address.to_string(), entry.parse()?);
+                }
+                Some(("allowance", owner)) => {
+                    self.allowances.insert(owner.to_string(), entry.parse()?);
+                }
+                Some(("active", address)) => {
+                    self.last_active.insert(address.to_string(), entry.parse()?);
+                }
+                Some(("chain", "supply")) => {
+                    let supply: SupplyLeaf = entry.parse()?;
+                    self.total_minted = supply.total_minted;
+                    self.total_burned = supply.total_burned;
+                    self.swept_balance = supply.swept_balance;
+                }
+                Some(("chain", "epoch")) => {
+                    let epoch: EpochLeaf = entry.parse()?;
+                    self.epoch_index = epoch.epoch_index;
+                    self.last_epoch_time = epoch.last_epoch_time;
+                }
+                Some(("chain", "unbonding")) => self.unbonding_queue = entry.parse()?,
+                Some(("chain", "redelegations")) => self.redelegations = entry.parse()?,
+                Some(("chain", "frozen_validator_set")) => {
+                    self.frozen_validator_set = entry.parse()?
+                }
+                Some(("chain", "authority_votes")) => {
+                    let votes: Vec<(AuthorityVote, BTreeSet<String>)> = entry.parse()?;
+                    self.authority_votes = votes.into_iter().collect();
+                }
+                Some(("chain", "bridge_messages")) => self.bridge_messages = entry.parse()?,
+                Some(("chain", "light_clients")) => self.light_clients = entry.parse()?,
+                Some(("chain", "dust_sweeps")) => self.dust_sweeps = entry.parse()?,
+                Some(("chain", "restored_dust")) => self.restored_dust = entry.parse()?,
+                Some(("chain", "processed_evidence")) => self.processed_evidence = entry.parse()?,
+                Some(("chain", "randao")) => {
+                    let randao: RandaoLeaf = entry.parse()?;
+                    self.randao_mix = randao.mix;
+                    self.randao_seed = randao.seed;
+                }
+                _ => return Err(format!("Unknown state entry {}", entry.key)),
+            }
+        }
+        Ok(())
+    }
+}
 
-        let mut sorted_accounts: Vec<_> = self.accounts.iter().collect();
-        sorted_accounts.sort_by(|a, b| a.0.cmp(b.0));
+#[derive(Serialize, Deserialize)]
+struct SupplyLeaf {
+    total_minted: u64,
+    total_burned: u64,
+    swept_balance: u64,
+}
 
-        let mut hasher = Sha256::new();
-        hasher.update(b"BDLM_STATE_V1");
+#[derive(Serialize, Deserialize)]
+struct EpochLeaf {
+    epoch_index: u64,
+    last_epoch_time: u64,
+}
 
-        for (pubkey, account) in sorted_accounts {
-            hasher.update(pubkey.as_bytes());
-            hasher.update(account.balance.to_le_bytes());
-            hasher.update(account.nonce.to_le_bytes());
+#[derive(Serialize, Deserialize)]
+struct RandaoLeaf {
+    mix: [u8; 32],
+    seed: [u8; 32],
+}
+
+// One leaf of the state root: a key naming a piece of state and that
+// state's canonical JSON encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateRootEntry {
+    pub key: String,
+    pub value: String,
+}
+
+impl StateRootEntry {
+    fn new(key: String, value: &impl Serialize) -> Self {
+        StateRootEntry {
+            key,
+            value: serde_json::to_string(value).unwrap_or_default(),
         }
+    }
 
-        hex::encode(hasher.finalize())
+    fn parse<T: serde::de::DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_str(&self.value)
+            .map_err(|e| format!("Invalid state entry {}: {}", self.key, e))
+    }
+
+    // The key is length-prefixed so no two entries share a leaf.
+    pub fn leaf(&self) -> String {
+        hash_fields(&[
+            STATE_ROOT_TAG,
+            &(self.key.len() as u64).to_le_bytes(),
+            self.key.as_bytes(),
+            self.value.as_bytes(),
+        ])
+    }
+}
+
+const STATE_ROOT_TAG: &[u8] = b"BDLM_STATE_V2";
+
+// The state root of a key-sorted entry list, e.g. one a peer sent, so it
+// can be checked against the root it claims to produce.
+pub fn state_root_of(entries: &[StateRootEntry]) -> String {
+    let leaves: Vec<String> = entries.iter().map(StateRootEntry::leaf).collect();
+    hash_fields(&[STATE_ROOT_TAG, merkle_root(&leaves).as_bytes()])
+}
+
+// One state root entry with its Merkle branch, so a peer can check a
+// single key against a block's state root without the rest of the state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProof {
+    pub entry: StateRootEntry,
+    pub index: u64,
+    pub branch: Vec<String>,
+}
+
+impl StateProof {
+    pub fn verify(&self, state_root: &str) -> bool {
+        let root = merkle_root_from_branch(&self.entry.leaf(), self.index, &self.branch);
+        hash_fields(&[STATE_ROOT_TAG, root.as_bytes()]) == state_root
+    }
+
+    // The tree repeats the last node of an odd level, so the rightmost leaf
+    // is its own sibling wherever it is a left child.
+    pub fn is_last(&self) -> bool {
+        let mut current = self.entry.leaf();
+        let mut position = self.index;
+        for sibling in &self.branch {
+            if position.is_multiple_of(2) {
+                if *sibling != current {
+                    return false;
+                }
+                current = hash_pair(&current, sibling);
+            } else {
+                current = hash_pair(sibling, &current);
+            }
+            position /= 2;
+        }
+        true
     }
 }
+
 impl Default for AccountState {
     fn default() -> Self {
         Self::new()
@@ -508,6 +2229,8 @@ impl Default for AccountState {
 mod tests {
     use super::*;
     use crate::crypto::KeyPair;
+    use crate::dust::DustProof;
+    use crate::transaction::BatchOutput;
     #[test]
     fn test_new_account() {
         let account = Account::new("pubkey123".into());
@@ -597,4 +2320,905 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Fee"));
     }
+    #[test]
+    fn test_validator_fees_accrue_until_claimed() {
+        let sender = KeyPair::generate().unwrap();
+        let validator = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let validator_pub = validator.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&sender_pub, 1000);
+        state.add_balance(&validator_pub, 10);
+        state.add_validator(validator_pub.clone(), 100);
+
+        let mut tx = Transaction::new_with_fee(sender_pub, "bob".into(), 100, 5, 0, vec![]);
+        tx.sign(&sender);
+        state.apply_block(&[tx], Some(&validator_pub)).unwrap();
+        assert_eq!(state.get_balance(&validator_pub), 10);
+        assert_eq!(state.get_pending_rewards(&validator_pub), 5);
+
+        let mut claim = Transaction::new_claim_rewards(validator_pub.clone(), 0);
+        claim.fee = 1;
+        claim.sign(&validator);
+        assert!(claim.is_valid());
+        state.validate_transaction(&claim).unwrap();
+        state.apply_transaction(&claim).unwrap();
+        assert_eq!(state.get_balance(&validator_pub), 14);
+        assert_eq!(state.get_pending_rewards(&validator_pub), 0);
+
+        let mut again = Transaction::new_claim_rewards(validator_pub.clone(), 1);
+        again.fee = 1;
+        again.sign(&validator);
+        assert!(state.validate_transaction(&again).is_err());
+    }
+    #[test]
+    fn test_staking_rules_min_stake_and_cap() {
+        let alice = KeyPair::generate().unwrap();
+        let mut state = AccountState::new();
+        state.staking_rules = StakingRules {
+            min_stake: 100,
+            max_validators: 2,
+        };
+        state.add_balance(&alice.public_key_hex(), 1000);
+        let mut small = Transaction::new_stake(alice.public_key_hex(), 50, 0);
+        small.fee = 1;
+        small.sign(&alice);
+        assert!(state
+            .validate_transaction(&small)
+            .unwrap_err()
+            .contains("minimum"));
+
+        state.add_validator("v1".into(), 300);
+        state.add_validator("v2".into(), 200);
+        state.add_validator("v3".into(), 150);
+        state.add_validator("v4".into(), 50);
+        let active = |state: &AccountState| -> Vec<String> {
+            state
+                .get_active_validators()
+                .iter()
+                .map(|v| v.address.clone())
+                .collect()
+        };
+        // The cap is only applied at the epoch boundary.
+        assert_eq!(active(&state), vec!["v1", "v2", "v3"]);
+        state.advance_epoch(0);
+        assert_eq!(active(&state), vec!["v1", "v2"]);
+        assert_eq!(state.get_total_stake(), 500);
+        assert!(!state.get_validator("v3").unwrap().active);
+        assert!(!state.get_validator("v4").unwrap().active);
+
+        // A full set does not take a new validator mid-epoch.
+        let mut stake = Transaction::new_stake(alice.public_key_hex(), 400, 0);
+        stake.fee = 1;
+        stake.sign(&alice);
+        state.validate_transaction(&stake).unwrap();
+        state.apply_transaction(&stake).unwrap();
+        assert!(!state.get_validator(&alice.public_key_hex()).unwrap().active);
+
+        state.freeze_validator_set_at(EPOCH_LENGTH);
+        assert_eq!(state.validators_for_block(EPOCH_LENGTH + 1).len(), 2);
+
+        // An evicted validator comes back once a seat frees up.
+        state.get_validator_mut("v1").unwrap().jailed = true;
+        state.get_validator_mut("v1").unwrap().jail_until = u64::MAX;
+        state.advance_epoch(0);
+        let mut expected = vec![alice.public_key_hex(), "v2".to_string()];
+        expected.sort();
+        assert_eq!(active(&state), expected);
+        state.get_validator_mut("v2").unwrap().jailed = true;
+        state.get_validator_mut("v2").unwrap().jail_until = u64::MAX;
+        state.advance_epoch(0);
+        let mut expected = vec![alice.public_key_hex(), "v3".to_string()];
+        expected.sort();
+        assert_eq!(active(&state), expected);
+
+        state
+            .get_validator_mut(&alice.public_key_hex())
+            .unwrap()
+            .stake = u64::MAX - 10;
+        let mut overflow = Transaction::new_stake(alice.public_key_hex(), 100, 1);
+        overflow.fee = 1;
+        overflow.sign(&alice);
+        assert!(state
+            .validate_transaction(&overflow)
+            .unwrap_err()
+            .contains("overflows"));
+    }
+    #[test]
+    fn test_authority_votes_tallied_by_majority() {
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate().unwrap()).collect();
+        let mut state = AccountState::new();
+        for kp in &keys {
+            state.add_balance(&kp.public_key_hex(), 100);
+            state.add_validator(kp.public_key_hex(), 0);
+        }
+        let vote = |state: &AccountState, kp: &KeyPair, vote: &AuthorityVote| {
+            let from = kp.public_key_hex();
+            let mut tx = Transaction::new_vote(from.clone(), vote, state.get_nonce(&from));
+            tx.fee = 1;
+            tx.sign(kp);
+            assert!(tx.is_valid());
+            tx
+        };
+
+        let add = AuthorityVote::Add("dave".into());
+        let tx = vote(&state, &keys[0], &add);
+        state.validate_transaction(&tx).unwrap();
+        state.apply_transaction(&tx).unwrap();
+        assert!(state.get_validator("dave").is_none());
+        let again = vote(&state, &keys[0], &add);
+        assert!(state.validate_transaction(&again).is_err());
+
+        let tx = vote(&state, &keys[1], &add);
+        state.validate_transaction(&tx).unwrap();
+        state.apply_transaction(&tx).unwrap();
+        assert!(state.get_validator("dave").unwrap().active);
+        assert!(state.authority_votes.is_empty());
+        assert_eq!(state.get_active_validators().len(), 4);
+
+        let remove = AuthorityVote::Remove(keys[2].public_key_hex());
+        for kp in &keys[..2] {
+            let tx = vote(&state, kp, &remove);
+            state.validate_transaction(&tx).unwrap();
+            state.apply_transaction(&tx).unwrap();
+        }
+        assert!(
+            state
+                .get_validator(&keys[2].public_key_hex())
+                .unwrap()
+                .active
+        );
+        assert_eq!(state.authority_votes[&remove].len(), 2);
+        let outsider = KeyPair::generate().unwrap();
+        state.add_balance(&outsider.public_key_hex(), 100);
+        let tx = vote(&state, &outsider, &remove);
+        assert!(state.validate_transaction(&tx).is_err());
+    }
+    #[test]
+    fn test_missed_slots_jail_until_unjail() {
+        let kp = KeyPair::generate().unwrap();
+        let addr = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&addr, 100);
+        state.add_validator(addr.clone(), 0);
+        state.add_validator("other".into(), 0);
+
+        state.record_missed_slots(Some("other"), std::slice::from_ref(&addr), 3);
+        state.record_missed_slots(Some(&addr), &[], 3);
+        assert_eq!(state.get_validator(&addr).unwrap().missed_slots, 0);
+        for _ in 0..3 {
+            state.record_missed_slots(Some("other"), std::slice::from_ref(&addr), 3);
+        }
+        let jailed = state.get_validator(&addr).unwrap();
+        assert!(jailed.jailed && !jailed.active);
+        assert_eq!(state.get_active_validators().len(), 1);
+        state.advance_epoch(u64::MAX as u128);
+        assert!(state.get_validator(&addr).unwrap().jailed);
+
+        let mut unjail = Transaction::new_unjail(addr.clone(), 0);
+        unjail.fee = 1;
+        unjail.sign(&kp);
+        assert!(unjail.is_valid());
+        state.validate_transaction(&unjail).unwrap();
+        state.apply_transaction(&unjail).unwrap();
+        let validator = state.get_validator(&addr).unwrap();
+        assert!(validator.active && !validator.jailed);
+        assert_eq!(validator.missed_slots, 0);
+    }
+    #[test]
+    fn test_validator_performance_tracking() {
+        let mut state = AccountState::new();
+        state.add_validator("alice".into(), 3_000);
+        state.add_validator("bob".into(), 1_000);
+        for height in 1..=4 {
+            state.record_proposal(height, Some("alice"));
+        }
+        state.record_proposal(0, Some("bob"));
+
+        let snapshot = state.active_validator_snapshot(0);
+        let alice_idx = snapshot
+            .validators
+            .iter()
+            .position(|v| v.address == "alice")
+            .unwrap();
+        let cert = FinalityCert {
+            epoch: 0,
+            checkpoint_height: 32,
+            checkpoint_hash: "cp".into(),
+            agg_sig_bls: Vec::new(),
+            bitmap: vec![1 << alice_idx],
+            set_hash: snapshot.set_hash.clone(),
+            state_root: String::new(),
+        };
+        state.record_finality_votes(&cert, &snapshot);
+        state.slash_offender("bob", 0.1, "");
+
+        let alice = state.validator_info("alice").unwrap();
+        assert_eq!((alice.blocks_proposed, alice.blocks_expected), (4, 3.0));
+        assert_eq!(alice.last_proposed_block, Some(4));
+        assert_eq!(
+            (alice.votes_signed, alice.votes_expected, alice.uptime),
+            (1, 1, 1.0)
+        );
+        let bob = state.validator_info("bob").unwrap();
+        assert_eq!((bob.blocks_proposed, bob.blocks_expected), (0, 1.0));
+        assert_eq!((bob.proposal_rate, bob.uptime), (0.0, 0.0));
+        assert!(bob.slashed);
+        assert_eq!(bob.slashes, 1);
+        assert!(state.validator_info("carol").is_none());
+
+        let mut rebuilt = AccountState::new();
+        rebuilt.carry_finality_votes(&state);
+        assert_eq!(rebuilt.performance["alice"].checkpoints_signed, 1);
+        assert_eq!(rebuilt.performance["alice"].blocks_proposed, 0);
+    }
+    #[test]
+    fn test_burn_reduces_total_supply() {
+        let kp = KeyPair::generate().unwrap();
+        let addr = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&addr, 1000);
+        assert_eq!(state.total_supply(), 1000);
+
+        let mut burn = Transaction::new_burn(addr.clone(), 300, 0);
+        burn.fee = 1;
+        burn.sign(&kp);
+        assert!(burn.is_valid());
+        state.apply_block(&[burn], Some("producer")).unwrap();
+        assert_eq!(state.get_balance(&addr), 699);
+        assert_eq!(state.get_balance("producer"), 1);
+        assert_eq!(state.total_burned, 300);
+        assert_eq!(state.total_supply(), 700);
+        assert_eq!(state.accounted_supply(), state.total_supply());
+
+        let mut empty = Transaction::new_burn(addr, 0, 1);
+        empty.sign(&kp);
+        assert!(!empty.is_valid());
+    }
+    #[test]
+    fn test_transfer_from_spends_allowance() {
+        let owner = KeyPair::generate().unwrap();
+        let spender = KeyPair::generate().unwrap();
+        let owner_pub = owner.public_key_hex();
+        let spender_pub = spender.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&owner_pub, 1000);
+        state.add_balance(&spender_pub, 10);
+
+        let mut approve = Transaction::new_approve(owner_pub.clone(), spender_pub.clone(), 300, 0);
+        approve.fee = 1;
+        approve.sign(&owner);
+        assert!(approve.is_valid());
+        state.validate_transaction(&approve).unwrap();
+        state.apply_transaction(&approve).unwrap();
+        assert_eq!(state.get_allowance(&owner_pub, &spender_pub), 300);
+        assert_eq!(state.get_balance(&owner_pub), 999);
+
+        let mut spend =
+            Transaction::new_transfer_from(spender_pub.clone(), &owner_pub, "bob".into(), 200, 0);
+        spend.fee = 1;
+        spend.sign(&spender);
+        assert!(spend.is_valid());
+        state.validate_transaction(&spend).unwrap();
+        state.apply_transaction(&spend).unwrap();
+        assert_eq!(state.get_balance("bob"), 200);
+        assert_eq!(state.get_balance(&owner_pub), 799);
+        assert_eq!(state.get_balance(&spender_pub), 9);
+        assert_eq!(state.get_allowance(&owner_pub, &spender_pub), 100);
+
+        let mut overspend =
+            Transaction::new_transfer_from(spender_pub.clone(), &owner_pub, "bob".into(), 101, 1);
+        overspend.fee = 1;
+        overspend.sign(&spender);
+        assert!(state.validate_transaction(&overspend).is_err());
+        assert!(state.apply_transaction(&overspend).is_err());
+
+        let mut revoke = Transaction::new_approve(owner_pub.clone(), spender_pub.clone(), 0, 1);
+        revoke.fee = 1;
+        revoke.sign(&owner);
+        state.apply_transaction(&revoke).unwrap();
+        assert_eq!(state.get_allowance(&owner_pub, &spender_pub), 0);
+        assert!(state.allowances.is_empty());
+    }
+    #[test]
+    fn test_batch_transfer_is_atomic() {
+        let kp = KeyPair::generate().unwrap();
+        let addr = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&addr, 1000);
+        let outputs = vec![
+            BatchOutput {
+                to: "bob".into(),
+                amount: 100,
+            },
+            BatchOutput {
+                to: "carol".into(),
+                amount: 250,
+            },
+        ];
+
+        let mut cheap = Transaction::new_batch(addr.clone(), &outputs, 0);
+        cheap.fee = 1;
+        cheap.sign(&kp);
+        assert!(state.validate_transaction(&cheap).is_err());
+
+        let mut batch = Transaction::new_batch(addr.clone(), &outputs, 0);
+        batch.fee = 2;
+        batch.sign(&kp);
+        assert!(batch.is_valid());
+        state.validate_transaction(&batch).unwrap();
+        state.apply_transaction(&batch).unwrap();
+        assert_eq!(state.get_balance("bob"), 100);
+        assert_eq!(state.get_balance("carol"), 250);
+        assert_eq!(state.get_balance(&addr), 648);
+        assert_eq!(state.get_nonce(&addr), 1);
+
+        let mut tampered = Transaction::new_batch(addr, &outputs, 1);
+        tampered.amount = 1;
+        tampered.fee = 2;
+        tampered.sign(&kp);
+        assert!(!tampered.is_valid());
+        assert!(state.apply_transaction(&tampered).is_err());
+        assert_eq!(state.get_balance("bob"), 100);
+    }
+    #[test]
+    fn test_stake_is_locked_by_account() {
+        let kp = KeyPair::generate().unwrap();
+        let alice = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&alice, 1_000);
+        let supply = state.accounted_supply();
+
+        let mut stake = Transaction::new_stake(alice.clone(), 600, 0);
+        stake.fee = 1;
+        stake.sign(&kp);
+        state.validate_transaction(&stake).unwrap();
+        state.apply_transaction(&stake).unwrap();
+        assert_eq!(state.get_balance(&alice), 399);
+        assert_eq!(state.locked_stake(&alice, &alice), 600);
+        assert_eq!(state.get_validator(&alice).unwrap().stake, 600);
+        assert_eq!(state.accounted_supply(), supply - 1);
+        state.check_stake_locks().unwrap();
+
+        let mut unstake = Transaction::new_with_chain_id(
+            alice.clone(),
+            String::new(),
+            700,
+            1,
+            1,
+            vec![],
+            crate::transaction::DEFAULT_CHAIN_ID,
+            TransactionType::Unstake,
+        );
+        unstake.sign(&kp);
+        assert!(state.validate_transaction(&unstake).is_err());
+        assert!(state.clone().apply_transaction(&unstake).is_err());
+
+        unstake.amount = 600;
+        unstake.hash = unstake.calculate_hash();
+        unstake.sign(&kp);
+        state.apply_transaction(&unstake).unwrap();
+        assert_eq!(state.locked_stake(&alice, &alice), 0);
+        assert!(state.accounts[&alice].locked.is_empty());
+        assert_eq!(state.get_validator(&alice).unwrap().stake, 0);
+        assert_eq!(state.accounted_supply(), supply - 2);
+        state.check_stake_locks().unwrap();
+
+        state.get_validator_mut(&alice).unwrap().stake = 5;
+        assert!(state.check_stake_locks().is_err());
+    }
+    #[test]
+    fn test_slashing_rewards_including_producer() {
+        use crate::block::{Block, BlockHeader};
+
+        let mut state = AccountState::new();
+        state.add_validator("offender".into(), 10_000);
+        let mut block = Block::new(5, "prev".into(), vec![]);
+        block.producer = Some("offender".into());
+        let header = BlockHeader::from_block(&block);
+        let evidence = SlashingEvidence::new(header.clone(), header, vec![], vec![]);
+
+        state.apply_slashing(
+            &[evidence.clone().with_reporter("whistleblower".into())],
+            0.1,
+            Some("producer"),
+        );
+        assert_eq!(state.get_validator("offender").unwrap().stake, 9_000);
+        assert_eq!(state.get_balance("whistleblower"), 0);
+        assert_eq!(state.get_balance("producer"), 100);
+        assert_eq!(state.total_burned, 900);
+
+        state.add_validator("offender".into(), 10_000);
+        state.apply_slashing(&[evidence], 0.1, Some("offender"));
+        assert_eq!(state.get_balance("offender"), 0);
+    }
+    #[test]
+    fn test_double_sign_tombstones_permanently() {
+        use crate::block::{Block, BlockHeader};
+
+        let kp = KeyPair::generate().unwrap();
+        let addr = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&addr, 100);
+        state.add_validator(addr.clone(), 1_000);
+        state.add_validator("idle".into(), 1_000);
+        for name in [&addr, "idle"] {
+            let validator = state.get_validator_mut(name).unwrap();
+            validator.jailed = true;
+            validator.active = false;
+            validator.jail_until = 0;
+        }
+
+        let mut block = Block::new(5, "prev".into(), vec![]);
+        block.producer = Some(addr.clone());
+        let header = BlockHeader::from_block(&block);
+        let evidence = SlashingEvidence::new(header.clone(), header, vec![], vec![]);
+        state.apply_slashing(&[evidence], 0.1, None);
+        state.advance_epoch(u64::MAX as u128);
+
+        let idle = state.get_validator("idle").unwrap();
+        assert!(idle.active && !idle.jailed && !idle.tombstoned);
+        let tombstoned = state.get_validator(&addr).unwrap();
+        assert!(tombstoned.tombstoned && tombstoned.jailed && !tombstoned.active);
+        assert_eq!(tombstoned.jail_until, u64::MAX);
+        assert_eq!(tombstoned.effective_stake(), 0);
+
+        let mut unjail = Transaction::new_unjail(addr.clone(), 0);
+        unjail.fee = 1;
+        unjail.sign(&kp);
+        assert_eq!(
+            state.validate_transaction(&unjail).unwrap_err(),
+            "Tombstoned validators cannot unjail"
+        );
+        let mut stake = Transaction::new_stake(addr.clone(), 10, 0);
+        stake.fee = 1;
+        stake.sign(&kp);
+        state.apply_transaction(&stake).unwrap();
+        assert!(!state.get_validator(&addr).unwrap().active);
+        assert_eq!(state.get_active_validators().len(), 1);
+        assert!(state.validator_info(&addr).unwrap().tombstoned);
+    }
+    #[test]
+    fn test_redelegate_moves_stake_and_keeps_source_liability() {
+        use crate::block::{Block, BlockHeader};
+
+        let kp = KeyPair::generate().unwrap();
+        let alice = kp.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&alice, 100);
+        state.add_validator(alice.clone(), 1_000);
+        state.add_validator("bob".into(), 1_000);
+        state.add_validator("carol".into(), 1_000);
+
+        let mut tx = Transaction::new_redelegate(alice.clone(), &alice, "bob".into(), 400, 0);
+        tx.fee = 1;
+        tx.sign(&kp);
+        assert!(tx.is_valid());
+        state.validate_transaction(&tx).unwrap();
+        state.apply_transaction(&tx).unwrap();
+        assert_eq!(state.get_validator(&alice).unwrap().stake, 600);
+        assert_eq!(state.get_validator("bob").unwrap().stake, 1_400);
+        assert_eq!(state.get_delegation(&alice, "bob"), 400);
+        assert_eq!(state.bonded_by("bob", "bob"), 1_000);
+        assert!(state.unbonding_queue.is_empty());
+
+        let mut hop = Transaction::new_redelegate(alice.clone(), "bob", "carol".into(), 100, 1);
+        hop.fee = 1;
+        hop.sign(&kp);
+        assert!(state.validate_transaction(&hop).is_err());
+
+        let mut block = Block::new(5, "prev".into(), vec![]);
+        block.producer = Some(alice.clone());
+        let header = BlockHeader::from_block(&block);
+        let evidence = SlashingEvidence::new(header.clone(), header, vec![], vec![]);
+        state.apply_slashing(&[evidence], 0.1, None);
+        assert_eq!(state.get_validator(&alice).unwrap().stake, 540);
+        assert_eq!(state.get_delegation(&alice, "bob"), 360);
+        assert_eq!(state.get_validator("bob").unwrap().stake, 1_360);
+        assert_eq!(state.locked_stake(&alice, &alice), 540);
+        state.check_stake_locks().unwrap();
+
+        for _ in 0..UNBONDING_EPOCHS {
+            state.advance_epoch(0);
+        }
+        assert!(state.redelegations.is_empty());
+        state.validate_transaction(&hop).unwrap();
+
+        let mut undelegate = Transaction::new_with_chain_id(
+            alice.clone(),
+            "bob".into(),
+            360,
+            1,
+            1,
+            vec![],
+            crate::transaction::DEFAULT_CHAIN_ID,
+            TransactionType::Unstake,
+        );
+        undelegate.sign(&kp);
+        state.validate_transaction(&undelegate).unwrap();
+        state.apply_transaction(&undelegate).unwrap();
+        assert_eq!(state.get_delegation(&alice, "bob"), 0);
+        assert_eq!(state.get_validator("bob").unwrap().stake, 1_000);
+        assert_eq!(state.unbonding_queue.len(), 1);
+    }
+    #[test]
+    fn test_epoch_rewards_follow_effective_stake() {
+        let mut state = AccountState::new();
+        state.reward_config = RewardConfig {
+            annual_rate_bps: 1000,
+            epochs_per_year: 10,
+        };
+        state.add_validator("alice".into(), 10_000);
+        state.add_validator("bob".into(), 30_000);
+        state.add_validator("carol".into(), 20_000);
+        state.get_validator_mut("carol").unwrap().jailed = true;
+        state.add_validator("dave".into(), 20_000);
+        state.get_validator_mut("dave").unwrap().slashed = true;
+
+        let receipt = state.advance_epoch(0);
+        assert_eq!(receipt.epoch, 0);
+        assert_eq!(receipt.minted, 400);
+        assert_eq!(state.get_pending_rewards("alice"), 100);
+        assert_eq!(state.get_pending_rewards("bob"), 300);
+        assert_eq!(state.get_pending_rewards("carol"), 0);
+        assert_eq!(state.get_pending_rewards("dave"), 0);
+        assert_eq!(state.total_minted, 400);
+        assert_eq!(state.epoch_index, 1);
+    }
+    #[test]
+    fn test_treasury_share_of_fees_and_rewards() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut state = AccountState::new();
+        state.treasury = TreasuryConfig {
+            address: "treasury".into(),
+            fee_bps: 2_000,
+            reward_bps: 1_000,
+        };
+        state.reward_config = RewardConfig {
+            annual_rate_bps: 1000,
+            epochs_per_year: 10,
+        };
+        state.add_balance(&sender_pub, 1000);
+        state.add_validator("val".into(), 10_000);
+
+        let mut tx = Transaction::new_with_fee(sender_pub, "bob".into(), 100, 10, 0, vec![]);
+        tx.sign(&sender);
+        state.apply_block(&[tx], Some("val")).unwrap();
+        assert_eq!(state.get_balance("treasury"), 2);
+        assert_eq!(state.get_pending_rewards("val"), 8);
+
+        let receipt = state.advance_epoch(0);
+        assert_eq!(receipt.minted, 100);
+        assert_eq!(receipt.treasury, 10);
+        assert_eq!(receipt.rewards, vec![("val".to_string(), 90)]);
+        assert_eq!(state.get_balance("treasury"), 12);
+        assert_eq!(state.get_pending_rewards("val"), 98);
+        assert_eq!(
+            state.treasury_credits,
+            vec![
+                TreasuryCredit {
+                    source: TreasurySource::Fees,
+                    amount: 2
+                },
+                TreasuryCredit {
+                    source: TreasurySource::Rewards,
+                    amount: 10
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_validator_set_frozen_for_epoch() {
+        let mut state = AccountState::new();
+        state.add_validator("alice".into(), 100);
+        state.freeze_validator_set_at(EPOCH_LENGTH);
+        let frozen_hash = state.validator_set_for_block(EPOCH_LENGTH + 1).set_hash;
+
+        state.add_validator("bob".into(), 100);
+        let mid_epoch = state.validators_for_block(EPOCH_LENGTH + 1);
+        assert_eq!(mid_epoch.len(), 1);
+        assert_eq!(mid_epoch[0].address, "alice");
+        assert_eq!(
+            state.validator_set_for_block(EPOCH_LENGTH + 5).set_hash,
+            frozen_hash
+        );
+
+        let next_epoch = state.validator_set_for_block(2 * EPOCH_LENGTH);
+        assert_eq!(next_epoch.validators.len(), 2);
+        assert_ne!(next_epoch.set_hash, frozen_hash);
+    }
+    #[test]
+    fn test_bridge_lock_release_and_mint() {
+        use crate::bridge::{BridgeTransfer, ForeignChain, InboundKind, InboundMessage};
+        let user = KeyPair::generate().unwrap();
+        let relayer = KeyPair::generate().unwrap();
+        let validator = KeyPair::generate().unwrap();
+        let addr = user.public_key_hex();
+        let mut state = AccountState::new();
+        state.add_balance(&addr, 1000);
+        state.add_balance(&relayer.public_key_hex(), 10);
+        state.add_validator(validator.public_key_hex(), 1000);
+
+        let transfer = BridgeTransfer {
+            destination: ForeignChain::Evm(1),
+            recipient: "0xbeef".into(),
+        };
+        let mut lock = Transaction::new_bridge_lock(addr.clone(), &transfer, 300, 0);
+        lock.fee = 1;
+        lock.sign(&user);
+        assert!(lock.is_valid());
+        state.apply_block(&[lock], None).unwrap();
+        assert_eq!(state.get_balance(BRIDGE_ESCROW), 300);
+        assert_eq!(state.get_balance(&addr), 699);
+
+        let inbound = |kind, amount| {
+            let mut signed = SignedInbound::new(
+                InboundMessage {
+                    source: ForeignChain::Evm(1),
+                    source_tx: format!("0x{:?}{}", kind, amount),
+                    nonce: 0,
+                    recipient: "bob".into(),
+                    amount,
+                    kind,
+                    dest_chain_id: crate::transaction::DEFAULT_CHAIN_ID,
+                },
+                &relayer,
+            );
+            signed.attest(&validator);
+            signed
+        };
+        let relay = |signed: &SignedInbound, nonce| {
+            let mut tx = Transaction::new_bridge_inbound(relayer.public_key_hex(), signed, nonce);
+            tx.fee = 1;
+            tx.sign(&relayer);
+            tx
+        };
+
+        let release = inbound(InboundKind::Release, 200);
+        let tx = relay(&release, 0);
+        assert!(tx.is_valid());
+        state.validate_transaction(&tx).unwrap();
+        state.apply_block(&[tx], None).unwrap();
+        assert_eq!(state.get_balance("bob"), 200);
+        assert_eq!(state.get_balance(BRIDGE_ESCROW), 100);
+
+        let replay = relay(&release, 1);
+        assert!(state
+            .validate_transaction(&replay)
+            .unwrap_err()
+            .contains("already processed"));
+        let overdrawn = relay(&inbound(InboundKind::Release, 500), 1);
+        assert!(state.validate_transaction(&overdrawn).is_err());
+        let mut unattested = inbound(InboundKind::Mint, 50);
+        unattested.attestations.clear();
+        assert!(state.validate_transaction(&relay(&unattested, 1)).is_err());
+
+        let (minted_before, supply_before) = (state.total_minted, state.accounted_supply());
+        let mint = relay(&inbound(InboundKind::Mint, 50), 1);
+        state.apply_block(&[mint], Some("producer")).unwrap();
+        assert_eq!(state.get_balance("bob"), 250);
+        assert_eq!(state.total_minted, minted_before + 50);
+        assert_eq!(state.accounted_supply(), supply_before + 50);
+    }
+    #[test]
+    fn test_light_client_proof_backs_bridge_mint() {
+        use crate::block::{Block, BlockHeader};
+        use crate::bridge::{BridgeTransfer, InboundKind, InboundMessage};
+        use crate::chain_config::FINALITY_CHECKPOINT_INTERVAL;
+        use crate::consensus::finality::test_keys::{entry, sign_cert};
+        use crate::consensus::finality::FinalityCert;
+        use crate::light_client::{HeaderUpdate, TxInclusionProof};
+        let validator = KeyPair::generate().unwrap();
+        let relayer = KeyPair::generate().unwrap();
+        let mut state = AccountState::new();
+        state.add_validator(validator.public_key_hex(), 1000);
+        state.add_balance(&validator.public_key_hex(), 10);
+        state.add_balance(&relayer.public_key_hex(), 10);
+
+        let remote_set = ValidatorSetSnapshot::new(0, vec![entry("remote", 100, 7)]);
+        let transfer = BridgeTransfer {
+            destination: ForeignChain::Budlum(crate::transaction::DEFAULT_CHAIN_ID),
+            recipient: "bob".into(),
+        };
+        let mut lock = Transaction::new_bridge_lock("alice".into(), &transfer, 40, 0);
+        lock.chain_id = 7;
+        lock.hash = lock.calculate_hash();
+        let mut block =
+            Block::new_with_chain_id(FINALITY_CHECKPOINT_INTERVAL, String::new(), vec![lock], 7);
+        block.validator_set_hash = remote_set.set_hash.clone();
+        block.tx_root = block.calculate_tx_root();
+        block.hash = block.calculate_hash();
+        let mut cert = FinalityCert {
+            epoch: 0,
+            checkpoint_height: block.index,
+            checkpoint_hash: block.hash.clone(),
+            agg_sig_bls: Vec::new(),
+            bitmap: vec![1],
+            set_hash: remote_set.set_hash.clone(),
+            state_root: String::new(),
+        };
+        sign_cert(&mut cert, &remote_set, 7);
+        let update = HeaderUpdate {
+            header: BlockHeader::from_block(&block),
+            cert,
+            validator_set: Some(remote_set),
+        };
+        let create = LightClientMessage::Create {
+            chain_id: 7,
+            update,
+        };
+        let mut by_relayer = Transaction::new_light_client(relayer.public_key_hex(), &create, 0);
+        by_relayer.fee = 1;
+        by_relayer.sign(&relayer);
+        assert!(by_relayer.is_valid());
+        assert!(state.validate_transaction(&by_relayer).is_err());
+        let mut tx = Transaction::new_light_client(validator.public_key_hex(), &create, 0);
+        tx.fee = 1;
+        tx.sign(&validator);
+        state.apply_block(&[tx], None).unwrap();
+        assert_eq!(state.light_clients[&7].latest_height, block.index);
+
+        let proof = TxInclusionProof::build(&block, 0).unwrap();
+        let mut inbound = SignedInbound::new(
+            InboundMessage {
+                source: ForeignChain::Budlum(7),
+                source_tx: proof.tx.hash.clone(),
+                nonce: 0,
+                recipient: "bob".into(),
+                amount: 40,
+                kind: InboundKind::Mint,
+                dest_chain_id: crate::transaction::DEFAULT_CHAIN_ID,
+            },
+            &relayer,
+        );
+        inbound.proof = Some(proof);
+        let mut mint = Transaction::new_bridge_inbound(relayer.public_key_hex(), &inbound, 0);
+        mint.fee = 1;
+        mint.sign(&relayer);
+        state.validate_transaction(&mint).unwrap();
+        state.apply_block(&[mint], None).unwrap();
+        assert_eq!(state.get_balance("bob"), 40);
+    }
+    #[test]
+    fn test_inactive_dust_swept_and_restored() {
+        let payer = KeyPair::generate().unwrap();
+        let payer_pub = payer.public_key_hex();
+        let mut state = AccountState::new();
+        state.dust_sweep = DustSweepConfig {
+            threshold: 10,
+            inactive_epochs: 2,
+        };
+        state.add_balance(&payer_pub, 1_000);
+        state.add_balance("dust", 5);
+        state.add_balance("empty", 0);
+        state.add_balance("rich", 50);
+        state.add_balance("used", 5);
+        state.get_or_create("used").nonce = 1;
+        state.add_validator("dust_validator".into(), 0);
+        state.add_balance("dust_validator", 1);
+
+        state.advance_epoch(0);
+        assert!(state.dust_sweeps.is_empty());
+        state.get_or_create(&payer_pub);
+        state.advance_epoch(0);
+        assert_eq!(state.epoch_index, 2);
+        assert!(state.accounts.get("dust").is_none());
+        assert!(state.accounts.get("empty").is_none());
+        for kept in ["rich", "used", "dust_validator", payer_pub.as_str()] {
+            assert!(state.accounts.contains_key(kept), "{} swept", kept);
+        }
+        assert_eq!(state.swept_balance, 5);
+        assert_eq!(state.accounted_supply(), state.total_supply());
+
+        let (epoch, swept) = state.swept_dust.pop().unwrap();
+        assert_eq!(epoch, 2);
+        let proof = DustProof::build(epoch, &swept, "dust").unwrap();
+        let restore = |state: &AccountState, proof: &DustProof| {
+            let mut tx = Transaction::new_restore_account(
+                payer_pub.clone(),
+                proof,
+                state.get_nonce(&payer_pub),
+            );
+            tx.fee = 1;
+            tx.sign(&payer);
+            assert!(tx.is_valid());
+            tx
+        };
+
+        let mut forged = proof.clone();
+        forged.account.balance = 500;
+        assert!(state
+            .validate_transaction(&restore(&state, &forged))
+            .is_err());
+
+        let tx = restore(&state, &proof);
+        state.validate_transaction(&tx).unwrap();
+        state.apply_block(&[tx], None).unwrap();
+        assert_eq!(state.get_balance("dust"), 5);
+        assert_eq!(state.swept_balance, 0);
+        assert_eq!(state.accounted_supply(), state.total_supply());
+        assert!(state
+            .validate_transaction(&restore(&state, &proof))
+            .is_err());
+
+        state.advance_epoch(0);
+        assert!(state.accounts.contains_key("dust"));
+    }
+
+    #[test]
+    fn test_state_root_commits_every_record() {
+        let mut state = AccountState::new();
+        state.add_balance("alice", 1_000);
+        state
+            .validators
+            .insert("val".into(), Validator::new("val".into(), 0));
+        let root = state.calculate_state_root();
+
+        let changes: Vec<fn(&mut AccountState)> = vec![
+            |s| {
+                s.get_or_create("alice").locked.insert("val".into(), 1);
+            },
+            |s| s.validators.get_mut("val").unwrap().jailed = true,
+            |s| s.validators.get_mut("val").unwrap().accrued_rewards = 1,
+            |s| {
+                s.allowances
+                    .entry("alice".into())
+                    .or_default()
+                    .insert("bob".into(), 5);
+            },
+            |s| s.total_burned = 1,
+            |s| s.epoch_index = 1,
+            |s| {
+                s.unbonding_queue.push(UnbondingEntry {
+                    address: "alice".into(),
+                    amount: 1,
+                    release_epoch: 7,
+                })
+            },
+            |s| {
+                s.redelegations.push(RedelegationEntry {
+                    delegator: "alice".into(),
+                    source: "val".into(),
+                    destination: "other".into(),
+                    amount: 1,
+                    completion_epoch: 7,
+                })
+            },
+            |s| s.frozen_validator_set = Some(s.active_validator_snapshot(0)),
+            |s| {
+                s.authority_votes
+                    .entry(AuthorityVote::Add("bob".into()))
+                    .or_default()
+                    .insert("val".into());
+            },
+            |s| {
+                s.bridge_messages.insert("msg".into());
+            },
+            |s| s.randao_mix = [1; 32],
+            |s| {
+                s.last_active.insert("alice".into(), 3);
+            },
+            |s| {
+                s.dust_sweeps.insert(1, "root".into());
+            },
+            |s| {
+                s.restored_dust.insert("leaf".into());
+            },
+        ];
+        for change in changes {
+            let mut changed = state.clone();
+            change(&mut changed);
+            assert_ne!(changed.calculate_state_root(), root);
+
+            let mut restored = AccountState::new();
+            restored
+                .restore_entries(&changed.state_root_entries())
+                .unwrap();
+            assert_eq!(
+                restored.calculate_state_root(),
+                changed.calculate_state_root()
+            );
+        }
+    }
 }
@@ -0,0 +1,203 @@
+use crate::chain_config::ChainLimits;
+use crate::Block;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+pub const ANALYTICS_WINDOW: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FeePercentiles {
+    pub p10: u64,
+    pub p25: u64,
+    pub p50: u64,
+    pub p75: u64,
+    pub p90: u64,
+}
+
+impl FeePercentiles {
+    // Nearest-rank percentiles over an already sorted sample.
+    pub(crate) fn from_sorted(fees: &[u64]) -> Self {
+        let rank = |p: usize| {
+            if fees.is_empty() {
+                return 0;
+            }
+            fees[((p * fees.len()).div_ceil(100)).max(1) - 1]
+        };
+        FeePercentiles {
+            p10: rank(10),
+            p25: rank(25),
+            p50: rank(50),
+            p75: rank(75),
+            p90: rank(90),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockStats {
+    pub height: u64,
+    pub timestamp: u128,
+    pub tx_count: usize,
+    pub size_bytes: usize,
+    pub byte_utilization: f64,
+    pub tx_utilization: f64,
+    pub total_fees: u64,
+    pub avg_fee: f64,
+    pub fees: FeePercentiles,
+    #[serde(skip)]
+    sorted_fees: Vec<u64>,
+}
+
+impl BlockStats {
+    pub fn from_block(block: &Block, limits: &ChainLimits) -> Self {
+        let mut sorted_fees: Vec<u64> = block.transactions.iter().map(|tx| tx.fee).collect();
+        sorted_fees.sort_unstable();
+        let total_fees: u64 = sorted_fees.iter().sum();
+        let size_bytes = serde_json::to_vec(block)
+            .map(|b| b.len())
+            .unwrap_or_default();
+        BlockStats {
+            height: block.index,
+            timestamp: block.timestamp,
+            tx_count: block.transactions.len(),
+            size_bytes,
+            byte_utilization: size_bytes as f64 / limits.max_block_size.max(1) as f64,
+            tx_utilization: block.transactions.len() as f64
+                / limits.max_transactions_per_block.max(1) as f64,
+            total_fees,
+            avg_fee: if sorted_fees.is_empty() {
+                0.0
+            } else {
+                total_fees as f64 / sorted_fees.len() as f64
+            },
+            fees: FeePercentiles::from_sorted(&sorted_fees),
+            sorted_fees,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChainStats {
+    pub blocks: usize,
+    pub from_height: u64,
+    pub to_height: u64,
+    pub tx_count: usize,
+    pub avg_txs_per_block: f64,
+    pub avg_byte_utilization: f64,
+    pub avg_tx_utilization: f64,
+    pub total_fees: u64,
+    pub avg_fee: f64,
+    pub fees: FeePercentiles,
+}
+
+// Rolling per-block statistics over the last ANALYTICS_WINDOW blocks,
+// updated as blocks are imported so queries never rescan the chain.
+#[derive(Debug, Clone, Default)]
+pub struct ChainAnalytics {
+    blocks: VecDeque<BlockStats>,
+}
+
+impl ChainAnalytics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, block: &Block, limits: &ChainLimits) {
+        self.truncate_from(block.index);
+        self.blocks.push_back(BlockStats::from_block(block, limits));
+        while self.blocks.len() > ANALYTICS_WINDOW {
+            self.blocks.pop_front();
+        }
+    }
+
+    // Drops stats for `height` and above, e.g. for blocks replaced by a reorg.
+    pub fn truncate_from(&mut self, height: u64) {
+        while self.blocks.back().is_some_and(|b| b.height >= height) {
+            self.blocks.pop_back();
+        }
+    }
+
+    pub fn recent(&self, count: usize) -> Vec<BlockStats> {
+        let skip = self.blocks.len().saturating_sub(count);
+        self.blocks.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn summary(&self, count: usize) -> Option<ChainStats> {
+        let skip = self.blocks.len().saturating_sub(count);
+        let window: Vec<&BlockStats> = self.blocks.iter().skip(skip).collect();
+        let (first, last) = (window.first()?, window.last()?);
+        let blocks = window.len();
+        let tx_count: usize = window.iter().map(|b| b.tx_count).sum();
+        let total_fees: u64 = window.iter().map(|b| b.total_fees).sum();
+        let mut fees: Vec<u64> = window
+            .iter()
+            .flat_map(|b| b.sorted_fees.iter().copied())
+            .collect();
+        fees.sort_unstable();
+        Some(ChainStats {
+            blocks,
+            from_height: first.height,
+            to_height: last.height,
+            tx_count,
+            avg_txs_per_block: tx_count as f64 / blocks as f64,
+            avg_byte_utilization: window.iter().map(|b| b.byte_utilization).sum::<f64>()
+                / blocks as f64,
+            avg_tx_utilization: window.iter().map(|b| b.tx_utilization).sum::<f64>()
+                / blocks as f64,
+            total_fees,
+            avg_fee: if tx_count == 0 {
+                0.0
+            } else {
+                total_fees as f64 / tx_count as f64
+            },
+            fees: FeePercentiles::from_sorted(&fees),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+
+    fn block_with_fees(index: u64, fees: &[u64]) -> Block {
+        let txs = fees
+            .iter()
+            .enumerate()
+            .map(|(i, fee)| {
+                Transaction::new_with_fee("alice".into(), "bob".into(), 1, *fee, i as u64, vec![])
+            })
+            .collect();
+        Block::new(index, String::new(), txs)
+    }
+
+    #[test]
+    fn test_fee_percentiles_and_rolling_window() {
+        let limits = ChainLimits::default();
+        let mut analytics = ChainAnalytics::new();
+        assert!(analytics.summary(10).is_none());
+
+        analytics.record(&block_with_fees(1, &[5, 1, 3, 2, 4]), &limits);
+        analytics.record(&block_with_fees(2, &[]), &limits);
+        let first = &analytics.recent(2)[0];
+        assert_eq!(first.fees.p50, 3);
+        assert_eq!(first.fees.p90, 5);
+        assert_eq!(first.avg_fee, 3.0);
+        assert!(first.byte_utilization > 0.0);
+
+        let stats = analytics.summary(10).unwrap();
+        assert_eq!((stats.blocks, stats.tx_count, stats.total_fees), (2, 5, 15));
+        assert_eq!(stats.avg_txs_per_block, 2.5);
+
+        // A reorg replacing height 2 drops its old stats first.
+        analytics.record(&block_with_fees(2, &[100]), &limits);
+        let stats = analytics.summary(1).unwrap();
+        assert_eq!((stats.from_height, stats.tx_count), (2, 1));
+        assert_eq!(stats.fees.p10, 100);
+
+        for height in 3..(ANALYTICS_WINDOW as u64 + 10) {
+            analytics.record(&block_with_fees(height, &[1]), &limits);
+        }
+        assert_eq!(analytics.recent(usize::MAX).len(), ANALYTICS_WINDOW);
+    }
+}
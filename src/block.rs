@@ -1,10 +1,62 @@
+use crate::chain_config::ForkId;
 use crate::crypto::{verify_signature, KeyPair};
+use crate::frost::ThresholdSigner;
 use crate::hash::hash_fields;
+use crate::signing::{signing_digest, SigningContext};
 use crate::transaction::Transaction;
 use serde::{Deserialize, Serialize};
 
 pub const DEFAULT_CHAIN_ID: u64 = 1337;
+// The high nibble is the major version and the low nibble the minor one.
+// Minor versions may only add data inside `extensions`, which every node
+// hashes but need not understand, so any minor of a known major is
+// accepted. A new major changes the block format and needs a node upgrade.
+// Version 0 is the unversioned format from before the block_version fork.
+pub const BLOCK_VERSION: u8 = 0x10;
+pub const MAX_BLOCK_EXTENSION_BYTES: usize = 256;
 use crate::consensus::pos::SlashingEvidence;
+use crate::slashing::SlashingEvidence as VoteEvidence;
+
+// Producers sign the block hash under the chain and the fork active at the
+// block's height.
+fn block_signing_digest(hash: &str, chain_id: u64, fork_hash: &str) -> [u8; 32] {
+    let fork = ForkId {
+        chain_id,
+        fork_hash: fork_hash.to_string(),
+    };
+    signing_digest(SigningContext::Block, &fork, hash.as_bytes())
+}
+
+pub fn block_version_major(version: u8) -> u8 {
+    version >> 4
+}
+
+// Version 0 blocks keep the BDLM_BLOCK_V2 preimage, so their hashes are
+// unchanged. Versioned blocks are tagged BDLM_BLOCK_V3 with the version
+// right after the tag and the length-prefixed extensions at the end.
+fn hash_block_fields(version: u8, extensions: &[u8], fields: &[&[u8]]) -> String {
+    if version == 0 {
+        let mut preimage: Vec<&[u8]> = vec![b"BDLM_BLOCK_V2"];
+        preimage.extend_from_slice(fields);
+        return hash_fields(&preimage);
+    }
+    let version = [version];
+    let extensions_len = (extensions.len() as u64).to_le_bytes();
+    let mut preimage: Vec<&[u8]> = vec![b"BDLM_BLOCK_V3", &version];
+    preimage.extend_from_slice(fields);
+    preimage.push(&extensions_len);
+    preimage.push(extensions);
+    hash_fields(&preimage)
+}
+
+// Double-vote evidence is appended to the hash preimage only when present so
+// blocks without it keep their existing hashes.
+fn vote_evidence_bytes(evidence: &Option<Vec<VoteEvidence>>) -> Vec<u8> {
+    evidence
+        .as_ref()
+        .map(|e| bincode::serialize(e).unwrap_or_default())
+        .unwrap_or_default()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BlockHeader {
@@ -23,6 +75,16 @@ pub struct BlockHeader {
     pub vrf_output: Vec<u8>,
     pub vrf_proof: Vec<u8>,
     pub validator_set_hash: String,
+    #[serde(default)]
+    pub vote_evidence: Option<Vec<VoteEvidence>>,
+    #[serde(default)]
+    pub randao_reveal: Vec<u8>,
+    #[serde(default)]
+    pub version: u8,
+    #[serde(default)]
+    pub extensions: Vec<u8>,
+    #[serde(default)]
+    pub state_snapshot_hash: String,
 }
 
 impl BlockHeader {
@@ -43,6 +105,11 @@ impl BlockHeader {
             vrf_output: block.vrf_output.clone(),
             vrf_proof: block.vrf_proof.clone(),
             validator_set_hash: block.validator_set_hash.clone(),
+            vote_evidence: block.vote_evidence.clone(),
+            randao_reveal: block.randao_reveal.clone(),
+            version: block.version,
+            extensions: block.extensions.clone(),
+            state_snapshot_hash: block.state_snapshot_hash.clone(),
         }
     }
 
@@ -59,26 +126,32 @@ impl BlockHeader {
             .map(|e| bincode::serialize(e).unwrap_or_default())
             .unwrap_or_default();
 
-        hash_fields(&[
-            b"BDLM_BLOCK_V2",
-            &self.index.to_le_bytes(),
-            &self.timestamp.to_le_bytes(),
-            self.previous_hash.as_bytes(),
-            self.tx_root.as_bytes(),
-            &self.nonce.to_le_bytes(),
-            &producer_bytes,
-            &evidence_bytes,
-            &self.chain_id.to_le_bytes(),
-            self.state_root.as_bytes(),
-            &self.epoch.to_le_bytes(),
-            &self.slot.to_le_bytes(),
-            &self.vrf_output,
-            &self.vrf_proof,
-            self.validator_set_hash.as_bytes(),
-        ])
+        hash_block_fields(
+            self.version,
+            &self.extensions,
+            &[
+                &self.index.to_le_bytes(),
+                &self.timestamp.to_le_bytes(),
+                self.previous_hash.as_bytes(),
+                self.tx_root.as_bytes(),
+                &self.nonce.to_le_bytes(),
+                &producer_bytes,
+                &evidence_bytes,
+                &self.chain_id.to_le_bytes(),
+                self.state_root.as_bytes(),
+                &self.epoch.to_le_bytes(),
+                &self.slot.to_le_bytes(),
+                &self.vrf_output,
+                &self.vrf_proof,
+                self.validator_set_hash.as_bytes(),
+                &vote_evidence_bytes(&self.vote_evidence),
+                &self.randao_reveal,
+                self.state_snapshot_hash.as_bytes(),
+            ],
+        )
     }
 
-    pub fn verify_signature(&self, signature: &[u8]) -> bool {
+    pub fn verify_signature(&self, signature: &[u8], fork_hash: &str) -> bool {
         let producer_hex = match &self.producer {
             Some(p) => p,
             None => return false,
@@ -91,7 +164,8 @@ impl BlockHeader {
         if calculated_hash != self.hash {
             return false;
         }
-        verify_signature(self.hash.as_bytes(), signature, &public_key).is_ok()
+        let digest = block_signing_digest(&self.hash, self.chain_id, fork_hash);
+        verify_signature(&digest, signature, &public_key).is_ok()
     }
 }
 
@@ -114,6 +188,16 @@ pub struct Block {
     pub vrf_output: Vec<u8>,
     pub vrf_proof: Vec<u8>,
     pub validator_set_hash: String,
+    #[serde(default)]
+    pub vote_evidence: Option<Vec<VoteEvidence>>,
+    #[serde(default)]
+    pub randao_reveal: Vec<u8>,
+    #[serde(default)]
+    pub version: u8,
+    #[serde(default)]
+    pub extensions: Vec<u8>,
+    #[serde(default)]
+    pub state_snapshot_hash: String,
 }
 
 impl Block {
@@ -150,6 +234,11 @@ impl Block {
             vrf_output: Vec::new(),
             vrf_proof: Vec::new(),
             validator_set_hash: String::new(),
+            vote_evidence: None,
+            randao_reveal: Vec::new(),
+            version: 0,
+            extensions: Vec::new(),
+            state_snapshot_hash: String::new(),
         };
         block.tx_root = block.calculate_tx_root();
         block.hash = block.calculate_hash();
@@ -198,28 +287,67 @@ impl Block {
             .map(|e| bincode::serialize(e).unwrap_or_default())
             .unwrap_or_default();
 
-        hash_fields(&[
-            b"BDLM_BLOCK_V2",
-            &self.index.to_le_bytes(),
-            &self.timestamp.to_le_bytes(),
-            self.previous_hash.as_bytes(),
-            self.tx_root.as_bytes(),
-            &self.nonce.to_le_bytes(),
-            &producer_bytes,
-            &evidence_bytes,
-            &self.chain_id.to_le_bytes(),
-            self.state_root.as_bytes(),
-            &self.epoch.to_le_bytes(),
-            &self.slot.to_le_bytes(),
-            &self.vrf_output,
-            &self.vrf_proof,
-            self.validator_set_hash.as_bytes(),
-        ])
+        hash_block_fields(
+            self.version,
+            &self.extensions,
+            &[
+                &self.index.to_le_bytes(),
+                &self.timestamp.to_le_bytes(),
+                self.previous_hash.as_bytes(),
+                self.tx_root.as_bytes(),
+                &self.nonce.to_le_bytes(),
+                &producer_bytes,
+                &evidence_bytes,
+                &self.chain_id.to_le_bytes(),
+                self.state_root.as_bytes(),
+                &self.epoch.to_le_bytes(),
+                &self.slot.to_le_bytes(),
+                &self.vrf_output,
+                &self.vrf_proof,
+                self.validator_set_hash.as_bytes(),
+                &vote_evidence_bytes(&self.vote_evidence),
+                &self.randao_reveal,
+                self.state_snapshot_hash.as_bytes(),
+            ],
+        )
+    }
+    // Before the block_version fork only unversioned blocks are valid. After
+    // it, any minor of a known major is, so older nodes keep following the
+    // chain across minor upgrades.
+    pub fn check_version(&self, versioned: bool) -> Result<(), String> {
+        if !versioned {
+            if self.version != 0 || !self.extensions.is_empty() {
+                return Err(format!(
+                    "Block version {:#04x} before the block_version fork",
+                    self.version
+                ));
+            }
+            return Ok(());
+        }
+        if block_version_major(self.version) != block_version_major(BLOCK_VERSION) {
+            return Err(format!(
+                "Unsupported block version {:#04x} (this node supports major {}); upgrade the node",
+                self.version,
+                block_version_major(BLOCK_VERSION)
+            ));
+        }
+        if self.extensions.len() > MAX_BLOCK_EXTENSION_BYTES {
+            return Err(format!(
+                "Block extensions are {} bytes, over the {} byte limit",
+                self.extensions.len(),
+                MAX_BLOCK_EXTENSION_BYTES
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn signing_digest(&self, fork_hash: &str) -> [u8; 32] {
+        block_signing_digest(&self.hash, self.chain_id, fork_hash)
     }
-    pub fn sign(&mut self, keypair: &KeyPair) {
+    pub fn sign(&mut self, keypair: &KeyPair, fork_hash: &str) {
         self.producer = Some(keypair.public_key_hex());
         self.hash = self.calculate_hash();
-        let signature = keypair.sign(self.hash.as_bytes());
+        let signature = keypair.sign(&self.signing_digest(fork_hash));
         self.signature = Some(signature.to_vec());
         println!(
             "Block {} signed by {}",
@@ -228,7 +356,20 @@ impl Block {
         );
     }
 
-    pub fn verify_signature(&self) -> bool {
+    // Same signature as `sign`, produced by a FROST group for its key.
+    pub fn sign_threshold(
+        &mut self,
+        signer: &ThresholdSigner,
+        fork_hash: &str,
+    ) -> Result<(), String> {
+        self.producer = Some(signer.public_key_hex());
+        self.hash = self.calculate_hash();
+        let signature = signer.sign(&self.signing_digest(fork_hash))?;
+        self.signature = Some(signature.to_vec());
+        Ok(())
+    }
+
+    pub fn verify_signature(&self, fork_hash: &str) -> bool {
         let producer_hex = match &self.producer {
             Some(p) => p,
             None => {
@@ -250,7 +391,7 @@ impl Block {
                 return false;
             }
         };
-        match verify_signature(self.hash.as_bytes(), signature, &public_key) {
+        match verify_signature(&self.signing_digest(fork_hash), signature, &public_key) {
             Ok(()) => {
                 println!("Block {} signature verified", self.index);
                 true
@@ -261,7 +402,7 @@ impl Block {
             }
         }
     }
-    pub fn verify_signature_with_pubkey(&self, expected_pubkey_hex: &str) -> bool {
+    pub fn verify_signature_with_pubkey(&self, expected_pubkey_hex: &str, fork_hash: &str) -> bool {
         let producer_hex = match &self.producer {
             Some(p) => p,
             None => return false,
@@ -274,7 +415,7 @@ impl Block {
             );
             return false;
         }
-        self.verify_signature()
+        self.verify_signature(fork_hash)
     }
     pub fn mine(&mut self, difficulty: usize) {
         let target = "0".repeat(difficulty);
@@ -304,27 +445,73 @@ mod tests {
     fn test_ed25519_sign_and_verify() {
         let keypair = KeyPair::generate().unwrap();
         let mut block = Block::new(1, "0".repeat(64), vec![]);
-        block.sign(&keypair);
+        block.sign(&keypair, "");
         assert!(block.signature.is_some());
         assert_eq!(block.signature.as_ref().unwrap().len(), 64);
-        assert!(block.verify_signature());
+        assert!(block.verify_signature(""));
+        assert!(!block.verify_signature("ab"));
+        let header = BlockHeader::from_block(&block);
+        assert!(header.verify_signature(block.signature.as_ref().unwrap(), ""));
+        assert!(!header.verify_signature(block.signature.as_ref().unwrap(), "ab"));
     }
     #[test]
     fn test_signature_with_specific_pubkey() {
         let keypair = KeyPair::generate().unwrap();
         let mut block = Block::new(1, "0".repeat(64), vec![]);
-        block.sign(&keypair);
-        assert!(block.verify_signature_with_pubkey(&keypair.public_key_hex()));
+        block.sign(&keypair, "");
+        assert!(block.verify_signature_with_pubkey(&keypair.public_key_hex(), ""));
         let other_keypair = KeyPair::generate().unwrap();
-        assert!(!block.verify_signature_with_pubkey(&other_keypair.public_key_hex()));
+        assert!(!block.verify_signature_with_pubkey(&other_keypair.public_key_hex(), ""));
     }
     #[test]
     fn test_modified_block_fails_verification() {
         let keypair = KeyPair::generate().unwrap();
         let mut block = Block::new(1, "0".repeat(64), vec![]);
-        block.sign(&keypair);
+        block.sign(&keypair, "");
         block.nonce = 12345;
         block.hash = block.calculate_hash();
-        assert!(!block.verify_signature());
+        assert!(!block.verify_signature(""));
+    }
+    #[test]
+    fn test_versioned_block_hash() {
+        let legacy = Block::new(1, "0".repeat(64), vec![]);
+        let fields: [&[u8]; 3] = [b"a", b"b", b""];
+        let mut v2: Vec<&[u8]> = vec![b"BDLM_BLOCK_V2"];
+        v2.extend_from_slice(&fields);
+        assert_eq!(hash_block_fields(0, &[], &fields), hash_fields(&v2));
+
+        let mut versioned = legacy.clone();
+        versioned.version = BLOCK_VERSION;
+        versioned.hash = versioned.calculate_hash();
+        assert_ne!(versioned.hash, legacy.hash);
+        assert_eq!(
+            BlockHeader::from_block(&versioned).calculate_hash(),
+            versioned.hash
+        );
+        let mut extended = versioned.clone();
+        extended.extensions = vec![1];
+        assert_ne!(extended.calculate_hash(), versioned.hash);
+        assert_eq!(
+            BlockHeader::from_block(&extended).calculate_hash(),
+            extended.calculate_hash()
+        );
+    }
+    #[test]
+    fn test_check_version() {
+        let mut block = Block::new(1, "0".repeat(64), vec![]);
+        assert!(block.check_version(false).is_ok());
+        block.version = BLOCK_VERSION;
+        assert!(block.check_version(false).is_err());
+        assert!(block.check_version(true).is_ok());
+        block.version = BLOCK_VERSION | 0x0f;
+        block.extensions = vec![0; MAX_BLOCK_EXTENSION_BYTES];
+        assert!(block.check_version(true).is_ok());
+        block.extensions.push(0);
+        assert!(block.check_version(true).is_err());
+        block.extensions.clear();
+        block.version = 0x20;
+        assert!(block.check_version(true).is_err());
+        block.version = 0;
+        assert!(block.check_version(true).is_err());
     }
 }
@@ -1,17 +1,43 @@
-use crate::account::AccountState;
-use crate::consensus::finality::{ValidatorEntry, ValidatorSetSnapshot};
+use crate::account::{AccountState, EpochRewardReceipt, StateProof, ValidatorSetSafety};
+use crate::analytics::{ChainAnalytics, ANALYTICS_WINDOW};
+use crate::block::BLOCK_VERSION;
+use crate::chain_config::{
+    ChainLimits, ForkId, ForkSchedule, WeakSubjectivityCheckpoint, FORK_BLOCK_VERSION,
+    FORK_ROLLING_VALIDATOR_SET, FORK_STATE_SNAPSHOT,
+};
+use crate::consensus::finality::{
+    is_checkpoint_height, verify_double_vote, CheckpointStatus, FinalityAggregator, FinalityCert,
+    FinalityStatus, FinalityTracker, Precommit, Prevote, ValidatorSetSnapshot,
+};
+use crate::consensus::pos::{EVIDENCE_MAX_AGE_EPOCHS, MAX_EVIDENCE_PER_BLOCK};
 use crate::consensus::ConsensusEngine;
+use crate::crypto::KeyPair;
+use crate::divergence::{changed_keys, DivergenceReport, PendingDivergence, MAX_STATE_PROOF_KEYS};
+use crate::dust::{sweep_storage_key, DustProof, SweptAccount};
+use crate::events::{BlockImportError, BlockRejectReason, BlockRejection, ChainEvent, EventBus};
 use crate::genesis::{GenesisConfig, GENESIS_TIMESTAMP};
 use crate::mempool::{Mempool, MempoolConfig};
-use crate::snapshot::PruningManager;
+use crate::replay::{balance_deltas, BlockTrace, TransactionTrace};
+use crate::slashing::SlashingEvidence as VoteEvidence;
+use crate::snapshot::{PruningManager, StateSnapshot};
+use crate::state_diff::{StateDiff, StateDiffEvent, StateView};
 use crate::storage::Storage;
+use crate::sync::{PeerVersions, SyncState};
 use crate::{Block, Transaction};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 
-pub const MAX_REORG_DEPTH: usize = 100;
 pub const FINALITY_DEPTH: usize = 50;
 pub const EPOCH_LENGTH: u64 = 32;
+pub const CHAIN_EXPORT_MAGIC: &[u8; 8] = b"BDLMCHN2";
+// Largest encoded block `import_chain` reads, well above any block the
+// network would carry.
+pub const MAX_CHAIN_EXPORT_RECORD_BYTES: usize = 10 * 1024 * 1024;
 
 pub struct Blockchain {
     pub chain: Vec<Block>,
@@ -23,14 +49,279 @@ pub struct Blockchain {
     pub pruning_manager: Option<PruningManager>,
     pub finalized_height: u64,
     pub finalized_hash: String,
+    pub weak_subjectivity: Vec<WeakSubjectivityCheckpoint>,
+    pub events: EventBus,
+    pub sync: SyncState,
+    pub peer_versions: PeerVersions,
+    vote_aggregators: HashMap<u64, FinalityAggregator>,
+    finality: FinalityTracker,
+    pending_vote_evidence: Vec<VoteEvidence>,
+    halt_timeout_secs: u64,
+    halted: bool,
+    min_validators: usize,
+    validator_set_safe: bool,
+    production_paused: bool,
+    observer: bool,
+    clock_offset_ms: i64,
+    target_block_size: usize,
+    analytics: ChainAnalytics,
+    balance_history: bool,
+    state_root_diagnostics: bool,
+    pending_divergence: Option<PendingDivergence>,
+    last_divergence: Option<DivergenceReport>,
+    chain_memory_budget: usize,
+    block_bytes: Vec<usize>,
+    chain_bytes: usize,
+    evicted_bodies: HashSet<String>,
+    eviction_cursor: usize,
+    spec: GenesisConfig,
+    compacting: Arc<AtomicBool>,
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainHealth {
+    pub height: u64,
+    pub last_block_age_secs: u64,
+    pub halt_timeout_secs: u64,
+    pub halted: bool,
+    pub production_paused: bool,
+    pub validator_set_safe: bool,
+    pub clock_offset_ms: i64,
+    pub chain_memory_bytes: usize,
+    pub evicted_bodies: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationStatus {
+    Pending,
+    Included,
+}
+
+// Where a transaction stands for an "N confirmations or finalized"
+// acceptance policy. `confirmations` counts the containing block and every
+// block on top of it (0 while pending); `finalized` is only set once a
+// finality certificate covers that block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxConfirmation {
+    pub transaction_hash: String,
+    pub status: ConfirmationStatus,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u64>,
+    pub confirmations: u64,
+    pub finalized: bool,
+    pub tip_height: u64,
+    pub finalized_height: u64,
+}
+
+impl TxConfirmation {
+    pub fn is_accepted(&self, min_confirmations: u64) -> bool {
+        self.finalized
+            || (self.status == ConfirmationStatus::Included
+                && self.confirmations >= min_confirmations.max(1))
+    }
+}
+
+// Everything a node needs to adopt finalized history without re-executing
+// it: the blocks up to a finalized checkpoint, the certificate finalizing
+// it, the validator set that signed it and the state at that height.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointBundle {
+    pub blocks: Vec<Block>,
+    pub cert: FinalityCert,
+    pub validator_set: ValidatorSetSnapshot,
+    pub snapshot: StateSnapshot,
+}
+// Assembles a `Blockchain` from optional components and rejects
+// combinations that cannot work together before any state is loaded.
+pub struct BlockchainBuilder {
+    consensus: Arc<dyn ConsensusEngine>,
+    chain_id: Option<u64>,
+    genesis: Option<GenesisConfig>,
+    storage: Option<Storage>,
+    pruning_manager: Option<PruningManager>,
+    mempool: Option<MempoolConfig>,
+    events: Option<EventBus>,
+}
+
+impl BlockchainBuilder {
+    pub fn new(consensus: Arc<dyn ConsensusEngine>) -> Self {
+        BlockchainBuilder {
+            consensus,
+            chain_id: None,
+            genesis: None,
+            storage: None,
+            pruning_manager: None,
+            mempool: None,
+            events: None,
+        }
+    }
+
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    pub fn genesis(mut self, genesis: GenesisConfig) -> Self {
+        self.genesis = Some(genesis);
+        self
+    }
+
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn pruning_manager(mut self, pruning_manager: PruningManager) -> Self {
+        self.pruning_manager = Some(pruning_manager);
+        self
+    }
+
+    pub fn mempool_config(mut self, config: MempoolConfig) -> Self {
+        self.mempool = Some(config);
+        self
+    }
+
+    // Lets an embedder share one bus between several components.
+    pub fn event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn build(self) -> Result<Blockchain, String> {
+        let genesis = match (self.genesis, self.chain_id) {
+            (Some(genesis), Some(chain_id)) if genesis.chain_id != chain_id => {
+                return Err(format!(
+                    "Genesis config is for chain {} but chain_id is {}",
+                    genesis.chain_id, chain_id
+                ));
+            }
+            (Some(genesis), _) => genesis,
+            (None, Some(chain_id)) => GenesisConfig::new(chain_id),
+            (None, None) => return Err("Either a chain_id or a genesis config is required".into()),
+        };
+        // Snapshots only cover account state; replaying on top of them needs
+        // the stored blocks they were taken from.
+        if self.pruning_manager.is_some() && self.storage.is_none() {
+            return Err("Pruning and snapshot restore require storage".into());
+        }
+        genesis.validate()?;
+        let mut blockchain = Blockchain::open(
+            self.consensus,
+            self.storage,
+            &genesis,
+            self.pruning_manager,
+            self.events.unwrap_or_default(),
+        );
+        if let Some(config) = self.mempool {
+            blockchain.mempool = Mempool::new(config);
+        }
+        Ok(blockchain)
+    }
+}
+
+// A block replay detached from the chain: it rebuilds the state before
+// `block` from `base` through the shared block transition, then
+// re-executes `block` one transaction at a time, recording balance changes
+// and the first reason each transaction would be rejected.
+pub struct ReplayJob {
+    base: AccountState,
+    blocks: Vec<Block>,
+    block: Block,
+    consensus: Arc<dyn ConsensusEngine>,
+}
+
+impl ReplayJob {
+    pub fn run(self) -> Result<BlockTrace, String> {
+        let ReplayJob {
+            mut base,
+            blocks,
+            block,
+            consensus,
+        } = self;
+        for prior in &blocks {
+            Blockchain::replay_block(&mut base, prior, consensus.as_ref())?;
+        }
+        base.treasury_credits.clear();
+        let prior = base;
+        let height = block.index;
+        let fork_hash = prior.forks.fork_hash(height);
+        let signing_version = prior.forks.tx_signing_version(height);
+
+        let mut state = prior.clone();
+        let mut transactions = Vec::with_capacity(block.transactions.len());
+        for (i, tx) in block.transactions.iter().enumerate() {
+            let before = state.accounts.clone();
+            let mut next = state.clone();
+            let result =
+                Blockchain::check_transaction_placement(&block, &fork_hash, signing_version, i, tx)
+                    .and_then(|()| next.validate_transaction(tx))
+                    .and_then(|()| next.apply_transaction(tx));
+            if result.is_ok() {
+                state = next;
+            }
+            transactions.push(TransactionTrace {
+                index: i,
+                hash: tx.hash.clone(),
+                from: tx.from.clone(),
+                to: tx.to.clone(),
+                error: result.err(),
+                balance_deltas: balance_deltas(&before, &state.accounts),
+            });
+        }
+
+        let mut commit_state = prior;
+        let (error, block_deltas, computed_state_root) =
+            match Blockchain::apply_to_state(&mut commit_state, &block, consensus.as_ref()) {
+                Ok(_) => (
+                    None,
+                    balance_deltas(&state.accounts, &commit_state.accounts),
+                    Some(commit_state.calculate_state_root()),
+                ),
+                Err(e) => (Some(e), Vec::new(), None),
+            };
+        Ok(BlockTrace {
+            height,
+            hash: block.hash.clone(),
+            producer: block.producer.clone(),
+            transactions,
+            block_deltas,
+            error,
+            state_root_matches: computed_state_root.as_ref() == Some(&block.state_root),
+            claimed_state_root: block.state_root.clone(),
+            computed_state_root,
+        })
+    }
+}
+
 impl Blockchain {
+    pub fn builder(consensus: Arc<dyn ConsensusEngine>) -> BlockchainBuilder {
+        BlockchainBuilder::new(consensus)
+    }
+
     pub fn new(
         consensus: Arc<dyn ConsensusEngine>,
         storage: Option<Storage>,
         chain_id: u64,
         pruning_manager: Option<PruningManager>,
     ) -> Self {
+        Self::open(
+            consensus,
+            storage,
+            &GenesisConfig::new(chain_id),
+            pruning_manager,
+            EventBus::new(),
+        )
+    }
+
+    fn open(
+        consensus: Arc<dyn ConsensusEngine>,
+        storage: Option<Storage>,
+        spec: &GenesisConfig,
+        pruning_manager: Option<PruningManager>,
+        events: EventBus,
+    ) -> Self {
+        let chain_id = spec.chain_id;
         println!("Consensus: {}", consensus.info());
         let mut chain_vec = Vec::new();
         let mut state = AccountState::new();
@@ -46,9 +337,15 @@ impl Blockchain {
             }
         }
 
-        if !loaded_chain {
-            let genesis = GenesisConfig::new(chain_id).build_genesis_block();
-            chain_vec.push(genesis);
+        if chain_vec.first().is_none_or(|b| b.index != 0) {
+            let genesis = spec.build_genesis_block();
+            if let Some(ref store) = storage {
+                let _ = store.insert_block(&genesis);
+                if !loaded_chain {
+                    let _ = store.save_last_hash(&genesis.hash);
+                }
+            }
+            chain_vec.insert(0, genesis);
         }
 
         let mut snapshot_height = 0;
@@ -61,6 +358,7 @@ impl Blockchain {
                     for (addr, balance) in &snapshot.balances {
                         let acc = state.get_or_create(addr);
                         acc.balance = *balance;
+                        state.total_minted += *balance;
                     }
                     for (addr, nonce) in &snapshot.nonces {
                         let acc = state.get_or_create(addr);
@@ -82,8 +380,29 @@ impl Blockchain {
             }
         }
 
+        if let Some(ref store) = storage {
+            match store.get_finalized() {
+                Ok(Some((height, hash))) if height >= restored_finalized_height => {
+                    if let Some(block) = chain_vec.get(height as usize) {
+                        if block.hash != hash {
+                            println!("CRITICAL: Stored block at finalized height {} does not match finalized hash {}. Corrupted database, exiting.", height, hash);
+                            std::process::exit(1);
+                        }
+                    }
+                    println!("Restored finalized checkpoint at height {}", height);
+                    restored_finalized_height = height;
+                    restored_finalized_hash = hash;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    println!("CRITICAL: Failed to read finalized checkpoint: {}. Corrupted database, exiting.", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
         let chain_len = chain_vec.len();
-        let start_index = if snapshot_height > 0 && snapshot_height < chain_len as u64 {
+        let mut start_index = if snapshot_height > 0 && snapshot_height < chain_len as u64 {
             (snapshot_height + 1) as usize
         } else {
             if snapshot_height >= chain_len as u64 {
@@ -94,703 +413,4242 @@ impl Blockchain {
             }
         };
 
+        if let Some(ref store) = storage {
+            match AccountState::load_checkpoint(store) {
+                Ok(Some((height, hash, saved)))
+                    if height >= snapshot_height
+                        && chain_vec
+                            .get(height as usize)
+                            .is_some_and(|b| b.hash == hash) =>
+                {
+                    println!("Restored account state checkpoint at height {}", height);
+                    state = saved;
+                    start_index = height as usize + 1;
+                }
+                Ok(Some((height, _, _))) => println!(
+                    " State checkpoint at height {} does not match the stored chain. Replaying.",
+                    height
+                ),
+                Ok(None) => {}
+                Err(e) => println!(" Failed to read state checkpoint: {}. Replaying.", e),
+            }
+        }
+
         println!(
             "Replaying blocks from index {} to {}...",
             start_index,
             chain_len - 1
         );
 
+        if start_index == 0 {
+            state = AccountState::from_genesis(spec);
+        } else {
+            state.apply_spec(spec);
+        }
         for block in chain_vec.iter().skip(start_index) {
-            if let Err(e) = state.apply_block(&block.transactions, block.producer.as_deref()) {
+            if let Err(e) = Blockchain::apply_to_state(&mut state, block, consensus.as_ref()) {
                 println!("CRITICAL: Failed to apply block {} during init: {}. Corrupted database, exiting.", block.index, e);
                 std::process::exit(1);
             }
         }
+        state.treasury_credits.clear();
+        Blockchain::archive_dust_sweeps(storage.as_ref(), &mut state);
 
-        Blockchain {
+        let mut analytics = ChainAnalytics::new();
+        let recent_from = chain_vec.len().saturating_sub(ANALYTICS_WINDOW);
+        for block in &chain_vec[recent_from..] {
+            analytics.record(block, &state.limits);
+        }
+
+        let mut blockchain = Blockchain {
             chain: chain_vec,
             consensus,
-            mempool: Mempool::new(MempoolConfig::default()),
+            mempool: Mempool::new(spec.mempool.clone()),
             storage,
             state,
             chain_id,
             pruning_manager,
             finalized_height: restored_finalized_height,
             finalized_hash: restored_finalized_hash,
-        }
+            weak_subjectivity: Vec::new(),
+            events,
+            sync: SyncState::new(),
+            peer_versions: PeerVersions::new(),
+            vote_aggregators: HashMap::new(),
+            finality: FinalityTracker::default(),
+            pending_vote_evidence: Vec::new(),
+            halt_timeout_secs: 0,
+            halted: false,
+            min_validators: 1,
+            validator_set_safe: true,
+            production_paused: false,
+            observer: false,
+            clock_offset_ms: 0,
+            target_block_size: 0,
+            analytics,
+            balance_history: false,
+            state_root_diagnostics: false,
+            pending_divergence: None,
+            last_divergence: None,
+            chain_memory_budget: 0,
+            block_bytes: Vec::new(),
+            chain_bytes: 0,
+            evicted_bodies: HashSet::new(),
+            eviction_cursor: 1,
+            spec: spec.clone(),
+            compacting: Arc::new(AtomicBool::new(false)),
+        };
+        blockchain.account_chain_memory(0);
+        blockchain
     }
 
-    fn load_chain_from_db(&mut self, last_hash: String) -> std::io::Result<()> {
-        let mut current_hash = last_hash;
-        let mut blocks = Vec::new();
-        if let Some(ref store) = self.storage {
-            while let Ok(Some(block)) = store.get_block(&current_hash) {
-                blocks.push(block.clone());
-                if block.previous_hash == "0".repeat(64) {
-                    break;
-                }
-                current_hash = block.previous_hash;
-            }
-        }
-        if blocks.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Chain broken or empty",
+    pub fn with_mempool_config(mut self, config: MempoolConfig) -> Self {
+        self.mempool = Mempool::new(config);
+        self
+    }
+
+    pub fn limits(&self) -> &ChainLimits {
+        &self.state.limits
+    }
+
+    pub fn forks(&self) -> &ForkSchedule {
+        &self.state.forks
+    }
+
+    pub fn analytics(&self) -> &ChainAnalytics {
+        &self.analytics
+    }
+
+    pub fn fork_id(&self) -> ForkId {
+        self.fork_id_at(self.chain.len().saturating_sub(1) as u64)
+    }
+
+    pub fn fork_id_at(&self, height: u64) -> ForkId {
+        self.state.forks.fork_id(self.chain_id, height)
+    }
+
+    // Peers report the fork hash at their tip. Ours at that height differs
+    // when either side activated a fork the other does not know about.
+    pub fn check_peer_fork(&self, best_height: u64, fork_hash: &str) -> Result<(), String> {
+        let expected = self.state.forks.fork_hash(best_height.saturating_sub(1));
+        if fork_hash != expected {
+            return Err(format!(
+                "fork hash {:?} at height {}, expected {:?}",
+                fork_hash, best_height, expected
             ));
         }
-        blocks.reverse();
-        self.chain = blocks;
-        println!("Loaded {} blocks from disk", self.chain.len());
         Ok(())
     }
-    fn create_genesis_block(&mut self) {
-        let genesis_block = Block::genesis();
-        self.chain.push(genesis_block.clone());
-        if let Some(ref store) = self.storage {
-            let _ = store.insert_block(&genesis_block);
-            let _ = store.save_last_hash(&genesis_block.hash);
+
+    // Swept accounts leave the state; their balances are kept in storage,
+    // keyed by the epoch's sweep root, so proofs can be built for them.
+    fn archive_dust_sweeps(storage: Option<&Storage>, state: &mut AccountState) {
+        for (epoch, swept) in std::mem::take(&mut state.swept_dust) {
+            let Some(store) = storage else { continue };
+            let root = state.dust_sweeps.get(&epoch).cloned().unwrap_or_default();
+            let result = serde_json::to_vec(&swept)
+                .map_err(|e| e.to_string())
+                .and_then(|data| {
+                    store
+                        .put_meta(&sweep_storage_key(&root), &data)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = result {
+                println!("Failed to archive dust sweep of epoch {}: {}", epoch, e);
+            }
         }
     }
-    pub fn last_block(&self) -> &Block {
-        self.chain.last().expect("Chain should never be empty")
-    }
 
-    pub fn get_validator_set_hash(&self) -> String {
-        let active_validators = self.state.get_active_validators();
-        let entries: Vec<ValidatorEntry> = active_validators
-            .into_iter()
-            .map(|v| ValidatorEntry {
-                address: v.address.clone(),
-                stake: v.stake,
-                bls_public_key: Vec::new(),
-                pop_signature: Vec::new(),
+    // The most recent sweep of `address` that has not been restored yet.
+    pub fn dust_proof(&self, address: &str) -> Option<DustProof> {
+        let store = self.storage.as_ref()?;
+        self.state
+            .dust_sweeps
+            .iter()
+            .rev()
+            .find_map(|(epoch, root)| {
+                let data = store.get_meta(&sweep_storage_key(root)).ok()??;
+                let swept: Vec<SweptAccount> = serde_json::from_slice(&data).ok()?;
+                DustProof::build(*epoch, &swept, address)
+                    .filter(|proof| !self.state.restored_dust.contains(&proof.leaf()))
             })
-            .collect();
-        ValidatorSetSnapshot::compute_hash(&entries)
     }
-    pub fn produce_block(&mut self, producer_address: String) {
-        let index = self.chain.len() as u64;
-        let previous_hash = self.chain.last().unwrap().hash.clone();
 
-        let mut valid_txs = Vec::new();
-        let mut temp_state = self.state.clone();
+    // An explicit observer validates and serves the chain but never produces
+    // blocks or admits transactions, whatever the consensus engine.
+    pub fn with_observer(mut self, observer: bool) -> Self {
+        self.observer = observer;
+        self
+    }
 
-        let pending_txs = self.mempool.get_sorted_transactions(1000);
-        for tx in &pending_txs {
-            if let Ok(_) = temp_state.validate_transaction(tx) {
-                if let Ok(_) = temp_state.apply_transaction(tx) {
-                    valid_txs.push(tx.clone());
-                }
-            } else {
-                println!("Discarding invalid transaction: {}", tx.hash);
-            }
+    pub fn is_observer(&self) -> bool {
+        self.observer
+            || (self.consensus.requires_signer() && self.consensus.signer_address().is_none())
+    }
+
+    // Observers and nodes with paused production leave transactions to the
+    // rest of the network instead of rejecting them as invalid.
+    pub fn accepts_transactions(&self) -> bool {
+        !self.observer && !self.production_paused
+    }
+
+    pub fn local_producer(&self) -> Option<String> {
+        if self.observer {
+            return None;
         }
+        self.consensus.signer_address()
+    }
 
-        let mut block = Block::new(index, previous_hash, valid_txs);
-        println!(
-            "Producing block {} with {} ({} txs)...",
-            index,
-            self.consensus.consensus_type(),
-            block.transactions.len()
-        );
+    pub fn with_halt_timeout(mut self, secs: u64) -> Self {
+        self.halt_timeout_secs = secs;
+        self
+    }
 
-        block.producer = Some(producer_address.clone());
+    // Fewest eligible validators the running set may shrink to before the
+    // node stops accepting finality certificates.
+    pub fn with_min_validators(mut self, min_validators: usize) -> Self {
+        self.min_validators = min_validators;
+        self.check_validator_set();
+        self
+    }
 
-        let mut state_for_root = self.state.clone();
-        if let Err(e) = state_for_root.apply_block(&block.transactions, block.producer.as_deref()) {
-            println!(
-                "Failed to apply block inside produce_block (state for root): {}",
-                e
-            );
-            return;
-        }
-        block.state_root = state_for_root.calculate_state_root();
+    // Soft cap on the size of blocks this node produces (0 = off). Blocks
+    // from other producers are still checked against the consensus limit.
+    pub fn with_target_block_size(mut self, bytes: usize) -> Self {
+        self.target_block_size = bytes;
+        self
+    }
 
-        if let Err(e) = self.consensus.prepare_block(&mut block, &self.state) {
-            println!("Block preparation failed: {}", e);
-            return;
+    // Opt-in per-block index of balance changes, kept in storage so
+    // `balance_at` answers without replaying the chain. Enabling it on an
+    // existing chain (or after a gap) starts the index at the current tip.
+    pub fn with_balance_history(mut self, enabled: bool) -> Self {
+        self.balance_history = enabled;
+        if let Some(store) = self.balance_history_store() {
+            if let Err(e) = store.migrate_balance_history() {
+                warn!("Failed to migrate the balance history index: {}", e);
+            }
+            let tip = self.last_block().index;
+            let indexed = matches!(store.get_balance_history_start(), Ok(Some(_)))
+                && store.has_balance_changes(tip).unwrap_or(false);
+            if !indexed {
+                self.restart_balance_history();
+            }
         }
+        self
+    }
 
-        println!("Block produced: {}", block.hash);
-        if let Some(ref store) = self.storage {
-            let _ = store.insert_block(&block);
-            let _ = store.save_last_hash(&block.hash);
-            let _ = store.save_state_root(block.index, &block.state_root);
-            let _ = store.save_canonical_height(block.index);
+    pub fn balance_at(&self, address: &str, height: u64) -> Result<u64, String> {
+        let store = self
+            .balance_history_store()
+            .ok_or("Balance history index is disabled")?;
+        let tip = self.last_block().index;
+        if height > tip {
+            return Err(format!("Height {} is above the chain tip {}", height, tip));
         }
-
-        if let Err(e) = self
-            .state
-            .apply_block(&block.transactions, block.producer.as_deref())
-        {
-            println!("Failed to apply block to canonical state: {}", e);
-            return;
+        let start = store
+            .get_balance_history_start()
+            .map_err(|e| e.to_string())?
+            .unwrap_or(tip);
+        if height < start {
+            return Err(format!("Balance history starts at height {}", start));
         }
+        Ok(store
+            .get_balance_at(address, height)
+            .map_err(|e| e.to_string())?
+            .unwrap_or(0))
+    }
 
-        if block.index > 0 && block.index % EPOCH_LENGTH == 0 {
-            self.state.advance_epoch(block.timestamp);
-        }
+    fn balance_history_store(&self) -> Option<&Storage> {
+        self.storage.as_ref().filter(|_| self.balance_history)
+    }
 
-        self.chain.push(block.clone());
+    // On a state root mismatch, keep this node's state entries for the
+    // block so they can be compared with proofs from the producer's state.
+    pub fn with_state_root_diagnostics(mut self, enabled: bool) -> Self {
+        self.state_root_diagnostics = enabled;
+        self
+    }
 
-        for tx in &block.transactions {
-            self.mempool.remove_transaction(&tx.hash);
+    // The mismatched block and the keys to ask a peer to prove, returned
+    // once per mismatch.
+    pub fn take_state_proof_request(&mut self) -> Option<(u64, String, Vec<String>)> {
+        let pending = self.pending_divergence.as_mut().filter(|p| !p.requested)?;
+        pending.requested = true;
+        Some((
+            pending.height,
+            pending.block_hash.clone(),
+            pending.keys.clone(),
+        ))
+    }
+
+    // Proofs of `keys` for a peer diagnosing `block_hash`, if that block is
+    // our tip. Older states are not kept.
+    pub fn state_proofs_for(
+        &self,
+        height: u64,
+        block_hash: &str,
+        keys: &[String],
+    ) -> Option<Vec<StateProof>> {
+        let tip = self.last_block();
+        (keys.len() <= MAX_STATE_PROOF_KEYS && tip.index == height && tip.hash == block_hash)
+            .then(|| self.state.state_proofs(keys))
+    }
+
+    // Checks a peer's proofs for the mismatched block against ours and logs
+    // the first proven key that differs. Proofs that do not hash to the
+    // block's claimed root are rejected and the mismatch stays pending.
+    pub fn diagnose_state_root(
+        &mut self,
+        height: u64,
+        block_hash: &str,
+        proofs: &[StateProof],
+    ) -> Result<DivergenceReport, String> {
+        let pending = self
+            .pending_divergence
+            .as_ref()
+            .filter(|p| p.height == height && p.block_hash == block_hash)
+            .ok_or_else(|| format!("No state root mismatch pending for block {}", block_hash))?;
+        let report = pending.diagnose(proofs)?;
+        match &report.first_divergence {
+            Some(d) => warn!(
+                "State divergence at height {}: first differing entry {} (local {:?}, producer {:?})",
+                height, d.key, d.local, d.remote
+            ),
+            None => warn!(
+                "State divergence at height {}: the {} proven keys match, the difference is elsewhere",
+                height, report.proven_keys
+            ),
         }
+        self.pending_divergence = None;
+        self.last_divergence = Some(report.clone());
+        Ok(report)
     }
-    pub fn mine_pending_transactions(&mut self, miner_address: String) {
-        self.produce_block(miner_address);
+
+    pub fn last_divergence(&self) -> Option<&DivergenceReport> {
+        self.last_divergence.as_ref()
     }
-    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
-        if transaction.chain_id != self.chain_id {
-            return Err(format!(
-                "Invalid Chain ID: expected {}, got {}",
-                self.chain_id, transaction.chain_id
-            ));
+
+    // Soft limit in bytes on the in-memory chain. Above it, transactions of
+    // the oldest blocks are dropped and served from storage on demand. The
+    // last FINALITY_DEPTH blocks are never evicted. 0 disables eviction.
+    pub fn with_chain_memory_budget(mut self, bytes: usize) -> Self {
+        self.chain_memory_budget = bytes;
+        self.evict_bodies();
+        self
+    }
+
+    pub fn chain_memory_bytes(&self) -> usize {
+        self.chain_bytes
+    }
+
+    // Re-accounts blocks from `from` on after they were appended or
+    // replaced. Sizes are the encoded size of each block as held in memory,
+    // which tracks its heap footprint closely enough for a soft limit.
+    fn account_chain_memory(&mut self, from: usize) {
+        let from = from.min(self.block_bytes.len());
+        for bytes in self.block_bytes.drain(from..) {
+            self.chain_bytes -= bytes;
         }
-        if transaction.from == "genesis" {
-            return Err("Genesis transactions cannot be submitted to the mempool".into());
+        for block in &self.chain[from..] {
+            let bytes = bincode::serialized_size(block).unwrap_or(0) as usize;
+            self.block_bytes.push(bytes);
+            self.chain_bytes += bytes;
         }
-        if !transaction.verify() {
-            return Err("Invalid transaction signature".into());
+        // Genesis anchors the chain and is never evicted.
+        self.eviction_cursor = self.eviction_cursor.min(from).max(1);
+        self.evict_bodies();
+    }
+
+    // The chain only grows at the tip and evicted bodies are read back from
+    // storage without being cached again, so evicting oldest first is the
+    // least recently used order. Blocks missing from storage, e.g. pruned
+    // ones, keep their bodies.
+    fn evict_bodies(&mut self) {
+        let Some(store) = self.storage.as_ref() else {
+            return;
+        };
+        if self.chain_memory_budget == 0 {
+            return;
         }
-        if let Err(e) = self.state.validate_transaction(&transaction) {
-            return Err(format!("Invalid transaction: {}", e));
+        let pinned_from = self.chain.len().saturating_sub(FINALITY_DEPTH);
+        while self.chain_bytes > self.chain_memory_budget && self.eviction_cursor < pinned_from {
+            let index = self.eviction_cursor;
+            self.eviction_cursor += 1;
+            let block = &mut self.chain[index];
+            if block.transactions.is_empty()
+                || !store.get_block(&block.hash).is_ok_and(|b| b.is_some())
+            {
+                continue;
+            }
+            block.transactions = Vec::new();
+            self.evicted_bodies.insert(block.hash.clone());
+            let bytes = bincode::serialized_size(block).unwrap_or(0) as usize;
+            self.chain_bytes = self.chain_bytes - self.block_bytes[index] + bytes;
+            self.block_bytes[index] = bytes;
         }
+    }
 
-        self.mempool
-            .add_transaction(transaction)
-            .map_err(|e| format!("Mempool error: {:?}", e))
+    // A chain block with its transactions, read back from storage if they
+    // were evicted.
+    fn with_body<'a>(&self, block: &'a Block) -> Result<Cow<'a, Block>, String> {
+        if !block.transactions.is_empty() || !self.evicted_bodies.contains(&block.hash) {
+            return Ok(Cow::Borrowed(block));
+        }
+        self.storage
+            .as_ref()
+            .and_then(|store| store.get_block(&block.hash).ok().flatten())
+            .map(Cow::Owned)
+            .ok_or_else(|| format!("Body of block {} is no longer in storage", block.index))
     }
 
-    pub fn init_genesis_account(&mut self, address: &str) {
-        self.state.add_balance(address, 1_000_000_000);
+    // Blocks `from..to` of the in-memory chain with their transactions, e.g.
+    // to serve a peer's range request. Stops at the first body that cannot
+    // be read back.
+    pub fn blocks_between(&self, from: usize, to: usize) -> Vec<Block> {
+        let to = to.min(self.chain.len());
+        self.chain[from.min(to)..to]
+            .iter()
+            .map_while(|block| self.with_body(block).ok().map(Cow::into_owned))
+            .collect()
     }
 
-    pub fn validate_and_add_block(&mut self, block: Block) -> Result<(), String> {
-        if block.index <= self.finalized_height && block.hash != self.finalized_hash {
-            if let Some(finalized_path_block) = self.chain.get(block.index as usize) {
-                if finalized_path_block.hash != block.hash {
-                    return Err(format!(
-                        "Block at height {} conflicts with finalized checkpoint",
-                        block.index
-                    ));
-                }
-            } else {
-                return Err(format!(
-                    "Block at height {} is below finalized height {}",
-                    block.index, self.finalized_height
-                ));
-            }
+    // Pre-block state is only captured when something consumes the diff.
+    fn state_view(&self) -> Option<StateView> {
+        if self.balance_history_store().is_none() && !self.events.wants_state_diffs() {
+            return None;
         }
+        Some(StateView::capture(&self.state))
+    }
 
-        if block.chain_id != self.chain_id {
-            return Err(format!(
-                "Invalid Chain ID: expected {}, got {}",
-                self.chain_id, block.chain_id
-            ));
+    fn publish_state_diff(&self, block: &Block, before: &StateView, after: &AccountState) {
+        let diff = StateDiff::compute(block, before, after);
+        if let Some(store) = self.balance_history_store() {
+            if let Err(e) = store.save_balance_changes(block.index, &diff.balances()) {
+                println!("Failed to index balances at height {}: {}", block.index, e);
+            }
         }
+        self.events.emit_state_diff(diff);
+    }
 
-        let expected_tx_root = block.calculate_tx_root();
-        if block.tx_root != expected_tx_root {
-            return Err(format!(
-                "tx_root mismatch: expected {}, got {}",
-                expected_tx_root, block.tx_root
-            ));
+    // Drops the balance history index and derives it again by replaying the
+    // chain from genesis. If the chain cannot be replayed, e.g. because old
+    // blocks were pruned, the index restarts at the tip instead. Returns the
+    // first indexed height.
+    pub fn rebuild_balance_history(&self) -> Result<u64, String> {
+        let store = self
+            .balance_history_store()
+            .ok_or("Balance history index is disabled")?;
+        store.clear_balance_history().map_err(|e| e.to_string())?;
+        match self.replay_balance_history() {
+            Ok(()) => {
+                store
+                    .save_balance_history_start(0)
+                    .map_err(|e| e.to_string())?;
+                Ok(0)
+            }
+            Err(e) => {
+                println!("Cannot replay balance history from genesis: {}", e);
+                store.clear_balance_history().map_err(|e| e.to_string())?;
+                self.restart_balance_history();
+                Ok(self.last_block().index)
+            }
         }
+    }
 
-        let expected_hash = block.calculate_hash();
-        if block.hash != expected_hash {
-            return Err(format!(
-                "block hash mismatch: expected {}, got {}",
-                expected_hash, block.hash
-            ));
+    fn replay_balance_history(&self) -> Result<(), String> {
+        if self.chain[0].index != 0 {
+            return Err(format!("chain starts at height {}", self.chain[0].index));
         }
-
-        if block.index > 0 && block.state_root.is_empty() {
-            return Err("Block missing state_root".into());
+        let mut state = self.rebuild_state(&[])?;
+        for block in &self.chain {
+            let block = self.with_body(block)?;
+            let before = StateView::capture(&state);
+            Blockchain::apply_to_state(&mut state, &block, self.consensus.as_ref())
+                .map_err(|e| format!("block {}: {}", block.index, e))?;
+            self.publish_state_diff(&block, &before, &state);
         }
-
-        if let Err(e) = self
-            .consensus
-            .full_validate(&block, &self.chain, &self.state)
-        {
-            return Err(format!("Consensus validation failed: {}", e));
+        Ok(())
+    }
+
+    fn restart_balance_history(&self) {
+        let Some(store) = self.balance_history_store() else {
+            return;
+        };
+        let tip = self.last_block().index;
+        let mut balances: Vec<(String, u64)> = self
+            .state
+            .accounts
+            .iter()
+            .map(|(address, account)| (address.clone(), account.balance))
+            .collect();
+        balances.sort();
+        if let Err(e) = store.save_balance_changes(tip, &balances) {
+            println!("Failed to index balances at height {}: {}", tip, e);
+        }
+        if let Err(e) = store.save_balance_history_start(tip) {
+            println!("Failed to start balance history at height {}: {}", tip, e);
         }
+    }
 
-        let mut temp_state = self.state.clone();
-        for (i, tx) in block.transactions.iter().enumerate() {
-            if tx.chain_id != block.chain_id {
-                return Err(format!(
-                    "Invalid transaction at index {}: Chain ID mismatch. Expected {}, got {}",
-                    i, block.chain_id, tx.chain_id
-                ));
-            }
-            if block.index > 0 && tx.from == "genesis" {
-                return Err(format!(
-                    "Invalid transaction at index {}: 'genesis' transactions only allowed in genesis block", i
-                ));
+    // Subscribers first hear that the orphaned blocks were reverted, newest
+    // first. Reorged blocks were never applied one at a time, so their diffs
+    // are re-derived by replaying from the fork point.
+    fn replay_state_diffs(&self, fork_point: usize, old_tip: u64, orphaned: &[(u64, String)]) {
+        for (height, hash) in orphaned.iter().rev() {
+            self.events.emit_state_revert(*height, hash.clone());
+        }
+        if let Some(store) = self.balance_history_store() {
+            if let Err(e) = store.revert_balance_history(fork_point as u64, old_tip) {
+                println!("Failed to revert balance history: {}", e);
             }
-            if block.index > 0 {
-                if let Err(e) = temp_state.validate_transaction(tx) {
-                    return Err(format!("Invalid transaction at index {}: {}", i, e));
-                }
+        } else if !self.events.wants_state_diffs() {
+            return;
+        }
+        let mut state = match self.state_after(&self.chain[..fork_point]) {
+            Ok(state) => state,
+            Err(e) => {
+                println!("Failed to replay state diffs: {}", e);
+                return;
             }
-            if let Err(e) = temp_state.apply_transaction(tx) {
-                return Err(format!("Failed to apply transaction at index {}: {}", i, e));
+        };
+        for block in &self.chain[fork_point..] {
+            let before = StateView::capture(&state);
+            if let Err(e) = Blockchain::apply_to_state(&mut state, block, self.consensus.as_ref()) {
+                println!("Failed to replay state diffs: {}", e);
+                return;
             }
+            self.publish_state_diff(block, &before, &state);
         }
+    }
 
-        let mut commit_state = self.state.clone();
-        if let Err(e) = commit_state.apply_block(&block.transactions, block.producer.as_deref()) {
-            return Err(format!("Failed to apply block: {}", e));
+    // Offset applied to the local clock, taken from the median of peer clocks.
+    pub fn set_clock_offset(&mut self, offset_ms: i64) {
+        self.clock_offset_ms = offset_ms;
+    }
+
+    pub fn clock_offset(&self) -> i64 {
+        self.clock_offset_ms
+    }
+
+    pub fn network_time_ms(&self) -> u128 {
+        let local = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i128;
+        (local + self.clock_offset_ms as i128).max(0) as u128
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_production_paused(&self) -> bool {
+        self.production_paused
+    }
+
+    // Stops local block production and mempool admission while leaving
+    // block import and sync running. Returns false if already paused.
+    pub fn pause_production(&mut self) -> bool {
+        if self.production_paused {
+            return false;
         }
+        self.production_paused = true;
+        println!(
+            "Block production paused at height {}",
+            self.last_block().index
+        );
+        true
+    }
 
-        if block.index > 0 {
-            let computed_root = commit_state.calculate_state_root();
-            if computed_root != block.state_root {
-                return Err(format!(
-                    "State root mismatch: expected {}, got {}",
-                    block.state_root, computed_root
-                ));
-            }
+    pub fn resume_production(&mut self) -> bool {
+        if !self.production_paused {
+            return false;
         }
+        self.production_paused = false;
+        println!(
+            "Block production resumed at height {}",
+            self.last_block().index
+        );
+        true
+    }
 
-        if let Some(ref store) = self.storage {
-            let _ = store.insert_block(&block);
-            let _ = store.save_last_hash(&block.hash);
-            let _ = store.save_state_root(block.index, &block.state_root);
-            let _ = store.save_canonical_height(block.index);
+    pub fn health(&self, now_ms: u128) -> ChainHealth {
+        let tip = self.last_block();
+        ChainHealth {
+            height: tip.index,
+            last_block_age_secs: (now_ms.saturating_sub(tip.timestamp) / 1000) as u64,
+            halt_timeout_secs: self.halt_timeout_secs,
+            halted: self.halted,
+            production_paused: self.production_paused,
+            validator_set_safe: self.validator_set_safe,
+            clock_offset_ms: self.clock_offset_ms,
+            chain_memory_bytes: self.chain_bytes,
+            evicted_bodies: self.evicted_bodies.len(),
         }
+    }
 
-        if let Some(evidences) = &block.slashing_evidence {
-            let slash_ratio = 0.1;
-            commit_state.apply_slashing(evidences, slash_ratio);
+    // Falls back to the bare finalized checkpoint when this node never saw
+    // its certificate, and reports it as justified too when nothing newer
+    // has reached a prevote quorum.
+    pub fn finality_status(&self) -> FinalityStatus {
+        let finalized = self
+            .finality
+            .finalized()
+            .filter(|f| f.height == self.finalized_height)
+            .cloned()
+            .unwrap_or_else(|| {
+                CheckpointStatus::unobserved(self.finalized_height, self.finalized_hash.clone())
+            });
+        let justified = self
+            .finality
+            .justified()
+            .filter(|j| j.height > finalized.height)
+            .cloned()
+            .unwrap_or_else(|| finalized.clone());
+        FinalityStatus {
+            justified,
+            finalized,
+            epochs: self.finality.epochs(),
         }
+    }
 
-        if block.index > 0 && block.index % EPOCH_LENGTH == 0 {
-            commit_state.advance_epoch(block.timestamp);
+    // Enters recovery mode once the tip is older than the halt timeout; the
+    // next block to land on the chain leaves it again.
+    pub fn check_halt(&mut self, now_ms: u128) -> bool {
+        if self.halt_timeout_secs == 0 || self.halted {
+            return self.halted;
         }
+        let health = self.health(now_ms);
+        if health.last_block_age_secs >= self.halt_timeout_secs {
+            println!(
+                "CHAIN HALTED: no block for {}s at height {}, entering recovery mode",
+                health.last_block_age_secs, health.height
+            );
+            self.halted = true;
+            self.events.emit(ChainEvent::Halted {
+                height: health.height,
+                stalled_secs: health.last_block_age_secs,
+            });
+        }
+        self.halted
+    }
 
-        self.state = commit_state;
+    fn note_progress(&mut self) {
+        if self.halted {
+            self.halted = false;
+            let height = self.last_block().index;
+            println!("Chain recovered at height {}", height);
+            self.events.emit(ChainEvent::Recovered { height });
+        }
+        self.check_validator_set();
+    }
 
-        self.chain.push(block);
+    // Certificates signed by the network's quorum are still accepted while
+    // the set is unsafe; only this node's own finality votes stop.
+    pub fn may_vote(&self) -> bool {
+        self.validator_set_safe
+    }
 
-        if let Err(e) = self.consensus.record_block(self.chain.last().unwrap()) {
-            println!("Engine record block error: {}", e);
-        }
+    pub fn validator_set_safety(&self) -> ValidatorSetSafety {
+        self.state.validator_set_safety(self.min_validators)
+    }
 
-        for tx in self.chain.last().unwrap().transactions.iter() {
-            self.mempool.remove_transaction(&tx.hash);
+    // Runs whenever the tip moves, which covers every epoch transition and
+    // every slash or jail. Chains without validators, like PoW, have nothing
+    // to check.
+    fn check_validator_set(&mut self) {
+        if self.state.validators.is_empty() {
+            return;
         }
+        let safety = self.validator_set_safety();
+        if safety.safe == self.validator_set_safe {
+            return;
+        }
+        self.validator_set_safe = safety.safe;
+        let height = self.last_block().index;
+        if safety.safe {
+            println!(
+                "Validator set of epoch {} is safe again at height {}",
+                safety.epoch, height
+            );
+            self.events.emit(ChainEvent::ValidatorSetRestored {
+                height,
+                epoch: safety.epoch,
+            });
+        } else {
+            println!(
+                "ALERT: validator set of epoch {} cannot finalize at height {}: {} of {} validators eligible (min {}), stake {} of quorum {}; this node stops voting",
+                safety.epoch,
+                height,
+                safety.eligible_validators,
+                safety.validators,
+                safety.min_validators,
+                safety.eligible_stake,
+                safety.quorum_stake
+            );
+            self.events.emit(ChainEvent::ValidatorSetUnsafe {
+                height,
+                epoch: safety.epoch,
+                eligible_validators: safety.eligible_validators,
+                eligible_stake: safety.eligible_stake,
+                quorum_stake: safety.quorum_stake,
+            });
+        }
+    }
 
-        if let Some(ref pruning_manager) = self.pruning_manager {
-            let last_block = self.chain.last().unwrap();
-            let height = last_block.index;
-            if pruning_manager.should_create_snapshot(height) {
-                let snapshot = crate::snapshot::StateSnapshot::from_state(
-                    height,
-                    last_block.hash.clone(),
-                    self.chain_id,
-                    &self.state,
-                    self.finalized_height,
-                    self.finalized_hash.clone(),
-                );
-                if let Err(e) = pruning_manager.save_snapshot(&snapshot) {
-                    println!("Failed to save snapshot at height {}: {}", height, e);
-                } else {
-                    println!("Saved state snapshot at height {}", height);
+    pub fn with_weak_subjectivity(
+        mut self,
+        mut checkpoints: Vec<WeakSubjectivityCheckpoint>,
+    ) -> Self {
+        checkpoints.sort_by_key(|cp| cp.height);
+        checkpoints.dedup();
+        self.weak_subjectivity = checkpoints;
+        self
+    }
 
-                    let prunable = pruning_manager.get_prunable_blocks(
-                        self.chain.len() as u64,
-                        height,
-                        self.finalized_height,
-                    );
-                    if !prunable.is_empty() {
-                        if let Some(ref store) = self.storage {
-                            for block_index in &prunable {
-                                let _ = store.delete_block(*block_index);
-                            }
-                            println!("Pruned {} old blocks from disk", prunable.len());
-                        }
-                    }
+    pub fn verify_weak_subjectivity(&self) -> Result<(), String> {
+        self.check_weak_subjectivity(&self.chain)
+    }
+
+    fn check_weak_subjectivity(&self, chain: &[Block]) -> Result<(), String> {
+        for cp in &self.weak_subjectivity {
+            if let Some(block) = chain.get(cp.height as usize) {
+                if block.hash != cp.hash {
+                    return Err(format!(
+                        "Block at height {} ({}) conflicts with weak subjectivity checkpoint {}",
+                        cp.height, block.hash, cp.hash
+                    ));
                 }
             }
         }
-
         Ok(())
     }
 
-    pub fn is_valid(&self) -> bool {
-        for i in 0..self.chain.len() {
-            let block = &self.chain[i];
-            let previous_chain = &self.chain[..i];
-            let dummy_state = AccountState::new();
-            if let Err(e) = self
-                .consensus
-                .validate_block(block, previous_chain, &dummy_state)
-            {
-                println!("Block {} validation failed: {}", i, e);
-                return false;
-            }
-        }
-        true
+    pub fn weak_subjectivity_floor(&self) -> Option<u64> {
+        self.weak_subjectivity
+            .iter()
+            .rev()
+            .find(|cp| (cp.height as usize) < self.chain.len())
+            .map(|cp| cp.height)
     }
-    pub fn is_valid_chain(&self, chain: &[Block]) -> bool {
-        if chain.is_empty() {
-            return false;
-        }
-
-        let genesis = &chain[0];
-        if genesis.index != 0
-            || genesis.previous_hash != "0".repeat(64)
-            || genesis.timestamp != GENESIS_TIMESTAMP
-            || genesis.hash != genesis.calculate_hash()
-            || genesis.chain_id != self.chain_id
-        {
-            return false;
-        }
 
-        for i in 0..chain.len() {
-            let block = &chain[i];
-            let previous_chain = &chain[..i];
-            let dummy_state = AccountState::new();
-            if let Err(_) = self
-                .consensus
-                .validate_block(block, previous_chain, &dummy_state)
-            {
-                return false;
+    fn load_chain_from_db(&mut self, last_hash: String) -> std::io::Result<()> {
+        let mut current_hash = last_hash;
+        let mut blocks = Vec::new();
+        if let Some(ref store) = self.storage {
+            while let Ok(Some(block)) = store.get_block(&current_hash) {
+                blocks.push(block.clone());
+                if block.previous_hash == "0".repeat(64) {
+                    break;
+                }
+                current_hash = block.previous_hash;
             }
         }
-        true
+        if blocks.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Chain broken or empty",
+            ));
+        }
+        blocks.reverse();
+        self.chain = blocks;
+        println!("Loaded {} blocks from disk", self.chain.len());
+        Ok(())
     }
-    pub fn find_fork_point(&self, other_chain: &[Block]) -> Option<usize> {
-        for (i, block) in self.chain.iter().enumerate() {
-            if i >= other_chain.len() {
-                return None;
+    fn create_genesis_block(&mut self) {
+        let genesis_block = Block::genesis();
+        self.chain.push(genesis_block.clone());
+        if let Some(ref store) = self.storage {
+            let _ = store.insert_block(&genesis_block);
+            let _ = store.save_last_hash(&genesis_block.hash);
+        }
+    }
+    pub fn last_block(&self) -> &Block {
+        self.chain.last().expect("Chain should never be empty")
+    }
+
+    // Last 10 hashes back from the tip, then exponentially spaced steps down
+    // to genesis, so a peer on a fork can still find a common ancestor.
+    pub fn block_locator(&self) -> Vec<String> {
+        let mut locator = Vec::new();
+        let mut step = 1;
+        let mut index = self.chain.len() - 1;
+        loop {
+            locator.push(self.chain[index].hash.clone());
+            if index == 0 {
+                break;
             }
-            if block.hash != other_chain[i].hash {
-                return Some(i);
+            if locator.len() >= 10 {
+                step *= 2;
             }
+            index = index.saturating_sub(step);
         }
-        None
+        locator
     }
-    pub fn try_reorg(&mut self, new_chain: Vec<Block>) -> Result<bool, String> {
-        if !self.consensus.is_better_chain(&self.chain, &new_chain) {
-            return Ok(false);
-        }
-        if !self.is_valid_chain(&new_chain) {
-            return Err("Invalid chain".to_string());
-        }
 
-        let fork_point = self.find_fork_point(&new_chain).unwrap_or(0);
-        let reorg_depth = self.chain.len().saturating_sub(fork_point);
+    // Height of the first locator entry on our chain, i.e. the newest block
+    // we share with the peer that sent it.
+    pub fn find_locator_fork(&self, locator: &[String]) -> Option<u64> {
+        locator.iter().find_map(|hash| {
+            self.chain
+                .iter()
+                .rposition(|b| &b.hash == hash)
+                .map(|i| i as u64)
+        })
+    }
 
-        if reorg_depth > MAX_REORG_DEPTH {
-            return Err(format!(
-                "Reorg depth {} exceeds max {}",
-                reorg_depth, MAX_REORG_DEPTH
-            ));
+    pub fn get_block_by_height(&self, height: u64) -> Option<Block> {
+        if let Some(block) = self.chain.get(height as usize) {
+            return self.with_body(block).ok().map(Cow::into_owned);
         }
+        self.storage
+            .as_ref()
+            .and_then(|store| store.get_block_by_height(height).ok().flatten())
+    }
 
-        let finalized_height = self.chain.len().saturating_sub(FINALITY_DEPTH);
-        if fork_point < finalized_height {
-            return Err("Cannot reorg past finality depth".to_string());
+    pub fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        if let Some(block) = self.chain.iter().rev().find(|b| b.hash == hash) {
+            return self.with_body(block).ok().map(Cow::into_owned);
         }
+        self.storage
+            .as_ref()
+            .and_then(|store| store.get_block(hash).ok().flatten())
+    }
 
-        println!(
-            "Reorg: replacing {} blocks from height {}",
-            reorg_depth, fork_point
-        );
+    // Block and position of an included transaction. The in-memory chain is
+    // searched newest first, then the storage transaction index, which also
+    // covers evicted bodies and blocks no longer held in memory.
+    pub fn find_transaction(&self, hash: &str) -> Option<(Cow<'_, Block>, usize)> {
+        let in_memory = self.chain.iter().rev().find_map(|block| {
+            block
+                .transactions
+                .iter()
+                .position(|tx| tx.hash == hash)
+                .map(|index| (Cow::Borrowed(block), index))
+        });
+        in_memory.or_else(|| {
+            self.storage
+                .as_ref()?
+                .get_transaction_location(hash)
+                .ok()
+                .flatten()
+                .map(|(block, index)| (Cow::Owned(block), index))
+        })
+    }
 
-        let new_state = Blockchain::rebuild_state(&new_chain)?;
+    // Only a block on the canonical chain counts, so after a reorg the answer
+    // follows the transaction into its new block, back to the mempool, or to
+    // None if this node no longer knows it.
+    pub fn tx_confirmation(&self, hash: &str) -> Option<TxConfirmation> {
+        let tip_height = self.last_block().index;
+        let included = self.find_transaction(hash).filter(|(block, _)| {
+            self.chain
+                .get(block.index as usize)
+                .is_some_and(|b| b.hash == block.hash)
+        });
+        let (status, block_hash, block_height) = match included {
+            Some((block, _)) => (
+                ConfirmationStatus::Included,
+                Some(block.hash.clone()),
+                Some(block.index),
+            ),
+            None if self.mempool.get(hash).is_some() => (ConfirmationStatus::Pending, None, None),
+            None => return None,
+        };
+        Some(TxConfirmation {
+            transaction_hash: hash.to_string(),
+            status,
+            block_hash,
+            block_height,
+            confirmations: block_height.map_or(0, |h| tip_height.saturating_sub(h) + 1),
+            finalized: block_height.is_some_and(|h| h <= self.finalized_height),
+            tip_height,
+            finalized_height: self.finalized_height,
+        })
+    }
 
-        self.chain = new_chain;
-        self.state = new_state;
+    pub fn get_next_nonce(&self, address: &str) -> u64 {
+        self.mempool
+            .next_nonce(address, self.state.get_nonce(address))
+    }
 
-        let mut new_pending = Vec::new();
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
 
-        let mut chain_txs = std::collections::HashSet::new();
-        for block in &self.chain {
-            for tx in &block.transactions {
-                chain_txs.insert(tx.hash.clone());
-            }
-        }
+    pub fn subscribe_state_diffs(&self) -> tokio::sync::broadcast::Receiver<StateDiffEvent> {
+        self.events.subscribe_state_diffs()
+    }
 
-        for tx in &self.mempool.get_sorted_transactions(1000) {
-            if !chain_txs.contains(&tx.hash) {
-                if self.state.validate_transaction(tx).is_ok() {
-                    new_pending.push(tx.clone());
-                }
-            }
-        }
+    // Epoch start blocks always commit to the validator set they open. Once
+    // the rolling validator set fork is active every block commits to its
+    // epoch's frozen set, so a light client can check the set from any
+    // header.
+    fn commits_validator_set(&self, height: u64) -> bool {
+        AccountState::is_epoch_start(height)
+            || self
+                .state
+                .forks
+                .is_active(FORK_ROLLING_VALIDATOR_SET, height)
+    }
 
-        self.mempool = Mempool::new(MempoolConfig::default());
-        for tx in new_pending {
-            let _ = self.mempool.add_transaction(tx);
+    // After the state snapshot fork, epoch start blocks also commit to the
+    // snapshot of the state they build on.
+    fn commits_state_snapshot(&self, height: u64) -> bool {
+        AccountState::is_epoch_start(height)
+            && self.state.forks.is_active(FORK_STATE_SNAPSHOT, height)
+    }
+
+    fn state_snapshot_commitment(&self) -> String {
+        let tip = self.last_block();
+        StateSnapshot::from_state(
+            tip.index,
+            tip.hash.clone(),
+            self.chain_id,
+            &self.state,
+            0,
+            String::new(),
+        )
+        .commitment()
+    }
+
+    // The commitment to the snapshot at `height`, carried by the block after
+    // it when that block is an epoch start.
+    pub fn committed_snapshot_hash(&self, height: u64) -> Option<String> {
+        self.chain
+            .get(height as usize + 1)
+            .map(|block| block.state_snapshot_hash.clone())
+            .filter(|hash| !hash.is_empty())
+    }
+
+    // Snapshots for heights this node has no commitment for pass, so the
+    // caller decides whether an uncommitted snapshot is acceptable.
+    pub fn verify_snapshot_commitment(&self, snapshot: &StateSnapshot) -> Result<(), String> {
+        match self.committed_snapshot_hash(snapshot.height) {
+            Some(hash) if hash != snapshot.commitment() => Err(format!(
+                "Snapshot at height {} does not match the hash committed on chain",
+                snapshot.height
+            )),
+            _ => Ok(()),
         }
+    }
 
-        if let Some(ref store) = self.storage {
-            if let Some(last) = self.chain.last() {
-                let _ = store.save_last_hash(&last.hash);
+    pub fn get_validator_set_hash(&self) -> String {
+        self.state.active_validator_snapshot(0).set_hash
+    }
+    // Called by the node's slot timer: produces at most one block per slot,
+    // and only when this node holds the signing key.
+    pub fn produce_slot_block(&mut self) -> Option<Block> {
+        if self.production_paused {
+            return None;
+        }
+        let clock = self.consensus.slot_clock()?;
+        let producer = self.local_producer()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let tip = self.last_block();
+        if tip.index > 0 && tip.slot >= clock.slot_at(now) {
+            return None;
+        }
+        let height = self.chain.len();
+        self.produce_block(producer);
+        if self.chain.len() > height {
+            self.chain.last().cloned()
+        } else {
+            None
+        }
+    }
+
+    pub fn produce_block(&mut self, producer_address: String) {
+        if self.observer {
+            println!("Observer mode: not producing blocks");
+            return;
+        }
+        if self.production_paused {
+            println!("Block production is paused");
+            return;
+        }
+        let index = self.chain.len() as u64;
+        let previous_hash = self.chain.last().unwrap().hash.clone();
+
+        let mut valid_txs = Vec::new();
+        let mut temp_state = self.state.clone();
+
+        // Fee order can put a sender's later nonce first, so keep passing over
+        // the leftovers until no more transactions become valid.
+        let max_txs = self.state.limits.max_transactions_per_block;
+        let size_budget = match self.target_block_size {
+            0 => usize::MAX,
+            target => target.min(self.state.limits.max_block_size),
+        };
+        let mut block_size = serde_json::to_vec(&Block::new(index, previous_hash.clone(), vec![]))
+            .map(|b| b.len())
+            .unwrap_or_default();
+        let mut pending_txs = self.consensus.maintenance_transactions(
+            &self.state,
+            &self.fork_id_at(index),
+            self.state.forks.tx_signing_version(index),
+        );
+        pending_txs.extend(self.mempool.block_candidates(1000));
+        let fork_hash = self.state.forks.fork_hash(index);
+        let signing_version = self.state.forks.tx_signing_version(index);
+        loop {
+            let included = valid_txs.len();
+            pending_txs.retain(|tx| {
+                let tx_size = serde_json::to_vec(tx)
+                    .map(|b| b.len() + 1)
+                    .unwrap_or_default();
+                if valid_txs.len() < max_txs
+                    && block_size + tx_size <= size_budget
+                    && tx.fork_hash == fork_hash
+                    && tx.signing_version == signing_version
+                    && !tx.is_expired_at(index)
+                    && temp_state.validate_transaction(tx).is_ok()
+                    && temp_state.apply_transaction(tx).is_ok()
+                {
+                    block_size += tx_size;
+                    valid_txs.push(tx.clone());
+                    return false;
+                }
+                true
+            });
+            if valid_txs.len() == included || valid_txs.len() >= max_txs {
+                break;
             }
-            for block in &self.chain[fork_point..] {
-                let _ = store.insert_block(block);
+        }
+        if valid_txs.len() < max_txs && size_budget == usize::MAX {
+            for tx in &pending_txs {
+                println!("Discarding invalid transaction: {}", tx.hash);
             }
         }
 
-        Ok(true)
+        let mut block = Block::new(index, previous_hash, valid_txs);
+        if self.state.forks.is_active(FORK_BLOCK_VERSION, index) {
+            block.version = BLOCK_VERSION;
+        }
+        println!(
+            "Producing block {} with {} ({} txs)...",
+            index,
+            self.consensus.consensus_type(),
+            block.transactions.len()
+        );
+
+        block.producer = Some(producer_address.clone());
+        if !self.pending_vote_evidence.is_empty() {
+            let take = self.pending_vote_evidence.len().min(MAX_EVIDENCE_PER_BLOCK);
+            block.vote_evidence = Some(self.pending_vote_evidence[..take].to_vec());
+        }
+        if self.commits_validator_set(index) {
+            block.validator_set_hash = self.state.validator_set_for_block(index).set_hash;
+        }
+        if self.commits_state_snapshot(index) {
+            block.state_snapshot_hash = self.state_snapshot_commitment();
+        }
+
+        if let Err(e) = self.consensus.prepare_block(&mut block, &self.state) {
+            println!("Block preparation failed: {}", e);
+            return;
+        }
+        let mut next_state = self.state.clone();
+        let reward_receipt =
+            match Blockchain::apply_to_state(&mut next_state, &block, self.consensus.as_ref()) {
+                Ok(receipt) => receipt,
+                Err(e) => {
+                    println!("Failed to apply block inside produce_block: {}", e);
+                    return;
+                }
+            };
+        block.state_root = next_state.calculate_state_root();
+        if let Err(e) = self.consensus.seal_block(&mut block, &self.state) {
+            println!("Block sealing failed: {}", e);
+            return;
+        }
+
+        println!("Block produced: {}", block.hash);
+        let state_before = self.state_view();
+        if let Some(ref store) = self.storage {
+            let _ = store.insert_block(&block);
+            let _ = store.save_last_hash(&block.hash);
+            let _ = store.save_state_root(block.index, &block.state_root);
+            let _ = store.save_canonical_height(block.index);
+        }
+
+        let drift_before = Blockchain::supply_drift_before(&self.state);
+        self.state = next_state;
+        self.prune_pending_evidence();
+        Blockchain::archive_dust_sweeps(self.storage.as_ref(), &mut self.state);
+        Blockchain::assert_supply_conserved(drift_before, &self.state, block.index);
+
+        self.chain.push(block.clone());
+        self.account_chain_memory(self.chain.len() - 1);
+        if let Some(before) = state_before {
+            self.publish_state_diff(&block, &before, &self.state);
+        }
+
+        for tx in &block.transactions {
+            self.mempool.remove_transaction(&tx.hash);
+        }
+        self.mempool.remove_expired_at(block.index + 1);
+        self.analytics.record(&block, &self.state.limits);
+        self.events.emit(ChainEvent::BlockAdded {
+            height: block.index,
+            hash: block.hash.clone(),
+        });
+        if let Some(receipt) = reward_receipt {
+            self.emit_epoch_rewards(&receipt);
+        }
+        self.emit_treasury_credits(block.index);
+        self.note_progress();
     }
 
-    fn rebuild_state(chain: &[Block]) -> Result<AccountState, String> {
-        let mut state = AccountState::new();
-        for block in chain.iter() {
-            if let Err(e) = state.apply_block(&block.transactions, block.producer.as_deref()) {
-                return Err(format!(
-                    "Failed to rebuild state at block {}: {}",
-                    block.index, e
-                ));
-            }
+    fn emit_epoch_rewards(&self, receipt: &EpochRewardReceipt) {
+        self.events.emit(ChainEvent::EpochRewards {
+            epoch: receipt.epoch,
+            minted: receipt.minted,
+            recipients: receipt.rewards.len(),
+        });
+    }
+
+    fn emit_treasury_credits(&mut self, height: u64) {
+        for credit in std::mem::take(&mut self.state.treasury_credits) {
+            self.events.emit(ChainEvent::TreasuryCredited {
+                height,
+                source: credit.source,
+                amount: credit.amount,
+            });
         }
-        Ok(state)
     }
-    pub fn print_info(&self) {
-        println!("================================");
-        println!("Blockchain Info");
-        println!("================================");
-        println!("Consensus: {}", self.consensus.info());
-        println!("Length: {}", self.chain.len());
-        println!("Pending Tx: {}", self.mempool.len());
-        println!("================================");
-        for block in &self.chain {
-            println!(" Block #{}: {}", block.index, &block.hash[..16]);
+    pub fn mine_pending_transactions(&mut self, miner_address: String) {
+        self.produce_block(miner_address);
+    }
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+        if self.observer {
+            return Err("Observer node does not accept transactions".into());
+        }
+        if self.production_paused {
+            return Err("Block production is paused, not accepting transactions".into());
+        }
+        if transaction.chain_id != self.chain_id {
+            return Err(format!(
+                "Invalid Chain ID: expected {}, got {}",
+                self.chain_id, transaction.chain_id
+            ));
         }
+        let fork_hash = self.state.forks.fork_hash(self.chain.len() as u64);
+        if transaction.fork_hash != fork_hash {
+            return Err(format!(
+                "Transaction signed for fork {:?}, the next block is on {:?}",
+                transaction.fork_hash, fork_hash
+            ));
+        }
+        let signing_version = self.state.forks.tx_signing_version(self.chain.len() as u64);
+        if transaction.signing_version != signing_version {
+            return Err(format!(
+                "Transaction uses signing version {}, the next block requires {}",
+                transaction.signing_version, signing_version
+            ));
+        }
+        if transaction.is_expired_at(self.chain.len() as u64) {
+            return Err(format!(
+                "Transaction expired at height {}, the next block is {}",
+                transaction.expires_at_height,
+                self.chain.len()
+            ));
+        }
+        if transaction.from == "genesis" {
+            return Err("Genesis transactions cannot be submitted to the mempool".into());
+        }
+        if !transaction.verify() {
+            return Err("Invalid transaction signature".into());
+        }
+        let state_nonce = self.state.get_nonce(&transaction.from);
+        let expected_nonce = if (state_nonce..=self.get_next_nonce(&transaction.from))
+            .contains(&transaction.nonce)
+        {
+            transaction.nonce
+        } else {
+            state_nonce
+        };
+        if let Err(e) = self
+            .state
+            .validate_transaction_at_nonce(&transaction, expected_nonce)
+        {
+            return Err(format!("Invalid transaction: {}", e));
+        }
+
+        self.mempool.set_height(self.last_block().index);
+        self.mempool
+            .add_transaction(transaction.clone())
+            .map_err(|e| format!("Mempool error: {:?}", e))?;
+        self.events
+            .emit(ChainEvent::PendingTransaction(transaction));
+
+        if let Some(producer) = self.consensus.instant_seal_producer() {
+            self.produce_block(producer);
+        }
+        Ok(())
     }
-    pub fn handle_finality_cert(
+
+    // Transactions submitted through this node (RPC, CLI) rather than
+    // received from peers; the network layer gossips them on success.
+    pub fn submit_local_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+        self.add_transaction(transaction.clone())?;
+        self.events.emit(ChainEvent::LocalTransaction(transaction));
+        Ok(())
+    }
+
+    pub fn cancel_pending_transaction(
         &mut self,
-        cert: crate::consensus::finality::FinalityCert,
-    ) -> Result<(), String> {
-        if cert.checkpoint_height <= self.finalized_height {
-            return Ok(());
+        key: &KeyPair,
+        nonce: u64,
+    ) -> Result<Transaction, String> {
+        let from = key.public_key_hex();
+        let fee = self
+            .mempool
+            .replacement_fee(&from, nonce)
+            .ok_or_else(|| format!("No pending transaction from {} with nonce {}", from, nonce))?
+            .max(self.mempool.config().min_fee);
+        let mut tx = Transaction::new_cancellation(from, nonce, fee, self.chain_id);
+        tx.sign(key);
+        self.submit_local_transaction(tx.clone())?;
+        Ok(tx)
+    }
+
+    pub fn init_genesis_account(&mut self, address: &str) {
+        self.state.add_balance(address, 1_000_000_000);
+    }
+
+    // Every failed import is also published as a `BlockRejected` event with
+    // a reason code, so operators can see what kind of blocks are refused.
+    pub fn validate_and_add_block(&mut self, block: Block) -> Result<(), String> {
+        let (height, hash, producer) = (block.index, block.hash.clone(), block.producer.clone());
+        self.import_block(block).map_err(|error| {
+            self.events.emit(ChainEvent::BlockRejected(BlockRejection {
+                height,
+                hash,
+                producer,
+                reason: error.reason,
+                error: error.message.clone(),
+            }));
+            error.message
+        })
+    }
+
+    fn import_block(&mut self, block: Block) -> Result<(), BlockImportError> {
+        if block.index <= self.finalized_height && block.hash != self.finalized_hash {
+            if let Some(finalized_path_block) = self.chain.get(block.index as usize) {
+                if finalized_path_block.hash != block.hash {
+                    return Err(BlockImportError::new(
+                        BlockRejectReason::FinalityConflict,
+                        format!(
+                            "Block at height {} conflicts with finalized checkpoint",
+                            block.index
+                        ),
+                    ));
+                }
+            } else {
+                return Err(BlockImportError::new(
+                    BlockRejectReason::FinalityConflict,
+                    format!(
+                        "Block at height {} is below finalized height {}",
+                        block.index, self.finalized_height
+                    ),
+                ));
+            }
         }
 
-        if !crate::consensus::finality::is_checkpoint_height(cert.checkpoint_height) {
-            return Err(format!(
-                "Height {} is not a valid checkpoint height",
-                cert.checkpoint_height
+        if let Some(cp) = self
+            .weak_subjectivity
+            .iter()
+            .find(|cp| cp.height == block.index)
+        {
+            if cp.hash != block.hash {
+                return Err(BlockImportError::new(
+                    BlockRejectReason::FinalityConflict,
+                    format!(
+                        "Block at height {} conflicts with weak subjectivity checkpoint {}",
+                        block.index, cp.hash
+                    ),
+                ));
+            }
+        }
+
+        if block.chain_id != self.chain_id {
+            return Err(BlockImportError::new(
+                BlockRejectReason::WrongChain,
+                format!(
+                    "Invalid Chain ID: expected {}, got {}",
+                    self.chain_id, block.chain_id
+                ),
             ));
         }
 
-        if let Some(block) = self.chain.get(cert.checkpoint_height as usize) {
-            if block.hash != cert.checkpoint_hash {
-                return Err(format!(
-                    "Certificate hash {} mismatch with our block hash {} at height {}",
-                    cert.checkpoint_hash, block.hash, cert.checkpoint_height
+        let expected_tx_root = block.calculate_tx_root();
+        if block.tx_root != expected_tx_root {
+            return Err(BlockImportError::new(
+                BlockRejectReason::BadTxRoot,
+                format!(
+                    "tx_root mismatch: expected {}, got {}",
+                    expected_tx_root, block.tx_root
+                ),
+            ));
+        }
+
+        block
+            .check_version(self.state.forks.is_active(FORK_BLOCK_VERSION, block.index))
+            .map_err(|e| BlockImportError::new(BlockRejectReason::BadVersion, e))?;
+
+        let expected_hash = block.calculate_hash();
+        if block.hash != expected_hash {
+            return Err(BlockImportError::new(
+                BlockRejectReason::BadHash,
+                format!(
+                    "block hash mismatch: expected {}, got {}",
+                    expected_hash, block.hash
+                ),
+            ));
+        }
+
+        if block.index > 0 && block.state_root.is_empty() {
+            return Err(BlockImportError::new(
+                BlockRejectReason::BadStateRoot,
+                "Block missing state_root".into(),
+            ));
+        }
+
+        if self.commits_validator_set(block.index) {
+            let expected_set_hash = self.state.validator_set_for_block(block.index).set_hash;
+            if block.validator_set_hash != expected_set_hash {
+                return Err(BlockImportError::new(
+                    BlockRejectReason::BadValidatorSet,
+                    format!(
+                        "Validator set hash mismatch at height {}: expected {}, got {}",
+                        block.index, expected_set_hash, block.validator_set_hash
+                    ),
                 ));
             }
+        }
+
+        let expected_snapshot_hash = if self.commits_state_snapshot(block.index) {
+            self.state_snapshot_commitment()
         } else {
-            return Err(format!(
-                "We don't have block at height {} yet",
-                cert.checkpoint_height
+            String::new()
+        };
+        if block.state_snapshot_hash != expected_snapshot_hash {
+            return Err(BlockImportError::new(
+                BlockRejectReason::BadSnapshotCommitment,
+                format!(
+                    "State snapshot hash mismatch at height {}: expected {:?}, got {:?}",
+                    block.index, expected_snapshot_hash, block.state_snapshot_hash
+                ),
+            ));
+        }
+
+        if let Err(e) =
+            self.consensus
+                .full_validate(&block, &self.chain, &self.state, self.network_time_ms())
+        {
+            return Err(BlockImportError::new(
+                e.reason,
+                format!("Consensus validation failed: {}", e),
             ));
         }
 
-        let active_validators = self.state.get_active_validators();
-        let entries: Vec<ValidatorEntry> = active_validators
-            .into_iter()
-            .map(|v| ValidatorEntry {
-                address: v.address.clone(),
-                stake: v.stake,
-                bls_public_key: Vec::new(),
-                pop_signature: Vec::new(),
-            })
-            .collect();
-        let snapshot = ValidatorSetSnapshot::new(cert.epoch, entries);
+        let mut temp_state = self.state.clone();
+        let fork_hash = self.state.forks.fork_hash(block.index);
+        let signing_version = self.state.forks.tx_signing_version(block.index);
+        for (i, tx) in block.transactions.iter().enumerate() {
+            Blockchain::check_transaction_placement(&block, &fork_hash, signing_version, i, tx)
+                .map_err(|e| BlockImportError::new(BlockRejectReason::BadTransaction, e))?;
+            if block.index > 0 {
+                if let Err(e) = temp_state.validate_transaction(tx) {
+                    return Err(BlockImportError::new(
+                        BlockRejectReason::BadTransaction,
+                        format!("Invalid transaction at index {}: {}", i, e),
+                    ));
+                }
+            }
+            if let Err(e) = temp_state.apply_transaction(tx) {
+                return Err(BlockImportError::new(
+                    BlockRejectReason::BadTransaction,
+                    format!("Failed to apply transaction at index {}: {}", i, e),
+                ));
+            }
+        }
+
+        self.check_slashing_evidence(&block)
+            .and_then(|_| self.check_vote_evidence(&block))
+            .map_err(|e| BlockImportError::new(BlockRejectReason::BadEvidence, e))?;
+
+        let drift_before = Blockchain::supply_drift_before(&self.state);
+        let mut commit_state = self.state.clone();
+        let reward_receipt =
+            Blockchain::apply_to_state(&mut commit_state, &block, self.consensus.as_ref())
+                .map_err(|e| {
+                    BlockImportError::new(
+                        BlockRejectReason::ExecutionFailed,
+                        format!("Failed to apply block: {}", e),
+                    )
+                })?;
+
+        if block.index > 0 {
+            let computed_root = commit_state.calculate_state_root();
+            if computed_root != block.state_root {
+                if self.state_root_diagnostics {
+                    warn!(
+                        "State root mismatch at height {}; will ask a peer for state proofs",
+                        block.index
+                    );
+                    // The keys this block changed here, plus the accounts
+                    // it names in case the producer changed one we did not.
+                    let entries = commit_state.state_root_entries();
+                    let mut keys = changed_keys(&self.state.state_root_entries(), &entries);
+                    keys.extend(
+                        block
+                            .transactions
+                            .iter()
+                            .flat_map(|tx| [&tx.from, &tx.to])
+                            .chain(block.producer.as_ref())
+                            .map(|address| format!("account:{}", address)),
+                    );
+                    keys.sort();
+                    keys.dedup();
+                    keys.truncate(MAX_STATE_PROOF_KEYS);
+                    self.pending_divergence = Some(PendingDivergence {
+                        height: block.index,
+                        block_hash: block.hash.clone(),
+                        producer: block.producer.clone(),
+                        claimed_root: block.state_root.clone(),
+                        computed_root: computed_root.clone(),
+                        entries,
+                        keys,
+                        requested: false,
+                    });
+                }
+                return Err(BlockImportError::new(
+                    BlockRejectReason::BadStateRoot,
+                    format!(
+                        "State root mismatch: expected {}, got {}",
+                        block.state_root, computed_root
+                    ),
+                ));
+            }
+        }
+
+        if let Some(ref store) = self.storage {
+            let _ = store.insert_block(&block);
+            let _ = store.save_last_hash(&block.hash);
+            let _ = store.save_state_root(block.index, &block.state_root);
+            let _ = store.save_canonical_height(block.index);
+        }
+
+        if let Some(before) = self.state_view() {
+            self.publish_state_diff(&block, &before, &commit_state);
+        }
+        self.state = commit_state;
+        Blockchain::archive_dust_sweeps(self.storage.as_ref(), &mut self.state);
+        Blockchain::assert_supply_conserved(drift_before, &self.state, block.index);
+        self.prune_pending_evidence();
+
+        self.chain.push(block);
+        self.account_chain_memory(self.chain.len() - 1);
+
+        if let Err(e) = self.consensus.record_block(self.chain.last().unwrap()) {
+            println!("Engine record block error: {}", e);
+        }
+
+        for tx in self.chain.last().unwrap().transactions.iter() {
+            self.mempool.remove_transaction(&tx.hash);
+        }
+        self.mempool.remove_expired_at(self.chain.len() as u64);
+        let tip = self.chain.last().unwrap();
+        let tip_height = tip.index;
+        self.analytics.record(tip, &self.state.limits);
+        self.events.emit(ChainEvent::BlockAdded {
+            height: tip_height,
+            hash: tip.hash.clone(),
+        });
+        if let Some(receipt) = reward_receipt {
+            self.emit_epoch_rewards(&receipt);
+        }
+        self.emit_treasury_credits(tip_height);
+        self.note_progress();
+
+        if let Some(ref store) = self.storage {
+            let height = self.chain.last().unwrap().index;
+            // Compaction scans the whole database, so it runs off the import
+            // path, one pass at a time.
+            if height.is_multiple_of(crate::storage::STORAGE_COMPACTION_INTERVAL)
+                && !self.compacting.swap(true, Ordering::AcqRel)
+            {
+                let store = store.clone();
+                let compacting = Arc::clone(&self.compacting);
+                std::thread::spawn(move || {
+                    match store.compact() {
+                        Ok(removed) if removed > 0 => {
+                            println!("Compacted storage: removed {} orphaned blocks", removed)
+                        }
+                        Ok(_) => {}
+                        Err(e) => println!("Storage compaction failed: {}", e),
+                    }
+                    compacting.store(false, Ordering::Release);
+                });
+            }
+        }
+
+        if let Some(ref pruning_manager) = self.pruning_manager {
+            let last_block = self.chain.last().unwrap();
+            let height = last_block.index;
+            if pruning_manager.should_create_snapshot(height) {
+                let snapshot = crate::snapshot::StateSnapshot::from_state(
+                    height,
+                    last_block.hash.clone(),
+                    self.chain_id,
+                    &self.state,
+                    self.finalized_height,
+                    self.finalized_hash.clone(),
+                );
+                if let Err(e) = pruning_manager.save_snapshot(&snapshot) {
+                    println!("Failed to save snapshot at height {}: {}", height, e);
+                } else {
+                    println!("Saved state snapshot at height {}", height);
+
+                    if let Some(ref store) = self.storage {
+                        match store.archive_before(height.min(self.finalized_height)) {
+                            Ok(moved) if moved > 0 => {
+                                println!("Moved {} old blocks to cold storage", moved)
+                            }
+                            Ok(_) => {}
+                            Err(e) => println!("Cold storage archival failed: {}", e),
+                        }
+                    }
+
+                    let prunable = pruning_manager.get_prunable_blocks(
+                        self.chain.len() as u64,
+                        height,
+                        self.finalized_height,
+                    );
+                    if !prunable.is_empty() {
+                        if let Some(ref store) = self.storage {
+                            for block_index in &prunable {
+                                let _ = store.delete_block(*block_index);
+                            }
+                            println!("Pruned {} old blocks from disk", prunable.len());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        for i in 0..self.chain.len() {
+            let block = &self.chain[i];
+            let previous_chain = &self.chain[..i];
+            let dummy_state = AccountState::new();
+            if let Err(e) = self
+                .consensus
+                .validate_block(block, previous_chain, &dummy_state)
+            {
+                println!("Block {} validation failed: {}", i, e);
+                return false;
+            }
+        }
+        true
+    }
+    pub fn is_valid_chain(&self, chain: &[Block]) -> bool {
+        if chain.is_empty() {
+            return false;
+        }
+
+        let genesis = &chain[0];
+        if genesis.index != 0
+            || genesis.previous_hash != "0".repeat(64)
+            || genesis.timestamp != GENESIS_TIMESTAMP
+            || genesis.hash != genesis.calculate_hash()
+            || genesis.chain_id != self.chain_id
+        {
+            return false;
+        }
+
+        for i in 0..chain.len() {
+            let block = &chain[i];
+            let previous_chain = &chain[..i];
+            let dummy_state = AccountState::new();
+            if let Err(_) = self
+                .consensus
+                .validate_block(block, previous_chain, &dummy_state)
+            {
+                return false;
+            }
+        }
+        true
+    }
+    pub fn find_fork_point(&self, other_chain: &[Block]) -> Option<usize> {
+        for (i, block) in self.chain.iter().enumerate() {
+            if i >= other_chain.len() {
+                return None;
+            }
+            if block.hash != other_chain[i].hash {
+                return Some(i);
+            }
+        }
+        None
+    }
+    pub fn try_reorg(&mut self, new_chain: Vec<Block>) -> Result<bool, String> {
+        if !self.consensus.is_better_chain(&self.chain, &new_chain) {
+            return Ok(false);
+        }
+        if !self.is_valid_chain(&new_chain) {
+            return Err("Invalid chain".to_string());
+        }
+
+        let fork_point = self.find_fork_point(&new_chain).unwrap_or(0);
+        let reorg_depth = self.chain.len().saturating_sub(fork_point);
+
+        if reorg_depth > self.state.limits.max_reorg_depth {
+            return Err(format!(
+                "Reorg depth {} exceeds max {}",
+                reorg_depth, self.state.limits.max_reorg_depth
+            ));
+        }
+
+        let finalized_height = self.chain.len().saturating_sub(FINALITY_DEPTH);
+        if fork_point < finalized_height {
+            return Err("Cannot reorg past finality depth".to_string());
+        }
+
+        match new_chain.get(self.finalized_height as usize) {
+            Some(block) if block.hash == self.finalized_hash => {}
+            _ => {
+                return Err(format!(
+                    "Cannot reorg past finalized checkpoint at height {}",
+                    self.finalized_height
+                ))
+            }
+        }
+
+        if let Some(floor) = self.weak_subjectivity_floor() {
+            if fork_point as u64 <= floor {
+                return Err(format!(
+                    "Cannot reorg past weak subjectivity checkpoint at height {}",
+                    floor
+                ));
+            }
+        }
+        self.check_weak_subjectivity(&new_chain)?;
+
+        println!(
+            "Reorg: replacing {} blocks from height {}",
+            reorg_depth, fork_point
+        );
+
+        let new_state = self.state_after(&new_chain)?;
+        let mut orphaned_txs = Vec::new();
+        let mut orphaned_evidence = Vec::new();
+        for block in &self.chain[fork_point..] {
+            let body = self.with_body(block)?.into_owned();
+            orphaned_evidence.extend(body.vote_evidence.unwrap_or_default());
+            orphaned_txs.extend(body.transactions);
+        }
+
+        let old_tip = self.last_block().hash.clone();
+        let old_height = self.last_block().index;
+        let orphaned: Vec<(u64, String)> = self.chain[fork_point..]
+            .iter()
+            .map(|block| (block.index, block.hash.clone()))
+            .collect();
+        self.chain = new_chain;
+        self.state = new_state;
+        self.account_chain_memory(fork_point);
+        self.replay_state_diffs(fork_point, old_height, &orphaned);
+        // Evidence only the orphaned blocks slashed is pending again.
+        for evidence in orphaned_evidence {
+            let key = evidence.key();
+            if !self.pending_vote_evidence.iter().any(|e| e.key() == key) {
+                self.pending_vote_evidence.push(evidence);
+            }
+        }
+        self.prune_pending_evidence();
+
+        self.events.emit(ChainEvent::Reorged {
+            depth: reorg_depth as u64,
+            fork_height: fork_point as u64,
+            old_tip,
+            new_tip: self.last_block().hash.clone(),
+        });
+        for block in &self.chain[fork_point..] {
+            self.analytics.record(block, &self.state.limits);
+            self.events.emit(ChainEvent::BlockAdded {
+                height: block.index,
+                hash: block.hash.clone(),
+            });
+        }
+        self.note_progress();
+
+        let mut new_pending = Vec::new();
+
+        let mut chain_txs = std::collections::HashSet::new();
+        for block in &self.chain {
+            for tx in &block.transactions {
+                chain_txs.insert(tx.hash.clone());
+            }
+        }
+
+        // Transactions of orphaned blocks go back to the pool ahead of the
+        // pending ones, in block order so a sender's nonces stay contiguous.
+        let mut dropped = 0;
+        let mut pool_state = self.state.clone();
+        orphaned_txs.extend(self.mempool.get_sorted_transactions(1000));
+        for tx in orphaned_txs {
+            if !chain_txs.insert(tx.hash.clone()) {
+                continue;
+            }
+            if pool_state.validate_transaction(&tx).is_ok()
+                && pool_state.apply_transaction(&tx).is_ok()
+            {
+                new_pending.push(tx);
+            } else {
+                dropped += 1;
+            }
+        }
+
+        let eviction_stats = self.mempool.eviction_stats();
+        self.mempool = Mempool::new(self.mempool.config().clone());
+        self.mempool.restore_eviction_stats(eviction_stats);
+        self.mempool.record_reorg_drops(dropped);
+        self.mempool.set_height(self.last_block().index);
+        for tx in new_pending {
+            let _ = self.mempool.add_transaction(tx);
+        }
+
+        if let Some(ref store) = self.storage {
+            if let Some(last) = self.chain.last() {
+                let _ = store.save_last_hash(&last.hash);
+            }
+            for block in &self.chain[fork_point..] {
+                let _ = store.insert_block(block);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Processed evidence is part of the state, so a reorg or replay sees
+    // exactly what its own chain slashed.
+    fn evidence_processed(&self, key: &str) -> bool {
+        self.state.evidence_processed(key)
+    }
+
+    fn check_slashing_evidence(&self, block: &Block) -> Result<(), String> {
+        let Some(evidences) = &block.slashing_evidence else {
+            return Ok(());
+        };
+        if evidences.len() > MAX_EVIDENCE_PER_BLOCK {
+            return Err(format!(
+                "Block carries {} slashing evidences (max {})",
+                evidences.len(),
+                MAX_EVIDENCE_PER_BLOCK
+            ));
+        }
+        let block_epoch = block.index / EPOCH_LENGTH;
+        let mut seen = HashSet::new();
+        for evidence in evidences {
+            let key = evidence.key();
+            let age = block_epoch.saturating_sub(evidence.header1.index / EPOCH_LENGTH);
+            if age > EVIDENCE_MAX_AGE_EPOCHS {
+                return Err(format!(
+                    "Slashing evidence {} expired ({} epochs old, max {})",
+                    key, age, EVIDENCE_MAX_AGE_EPOCHS
+                ));
+            }
+            if !seen.insert(key.clone()) || self.evidence_processed(&key) {
+                return Err(format!("Slashing evidence {} already processed", key));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_vote_evidence(&self, block: &Block) -> Result<(), String> {
+        let Some(evidences) = &block.vote_evidence else {
+            return Ok(());
+        };
+        if evidences.len() > MAX_EVIDENCE_PER_BLOCK {
+            return Err(format!(
+                "Block carries {} double-vote evidences (max {})",
+                evidences.len(),
+                MAX_EVIDENCE_PER_BLOCK
+            ));
+        }
+        let block_epoch = block.index / EPOCH_LENGTH;
+        let mut seen = HashSet::new();
+        for evidence in evidences {
+            let key = evidence.key();
+            let age = block_epoch.saturating_sub(evidence.epoch);
+            if age > EVIDENCE_MAX_AGE_EPOCHS {
+                return Err(format!(
+                    "Double-vote evidence {} expired ({} epochs old, max {})",
+                    key, age, EVIDENCE_MAX_AGE_EPOCHS
+                ));
+            }
+            verify_double_vote(evidence, &self.fork_id_at(evidence.height))
+                .map_err(|e| format!("Invalid double-vote evidence {}: {}", key, e))?;
+            if !seen.insert(key.clone()) || self.evidence_processed(&key) {
+                return Err(format!("Double-vote evidence {} already processed", key));
+            }
+        }
+        Ok(())
+    }
+
+    fn prune_pending_evidence(&mut self) {
+        let state = &self.state;
+        self.pending_vote_evidence
+            .retain(|e| !state.evidence_processed(&e.key()));
+    }
+
+    // Finality votes that already passed `authenticate` are tallied per
+    // checkpoint against our own block at that height; a voter naming two
+    // hashes for the same checkpoint becomes double-vote evidence.
+    pub fn add_prevote(&mut self, vote: Prevote) -> Result<(), String> {
+        let height = vote.checkpoint_height;
+        let result = self.vote_aggregator(vote.epoch, height)?.add_prevote(vote);
+        self.observe_votes(height);
+        self.collect_double_votes();
+        result
+    }
+
+    // Precommits also carry the voter's BLS share of the certificate, which
+    // must verify before it can be aggregated.
+    pub fn add_precommit(&mut self, vote: Precommit) -> Result<(), String> {
+        let height = vote.checkpoint_height;
+        let chain_id = self.chain_id;
+        let aggregator = self.vote_aggregator(vote.epoch, height)?;
+        if let Some(snapshot) = &aggregator.validator_snapshot {
+            vote.verify_bls(snapshot, chain_id)?;
+        }
+        let result = aggregator.add_precommit(vote);
+        self.observe_votes(height);
+        self.collect_double_votes();
+        result
+    }
+
+    fn vote_aggregator(
+        &mut self,
+        epoch: u64,
+        height: u64,
+    ) -> Result<&mut FinalityAggregator, String> {
+        if height <= self.finalized_height || !is_checkpoint_height(height) {
+            return Err(format!("Height {} is not an open checkpoint", height));
+        }
+        let (hash, state_root) = self
+            .chain
+            .get(height as usize)
+            .map(|b| (b.hash.clone(), b.state_root.clone()))
+            .ok_or_else(|| format!("We don't have block at height {} yet", height))?;
+        let finalized = self.finalized_height;
+        self.vote_aggregators.retain(|h, _| *h > finalized);
+        let snapshot = self.state.active_validator_snapshot(epoch);
+        let aggregator = self.vote_aggregators.entry(height).or_insert_with(|| {
+            let mut aggregator =
+                FinalityAggregator::new(epoch, height, hash).with_state_root(state_root);
+            aggregator.set_validator_snapshot(snapshot);
+            aggregator
+        });
+        Ok(aggregator)
+    }
+
+    fn observe_votes(&mut self, height: u64) {
+        if let Some(aggregator) = self.vote_aggregators.get(&height) {
+            self.finality.observe(aggregator);
+        }
+    }
+
+    fn collect_double_votes(&mut self) {
+        let reporter = self.local_producer().unwrap_or_default();
+        let found: Vec<VoteEvidence> = self
+            .vote_aggregators
+            .values_mut()
+            .flat_map(|a| a.take_double_votes())
+            .collect();
+        for mut evidence in found {
+            evidence.reporter = reporter.clone();
+            if let Err(e) = self.submit_vote_evidence(evidence) {
+                println!("Discarding double-vote evidence: {}", e);
+            }
+        }
+    }
+
+    pub fn submit_vote_evidence(&mut self, evidence: VoteEvidence) -> Result<(), String> {
+        verify_double_vote(&evidence, &self.fork_id_at(evidence.height))?;
+        let key = evidence.key();
+        if self.state.get_validator(&evidence.validator).is_none() {
+            return Err(format!("Unknown validator {}", evidence.validator));
+        }
+        if self.evidence_processed(&key)
+            || self.pending_vote_evidence.iter().any(|e| e.key() == key)
+        {
+            return Err(format!("Double-vote evidence {} already known", key));
+        }
+        self.pending_vote_evidence.push(evidence);
+        Ok(())
+    }
+
+    pub fn pending_vote_evidence(&self) -> &[VoteEvidence] {
+        &self.pending_vote_evidence
+    }
+
+    // Checks on a transaction that depend on the block carrying it rather
+    // than on account state.
+    fn check_transaction_placement(
+        block: &Block,
+        fork_hash: &str,
+        signing_version: u8,
+        i: usize,
+        tx: &Transaction,
+    ) -> Result<(), String> {
+        if tx.chain_id != block.chain_id {
+            return Err(format!(
+                "Invalid transaction at index {}: Chain ID mismatch. Expected {}, got {}",
+                i, block.chain_id, tx.chain_id
+            ));
+        }
+        if block.index > 0 && tx.fork_hash != fork_hash {
+            return Err(format!(
+                "Invalid transaction at index {}: signed for fork {:?}, block is on {:?}",
+                i, tx.fork_hash, fork_hash
+            ));
+        }
+        if block.index > 0 && tx.signing_version != signing_version {
+            return Err(format!(
+                "Invalid transaction at index {}: signing version {}, block requires {}",
+                i, tx.signing_version, signing_version
+            ));
+        }
+        if tx.is_expired_at(block.index) {
+            return Err(format!(
+                "Invalid transaction at index {}: expired at height {}",
+                i, tx.expires_at_height
+            ));
+        }
+        if block.index > 0 && tx.from == "genesis" {
+            return Err(format!(
+                "Invalid transaction at index {}: 'genesis' transactions only allowed in genesis block", i
+            ));
+        }
+        Ok(())
+    }
+
+    // The state transition of one block, shared by production, import and
+    // every replay, so a restarted or reorged node ends up where the live
+    // chain did. Evidence in the block is slashed, and epoch boundaries also
+    // pay rewards and process unbonding.
+    fn apply_to_state(
+        state: &mut AccountState,
+        block: &Block,
+        consensus: &dyn ConsensusEngine,
+    ) -> Result<Option<EpochRewardReceipt>, String> {
+        state.clear_touched();
+        state.freeze_validator_set_at(block.index);
+        // The reveal opens the commitment the block was prepared against, so
+        // it goes in before a RandaoCommit in the same block replaces it.
+        state.apply_randao_reveal(block.producer.as_deref(), &block.randao_reveal);
+        state.apply_block(&block.transactions, block.producer.as_deref())?;
+        state.record_proposal(block.index, block.producer.as_deref());
+        consensus.track_liveness(block, state);
+        state.last_block_timestamp = block.timestamp;
+        if let Some(evidences) = &block.slashing_evidence {
+            let slash_ratio = 0.1;
+            state.apply_slashing(evidences, slash_ratio, block.producer.as_deref());
+        }
+        if let Some(evidences) = &block.vote_evidence {
+            state.apply_vote_slashing(evidences, 0.1, block.producer.as_deref());
+        }
+        if block.index > 0 && block.index % EPOCH_LENGTH == 0 {
+            return Ok(Some(state.advance_epoch(block.timestamp)));
+        }
+        Ok(None)
+    }
+
+    // Debug and test builds check after every block, including slashing and
+    // epoch processing, that balances, stake and burns still add up to the
+    // minted supply and that every validator's stake is what accounts locked
+    // with it. Release builds skip the scan over every account.
+    fn supply_drift_before(state: &AccountState) -> Option<i128> {
+        cfg!(debug_assertions).then(|| state.supply_drift())
+    }
+
+    fn assert_supply_conserved(drift_before: Option<i128>, state: &AccountState, height: u64) {
+        if let Some(before) = drift_before {
+            let after = state.supply_drift();
+            assert_eq!(
+                after,
+                before,
+                "Supply not conserved at height {}: accounted {}, minted {}, burned {}",
+                height,
+                state.accounted_supply(),
+                state.total_minted,
+                state.total_burned
+            );
+            if let Err(e) = state.check_stake_locks() {
+                panic!("Stake not conserved at height {}: {}", height, e);
+            }
+        }
+    }
+
+    fn rebuild_state(&self, chain: &[Block]) -> Result<AccountState, String> {
+        self.replay_onto(AccountState::from_genesis(&self.spec), chain)
+    }
+
+    // Applies `chain` on top of `state` under this node's configuration.
+    fn replay_onto(
+        &self,
+        mut state: AccountState,
+        chain: &[Block],
+    ) -> Result<AccountState, String> {
+        self.apply_node_config(&mut state);
+        for block in chain.iter() {
+            let block = self.with_body(block)?;
+            Blockchain::replay_block(&mut state, &block, self.consensus.as_ref())?;
+        }
+        state.treasury_credits.clear();
+        state.carry_finality_votes(&self.state);
+        Blockchain::archive_dust_sweeps(self.storage.as_ref(), &mut state);
+        Ok(state)
+    }
+
+    fn apply_node_config(&self, state: &mut AccountState) {
+        state.staking_rules = self.state.staking_rules;
+        state.reward_config = self.state.reward_config;
+        state.treasury = self.state.treasury.clone();
+        state.limits = self.state.limits;
+        state.dust_sweep = self.state.dust_sweep;
+        state.forks = self.state.forks.clone();
+    }
+
+    fn replay_block(
+        state: &mut AccountState,
+        block: &Block,
+        consensus: &dyn ConsensusEngine,
+    ) -> Result<(), String> {
+        Blockchain::apply_to_state(state, block, consensus)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to rebuild state at block {}: {}", block.index, e))
+    }
+
+    // State at the end of block `height - 1`.
+    fn state_before(&self, height: u64) -> Result<AccountState, String> {
+        self.state_after(&self.chain[..height as usize])
+    }
+
+    // State after applying `chain`: replayed from the stored checkpoint if
+    // that block is on `chain`, otherwise from genesis.
+    fn state_after(&self, chain: &[Block]) -> Result<AccountState, String> {
+        match self.checkpoint_on(chain) {
+            Some((cp_height, state)) => self.replay_onto(state, &chain[cp_height as usize + 1..]),
+            None => self.rebuild_state(chain),
+        }
+    }
+
+    fn checkpoint_on(&self, chain: &[Block]) -> Option<(u64, AccountState)> {
+        self.storage
+            .as_ref()
+            .and_then(|store| AccountState::load_checkpoint(store).ok().flatten())
+            .filter(|(cp_height, hash, _)| {
+                chain
+                    .get(*cp_height as usize)
+                    .is_some_and(|b| b.index == *cp_height && b.hash == *hash)
+            })
+            .map(|(cp_height, _, state)| (cp_height, state))
+    }
+
+    // Re-executes the block at `height` one transaction at a time against
+    // the state before it. See `ReplayJob`.
+    pub fn debug_replay_block(&self, height: u64) -> Result<BlockTrace, String> {
+        self.replay_job(height)?.run()
+    }
+
+    // Copies what a replay of block `height` needs: the last state
+    // checkpoint below it and the blocks from there on. Running the job
+    // does not need the chain, so callers can release its lock first.
+    pub fn replay_job(&self, height: u64) -> Result<ReplayJob, String> {
+        if height == 0 {
+            return Err("The genesis block has no prior state to replay".into());
+        }
+        let block = self
+            .chain
+            .get(height as usize)
+            .ok_or_else(|| format!("Block {} is not in the in-memory chain", height))?;
+        let block = self.with_body(block)?.into_owned();
+        let prior = &self.chain[..height as usize];
+        let (mut base, from) = match self.checkpoint_on(prior) {
+            Some((cp_height, state)) => (state, cp_height as usize + 1),
+            None => (AccountState::from_genesis(&self.spec), 0),
+        };
+        self.apply_node_config(&mut base);
+        let blocks = prior[from..]
+            .iter()
+            .map(|b| self.with_body(b).map(|b| b.into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ReplayJob {
+            base,
+            blocks,
+            block,
+            consensus: self.consensus.clone(),
+        })
+    }
+
+    pub fn print_info(&self) {
+        println!("================================");
+        println!("Blockchain Info");
+        println!("================================");
+        println!("Consensus: {}", self.consensus.info());
+        println!("Length: {}", self.chain.len());
+        println!("Pending Tx: {}", self.mempool.len());
+        println!("================================");
+        for block in &self.chain {
+            println!(" Block #{}: {}", block.index, &block.hash[..16]);
+        }
+    }
+    pub fn handle_finality_cert(
+        &mut self,
+        cert: crate::consensus::finality::FinalityCert,
+    ) -> Result<(), String> {
+        if cert.checkpoint_height <= self.finalized_height {
+            return Ok(());
+        }
+        if !crate::consensus::finality::is_checkpoint_height(cert.checkpoint_height) {
+            return Err(format!(
+                "Height {} is not a valid checkpoint height",
+                cert.checkpoint_height
+            ));
+        }
+
+        if let Some(block) = self.chain.get(cert.checkpoint_height as usize) {
+            if block.hash != cert.checkpoint_hash {
+                return Err(format!(
+                    "Certificate hash {} mismatch with our block hash {} at height {}",
+                    cert.checkpoint_hash, block.hash, cert.checkpoint_height
+                ));
+            }
+            cert.verify_state_root(&block.state_root)?;
+        } else {
+            return Err(format!(
+                "We don't have block at height {} yet",
+                cert.checkpoint_height
+            ));
+        }
+
+        let snapshot = self.state.active_validator_snapshot(cert.epoch);
+
+        cert.verify(&snapshot, self.chain_id)?;
+        self.state.record_finality_votes(&cert, &snapshot);
+
+        if let Some(ref store) = self.storage {
+            let _ = store.save_finality_cert(cert.checkpoint_height, &cert);
+            let _ = store.save_canonical_height(cert.checkpoint_height);
+        }
+        self.set_finalized(cert.checkpoint_height, cert.checkpoint_hash.clone())?;
+        self.finality.on_finalized(&cert, &snapshot);
+
+        info!(
+            "FINALIZED checkpoint: height={}, hash={}",
+            self.finalized_height, self.finalized_hash
+        );
+
+        Ok(())
+    }
+
+    pub fn finalized_state_root(&self, height: u64) -> Option<String> {
+        if height == 0 || height > self.finalized_height {
+            return None;
+        }
+        self.storage
+            .as_ref()
+            .and_then(|store| store.get_finality_cert(height).ok().flatten())
+            .map(|cert| cert.state_root)
+            .filter(|root| !root.is_empty())
+    }
+
+    pub fn verify_finalized_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), String> {
+        let root = self.finalized_state_root(snapshot.height).ok_or_else(|| {
+            format!(
+                "No finalized state root known for height {}",
+                snapshot.height
+            )
+        })?;
+        if !snapshot.verify() || snapshot.state_root().as_deref() != Ok(root.as_str()) {
+            return Err(format!(
+                "Snapshot at height {} does not match the finalized state root",
+                snapshot.height
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn export_checkpoint(&self) -> Result<CheckpointBundle, String> {
+        let height = self.finalized_height;
+        if height == 0 {
+            return Err("No finalized checkpoint to export".into());
+        }
+        let cert = self
+            .storage
+            .as_ref()
+            .and_then(|store| store.get_finality_cert(height).ok().flatten())
+            .ok_or_else(|| format!("No finality certificate stored for height {}", height))?;
+        let blocks = self.blocks_between(0, height as usize + 1);
+        if blocks.len() != height as usize + 1 {
+            return Err(format!("Block bodies below height {} are missing", height));
+        }
+        let state = if self.last_block().index == height {
+            self.state.clone()
+        } else {
+            self.state_after(&blocks)?
+        };
+        let validator_set = state.active_validator_snapshot(cert.epoch);
+        cert.verify(&validator_set, self.chain_id)?;
+        let snapshot = StateSnapshot::from_state(
+            height,
+            self.finalized_hash.clone(),
+            self.chain_id,
+            &state,
+            height,
+            self.finalized_hash.clone(),
+        );
+        Ok(CheckpointBundle {
+            blocks: blocks[1..].to_vec(),
+            cert,
+            validator_set,
+            snapshot,
+        })
+    }
+
+    // Fast sync: the bundle must end at a weak subjectivity checkpoint, whose
+    // hash pins every block below it, so those are only checked for
+    // integrity and linkage. The finality cert's aggregate signature is
+    // checked against the validator set committed in that pinned history,
+    // and the state root of the checkpoint block vouches for the state.
+    // Everything after the checkpoint is validated and executed as usual.
+    pub fn checkpoint_sync(&mut self, bundle: CheckpointBundle) -> Result<u64, String> {
+        let CheckpointBundle {
+            blocks,
+            cert,
+            validator_set,
+            snapshot,
+        } = bundle;
+        if self.weak_subjectivity.is_empty() {
+            return Err("Checkpoint sync requires a weak subjectivity checkpoint".into());
+        }
+        if blocks.first().map(|b| b.index) != Some(self.chain.len() as u64) {
+            return Err(format!(
+                "Checkpoint blocks must start at height {}",
+                self.chain.len()
+            ));
+        }
+        let mut prev_hash = self.last_block().hash.clone();
+        for block in &blocks {
+            if block.chain_id != self.chain_id {
+                return Err(format!("Block {} has the wrong chain id", block.index));
+            }
+            if block.previous_hash != prev_hash
+                || block.hash != block.calculate_hash()
+                || block.tx_root != block.calculate_tx_root()
+            {
+                return Err(format!(
+                    "Block {} does not link or hash correctly",
+                    block.index
+                ));
+            }
+            prev_hash = block.hash.clone();
+        }
+
+        let mut new_chain = self.chain.clone();
+        new_chain.extend(blocks);
+        self.check_weak_subjectivity(&new_chain)?;
+        let checkpoint = new_chain.last().unwrap();
+        if !self
+            .weak_subjectivity
+            .iter()
+            .any(|cp| cp.height == checkpoint.index && cp.hash == checkpoint.hash)
+        {
+            return Err(format!(
+                "Checkpoint {} is not a weak subjectivity checkpoint",
+                checkpoint.index
+            ));
+        }
+        if !is_checkpoint_height(cert.checkpoint_height)
+            || cert.checkpoint_height != checkpoint.index
+            || cert.checkpoint_hash != checkpoint.hash
+        {
+            return Err("Finality certificate does not cover the last block".into());
+        }
+        // The set signing the cert is the one committed at the epoch start.
+        let epoch_start = &new_chain[(checkpoint.index - checkpoint.index % EPOCH_LENGTH) as usize];
+        if validator_set.set_hash != ValidatorSetSnapshot::compute_hash(&validator_set.validators)
+            || validator_set.set_hash != epoch_start.validator_set_hash
+        {
+            return Err("Validator set does not match the checkpoint epoch".into());
+        }
+        cert.verify(&validator_set, self.chain_id)?;
+        cert.verify_state_root(&checkpoint.state_root)?;
+        if !snapshot.verify()
+            || snapshot.chain_id != self.chain_id
+            || snapshot.height != checkpoint.index
+            || snapshot.block_hash != checkpoint.hash
+            || snapshot.state_root().as_deref() != Ok(checkpoint.state_root.as_str())
+        {
+            return Err("State snapshot does not match the checkpoint block".into());
+        }
+
+        // The snapshot carries the validators, frozen set, epoch and supply
+        // the checkpoint root commits to; only config comes from this node.
+        let mut state = snapshot.to_account_state()?;
+        state.staking_rules = self.state.staking_rules;
+        state.reward_config = self.state.reward_config;
+        state.treasury = self.state.treasury.clone();
+        state.limits = self.state.limits;
+        state.dust_sweep = self.state.dust_sweep;
+        state.forks = self.state.forks.clone();
+        state.last_block_timestamp = checkpoint.timestamp;
+
+        let from = self.chain.len();
+        if let Some(ref store) = self.storage {
+            for block in &new_chain[from..] {
+                let _ = store.insert_block(block);
+            }
+            let _ = store.save_last_hash(&checkpoint.hash);
+            let _ = store.save_finality_cert(cert.checkpoint_height, &cert);
+        }
+        let height = checkpoint.index;
+        let hash = checkpoint.hash.clone();
+        self.chain = new_chain;
+        self.state = state;
+        self.account_chain_memory(from);
+        self.restart_balance_history();
+        for block in &self.chain[from..] {
+            self.analytics.record(block, &self.state.limits);
+            self.events.emit(ChainEvent::BlockAdded {
+                height: block.index,
+                hash: block.hash.clone(),
+            });
+        }
+        self.set_finalized(height, hash)?;
+        self.note_progress();
+        info!("Checkpoint sync adopted finalized height {}", height);
+        Ok(height)
+    }
+
+    pub fn set_finalized(&mut self, height: u64, hash: String) -> Result<(), String> {
+        if let Some(ref store) = self.storage {
+            store
+                .save_finalized(height, &hash)
+                .map_err(|e| format!("Failed to persist finalized checkpoint: {}", e))?;
+        }
+        self.finalized_height = height;
+        self.finalized_hash = hash.clone();
+        self.persist_state_checkpoint();
+        self.events.emit(ChainEvent::Finalized { height, hash });
+        Ok(())
+    }
+
+    // Saves the state at the finalized block, which no reorg can revert, so
+    // the next start resumes from there and replays only the later blocks.
+    fn persist_state_checkpoint(&self) {
+        let Some(ref store) = self.storage else {
+            return;
+        };
+        let height = self.finalized_height;
+        let Some(block) = self.chain.get(height as usize) else {
+            return;
+        };
+        let saved = if height == self.last_block().index {
+            self.state.save_checkpoint(store, height, &block.hash)
+        } else {
+            self.state_before(height + 1)
+                .and_then(|state| state.save_checkpoint(store, height, &block.hash))
+        };
+        if let Err(e) = saved {
+            println!("Failed to persist state checkpoint at {}: {}", height, e);
+        }
+    }
+
+    pub fn consensus(&self) -> &dyn ConsensusEngine {
+        self.consensus.as_ref()
+    }
+
+    pub fn export_chain(&self, out: &mut impl Write) -> Result<u64, String> {
+        let io_err = |e: std::io::Error| format!("Failed to write chain export: {}", e);
+        out.write_all(CHAIN_EXPORT_MAGIC).map_err(io_err)?;
+        out.write_all(&self.chain_id.to_le_bytes())
+            .map_err(io_err)?;
+        for block in &self.chain {
+            let block = self.with_body(block)?;
+            let bytes = bincode::serialize(&block)
+                .map_err(|e| format!("Failed to encode block {}: {}", block.index, e))?;
+            out.write_all(&(bytes.len() as u32).to_le_bytes())
+                .map_err(io_err)?;
+            out.write_all(&bytes).map_err(io_err)?;
+        }
+        out.flush().map_err(io_err)?;
+        Ok(self.chain.len() as u64)
+    }
+
+    pub fn import_chain(&mut self, input: &mut impl Read) -> Result<u64, String> {
+        let io_err = |e: std::io::Error| format!("Failed to read chain export: {}", e);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(io_err)?;
+        if &magic != CHAIN_EXPORT_MAGIC {
+            return Err("Not a Budlum chain export file".to_string());
+        }
+        let mut chain_id = [0u8; 8];
+        input.read_exact(&mut chain_id).map_err(io_err)?;
+        let chain_id = u64::from_le_bytes(chain_id);
+        if chain_id != self.chain_id {
+            return Err(format!(
+                "Invalid Chain ID: expected {}, got {}",
+                self.chain_id, chain_id
+            ));
+        }
+
+        let mut imported = 0;
+        loop {
+            let mut len = [0u8; 4];
+            match input.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(io_err(e)),
+            }
+            let bytes = crate::snapshot::read_bounded(
+                input,
+                u32::from_le_bytes(len) as usize,
+                MAX_CHAIN_EXPORT_RECORD_BYTES,
+            )
+            .map_err(|e| format!("Failed to read chain export: {}", e))?;
+            let block: Block = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode block: {}", e))?;
+
+            if let Some(existing) = self.chain.get(block.index as usize) {
+                if existing.hash != block.hash {
+                    return Err(format!(
+                        "Block {} conflicts with local chain: local {}, imported {}",
+                        block.index, existing.hash, block.hash
+                    ));
+                }
+                continue;
+            }
+            if block.previous_hash != self.last_block().hash {
+                return Err(format!(
+                    "Block {} does not extend local tip {}",
+                    block.index,
+                    self.last_block().index
+                ));
+            }
+            let index = block.index;
+            self.validate_and_add_block(block)
+                .map_err(|e| format!("Block {} rejected: {}", index, e))?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+}
+
+impl Clone for Blockchain {
+    fn clone(&self) -> Self {
+        Blockchain {
+            chain: self.chain.clone(),
+            consensus: Arc::clone(&self.consensus),
+            mempool: Mempool::default(),
+            storage: self.storage.clone(),
+            state: self.state.clone(),
+            chain_id: self.chain_id,
+            pruning_manager: self.pruning_manager.clone(),
+            finalized_height: self.finalized_height,
+            finalized_hash: self.finalized_hash.clone(),
+            weak_subjectivity: self.weak_subjectivity.clone(),
+            events: EventBus::new(),
+            sync: self.sync.clone(),
+            peer_versions: self.peer_versions.clone(),
+            vote_aggregators: HashMap::new(),
+            finality: self.finality.clone(),
+            pending_vote_evidence: self.pending_vote_evidence.clone(),
+            halt_timeout_secs: self.halt_timeout_secs,
+            halted: self.halted,
+            min_validators: self.min_validators,
+            validator_set_safe: self.validator_set_safe,
+            production_paused: self.production_paused,
+            observer: self.observer,
+            clock_offset_ms: self.clock_offset_ms,
+            target_block_size: self.target_block_size,
+            analytics: self.analytics.clone(),
+            balance_history: self.balance_history,
+            state_root_diagnostics: self.state_root_diagnostics,
+            pending_divergence: self.pending_divergence.clone(),
+            last_divergence: self.last_divergence.clone(),
+            chain_memory_budget: self.chain_memory_budget,
+            block_bytes: self.block_bytes.clone(),
+            chain_bytes: self.chain_bytes,
+            evicted_bodies: self.evicted_bodies.clone(),
+            eviction_cursor: self.eviction_cursor,
+            spec: self.spec.clone(),
+            compacting: Arc::clone(&self.compacting),
+        }
+    }
+}
+#[cfg(all(test, feature = "pow-miner"))]
+mod tests {
+    use super::*;
+    use crate::consensus::finality::sign_pop;
+    use crate::consensus::finality::test_keys::{bls_key, sign_cert};
+    use crate::consensus::{PoWEngine, MIN_BLOCK_INTERVAL_MS};
+    use crate::crypto::KeyPair;
+
+    // Produces a block and backdates it onto a one-interval grid so a
+    // follower importing the chain passes the minimum block interval check.
+    // Epoch processing reads the block time, so the block is re-applied to
+    // the parent state at the new time.
+    fn produce_spaced(bc: &mut Blockchain, producer: &str) {
+        let parent = bc.state.clone();
+        bc.produce_block(producer.into());
+        let mut tip = bc.chain.pop().unwrap();
+        tip.timestamp = tip.timestamp - 60 * 60 * 1000 + tip.index as u128 * MIN_BLOCK_INTERVAL_MS;
+        bc.state = parent;
+        Blockchain::apply_to_state(&mut bc.state, &tip, bc.consensus.as_ref()).unwrap();
+        tip.state_root = bc.state.calculate_state_root();
+        tip.hash = tip.calculate_hash();
+        bc.chain.push(tip);
+    }
+
+    #[test]
+    fn test_blockchain_with_pow() {
+        let consensus = Arc::new(PoWEngine::new(1));
+        let mut blockchain = Blockchain::new(consensus, None, 1337, None);
+
+        let keypair = KeyPair::generate().unwrap();
+        let pubkey = keypair.public_key_hex();
+
+        blockchain.state.add_balance(&pubkey, 100);
+
+        let mut tx = Transaction::new(pubkey.clone(), "bob".to_string(), 50, vec![]);
+        tx.fee = 1;
+        tx.sign(&keypair);
+
+        blockchain.add_transaction(tx).unwrap();
+
+        blockchain.produce_block("miner1".to_string());
+        assert!(blockchain.is_valid());
+        assert_eq!(blockchain.chain.len(), 2);
+    }
+
+    #[test]
+    fn test_epoch_transition_and_unjailing() {
+        let consensus = Arc::new(PoWEngine::new(1));
+        let mut blockchain = Blockchain::new(consensus, None, 1337, None);
+
+        let validator_addr = "validator1".to_string();
+        blockchain.state.add_validator(validator_addr.clone(), 1000);
+
+        if let Some(v) = blockchain.state.get_validator_mut(&validator_addr) {
+            v.jailed = true;
+            v.active = false;
+            v.jail_until = 0;
+        }
+
+        assert_eq!(blockchain.state.epoch_index, 0);
+        if let Some(v) = blockchain.state.get_validator(&validator_addr) {
+            assert!(v.jailed);
+        }
+
+        for _ in 0..EPOCH_LENGTH {
+            blockchain.produce_block("miner".to_string());
+        }
+
+        assert_eq!(blockchain.chain.len(), (EPOCH_LENGTH as usize) + 1);
+
+        assert_eq!(blockchain.state.epoch_index, 1);
+
+        if let Some(v) = blockchain.state.get_validator(&validator_addr) {
+            assert!(!v.jailed, "Validator should have been unjailed");
+            assert!(v.active, "Validator should be active");
+        } else {
+            panic!("Validator not found");
+        }
+    }
+
+    #[test]
+    fn test_slashing_evidence_bounded_and_deduplicated() {
+        use crate::block::BlockHeader;
+        use crate::consensus::pos::SlashingEvidence;
+
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let evidence_at = |height: u64| {
+            let mut header = BlockHeader::from_block(&Block::new(height, "prev".into(), vec![]));
+            header.producer = Some("offender".into());
+            SlashingEvidence::new(header.clone(), header, vec![], vec![])
+        };
+        let mut block = Block::new(EPOCH_LENGTH * 10, "prev".into(), vec![]);
+
+        block.slashing_evidence = Some(
+            (0..=MAX_EVIDENCE_PER_BLOCK as u64)
+                .map(|h| evidence_at(EPOCH_LENGTH * 9 + h))
+                .collect(),
+        );
+        assert!(bc.check_slashing_evidence(&block).is_err());
+
+        block.slashing_evidence = Some(vec![evidence_at(EPOCH_LENGTH)]);
+        assert!(bc
+            .check_slashing_evidence(&block)
+            .unwrap_err()
+            .contains("expired"));
+
+        block.slashing_evidence = Some(vec![
+            evidence_at(EPOCH_LENGTH * 9),
+            evidence_at(EPOCH_LENGTH * 9),
+        ]);
+        assert!(bc.check_slashing_evidence(&block).is_err());
+
+        block.slashing_evidence = Some(vec![evidence_at(EPOCH_LENGTH * 9)]);
+        bc.check_slashing_evidence(&block).unwrap();
+        let fork = bc.clone();
+        bc.state
+            .apply_slashing(block.slashing_evidence.as_ref().unwrap(), 0.1, None);
+        assert!(bc
+            .check_slashing_evidence(&block)
+            .unwrap_err()
+            .contains("already processed"));
+        // A chain that did not slash it, e.g. after a reorg, still accepts it.
+        fork.check_slashing_evidence(&block).unwrap();
+
+        // Keys are dropped once the evidence would be rejected as expired.
+        for _ in 0..=EVIDENCE_MAX_AGE_EPOCHS + 9 {
+            bc.state.advance_epoch(0);
+        }
+        assert!(!bc
+            .state
+            .evidence_processed(&evidence_at(EPOCH_LENGTH * 9).key()));
+    }
+
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_slashing_execution() {
+        use crate::block::BlockHeader;
+        use crate::consensus::pos::{PoSConfig, SlashingEvidence};
+        use crate::consensus::PoSEngine;
+
+        let alice_keys = crate::crypto::ValidatorKeys::generate().unwrap();
+        let alice_key = alice_keys.sig_key.clone();
+        let alice_vrf_pub = alice_keys.vrf_key.public.to_bytes().to_vec();
+        let alice_pub = alice_key.public_key_hex();
+
+        let mut config = PoSConfig::default();
+        config.slashing_penalty = 0.50;
+
+        let engine = Arc::new(PoSEngine::new(config, Some(alice_keys)));
+
+        let mut blockchain = Blockchain::new(engine.clone(), None, 1337, None);
+
+        blockchain.state.add_validator(alice_pub.clone(), 2000);
+        if let Some(v) = blockchain.state.get_validator_mut(&alice_pub) {
+            v.vrf_public_key = alice_vrf_pub.clone();
+        }
+        blockchain.state.add_balance(&alice_pub, 100);
+
+        let mut real_b1 = Block::new(10, "prev".into(), vec![]);
+        real_b1.producer = Some(alice_pub.clone());
+        real_b1.hash = real_b1.calculate_hash();
+        let sig1 = alice_key.sign(&real_b1.signing_digest("")).to_vec();
+        real_b1.signature = Some(sig1.clone());
+        let h1 = BlockHeader::from_block(&real_b1);
+
+        let mut real_b2 = Block::new(10, "prev".into(), vec![]);
+        real_b2.timestamp += 1;
+        real_b2.producer = Some(alice_pub.clone());
+        real_b2.hash = real_b2.calculate_hash();
+        let sig2 = alice_key.sign(&real_b2.signing_digest("")).to_vec();
+        real_b2.signature = Some(sig2.clone());
+        let h2 = BlockHeader::from_block(&real_b2);
+
+        let evidence = SlashingEvidence::new(h1, h2, sig1, sig2);
+
+        {
+            let mut guard = engine.slashing_evidence.write().unwrap();
+            guard.push(evidence);
+        }
+
+        blockchain.produce_block(alice_pub.clone());
+
+        let produced_block = blockchain.chain.last().unwrap();
+        assert!(
+            produced_block.slashing_evidence.is_some(),
+            "Block should contain slashing evidence"
+        );
+        assert_eq!(produced_block.slashing_evidence.as_ref().unwrap().len(), 1);
+
+        let mut blockchain2 = Blockchain::new(engine.clone(), None, 1337, None);
+        blockchain2.state.add_validator(alice_pub.clone(), 2000);
+        if let Some(v) = blockchain2.state.get_validator_mut(&alice_pub) {
+            v.vrf_public_key = alice_vrf_pub.clone();
+        }
+        blockchain2.state.add_balance(&alice_pub, 100);
+        blockchain2
+            .validate_and_add_block(produced_block.clone())
+            .unwrap();
+
+        let validator = blockchain2.state.get_validator(&alice_pub).unwrap();
+        assert!(validator.slashed, "Validator should be slashed");
+        assert!(!validator.active);
+        assert!(validator.stake < 2000);
+    }
+
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_slot_aligned_pos_production() {
+        use crate::consensus::pos::PoSConfig;
+        use crate::consensus::PoSEngine;
+
+        let keys = crate::crypto::ValidatorKeys::generate().unwrap();
+        let vrf_pub = keys.vrf_key.public.to_bytes().to_vec();
+        let sig_key = keys.sig_key.clone();
+        let pubkey = sig_key.public_key_hex();
+        let config = PoSConfig {
+            slot_duration: 3600,
+            ..Default::default()
+        };
+        let engine = Arc::new(PoSEngine::new(config, Some(keys)));
+        let setup = |bc: &mut Blockchain| {
+            bc.state.add_validator(pubkey.clone(), 2000);
+            bc.state.get_validator_mut(&pubkey).unwrap().vrf_public_key = vrf_pub.clone();
+        };
+        let mut producer = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut producer);
+
+        let block = producer.produce_slot_block().unwrap();
+        let clock = engine.clock();
+        assert_eq!(block.slot, clock.slot_at(block.timestamp));
+        assert!(producer.produce_slot_block().is_none());
+
+        let mut follower = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut follower);
+        let mut wrong_slot = block.clone();
+        wrong_slot.slot += 1;
+        wrong_slot.sign(&sig_key, "");
+        assert!(follower
+            .validate_and_add_block(wrong_slot)
+            .unwrap_err()
+            .contains("does not match its timestamp slot"));
+        follower.validate_and_add_block(block.clone()).unwrap();
+
+        let mut same_slot = Block::new(2, block.hash.clone(), vec![]);
+        same_slot.timestamp = block.timestamp + 1;
+        same_slot.producer = Some(pubkey.clone());
+        engine
+            .prepare_block(&mut same_slot, &follower.state)
+            .unwrap();
+        engine.seal_block(&mut same_slot, &follower.state).unwrap();
+        let err = engine
+            .validate_block(&same_slot, &follower.chain, &follower.state)
+            .unwrap_err();
+        assert!(err.message.contains("not after parent slot"));
+        assert_eq!(err.reason, BlockRejectReason::BadTimestamp);
+    }
+
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_randao_reveals_mix_into_epoch_seed() {
+        use crate::consensus::pos::PoSConfig;
+        use crate::consensus::PoSEngine;
+
+        let keys = crate::crypto::ValidatorKeys::generate().unwrap();
+        let vrf_pub = keys.vrf_key.public.to_bytes().to_vec();
+        let sig_key = keys.sig_key.clone();
+        let pubkey = sig_key.public_key_hex();
+        let config = PoSConfig {
+            slot_duration: 3600,
+            ..Default::default()
+        };
+        let engine = Arc::new(PoSEngine::new(config, Some(keys)));
+        let commitment = engine.randao_commitment(0).unwrap().to_vec();
+        let setup = |bc: &mut Blockchain| {
+            bc.state.add_validator(pubkey.clone(), 2000);
+            let validator = bc.state.get_validator_mut(&pubkey).unwrap();
+            validator.vrf_public_key = vrf_pub.clone();
+            validator.randao_commitment = commitment.clone();
+        };
+        let mut producer = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut producer);
+        let mut follower = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut follower);
+
+        let block = producer.produce_slot_block().unwrap();
+        assert!(crate::randao::verify_reveal(
+            &commitment,
+            &block.randao_reveal
+        ));
+        let mut forged = block.clone();
+        forged.randao_reveal = vec![7; 32];
+        forged.sign(&sig_key, "");
+        assert!(follower
+            .validate_and_add_block(forged)
+            .unwrap_err()
+            .contains("RANDAO reveal"));
+        follower.validate_and_add_block(block.clone()).unwrap();
+        assert_eq!(
+            follower
+                .state
+                .get_validator(&pubkey)
+                .unwrap()
+                .randao_commitment,
+            block.randao_reveal
+        );
+        assert_ne!(follower.state.randao_mix, [0u8; 32]);
+        assert_eq!(follower.state.randao_seed, [0u8; 32]);
+        assert_eq!(follower.state.randao_mix, producer.state.randao_mix);
+
+        let mix = follower.state.randao_mix;
+        follower.state.advance_epoch(block.timestamp);
+        assert_eq!(follower.state.randao_seed, mix);
+    }
+
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_randao_recommits_before_onion_runs_out() {
+        use crate::consensus::pos::{PoSConfig, RANDAO_RECOMMIT_MARGIN};
+        use crate::consensus::PoSEngine;
+        use crate::randao::HashOnion;
+
+        let keys = crate::crypto::ValidatorKeys::generate().unwrap();
+        let vrf_pub = keys.vrf_key.public.to_bytes().to_vec();
+        let pubkey = keys.sig_key.public_key_hex();
+        // Layer RANDAO_RECOMMIT_MARGIN of the generation-0 onion.
+        let mut secret = keys.vrf_key.secret.to_bytes().to_vec();
+        secret.extend_from_slice(&0u64.to_le_bytes());
+        let worn = HashOnion::new(&secret, RANDAO_RECOMMIT_MARGIN + 1).commitment();
+        let config = PoSConfig {
+            slot_duration: 3600,
+            ..Default::default()
+        };
+        let engine = Arc::new(PoSEngine::new(config, Some(keys)));
+        let next = engine.randao_commitment(1).unwrap();
+        let setup = |bc: &mut Blockchain| {
+            bc.state.add_balance(&pubkey, 100);
+            bc.state.add_validator(pubkey.clone(), 2000);
+            let validator = bc.state.get_validator_mut(&pubkey).unwrap();
+            validator.vrf_public_key = vrf_pub.clone();
+            validator.randao_commitment = worn.to_vec();
+        };
+        let mut producer = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut producer);
+        let mut follower = Blockchain::new(engine.clone(), None, 1337, None);
+        setup(&mut follower);
+
+        // The block still reveals from the old onion and registers the next.
+        let block = producer.produce_slot_block().unwrap();
+        assert!(crate::randao::verify_reveal(&worn, &block.randao_reveal));
+        assert!(block
+            .transactions
+            .iter()
+            .any(|tx| tx.tx_type == crate::transaction::TransactionType::RandaoCommit));
+        follower.validate_and_add_block(block.clone()).unwrap();
+        let validator = follower.state.get_validator(&pubkey).unwrap();
+        assert_eq!(validator.randao_generation, 1);
+        assert_eq!(validator.randao_commitment, next.to_vec());
+        assert_eq!(follower.state.randao_mix, producer.state.randao_mix);
+
+        // The following block reveals from the new onion.
+        let mut following = Block::new(2, block.hash.clone(), vec![]);
+        following.timestamp = block.timestamp + engine.clock().slot_ms as u128;
+        following.producer = Some(pubkey.clone());
+        engine
+            .prepare_block(&mut following, &follower.state)
+            .unwrap();
+        engine.seal_block(&mut following, &follower.state).unwrap();
+        assert!(crate::randao::verify_reveal(
+            &next,
+            &following.randao_reveal
+        ));
+        engine
+            .validate_block(&following, &follower.chain, &follower.state)
+            .unwrap();
+
+        // The mix is committed, so a node with a different one diverges.
+        let mut skewed = follower.state.clone();
+        skewed.randao_mix = [0u8; 32];
+        assert_ne!(
+            skewed.calculate_state_root(),
+            follower.state.calculate_state_root()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_pos_skips_missed_slots_but_not_heights() {
+        use crate::consensus::pos::PoSConfig;
+        use crate::consensus::PoSEngine;
+
+        let keys = crate::crypto::ValidatorKeys::generate().unwrap();
+        let vrf_pub = keys.vrf_key.public.to_bytes().to_vec();
+        let pubkey = keys.sig_key.public_key_hex();
+        let config = PoSConfig {
+            slot_duration: 3600,
+            ..Default::default()
+        };
+        let engine = Arc::new(PoSEngine::new(config, Some(keys)));
+        let mut bc = Blockchain::new(engine.clone(), None, 1337, None);
+        bc.state.add_validator(pubkey.clone(), 2000);
+        bc.state.get_validator_mut(&pubkey).unwrap().vrf_public_key = vrf_pub;
+        let parent = bc.produce_slot_block().unwrap();
+
+        let slot_ms = engine.clock().slot_ms as u128;
+        let mut after_gap = Block::new(2, parent.hash.clone(), vec![]);
+        after_gap.timestamp = parent.timestamp + 3 * slot_ms;
+        after_gap.producer = Some(pubkey.clone());
+        engine.prepare_block(&mut after_gap, &bc.state).unwrap();
+        engine.seal_block(&mut after_gap, &bc.state).unwrap();
+        assert_eq!(after_gap.slot, parent.slot + 3);
+        assert_eq!(PoSEngine::skipped_slots(&parent, &after_gap), 2);
+        engine
+            .validate_block(&after_gap, &bc.chain, &bc.state)
+            .unwrap();
+
+        let mut height_gap = Block::new(3, parent.hash.clone(), vec![]);
+        height_gap.timestamp = after_gap.timestamp;
+        height_gap.producer = Some(pubkey);
+        engine.prepare_block(&mut height_gap, &bc.state).unwrap();
+        engine.seal_block(&mut height_gap, &bc.state).unwrap();
+        let err = engine
+            .validate_block(&height_gap, &bc.chain, &bc.state)
+            .unwrap_err();
+        assert!(err.message.contains("does not follow parent height"));
+    }
+
+    #[test]
+    fn test_double_vote_becomes_block_evidence() {
+        let voter = KeyPair::generate().unwrap();
+        let voter_pub = voter.public_key_hex();
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        bc.state.add_validator(voter_pub.clone(), 2000);
+        bc.state.get_validator_mut(&voter_pub).unwrap().active = true;
+        for _ in 0..100 {
+            bc.produce_block("miner".into());
+        }
+        let follower = bc.clone();
+
+        let fork = bc.fork_id_at(100);
+        let vote_for = |hash: &str| {
+            let mut vote = Prevote {
+                epoch: 1,
+                checkpoint_height: 100,
+                checkpoint_hash: hash.to_string(),
+                voter_id: String::new(),
+                sig_bls: vec![],
+                signature: vec![],
+            };
+            vote.sign(&voter, &fork);
+            vote
+        };
+        let checkpoint_hash = bc.chain[100].hash.clone();
+        assert!(bc.add_prevote(vote_for("fork_hash")).is_err());
+        assert!(bc.pending_vote_evidence().is_empty());
+        bc.add_prevote(vote_for(&checkpoint_hash)).unwrap();
+        assert_eq!(bc.pending_vote_evidence().len(), 1);
+        let evidence = bc.pending_vote_evidence()[0].clone();
+        assert!(bc.submit_vote_evidence(evidence).is_err());
+
+        bc.produce_block("miner".into());
+        let block = bc.chain.last().unwrap().clone();
+        assert_eq!(block.vote_evidence.as_ref().map(|e| e.len()), Some(1));
+        assert!(bc.state.get_validator(&voter_pub).unwrap().slashed);
+        assert_eq!(bc.state.get_balance("miner"), 20);
+        assert!(bc.pending_vote_evidence().is_empty());
+        assert!(bc.check_vote_evidence(&block).is_err());
+
+        // A replay slashes the same stake.
+        let replayed = follower
+            .replay_onto(follower.state.clone(), std::slice::from_ref(&block))
+            .unwrap();
+        assert_eq!(
+            replayed.calculate_state_root(),
+            bc.state.calculate_state_root()
+        );
+
+        follower.check_vote_evidence(&block).unwrap();
+        let mut forged = block.clone();
+        forged.vote_evidence.as_mut().unwrap()[0].checkpoint_hash_1 = Some("other".into());
+        assert!(follower.check_vote_evidence(&forged).is_err());
+    }
+
+    #[test]
+    fn test_fee_reaches_producer() {
+        let consensus = Arc::new(PoWEngine::new(0));
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::new(consensus, None, 1337, None);
+        bc.state.add_balance(&sender_pub, 1000);
+
+        let mut tx = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 5, 0, vec![]);
+        tx.sign(&sender);
+        bc.add_transaction(tx).unwrap();
+
+        bc.produce_block("miner_addr".to_string());
+        assert_eq!(bc.state.get_balance("miner_addr"), 5);
+    }
+
+    #[test]
+    fn test_debug_replay_block_traces_transactions() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            Some(Storage::memory()),
+            1337,
+            None,
+        );
+        bc.init_genesis_account(&sender_pub);
+        bc.produce_block("miner".into());
+        let hash = bc.chain[1].hash.clone();
+        bc.set_finalized(1, hash).unwrap();
+        let mut tx = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 5, 0, vec![]);
+        tx.sign(&sender);
+        bc.add_transaction(tx).unwrap();
+        bc.produce_block("miner".into());
+
+        let trace = bc.debug_replay_block(2).unwrap();
+        assert!(trace.state_root_matches, "{:?}", trace);
+        assert_eq!(trace.error, None);
+        assert_eq!(trace.transactions.len(), 1);
+        assert_eq!(trace.transactions[0].error, None);
+        let deltas: Vec<(&str, i128)> = trace.transactions[0]
+            .balance_deltas
+            .iter()
+            .map(|d| (d.address.as_str(), d.delta))
+            .collect();
+        assert!(deltas.contains(&("bob", 100)));
+        assert!(deltas.contains(&(sender_pub.as_str(), -105)));
+        assert_eq!(trace.block_deltas[0].address, "miner");
+        assert_eq!(trace.block_deltas[0].delta, 5);
+
+        bc.chain[2].transactions[0].amount = 200;
+        let trace = bc.debug_replay_block(2).unwrap();
+        assert!(trace.transactions[0].error.is_some());
+        assert!(trace.transactions[0].balance_deltas.is_empty());
+        assert!(!trace.state_root_matches);
+        assert!(bc.debug_replay_block(0).is_err());
+        assert!(bc.debug_replay_block(3).is_err());
+
+        // Epoch processing is part of the replayed transition, so a block
+        // at an epoch boundary still matches its state root.
+        bc.chain[2].transactions[0].amount = 100;
+        while bc.last_block().index < EPOCH_LENGTH {
+            bc.produce_block("miner".into());
+        }
+        let trace = bc.debug_replay_block(EPOCH_LENGTH).unwrap();
+        assert!(trace.state_root_matches, "{:?}", trace);
+    }
+
+    #[test]
+    fn test_state_root_mismatch_diagnostics() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let mut producer =
+            Blockchain::new(engine.clone(), None, 1337, None).with_state_root_diagnostics(true);
+        let mut peer = Blockchain::new(engine, None, 1337, None).with_state_root_diagnostics(true);
+        producer.state.add_balance("miner", 5);
+        producer.state.add_balance("extra", 5);
+        producer.produce_block("miner".into());
+        let block = producer.last_block().clone();
+
+        let err = peer.validate_and_add_block(block.clone()).unwrap_err();
+        assert!(err.contains("State root mismatch"), "{}", err);
+        let (height, hash, keys) = peer.take_state_proof_request().unwrap();
+        assert_eq!((height, hash.as_str()), (1, block.hash.as_str()));
+        assert!(keys.contains(&"account:miner".to_string()));
+        assert!(peer.take_state_proof_request().is_none());
+
+        let proofs = producer.state_proofs_for(height, &hash, &keys).unwrap();
+        assert!(producer.state_proofs_for(0, &hash, &keys).is_none());
+        let mut forged = proofs.clone();
+        forged[0].index += 1;
+        assert!(peer.diagnose_state_root(height, &hash, &forged).is_err());
+        let report = peer.diagnose_state_root(height, &hash, &proofs).unwrap();
+        assert_eq!(report.proven_keys, keys.len());
+        let first = report.first_divergence.unwrap();
+        assert_eq!(first.key, "account:miner");
+        assert_eq!(first.local, None);
+        assert!(first.remote.unwrap().value.contains("\"balance\":5"));
+        assert_eq!(peer.last_divergence().unwrap().height, 1);
+        assert!(peer.diagnose_state_root(height, &hash, &proofs).is_err());
+    }
+
+    #[test]
+    fn test_state_root_commits_locked_stake() {
+        let mut producer = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        producer.state.add_validator("v1".into(), 500);
+        let mut peer = producer.clone();
+        // Same validator stake, bonded by another account.
+        peer.state.get_or_create("v1").locked.clear();
+        peer.state
+            .get_or_create("alice")
+            .locked
+            .insert("v1".into(), 500);
+        peer.state.check_stake_locks().unwrap();
+
+        producer.produce_block("miner".into());
+        let err = peer
+            .validate_and_add_block(producer.last_block().clone())
+            .unwrap_err();
+        assert!(err.contains("State root mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn test_chain_memory_budget_evicts_old_bodies() {
+        let key = KeyPair::generate().unwrap();
+        let sender = key.public_key_hex();
+        let mut bc = Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            Some(Storage::memory()),
+            1337,
+            None,
+        );
+        bc.init_genesis_account(&sender);
+        for nonce in 0..60 {
+            let mut tx =
+                Transaction::new_with_fee(sender.clone(), "bob".into(), 1, 1, nonce, vec![]);
+            tx.sign(&key);
+            bc.add_transaction(tx).unwrap();
+            bc.produce_block("miner".into());
+        }
+        let whole = bc.clone();
+        let full_bytes = bc.chain_memory_bytes();
+
+        let mut bc = bc.with_chain_memory_budget(1);
+        let pinned_from = bc.chain.len() - FINALITY_DEPTH;
+        assert_eq!(pinned_from, 11);
+        assert!(bc.chain[1..pinned_from]
+            .iter()
+            .all(|b| b.transactions.is_empty()));
+        assert!(bc.chain[pinned_from..]
+            .iter()
+            .all(|b| b.transactions.len() == 1));
+        assert_eq!(bc.health(0).evicted_bodies, 10);
+        assert!(bc.chain_memory_bytes() < full_bytes);
+
+        assert_eq!(bc.get_block_by_height(1).unwrap(), whole.chain[1]);
+        assert_eq!(
+            bc.get_block_by_hash(&whole.chain[2].hash).unwrap(),
+            whole.chain[2]
+        );
+        assert_eq!(bc.blocks_between(0, 12), whole.chain[..12].to_vec());
+        let (block, index) = bc
+            .find_transaction(&whole.chain[1].transactions[0].hash)
+            .unwrap();
+        assert_eq!(
+            (block.hash.as_str(), index),
+            (whole.chain[1].hash.as_str(), 0)
+        );
+        let (mut exported, mut expected) = (Vec::new(), Vec::new());
+        bc.export_chain(&mut exported).unwrap();
+        whole.export_chain(&mut expected).unwrap();
+        assert_eq!(exported, expected);
+
+        let mut tx = Transaction::new_with_fee(sender.clone(), "bob".into(), 1, 1, 60, vec![]);
+        tx.sign(&key);
+        bc.add_transaction(tx).unwrap();
+        bc.produce_block("miner".into());
+        assert!(bc.chain[11].transactions.is_empty());
+        assert_eq!(bc.last_block().transactions.len(), 1);
+        assert_eq!(bc.state.get_balance("bob"), 61);
+    }
+
+    #[test]
+    fn test_builder_rejects_incompatible_components() {
+        let pow = || Arc::new(PoWEngine::new(0));
+        assert!(Blockchain::builder(pow()).build().is_err());
+        assert!(Blockchain::builder(pow())
+            .chain_id(1)
+            .genesis(GenesisConfig::new(2))
+            .build()
+            .is_err());
+        let dir = std::env::temp_dir().join("budlum_builder_pm");
+        let pm = PruningManager::new(10, 10, dir.to_string_lossy().to_string());
+        assert!(Blockchain::builder(pow())
+            .chain_id(1337)
+            .pruning_manager(pm)
+            .build()
+            .is_err());
+
+        let events = EventBus::new();
+        let mut listener = events.subscribe();
+        let mut spec = GenesisConfig::new(7);
+        spec.limits.max_reorg_depth = 5;
+        let mut bc = Blockchain::builder(pow())
+            .genesis(spec)
+            .storage(Storage::memory())
+            .mempool_config(MempoolConfig {
+                min_fee: 3,
+                ..Default::default()
+            })
+            .event_bus(events)
+            .build()
+            .unwrap();
+        assert_eq!(bc.chain_id, 7);
+        assert_eq!(bc.chain[0].chain_id, 7);
+        assert_eq!(bc.limits().max_reorg_depth, 5);
+        assert_eq!(bc.mempool.config().min_fee, 3);
+        bc.produce_block("miner".into());
+        assert!(matches!(
+            listener.try_recv(),
+            Ok(ChainEvent::BlockAdded { height: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_state_checkpoint_skips_replay_on_restart() {
+        let storage = Storage::memory();
+        let funded = "funded".to_string();
+        let mut bc = Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            Some(storage.clone()),
+            1337,
+            None,
+        );
+        bc.init_genesis_account(&funded);
+        bc.produce_block("miner".into());
+        let hash = bc.chain[1].hash.clone();
+        bc.set_finalized(1, hash).unwrap();
+        bc.produce_block("miner".into());
+        bc.produce_block("miner".into());
+        // Finalizing behind the tip checkpoints the finalized block's state,
+        // rebuilt from the previous checkpoint, not the tip's.
+        let hash = bc.chain[2].hash.clone();
+        bc.set_finalized(2, hash.clone()).unwrap();
+        let (height, cp_hash, _) = AccountState::load_checkpoint(&storage).unwrap().unwrap();
+        assert_eq!((height, cp_hash), (2, hash));
+
+        // Genesis funding isn't part of any block, so it only survives a
+        // restart through the checkpoint.
+        let restored = Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            Some(storage.clone()),
+            1337,
+            None,
+        );
+        assert_eq!(restored.chain.len(), 4);
+        assert_eq!(restored.state.get_balance(&funded), 1_000_000_000);
+        assert_eq!(restored.state.state_root(), bc.state.state_root());
+
+        storage
+            .put_meta(crate::account::STATE_CHECKPOINT_KEY, b"corrupt")
+            .unwrap();
+        let replayed = Blockchain::new(Arc::new(PoWEngine::new(0)), Some(storage), 1337, None);
+        assert_eq!(replayed.state.get_balance(&funded), 0);
+        assert_eq!(
+            replayed.state.get_balance("miner"),
+            bc.state.get_balance("miner")
+        );
+    }
+
+    #[test]
+    fn test_restart_replays_epoch_processing() {
+        let key = KeyPair::generate().unwrap();
+        let validator = key.public_key_hex();
+        let spec = GenesisConfig {
+            staking_rewards: crate::genesis::RewardConfig {
+                annual_rate_bps: 10_000,
+                epochs_per_year: 10,
+            },
+            ..GenesisConfig::new(1337).with_allocation(validator.clone(), 10_000)
+        };
+        let storage = Storage::memory();
+        let open = || {
+            Blockchain::builder(Arc::new(PoWEngine::new(0)))
+                .genesis(spec.clone())
+                .storage(storage.clone())
+                .build()
+                .unwrap()
+        };
+        let mut bc = open();
+        let mut stake = Transaction::new_stake(validator.clone(), 1_000, 0);
+        stake.fee = 1;
+        stake.sign(&key);
+        bc.add_transaction(stake).unwrap();
+        for _ in 0..EPOCH_LENGTH {
+            bc.produce_block("miner".into());
+        }
+        let accrued = bc.state.get_validator(&validator).unwrap().accrued_rewards;
+        assert!(accrued > 0);
+        let mut claim = Transaction::new_claim_rewards(validator.clone(), 1);
+        claim.fee = 1;
+        claim.sign(&key);
+        bc.add_transaction(claim).unwrap();
+        bc.produce_block("miner".into());
+        assert_eq!(
+            bc.state.get_balance(&validator),
+            10_000 - 1_000 - 2 + accrued
+        );
+
+        // Rewards are minted by epoch processing, so the claim only replays
+        // if the replay ran it too.
+        let restarted = open();
+        assert_eq!(restarted.state.epoch_index, 1);
+        assert_eq!(
+            restarted.state.calculate_state_root(),
+            bc.state.calculate_state_root()
+        );
+        assert_eq!(
+            restarted.state.get_balance(&validator),
+            bc.state.get_balance(&validator)
+        );
+        let rebuilt = bc.rebuild_state(&bc.chain).unwrap();
+        assert_eq!(
+            rebuilt.calculate_state_root(),
+            bc.state.calculate_state_root()
+        );
+    }
+
+    #[test]
+    fn test_dust_sweep_archived_for_proofs() {
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                dust_sweep: crate::dust::DustSweepConfig {
+                    threshold: 10,
+                    inactive_epochs: 1,
+                },
+                ..GenesisConfig::new(1337)
+            })
+            .storage(Storage::memory())
+            .build()
+            .unwrap();
+        bc.state.add_balance("dust", 5);
+        for _ in 0..EPOCH_LENGTH {
+            bc.produce_block("miner".into());
+        }
+        assert_eq!(bc.state.get_balance("dust"), 0);
+        assert!(bc.state.swept_dust.is_empty());
+        let proof = bc.dust_proof("dust").unwrap();
+        assert_eq!(proof.account.balance, 5);
+        assert_eq!(bc.state.dust_sweeps.get(&proof.epoch), Some(&proof.root()));
+        assert!(bc.dust_proof("miner").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "poa")]
+    fn test_observer_without_validator_key() {
+        use crate::consensus::poa::{PoAConfig, PoAEngine};
+        let pow = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        assert!(!pow.is_observer());
+        let observer = Blockchain::new(
+            Arc::new(PoAEngine::new(PoAConfig::default(), None)),
+            None,
+            1337,
+            None,
+        );
+        assert!(observer.is_observer());
+        assert_eq!(observer.local_producer(), None);
+
+        let key = KeyPair::generate().unwrap();
+        let signer = Blockchain::new(
+            Arc::new(PoAEngine::new(PoAConfig::default(), Some(key.clone()))),
+            None,
+            1337,
+            None,
+        );
+        assert!(!signer.is_observer());
+        assert_eq!(signer.local_producer(), Some(key.public_key_hex()));
+
+        let mut explicit = Blockchain::new(
+            Arc::new(PoAEngine::new(PoAConfig::default(), Some(key.clone()))),
+            None,
+            1337,
+            None,
+        )
+        .with_observer(true);
+        assert!(explicit.is_observer());
+        assert_eq!(explicit.local_producer(), None);
+        assert!(explicit.produce_slot_block().is_none());
+
+        let mut pow = pow.with_observer(true);
+        pow.init_genesis_account(&key.public_key_hex());
+        let mut tx = Transaction::new_with_fee(key.public_key_hex(), "bob".into(), 5, 1, 0, vec![]);
+        tx.sign(&key);
+        assert!(!pow.accepts_transactions());
+        assert!(pow.add_transaction(tx).is_err());
+        pow.produce_block("miner".into());
+        assert_eq!(pow.last_block().index, 0);
+    }
+
+    #[test]
+    fn test_chain_limits_bound_production_and_validation() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut full = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let mut tight = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                limits: ChainLimits {
+                    max_transactions_per_block: 2,
+                    ..Default::default()
+                },
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        for bc in [&mut full, &mut tight] {
+            bc.state.add_balance(&sender_pub, 1000);
+            for nonce in 0..3 {
+                let mut tx = Transaction::new_with_fee(
+                    sender_pub.clone(),
+                    "bob".into(),
+                    10,
+                    1 + nonce,
+                    nonce,
+                    vec![],
+                );
+                tx.sign(&sender);
+                bc.add_transaction(tx).unwrap();
+            }
+        }
+
+        full.produce_block("miner".into());
+        let block = full.last_block().clone();
+        assert_eq!(block.transactions.len(), 3);
+        let err = tight.validate_and_add_block(block).unwrap_err();
+        assert!(err.contains("Too many transactions"), "{}", err);
+
+        tight.produce_block("miner".into());
+        assert_eq!(tight.last_block().transactions.len(), 2);
+        assert_eq!(tight.state.get_nonce(&sender_pub), 2);
+    }
+
+    #[test]
+    fn test_fork_hash_separates_transactions_and_peers() {
+        use crate::chain_config::ForkActivation;
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let fork = ForkActivation {
+            name: "f".into(),
+            height: 2,
+        };
+        let bad_spec = GenesisConfig {
+            forks: vec![fork.clone(), fork.clone()],
+            ..GenesisConfig::new(1337)
+        };
+        assert!(Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(bad_spec)
+            .build()
+            .is_err());
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                forks: vec![fork],
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        bc.state.add_balance(&sender_pub, 1000);
+        let transfer = |nonce, fork_hash: String| {
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, 1, nonce, vec![])
+                    .with_fork_hash(fork_hash);
+            tx.sign(&sender);
+            tx
+        };
+
+        assert_eq!(bc.fork_id().fork_hash, "");
+        bc.add_transaction(transfer(0, String::new())).unwrap();
+        bc.produce_block("miner".into());
+        assert!(bc.check_peer_fork(2, "").is_ok());
+        let mut legacy = bc.clone();
+        legacy.state.forks = ForkSchedule::default();
+
+        let fork_hash = bc.forks().fork_hash(2);
+        let err = bc.add_transaction(transfer(1, String::new())).unwrap_err();
+        assert!(err.contains("signed for fork"), "{}", err);
+        let forked = transfer(1, fork_hash.clone());
+        assert!(legacy.add_transaction(forked.clone()).is_err());
+        bc.add_transaction(forked).unwrap();
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().transactions.len(), 1);
+        assert_eq!(bc.fork_id().fork_hash, fork_hash);
+
+        assert!(bc.check_peer_fork(3, &fork_hash).is_ok());
+        assert!(bc.check_peer_fork(3, "").is_err());
+        assert!(legacy.check_peer_fork(3, &fork_hash).is_err());
+    }
+
+    #[test]
+    fn test_tx_signing_version_fork() {
+        use crate::chain_config::{ForkActivation, FORK_TX_SIGNING_V2};
+        use crate::transaction::{TX_SIGNING_LEGACY, TX_SIGNING_V2};
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                forks: vec![ForkActivation {
+                    name: FORK_TX_SIGNING_V2.into(),
+                    height: 2,
+                }],
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        bc.state.add_balance(&sender_pub, 1000);
+        let fork_hash = bc.forks().fork_hash(2);
+        let transfer = |nonce, fork_hash: &str, signing_version| {
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, 1, nonce, vec![])
+                    .with_fork_hash(fork_hash.to_string());
+            tx.signing_version = signing_version;
+            tx.hash = tx.calculate_hash();
+            tx.sign(&sender);
+            tx
+        };
+
+        assert!(bc.add_transaction(transfer(0, "", TX_SIGNING_V2)).is_err());
+        bc.add_transaction(transfer(0, "", TX_SIGNING_LEGACY))
+            .unwrap();
+        bc.produce_block("miner".into());
+
+        let err = bc
+            .add_transaction(transfer(1, &fork_hash, TX_SIGNING_LEGACY))
+            .unwrap_err();
+        assert!(err.contains("signing version"), "{}", err);
+        let mut follower = bc.clone();
+        bc.add_transaction(transfer(1, &fork_hash, TX_SIGNING_V2))
+            .unwrap();
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().transactions.len(), 1);
+
+        let mut legacy = bc.last_block().clone();
+        legacy.transactions = vec![transfer(1, &fork_hash, TX_SIGNING_LEGACY)];
+        legacy.timestamp = follower.last_block().timestamp + MIN_BLOCK_INTERVAL_MS as u128;
+        legacy.tx_root = legacy.calculate_tx_root();
+        legacy.hash = legacy.calculate_hash();
+        let err = follower.validate_and_add_block(legacy).unwrap_err();
+        assert!(err.contains("signing version"), "{}", err);
+    }
+
+    #[test]
+    fn test_rolling_validator_set_hash() {
+        use crate::chain_config::ForkActivation;
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                forks: vec![ForkActivation {
+                    name: FORK_ROLLING_VALIDATOR_SET.into(),
+                    height: 3,
+                }],
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        bc.state.add_validator("v1".into(), 2000);
+        bc.state.get_validator_mut("v1").unwrap().active = true;
+        for _ in 0..4 {
+            bc.produce_block("miner".into());
+        }
+        let set_hash = bc.state.validator_set_for_block(5).set_hash;
+        assert_eq!(bc.chain[2].validator_set_hash, "");
+        assert_eq!(bc.chain[3].validator_set_hash, set_hash);
+        assert_eq!(bc.chain[4].validator_set_hash, set_hash);
+
+        let mut follower = bc.clone();
+        bc.produce_block("miner".into());
+        let mut forged = bc.last_block().clone();
+        assert_eq!(forged.validator_set_hash, set_hash);
+        forged.validator_set_hash = "00".repeat(32);
+        forged.hash = forged.calculate_hash();
+        let err = follower.validate_and_add_block(forged).unwrap_err();
+        assert!(err.contains("Validator set hash mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn test_state_snapshot_commitment() {
+        use crate::chain_config::ForkActivation;
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                forks: vec![ForkActivation {
+                    name: FORK_STATE_SNAPSHOT.into(),
+                    height: 1,
+                }],
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        bc.state.add_balance("alice", 1_000);
+        for _ in 0..EPOCH_LENGTH - 1 {
+            produce_spaced(&mut bc, "miner");
+        }
+        let boundary = bc.last_block().clone();
+        let snapshot = StateSnapshot::from_state(
+            boundary.index,
+            boundary.hash.clone(),
+            bc.chain_id,
+            &bc.state,
+            0,
+            String::new(),
+        );
+        let mut follower = bc.clone();
+        produce_spaced(&mut bc, "miner");
+        produce_spaced(&mut bc, "miner");
+        assert_eq!(bc.chain[EPOCH_LENGTH as usize - 1].state_snapshot_hash, "");
+        assert_eq!(
+            bc.chain[EPOCH_LENGTH as usize].state_snapshot_hash,
+            snapshot.commitment()
+        );
+        assert_eq!(bc.chain[EPOCH_LENGTH as usize + 1].state_snapshot_hash, "");
+        assert_eq!(
+            bc.committed_snapshot_hash(boundary.index),
+            Some(snapshot.commitment())
+        );
+        bc.verify_snapshot_commitment(&snapshot).unwrap();
+        let mut tampered = snapshot.clone();
+        tampered.balances.insert("alice".into(), 1_000_000);
+        assert!(bc.verify_snapshot_commitment(&tampered).is_err());
+
+        let mut forged = bc.chain[EPOCH_LENGTH as usize].clone();
+        forged.state_snapshot_hash = tampered.commitment();
+        forged.hash = forged.calculate_hash();
+        let err = follower.validate_and_add_block(forged).unwrap_err();
+        assert!(err.contains("State snapshot hash mismatch"), "{}", err);
+        follower
+            .validate_and_add_block(bc.chain[EPOCH_LENGTH as usize].clone())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_rejected_blocks_are_published() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let mut follower = bc.clone();
+        bc.produce_block("miner".into());
+        let mut events = follower.subscribe_events();
+        let mut forged = bc.last_block().clone();
+        forged.state_root = "00".repeat(32);
+        forged.hash = forged.calculate_hash();
+        let err = follower.validate_and_add_block(forged.clone()).unwrap_err();
+        match events.try_recv() {
+            Ok(ChainEvent::BlockRejected(rejection)) => {
+                assert_eq!(rejection.reason, BlockRejectReason::BadStateRoot);
+                assert_eq!((rejection.height, rejection.hash), (1, forged.hash));
+                assert_eq!(rejection.error, err);
+            }
+            other => panic!("expected a rejection event, got {:?}", other),
+        }
+        follower
+            .validate_and_add_block(bc.last_block().clone())
+            .unwrap();
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ChainEvent::BlockAdded { height: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_version_fork() {
+        use crate::chain_config::ForkActivation;
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig {
+                forks: vec![ForkActivation {
+                    name: FORK_BLOCK_VERSION.into(),
+                    height: 2,
+                }],
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        let mut follower = bc.clone();
+        produce_spaced(&mut bc, "miner");
+        produce_spaced(&mut bc, "miner");
+        assert_eq!(bc.chain[1].version, 0);
+        assert_eq!(bc.chain[2].version, BLOCK_VERSION);
+
+        let mut early = bc.chain[1].clone();
+        early.version = BLOCK_VERSION;
+        early.hash = early.calculate_hash();
+        let err = follower.validate_and_add_block(early).unwrap_err();
+        assert!(err.contains("before the block_version fork"), "{}", err);
+        follower
+            .validate_and_add_block(bc.chain[1].clone())
+            .unwrap();
+
+        let mut newer_major = bc.chain[2].clone();
+        newer_major.version = 0x20;
+        newer_major.hash = newer_major.calculate_hash();
+        let err = follower.validate_and_add_block(newer_major).unwrap_err();
+        assert!(err.contains("upgrade the node"), "{}", err);
+        follower
+            .validate_and_add_block(bc.chain[2].clone())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_double_spend_race() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        bc.state.add_balance(&sender_pub, 1000);
+        let spend = |to: &str, nonce| {
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), to.into(), 900, 1, nonce, vec![]);
+            tx.sign(&sender);
+            tx
+        };
+        let drift = bc.state.supply_drift();
+        let mut follower = bc.clone();
+        let mut rival = bc.clone();
+
+        bc.add_transaction(spend("bob", 0)).unwrap();
+        let _ = bc.add_transaction(spend("carol", 1));
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().transactions.len(), 1);
+        assert_eq!(bc.state.get_balance("bob"), 900);
+        assert_eq!(bc.state.get_balance("carol"), 0);
+        assert_eq!(bc.state.supply_drift(), drift);
 
-        cert.verify(&snapshot)?;
+        rival.add_transaction(spend("carol", 0)).unwrap();
+        rival.produce_block("miner".into());
+        assert_eq!(rival.state.get_balance("carol"), 900);
 
-        self.finalized_height = cert.checkpoint_height;
-        self.finalized_hash = cert.checkpoint_hash.clone();
+        let mut both = bc.last_block().clone();
+        both.transactions.push(spend("carol", 1));
+        both.tx_root = both.calculate_tx_root();
+        both.hash = both.calculate_hash();
+        let err = follower.validate_and_add_block(both).unwrap_err();
+        assert!(err.contains("at index 1"), "{}", err);
 
-        info!(
-            "FINALIZED checkpoint: height={}, hash={}",
-            self.finalized_height, self.finalized_hash
+        follower
+            .validate_and_add_block(bc.last_block().clone())
+            .unwrap();
+        assert!(follower.add_transaction(spend("carol", 0)).is_err());
+        assert_eq!(
+            follower.state.get_balance("bob") + follower.state.get_balance("carol"),
+            900
         );
+        assert_eq!(follower.state.supply_drift(), drift);
+    }
 
-        if let Some(ref store) = self.storage {
-            let _ = store.save_finality_cert(self.finalized_height, &cert);
-            let _ = store.save_canonical_height(self.finalized_height);
-        }
-
-        Ok(())
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Supply not conserved")]
+    fn test_supply_invariant_catches_unbacked_credit() {
+        let state = AccountState::new();
+        let before = Blockchain::supply_drift_before(&state);
+        let mut after = state.clone();
+        after.accounts.insert(
+            "alice".into(),
+            crate::account::Account::with_balance("alice".into(), 5),
+        );
+        Blockchain::assert_supply_conserved(before, &after, 1);
     }
 
-    pub fn consensus(&self) -> &dyn ConsensusEngine {
-        self.consensus.as_ref()
+    #[test]
+    fn test_transaction_expiry_height() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        bc.state.add_balance(&sender_pub, 1000);
+        bc.produce_block("miner".into());
+        let transfer = |nonce, expires_at_height| {
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, 1, nonce, vec![])
+                    .with_expiry(expires_at_height);
+            tx.sign(&sender);
+            tx
+        };
+
+        let err = bc.add_transaction(transfer(0, 1)).unwrap_err();
+        assert!(err.contains("expired at height 1"), "{}", err);
+        bc.add_transaction(transfer(0, 2)).unwrap();
+        bc.add_transaction(transfer(1, 3)).unwrap();
+
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().transactions.len(), 2);
+        assert_eq!(bc.state.get_nonce(&sender_pub), 2);
+        assert!(bc.add_transaction(transfer(2, 2)).is_err());
     }
-}
 
-impl Clone for Blockchain {
-    fn clone(&self) -> Self {
-        Blockchain {
-            chain: self.chain.clone(),
-            consensus: Arc::clone(&self.consensus),
-            mempool: Mempool::default(),
-            storage: self.storage.clone(),
-            state: self.state.clone(),
-            chain_id: self.chain_id,
-            pruning_manager: self.pruning_manager.clone(),
-            finalized_height: self.finalized_height,
-            finalized_hash: self.finalized_hash.clone(),
+    #[test]
+    fn test_target_block_size_only_bounds_own_blocks() {
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut full = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let empty_size = serde_json::to_vec(&Block::new(1, full.last_block().hash.clone(), vec![]))
+            .unwrap()
+            .len();
+        let mut soft = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None)
+            .with_target_block_size(empty_size + 1200);
+        let mut txs = Vec::new();
+        for nonce in 0..4 {
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, 1, nonce, vec![]);
+            tx.sign(&sender);
+            txs.push(tx);
+        }
+        let tx_size = serde_json::to_vec(&txs[0]).unwrap().len() + 1;
+        let fits = 1200 / tx_size;
+        assert!(fits > 0 && fits < 4);
+        for bc in [&mut full, &mut soft] {
+            bc.state.add_balance(&sender_pub, 1000);
+            for tx in &txs {
+                bc.add_transaction(tx.clone()).unwrap();
+            }
         }
+
+        soft.produce_block("miner".into());
+        assert_eq!(soft.last_block().transactions.len(), fits);
+        assert_eq!(soft.mempool.len(), 4 - fits);
+
+        let mut peer = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None)
+            .with_target_block_size(empty_size + 1200);
+        peer.state.add_balance(&sender_pub, 1000);
+        full.produce_block("miner".into());
+        let block = full.last_block().clone();
+        assert_eq!(block.transactions.len(), 4);
+        peer.validate_and_add_block(block).unwrap();
+        assert_eq!(peer.state.get_nonce(&sender_pub), 4);
     }
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::consensus::PoWEngine;
-    use crate::crypto::KeyPair;
 
     #[test]
-    fn test_blockchain_with_pow() {
-        let consensus = Arc::new(PoWEngine::new(1));
-        let mut blockchain = Blockchain::new(consensus, None, 1337, None);
+    fn test_balance_history_index() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let new_chain = || {
+            let mut bc = Blockchain::new(engine.clone(), Some(Storage::memory()), 1337, None);
+            bc.init_genesis_account(&sender_pub);
+            bc.with_balance_history(true)
+        };
+        let mut producer = new_chain();
+        let mut peer = new_chain();
+        let mut tx = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 1, 0, vec![]);
+        tx.sign(&sender);
+        producer.add_transaction(tx).unwrap();
+        producer.produce_block("miner".into());
+        peer.validate_and_add_block(producer.last_block().clone())
+            .unwrap();
 
-        let keypair = KeyPair::generate().unwrap();
-        let pubkey = keypair.public_key_hex();
+        for bc in [&producer, &peer] {
+            assert_eq!(bc.balance_at("bob", 0).unwrap(), 0);
+            assert_eq!(bc.balance_at("bob", 1).unwrap(), 100);
+            assert_eq!(bc.balance_at("miner", 1).unwrap(), 1);
+            assert_eq!(bc.balance_at(&sender_pub, 0).unwrap(), 1_000_000_000);
+            assert_eq!(
+                bc.balance_at(&sender_pub, 1).unwrap(),
+                bc.state.get_balance(&sender_pub)
+            );
+            assert!(bc.balance_at("bob", 2).is_err());
+        }
 
-        blockchain.state.add_balance(&pubkey, 100);
+        let mut fork_source = Blockchain::new(engine.clone(), None, 1337, None);
+        for _ in 0..3 {
+            fork_source.produce_block("other".into());
+        }
+        assert!(peer.try_reorg(fork_source.chain.clone()).unwrap());
+        assert_eq!(peer.balance_at("bob", 1).unwrap(), 0);
+        assert_eq!(peer.balance_at("miner", 3).unwrap(), 0);
+        assert_eq!(peer.balance_at("bob", 3).unwrap(), 0);
 
-        let mut tx = Transaction::new(pubkey.clone(), "bob".to_string(), 50, vec![]);
-        tx.fee = 1;
-        tx.sign(&keypair);
+        let disabled = Blockchain::new(engine, Some(Storage::memory()), 1337, None);
+        assert!(disabled.balance_at("bob", 0).is_err());
+    }
 
-        blockchain.add_transaction(tx).unwrap();
+    #[test]
+    fn test_state_diffs_only_computed_for_subscribers() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut producer = Blockchain::new(engine.clone(), None, 1337, None);
+        let mut peer = Blockchain::new(engine, None, 1337, None);
+        producer.init_genesis_account(&sender_pub);
+        peer.init_genesis_account(&sender_pub);
+        assert!(producer.state_view().is_none());
 
-        blockchain.produce_block("miner1".to_string());
-        assert!(blockchain.is_valid());
-        assert_eq!(blockchain.chain.len(), 2);
+        let mut produced = producer.subscribe_state_diffs();
+        let mut imported = peer.subscribe_state_diffs();
+        let mut tx = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 1, 0, vec![]);
+        tx.sign(&sender);
+        producer.add_transaction(tx).unwrap();
+        producer.produce_block("miner".into());
+        peer.validate_and_add_block(producer.last_block().clone())
+            .unwrap();
+
+        let applied = |event| match event {
+            StateDiffEvent::Applied(diff) => diff,
+            other => panic!("expected a diff, got {:?}", other),
+        };
+        let diff = applied(produced.try_recv().unwrap());
+        assert_eq!(*diff, *applied(imported.try_recv().unwrap()));
+        assert_eq!(diff.height, 1);
+        let bob = diff.accounts.iter().find(|c| c.address == "bob").unwrap();
+        assert!(bob.before.is_none());
+        assert_eq!(bob.after.as_ref().unwrap().balance, 100);
+        let sender_change = diff
+            .accounts
+            .iter()
+            .find(|c| c.address == sender_pub)
+            .unwrap();
+        assert_eq!(sender_change.after.as_ref().unwrap().nonce, 1);
+        assert!(diff.accounts.iter().any(|c| c.address == "miner"));
     }
 
     #[test]
-    fn test_epoch_transition_and_unjailing() {
-        let consensus = Arc::new(PoWEngine::new(1));
-        let mut blockchain = Blockchain::new(consensus, None, 1337, None);
-
-        let validator_addr = "validator1".to_string();
-        blockchain.state.add_validator(validator_addr.clone(), 1000);
+    fn test_in_flight_nonces_accepted_and_mined() {
+        let consensus = Arc::new(PoWEngine::new(0));
+        let sender = KeyPair::generate().unwrap();
+        let sender_pub = sender.public_key_hex();
+        let mut bc = Blockchain::new(consensus, None, 1337, None);
+        bc.state.add_balance(&sender_pub, 1000);
 
-        if let Some(v) = blockchain.state.get_validator_mut(&validator_addr) {
-            v.jailed = true;
-            v.active = false;
-            v.jail_until = 0;
+        for (nonce, fee) in [(0, 1), (1, 5), (2, 9)] {
+            assert_eq!(bc.get_next_nonce(&sender_pub), nonce);
+            let mut tx =
+                Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, fee, nonce, vec![]);
+            tx.sign(&sender);
+            bc.add_transaction(tx).unwrap();
         }
+        let mut gap = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 10, 1, 5, vec![]);
+        gap.sign(&sender);
+        assert!(bc.add_transaction(gap).is_err());
 
-        assert_eq!(blockchain.state.epoch_index, 0);
-        if let Some(v) = blockchain.state.get_validator(&validator_addr) {
-            assert!(v.jailed);
-        }
+        bc.produce_block("miner_addr".to_string());
+        assert_eq!(bc.chain.last().unwrap().transactions.len(), 3);
+        assert_eq!(bc.state.get_nonce(&sender_pub), 3);
+        assert_eq!(bc.get_next_nonce(&sender_pub), 3);
+    }
 
-        for _ in 0..EPOCH_LENGTH {
-            blockchain.produce_block("miner".to_string());
+    #[test]
+    fn test_block_locator_backs_off_to_genesis() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        for _ in 0..40 {
+            bc.produce_block("miner".into());
         }
+        let locator = bc.block_locator();
+        let heights: Vec<u64> = locator
+            .iter()
+            .map(|hash| bc.find_locator_fork(std::slice::from_ref(hash)).unwrap())
+            .collect();
+        assert_eq!(
+            heights,
+            vec![40, 39, 38, 37, 36, 35, 34, 33, 32, 31, 29, 25, 17, 1, 0]
+        );
 
-        assert_eq!(blockchain.chain.len(), (EPOCH_LENGTH as usize) + 1);
+        let mut fork = bc.chain[..20].to_vec();
+        fork[19].hash = "fork".into();
+        let mut other = bc.clone();
+        other.chain = fork;
+        assert_eq!(bc.find_locator_fork(&other.block_locator()), Some(18));
+        assert_eq!(bc.find_locator_fork(&["unknown".into()]), None);
+    }
 
-        assert_eq!(blockchain.state.epoch_index, 1);
+    #[test]
+    fn test_checkpoint_sync_skips_finalized_history() {
+        use crate::chain_config::FINALITY_CHECKPOINT_INTERVAL;
 
-        if let Some(v) = blockchain.state.get_validator(&validator_addr) {
-            assert!(!v.jailed, "Validator should have been unjailed");
-            assert!(v.active, "Validator should be active");
-        } else {
-            panic!("Validator not found");
+        let mut src = Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            Some(Storage::memory()),
+            1337,
+            None,
+        );
+        let kp = KeyPair::generate().unwrap();
+        src.init_genesis_account(&kp.public_key_hex());
+        src.state.add_validator("val".into(), 5_000);
+        let key = bls_key("val");
+        src.state
+            .register_bls_key(
+                "val",
+                key.public_key_bytes().to_vec(),
+                sign_pop(&key, 1337, "val"),
+                1337,
+            )
+            .unwrap();
+        for nonce in 0..FINALITY_CHECKPOINT_INTERVAL {
+            if nonce < 3 {
+                let mut tx = Transaction::new_with_fee(
+                    kp.public_key_hex(),
+                    "bob".into(),
+                    10,
+                    1,
+                    nonce,
+                    vec![],
+                );
+                tx.sign(&kp);
+                src.add_transaction(tx).unwrap();
+            }
+            src.produce_block("miner".into());
         }
+        let checkpoint = src.last_block().clone();
+        let set = src.state.active_validator_snapshot(3);
+        let mut cert = FinalityCert {
+            epoch: 3,
+            checkpoint_height: checkpoint.index,
+            checkpoint_hash: checkpoint.hash.clone(),
+            agg_sig_bls: Vec::new(),
+            bitmap: vec![1],
+            set_hash: set.set_hash.clone(),
+            state_root: "wrong_root".into(),
+        };
+        sign_cert(&mut cert, &set, 1337);
+        assert!(src.handle_finality_cert(cert.clone()).is_err());
+        cert.state_root = checkpoint.state_root.clone();
+        assert!(src.handle_finality_cert(cert.clone()).is_err());
+        sign_cert(&mut cert, &set, 1337);
+        src.handle_finality_cert(cert).unwrap();
+        let bundle = src.export_checkpoint().unwrap();
+
+        let mut dst = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let mut tampered = bundle.clone();
+        tampered.snapshot.balances.insert("bob".into(), 1_000_000);
+        assert!(dst.checkpoint_sync(bundle.clone()).is_err());
+
+        // Only an operator-pinned checkpoint may be synced to.
+        let mut mid = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None)
+            .with_weak_subjectivity(vec![WeakSubjectivityCheckpoint {
+                height: 50,
+                hash: src.chain[50].hash.clone(),
+            }]);
+        assert!(mid.checkpoint_sync(bundle.clone()).is_err());
+        dst = dst.with_weak_subjectivity(vec![WeakSubjectivityCheckpoint {
+            height: checkpoint.index,
+            hash: checkpoint.hash.clone(),
+        }]);
+        assert!(dst.checkpoint_sync(tampered).is_err());
+        assert_eq!(
+            dst.checkpoint_sync(bundle.clone()).unwrap(),
+            checkpoint.index
+        );
+        assert_eq!(dst.finalized_height, checkpoint.index);
+        assert_eq!(dst.state.get_balance("bob"), 30);
+        assert_eq!(
+            dst.state.calculate_state_root(),
+            src.state.calculate_state_root()
+        );
+        assert_eq!(
+            src.finalized_state_root(checkpoint.index),
+            Some(checkpoint.state_root.clone())
+        );
+        src.verify_finalized_snapshot(&bundle.snapshot).unwrap();
+        let mut forged_state = src.state.clone();
+        forged_state.add_balance("bob", 1_000_000);
+        let forged = StateSnapshot::from_state(
+            checkpoint.index,
+            checkpoint.hash.clone(),
+            1337,
+            &forged_state,
+            checkpoint.index,
+            checkpoint.hash.clone(),
+        );
+        assert!(forged.verify());
+        assert!(src.verify_finalized_snapshot(&forged).is_err());
+
+        src.produce_block("miner".into());
+        let mut next = src.last_block().clone();
+        next.timestamp = checkpoint.timestamp + 1_000;
+        next.hash = next.calculate_hash();
+        dst.validate_and_add_block(next).unwrap();
+        assert_eq!(dst.last_block().index, checkpoint.index + 1);
     }
 
     #[test]
-    fn test_slashing_execution() {
-        use crate::block::BlockHeader;
-        use crate::consensus::pos::{PoSConfig, SlashingEvidence};
-        use crate::consensus::PoSEngine;
+    fn test_cancel_pending_transaction() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let kp = KeyPair::generate().unwrap();
+        let sender = kp.public_key_hex();
+        bc.init_genesis_account(&sender);
+        let mut events = bc.subscribe_events();
+        let mut tx = Transaction::new_with_fee(sender.clone(), "bob".into(), 100, 10, 0, vec![]);
+        tx.sign(&kp);
+        bc.add_transaction(tx.clone()).unwrap();
 
-        let alice_keys = crate::crypto::ValidatorKeys::generate().unwrap();
-        let alice_key = alice_keys.sig_key.clone();
-        let alice_vrf_pub = alice_keys.vrf_key.public.to_bytes().to_vec();
-        let alice_pub = alice_key.public_key_hex();
+        assert!(bc.cancel_pending_transaction(&kp, 1).is_err());
+        let cancel = bc.cancel_pending_transaction(&kp, 0).unwrap();
+        assert_eq!(
+            (cancel.to.as_str(), cancel.amount, cancel.fee),
+            (sender.as_str(), 0, 11)
+        );
+        assert!(bc.mempool.get(&tx.hash).is_none());
+        assert_eq!(bc.mempool.pending_for_sender(&sender), vec![cancel.clone()]);
+        let mut local = None;
+        while let Ok(event) = events.try_recv() {
+            if let ChainEvent::LocalTransaction(tx) = event {
+                local = Some(tx);
+            }
+        }
+        assert_eq!(local, Some(cancel));
 
-        let mut config = PoSConfig::default();
-        config.slashing_penalty = 0.50;
+        bc.produce_block("miner".into());
+        assert_eq!(bc.state.get_nonce(&sender), 1);
+        assert_eq!(bc.state.get_balance("bob"), 0);
+    }
 
-        let engine = Arc::new(PoSEngine::new(config, Some(alice_keys)));
+    #[test]
+    fn test_halt_detection_and_recovery() {
+        let mut bc =
+            Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None).with_halt_timeout(60);
+        let mut events = bc.subscribe_events();
+        bc.produce_block("miner".into());
+        let tip = bc.last_block().timestamp;
 
-        let mut blockchain = Blockchain::new(engine.clone(), None, 1337, None);
+        assert!(!bc.check_halt(tip + 59_000));
+        assert!(bc.check_halt(tip + 61_000));
+        assert!(bc.check_halt(tip + 120_000));
+        let health = bc.health(tip + 120_000);
+        assert!(health.halted);
+        assert_eq!(health.last_block_age_secs, 120);
 
-        blockchain.state.add_validator(alice_pub.clone(), 2000);
-        if let Some(v) = blockchain.state.get_validator_mut(&alice_pub) {
-            v.vrf_public_key = alice_vrf_pub.clone();
-        }
-        blockchain.state.add_balance(&alice_pub, 100);
+        bc.produce_block("miner".into());
+        assert!(!bc.is_halted());
+        let received: Vec<ChainEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        let halts = received
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    ChainEvent::Halted {
+                        height: 1,
+                        stalled_secs: 61
+                    }
+                )
+            })
+            .count();
+        assert_eq!(halts, 1);
+        assert!(received.contains(&ChainEvent::Recovered { height: 2 }));
+    }
 
-        let mut real_b1 = Block::new(10, "prev".into(), vec![]);
-        real_b1.producer = Some(alice_pub.clone());
-        real_b1.hash = real_b1.calculate_hash();
-        let sig1 = alice_key.sign(real_b1.hash.as_bytes()).to_vec();
-        real_b1.signature = Some(sig1.clone());
-        let h1 = BlockHeader::from_block(&real_b1);
+    #[test]
+    fn test_unsafe_validator_set_stops_voting() {
+        use crate::chain_config::FINALITY_CHECKPOINT_INTERVAL;
 
-        let mut real_b2 = Block::new(10, "prev".into(), vec![]);
-        real_b2.timestamp += 1;
-        real_b2.producer = Some(alice_pub.clone());
-        real_b2.hash = real_b2.calculate_hash();
-        let sig2 = alice_key.sign(real_b2.hash.as_bytes()).to_vec();
-        real_b2.signature = Some(sig2.clone());
-        let h2 = BlockHeader::from_block(&real_b2);
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        for name in ["alice", "bob", "carol"] {
+            bc.state.add_validator(name.into(), 1_000);
+            let key = bls_key(name);
+            bc.state
+                .register_bls_key(
+                    name,
+                    key.public_key_bytes().to_vec(),
+                    sign_pop(&key, 1337, name),
+                    1337,
+                )
+                .unwrap();
+        }
+        let frozen = bc.state.active_validator_snapshot(0);
+        let mut bc = bc.with_min_validators(2);
+        while bc.last_block().index < FINALITY_CHECKPOINT_INTERVAL - 1 {
+            bc.produce_block("miner".into());
+        }
+        let mut events = bc.subscribe_events();
+        assert!(bc.validator_set_safety().safe);
+        assert!(bc.may_vote());
 
-        let evidence = SlashingEvidence::new(h1, h2, sig1, sig2);
+        bc.state.frozen_validator_set = Some(frozen);
+        bc.state.get_validator_mut("bob").unwrap().jailed = true;
+        bc.state.get_validator_mut("carol").unwrap().tombstoned = true;
+        bc.produce_block("miner".into());
+        let safety = bc.validator_set_safety();
+        assert!(!safety.safe);
+        assert_eq!((safety.eligible_validators, safety.validators), (1, 3));
+        assert_eq!((safety.eligible_stake, safety.quorum_stake), (1_000, 2_000));
+        assert!(!bc.health(0).validator_set_safe);
+        assert!(!bc.may_vote());
 
-        {
-            let mut guard = engine.slashing_evidence.write().unwrap();
-            guard.push(evidence);
-        }
+        // A quorum certificate from the network still finalizes.
+        let checkpoint = bc.last_block().clone();
+        let set = bc.state.active_validator_snapshot(0);
+        let mut cert = FinalityCert {
+            epoch: 0,
+            checkpoint_height: checkpoint.index,
+            checkpoint_hash: checkpoint.hash.clone(),
+            agg_sig_bls: Vec::new(),
+            bitmap: vec![(1u8 << set.validators.len()) - 1],
+            set_hash: set.set_hash.clone(),
+            state_root: checkpoint.state_root.clone(),
+        };
+        sign_cert(&mut cert, &set, 1337);
+        bc.handle_finality_cert(cert).unwrap();
+        assert_eq!(bc.finalized_height, checkpoint.index);
+        assert!(!bc.may_vote());
 
-        blockchain.produce_block(alice_pub.clone());
+        bc.state.get_validator_mut("bob").unwrap().jailed = false;
+        bc.produce_block("miner".into());
+        assert!(bc.validator_set_safety().safe);
+        assert!(bc.may_vote());
+        let received: Vec<ChainEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert!(received.contains(&ChainEvent::ValidatorSetUnsafe {
+            height: FINALITY_CHECKPOINT_INTERVAL,
+            epoch: 0,
+            eligible_validators: 1,
+            eligible_stake: 1_000,
+            quorum_stake: 2_000,
+        }));
+        assert!(received.contains(&ChainEvent::ValidatorSetRestored {
+            height: FINALITY_CHECKPOINT_INTERVAL + 1,
+            epoch: 0
+        }));
+    }
 
-        let produced_block = blockchain.chain.last().unwrap();
-        assert!(
-            produced_block.slashing_evidence.is_some(),
-            "Block should contain slashing evidence"
-        );
-        assert_eq!(produced_block.slashing_evidence.as_ref().unwrap().len(), 1);
+    #[test]
+    fn test_pause_and_resume_production() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let kp = KeyPair::generate().unwrap();
+        bc.init_genesis_account(&kp.public_key_hex());
+        let mut tx = Transaction::new_with_fee(kp.public_key_hex(), "bob".into(), 5, 1, 0, vec![]);
+        tx.sign(&kp);
+        let mut producer = bc.clone();
 
-        let mut blockchain2 = Blockchain::new(engine.clone(), None, 1337, None);
-        blockchain2.state.add_validator(alice_pub.clone(), 2000);
-        if let Some(v) = blockchain2.state.get_validator_mut(&alice_pub) {
-            v.vrf_public_key = alice_vrf_pub.clone();
-        }
-        blockchain2.state.add_balance(&alice_pub, 100);
-        blockchain2
-            .validate_and_add_block(produced_block.clone())
+        assert!(bc.pause_production());
+        assert!(!bc.pause_production());
+        assert!(bc.health(0).production_paused);
+        assert!(bc.add_transaction(tx.clone()).is_err());
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().index, 0);
+
+        producer.produce_block("miner".into());
+        bc.validate_and_add_block(producer.last_block().clone())
             .unwrap();
+        assert_eq!(bc.last_block().index, 1);
 
-        let validator = blockchain2.state.get_validator(&alice_pub).unwrap();
-        assert!(validator.slashed, "Validator should be slashed");
-        assert!(!validator.active);
-        assert!(validator.stake < 2000);
+        assert!(bc.resume_production());
+        assert!(!bc.resume_production());
+        bc.add_transaction(tx).unwrap();
+        bc.produce_block("miner".into());
+        assert_eq!(bc.last_block().index, 2);
+        assert_eq!(bc.state.get_balance("bob"), 5);
     }
 
     #[test]
-    fn test_fee_reaches_producer() {
+    fn test_treasury_fee_split_emits_events() {
         let consensus = Arc::new(PoWEngine::new(0));
         let sender = KeyPair::generate().unwrap();
         let sender_pub = sender.public_key_hex();
-        let mut bc = Blockchain::new(consensus, None, 1337, None);
+        let mut bc = Blockchain::builder(consensus)
+            .genesis(GenesisConfig {
+                treasury: crate::genesis::TreasuryConfig {
+                    address: "treasury".into(),
+                    fee_bps: 5_000,
+                    reward_bps: 0,
+                },
+                ..GenesisConfig::new(1337)
+            })
+            .build()
+            .unwrap();
+        let mut events = bc.subscribe_events();
         bc.state.add_balance(&sender_pub, 1000);
 
-        let mut tx = Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 5, 0, vec![]);
+        let mut tx =
+            Transaction::new_with_fee(sender_pub.clone(), "bob".into(), 100, 10, 0, vec![]);
         tx.sign(&sender);
         bc.add_transaction(tx).unwrap();
-
         bc.produce_block("miner_addr".to_string());
+        assert_eq!(bc.state.get_balance("treasury"), 5);
         assert_eq!(bc.state.get_balance("miner_addr"), 5);
+
+        let mut credited = None;
+        while let Ok(event) = events.try_recv() {
+            if let ChainEvent::TreasuryCredited { height, amount, .. } = event {
+                credited = Some((height, amount));
+            }
+        }
+        assert_eq!(credited, Some((1, 5)));
+        assert!(bc.state.treasury_credits.is_empty());
     }
 
     #[test]
@@ -855,4 +4713,198 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("hash"));
     }
+
+    #[test]
+    fn test_chain_export_import_roundtrip() {
+        let keypair = KeyPair::generate().unwrap();
+        let pubkey = keypair.public_key_hex();
+
+        let mut source = Blockchain::new(Arc::new(PoWEngine::new(1)), None, 1337, None);
+        source.state.add_balance(&pubkey, 100);
+        let mut tx = Transaction::new(pubkey.clone(), "bob".to_string(), 50, vec![]);
+        tx.fee = 1;
+        tx.sign(&keypair);
+        source.add_transaction(tx).unwrap();
+        source.produce_block("miner1".to_string());
+
+        let mut exported = Vec::new();
+        assert_eq!(source.export_chain(&mut exported).unwrap(), 2);
+
+        let mut target = Blockchain::new(Arc::new(PoWEngine::new(1)), None, 1337, None);
+        target.state.add_balance(&pubkey, 100);
+        assert_eq!(target.import_chain(&mut exported.as_slice()).unwrap(), 1);
+        assert_eq!(target.last_block().hash, source.last_block().hash);
+        assert_eq!(target.state.get_balance("bob"), 50);
+
+        let mut other_chain = Blockchain::new(Arc::new(PoWEngine::new(1)), None, 1, None);
+        assert!(other_chain.import_chain(&mut exported.as_slice()).is_err());
+
+        let mut tampered = exported.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        let mut fresh = Blockchain::new(Arc::new(PoWEngine::new(1)), None, 1337, None);
+        fresh.state.add_balance(&pubkey, 100);
+        assert!(fresh.import_chain(&mut tampered.as_slice()).is_err());
+        assert_eq!(fresh.chain.len(), 1);
+    }
+
+    #[test]
+    fn test_weak_subjectivity_blocks_conflicting_history() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let mut source = Blockchain::new(engine.clone(), None, 1337, None);
+        source.produce_block("miner".into());
+        source.produce_block("miner".into());
+        let trusted = source.chain[1].clone();
+
+        let mut fork_source = Blockchain::new(engine.clone(), None, 1337, None);
+        fork_source.produce_block("other".into());
+        assert_ne!(fork_source.chain[1].hash, trusted.hash);
+
+        let checkpoint = WeakSubjectivityCheckpoint {
+            height: 1,
+            hash: trusted.hash.clone(),
+        };
+        let mut bc = Blockchain::new(engine.clone(), None, 1337, None)
+            .with_weak_subjectivity(vec![checkpoint]);
+        let err = bc
+            .validate_and_add_block(fork_source.chain[1].clone())
+            .unwrap_err();
+        assert!(err.contains("weak subjectivity"));
+        bc.validate_and_add_block(trusted).unwrap();
+        assert_eq!(bc.weak_subjectivity_floor(), Some(1));
+        assert!(bc.verify_weak_subjectivity().is_ok());
+
+        fork_source.produce_block("other".into());
+        fork_source.produce_block("other".into());
+        let err = bc.try_reorg(fork_source.chain.clone()).unwrap_err();
+        assert!(err.contains("weak subjectivity"));
+        assert_eq!(bc.chain.len(), 2);
+    }
+
+    #[test]
+    fn test_finalized_checkpoint_survives_restart() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let storage = Storage::memory();
+        let mut bc = Blockchain::new(engine.clone(), Some(storage.clone()), 1337, None);
+        bc.produce_block("miner".into());
+        bc.produce_block("miner".into());
+        let finalized = bc.chain[1].hash.clone();
+        bc.set_finalized(1, finalized.clone()).unwrap();
+
+        let mut fork_source = Blockchain::new(engine.clone(), None, 1337, None);
+        for _ in 0..4 {
+            fork_source.produce_block("other".into());
+        }
+
+        let mut restarted = Blockchain::new(engine, Some(storage), 1337, None);
+        assert_eq!(restarted.chain.len(), 3);
+        assert_eq!(restarted.finalized_height, 1);
+        assert_eq!(restarted.finalized_hash, finalized);
+
+        let err = restarted
+            .validate_and_add_block(fork_source.chain[1].clone())
+            .unwrap_err();
+        assert!(err.contains("finalized"));
+        let err = restarted.try_reorg(fork_source.chain.clone()).unwrap_err();
+        assert!(err.contains("finalized checkpoint"));
+    }
+
+    #[test]
+    fn test_tx_confirmation_follows_reorgs() {
+        let kp = KeyPair::generate().unwrap();
+        let sender = kp.public_key_hex();
+        // A genesis allocation, unlike a balance added to the live state,
+        // survives the replay of the reorg.
+        let mut bc = Blockchain::builder(Arc::new(PoWEngine::new(0)))
+            .genesis(GenesisConfig::new(1337).with_allocation(sender.clone(), 1_000))
+            .build()
+            .unwrap();
+        let mut fork_source = bc.clone();
+        let mut tx = Transaction::new_with_fee(sender, "bob".into(), 100, 1, 0, vec![]);
+        tx.sign(&kp);
+        assert_eq!(bc.tx_confirmation(&tx.hash), None);
+        bc.add_transaction(tx.clone()).unwrap();
+
+        let pending = bc.tx_confirmation(&tx.hash).unwrap();
+        assert_eq!(pending.status, ConfirmationStatus::Pending);
+        assert_eq!((pending.confirmations, pending.finalized), (0, false));
+        assert!(!pending.is_accepted(0));
+
+        bc.produce_block("miner".into());
+        let included = bc.tx_confirmation(&tx.hash).unwrap();
+        assert_eq!(included.status, ConfirmationStatus::Included);
+        assert_eq!(included.block_height, Some(1));
+        assert_eq!(included.confirmations, 1);
+
+        for _ in 0..3 {
+            fork_source.produce_block("other".into());
+        }
+        assert!(bc.try_reorg(fork_source.chain.clone()).unwrap());
+        let reorged = bc.tx_confirmation(&tx.hash).unwrap();
+        assert_eq!(reorged.status, ConfirmationStatus::Pending);
+        assert_eq!(reorged.block_height, None);
+
+        bc.produce_block("miner".into());
+        bc.produce_block("miner".into());
+        let confirmed = bc.tx_confirmation(&tx.hash).unwrap();
+        assert_eq!(confirmed.block_height, Some(4));
+        assert_eq!((confirmed.tip_height, confirmed.confirmations), (5, 2));
+        assert!(confirmed.is_accepted(2) && !confirmed.is_accepted(3));
+
+        bc.set_finalized(4, confirmed.block_hash.clone().unwrap())
+            .unwrap();
+        let finalized = bc.tx_confirmation(&tx.hash).unwrap();
+        assert!(finalized.finalized && finalized.is_accepted(10));
+    }
+
+    #[test]
+    fn test_reorg_emits_events() {
+        let engine = Arc::new(PoWEngine::new(0));
+        let mut bc = Blockchain::new(engine.clone(), None, 1337, None);
+        let mut events = bc.subscribe_events();
+        bc.produce_block("miner".into());
+        let old_tip = bc.last_block().hash.clone();
+        let orphaned_tip = old_tip.clone();
+        let mut diffs = bc.subscribe_state_diffs();
+
+        let mut fork_source = Blockchain::new(engine, None, 1337, None);
+        for _ in 0..3 {
+            fork_source.produce_block("other".into());
+        }
+        assert!(bc.try_reorg(fork_source.chain.clone()).unwrap());
+
+        let received: Vec<ChainEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received[0],
+            ChainEvent::BlockAdded {
+                height: 1,
+                hash: old_tip.clone()
+            }
+        );
+        assert_eq!(
+            received[1],
+            ChainEvent::Reorged {
+                depth: 1,
+                fork_height: 1,
+                old_tip,
+                new_tip: fork_source.last_block().hash.clone(),
+            }
+        );
+        assert_eq!(received.len(), 5);
+
+        // The orphaned block is reverted before the new branch's diffs.
+        let diffs: Vec<StateDiffEvent> = std::iter::from_fn(|| diffs.try_recv().ok()).collect();
+        assert!(matches!(
+            &diffs[0],
+            StateDiffEvent::Reverted { height: 1, hash } if *hash == orphaned_tip
+        ));
+        let heights: Vec<u64> = diffs[1..]
+            .iter()
+            .map(|event| match event {
+                StateDiffEvent::Applied(diff) => diff.height,
+                other => panic!("expected a diff, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(heights, vec![1, 2, 3]);
+    }
 }
@@ -0,0 +1,238 @@
+use crate::account::Validator;
+use crate::chain_config::{FINALITY_QUORUM_DENOMINATOR, FINALITY_QUORUM_NUMERATOR};
+use crate::crypto::{verify_signature, KeyPair};
+use crate::light_client::TxInclusionProof;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashSet;
+
+// Holds coins locked for transfer to another chain until a release message
+// brings them back.
+pub const BRIDGE_ESCROW: &str = "bridge_escrow";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "chain_id", rename_all = "snake_case")]
+pub enum ForeignChain {
+    Budlum(u64),
+    Evm(u64),
+}
+
+impl ForeignChain {
+    fn encode(&self) -> [u8; 9] {
+        let (tag, chain_id) = match self {
+            ForeignChain::Budlum(id) => (0u8, id),
+            ForeignChain::Evm(id) => (1u8, id),
+        };
+        let mut bytes = [tag; 9];
+        bytes[1..].copy_from_slice(&chain_id.to_le_bytes());
+        bytes
+    }
+}
+
+// Payload of BridgeLock and BridgeBurn transactions: where the relayer
+// should deliver the coins on the other side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BridgeTransfer {
+    pub destination: ForeignChain,
+    pub recipient: String,
+}
+
+impl BridgeTransfer {
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let transfer: BridgeTransfer = serde_json::from_slice(data)
+            .map_err(|e| format!("Invalid bridge transfer payload: {}", e))?;
+        if transfer.recipient.is_empty() {
+            return Err("Bridge transfer needs a recipient on the destination chain".into());
+        }
+        Ok(transfer)
+    }
+}
+
+// Mint credits wrapped coins for assets locked on the source chain; release
+// pays out coins this chain locked in BRIDGE_ESCROW earlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InboundKind {
+    Mint,
+    Release,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboundMessage {
+    pub source: ForeignChain,
+    pub source_tx: String,
+    pub nonce: u64,
+    pub recipient: String,
+    pub amount: u64,
+    pub kind: InboundKind,
+    pub dest_chain_id: u64,
+}
+
+impl InboundMessage {
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"BDLM_BRIDGE_V1");
+        hasher.update(self.source.encode());
+        hasher.update((self.source_tx.len() as u64).to_le_bytes());
+        hasher.update(self.source_tx.as_bytes());
+        hasher.update(self.nonce.to_le_bytes());
+        hasher.update((self.recipient.len() as u64).to_le_bytes());
+        hasher.update(self.recipient.as_bytes());
+        hasher.update(self.amount.to_le_bytes());
+        hasher.update([self.kind as u8]);
+        hasher.update(self.dest_chain_id.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    // Identifies the message for replay protection, independent of who
+    // relayed or attested it.
+    pub fn id(&self) -> String {
+        hex::encode(self.signing_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub validator: String,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedInbound {
+    pub message: InboundMessage,
+    pub relayer: String,
+    pub relayer_signature: Vec<u8>,
+    pub attestations: Vec<Attestation>,
+    // Replaces the attestations for transfers from a Budlum chain this chain
+    // tracks with a light client.
+    #[serde(default)]
+    pub proof: Option<TxInclusionProof>,
+}
+
+fn check_signature(message: &[u8], signature: &[u8], signer: &str) -> Result<(), String> {
+    let public_key = hex::decode(signer).map_err(|_| format!("Invalid signer {}", signer))?;
+    verify_signature(message, signature, &public_key).map_err(|e| e.to_string())
+}
+
+impl SignedInbound {
+    pub fn new(message: InboundMessage, relayer: &KeyPair) -> Self {
+        let relayer_signature = relayer.sign(&message.signing_bytes()).to_vec();
+        SignedInbound {
+            message,
+            relayer: relayer.public_key_hex(),
+            relayer_signature,
+            attestations: Vec::new(),
+            proof: None,
+        }
+    }
+
+    pub fn attest(&mut self, validator: &KeyPair) {
+        self.attestations.push(Attestation {
+            validator: validator.public_key_hex(),
+            signature: validator.sign(&self.message.signing_bytes()).to_vec(),
+        });
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(data).map_err(|e| format!("Invalid bridge message payload: {}", e))
+    }
+
+    pub fn verify_relayer(&self) -> Result<(), String> {
+        check_signature(
+            &self.message.signing_bytes(),
+            &self.relayer_signature,
+            &self.relayer,
+        )
+        .map_err(|e| format!("Invalid relayer signature: {}", e))
+    }
+
+    // The relayer must sign the message and validators holding at least the
+    // finality quorum of active stake must attest to it.
+    pub fn verify(&self, validators: &[&Validator]) -> Result<(), String> {
+        self.verify_relayer()?;
+        let signing_bytes = self.message.signing_bytes();
+
+        let total: u64 = validators.iter().map(|v| v.stake).sum();
+        let mut seen = HashSet::new();
+        let mut attested = 0u64;
+        for attestation in &self.attestations {
+            if !seen.insert(attestation.validator.as_str()) {
+                return Err(format!(
+                    "Duplicate attestation from {}",
+                    attestation.validator
+                ));
+            }
+            let validator = validators
+                .iter()
+                .find(|v| v.address == attestation.validator)
+                .ok_or_else(|| format!("{} is not an active validator", attestation.validator))?;
+            check_signature(&signing_bytes, &attestation.signature, &validator.address)
+                .map_err(|e| format!("Invalid attestation from {}: {}", validator.address, e))?;
+            attested += validator.stake;
+        }
+        let quorum = total * FINALITY_QUORUM_NUMERATOR / FINALITY_QUORUM_DENOMINATOR;
+        if total == 0 || attested < quorum {
+            return Err(format!(
+                "Bridge message attested by {} of {} stake, needs {}",
+                attested, total, quorum
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> InboundMessage {
+        InboundMessage {
+            source: ForeignChain::Evm(1),
+            source_tx: "0xabc".into(),
+            nonce: 7,
+            recipient: "alice".into(),
+            amount: 500,
+            kind: InboundKind::Mint,
+            dest_chain_id: 1337,
+        }
+    }
+
+    #[test]
+    fn test_inbound_message_needs_relayer_and_stake_quorum() {
+        let relayer = KeyPair::generate().unwrap();
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate().unwrap()).collect();
+        let validators: Vec<Validator> = keys
+            .iter()
+            .map(|k| Validator::new(k.public_key_hex(), 100))
+            .collect();
+        let active: Vec<&Validator> = validators.iter().collect();
+
+        let mut signed = SignedInbound::new(message(), &relayer);
+        signed.attest(&keys[0]);
+        assert!(signed.verify(&active).unwrap_err().contains("needs 200"));
+
+        signed.attest(&keys[1]);
+        signed.verify(&active).unwrap();
+        assert_eq!(SignedInbound::decode(&signed.encode()).unwrap(), signed);
+
+        let mut duplicate = signed.clone();
+        duplicate.attest(&keys[1]);
+        assert!(duplicate.verify(&active).is_err());
+
+        let mut outsider = SignedInbound::new(message(), &relayer);
+        outsider.attest(&keys[0]);
+        outsider.attest(&relayer);
+        assert!(outsider.verify(&active).is_err());
+
+        let mut tampered = signed.clone();
+        tampered.message.amount = 5_000;
+        assert!(tampered.verify(&active).is_err());
+        assert_ne!(tampered.message.id(), signed.message.id());
+    }
+}
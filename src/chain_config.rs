@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
 pub const PROTOCOL_VERSION: u32 = 1;
 pub const CHAIN_ID_MAINNET: u64 = 1;
 pub const CHAIN_ID_TESTNET: u64 = 42;
@@ -13,6 +14,12 @@ pub const VRF_BASE_PROB: f64 = 1.0;
 pub const QC_BLOB_TTL_EPOCHS: u64 = 10;
 pub const MAX_QC_BLOB_BYTES: usize = 1_048_576;
 pub const MAX_VOTES_PER_MSG: usize = 128;
+// Fork names that switch on consensus rules. A chain that does not list one
+// in its fork schedule keeps the rule it replaces.
+pub const FORK_ROLLING_VALIDATOR_SET: &str = "rolling_validator_set";
+pub const FORK_BLOCK_VERSION: &str = "block_version";
+pub const FORK_STATE_SNAPSHOT: &str = "state_snapshot";
+pub const FORK_TX_SIGNING_V2: &str = "tx_signing_v2";
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ChainId(pub u64);
 impl ChainId {
@@ -49,6 +56,175 @@ impl std::fmt::Display for ChainId {
         write!(f, "{}({})", self.name(), self.0)
     }
 }
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeakSubjectivityCheckpoint {
+    pub height: u64,
+    pub hash: String,
+}
+impl std::str::FromStr for WeakSubjectivityCheckpoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hash, height) = s
+            .split_once('@')
+            .ok_or_else(|| format!("Expected HASH@HEIGHT, got {}", s))?;
+        let height = height
+            .parse::<u64>()
+            .map_err(|e| format!("Invalid checkpoint height {}: {}", height, e))?;
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid checkpoint hash {}", hash));
+        }
+        Ok(WeakSubjectivityCheckpoint {
+            height,
+            hash: hash.to_lowercase(),
+        })
+    }
+}
+impl std::fmt::Display for WeakSubjectivityCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.hash, self.height)
+    }
+}
+// Block and reorg limits every node on a network has to agree on. The
+// consensus constants are only the defaults; testnets and private chains
+// override them through the chain spec or the `[limits]` config section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainLimits {
+    pub max_block_size: usize,
+    pub max_transactions_per_block: usize,
+    pub min_block_interval_ms: u64,
+    pub max_reorg_depth: usize,
+}
+impl Default for ChainLimits {
+    fn default() -> Self {
+        ChainLimits {
+            max_block_size: crate::consensus::MAX_BLOCK_SIZE,
+            max_transactions_per_block: crate::consensus::MAX_TRANSACTIONS_PER_BLOCK,
+            min_block_interval_ms: crate::consensus::MIN_BLOCK_INTERVAL_MS as u64,
+            max_reorg_depth: crate::consensus::MAX_REORG_DEPTH,
+        }
+    }
+}
+impl ChainLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_block_size == 0 || self.max_transactions_per_block == 0 {
+            return Err("Block size and transaction limits must be positive".into());
+        }
+        if self.max_reorg_depth == 0 {
+            return Err("max_reorg_depth must be positive".into());
+        }
+        if self.max_block_size > crate::network::MAX_MESSAGE_BLOCK_SIZE {
+            return Err(format!(
+                "max_block_size {} exceeds the {} byte network message limit",
+                self.max_block_size,
+                crate::network::MAX_MESSAGE_BLOCK_SIZE
+            ));
+        }
+        Ok(())
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkActivation {
+    pub name: String,
+    pub height: u64,
+}
+
+// Identifies which branch of a hard fork a node follows. Both sides keep the
+// chain_id, so only the fork hash tells them apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkId {
+    pub chain_id: u64,
+    pub fork_hash: String,
+}
+
+impl std::fmt::Display for ForkId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.fork_hash.as_str() {
+            "" => write!(f, "{}", ChainId(self.chain_id)),
+            hash => write!(
+                f,
+                "{}/{}",
+                ChainId(self.chain_id),
+                &hash[..16.min(hash.len())]
+            ),
+        }
+    }
+}
+
+// Hard forks from the chain spec, ordered by activation height. The fork
+// hash is empty until the first activation so pre-fork transactions and
+// handshakes keep their existing encoding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForkSchedule {
+    forks: Vec<ForkActivation>,
+}
+
+impl ForkSchedule {
+    pub fn new(forks: &[ForkActivation]) -> Result<Self, String> {
+        let mut sorted = forks.to_vec();
+        sorted.sort_by_key(|f| f.height);
+        for (i, fork) in sorted.iter().enumerate() {
+            if fork.height == 0 || fork.name.is_empty() {
+                return Err("Forks need a name and an activation height above 0".into());
+            }
+            if sorted[..i]
+                .iter()
+                .any(|f| f.height == fork.height || f.name == fork.name)
+            {
+                return Err(format!(
+                    "Fork {} at height {} duplicates another fork's name or height",
+                    fork.name, fork.height
+                ));
+            }
+        }
+        Ok(ForkSchedule { forks: sorted })
+    }
+
+    pub fn forks(&self) -> &[ForkActivation] {
+        &self.forks
+    }
+
+    // Chains the name and height of every fork active at `height`.
+    pub fn fork_hash(&self, height: u64) -> String {
+        let mut hash = String::new();
+        for fork in self.forks.iter().take_while(|f| f.height <= height) {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"BDLM_FORK_V1");
+            hasher.update(hash.as_bytes());
+            hasher.update(fork.name.as_bytes());
+            hasher.update(fork.height.to_le_bytes());
+            hash = hex::encode(hasher.finalize());
+        }
+        hash
+    }
+
+    pub fn fork_id(&self, chain_id: u64, height: u64) -> ForkId {
+        ForkId {
+            chain_id,
+            fork_hash: self.fork_hash(height),
+        }
+    }
+
+    pub fn next_fork(&self, height: u64) -> Option<&ForkActivation> {
+        self.forks.iter().find(|f| f.height > height)
+    }
+
+    pub fn is_active(&self, name: &str, height: u64) -> bool {
+        self.forks
+            .iter()
+            .any(|f| f.name == name && f.height <= height)
+    }
+
+    // The only signing hash layout transactions in a block at `height` may
+    // use.
+    pub fn tx_signing_version(&self, height: u64) -> u8 {
+        if self.is_active(FORK_TX_SIGNING_V2, height) {
+            crate::transaction::TX_SIGNING_V2
+        } else {
+            crate::transaction::TX_SIGNING_LEGACY
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -64,4 +240,50 @@ mod tests {
         assert_eq!(format!("{}", ChainId::MAINNET), "mainnet(1)");
         assert_eq!(format!("{}", ChainId::new(123)), "custom(123)");
     }
+    #[test]
+    fn test_weak_subjectivity_parse() {
+        let hash = "ab".repeat(32);
+        let cp: WeakSubjectivityCheckpoint = format!("{}@128", hash).parse().unwrap();
+        assert_eq!(cp.height, 128);
+        assert_eq!(cp.to_string(), format!("{}@128", hash));
+        assert!("deadbeef@1".parse::<WeakSubjectivityCheckpoint>().is_err());
+        assert!(hash.parse::<WeakSubjectivityCheckpoint>().is_err());
+    }
+    #[test]
+    fn test_chain_limits_partial_override() {
+        let limits: ChainLimits = toml::from_str("max_transactions_per_block = 10").unwrap();
+        assert_eq!(limits.max_transactions_per_block, 10);
+        assert_eq!(limits.max_block_size, ChainLimits::default().max_block_size);
+        assert!(limits.validate().is_ok());
+        let zero = ChainLimits {
+            max_reorg_depth: 0,
+            ..Default::default()
+        };
+        assert!(zero.validate().is_err());
+    }
+    #[test]
+    fn test_fork_hash_changes_at_each_activation() {
+        let fork = |name: &str, height| ForkActivation {
+            name: name.into(),
+            height,
+        };
+        let schedule = ForkSchedule::new(&[fork("b", 200), fork("a", 100)]).unwrap();
+        assert_eq!(schedule.fork_hash(99), "");
+        assert_eq!(schedule.fork_id(1, 99).to_string(), "mainnet(1)");
+        let first = schedule.fork_hash(100);
+        assert_eq!(first, schedule.fork_hash(199));
+        assert_ne!(first, schedule.fork_hash(200));
+        assert_eq!(schedule.next_fork(150).unwrap().name, "b");
+        assert!(schedule.next_fork(200).is_none());
+        assert!(!schedule.is_active("b", 199));
+        assert!(schedule.is_active("b", 200));
+        assert!(!schedule.is_active("c", 1000));
+
+        // A branch that skipped fork "a" never shares its fork hash.
+        let other = ForkSchedule::new(&[fork("c", 100)]).unwrap();
+        assert_ne!(other.fork_hash(100), first);
+
+        assert!(ForkSchedule::new(&[fork("a", 100), fork("b", 100)]).is_err());
+        assert!(ForkSchedule::new(&[fork("a", 0)]).is_err());
+    }
 }
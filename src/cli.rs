@@ -1,5 +1,33 @@
-use clap::Parser;
+use crate::account::StakingRules;
+use crate::blockchain::Blockchain;
+use crate::chain_config::{ChainLimits, ForkActivation, ForkSchedule, WeakSubjectivityCheckpoint};
+#[cfg(feature = "poa")]
+use crate::consensus::PoAEngine;
+#[cfg(feature = "pos")]
+use crate::consensus::PoSEngine;
+#[cfg(feature = "pow-miner")]
+use crate::consensus::PoWEngine;
+use crate::consensus::{ConsensusEngine, DevEngine};
+use crate::crypto::ValidatorKeys;
+use crate::data_dir::{DataDir, DEFAULT_DATA_DIR};
+use crate::dust::DustSweepConfig;
+use crate::frost::{load_token, KeyShare, RemoteParticipant, ThresholdSigner};
+use crate::genesis::{GenesisConfig, TreasuryConfig};
+use crate::keystore::{KeyType, Keystore};
+use crate::mempool::{MempoolConfig, ProducerRule};
+use crate::network::gossip::GossipConfig;
+use crate::network::outbound::ChaosConfig;
+use crate::snapshot::{PruningManager, DELTAS_PER_FULL_SNAPSHOT};
+use crate::storage::{storage_key_from_command, EncryptionSecret, Storage};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::net::IpAddr;
 use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use zeroize::Zeroizing;
 #[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
 pub enum ConsensusType {
     #[value(name = "pow")]
@@ -8,6 +36,33 @@ pub enum ConsensusType {
     PoS,
     #[value(name = "poa")]
     PoA,
+    #[value(name = "dev")]
+    Dev,
+}
+impl ConsensusType {
+    pub fn is_compiled(self) -> bool {
+        match self {
+            ConsensusType::PoW => cfg!(feature = "pow-miner"),
+            ConsensusType::PoS => cfg!(feature = "pos"),
+            ConsensusType::PoA => cfg!(feature = "poa"),
+            ConsensusType::Dev => true,
+        }
+    }
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            ConsensusType::PoW => Some("pow-miner"),
+            ConsensusType::PoS => Some("pos"),
+            ConsensusType::PoA => Some("poa"),
+            ConsensusType::Dev => None,
+        }
+    }
+    // PoW unless the build leaves it out; the dev engine is always there.
+    pub fn compiled_default() -> Self {
+        [ConsensusType::PoW, ConsensusType::PoS, ConsensusType::PoA]
+            .into_iter()
+            .find(|c| c.is_compiled())
+            .unwrap_or(ConsensusType::Dev)
+    }
 }
 impl std::fmt::Display for ConsensusType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -15,6 +70,7 @@ impl std::fmt::Display for ConsensusType {
             ConsensusType::PoW => write!(f, "PoW (Proof of Work)"),
             ConsensusType::PoS => write!(f, "PoS (Proof of Stake)"),
             ConsensusType::PoA => write!(f, "PoA (Proof of Authority)"),
+            ConsensusType::Dev => write!(f, "Dev (Instant Seal)"),
         }
     }
 }
@@ -39,16 +95,45 @@ impl std::fmt::Display for PrivacyLevel {
         }
     }
 }
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum DbBackend {
+    #[value(name = "sled")]
+    Sled,
+    #[value(name = "memory")]
+    Memory,
+}
 #[derive(Parser, Debug)]
 #[command(name = "budlum-core")]
 #[command(about = "Budlum privacy-focused blockchain node")]
 pub struct NodeConfig {
-    #[arg(long, default_value = "pow")]
+    #[arg(long)]
+    pub config: Option<String>,
+    #[arg(long, value_enum, default_value_t = ConsensusType::compiled_default())]
     pub consensus: ConsensusType,
     #[arg(long, default_value = "2")]
     pub difficulty: usize,
     #[arg(long, default_value = "1000")]
     pub min_stake: u64,
+    #[arg(long, default_value = "0")]
+    pub max_validators: usize,
+    #[arg(long)]
+    pub treasury_address: Option<String>,
+    #[arg(long, default_value = "0")]
+    pub treasury_fee_bps: u64,
+    #[arg(long, default_value = "0")]
+    pub treasury_reward_bps: u64,
+    #[arg(long, default_value = "0")]
+    pub dust_threshold: u64,
+    #[arg(long, default_value = "0")]
+    pub dust_inactive_epochs: u64,
+    #[arg(long, default_value = "0")]
+    pub halt_timeout_secs: u64,
+    #[arg(long, default_value = "1")]
+    pub min_validators: usize,
+    #[arg(long, default_value = "0")]
+    pub target_block_size: usize,
+    #[arg(long = "weak-subjectivity", value_name = "HASH@HEIGHT")]
+    pub weak_subjectivity: Vec<WeakSubjectivityCheckpoint>,
     #[arg(long, default_value = "none")]
     pub privacy: PrivacyLevel,
     #[arg(long, default_value = "11")]
@@ -57,8 +142,32 @@ pub struct NodeConfig {
     pub port: u16,
     #[arg(long)]
     pub bootstrap: Option<String>,
-    #[arg(long, default_value = "./data/budlum.db")]
-    pub db_path: String,
+    #[arg(long)]
+    pub chain_spec: Option<String>,
+    #[arg(skip)]
+    pub bootnodes: Vec<String>,
+    #[arg(skip)]
+    pub dns_seeds: Vec<String>,
+    #[arg(long, default_value = DEFAULT_DATA_DIR)]
+    pub data_dir: String,
+    #[arg(long)]
+    pub db_path: Option<String>,
+    #[arg(long, default_value = "sled")]
+    pub db_backend: DbBackend,
+    #[arg(long, default_value = "0")]
+    pub db_sync_interval_ms: u64,
+    #[arg(long)]
+    pub cold_db_path: Option<String>,
+    #[arg(long)]
+    pub db_passphrase_file: Option<String>,
+    #[arg(long)]
+    pub db_key_command: Option<String>,
+    #[arg(long)]
+    pub balance_history: bool,
+    #[arg(long, default_value = "0")]
+    pub chain_memory_budget: usize,
+    #[arg(long)]
+    pub state_root_diagnostics: bool,
     #[arg(long, default_value = "./validators.json")]
     pub validators_file: String,
     #[arg(long)]
@@ -67,12 +176,797 @@ pub struct NodeConfig {
     pub dial: Option<String>,
     #[arg(long, default_value = "1337")]
     pub chain_id: u64,
-    #[arg(long)]
+    #[arg(long, visible_alias = "validator-key")]
     pub validator_key_file: Option<String>,
     #[arg(long)]
+    pub observer: bool,
+    #[arg(long = "threshold-share", value_name = "FILE")]
+    pub threshold_shares: Vec<String>,
+    #[arg(long = "threshold-remote", value_name = "ID@HOST:PORT")]
+    pub threshold_remotes: Vec<String>,
+    #[arg(long)]
+    pub threshold_token_file: Option<String>,
+    #[arg(long)]
     pub gen_key: Option<String>,
+    #[arg(long)]
+    pub rpc_port: Option<u16>,
+    #[arg(long)]
+    pub ws_port: Option<u16>,
+    #[arg(long)]
+    pub grpc_port: Option<u16>,
+    #[arg(long, default_value = "127.0.0.1")]
+    pub rpc_bind: IpAddr,
+    #[arg(long, default_value = "100")]
+    pub rpc_rate_limit: u32,
+    #[arg(long)]
+    pub admin_socket: bool,
+    #[arg(long, default_value = "info")]
+    pub log_level: String,
+    #[arg(long, default_value_t = crate::network::DEFAULT_MAX_PEERS)]
+    pub max_peers: usize,
+    #[arg(long, default_value = "0")]
+    pub max_peer_inbound_bps: u64,
+    #[arg(long, default_value = "0")]
+    pub max_inbound_bps: u64,
+    #[arg(long)]
+    pub chaos: bool,
+    #[arg(long, default_value = "0.05")]
+    pub chaos_drop: f64,
+    #[arg(long, default_value = "0.05")]
+    pub chaos_duplicate: f64,
+    #[arg(long, default_value = "0.2")]
+    pub chaos_delay: f64,
+    #[arg(long, default_value = "2000")]
+    pub chaos_max_delay_ms: u64,
+    #[arg(skip)]
+    pub mempool: MempoolConfig,
+    #[arg(skip)]
+    pub pruning: PruningConfig,
+    #[arg(skip)]
+    pub limits: ChainLimits,
+    #[arg(skip)]
+    pub forks: Vec<ForkActivation>,
+    #[arg(skip)]
+    pub gossip: GossipConfig,
+    // The loaded `--chain-spec`, which is the chain's genesis config.
+    #[arg(skip)]
+    pub spec: Option<GenesisConfig>,
+    // Fields set on the command line or in the config file rather than
+    // left at their defaults.
+    #[arg(skip)]
+    explicit: HashSet<String>,
+    // The config file's `[mempool]` values, laid over the chain spec's.
+    #[arg(skip)]
+    mempool_overrides: MempoolSection,
+    // The database key, resolved once so a `key_command` runs a single
+    // time per process and every file is sealed with the same secret.
+    #[arg(skip)]
+    secret: OnceLock<Option<EncryptionSecret>>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[command(subcommand, about = "Export or import state snapshots")]
+    Snapshot(SnapshotCommand),
+    #[command(subcommand, about = "Back up or restore the block chain")]
+    Chain(ChainCommand),
+    #[command(subcommand, about = "Inspect and maintain the block database")]
+    Db(DbCommand),
+    #[command(subcommand, about = "Manage node configuration files")]
+    Config(ConfigCommand),
+    #[command(subcommand, about = "Generate, inspect and move validator keys")]
+    Keys(KeysCommand),
+    #[command(about = "Call a method on the running node's admin socket")]
+    Admin {
+        method: String,
+        // JSON array or object of method parameters.
+        params: Option<String>,
+    },
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum KeysCommand {
+    #[command(about = "Generate a key in the data directory")]
+    Generate {
+        #[arg(long = "type", value_enum, default_value = "ed25519")]
+        key_type: KeyType,
+        #[arg(long)]
+        force: bool,
+    },
+    #[command(about = "Print the public identities of stored keys")]
+    Show {
+        #[arg(long = "type", value_enum)]
+        key_type: Option<KeyType>,
+    },
+    #[command(about = "Install a hex encoded secret key into the data directory")]
+    Import {
+        file: String,
+        #[arg(long = "type", value_enum, default_value = "ed25519")]
+        key_type: KeyType,
+        #[arg(long)]
+        force: bool,
+    },
+    #[command(about = "Write a stored secret key as hex for backup or migration")]
+    Export {
+        #[arg(long = "type", value_enum, default_value = "ed25519")]
+        key_type: KeyType,
+        #[arg(long)]
+        out: String,
+    },
+    #[command(about = "Split the ed25519 key into FROST threshold signing shares")]
+    Split {
+        #[arg(long)]
+        threshold: u16,
+        #[arg(long)]
+        shares: u16,
+        #[arg(long)]
+        out_dir: String,
+    },
+    #[command(about = "Serve one threshold share to a remote PoA coordinator")]
+    ServeShare {
+        #[arg(long)]
+        share: String,
+        #[arg(long, default_value = "127.0.0.1:4100")]
+        listen: String,
+        #[arg(long)]
+        token_file: String,
+    },
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    #[command(about = "Write a commented default configuration file")]
+    Init {
+        #[arg(long, default_value = "node.toml")]
+        out: String,
+        #[arg(long)]
+        force: bool,
+    },
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum DbCommand {
+    #[command(about = "Report disk usage per keyspace and estimated reclaimable space")]
+    Stats,
+    #[command(about = "Remove orphaned blocks and flush the database")]
+    Compact,
+    #[command(about = "Rebuild height, transaction and balance indexes from stored blocks")]
+    Reindex,
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum ChainCommand {
+    #[command(about = "Stream all stored blocks into a portable backup file")]
+    Export { out: String },
+    #[command(about = "Validate and append blocks from a backup file")]
+    Import { file: String },
+    #[command(about = "Write the latest finalized checkpoint as a fast sync bundle")]
+    ExportCheckpoint { out: String },
+    #[command(about = "Adopt a checkpoint bundle without re-executing finalized history")]
+    CheckpointSync { file: String },
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnapshotCommand {
+    #[command(about = "Write a self-contained snapshot file for out-of-band distribution")]
+    Export {
+        #[arg(long)]
+        height: Option<u64>,
+        #[arg(long)]
+        out: String,
+    },
+    #[command(about = "Verify and install a snapshot file produced by `snapshot export`")]
+    Import {
+        file: String,
+        #[arg(long)]
+        state_root: Option<String>,
+    },
+    #[command(about = "Print the manifest of a snapshot file as JSON")]
+    Inspect { file: String },
+}
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub log_level: String,
+    pub max_peers: usize,
+    pub mempool: MempoolConfig,
+    pub rpc_rate_limit: u32,
+}
+#[derive(Debug, Clone)]
+pub struct PruningConfig {
+    pub min_blocks_to_keep: u64,
+    pub snapshot_interval: u64,
+    pub deltas_per_full: u64,
+}
+impl Default for PruningConfig {
+    fn default() -> Self {
+        PruningConfig {
+            min_blocks_to_keep: 1000,
+            snapshot_interval: 100,
+            deltas_per_full: DELTAS_PER_FULL_SNAPSHOT,
+        }
+    }
 }
 impl NodeConfig {
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(std::env::args_os())
+    }
+    pub fn load_from<I, T>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = NodeConfig::command()
+            .try_get_matches_from(args)
+            .unwrap_or_else(|e| e.exit());
+        let mut config = NodeConfig::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+        config.explicit = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(|id| id.to_string())
+            .collect();
+        if let Some(path) = config.config.clone() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+            let file: FileConfig = toml::from_str(&content)
+                .map_err(|e| format!("Failed to parse config file {}: {}", path, e))?;
+            config.apply_file(file)?;
+        }
+        config.apply_chain_spec()?;
+        if let (false, Some(feature)) = (config.consensus.is_compiled(), config.consensus.feature())
+        {
+            return Err(format!(
+                "{} is not compiled into this build (enable the `{}` feature)",
+                config.consensus, feature
+            ));
+        }
+        if config.observer
+            && (config.validator_key_file.is_some() || !config.threshold_shares.is_empty())
+        {
+            return Err("An observer node cannot load validator keys or threshold shares".into());
+        }
+        Ok(config)
+    }
+    // A chain spec fixes the chain id, forks and consensus rules of the
+    // network it describes and lists its bootnodes and DNS seeds, so joining
+    // needs nothing but the spec file. Local settings may repeat the spec's
+    // values but not contradict them.
+    fn apply_chain_spec(&mut self) -> Result<(), String> {
+        let Some(path) = self.chain_spec.clone() else {
+            return Ok(());
+        };
+        let spec = GenesisConfig::load(&path)?;
+        // `field` names the local setting, `differs` compares it with the
+        // spec's.
+        let conflict = |field: &str, differs: bool| {
+            if differs && self.explicit.contains(field) {
+                return Err(format!("{} differs from chain spec {}", field, path));
+            }
+            Ok(())
+        };
+        if self.explicit.contains("chain_id") && self.chain_id != spec.chain_id {
+            return Err(format!(
+                "Chain spec {} is for chain {} but chain_id is {}",
+                path, spec.chain_id, self.chain_id
+            ));
+        }
+        if !self.forks.is_empty() && self.forks != spec.forks {
+            return Err(format!(
+                "Forks in the config file differ from chain spec {}",
+                path
+            ));
+        }
+        conflict("limits", self.limits != spec.limits)?;
+        conflict("min_stake", self.min_stake != spec.staking.min_stake)?;
+        conflict(
+            "max_validators",
+            self.max_validators != spec.staking.max_validators,
+        )?;
+        conflict(
+            "treasury_address",
+            self.treasury_address.as_deref().unwrap_or_default() != spec.treasury.address,
+        )?;
+        conflict(
+            "treasury_fee_bps",
+            self.treasury_fee_bps != spec.treasury.fee_bps,
+        )?;
+        conflict(
+            "treasury_reward_bps",
+            self.treasury_reward_bps != spec.treasury.reward_bps,
+        )?;
+        conflict(
+            "dust_threshold",
+            self.dust_threshold != spec.dust_sweep.threshold,
+        )?;
+        conflict(
+            "dust_inactive_epochs",
+            self.dust_inactive_epochs != spec.dust_sweep.inactive_epochs,
+        )?;
+        self.chain_id = spec.chain_id;
+        self.forks = spec.forks.clone();
+        self.limits = spec.limits;
+        self.min_stake = spec.staking.min_stake;
+        self.max_validators = spec.staking.max_validators;
+        self.treasury_address = Some(spec.treasury.address.clone()).filter(|a| !a.is_empty());
+        self.treasury_fee_bps = spec.treasury.fee_bps;
+        self.treasury_reward_bps = spec.treasury.reward_bps;
+        self.dust_threshold = spec.dust_sweep.threshold;
+        self.dust_inactive_epochs = spec.dust_sweep.inactive_epochs;
+        self.bootnodes = spec.bootnodes.clone();
+        self.dns_seeds = spec.dns_seeds.clone();
+        self.mempool = spec.mempool.clone();
+        self.apply_mempool_overrides()?;
+        self.spec = Some(spec);
+        Ok(())
+    }
+    // The chain spec if one was given, otherwise a genesis config built from
+    // the local settings.
+    pub fn genesis(&self) -> GenesisConfig {
+        self.spec.clone().unwrap_or_else(|| GenesisConfig {
+            forks: self.forks.clone(),
+            dust_sweep: self.dust_sweep(),
+            staking: self.staking_rules(),
+            treasury: self.treasury_config(),
+            limits: self.limits,
+            mempool: self.mempool.clone(),
+            ..GenesisConfig::new(self.chain_id)
+        })
+    }
+    fn apply_mempool_overrides(&mut self) -> Result<(), String> {
+        let overrides = self.mempool_overrides.clone();
+        let mempool = &mut self.mempool;
+        mempool.max_size = overrides.max_size.unwrap_or(mempool.max_size);
+        mempool.max_per_sender = overrides.max_per_sender.unwrap_or(mempool.max_per_sender);
+        mempool.min_fee = overrides.min_fee.unwrap_or(mempool.min_fee);
+        mempool.tx_ttl_secs = overrides.tx_ttl_secs.unwrap_or(mempool.tx_ttl_secs);
+        mempool.rbf_bump_percent = overrides
+            .rbf_bump_percent
+            .unwrap_or(mempool.rbf_bump_percent);
+        mempool.rebroadcast_after_blocks = overrides
+            .rebroadcast_after_blocks
+            .unwrap_or(mempool.rebroadcast_after_blocks);
+        mempool.rebroadcast_limit = overrides
+            .rebroadcast_limit
+            .unwrap_or(mempool.rebroadcast_limit);
+        if let Some(senders) = overrides.priority_senders {
+            mempool.priority_senders = senders;
+        }
+        if let Some(rules) = overrides.producer_rules {
+            mempool.producer_rules = rules;
+        }
+        mempool.validate()
+    }
+    pub fn dust_sweep(&self) -> DustSweepConfig {
+        DustSweepConfig {
+            threshold: self.dust_threshold,
+            inactive_epochs: self.dust_inactive_epochs,
+        }
+    }
+    pub fn runtime_config(&self) -> RuntimeConfig {
+        RuntimeConfig {
+            log_level: self.log_level.clone(),
+            max_peers: self.max_peers,
+            mempool: self.mempool.clone(),
+            rpc_rate_limit: self.rpc_rate_limit,
+        }
+    }
+    // Command-line values win over the file's.
+    fn apply_file(&mut self, file: FileConfig) -> Result<(), String> {
+        macro_rules! merge {
+            ($field:ident, $value:expr) => {
+                if let Some(v) = $value {
+                    if self.explicit.insert(stringify!($field).to_string()) {
+                        self.$field = v;
+                    }
+                }
+            };
+        }
+        let consensus = file
+            .consensus
+            .kind
+            .map(|k| ConsensusType::from_str(&k, true))
+            .transpose()
+            .map_err(|e| format!("Invalid consensus kind: {}", e))?;
+        let privacy = file
+            .privacy
+            .map(|p| PrivacyLevel::from_str(&p, true))
+            .transpose()
+            .map_err(|e| format!("Invalid privacy level: {}", e))?;
+        let weak_subjectivity = file
+            .consensus
+            .weak_subjectivity
+            .map(|cps| {
+                cps.iter()
+                    .map(|cp| cp.parse::<WeakSubjectivityCheckpoint>())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        let db_backend = file
+            .storage
+            .backend
+            .map(|b| DbBackend::from_str(&b, true))
+            .transpose()
+            .map_err(|e| format!("Invalid storage backend: {}", e))?;
+
+        merge!(data_dir, file.data_dir);
+        merge!(log_level, file.log_level);
+        merge!(max_peers, file.network.max_peers);
+        merge!(max_peer_inbound_bps, file.network.max_peer_inbound_bps);
+        merge!(max_inbound_bps, file.network.max_inbound_bps);
+        merge!(rpc_rate_limit, file.rpc.rate_limit_per_sec);
+        merge!(privacy, privacy);
+        merge!(ring_size, file.ring_size);
+        merge!(chain_id, file.network.chain_id);
+        merge!(port, file.network.port);
+        merge!(bootstrap, file.network.bootstrap.map(Some));
+        merge!(dial, file.network.dial.map(Some));
+        merge!(chain_spec, file.network.chain_spec.map(Some));
+        merge!(consensus, consensus);
+        merge!(difficulty, file.consensus.difficulty);
+        merge!(min_stake, file.consensus.min_stake);
+        merge!(max_validators, file.consensus.max_validators);
+        merge!(treasury_address, file.consensus.treasury_address.map(Some));
+        merge!(treasury_fee_bps, file.consensus.treasury_fee_bps);
+        merge!(treasury_reward_bps, file.consensus.treasury_reward_bps);
+        merge!(dust_threshold, file.consensus.dust_threshold);
+        merge!(dust_inactive_epochs, file.consensus.dust_inactive_epochs);
+        merge!(halt_timeout_secs, file.consensus.halt_timeout_secs);
+        merge!(min_validators, file.consensus.min_validators);
+        merge!(observer, file.consensus.observer);
+        merge!(target_block_size, file.consensus.target_block_size);
+        merge!(weak_subjectivity, weak_subjectivity);
+        merge!(validators_file, file.consensus.validators_file);
+        merge!(
+            validator_address,
+            file.consensus.validator_address.map(Some)
+        );
+        merge!(
+            validator_key_file,
+            file.consensus.validator_key_file.map(Some)
+        );
+        merge!(threshold_shares, file.consensus.threshold_shares);
+        merge!(threshold_remotes, file.consensus.threshold_remotes);
+        merge!(
+            threshold_token_file,
+            file.consensus.threshold_token_file.map(Some)
+        );
+        merge!(db_path, file.storage.path.map(Some));
+        merge!(db_backend, db_backend);
+        merge!(db_sync_interval_ms, file.storage.sync_interval_ms);
+        merge!(cold_db_path, file.storage.cold_path.map(Some));
+        merge!(db_passphrase_file, file.storage.passphrase_file.map(Some));
+        merge!(db_key_command, file.storage.key_command.map(Some));
+        merge!(balance_history, file.storage.balance_history);
+        merge!(chain_memory_budget, file.storage.chain_memory_budget);
+        merge!(rpc_port, file.rpc.port.map(Some));
+        merge!(ws_port, file.rpc.ws_port.map(Some));
+        merge!(grpc_port, file.rpc.grpc_port.map(Some));
+        merge!(rpc_bind, file.rpc.bind);
+        if file.rpc.admin == Some(true) {
+            return Err(
+                "rpc.admin is no longer supported: admin and debug methods are only served on the admin socket (rpc.admin_socket)"
+                    .into(),
+            );
+        }
+        merge!(admin_socket, file.rpc.admin_socket);
+
+        self.mempool_overrides = file.mempool;
+        self.apply_mempool_overrides()?;
+
+        let limits = file.limits;
+        self.limits.max_block_size = limits.max_block_size.unwrap_or(self.limits.max_block_size);
+        self.limits.max_transactions_per_block = limits
+            .max_transactions_per_block
+            .unwrap_or(self.limits.max_transactions_per_block);
+        self.limits.min_block_interval_ms = limits
+            .min_block_interval_ms
+            .unwrap_or(self.limits.min_block_interval_ms);
+        self.limits.max_reorg_depth = limits
+            .max_reorg_depth
+            .unwrap_or(self.limits.max_reorg_depth);
+        self.limits.validate()?;
+        if limits.max_block_size.is_some()
+            || limits.max_transactions_per_block.is_some()
+            || limits.min_block_interval_ms.is_some()
+            || limits.max_reorg_depth.is_some()
+        {
+            self.explicit.insert("limits".into());
+        }
+        if !file.forks.is_empty() {
+            ForkSchedule::new(&file.forks)?;
+            self.forks = file.forks;
+        }
+
+        let pruning = file.pruning;
+        self.pruning.min_blocks_to_keep = pruning
+            .min_blocks_to_keep
+            .unwrap_or(self.pruning.min_blocks_to_keep);
+        self.pruning.snapshot_interval = pruning
+            .snapshot_interval
+            .unwrap_or(self.pruning.snapshot_interval);
+        self.pruning.deltas_per_full = pruning
+            .deltas_per_full
+            .unwrap_or(self.pruning.deltas_per_full);
+
+        let gossip = file.gossip;
+        self.gossip.mesh_n = gossip.mesh_n.unwrap_or(self.gossip.mesh_n);
+        self.gossip.mesh_n_low = gossip.mesh_n_low.unwrap_or(self.gossip.mesh_n_low);
+        self.gossip.mesh_n_high = gossip.mesh_n_high.unwrap_or(self.gossip.mesh_n_high);
+        self.gossip.mesh_outbound_min = gossip
+            .mesh_outbound_min
+            .unwrap_or(self.gossip.mesh_outbound_min);
+        self.gossip.gossip_lazy = gossip.gossip_lazy.unwrap_or(self.gossip.gossip_lazy);
+        self.gossip.heartbeat_interval_ms = gossip
+            .heartbeat_interval_ms
+            .unwrap_or(self.gossip.heartbeat_interval_ms);
+        self.gossip.flood_publish = gossip.flood_publish.unwrap_or(self.gossip.flood_publish);
+        self.gossip.max_transmit_size = gossip
+            .max_transmit_size
+            .unwrap_or(self.gossip.max_transmit_size);
+        self.gossip.topic_max_sizes.extend(gossip.max_message_size);
+        self.gossip.validate()?;
+        Ok(())
+    }
+    pub fn staking_rules(&self) -> StakingRules {
+        StakingRules {
+            min_stake: if self.consensus == ConsensusType::PoS {
+                self.min_stake
+            } else {
+                0
+            },
+            max_validators: self.max_validators,
+        }
+    }
+    pub fn treasury_config(&self) -> TreasuryConfig {
+        TreasuryConfig {
+            address: self.treasury_address.clone().unwrap_or_default(),
+            fee_bps: self.treasury_fee_bps,
+            reward_bps: self.treasury_reward_bps,
+        }
+    }
+    pub fn chaos_config(&self) -> Result<Option<ChaosConfig>, String> {
+        if !self.chaos {
+            return Ok(None);
+        }
+        let probabilities = [self.chaos_drop, self.chaos_duplicate, self.chaos_delay];
+        if probabilities.iter().any(|p| !(0.0..=1.0).contains(p))
+            || probabilities.iter().sum::<f64>() > 1.0
+        {
+            return Err("Chaos probabilities must be within 0..=1 and sum to at most 1".into());
+        }
+        Ok(Some(ChaosConfig {
+            drop_probability: self.chaos_drop,
+            duplicate_probability: self.chaos_duplicate,
+            delay_probability: self.chaos_delay,
+            max_delay_ms: self.chaos_max_delay_ms,
+        }))
+    }
+    // Engine for `--consensus`; PoS and PoA sign with `validator_keys` and
+    // stay observers without them. `load_from` rejects engines left out of
+    // the build, so only a hand-built config can reach the panic below.
+    #[cfg_attr(not(any(feature = "pos", feature = "poa")), allow(unused_variables))]
+    pub fn build_consensus(
+        &self,
+        validator_keys: Option<ValidatorKeys>,
+    ) -> Arc<dyn ConsensusEngine> {
+        match self.consensus {
+            #[cfg(feature = "pow-miner")]
+            ConsensusType::PoW => {
+                println!(" PoW mode - difficulty: {}", self.difficulty);
+                Arc::new(PoWEngine::new(self.difficulty))
+            }
+            #[cfg(feature = "pos")]
+            ConsensusType::PoS => {
+                println!("PoS mode - min stake: {}", self.min_stake);
+                let pos_config = crate::consensus::pos::PoSConfig {
+                    min_stake: self.min_stake,
+                    ..Default::default()
+                };
+                Arc::new(PoSEngine::new(pos_config, validator_keys))
+            }
+            #[cfg(feature = "poa")]
+            ConsensusType::PoA => {
+                println!("PoA mode");
+                let engine = PoAEngine::new(
+                    crate::consensus::poa::PoAConfig {
+                        emergency: self.genesis().emergency_quorum,
+                        ..Default::default()
+                    },
+                    validator_keys.map(|keys| keys.sig_key),
+                );
+                match self.threshold_signer() {
+                    Ok(Some(signer)) => {
+                        println!("PoA threshold signer for {}", signer.public_key_hex());
+                        Arc::new(engine.with_threshold_signer(signer))
+                    }
+                    Ok(None) => Arc::new(engine),
+                    Err(e) => {
+                        println!("PoA: ignoring threshold shares: {}", e);
+                        Arc::new(engine)
+                    }
+                }
+            }
+            ConsensusType::Dev => {
+                println!("Dev mode - sealing a block for every accepted transaction");
+                Arc::new(DevEngine::new(self.validator_address.clone()))
+            }
+            #[allow(unreachable_patterns)]
+            other => panic!("{} is not compiled into this build", other),
+        }
+    }
+    // FROST shares of a PoA authority key (`keys split`): the ones held by
+    // this node plus remote holders running `keys serve-share`. Together they
+    // must reach the group threshold.
+    pub fn threshold_signer(&self) -> Result<Option<ThresholdSigner>, String> {
+        if self.threshold_shares.is_empty() {
+            return match self.threshold_remotes.is_empty() {
+                true => Ok(None),
+                false => Err("threshold_remotes needs at least one local share".into()),
+            };
+        }
+        let shares = self
+            .threshold_shares
+            .iter()
+            .map(KeyShare::load)
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.threshold_remotes.is_empty() {
+            return ThresholdSigner::local(shares).map(Some);
+        }
+        let token = load_token(
+            self.threshold_token_file
+                .as_deref()
+                .ok_or("threshold_remotes needs threshold_token_file")?,
+        )?;
+        let remotes = self
+            .threshold_remotes
+            .iter()
+            .map(|remote| {
+                let (id, addr) = remote
+                    .split_once('@')
+                    .ok_or_else(|| format!("Expected ID@HOST:PORT, got {}", remote))?;
+                let id = id
+                    .parse()
+                    .map_err(|_| format!("Invalid share identifier in {}", remote))?;
+                Ok(RemoteParticipant::new(id, addr.to_string(), token.clone()))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        ThresholdSigner::with_remotes(shares, remotes).map(Some)
+    }
+    pub fn open_storage(&self) -> std::io::Result<Storage> {
+        let hot = match self.db_backend {
+            DbBackend::Sled => Storage::open_sled(&self.db_path(), self.db_sync_interval_ms)?,
+            DbBackend::Memory => Storage::memory(),
+        };
+        let secret = self.encryption_secret()?;
+        let hot = Self::migrate_storage(Self::encrypt_storage(hot, secret.as_ref())?)?;
+        match &self.cold_db_path {
+            Some(path) => Ok(Storage::tiered(
+                hot,
+                Self::migrate_storage(Self::encrypt_storage(
+                    Storage::open_sled(path, self.db_sync_interval_ms)?,
+                    secret.as_ref(),
+                )?)?,
+            )),
+            None => Ok(hot),
+        }
+    }
+    // Brings a database written by an older version up to the current key
+    // layout. Each tier is migrated on its own, below the tiering.
+    fn migrate_storage(store: Storage) -> std::io::Result<Storage> {
+        let moved = store.migrate_block_keys()?;
+        if moved > 0 {
+            println!("Moved {} stored blocks to the BLOCK: key prefix", moved);
+        }
+        Ok(store)
+    }
+    // The database key source, also used to seal keystore files. It is
+    // read on first use and reused afterwards.
+    pub fn encryption_secret(&self) -> std::io::Result<Option<EncryptionSecret>> {
+        if let Some(secret) = self.secret.get() {
+            return Ok(secret.clone());
+        }
+        let secret = self.read_encryption_secret()?;
+        Ok(self.secret.get_or_init(|| secret).clone())
+    }
+    fn read_encryption_secret(&self) -> std::io::Result<Option<EncryptionSecret>> {
+        match (&self.db_passphrase_file, &self.db_key_command) {
+            (Some(_), Some(_)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "db_passphrase_file and db_key_command are mutually exclusive",
+            )),
+            (Some(path), None) => {
+                let passphrase = Zeroizing::new(std::fs::read_to_string(path)?);
+                Ok(Some(EncryptionSecret::Passphrase(Zeroizing::new(
+                    passphrase.trim_end_matches(['\r', '\n']).to_string(),
+                ))))
+            }
+            (None, Some(command)) => Ok(Some(EncryptionSecret::Key(Zeroizing::new(
+                storage_key_from_command(command)?,
+            )))),
+            (None, None) => Ok(None),
+        }
+    }
+    fn encrypt_storage(
+        store: Storage,
+        secret: Option<&EncryptionSecret>,
+    ) -> std::io::Result<Storage> {
+        let Some(secret) = secret else {
+            if store.is_encrypted()? {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "database is encrypted; set db_passphrase_file or db_key_command",
+                ));
+            }
+            return Ok(store);
+        };
+        let key = Zeroizing::new(secret.storage_key(&store)?);
+        Storage::encrypted(store, &key)
+    }
+    pub fn keystore(&self) -> std::io::Result<Keystore> {
+        Ok(Keystore::new(self.data_dir().keys_dir()).with_secret(self.encryption_secret()?))
+    }
+    pub fn pruning_manager(&self) -> PruningManager {
+        PruningManager::new(
+            self.pruning.min_blocks_to_keep,
+            self.pruning.snapshot_interval,
+            self.snapshot_dir(),
+        )
+        .with_deltas_per_full(self.pruning.deltas_per_full)
+    }
+    // Assembles the chain the same way the node binary does, so embedders
+    // get identical storage, limits and economics from a `NodeConfig`.
+    pub fn build_blockchain(
+        &self,
+        consensus: Arc<dyn ConsensusEngine>,
+    ) -> Result<Blockchain, String> {
+        if self.target_block_size > self.limits.max_block_size {
+            return Err(format!(
+                "target_block_size {} exceeds max_block_size {}",
+                self.target_block_size, self.limits.max_block_size
+            ));
+        }
+        let mut builder = Blockchain::builder(consensus)
+            .genesis(self.genesis())
+            .mempool_config(self.mempool.clone());
+        // A node that cannot open or decrypt its database must not run
+        // without one, or the blocks it imports are never persisted.
+        let storage = self
+            .open_storage()
+            .map_err(|e| format!("Failed to initialize storage: {}", e))?;
+        builder = builder
+            .storage(storage)
+            .pruning_manager(self.pruning_manager());
+        Ok(builder
+            .build()?
+            .with_halt_timeout(self.halt_timeout_secs)
+            .with_min_validators(self.min_validators)
+            .with_target_block_size(self.target_block_size)
+            .with_weak_subjectivity(self.weak_subjectivity.clone())
+            .with_balance_history(self.balance_history)
+            .with_chain_memory_budget(self.chain_memory_budget)
+            .with_state_root_diagnostics(self.state_root_diagnostics)
+            .with_observer(self.observer))
+    }
+    pub fn data_dir(&self) -> DataDir {
+        DataDir::new(&self.data_dir, self.chain_id)
+    }
+    pub fn db_path(&self) -> String {
+        self.db_path
+            .clone()
+            .unwrap_or_else(|| self.data_dir().db_path().to_string_lossy().to_string())
+    }
+    pub fn snapshot_dir(&self) -> String {
+        self.data_dir().snapshot_dir().to_string_lossy().to_string()
+    }
+    pub fn validator_key_path(&self) -> Option<String> {
+        if self.observer {
+            return None;
+        }
+        if self.validator_key_file.is_some() {
+            return self.validator_key_file.clone();
+        }
+        let default = self.data_dir().validator_key_path();
+        if default.exists() {
+            Some(default.to_string_lossy().to_string())
+        } else {
+            None
+        }
+    }
     pub fn load_validators(&self) -> Vec<String> {
         let path = Path::new(&self.validators_file);
         if !path.exists() {
@@ -105,6 +999,295 @@ impl NodeConfig {
 struct ValidatorsConfig {
     validators: Vec<String>,
 }
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub data_dir: Option<String>,
+    pub log_level: Option<String>,
+    pub privacy: Option<String>,
+    pub ring_size: Option<usize>,
+    pub network: NetworkSection,
+    pub consensus: ConsensusSection,
+    pub storage: StorageSection,
+    pub mempool: MempoolSection,
+    pub limits: LimitsSection,
+    pub forks: Vec<ForkActivation>,
+    pub pruning: PruningSection,
+    pub rpc: RpcSection,
+    pub gossip: GossipSection,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSection {
+    pub chain_id: Option<u64>,
+    pub port: Option<u16>,
+    pub max_peers: Option<usize>,
+    pub max_peer_inbound_bps: Option<u64>,
+    pub max_inbound_bps: Option<u64>,
+    pub bootstrap: Option<String>,
+    pub dial: Option<String>,
+    pub chain_spec: Option<String>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsensusSection {
+    pub kind: Option<String>,
+    pub difficulty: Option<usize>,
+    pub min_stake: Option<u64>,
+    pub max_validators: Option<usize>,
+    pub treasury_address: Option<String>,
+    pub treasury_fee_bps: Option<u64>,
+    pub treasury_reward_bps: Option<u64>,
+    pub dust_threshold: Option<u64>,
+    pub dust_inactive_epochs: Option<u64>,
+    pub halt_timeout_secs: Option<u64>,
+    pub min_validators: Option<usize>,
+    pub target_block_size: Option<usize>,
+    pub validators_file: Option<String>,
+    pub validator_address: Option<String>,
+    #[serde(alias = "validator_key")]
+    pub validator_key_file: Option<String>,
+    pub observer: Option<bool>,
+    pub threshold_shares: Option<Vec<String>>,
+    pub threshold_remotes: Option<Vec<String>>,
+    pub threshold_token_file: Option<String>,
+    pub weak_subjectivity: Option<Vec<String>>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSection {
+    pub path: Option<String>,
+    pub backend: Option<String>,
+    pub sync_interval_ms: Option<u64>,
+    pub cold_path: Option<String>,
+    pub passphrase_file: Option<String>,
+    pub key_command: Option<String>,
+    pub balance_history: Option<bool>,
+    pub chain_memory_budget: Option<usize>,
+}
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolSection {
+    pub max_size: Option<usize>,
+    pub max_per_sender: Option<usize>,
+    pub min_fee: Option<u64>,
+    pub tx_ttl_secs: Option<u64>,
+    pub rbf_bump_percent: Option<u64>,
+    pub rebroadcast_after_blocks: Option<u64>,
+    pub rebroadcast_limit: Option<usize>,
+    pub priority_senders: Option<Vec<String>>,
+    pub producer_rules: Option<Vec<ProducerRule>>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSection {
+    pub max_block_size: Option<usize>,
+    pub max_transactions_per_block: Option<usize>,
+    pub min_block_interval_ms: Option<u64>,
+    pub max_reorg_depth: Option<usize>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PruningSection {
+    pub min_blocks_to_keep: Option<u64>,
+    pub snapshot_interval: Option<u64>,
+    pub deltas_per_full: Option<u64>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcSection {
+    pub port: Option<u16>,
+    pub ws_port: Option<u16>,
+    pub grpc_port: Option<u16>,
+    pub bind: Option<IpAddr>,
+    pub rate_limit_per_sec: Option<u32>,
+    // Only still parsed so older config files keep loading; `true` is
+    // rejected.
+    pub admin: Option<bool>,
+    pub admin_socket: Option<bool>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GossipSection {
+    pub mesh_n: Option<usize>,
+    pub mesh_n_low: Option<usize>,
+    pub mesh_n_high: Option<usize>,
+    pub mesh_outbound_min: Option<usize>,
+    pub gossip_lazy: Option<usize>,
+    pub heartbeat_interval_ms: Option<u64>,
+    pub flood_publish: Option<bool>,
+    pub max_transmit_size: Option<usize>,
+    pub max_message_size: HashMap<String, usize>,
+}
+
+pub const DEFAULT_CONFIG_TOML: &str = r#"# Budlum node configuration.
+# Every key is optional; command line flags override values set here.
+# log_level, network.max_peers, [mempool] and rpc.rate_limit_per_sec are
+# re-read on SIGHUP or the `reload` console command.
+
+# Root directory; data lives under <data_dir>/chain-<chain_id>/.
+data_dir = "./data"
+# error | warn | info | debug | trace
+log_level = "info"
+# none | stealth | confidential | full
+privacy = "none"
+ring_size = 11
+
+[network]
+# Taken from chain_spec when one is set.
+# chain_id = 1337
+port = 4001
+max_peers = 50
+# Inbound byte/sec caps per peer and for all peers together (0 = off). Over
+# the global cap only consensus messages are processed.
+max_peer_inbound_bps = 0
+max_inbound_bps = 0
+# bootstrap = "/ip4/1.2.3.4/tcp/4001/p2p/<peer-id>"
+# dial = "/ip4/127.0.0.1/tcp/4002"
+# JSON chain spec of a public network: chain id, forks, consensus rules,
+# bootnodes and DNS seeds.
+# chain_spec = "./budlum-mainnet.json"
+
+[consensus]
+# pow | pos | poa | dev
+kind = "pow"
+difficulty = 2
+# The staking, treasury and dust settings below define the genesis of a
+# chain without a spec; with chain_spec set they may be left out, and values
+# that differ from the spec are an error.
+# min_stake = 1000
+# Active validator cap enforced at epoch boundaries (0 = unlimited).
+# max_validators = 0
+# Share of block fees and epoch rewards (basis points) paid to the treasury.
+# treasury_address = "<hex public key>"
+# treasury_fee_bps = 0
+# treasury_reward_bps = 0
+# Accounts below dust_threshold that never sent a transaction and were not
+# touched for dust_inactive_epochs epochs are pruned from state. Their
+# balances can be restored with a proof (get_dust_proof). 0 = off.
+# dust_threshold = 0
+# dust_inactive_epochs = 0
+# Seconds without a new block before the node enters recovery mode (0 = off).
+halt_timeout_secs = 0
+# Fewest unjailed, unslashed validators the current set may drop to (and it
+# must still hold a finality quorum) before the node raises an alert and
+# stops accepting finality certificates.
+min_validators = 1
+# Soft cap (bytes) on blocks this node produces, at most limits.max_block_size.
+# Larger valid blocks from other producers are still accepted (0 = off).
+target_block_size = 0
+validators_file = "./validators.json"
+# validator_address = "<hex public key>"
+# validator_key_file = "./data/chain-1337/keys/validator.key"
+# Validate and serve the chain without ever signing, mining or admitting
+# transactions. Cannot be combined with a validator key.
+observer = false
+# PoA: FROST shares of the authority key (see `keys split`). Shares held on
+# other hosts are served by `keys serve-share` and listed as "id@host:port";
+# local and remote shares together must reach the group threshold.
+# threshold_shares = ["./shares/share-1.json"]
+# threshold_remotes = ["2@10.0.0.2:4100"]
+# threshold_token_file = "/etc/budlum/threshold.token"
+# Trusted "hash@height" checkpoints that sync and reorgs never revert past.
+# weak_subjectivity = ["<block hash>@1000"]
+
+[storage]
+# Defaults to <data_dir>/chain-<chain_id>/db.
+# path = "./data/chain-1337/db"
+# sled | memory
+backend = "sled"
+# 0 flushes after every write; larger values batch flushes.
+sync_interval_ms = 0
+# Finalized blocks older than the latest snapshot move here; reads fall
+# through transparently. Unset keeps everything in `path`.
+# cold_path = "/mnt/archive/chain-1337/db"
+# Encrypt stored values and keystore secret files with AES-256-GCM. The key
+# comes from a passphrase file or from a command (e.g. a KMS client)
+# printing a 32-byte hex key. Snapshot and export files stay plaintext.
+# passphrase_file = "/etc/budlum/db.pass"
+# key_command = "kms-client decrypt budlum-db-key"
+# Index per-block balance changes for get_balance_at. Costs one entry per
+# changed account per block.
+balance_history = false
+# Soft limit (bytes) on blocks held in memory. Above it, transactions of the
+# oldest blocks are dropped from memory and read back from the database when
+# needed; the last 50 blocks always stay whole (0 = off).
+chain_memory_budget = 0
+
+[mempool]
+# Admission policy of this node. Defaults come from the chain spec's
+# "mempool" section (or the values shown); settings here override them.
+# max_size = 5000
+# max_per_sender = 16
+# min_fee = 1
+# tx_ttl_secs = 3600
+# rbf_bump_percent = 10
+# Re-gossip the highest-fee txs still pending after this many blocks, doubling
+# the wait after every attempt (0 = off).
+# rebroadcast_after_blocks = 5
+# rebroadcast_limit = 16
+# System accounts (oracles, bridges) whose txs skip fee ordering and the
+# per-sender cap, e.g. ["<hex public key>"].
+# priority_senders = []
+# Ordering rules for blocks this node produces; they do not affect other
+# producers' blocks. The first rule matching a tx's sender and/or tx_type
+# moves it to the front ("include") or back ("deprioritize"), e.g.
+# [{ tx_type = "Vote", action = "include" },
+#  { sender = "<hex public key>", action = "deprioritize" }]
+# producer_rules = []
+
+# Chain-wide block limits of a chain without a spec; every node on the
+# network must use the same values. A chain spec's limits replace these, and
+# differing values are an error.
+[limits]
+# max_block_size = 1000000
+# max_transactions_per_block = 5000
+# min_block_interval_ms = 1000
+# max_reorg_depth = 100
+
+# Hard forks, in activation order. Every node on the network must list the
+# same forks; transactions and handshakes carry the resulting fork hash.
+# [[forks]]
+# name = "example"
+# height = 100000
+
+[pruning]
+min_blocks_to_keep = 1000
+snapshot_interval = 100
+# Delta snapshots written between two full snapshots.
+deltas_per_full = 4
+
+[rpc]
+# port = 8545
+# WebSocket push notifications for addresses registered with watch_address.
+# ws_port = 8546
+# Typed gRPC service defined in proto/rpc.proto.
+# grpc_port = 50051
+# Listen address of the servers above; use 0.0.0.0 to accept remote clients.
+bind = "127.0.0.1"
+rate_limit_per_sec = 100
+# Serve the admin API, the only place admin_* and debug_* methods are
+# available, on <data-dir>/chain-<id>/admin.sock. Requests need the token in
+# admin.token next to it and are logged to admin_audit.log.
+admin_socket = false
+
+# Gossipsub mesh tuning. Blocks and transactions are only relayed after they
+# pass local validation.
+[gossip]
+mesh_n = 6
+mesh_n_low = 5
+mesh_n_high = 12
+mesh_outbound_min = 2
+gossip_lazy = 6
+heartbeat_interval_ms = 10000
+flood_publish = true
+max_transmit_size = 10485760
+
+# Per-topic message size caps in bytes (default: max_transmit_size).
+[gossip.max_message_size]
+# transactions = 102400
+"#;
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,4 +1295,357 @@ mod tests {
     fn test_consensus_type_parsing() {
         assert_eq!(ConsensusType::PoW as u8, 0);
     }
+    #[test]
+    fn test_data_dir_layout_and_overrides() {
+        let config = NodeConfig::parse_from([
+            "budlum-core",
+            "--data-dir",
+            "/srv/budlum",
+            "--chain-id",
+            "7",
+        ]);
+        assert_eq!(config.db_path(), "/srv/budlum/chain-7/db");
+        assert_eq!(config.snapshot_dir(), "/srv/budlum/chain-7/snapshots");
+        assert_eq!(config.validator_key_path(), None);
+
+        let config = NodeConfig::parse_from(["budlum-core", "--db-path", "/tmp/custom.db"]);
+        assert_eq!(config.db_path(), "/tmp/custom.db");
+
+        let config = NodeConfig::parse_from(["budlum-core", "--validator-key", "/keys/v.key"]);
+        assert_eq!(config.validator_key_path().as_deref(), Some("/keys/v.key"));
+    }
+    #[test]
+    fn test_snapshot_subcommand_parsing() {
+        let config = NodeConfig::parse_from([
+            "budlum-core",
+            "snapshot",
+            "export",
+            "--height",
+            "100",
+            "--out",
+            "snap.bin",
+        ]);
+        match config.command {
+            Some(Command::Snapshot(SnapshotCommand::Export { height, out })) => {
+                assert_eq!(height, Some(100));
+                assert_eq!(out, "snap.bin");
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+    #[test]
+    fn test_keys_subcommand_parsing() {
+        #[cfg(feature = "pq-crypto")]
+        {
+            let config =
+                NodeConfig::parse_from(["budlum-core", "keys", "generate", "--type", "dilithium"]);
+            match config.command {
+                Some(Command::Keys(KeysCommand::Generate { key_type, force })) => {
+                    assert_eq!(key_type, KeyType::Dilithium);
+                    assert!(!force);
+                }
+                other => panic!("unexpected command: {:?}", other),
+            }
+        }
+        let config = NodeConfig::parse_from(["budlum-core", "keys", "show"]);
+        assert!(matches!(
+            config.command,
+            Some(Command::Keys(KeysCommand::Show { key_type: None }))
+        ));
+        let config = NodeConfig::parse_from([
+            "budlum-core",
+            "keys",
+            "split",
+            "--threshold",
+            "2",
+            "--shares",
+            "3",
+            "--out-dir",
+            "shares",
+        ]);
+        assert!(matches!(
+            config.command,
+            Some(Command::Keys(KeysCommand::Split {
+                threshold: 2,
+                shares: 3,
+                ..
+            }))
+        ));
+        let config = NodeConfig::parse_from([
+            "budlum-core",
+            "--threshold-share",
+            "a.json",
+            "--threshold-share",
+            "b.json",
+        ]);
+        assert_eq!(config.threshold_shares, vec!["a.json", "b.json"]);
+        assert!(config.threshold_signer().is_err());
+        let config =
+            NodeConfig::parse_from(["budlum-core", "--threshold-remote", "2@10.0.0.2:4100"]);
+        assert_eq!(config.threshold_remotes, vec!["2@10.0.0.2:4100"]);
+        assert!(config.threshold_signer().is_err());
+    }
+    fn write_config(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "{}_{}.toml",
+            name,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+    #[test]
+    #[cfg(feature = "pow-miner")]
+    fn test_default_config_file_matches_defaults() {
+        let path = write_config("budlum_default_config", DEFAULT_CONFIG_TOML);
+        let from_file = NodeConfig::load_from(["budlum-core", "--config", &path]).unwrap();
+        let defaults = NodeConfig::load_from(["budlum-core"]).unwrap();
+        assert_eq!(from_file.port, defaults.port);
+        assert_eq!(from_file.chain_id, defaults.chain_id);
+        assert_eq!(from_file.consensus, defaults.consensus);
+        assert_eq!(from_file.db_path(), defaults.db_path());
+        assert_eq!(from_file.mempool.max_size, defaults.mempool.max_size);
+        assert_eq!(from_file.limits, defaults.limits);
+        assert_eq!(
+            from_file.pruning.deltas_per_full,
+            defaults.pruning.deltas_per_full
+        );
+        assert_eq!(from_file.gossip, defaults.gossip);
+        assert_eq!(
+            from_file.max_peer_inbound_bps,
+            defaults.max_peer_inbound_bps
+        );
+        assert_eq!(from_file.max_inbound_bps, defaults.max_inbound_bps);
+        assert_eq!(from_file.balance_history, defaults.balance_history);
+        assert_eq!(from_file.admin_socket, defaults.admin_socket);
+        assert_eq!(from_file.rpc_bind, defaults.rpc_bind);
+        assert_eq!(from_file.observer, defaults.observer);
+        assert_eq!(from_file.min_validators, defaults.min_validators);
+        assert_eq!(from_file.dust_threshold, defaults.dust_threshold);
+        assert_eq!(
+            from_file.dust_inactive_epochs,
+            defaults.dust_inactive_epochs
+        );
+        let _ = std::fs::remove_file(path);
+    }
+    #[test]
+    fn test_open_storage_with_key_command() {
+        let runs =
+            std::env::temp_dir().join(format!("budlum_key_command_runs_{}", std::process::id()));
+        let _ = std::fs::remove_file(&runs);
+        let command = format!("echo run >> {}; echo {}", runs.display(), "ab".repeat(32));
+        let config = NodeConfig::load_from([
+            "budlum-core",
+            "--db-backend",
+            "memory",
+            "--db-key-command",
+            &command,
+        ])
+        .unwrap();
+        assert!(config.open_storage().unwrap().is_encrypted().unwrap());
+        assert!(config.keystore().is_ok());
+        // The key command runs once however many files use the key.
+        assert_eq!(std::fs::read_to_string(&runs).unwrap().lines().count(), 1);
+        let _ = std::fs::remove_file(&runs);
+
+        let bad = NodeConfig::load_from([
+            "budlum-core",
+            "--db-backend",
+            "memory",
+            "--db-key-command",
+            "echo not-a-key",
+        ])
+        .unwrap();
+        assert!(bad.open_storage().is_err());
+        let consensus = bad.build_consensus(None);
+        assert!(bad.build_blockchain(consensus).is_err());
+    }
+    #[test]
+    #[cfg(feature = "pos")]
+    fn test_cli_flags_override_config_file() {
+        let path = write_config(
+            "budlum_override_config",
+            "[network]\nport = 5000\nchain_id = 9\n[consensus]\nkind = \"pos\"\n[mempool]\nmin_fee = 7\n",
+        );
+        let config =
+            NodeConfig::load_from(["budlum-core", "--config", &path, "--port", "6000"]).unwrap();
+        assert_eq!(config.port, 6000);
+        assert_eq!(config.chain_id, 9);
+        assert_eq!(config.consensus, ConsensusType::PoS);
+        assert_eq!(config.mempool.min_fee, 7);
+
+        let limits = write_config(
+            "budlum_limits_config",
+            "[limits]\nmax_transactions_per_block = 50\nmin_block_interval_ms = 250\n",
+        );
+        let config = NodeConfig::load_from(["budlum-core", "--config", &limits]).unwrap();
+        assert_eq!(config.limits.max_transactions_per_block, 50);
+        assert_eq!(config.limits.min_block_interval_ms, 250);
+        assert_eq!(
+            config.limits.max_reorg_depth,
+            ChainLimits::default().max_reorg_depth
+        );
+        let _ = std::fs::remove_file(limits);
+
+        let forks = write_config(
+            "budlum_forks_config",
+            "[[forks]]\nname = \"a\"\nheight = 10\n[[forks]]\nname = \"b\"\nheight = 20\n",
+        );
+        let config = NodeConfig::load_from(["budlum-core", "--config", &forks]).unwrap();
+        assert_eq!(config.forks.len(), 2);
+        assert_eq!(config.forks[1].name, "b");
+        let _ = std::fs::remove_file(forks);
+        let dup = write_config(
+            "budlum_dup_forks_config",
+            "[[forks]]\nname = \"a\"\nheight = 10\n[[forks]]\nname = \"a\"\nheight = 20\n",
+        );
+        assert!(NodeConfig::load_from(["budlum-core", "--config", &dup]).is_err());
+        let _ = std::fs::remove_file(dup);
+
+        let rules = write_config(
+            "budlum_producer_rules_config",
+            "[mempool]\nproducer_rules = [{ tx_type = \"Vote\", action = \"include\" }, { sender = \"ab\", action = \"deprioritize\" }]\n",
+        );
+        let config = NodeConfig::load_from(["budlum-core", "--config", &rules]).unwrap();
+        assert_eq!(config.mempool.producer_rules.len(), 2);
+        assert_eq!(
+            config.mempool.producer_rules[0].tx_type,
+            Some(crate::transaction::TransactionType::Vote)
+        );
+        let _ = std::fs::remove_file(rules);
+        let catch_all = write_config(
+            "budlum_catch_all_rule_config",
+            "[mempool]\nproducer_rules = [{ action = \"include\" }]\n",
+        );
+        assert!(NodeConfig::load_from(["budlum-core", "--config", &catch_all]).is_err());
+        let _ = std::fs::remove_file(catch_all);
+
+        let gossip = write_config(
+            "budlum_gossip_config",
+            "[gossip]\nmesh_n = 8\nflood_publish = false\n[gossip.max_message_size]\ntransactions = 2048\n",
+        );
+        let config = NodeConfig::load_from(["budlum-core", "--config", &gossip]).unwrap();
+        assert_eq!(config.gossip.mesh_n, 8);
+        assert!(!config.gossip.flood_publish);
+        assert_eq!(config.gossip.max_message_size("transactions"), 2048);
+        let _ = std::fs::remove_file(gossip);
+        let bad_mesh = write_config("budlum_bad_mesh_config", "[gossip]\nmesh_n = 2\n");
+        assert!(NodeConfig::load_from(["budlum-core", "--config", &bad_mesh]).is_err());
+        let _ = std::fs::remove_file(bad_mesh);
+
+        let public_admin = write_config("budlum_rpc_admin_config", "[rpc]\nadmin = true\n");
+        assert!(NodeConfig::load_from(["budlum-core", "--config", &public_admin]).is_err());
+        let _ = std::fs::remove_file(public_admin);
+
+        let bad = write_config("budlum_bad_config", "[network]\nprot = 1\n");
+        assert!(NodeConfig::load_from(["budlum-core", "--config", &bad]).is_err());
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(bad);
+    }
+    #[test]
+    fn test_runtime_config_reads_reloadable_settings() {
+        let path = write_config(
+            "budlum_runtime_config",
+            "log_level = \"debug\"\n[network]\nmax_peers = 8\n[mempool]\nmax_size = 42\n[rpc]\nrate_limit_per_sec = 5\n",
+        );
+        let runtime = NodeConfig::load_from(["budlum-core", "--config", &path])
+            .unwrap()
+            .runtime_config();
+        assert_eq!(runtime.log_level, "debug");
+        assert_eq!(runtime.max_peers, 8);
+        assert_eq!(runtime.mempool.max_size, 42);
+        assert_eq!(runtime.rpc_rate_limit, 5);
+        let _ = std::fs::remove_file(path);
+    }
+    #[test]
+    fn test_observer_rejects_validator_keys() {
+        let config = NodeConfig::load_from(["budlum-core", "--observer"]).unwrap();
+        assert!(config.observer);
+        assert_eq!(config.validator_key_path(), None);
+        assert!(NodeConfig::load_from([
+            "budlum-core",
+            "--observer",
+            "--validator-key",
+            "validator.key"
+        ])
+        .is_err());
+    }
+    #[test]
+    #[cfg(feature = "pow-miner")]
+    fn test_chain_spec_sets_chain_and_bootnodes() {
+        let peer = libp2p::PeerId::random();
+        let spec = write_config(
+            "budlum_chain_spec",
+            &format!(
+                r#"{{"chain_id": 77, "forks": [{{"name": "a", "height": 10}}], "staking": {{"min_stake": 500, "max_validators": 3}}, "treasury": {{"address": "t", "fee_bps": 100, "reward_bps": 0}}, "limits": {{"max_block_size": 500000, "max_transactions_per_block": 100, "min_block_interval_ms": 1000, "max_reorg_depth": 100}}, "mempool": {{"min_fee": 5, "max_size": 100}}, "bootnodes": ["/ip4/10.0.0.1/tcp/4001/p2p/{}"]}}"#,
+                peer
+            ),
+        );
+        let config = NodeConfig::load_from(["budlum-core", "--chain-spec", &spec]).unwrap();
+        assert_eq!(config.chain_id, 77);
+        assert_eq!(config.forks.len(), 1);
+        assert_eq!(config.bootnodes.len(), 1);
+        assert_eq!(config.min_stake, 500);
+        assert_eq!(config.genesis().staking.max_validators, 3);
+        assert_eq!(config.treasury_config().fee_bps, 100);
+        assert_eq!(config.limits.max_transactions_per_block, 100);
+        assert_eq!(config.mempool.min_fee, 5);
+        assert_eq!(config.mempool.max_size, 100);
+        let local = write_config("budlum_spec_mempool_config", "[mempool]\nmax_size = 42\n");
+        let config =
+            NodeConfig::load_from(["budlum-core", "--config", &local, "--chain-spec", &spec])
+                .unwrap();
+        assert_eq!(config.mempool.min_fee, 5);
+        assert_eq!(config.mempool.max_size, 42);
+        let _ = std::fs::remove_file(local);
+        let conflicting = write_config(
+            "budlum_spec_limits_config",
+            "[limits]\nmax_transactions_per_block = 50\n",
+        );
+        assert!(NodeConfig::load_from([
+            "budlum-core",
+            "--config",
+            &conflicting,
+            "--chain-spec",
+            &spec
+        ])
+        .unwrap_err()
+        .contains("limits"));
+        let _ = std::fs::remove_file(conflicting);
+        let default = write_config("budlum_spec_default_config", DEFAULT_CONFIG_TOML);
+        assert!(NodeConfig::load_from([
+            "budlum-core",
+            "--config",
+            &default,
+            "--chain-spec",
+            &spec
+        ])
+        .is_ok());
+        let _ = std::fs::remove_file(default);
+        assert!(
+            NodeConfig::load_from(["budlum-core", "--chain-spec", &spec, "--chain-id", "78"])
+                .is_err()
+        );
+        assert!(NodeConfig::load_from([
+            "budlum-core",
+            "--chain-spec",
+            &spec,
+            "--min-stake",
+            "500"
+        ])
+        .is_ok());
+        assert!(NodeConfig::load_from([
+            "budlum-core",
+            "--chain-spec",
+            &spec,
+            "--min-stake",
+            "600"
+        ])
+        .unwrap_err()
+        .contains("min_stake"));
+        let _ = std::fs::remove_file(spec);
+    }
 }
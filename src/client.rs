@@ -0,0 +1,298 @@
+use crate::blockchain::TxConfirmation;
+use crate::chain_config::ForkId;
+use crate::crypto::KeyPair;
+use crate::json::{BlockJson, ReceiptJson, TransactionJson};
+use crate::rpc::{FeeEstimate, RpcError, RpcHandler, RpcRequest, RpcResponse};
+use crate::transaction::{Transaction, TransactionType};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// Client side of the JSON-RPC API for applications that build, sign and
+// submit transactions. Nonce, fee, chain id and fork hash are filled in from
+// the node, and signing goes through `Transaction::sign`, so callers never
+// reimplement the signing-hash rules.
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ClientError {
+    Transport(String),
+    Rpc(RpcError),
+    Decode(String),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Transport(s) => write!(f, "Transport error: {}", s),
+            ClientError::Rpc(e) => write!(f, "RPC error {}: {}", e.code, e.message),
+            ClientError::Decode(s) => write!(f, "Decode error: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+pub trait Transport {
+    fn call(&self, method: &str, params: Value) -> Result<Value, ClientError>;
+}
+
+fn into_result(response: RpcResponse) -> Result<Value, ClientError> {
+    match response.error {
+        Some(e) => Err(ClientError::Rpc(e)),
+        None => Ok(response.result.unwrap_or(Value::Null)),
+    }
+}
+
+// One blocking HTTP/1.1 request per call, matching the node's RPC server,
+// which closes the connection after each response.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    addr: String,
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    pub fn new(addr: impl Into<String>) -> Self {
+        HttpTransport {
+            addr: addr.into(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Transport for HttpTransport {
+    fn call(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let body = serde_json::to_vec(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }))
+        .map_err(|e| ClientError::Decode(e.to_string()))?;
+        let transport = |e: std::io::Error| ClientError::Transport(format!("{}: {}", self.addr, e));
+        let mut stream = TcpStream::connect(&self.addr).map_err(transport)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .map_err(transport)?;
+        stream
+            .set_write_timeout(Some(self.timeout))
+            .map_err(transport)?;
+        let header = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.addr,
+            body.len()
+        );
+        stream.write_all(header.as_bytes()).map_err(transport)?;
+        stream.write_all(&body).map_err(transport)?;
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).map_err(transport)?;
+
+        let split = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| ClientError::Transport("malformed HTTP response".into()))?;
+        let status = String::from_utf8_lossy(&raw[..split]);
+        let status = status.lines().next().unwrap_or_default();
+        if !status.contains(" 200 ") {
+            return Err(ClientError::Transport(format!("HTTP {}", status)));
+        }
+        let response: RpcResponse = serde_json::from_slice(&raw[split + 4..])
+            .map_err(|e| ClientError::Decode(e.to_string()))?;
+        into_result(response)
+    }
+}
+
+// Calls a handler in the same process, e.g. for an application embedding the
+// node, or in tests.
+impl Transport for RpcHandler {
+    fn call(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        into_result(self.handle(RpcRequest {
+            id: json!(1),
+            method: method.to_string(),
+            params,
+        }))
+    }
+}
+
+pub struct Client<T: Transport> {
+    transport: T,
+}
+
+impl Client<HttpTransport> {
+    pub fn http(addr: impl Into<String>) -> Self {
+        Client::new(HttpTransport::new(addr))
+    }
+}
+
+impl<T: Transport> Client<T> {
+    pub fn new(transport: T) -> Self {
+        Client { transport }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn call<R: DeserializeOwned>(&self, method: &str, params: Value) -> Result<R, ClientError> {
+        let value = self.transport.call(method, params)?;
+        serde_json::from_value(value).map_err(|e| ClientError::Decode(format!("{}: {}", method, e)))
+    }
+
+    pub fn next_nonce(&self, address: &str) -> Result<u64, ClientError> {
+        self.call("get_next_nonce", json!([address]))
+    }
+
+    pub fn fork_id(&self) -> Result<ForkId, ClientError> {
+        let value: Value = self.call("get_fork_id", Value::Null)?;
+        serde_json::from_value(value["fork_id"].clone())
+            .map_err(|e| ClientError::Decode(format!("get_fork_id: {}", e)))
+    }
+
+    // Nodes that predate the field only accept the legacy layout.
+    pub fn tx_signing_version(&self) -> Result<u8, ClientError> {
+        let value: Value = self.call("get_fork_id", Value::Null)?;
+        match value.get("tx_signing_version") {
+            Some(version) => serde_json::from_value(version.clone())
+                .map_err(|e| ClientError::Decode(format!("get_fork_id: {}", e))),
+            None => Ok(crate::transaction::TX_SIGNING_LEGACY),
+        }
+    }
+
+    pub fn fee_estimate(&self) -> Result<FeeEstimate, ClientError> {
+        self.call("get_fee_estimate", Value::Null)
+    }
+
+    pub fn block(&self, height: u64) -> Result<Option<BlockJson>, ClientError> {
+        self.call("get_block", json!([height]))
+    }
+
+    pub fn receipt(&self, hash: &str) -> Result<Option<ReceiptJson>, ClientError> {
+        self.call("get_transaction_receipt", json!([hash]))
+    }
+
+    // Poll this rather than caching a receipt: the answer changes if a reorg
+    // moves or drops the transaction.
+    pub fn confirmation(&self, hash: &str) -> Result<Option<TxConfirmation>, ClientError> {
+        self.call("get_transaction_confirmation", json!([hash]))
+    }
+
+    pub fn pending_transactions(&self, address: &str) -> Result<Vec<TransactionJson>, ClientError> {
+        self.call("get_pending_transactions", json!([address]))
+    }
+
+    // Fills in what the node knows (next nonce, chain id, fork hash, signing
+    // version and, if
+    // `fee` is 0, the suggested fee for the transaction's fee units) and
+    // signs. `from` is always the key's address.
+    pub fn prepare(&self, key: &KeyPair, mut tx: Transaction) -> Result<Transaction, ClientError> {
+        tx.from = key.public_key_hex();
+        tx.nonce = self.next_nonce(&tx.from)?;
+        let fork_id = self.fork_id()?;
+        tx.chain_id = fork_id.chain_id;
+        tx.fork_hash = fork_id.fork_hash;
+        tx.signing_version = self.tx_signing_version()?;
+        if tx.fee == 0 {
+            tx.fee = self
+                .fee_estimate()?
+                .suggested_fee
+                .saturating_mul(tx.fee_units());
+        }
+        tx.hash = tx.calculate_hash();
+        tx.sign(key);
+        Ok(tx)
+    }
+
+    pub fn submit(&self, tx: &Transaction) -> Result<String, ClientError> {
+        self.call("send_transaction", json!([TransactionJson::from(tx)]))
+    }
+
+    pub fn send(&self, key: &KeyPair, tx: Transaction) -> Result<Transaction, ClientError> {
+        let tx = self.prepare(key, tx)?;
+        self.submit(&tx)?;
+        Ok(tx)
+    }
+
+    pub fn transfer(
+        &self,
+        key: &KeyPair,
+        to: &str,
+        amount: u64,
+    ) -> Result<Transaction, ClientError> {
+        let tx = Transaction::new_with_chain_id(
+            key.public_key_hex(),
+            to.to_string(),
+            amount,
+            0,
+            0,
+            vec![],
+            0,
+            TransactionType::Transfer,
+        );
+        self.send(key, tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::PoWEngine;
+    use crate::rpc::INVALID_PARAMS;
+    use crate::Blockchain;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_prepare_sign_and_submit() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let key = KeyPair::generate().unwrap();
+        bc.init_genesis_account(&key.public_key_hex());
+        let chain = Arc::new(Mutex::new(bc));
+        let client = Client::new(RpcHandler::new(chain.clone()));
+
+        assert_eq!(
+            client.fee_estimate().unwrap(),
+            FeeEstimate {
+                min_fee: 1,
+                suggested_fee: 1,
+            }
+        );
+        let first = client.transfer(&key, "bob", 10).unwrap();
+        let second = client.transfer(&key, "bob", 5).unwrap();
+        assert_eq!((first.nonce, second.nonce), (0, 1));
+        assert_eq!(first.chain_id, 1337);
+        assert_eq!(first.fee, 1);
+        assert!(first.verify());
+        assert_eq!(client.pending_transactions(&first.from).unwrap().len(), 2);
+
+        chain.lock().unwrap().produce_block("miner".into());
+        let receipt = client.receipt(&second.hash).unwrap().unwrap();
+        assert_eq!(receipt.block_height, 1);
+        let confirmation = client.confirmation(&second.hash).unwrap().unwrap();
+        assert_eq!(confirmation.confirmations, 1);
+        assert!(confirmation.is_accepted(1) && !confirmation.finalized);
+        let block = client.block(1).unwrap().unwrap();
+        assert_eq!(block.transactions.len(), 2);
+        assert_eq!(block.transactions[receipt.index as usize].hash, second.hash);
+        assert_eq!(chain.lock().unwrap().state.get_balance("bob"), 15);
+
+        let mut forged = client
+            .prepare(
+                &key,
+                Transaction::new_with_fee(String::new(), "bob".into(), 1, 1, 0, vec![]),
+            )
+            .unwrap();
+        forged.amount = 1_000;
+        match client.submit(&forged) {
+            Err(ClientError::Rpc(e)) => assert_eq!(e.code, INVALID_PARAMS),
+            other => panic!("expected rejection, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::{ConsensusEngine, ConsensusError};
+use crate::account::AccountState;
+use crate::events::BlockRejectReason;
+use crate::Block;
+
+pub const DEV_PRODUCER: &str = "dev";
+
+pub struct DevEngine {
+    pub producer: String,
+}
+impl DevEngine {
+    pub fn new(producer: Option<String>) -> Self {
+        DevEngine {
+            producer: producer.unwrap_or_else(|| DEV_PRODUCER.to_string()),
+        }
+    }
+}
+impl Default for DevEngine {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+impl ConsensusEngine for DevEngine {
+    fn prepare_block(
+        &self,
+        _block: &mut Block,
+        _state: &AccountState,
+    ) -> Result<(), ConsensusError> {
+        Ok(())
+    }
+    fn validate_block(
+        &self,
+        block: &Block,
+        chain: &[Block],
+        _state: &AccountState,
+    ) -> Result<(), ConsensusError> {
+        if let Some(prev_block) = chain.last() {
+            if block.index > 0 && block.previous_hash != prev_block.hash {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadParent,
+                    format!(
+                        "Previous hash mismatch. Expected: {}, Got: {}",
+                        prev_block.hash, block.previous_hash
+                    ),
+                ));
+            }
+        }
+        let calculated_hash = block.calculate_hash();
+        if block.hash != calculated_hash {
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::BadHash,
+                format!(
+                    "Invalid block hash. Calculated: {}, Existing: {}",
+                    calculated_hash, block.hash
+                ),
+            ));
+        }
+        Ok(())
+    }
+    fn full_validate(
+        &self,
+        block: &Block,
+        chain: &[Block],
+        state: &AccountState,
+        _now: u128,
+    ) -> Result<(), ConsensusError> {
+        self.validate_block_size(block, &state.limits)?;
+        self.validate_block(block, chain, state)
+    }
+    fn consensus_type(&self) -> &'static str {
+        "Dev"
+    }
+    fn info(&self) -> String {
+        format!("Dev (instant seal, producer: {})", self.producer)
+    }
+    fn instant_seal_producer(&self) -> Option<String> {
+        Some(self.producer.clone())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::{Blockchain, Transaction};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_dev_engine_seals_on_pending_transaction() {
+        let mut bc = Blockchain::new(Arc::new(DevEngine::default()), None, 1337, None);
+        let kp = KeyPair::generate().unwrap();
+        bc.init_genesis_account(&kp.public_key_hex());
+
+        for nonce in 0..2 {
+            let mut tx = Transaction::new_with_fee(
+                kp.public_key_hex(),
+                "recipient".into(),
+                10,
+                1,
+                nonce,
+                vec![],
+            );
+            tx.sign(&kp);
+            bc.add_transaction(tx).unwrap();
+        }
+
+        assert_eq!(bc.chain.len(), 3);
+        assert!(bc.mempool.is_empty());
+        assert_eq!(bc.state.get_balance("recipient"), 20);
+        assert_eq!(bc.last_block().producer.as_deref(), Some(DEV_PRODUCER));
+
+        let mut follower = Blockchain::new(Arc::new(DevEngine::default()), None, 1337, None);
+        follower.init_genesis_account(&kp.public_key_hex());
+        for block in bc.chain[1..].iter().cloned() {
+            follower.validate_and_add_block(block).unwrap();
+        }
+        assert_eq!(follower.last_block().hash, bc.last_block().hash);
+    }
+}
@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::chain_config::{
-    FINALITY_CHECKPOINT_INTERVAL, FINALITY_QUORUM_DENOMINATOR, FINALITY_QUORUM_NUMERATOR,
+    ForkId, FINALITY_CHECKPOINT_INTERVAL, FINALITY_QUORUM_DENOMINATOR, FINALITY_QUORUM_NUMERATOR,
 };
+use crate::crypto::{aggregate_bls_signatures, verify_bls_aggregate, BlsKeyPair, KeyPair};
+use crate::signing::{signing_digest, SigningContext};
+use crate::slashing::SlashingEvidence;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidatorSetSnapshot {
@@ -64,6 +67,8 @@ pub struct Prevote {
     pub checkpoint_hash: String,
     pub voter_id: String,
     pub sig_bls: Vec<u8>,
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,8 +78,14 @@ pub struct Precommit {
     pub checkpoint_hash: String,
     pub voter_id: String,
     pub sig_bls: Vec<u8>,
+    #[serde(default)]
+    pub signature: Vec<u8>,
+    #[serde(default)]
+    pub state_root: String,
 }
 
+// A cert commits to the checkpoint's account state root as well as its hash,
+// so snap-sync clients can check downloaded state against it directly.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinalityCert {
     pub epoch: u64,
@@ -83,27 +94,160 @@ pub struct FinalityCert {
     pub agg_sig_bls: Vec<u8>,
     pub bitmap: Vec<u8>,
     pub set_hash: String,
+    #[serde(default)]
+    pub state_root: String,
+}
+
+// Gossiped votes carry an ed25519 signature by `voter_id` so a node can
+// drop votes from outside the validator set before they reach the
+// aggregator.
+pub fn authenticate_vote(
+    snapshot: &ValidatorSetSnapshot,
+    voter_id: &str,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), String> {
+    if snapshot.find_validator(voter_id).is_none() {
+        return Err(format!("Voter {} is not in the validator set", voter_id));
+    }
+    let public_key =
+        hex::decode(voter_id).map_err(|_| format!("Voter id {} is not a public key", voter_id))?;
+    crate::crypto::verify_signature(message, signature, &public_key)
+        .map_err(|e| format!("Invalid vote signature from {}: {}", voter_id, e))
+}
+
+// Evidence built by the aggregator carries the two vote signatures; both must
+// verify under the same vote kind for their respective checkpoint hashes.
+pub fn verify_double_vote(evidence: &SlashingEvidence, fork: &ForkId) -> Result<(), String> {
+    evidence.verify_double_vote()?;
+    let (Some(hash1), Some(hash2), Some(sig1), Some(sig2)) = (
+        &evidence.checkpoint_hash_1,
+        &evidence.checkpoint_hash_2,
+        &evidence.signature_1,
+        &evidence.signature_2,
+    ) else {
+        return Err("Incomplete double vote evidence".into());
+    };
+    let public_key =
+        hex::decode(&evidence.validator).map_err(|e| format!("Invalid validator pubkey: {}", e))?;
+    let signed = |message: [u8; 32], sig: &[u8]| {
+        crate::crypto::verify_signature(&message, sig, &public_key).is_ok()
+    };
+    let prevote = |hash: &str| {
+        Prevote {
+            epoch: evidence.epoch,
+            checkpoint_height: evidence.height,
+            checkpoint_hash: hash.to_string(),
+            voter_id: evidence.validator.clone(),
+            sig_bls: Vec::new(),
+            signature: Vec::new(),
+        }
+        .signing_message(fork)
+    };
+    let precommit = |hash: &str, state_root: &Option<String>| {
+        Precommit {
+            epoch: evidence.epoch,
+            checkpoint_height: evidence.height,
+            checkpoint_hash: hash.to_string(),
+            voter_id: evidence.validator.clone(),
+            sig_bls: Vec::new(),
+            signature: Vec::new(),
+            state_root: state_root.clone().unwrap_or_default(),
+        }
+        .signing_message(fork)
+    };
+    if (signed(prevote(hash1), sig1) && signed(prevote(hash2), sig2))
+        || (signed(precommit(hash1, &evidence.state_root_1), sig1)
+            && signed(precommit(hash2, &evidence.state_root_2), sig2))
+    {
+        Ok(())
+    } else {
+        Err("Double vote signatures do not verify".into())
+    }
 }
 
 impl Prevote {
-    pub fn signing_message(&self) -> Vec<u8> {
+    pub fn sign(&mut self, key: &KeyPair, fork: &ForkId) {
+        self.voter_id = key.public_key_hex();
+        self.signature = key.sign(&self.signing_message(fork)).to_vec();
+    }
+
+    pub fn authenticate(
+        &self,
+        snapshot: &ValidatorSetSnapshot,
+        fork: &ForkId,
+    ) -> Result<(), String> {
+        authenticate_vote(
+            snapshot,
+            &self.voter_id,
+            &self.signing_message(fork),
+            &self.signature,
+        )
+    }
+
+    pub fn signing_message(&self, fork: &ForkId) -> [u8; 32] {
         let mut msg = Vec::new();
-        msg.extend_from_slice(b"BUDLUM_PREVOTE");
         msg.extend_from_slice(&self.epoch.to_le_bytes());
         msg.extend_from_slice(&self.checkpoint_height.to_le_bytes());
         msg.extend_from_slice(self.checkpoint_hash.as_bytes());
-        msg
+        signing_digest(SigningContext::Prevote, fork, &msg)
     }
 }
 
 impl Precommit {
-    pub fn signing_message(&self) -> Vec<u8> {
+    pub fn sign(&mut self, key: &KeyPair, fork: &ForkId) {
+        self.voter_id = key.public_key_hex();
+        self.signature = key.sign(&self.signing_message(fork)).to_vec();
+    }
+
+    pub fn authenticate(
+        &self,
+        snapshot: &ValidatorSetSnapshot,
+        fork: &ForkId,
+    ) -> Result<(), String> {
+        authenticate_vote(
+            snapshot,
+            &self.voter_id,
+            &self.signing_message(fork),
+            &self.signature,
+        )
+    }
+
+    pub fn signing_message(&self, fork: &ForkId) -> [u8; 32] {
         let mut msg = Vec::new();
-        msg.extend_from_slice(b"BUDLUM_PRECOMMIT");
         msg.extend_from_slice(&self.epoch.to_le_bytes());
         msg.extend_from_slice(&self.checkpoint_height.to_le_bytes());
         msg.extend_from_slice(self.checkpoint_hash.as_bytes());
-        msg
+        msg.extend_from_slice(self.state_root.as_bytes());
+        signing_digest(SigningContext::Precommit, fork, &msg)
+    }
+
+    fn bls_message(&self, chain_id: u64) -> [u8; 32] {
+        finality_signing_message(
+            chain_id,
+            self.epoch,
+            self.checkpoint_height,
+            &self.checkpoint_hash,
+            &self.state_root,
+        )
+    }
+
+    pub fn sign_bls(&mut self, key: &BlsKeyPair, chain_id: u64) {
+        self.sig_bls = key.sign(&self.bls_message(chain_id)).to_vec();
+    }
+
+    // The BLS share that goes into the certificate, checked against the
+    // voter's registered key.
+    pub fn verify_bls(&self, snapshot: &ValidatorSetSnapshot, chain_id: u64) -> Result<(), String> {
+        let voter = snapshot
+            .find_validator(&self.voter_id)
+            .ok_or("Voter not in validator set")?;
+        verify_bls_aggregate(
+            [&voter.bls_public_key[..]],
+            &self.bls_message(chain_id),
+            &self.sig_bls,
+        )
+        .map_err(|e| format!("Precommit BLS signature from {}: {}", self.voter_id, e))
     }
 }
 
@@ -111,25 +255,56 @@ pub fn is_checkpoint_height(height: u64) -> bool {
     height > 0 && height % FINALITY_CHECKPOINT_INTERVAL == 0
 }
 
-pub fn pop_signing_message(address: &str, bls_pk: &[u8]) -> Vec<u8> {
+// Finality keys and certificates outlive forks, and light clients of a
+// remote chain do not track its fork schedule, so BLS messages bind only the
+// chain id.
+fn bls_fork(chain_id: u64) -> ForkId {
+    ForkId {
+        chain_id,
+        fork_hash: String::new(),
+    }
+}
+
+pub fn pop_signing_message(chain_id: u64, address: &str, bls_pk: &[u8]) -> [u8; 32] {
     let mut msg = Vec::new();
-    msg.extend_from_slice(b"BUDLUM_BLS_POP");
+    msg.extend_from_slice(&(address.len() as u64).to_le_bytes());
     msg.extend_from_slice(address.as_bytes());
     msg.extend_from_slice(bls_pk);
-    msg
+    signing_digest(SigningContext::BlsPop, &bls_fork(chain_id), &msg)
 }
 
-pub fn verify_pop(entry: &ValidatorEntry) -> bool {
-    if entry.bls_public_key.is_empty() || entry.pop_signature.is_empty() {
-        return false;
+// Proves that `address` holds the secret of its BLS key, so nobody can
+// register a key derived from others' keys to forge an aggregate.
+pub fn sign_pop(key: &BlsKeyPair, chain_id: u64, address: &str) -> Vec<u8> {
+    key.sign(&pop_signing_message(
+        chain_id,
+        address,
+        &key.public_key_bytes(),
+    ))
+    .to_vec()
+}
+
+// What precommits sign with BLS and a certificate aggregates.
+pub fn finality_signing_message(
+    chain_id: u64,
+    epoch: u64,
+    checkpoint_height: u64,
+    checkpoint_hash: &str,
+    state_root: &str,
+) -> [u8; 32] {
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&epoch.to_le_bytes());
+    msg.extend_from_slice(&checkpoint_height.to_le_bytes());
+    for field in [checkpoint_hash, state_root] {
+        msg.extend_from_slice(&(field.len() as u64).to_le_bytes());
+        msg.extend_from_slice(field.as_bytes());
     }
-    let msg = pop_signing_message(&entry.address, &entry.bls_public_key);
-    let _msg_hash = {
-        let mut hasher = Sha3_256::new();
-        hasher.update(&msg);
-        hasher.finalize()
-    };
-    !entry.pop_signature.is_empty()
+    signing_digest(SigningContext::Finality, &bls_fork(chain_id), &msg)
+}
+
+pub fn verify_pop(entry: &ValidatorEntry, chain_id: u64) -> bool {
+    let message = pop_signing_message(chain_id, &entry.address, &entry.bls_public_key);
+    verify_bls_aggregate([&entry.bls_public_key[..]], &message, &entry.pop_signature).is_ok()
 }
 
 #[derive(Debug)]
@@ -137,11 +312,17 @@ pub struct FinalityAggregator {
     pub epoch: u64,
     pub checkpoint_height: u64,
     pub checkpoint_hash: String,
+    pub checkpoint_state_root: String,
     pub prevotes: HashMap<String, Prevote>,
     pub precommits: HashMap<String, Precommit>,
     pub validator_snapshot: Option<ValidatorSetSnapshot>,
     pub prevote_quorum_reached: bool,
     pub precommit_quorum_reached: bool,
+    // First vote seen from each voter for this epoch and height, whatever
+    // hash it named, so a second vote for another hash can be proven.
+    first_prevotes: HashMap<String, Prevote>,
+    first_precommits: HashMap<String, Precommit>,
+    pub double_votes: Vec<SlashingEvidence>,
 }
 
 impl FinalityAggregator {
@@ -150,12 +331,56 @@ impl FinalityAggregator {
             epoch,
             checkpoint_height,
             checkpoint_hash,
+            checkpoint_state_root: String::new(),
             prevotes: HashMap::new(),
             precommits: HashMap::new(),
             validator_snapshot: None,
             prevote_quorum_reached: false,
             precommit_quorum_reached: false,
+            first_prevotes: HashMap::new(),
+            first_precommits: HashMap::new(),
+            double_votes: Vec::new(),
+        }
+    }
+
+    pub fn with_state_root(mut self, state_root: String) -> Self {
+        self.checkpoint_state_root = state_root;
+        self
+    }
+
+    pub fn take_double_votes(&mut self) -> Vec<SlashingEvidence> {
+        std::mem::take(&mut self.double_votes)
+    }
+
+    fn record_double_vote(
+        &mut self,
+        voter_id: &str,
+        first: (&str, &[u8]),
+        second: (&str, &[u8]),
+        state_roots: Option<(String, String)>,
+    ) {
+        if self.double_votes.iter().any(|e| e.validator == voter_id) {
+            return;
         }
+        println!(
+            "DOUBLE-VOTE: {} voted for {} and {} in epoch {}",
+            voter_id, first.0, second.0, self.epoch
+        );
+        let mut evidence = SlashingEvidence::double_vote(
+            voter_id.to_string(),
+            self.epoch,
+            first.0.to_string(),
+            second.0.to_string(),
+            first.1.to_vec(),
+            second.1.to_vec(),
+            String::new(),
+        );
+        evidence.height = self.checkpoint_height;
+        if let Some((root1, root2)) = state_roots {
+            evidence.state_root_1 = Some(root1);
+            evidence.state_root_2 = Some(root2);
+        }
+        self.double_votes.push(evidence);
     }
 
     pub fn set_validator_snapshot(&mut self, snapshot: ValidatorSetSnapshot) {
@@ -166,12 +391,27 @@ impl FinalityAggregator {
         if vote.epoch != self.epoch {
             return Err("Prevote epoch mismatch".into());
         }
-        if vote.checkpoint_hash != self.checkpoint_hash {
-            return Err("Prevote checkpoint hash mismatch".into());
-        }
         if vote.checkpoint_height != self.checkpoint_height {
             return Err("Prevote checkpoint height mismatch".into());
         }
+        match self.first_prevotes.get(&vote.voter_id).cloned() {
+            Some(first) if first.checkpoint_hash != vote.checkpoint_hash => {
+                self.record_double_vote(
+                    &vote.voter_id,
+                    (&first.checkpoint_hash, &first.signature),
+                    (&vote.checkpoint_hash, &vote.signature),
+                    None,
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.first_prevotes
+                    .insert(vote.voter_id.clone(), vote.clone());
+            }
+        }
+        if vote.checkpoint_hash != self.checkpoint_hash {
+            return Err("Prevote checkpoint hash mismatch".into());
+        }
 
         if let Some(ref snapshot) = self.validator_snapshot {
             if snapshot.find_validator(&vote.voter_id).is_none() {
@@ -192,11 +432,29 @@ impl FinalityAggregator {
         if vote.epoch != self.epoch {
             return Err("Precommit epoch mismatch".into());
         }
+        if vote.checkpoint_height != self.checkpoint_height {
+            return Err("Precommit checkpoint height mismatch".into());
+        }
+        match self.first_precommits.get(&vote.voter_id).cloned() {
+            Some(first) if first.checkpoint_hash != vote.checkpoint_hash => {
+                self.record_double_vote(
+                    &vote.voter_id,
+                    (&first.checkpoint_hash, &first.signature),
+                    (&vote.checkpoint_hash, &vote.signature),
+                    Some((first.state_root.clone(), vote.state_root.clone())),
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.first_precommits
+                    .insert(vote.voter_id.clone(), vote.clone());
+            }
+        }
         if vote.checkpoint_hash != self.checkpoint_hash {
             return Err("Precommit checkpoint hash mismatch".into());
         }
-        if vote.checkpoint_height != self.checkpoint_height {
-            return Err("Precommit checkpoint height mismatch".into());
+        if vote.state_root != self.checkpoint_state_root {
+            return Err("Precommit state root mismatch".into());
         }
 
         if !self.prevote_quorum_reached {
@@ -218,15 +476,26 @@ impl FinalityAggregator {
         Ok(())
     }
 
-    fn check_prevote_quorum(&mut self) {
-        if let Some(ref snapshot) = self.validator_snapshot {
-            let voted_stake: u64 = self
-                .prevotes
-                .keys()
+    fn voted_stake<'a>(&self, voters: impl Iterator<Item = &'a String>) -> u64 {
+        self.validator_snapshot.as_ref().map_or(0, |snapshot| {
+            voters
                 .filter_map(|addr| snapshot.find_validator(addr))
                 .map(|v| v.stake)
-                .sum();
-            if voted_stake >= snapshot.quorum_stake() {
+                .sum()
+        })
+    }
+
+    pub fn prevote_stake(&self) -> u64 {
+        self.voted_stake(self.prevotes.keys())
+    }
+
+    pub fn precommit_stake(&self) -> u64 {
+        self.voted_stake(self.precommits.keys())
+    }
+
+    fn check_prevote_quorum(&mut self) {
+        if let Some(ref snapshot) = self.validator_snapshot {
+            if self.prevote_stake() >= snapshot.quorum_stake() {
                 self.prevote_quorum_reached = true;
             }
         }
@@ -234,13 +503,7 @@ impl FinalityAggregator {
 
     fn check_precommit_quorum(&mut self) {
         if let Some(ref snapshot) = self.validator_snapshot {
-            let voted_stake: u64 = self
-                .precommits
-                .keys()
-                .filter_map(|addr| snapshot.find_validator(addr))
-                .map(|v| v.stake)
-                .sum();
-            if voted_stake >= snapshot.quorum_stake() {
+            if self.precommit_stake() >= snapshot.quorum_stake() {
                 self.precommit_quorum_reached = true;
             }
         }
@@ -254,28 +517,42 @@ impl FinalityAggregator {
         let snapshot = self.validator_snapshot.as_ref()?;
 
         let mut bitmap = vec![0u8; (snapshot.validators.len() + 7) / 8];
-        let mut all_sigs: Vec<u8> = Vec::new();
+        let mut shares = Vec::new();
 
         for (addr, precommit) in &self.precommits {
             if let Some(idx) = snapshot.validator_index(addr) {
                 bitmap[idx / 8] |= 1 << (idx % 8);
-                all_sigs.extend_from_slice(&precommit.sig_bls);
+                shares.push(&precommit.sig_bls[..]);
             }
         }
+        let agg_sig_bls = aggregate_bls_signatures(shares).ok()?;
 
         Some(FinalityCert {
             epoch: self.epoch,
             checkpoint_height: self.checkpoint_height,
             checkpoint_hash: self.checkpoint_hash.clone(),
-            agg_sig_bls: all_sigs,
+            agg_sig_bls: agg_sig_bls.to_vec(),
             bitmap,
             set_hash: snapshot.set_hash.clone(),
+            state_root: self.checkpoint_state_root.clone(),
         })
     }
 }
 
 impl FinalityCert {
-    pub fn verify(&self, snapshot: &ValidatorSetSnapshot) -> Result<(), String> {
+    pub fn signing_message(&self, chain_id: u64) -> [u8; 32] {
+        finality_signing_message(
+            chain_id,
+            self.epoch,
+            self.checkpoint_height,
+            &self.checkpoint_hash,
+            &self.state_root,
+        )
+    }
+
+    // `snapshot` must come from a trusted source: this node's state, a
+    // weak subjectivity checkpoint or a light client's tracked set.
+    pub fn verify(&self, snapshot: &ValidatorSetSnapshot, chain_id: u64) -> Result<(), String> {
         if self.set_hash != snapshot.set_hash {
             return Err("Validator set hash mismatch".into());
         }
@@ -308,13 +585,45 @@ impl FinalityCert {
             return Err("No signers in bitmap".into());
         }
 
-        if self.agg_sig_bls.is_empty() {
-            return Err("Empty aggregated BLS signature".into());
+        let mut signers = Vec::with_capacity(signer_count);
+        for (idx, validator) in snapshot.validators.iter().enumerate() {
+            if self.signed_by(idx) {
+                if validator.bls_public_key.is_empty() {
+                    return Err(format!("Signer {} has no BLS key", validator.address));
+                }
+                signers.push(&validator.bls_public_key[..]);
+            }
         }
+        verify_bls_aggregate(signers, &self.signing_message(chain_id), &self.agg_sig_bls)
+            .map_err(|e| format!("Aggregated BLS signature: {}", e))
+    }
 
+    pub fn verify_state_root(&self, state_root: &str) -> Result<(), String> {
+        if self.state_root != state_root {
+            return Err(format!(
+                "Certificate state root {} does not match {} at height {}",
+                self.state_root, state_root, self.checkpoint_height
+            ));
+        }
         Ok(())
     }
 
+    pub fn signed_by(&self, validator_index: usize) -> bool {
+        self.bitmap
+            .get(validator_index / 8)
+            .is_some_and(|byte| byte & (1 << (validator_index % 8)) != 0)
+    }
+
+    pub fn signed_stake(&self, snapshot: &ValidatorSetSnapshot) -> u64 {
+        snapshot
+            .validators
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| self.signed_by(*idx))
+            .map(|(_, v)| v.stake)
+            .sum()
+    }
+
     pub fn signer_count(&self, validator_count: usize) -> usize {
         let mut count = 0;
         for idx in 0..validator_count {
@@ -328,17 +637,221 @@ impl FinalityCert {
     }
 }
 
+pub const FINALITY_PARTICIPATION_EPOCHS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckpointStatus {
+    pub height: u64,
+    pub hash: String,
+    pub epoch: u64,
+    pub signers: usize,
+    pub participating_stake: u64,
+    pub total_stake: u64,
+}
+
+impl CheckpointStatus {
+    // A checkpoint whose votes this node did not see, e.g. one finalized
+    // before a restart or adopted through checkpoint sync.
+    pub fn unobserved(height: u64, hash: String) -> Self {
+        CheckpointStatus {
+            height,
+            hash,
+            epoch: 0,
+            signers: 0,
+            participating_stake: 0,
+            total_stake: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EpochParticipation {
+    pub epoch: u64,
+    pub checkpoint_height: u64,
+    pub total_stake: u64,
+    pub prevote_stake: u64,
+    pub precommit_stake: u64,
+    pub prevote_rate: f64,
+    pub precommit_rate: f64,
+    pub finalized: bool,
+}
+
+impl EpochParticipation {
+    fn record(&mut self, prevote_stake: u64, precommit_stake: u64, total_stake: u64) {
+        self.total_stake = total_stake;
+        self.prevote_stake = self.prevote_stake.max(prevote_stake);
+        self.precommit_stake = self.precommit_stake.max(precommit_stake);
+        let rate = |stake: u64| {
+            if total_stake == 0 {
+                0.0
+            } else {
+                stake as f64 / total_stake as f64
+            }
+        };
+        self.prevote_rate = rate(self.prevote_stake);
+        self.precommit_rate = rate(self.precommit_stake);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FinalityStatus {
+    pub justified: CheckpointStatus,
+    pub finalized: CheckpointStatus,
+    pub epochs: Vec<EpochParticipation>,
+}
+
+// What this node has seen of the finality votes: the newest checkpoint with
+// a prevote quorum (justified), the newest with a certificate (finalized),
+// and how much stake voted in each of the last
+// FINALITY_PARTICIPATION_EPOCHS epochs.
+#[derive(Debug, Clone, Default)]
+pub struct FinalityTracker {
+    justified: Option<CheckpointStatus>,
+    finalized: Option<CheckpointStatus>,
+    epochs: BTreeMap<u64, EpochParticipation>,
+}
+
+impl FinalityTracker {
+    pub fn justified(&self) -> Option<&CheckpointStatus> {
+        self.justified.as_ref()
+    }
+
+    pub fn finalized(&self) -> Option<&CheckpointStatus> {
+        self.finalized.as_ref()
+    }
+
+    pub fn epochs(&self) -> Vec<EpochParticipation> {
+        self.epochs.values().rev().cloned().collect()
+    }
+
+    // A later checkpoint in the same epoch replaces the earlier one's entry.
+    fn epoch_entry(&mut self, epoch: u64, checkpoint_height: u64) -> &mut EpochParticipation {
+        let entry = self.epochs.entry(epoch).or_default();
+        if entry.checkpoint_height < checkpoint_height {
+            *entry = EpochParticipation {
+                epoch,
+                checkpoint_height,
+                ..Default::default()
+            };
+        }
+        while self.epochs.len() > FINALITY_PARTICIPATION_EPOCHS {
+            self.epochs.pop_first();
+        }
+        self.epochs.entry(epoch).or_default()
+    }
+
+    pub fn observe(&mut self, aggregator: &FinalityAggregator) {
+        let Some(snapshot) = aggregator.validator_snapshot.as_ref() else {
+            return;
+        };
+        let prevote_stake = aggregator.prevote_stake();
+        self.epoch_entry(aggregator.epoch, aggregator.checkpoint_height)
+            .record(
+                prevote_stake,
+                aggregator.precommit_stake(),
+                snapshot.total_stake,
+            );
+        let newer = self
+            .justified
+            .as_ref()
+            .is_none_or(|j| j.height < aggregator.checkpoint_height);
+        if aggregator.prevote_quorum_reached && newer {
+            self.justified = Some(CheckpointStatus {
+                height: aggregator.checkpoint_height,
+                hash: aggregator.checkpoint_hash.clone(),
+                epoch: aggregator.epoch,
+                signers: aggregator.prevotes.len(),
+                participating_stake: prevote_stake,
+                total_stake: snapshot.total_stake,
+            });
+        }
+    }
+
+    pub fn on_finalized(&mut self, cert: &FinalityCert, snapshot: &ValidatorSetSnapshot) {
+        let stake = cert.signed_stake(snapshot);
+        let entry = self.epoch_entry(cert.epoch, cert.checkpoint_height);
+        entry.record(0, stake, snapshot.total_stake);
+        entry.finalized = true;
+        let status = CheckpointStatus {
+            height: cert.checkpoint_height,
+            hash: cert.checkpoint_hash.clone(),
+            epoch: cert.epoch,
+            signers: cert.signer_count(snapshot.validators.len()),
+            participating_stake: stake,
+            total_stake: snapshot.total_stake,
+        };
+        if self
+            .justified
+            .as_ref()
+            .is_none_or(|j| j.height <= status.height)
+        {
+            self.justified = Some(status.clone());
+        }
+        self.finalized = Some(status);
+    }
+}
+
+// Deterministic finality keys for validators in tests, named by address.
+#[cfg(test)]
+pub(crate) mod test_keys {
+    use super::*;
+
+    pub fn bls_key(address: &str) -> BlsKeyPair {
+        let seed: [u8; 32] = Sha3_256::digest(address.as_bytes()).into();
+        BlsKeyPair::derive(&KeyPair::from_seed(&seed).unwrap())
+    }
+
+    pub fn entry(address: &str, stake: u64, chain_id: u64) -> ValidatorEntry {
+        let key = bls_key(address);
+        ValidatorEntry {
+            address: address.to_string(),
+            stake,
+            bls_public_key: key.public_key_bytes().to_vec(),
+            pop_signature: sign_pop(&key, chain_id, address),
+        }
+    }
+
+    // Aggregates the signatures of every set member named in the bitmap.
+    pub fn sign_cert(cert: &mut FinalityCert, snapshot: &ValidatorSetSnapshot, chain_id: u64) {
+        let message = cert.signing_message(chain_id);
+        let shares: Vec<[u8; 96]> = snapshot
+            .validators
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| cert.signed_by(*idx))
+            .map(|(_, v)| bls_key(&v.address).sign(&message))
+            .collect();
+        cert.agg_sig_bls = aggregate_bls_signatures(shares.iter().map(|s| &s[..]))
+            .unwrap()
+            .to_vec();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn test_fork() -> ForkId {
+        ForkId {
+            chain_id: 1337,
+            fork_hash: String::new(),
+        }
+    }
+
+    fn bls_key(i: usize) -> BlsKeyPair {
+        BlsKeyPair::derive(&KeyPair::from_seed(&[i as u8 + 1; 32]).unwrap())
+    }
+
     fn make_snapshot(n: usize, stake_each: u64) -> ValidatorSetSnapshot {
         let validators: Vec<ValidatorEntry> = (0..n)
-            .map(|i| ValidatorEntry {
-                address: format!("validator_{}", i),
-                stake: stake_each,
-                bls_public_key: vec![i as u8; 48],
-                pop_signature: vec![i as u8; 96],
+            .map(|i| {
+                let address = format!("validator_{}", i);
+                ValidatorEntry {
+                    pop_signature: sign_pop(&bls_key(i), 1337, &address),
+                    address,
+                    stake: stake_each,
+                    bls_public_key: bls_key(i).public_key_bytes().to_vec(),
+                }
             })
             .collect();
         ValidatorSetSnapshot::new(1, validators)
@@ -364,11 +877,18 @@ mod tests {
 
     #[test]
     fn test_pop_message_deterministic() {
-        let msg1 = pop_signing_message("alice", &[1, 2, 3]);
-        let msg2 = pop_signing_message("alice", &[1, 2, 3]);
+        let msg1 = pop_signing_message(1337, "alice", &[1, 2, 3]);
+        let msg2 = pop_signing_message(1337, "alice", &[1, 2, 3]);
         assert_eq!(msg1, msg2);
-        let msg3 = pop_signing_message("bob", &[1, 2, 3]);
+        let msg3 = pop_signing_message(1337, "bob", &[1, 2, 3]);
         assert_ne!(msg1, msg3);
+
+        let snap = make_snapshot(2, 1000);
+        assert!(verify_pop(&snap.validators[0], 1337));
+        assert!(!verify_pop(&snap.validators[0], 42));
+        let mut stolen = snap.validators[1].clone();
+        stolen.bls_public_key = snap.validators[0].bls_public_key.clone();
+        assert!(!verify_pop(&stolen, 1337));
     }
 
     #[test]
@@ -379,9 +899,25 @@ mod tests {
             checkpoint_hash: "abc".into(),
             voter_id: "v0".into(),
             sig_bls: vec![],
+            signature: vec![],
         };
-        let msg = vote.signing_message();
-        assert!(msg.starts_with(b"BUDLUM_PREVOTE"));
+        let precommit = Precommit {
+            epoch: 1,
+            checkpoint_height: 100,
+            checkpoint_hash: "abc".into(),
+            voter_id: "v0".into(),
+            sig_bls: vec![],
+            signature: vec![],
+            state_root: String::new(),
+        };
+        let fork = test_fork();
+        let msg = vote.signing_message(&fork);
+        assert_ne!(msg, precommit.signing_message(&fork));
+        let other_fork = ForkId {
+            fork_hash: "ab".into(),
+            ..fork
+        };
+        assert_ne!(msg, vote.signing_message(&other_fork));
     }
 
     #[test]
@@ -397,6 +933,7 @@ mod tests {
                 checkpoint_hash: "cp_hash".into(),
                 voter_id: format!("validator_{}", i),
                 sig_bls: vec![i as u8; 48],
+                signature: vec![],
             };
             agg.add_prevote(vote).unwrap();
         }
@@ -415,6 +952,7 @@ mod tests {
             checkpoint_hash: "cp_hash".into(),
             voter_id: "validator_0".into(),
             sig_bls: vec![0; 48],
+            signature: vec![],
         };
         agg.add_prevote(vote.clone()).unwrap();
         assert!(agg.add_prevote(vote).is_err());
@@ -432,6 +970,7 @@ mod tests {
             checkpoint_hash: "cp_hash".into(),
             voter_id: "validator_0".into(),
             sig_bls: vec![0; 48],
+            signature: vec![],
         };
         assert!(agg.add_prevote(vote).is_err());
     }
@@ -448,6 +987,8 @@ mod tests {
             checkpoint_hash: "cp_hash".into(),
             voter_id: "validator_0".into(),
             sig_bls: vec![0; 48],
+            signature: vec![],
+            state_root: String::new(),
         };
         assert!(agg.add_precommit(pc).is_err());
     }
@@ -465,19 +1006,24 @@ mod tests {
                 checkpoint_hash: "cp_hash".into(),
                 voter_id: format!("validator_{}", i),
                 sig_bls: vec![i as u8; 48],
+                signature: vec![],
             };
             agg.add_prevote(vote).unwrap();
         }
         assert!(agg.prevote_quorum_reached);
 
         for i in 0..3 {
-            let vote = Precommit {
+            let mut vote = Precommit {
                 epoch: 1,
                 checkpoint_height: 100,
                 checkpoint_hash: "cp_hash".into(),
                 voter_id: format!("validator_{}", i),
-                sig_bls: vec![i as u8; 48],
+                sig_bls: vec![],
+                signature: vec![],
+                state_root: String::new(),
             };
+            vote.sign_bls(&bls_key(i), 1337);
+            vote.verify_bls(&snap, 1337).unwrap();
             agg.add_precommit(vote).unwrap();
         }
         assert!(agg.precommit_quorum_reached);
@@ -489,7 +1035,119 @@ mod tests {
         assert_eq!(cert.set_hash, snap.set_hash);
         assert_eq!(cert.signer_count(4), 3);
 
-        assert!(cert.verify(&snap).is_ok());
+        cert.verify(&snap, 1337).unwrap();
+        assert!(cert.verify(&snap, 42).is_err());
+
+        // Neither a forged aggregate nor a bitmap naming a signer whose share
+        // is missing passes.
+        let mut forged = cert.clone();
+        forged.agg_sig_bls = vec![1; 96];
+        assert!(forged.verify(&snap, 1337).is_err());
+        let mut inflated = cert.clone();
+        inflated.bitmap = vec![0b0000_1111];
+        assert!(inflated
+            .verify(&snap, 1337)
+            .unwrap_err()
+            .contains("BLS signature"));
+        let mut tampered = cert;
+        tampered.state_root = "other".into();
+        assert!(tampered.verify(&snap, 1337).is_err());
+    }
+
+    #[test]
+    fn test_vote_authentication() {
+        let key = KeyPair::generate().unwrap();
+        let outsider = KeyPair::generate().unwrap();
+        let snap = ValidatorSetSnapshot::new(
+            1,
+            vec![ValidatorEntry {
+                address: key.public_key_hex(),
+                stake: 1000,
+                bls_public_key: vec![],
+                pop_signature: vec![],
+            }],
+        );
+        let mut vote = Prevote {
+            epoch: 1,
+            checkpoint_height: 100,
+            checkpoint_hash: "cp_hash".into(),
+            voter_id: String::new(),
+            sig_bls: vec![],
+            signature: vec![],
+        };
+        let fork = test_fork();
+        vote.sign(&key, &fork);
+        assert!(vote.authenticate(&snap, &fork).is_ok());
+        let other_chain = ForkId {
+            chain_id: 42,
+            ..fork.clone()
+        };
+        assert!(vote.authenticate(&snap, &other_chain).is_err());
+
+        let mut tampered = vote.clone();
+        tampered.checkpoint_hash = "other".into();
+        assert!(tampered.authenticate(&snap, &fork).is_err());
+
+        let mut unknown = vote.clone();
+        unknown.sign(&outsider, &fork);
+        assert!(unknown
+            .authenticate(&snap, &fork)
+            .unwrap_err()
+            .contains("not in the validator set"));
+
+        let mut precommit = Precommit {
+            epoch: 1,
+            checkpoint_height: 100,
+            checkpoint_hash: "cp_hash".into(),
+            voter_id: key.public_key_hex(),
+            sig_bls: vec![],
+            signature: vec![],
+            state_root: String::new(),
+        };
+        assert!(precommit.authenticate(&snap, &fork).is_err());
+        precommit.sign(&key, &fork);
+        assert!(precommit.authenticate(&snap, &fork).is_ok());
+    }
+
+    #[test]
+    fn test_aggregator_detects_double_vote() {
+        let key = KeyPair::generate().unwrap();
+        let snap = ValidatorSetSnapshot::new(
+            1,
+            vec![ValidatorEntry {
+                address: key.public_key_hex(),
+                stake: 1000,
+                bls_public_key: vec![],
+                pop_signature: vec![],
+            }],
+        );
+        let mut agg = FinalityAggregator::new(1, 100, "cp_hash".into());
+        agg.set_validator_snapshot(snap);
+        let vote_for = |hash: &str| {
+            let mut vote = Prevote {
+                epoch: 1,
+                checkpoint_height: 100,
+                checkpoint_hash: hash.into(),
+                voter_id: String::new(),
+                sig_bls: vec![],
+                signature: vec![],
+            };
+            vote.sign(&key, &test_fork());
+            vote
+        };
+
+        assert!(agg.add_prevote(vote_for("fork_hash")).is_err());
+        assert!(agg.double_votes.is_empty());
+        agg.add_prevote(vote_for("cp_hash")).unwrap();
+        let evidence = agg.take_double_votes();
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].validator, key.public_key_hex());
+        assert_eq!(evidence[0].height, 100);
+        assert!(verify_double_vote(&evidence[0], &test_fork()).is_ok());
+
+        let mut forged = evidence[0].clone();
+        forged.checkpoint_hash_2 = Some("other".into());
+        assert!(verify_double_vote(&forged, &test_fork()).is_err());
     }
 
     #[test]
@@ -502,8 +1160,9 @@ mod tests {
             agg_sig_bls: vec![1; 48],
             bitmap: vec![0b0000_0001],
             set_hash: snap.set_hash.clone(),
+            state_root: String::new(),
         };
-        let result = cert.verify(&snap);
+        let result = cert.verify(&snap, 1337);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Insufficient quorum"));
     }
@@ -518,9 +1177,63 @@ mod tests {
             agg_sig_bls: vec![1; 48],
             bitmap: vec![0b0000_1111],
             set_hash: "wrong_hash".into(),
+            state_root: String::new(),
         };
-        let result = cert.verify(&snap);
+        let result = cert.verify(&snap, 1337);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("set hash mismatch"));
     }
+
+    #[test]
+    fn test_tracker_reports_justified_and_finalized() {
+        let snap = make_snapshot(4, 1000);
+        let mut tracker = FinalityTracker::default();
+        let mut agg = FinalityAggregator::new(1, 100, "cp_hash".into());
+        agg.set_validator_snapshot(snap.clone());
+        for i in 0..3 {
+            agg.add_prevote(Prevote {
+                epoch: 1,
+                checkpoint_height: 100,
+                checkpoint_hash: "cp_hash".into(),
+                voter_id: format!("validator_{}", i),
+                sig_bls: vec![i as u8; 48],
+                signature: vec![],
+            })
+            .unwrap();
+            tracker.observe(&agg);
+        }
+        let justified = tracker.justified().unwrap();
+        assert_eq!((justified.height, justified.signers), (100, 3));
+        assert_eq!(justified.participating_stake, 3000);
+        assert!(tracker.finalized().is_none());
+        assert_eq!(tracker.epochs()[0].prevote_rate, 0.75);
+
+        let cert = FinalityCert {
+            epoch: 1,
+            checkpoint_height: 100,
+            checkpoint_hash: "cp_hash".into(),
+            agg_sig_bls: vec![1; 48],
+            bitmap: vec![0b0000_1111],
+            set_hash: snap.set_hash.clone(),
+            state_root: String::new(),
+        };
+        tracker.on_finalized(&cert, &snap);
+        let finalized = tracker.finalized().unwrap();
+        assert_eq!(
+            (finalized.signers, finalized.participating_stake),
+            (4, 4000)
+        );
+        let epoch = &tracker.epochs()[0];
+        assert!(epoch.finalized);
+        assert_eq!((epoch.prevote_rate, epoch.precommit_rate), (0.75, 1.0));
+
+        for epoch in 2..30 {
+            let mut agg = FinalityAggregator::new(epoch, epoch * 100, "h".into());
+            agg.set_validator_snapshot(snap.clone());
+            tracker.observe(&agg);
+        }
+        assert_eq!(tracker.epochs().len(), FINALITY_PARTICIPATION_EPOCHS);
+        assert_eq!(tracker.epochs()[0].epoch, 29);
+        assert_eq!(tracker.justified().unwrap().height, 100);
+    }
 }
@@ -1,20 +1,42 @@
+pub mod dev;
 pub mod finality;
+#[cfg(feature = "poa")]
 pub mod poa;
 pub mod pos;
+#[cfg(feature = "pow-miner")]
 mod pow;
 pub mod qc;
+use crate::chain_config::{ChainLimits, ForkId};
+use crate::events::BlockRejectReason;
+use crate::transaction::Transaction;
 use crate::Block;
+pub use dev::DevEngine;
+#[cfg(feature = "poa")]
 pub use poa::PoAEngine;
+#[cfg(feature = "pos")]
 pub use pos::PoSEngine;
+#[cfg(feature = "pow-miner")]
 pub use pow::PoWEngine;
 use std::error::Error;
 use std::fmt;
-use std::time::{SystemTime, UNIX_EPOCH};
+// `reason` says which check a rejected block failed; errors that are not
+// about a block's validity, e.g. a poisoned lock, carry `Other`.
 #[derive(Debug)]
-pub struct ConsensusError(pub String);
+pub struct ConsensusError {
+    pub reason: BlockRejectReason,
+    pub message: String,
+}
+impl ConsensusError {
+    pub fn new(message: String) -> Self {
+        Self::rejected(BlockRejectReason::Other, message)
+    }
+    pub fn rejected(reason: BlockRejectReason, message: String) -> Self {
+        ConsensusError { reason, message }
+    }
+}
 impl fmt::Display for ConsensusError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Consensus error: {}", self.0)
+        write!(f, "Consensus error: {}", self.message)
     }
 }
 impl Error for ConsensusError {}
@@ -24,10 +46,44 @@ pub const MIN_BLOCK_INTERVAL_MS: u128 = 1000;
 pub const MAX_BLOCK_SIZE: usize = 1_000_000;
 pub const MAX_TRANSACTIONS_PER_BLOCK: usize = 5000;
 pub const MAX_REORG_DEPTH: usize = 100;
+
+// Maps wall-clock time onto fixed-length slots counted from genesis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotClock {
+    pub genesis_ms: u128,
+    pub slot_ms: u64,
+}
+
+impl SlotClock {
+    pub fn new(genesis_ms: u128, slot_ms: u64) -> Self {
+        SlotClock {
+            genesis_ms,
+            slot_ms: slot_ms.max(1),
+        }
+    }
+
+    pub fn slot_at(&self, timestamp_ms: u128) -> u64 {
+        (timestamp_ms.saturating_sub(self.genesis_ms) / self.slot_ms as u128) as u64
+    }
+
+    pub fn slot_start(&self, slot: u64) -> u128 {
+        self.genesis_ms + slot as u128 * self.slot_ms as u128
+    }
+
+    pub fn until_next_slot(&self, now_ms: u128) -> u64 {
+        (self.slot_start(self.slot_at(now_ms) + 1) - now_ms) as u64
+    }
+}
 use crate::account::AccountState;
 
 pub trait ConsensusEngine: Send + Sync {
+    // Fills in the consensus fields of a block this node produces. The block
+    // is sealed once its state root, which depends on those fields, is known.
     fn prepare_block(&self, block: &mut Block, state: &AccountState) -> Result<(), ConsensusError>;
+    fn seal_block(&self, block: &mut Block, _state: &AccountState) -> Result<(), ConsensusError> {
+        block.hash = block.calculate_hash();
+        Ok(())
+    }
     fn validate_block(
         &self,
         block: &Block,
@@ -37,61 +93,94 @@ pub trait ConsensusEngine: Send + Sync {
     fn record_block(&self, _block: &Block) -> Result<(), ConsensusError> {
         Ok(())
     }
+    fn track_liveness(&self, _block: &Block, _state: &mut AccountState) {}
+    // Transactions the engine needs in its own blocks to keep producing,
+    // signed by this node and put ahead of the mempool.
+    fn maintenance_transactions(
+        &self,
+        _state: &AccountState,
+        _fork: &ForkId,
+        _signing_version: u8,
+    ) -> Vec<Transaction> {
+        Vec::new()
+    }
     fn consensus_type(&self) -> &'static str;
     fn info(&self) -> String;
+    // `now` is the node's network-adjusted time rather than the raw local
+    // clock, so a skewed clock does not make the node reject every block.
     fn validate_timestamp(
         &self,
         block: &Block,
         prev_block: Option<&Block>,
+        now: u128,
+        limits: &ChainLimits,
     ) -> Result<(), ConsensusError> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
         if block.timestamp > now + MAX_FUTURE_BLOCK_TIME_MS {
-            return Err(ConsensusError(format!(
-                "Block timestamp too far in future: {} ms ahead",
-                block.timestamp - now
-            )));
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::BadTimestamp,
+                format!(
+                    "Block timestamp too far in future: {} ms ahead",
+                    block.timestamp - now
+                ),
+            ));
         }
         if block.timestamp + MAX_PAST_BLOCK_TIME_MS < now {
-            return Err(ConsensusError(format!(
-                "Block timestamp too old: {} ms behind",
-                now - block.timestamp
-            )));
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::BadTimestamp,
+                format!(
+                    "Block timestamp too old: {} ms behind",
+                    now - block.timestamp
+                ),
+            ));
         }
         if let Some(prev) = prev_block {
             if block.timestamp <= prev.timestamp {
-                return Err(ConsensusError(format!(
-                    "Block timestamp not monotonic. Prev: {}, Current: {}",
-                    prev.timestamp, block.timestamp
-                )));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadTimestamp,
+                    format!(
+                        "Block timestamp not monotonic. Prev: {}, Current: {}",
+                        prev.timestamp, block.timestamp
+                    ),
+                ));
             }
             let interval = block.timestamp - prev.timestamp;
-            if interval < MIN_BLOCK_INTERVAL_MS {
-                return Err(ConsensusError(format!(
-                    "Block produced too fast. Min interval: {} ms, Got: {} ms",
-                    MIN_BLOCK_INTERVAL_MS, interval
-                )));
+            if interval < limits.min_block_interval_ms as u128 {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadTimestamp,
+                    format!(
+                        "Block produced too fast. Min interval: {} ms, Got: {} ms",
+                        limits.min_block_interval_ms, interval
+                    ),
+                ));
             }
         }
         Ok(())
     }
-    fn validate_block_size(&self, block: &Block) -> Result<(), ConsensusError> {
-        if block.transactions.len() > MAX_TRANSACTIONS_PER_BLOCK {
-            return Err(ConsensusError(format!(
-                "Too many transactions. Max: {}, Got: {}",
-                MAX_TRANSACTIONS_PER_BLOCK,
-                block.transactions.len()
-            )));
+    fn validate_block_size(
+        &self,
+        block: &Block,
+        limits: &ChainLimits,
+    ) -> Result<(), ConsensusError> {
+        if block.transactions.len() > limits.max_transactions_per_block {
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::TooLarge,
+                format!(
+                    "Too many transactions. Max: {}, Got: {}",
+                    limits.max_transactions_per_block,
+                    block.transactions.len()
+                ),
+            ));
         }
         let serialized = serde_json::to_vec(block).unwrap_or_default();
-        if serialized.len() > MAX_BLOCK_SIZE {
-            return Err(ConsensusError(format!(
-                "Block too large. Max: {} bytes, Got: {} bytes",
-                MAX_BLOCK_SIZE,
-                serialized.len()
-            )));
+        if serialized.len() > limits.max_block_size {
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::TooLarge,
+                format!(
+                    "Block too large. Max: {} bytes, Got: {} bytes",
+                    limits.max_block_size,
+                    serialized.len()
+                ),
+            ));
         }
         Ok(())
     }
@@ -101,7 +190,12 @@ pub trait ConsensusEngine: Send + Sync {
         }
         chains.iter().max_by_key(|c| c.len()).copied()
     }
-    fn can_reorg(&self, current_chain: &[Block], new_chain: &[Block]) -> bool {
+    fn can_reorg(
+        &self,
+        current_chain: &[Block],
+        new_chain: &[Block],
+        limits: &ChainLimits,
+    ) -> bool {
         if new_chain.len() <= current_chain.len() {
             return false;
         }
@@ -111,10 +205,10 @@ pub trait ConsensusEngine: Send + Sync {
             .find(|b| new_chain.iter().any(|nb| nb.hash == b.hash));
         if let Some(ancestor) = common_ancestor {
             let reorg_depth = current_chain.len() - ancestor.index as usize - 1;
-            if reorg_depth > MAX_REORG_DEPTH {
+            if reorg_depth > limits.max_reorg_depth {
                 println!(
                     " Rejecting deep reorg: {} blocks (max: {})",
-                    reorg_depth, MAX_REORG_DEPTH
+                    reorg_depth, limits.max_reorg_depth
                 );
                 return false;
             }
@@ -126,13 +220,14 @@ pub trait ConsensusEngine: Send + Sync {
         block: &Block,
         chain: &[Block],
         state: &AccountState,
+        now: u128,
     ) -> Result<(), ConsensusError> {
         if block.index == 0 {
             return self.validate_block(block, chain, state);
         }
         let prev_block = chain.last();
-        self.validate_timestamp(block, prev_block)?;
-        self.validate_block_size(block)?;
+        self.validate_timestamp(block, prev_block, now, &state.limits)?;
+        self.validate_block_size(block, &state.limits)?;
         self.validate_block(block, chain, state)?;
         Ok(())
     }
@@ -144,6 +239,23 @@ pub trait ConsensusEngine: Send + Sync {
     fn is_better_chain(&self, current: &[Block], candidate: &[Block]) -> bool {
         self.fork_choice_score(candidate) > self.fork_choice_score(current)
     }
+
+    fn instant_seal_producer(&self) -> Option<String> {
+        None
+    }
+    // Engines whose blocks must be signed by a validator report the key they
+    // hold; without one the node can only follow the chain.
+    fn requires_signer(&self) -> bool {
+        false
+    }
+    fn signer_address(&self) -> Option<String> {
+        None
+    }
+    // Slot-based engines produce at most one block per slot and are driven
+    // by the node's slot timer instead of on demand.
+    fn slot_clock(&self) -> Option<SlotClock> {
+        None
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -154,4 +266,15 @@ mod tests {
         assert_eq!(MIN_BLOCK_INTERVAL_MS, 1000);
         assert_eq!(MAX_REORG_DEPTH, 100);
     }
+
+    #[test]
+    fn test_slot_clock() {
+        let clock = SlotClock::new(10_000, 6000);
+        assert_eq!(clock.slot_at(5_000), 0);
+        assert_eq!(clock.slot_at(15_999), 0);
+        assert_eq!(clock.slot_at(16_000), 1);
+        assert_eq!(clock.slot_start(3), 28_000);
+        assert_eq!(clock.until_next_slot(16_000), 6000);
+        assert_eq!(clock.until_next_slot(21_500), 500);
+    }
 }
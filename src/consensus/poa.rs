@@ -1,5 +1,7 @@
 use super::{ConsensusEngine, ConsensusError};
 use crate::account::{AccountState, Validator};
+use crate::events::BlockRejectReason;
+use crate::genesis::EmergencyQuorum;
 use crate::Block;
 use std::collections::HashMap;
 #[derive(Debug, Clone)]
@@ -8,6 +10,8 @@ pub struct PoAConfig {
     pub epoch_length: u64,
     pub quorum_ratio: f64,
     pub validators_file: Option<String>,
+    pub max_missed_slots: u64,
+    pub emergency: EmergencyQuorum,
 }
 impl Default for PoAConfig {
     fn default() -> Self {
@@ -16,27 +20,55 @@ impl Default for PoAConfig {
             epoch_length: 30000,
             quorum_ratio: 0.67,
             validators_file: None,
+            max_missed_slots: 10,
+            emergency: EmergencyQuorum::default(),
         }
     }
 }
 
 use crate::crypto::KeyPair;
+use crate::frost::ThresholdSigner;
 
 pub struct PoAEngine {
     pub config: PoAConfig,
     keypair: Option<KeyPair>,
+    threshold: Option<ThresholdSigner>,
 }
 
 impl PoAEngine {
     pub fn new(config: PoAConfig, keypair: Option<KeyPair>) -> Self {
-        PoAEngine { config, keypair }
+        PoAEngine {
+            config,
+            keypair,
+            threshold: None,
+        }
     }
     pub fn with_config(
         config: PoAConfig,
         _validators: Vec<String>,
         keypair: Option<KeyPair>,
     ) -> Self {
-        PoAEngine { config, keypair }
+        Self::new(config, keypair)
+    }
+
+    // Signs as the authority whose key is split across the signer's group.
+    // Takes precedence over a plain keypair.
+    pub fn with_threshold_signer(mut self, signer: ThresholdSigner) -> Self {
+        self.threshold = Some(signer);
+        self
+    }
+
+    fn sign_block(&self, block: &mut Block, fork_hash: &str) -> Result<(), ConsensusError> {
+        match (&self.threshold, &self.keypair) {
+            (Some(signer), _) => block
+                .sign_threshold(signer, fork_hash)
+                .map_err(|e| ConsensusError::new(format!("Threshold signing failed: {}", e))),
+            (None, Some(keypair)) => {
+                block.sign(keypair, fork_hash);
+                Ok(())
+            }
+            (None, None) => Err(ConsensusError::new("No signer configured".into())),
+        }
     }
 
     pub fn expected_proposer<'a>(
@@ -51,6 +83,66 @@ impl PoAEngine {
         Some(active_validators[slot])
     }
 
+    // Every full block period that passes without a block hands the slot to
+    // the next authority in rotation.
+    pub fn skipped_slots(&self, block: &Block, state: &AccountState) -> u64 {
+        let period_ms = self.config.block_period as u128 * 1000;
+        if block.index <= 1 || period_ms == 0 || state.last_block_timestamp == 0 {
+            return 0;
+        }
+        let elapsed = block.timestamp.saturating_sub(state.last_block_timestamp);
+        ((elapsed / period_ms) as u64).saturating_sub(1)
+    }
+
+    pub fn slot_proposer<'a>(
+        &self,
+        block: &Block,
+        state: &AccountState,
+        active_validators: &'a [&Validator],
+    ) -> Option<&'a Validator> {
+        let slot = block.index.wrapping_add(self.skipped_slots(block, state));
+        self.expected_proposer(slot, active_validators)
+    }
+
+    // Once the chain has been stalled past the emergency threshold the
+    // emergency authorities take over the rotation until regular blocks resume.
+    pub fn emergency_proposer(&self, block: &Block, state: &AccountState) -> Option<&str> {
+        let emergency = &self.config.emergency;
+        if !emergency.is_enabled() || block.index <= 1 || state.last_block_timestamp == 0 {
+            return None;
+        }
+        let elapsed = block.timestamp.saturating_sub(state.last_block_timestamp);
+        if elapsed < emergency.after_secs as u128 * 1000 {
+            return None;
+        }
+        let slot = block.index.wrapping_add(self.skipped_slots(block, state));
+        let authorities = &emergency.authorities;
+        Some(authorities[(slot as usize) % authorities.len()].as_str())
+    }
+
+    // The authority in rotation for `block`, or None for genesis.
+    fn expected_signer(
+        &self,
+        block: &Block,
+        state: &AccountState,
+    ) -> Result<Option<String>, ConsensusError> {
+        let active_refs = state.validators_for_block(block.index);
+        match self.slot_proposer(block, state, &active_refs) {
+            Some(expected) => Ok(Some(expected.address.clone())),
+            None if block.index == 0 => Ok(None),
+            None => Err(ConsensusError::new("No active validators found".into())),
+        }
+    }
+
+    fn is_our_turn(&self, block: &Block, state: &AccountState) -> Result<bool, ConsensusError> {
+        let (Some(ours), Some(expected)) =
+            (self.signer_address(), self.expected_signer(block, state)?)
+        else {
+            return Ok(false);
+        };
+        Ok(ours == expected || self.emergency_proposer(block, state) == Some(ours.as_str()))
+    }
+
     pub fn active_validator_count(&self, state: &AccountState) -> usize {
         state.get_active_validators().len()
     }
@@ -59,54 +151,46 @@ impl PoAEngine {
 impl ConsensusEngine for PoAEngine {
     fn prepare_block(&self, block: &mut Block, state: &AccountState) -> Result<(), ConsensusError> {
         let slot = block.index;
-        let active_refs = state.get_active_validators();
-
-        let expected_signer_addr =
-            if let Some(expected) = self.expected_proposer(slot, &active_refs) {
-                expected.address.clone()
-            } else {
-                // Genesis or bootstrap
-                if block.index == 0 {
-                    String::new()
-                } else {
-                    return Err(ConsensusError("No active validators found".into()));
-                }
-            };
-
-        if !expected_signer_addr.is_empty() {
+        if let Some(authority) = self.emergency_proposer(block, state) {
             println!(
-                "PoA: Block {} should be proposed by: {}",
-                slot,
-                &expected_signer_addr[..16.min(expected_signer_addr.len())]
+                "PoA: Chain stalled, emergency authority {} may also propose block {}",
+                &authority[..16.min(authority.len())],
+                slot
             );
+        }
 
-            if let Some(keypair) = &self.keypair {
-                if keypair.public_key_hex() == expected_signer_addr {
-                    block.sign(keypair);
-                    println!(
-                        " PoA: Block {} signed by us ({})",
-                        block.index,
-                        &expected_signer_addr[..16.min(expected_signer_addr.len())]
-                    );
-                } else {
-                    /*
-                    println!(
-                        " PoA: We are not the proposer (us: {}, expected: {})",
-                        keypair.public_key_hex(),
-                        expected_signer_addr
-                    );
-                    */
-                }
-            } else {
-                println!(" PoA: No keypair configured, cannot sign block");
+        let Some(expected_signer_addr) = self.expected_signer(block, state)? else {
+            return Ok(());
+        };
+        println!(
+            "PoA: Block {} should be proposed by: {}",
+            slot,
+            &expected_signer_addr[..16.min(expected_signer_addr.len())]
+        );
+        match self.signer_address() {
+            Some(ours) if self.is_our_turn(block, state)? => {
+                block.producer = Some(ours);
             }
+            Some(_) => {}
+            None => println!(" PoA: No keypair configured, cannot sign block"),
         }
 
-        if block.signature.is_none() {
+        println!("PoA: Block {} prepared", block.index);
+        Ok(())
+    }
+
+    fn seal_block(&self, block: &mut Block, state: &AccountState) -> Result<(), ConsensusError> {
+        if self.is_our_turn(block, state)? {
+            self.sign_block(block, &state.forks.fork_hash(block.index))?;
+            let ours = block.producer.clone().unwrap_or_default();
+            println!(
+                " PoA: Block {} signed by us ({})",
+                block.index,
+                &ours[..16.min(ours.len())]
+            );
+        } else {
             block.hash = block.calculate_hash();
         }
-
-        println!("PoA: Block {} prepared", block.index);
         Ok(())
     }
 
@@ -118,40 +202,60 @@ impl ConsensusEngine for PoAEngine {
     ) -> Result<(), ConsensusError> {
         if block.index == 0 {
             if block.hash != block.calculate_hash() {
-                return Err(ConsensusError("Invalid genesis block hash".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadHash,
+                    "Invalid genesis block hash".into(),
+                ));
             }
             return Ok(());
         }
         if let Some(prev_block) = chain.last() {
             if block.previous_hash != prev_block.hash {
-                return Err(ConsensusError(format!(
-                    "Previous hash mismatch. Expected: {}, Got: {}",
-                    prev_block.hash, block.previous_hash
-                )));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadParent,
+                    format!(
+                        "Previous hash mismatch. Expected: {}, Got: {}",
+                        prev_block.hash, block.previous_hash
+                    ),
+                ));
             }
         }
 
-        let active_refs = state.get_active_validators();
+        let active_refs = state.validators_for_block(block.index);
         if !active_refs.is_empty() {
             let expected = self
-                .expected_proposer(block.index, &active_refs)
-                .ok_or_else(|| ConsensusError("No proposer for this slot".into()))?;
-
-            let producer = block
-                .producer
-                .as_ref()
-                .ok_or_else(|| ConsensusError("Block has no producer".into()))?;
-
-            if producer != &expected.address {
-                return Err(ConsensusError(format!(
-                    "Wrong proposer. Expected: {}, Got: {}",
-                    &expected.address[..16.min(expected.address.len())],
-                    &producer[..16.min(producer.len())]
-                )));
+                .slot_proposer(block, state, &active_refs)
+                .ok_or_else(|| {
+                    ConsensusError::rejected(
+                        BlockRejectReason::BadProducer,
+                        "No proposer for this slot".into(),
+                    )
+                })?;
+
+            let producer = block.producer.as_ref().ok_or_else(|| {
+                ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Block has no producer".into(),
+                )
+            })?;
+
+            let emergency = self.emergency_proposer(block, state);
+            if producer != &expected.address && emergency != Some(producer.as_str()) {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    format!(
+                        "Wrong proposer. Expected: {}, Got: {}",
+                        &expected.address[..16.min(expected.address.len())],
+                        &producer[..16.min(producer.len())]
+                    ),
+                ));
             }
 
-            if !block.verify_signature() {
-                return Err(ConsensusError("Invalid block signature".into()));
+            if !block.verify_signature(&state.forks.fork_hash(block.index)) {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadSignature,
+                    "Invalid block signature".into(),
+                ));
             }
 
             println!(
@@ -162,7 +266,10 @@ impl ConsensusEngine for PoAEngine {
         } else {
             // No validators - maybe test environment
             if block.hash != block.calculate_hash() {
-                return Err(ConsensusError("Invalid block hash".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadHash,
+                    "Invalid block hash".into(),
+                ));
             }
         }
         Ok(())
@@ -170,6 +277,15 @@ impl ConsensusEngine for PoAEngine {
     fn consensus_type(&self) -> &'static str {
         "PoA"
     }
+    fn requires_signer(&self) -> bool {
+        true
+    }
+    fn signer_address(&self) -> Option<String> {
+        match &self.threshold {
+            Some(signer) => Some(signer.public_key_hex()),
+            None => self.keypair.as_ref().map(|k| k.public_key_hex()),
+        }
+    }
     fn info(&self) -> String {
         format!(
             "PoA (validators: in-state, quorum: {:.0}%)",
@@ -180,6 +296,32 @@ impl ConsensusEngine for PoAEngine {
     fn fork_choice_score(&self, chain: &[Block]) -> u128 {
         chain.len() as u128
     }
+
+    fn track_liveness(&self, block: &Block, state: &mut AccountState) {
+        if block.index == 0 {
+            return;
+        }
+        let active: Vec<String> = state
+            .validators_for_block(block.index)
+            .iter()
+            .map(|v| v.address.clone())
+            .collect();
+        if active.is_empty() {
+            return;
+        }
+        let skipped = self
+            .skipped_slots(block, state)
+            .min(active.len() as u64 - 1);
+        let missed: Vec<String> = (0..skipped)
+            .map(|i| active[(block.index.wrapping_add(i) as usize) % active.len()].clone())
+            .filter(|addr| block.producer.as_ref() != Some(addr))
+            .collect();
+        state.record_missed_slots(
+            block.producer.as_deref(),
+            &missed,
+            self.config.max_missed_slots,
+        );
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -227,6 +369,75 @@ mod tests {
         assert_ne!(p1.address, p2.address);
     }
 
+    #[test]
+    fn test_skipped_slot_goes_to_next_authority() {
+        let mut state = AccountState::new();
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate().unwrap()).collect();
+        for kp in &keys {
+            state.add_validator(kp.public_key_hex(), 0);
+        }
+        let engine = PoAEngine::new(
+            PoAConfig {
+                max_missed_slots: 2,
+                ..PoAConfig::default()
+            },
+            None,
+        );
+        let active: Vec<String> = state
+            .get_active_validators()
+            .iter()
+            .map(|v| v.address.clone())
+            .collect();
+
+        let mut block = Block::new(3, "prev".into(), vec![]);
+        state.last_block_timestamp = block.timestamp - 11_000;
+        assert_eq!(engine.skipped_slots(&block, &state), 1);
+        let refs = state.get_active_validators();
+        let proposer = engine.slot_proposer(&block, &state, &refs).unwrap();
+        assert_eq!(proposer.address, active[4 % 3]);
+
+        block.producer = Some(active[4 % 3].clone());
+        engine.track_liveness(&block, &mut state);
+        engine.track_liveness(&block, &mut state);
+        let missed = state.get_validator(&active[0]).unwrap();
+        assert!(missed.jailed);
+        assert_eq!(state.get_active_validators().len(), 2);
+    }
+
+    #[test]
+    fn test_emergency_quorum_takes_over_stalled_chain() {
+        let mut state = AccountState::new();
+        let authority = KeyPair::generate().unwrap();
+        let emergency = KeyPair::generate().unwrap();
+        state.add_validator(authority.public_key_hex(), 0);
+        let engine = PoAEngine::new(
+            PoAConfig {
+                emergency: EmergencyQuorum {
+                    authorities: vec![emergency.public_key_hex()],
+                    after_secs: 60,
+                },
+                ..PoAConfig::default()
+            },
+            Some(emergency),
+        );
+
+        let mut block = Block::new(5, "prev".into(), vec![]);
+        state.last_block_timestamp = block.timestamp - 10_000;
+        engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
+        assert!(block.signature.is_none());
+
+        let mut block = Block::new(5, "prev".into(), vec![]);
+        state.last_block_timestamp = block.timestamp - 61_000;
+        engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
+        assert!(block.signature.is_some());
+        assert!(engine.validate_block(&block, &[], &state).is_ok());
+
+        state.last_block_timestamp = block.timestamp - 10_000;
+        assert!(engine.validate_block(&block, &[], &state).is_err());
+    }
+
     #[test]
     fn test_poa_signing() {
         let keypair = KeyPair::generate().unwrap();
@@ -243,10 +454,34 @@ mod tests {
         let mut block = Block::new(1, "prev".into(), vec![]);
 
         engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
 
         assert!(block.producer.is_some());
         assert_eq!(block.producer.as_ref().unwrap(), &pubkey);
         assert!(block.signature.is_some());
-        assert!(block.verify_signature());
+        assert!(block.verify_signature(""));
+    }
+
+    #[test]
+    fn test_poa_threshold_signing() {
+        let authority = KeyPair::generate().unwrap();
+        let pubkey = authority.public_key_hex();
+        let shares = crate::frost::split_key(&authority, 2, 3).unwrap();
+
+        let mut state = AccountState::new();
+        state
+            .validators
+            .insert(pubkey.clone(), Validator::new(pubkey.clone(), 0));
+        state.validators.get_mut(&pubkey).unwrap().active = true;
+
+        let signer = ThresholdSigner::local(vec![shares[0].clone(), shares[2].clone()]).unwrap();
+        let engine = PoAEngine::new(PoAConfig::default(), None).with_threshold_signer(signer);
+        assert_eq!(engine.signer_address(), Some(pubkey.clone()));
+
+        let mut block = Block::new(1, "prev".into(), vec![]);
+        engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
+        assert_eq!(block.producer.as_ref(), Some(&pubkey));
+        assert!(engine.validate_block(&block, &[], &state).is_ok());
     }
 }
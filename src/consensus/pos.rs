@@ -1,8 +1,20 @@
-use super::{ConsensusEngine, ConsensusError};
+#[cfg(feature = "pos")]
+use super::{ConsensusEngine, ConsensusError, SlotClock};
+#[cfg(feature = "pos")]
 use crate::account::AccountState;
+#[cfg(feature = "pos")]
+use crate::account::MIN_TX_FEE;
+#[cfg(feature = "pos")]
+use crate::chain_config::{ForkId, ForkSchedule};
+#[cfg(feature = "pos")]
+use crate::events::BlockRejectReason;
+#[cfg(feature = "pos")]
+use crate::transaction::Transaction;
+#[cfg(feature = "pos")]
 use crate::Block;
-use hex;
+#[cfg(feature = "pos")]
 use sha3::{Digest, Sha3_256};
+#[cfg(feature = "pos")]
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
@@ -10,10 +22,10 @@ pub struct PoSConfig {
     pub min_stake: u64,
     pub slot_duration: u64,
     pub epoch_length: u64,
-    pub annual_reward_rate: f64,
     pub slashing_penalty: f64,
     pub double_sign_penalty: f64,
     pub unbonding_epochs: u64,
+    pub genesis_time_ms: u128,
 }
 impl Default for PoSConfig {
     fn default() -> Self {
@@ -21,10 +33,10 @@ impl Default for PoSConfig {
             min_stake: 1000,
             slot_duration: 6,
             epoch_length: 32,
-            annual_reward_rate: 0.05,
             slashing_penalty: 0.10,
             double_sign_penalty: 0.50,
             unbonding_epochs: crate::account::UNBONDING_EPOCHS,
+            genesis_time_ms: crate::genesis::GENESIS_TIMESTAMP,
         }
     }
 }
@@ -33,12 +45,22 @@ use serde::{Deserialize, Serialize};
 
 use crate::block::BlockHeader;
 
+pub const MAX_EVIDENCE_PER_BLOCK: usize = 16;
+pub const EVIDENCE_MAX_AGE_EPOCHS: u64 = 8;
+// Reveals left on the current onion when a validator re-commits; the margin
+// covers the blocks it takes for the commit to be included.
+pub const RANDAO_RECOMMIT_MARGIN: usize = 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SlashingEvidence {
     pub header1: BlockHeader,
     pub header2: BlockHeader,
     pub signature1: Vec<u8>,
     pub signature2: Vec<u8>,
+    // Unsigned, so only informational; the slashing reward goes to the
+    // producer that includes the evidence.
+    #[serde(default)]
+    pub reporter: String,
 }
 
 impl SlashingEvidence {
@@ -53,8 +75,23 @@ impl SlashingEvidence {
             header2,
             signature1,
             signature2,
+            reporter: String::new(),
         }
     }
+
+    pub fn with_reporter(mut self, reporter: String) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    // One offence per producer and height, whichever pair of headers proves it.
+    pub fn key(&self) -> String {
+        format!(
+            "{}:{}",
+            self.header1.producer.as_deref().unwrap_or_default(),
+            self.header1.index
+        )
+    }
 }
 #[derive(Debug, Clone)]
 pub struct Checkpoint {
@@ -62,18 +99,25 @@ pub struct Checkpoint {
     pub block_hash: String,
     pub timestamp: u128,
 }
+// Evidence and checkpoint types above are shared with block import and
+// stay available without the `pos` feature; the engine below does not.
+#[cfg(feature = "pos")]
 use crate::crypto::{KeyPair, ValidatorKeys};
+#[cfg(feature = "pos")]
+use crate::randao::{verify_reveal, HashOnion, RANDAO_ONION_DEPTH};
+#[cfg(feature = "pos")]
+use std::sync::{Arc, Mutex, RwLock};
 
-use std::sync::RwLock;
-
+#[cfg(feature = "pos")]
 pub struct PoSEngine {
     pub config: PoSConfig,
     seen_blocks: RwLock<HashMap<(String, u64), (BlockHeader, Vec<u8>)>>,
     pub slashing_evidence: RwLock<Vec<SlashingEvidence>>,
     checkpoints: RwLock<Vec<Checkpoint>>,
     validator_keys: Option<ValidatorKeys>,
-    epoch_seed: RwLock<[u8; 32]>,
+    randao_onion: Mutex<Option<(u64, Arc<HashOnion>)>>,
 }
+#[cfg(feature = "pos")]
 impl PoSEngine {
     pub fn new(config: PoSConfig, validator_keys: Option<ValidatorKeys>) -> Self {
         PoSEngine {
@@ -82,11 +126,41 @@ impl PoSEngine {
             slashing_evidence: RwLock::new(Vec::new()),
             checkpoints: RwLock::new(Vec::new()),
             validator_keys,
-            epoch_seed: RwLock::new([0u8; 32]),
+            randao_onion: Mutex::new(None),
         }
     }
 
-    pub fn verify_evidence(&self, evidence: &SlashingEvidence) -> bool {
+    // Built on first use of a generation; the full onion is kept so each
+    // block's reveal is a lookup rather than a re-hash from the secret.
+    fn randao_onion(&self, generation: u64) -> Option<Arc<HashOnion>> {
+        let keys = self.validator_keys.as_ref()?;
+        let mut cached = self.randao_onion.lock().ok()?;
+        match cached.as_ref() {
+            Some((built, onion)) if *built == generation => Some(onion.clone()),
+            _ => {
+                let onion = Arc::new(HashOnion::from_keys(keys, generation, RANDAO_ONION_DEPTH));
+                *cached = Some((generation, onion.clone()));
+                Some(onion)
+            }
+        }
+    }
+
+    pub fn randao_commitment(&self, generation: u64) -> Option<[u8; 32]> {
+        self.randao_onion(generation).map(|o| o.commitment())
+    }
+
+    pub fn clock(&self) -> SlotClock {
+        SlotClock::new(
+            self.config.genesis_time_ms,
+            self.config.slot_duration * 1000,
+        )
+    }
+
+    pub fn skipped_slots(parent: &Block, block: &Block) -> u64 {
+        block.slot.saturating_sub(parent.slot + 1)
+    }
+
+    pub fn verify_evidence(&self, evidence: &SlashingEvidence, forks: &ForkSchedule) -> bool {
         if evidence.header1.index != evidence.header2.index {
             return false;
         }
@@ -97,10 +171,17 @@ impl PoSEngine {
             return false;
         }
 
-        if !evidence.header1.verify_signature(&evidence.signature1) {
+        let fork_hash = forks.fork_hash(evidence.header1.index);
+        if !evidence
+            .header1
+            .verify_signature(&evidence.signature1, &fork_hash)
+        {
             return false;
         }
-        if !evidence.header2.verify_signature(&evidence.signature2) {
+        if !evidence
+            .header2
+            .verify_signature(&evidence.signature2, &fork_hash)
+        {
             return false;
         }
 
@@ -115,21 +196,22 @@ impl PoSEngine {
         self.slashing_evidence
             .read()
             .map(|guard| guard.clone())
-            .map_err(|_| ConsensusError("Failed to acquire read lock on slashing evidence".into()))
+            .map_err(|_| {
+                ConsensusError::new("Failed to acquire read lock on slashing evidence".into())
+            })
     }
 
     pub fn get_checkpoints(&self) -> Result<Vec<Checkpoint>, ConsensusError> {
         self.checkpoints
             .read()
             .map(|guard| guard.clone())
-            .map_err(|_| ConsensusError("Failed to acquire read lock on checkpoints".into()))
+            .map_err(|_| ConsensusError::new("Failed to acquire read lock on checkpoints".into()))
     }
 
     pub fn add_checkpoint(&self, block: &Block) -> Result<(), ConsensusError> {
-        let mut checkpoints = self
-            .checkpoints
-            .write()
-            .map_err(|_| ConsensusError("Failed to acquire write lock on checkpoints".into()))?;
+        let mut checkpoints = self.checkpoints.write().map_err(|_| {
+            ConsensusError::new("Failed to acquire write lock on checkpoints".into())
+        })?;
         checkpoints.push(Checkpoint {
             block_index: block.index,
             block_hash: block.hash.clone(),
@@ -145,19 +227,22 @@ impl PoSEngine {
         }
         false
     }
+    // `randao_seed` is the RANDAO mix committed in state at the last epoch
+    // boundary, so a producer cannot steer its successors' election by
+    // choosing what goes into its own block.
     pub fn calculate_seed(
         &self,
         chain_id: u64,
         epoch: u64,
         slot: u64,
         validator_set_hash: &str,
+        randao_seed: &[u8; 32],
     ) -> [u8; 32] {
-        let prev_seed = self.epoch_seed.read().unwrap_or_else(|e| e.into_inner());
         let mut hasher = Sha3_256::new();
         hasher.update(chain_id.to_le_bytes());
         hasher.update(epoch.to_le_bytes());
         hasher.update(slot.to_le_bytes());
-        hasher.update(*prev_seed);
+        hasher.update(randao_seed);
         hasher.update(validator_set_hash.as_bytes());
         hasher.finalize().into()
     }
@@ -186,14 +271,6 @@ impl PoSEngine {
             v.active && !v.slashed && v.stake >= self.config.min_stake
         })
     }
-    #[allow(dead_code)]
-    fn calculate_reward(&self, validator_stake: u64) -> u64 {
-        let slots_per_year = 365 * 24 * 60 * 60 / self.config.slot_duration;
-        let reward = (validator_stake as f64 * self.config.annual_reward_rate
-            / slots_per_year as f64) as u64;
-        reward.max(1)
-    }
-
     pub fn serialize_state(&self) -> Result<Vec<u8>, String> {
         let state = serde_json::json!({
             "checkpoints": self.checkpoints.read().map_err(|_| "Lock error".to_string())?.iter().map(|c| {
@@ -264,37 +341,38 @@ impl PoSEngine {
         Ok(())
     }
 }
+#[cfg(feature = "pos")]
 impl ConsensusEngine for PoSEngine {
     fn prepare_block(&self, block: &mut Block, state: &AccountState) -> Result<(), ConsensusError> {
-        let slot = block.index;
+        let slot = self.clock().slot_at(block.timestamp);
         let epoch = slot / crate::chain_config::EPOCH_LEN;
         block.epoch = epoch;
         block.slot = slot;
 
-        let active_validators = state.get_active_validators();
-        let total_stake = state.get_total_stake();
+        let validator_set = state.validator_set_for_block(block.index);
 
         if let Ok(mut evidences) = self.slashing_evidence.write() {
             if !evidences.is_empty() {
-                block.slashing_evidence = Some(evidences.clone());
-                evidences.clear();
+                let take = evidences.len().min(MAX_EVIDENCE_PER_BLOCK);
+                block.slashing_evidence = Some(evidences.drain(..take).collect());
             }
         }
 
-        if !active_validators.is_empty() {
+        if !validator_set.validators.is_empty() {
             if let Some(keys) = &self.validator_keys {
                 let pubkey = keys.sig_key.public_key_hex();
 
-                if let Some(validator) = state.get_validator(&pubkey) {
-                    if validator.active
-                        && !validator.slashed
-                        && validator.stake >= self.config.min_stake
-                    {
+                if let (Some(entry), Some(validator)) = (
+                    validator_set.find_validator(&pubkey),
+                    state.get_validator(&pubkey),
+                ) {
+                    if !validator.slashed && entry.stake >= self.config.min_stake {
                         let seed = self.calculate_seed(
                             block.chain_id,
                             epoch,
                             slot,
                             &block.validator_set_hash,
+                            &state.randao_seed,
                         );
                         let (vrf_io, vrf_proof, _) = keys.vrf_key.vrf_sign(
                             schnorrkel::context::signing_context(b"BUDLUM_VRF").bytes(&seed),
@@ -302,21 +380,41 @@ impl ConsensusEngine for PoSEngine {
                         let vrf_output = vrf_io.to_preout().to_bytes();
                         let proof_bytes = vrf_proof.to_bytes();
 
-                        let threshold = self.calculate_vrf_threshold(validator.stake, total_stake);
+                        let threshold =
+                            self.calculate_vrf_threshold(entry.stake, validator_set.total_stake);
                         if self.check_vrf_threshold(&vrf_output, threshold) {
+                            if !validator.randao_commitment.is_empty() {
+                                block.randao_reveal = self
+                                    .randao_onion(validator.randao_generation)
+                                    .and_then(|o| o.reveal_after(&validator.randao_commitment))
+                                    .ok_or_else(|| {
+                                        ConsensusError::new(
+                                            "RANDAO hash onion does not open the registered commitment"
+                                                .into(),
+                                        )
+                                    })?
+                                    .to_vec();
+                            }
                             block.vrf_output = vrf_output.to_vec();
                             block.vrf_proof = proof_bytes.to_vec();
-                            block.sign(&keys.sig_key);
+                            block.producer = Some(pubkey);
                             return Ok(());
                         }
                     }
                 }
             }
-            return Err(ConsensusError(
+            return Err(ConsensusError::new(
                 "Not selected as VRF leader for this slot".into(),
             ));
-        } else {
-            block.hash = block.calculate_hash();
+        }
+        Ok(())
+    }
+    fn seal_block(&self, block: &mut Block, state: &AccountState) -> Result<(), ConsensusError> {
+        match &self.validator_keys {
+            Some(keys) if !block.vrf_output.is_empty() => {
+                block.sign(&keys.sig_key, &state.forks.fork_hash(block.index))
+            }
+            _ => block.hash = block.calculate_hash(),
         }
         Ok(())
     }
@@ -328,40 +426,104 @@ impl ConsensusEngine for PoSEngine {
     ) -> Result<(), ConsensusError> {
         if block.index == 0 {
             if block.hash != block.calculate_hash() {
-                return Err(ConsensusError("Invalid genesis block hash".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadHash,
+                    "Invalid genesis block hash".into(),
+                ));
             }
             return Ok(());
         }
         if let Some(prev_block) = chain.last() {
             if block.previous_hash != prev_block.hash {
-                return Err(ConsensusError(format!(
-                    "Previous hash mismatch. Expected: {}, Got: {}",
-                    prev_block.hash, block.previous_hash
-                )));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadParent,
+                    format!(
+                        "Previous hash mismatch. Expected: {}, Got: {}",
+                        prev_block.hash, block.previous_hash
+                    ),
+                ));
+            }
+            if block.index != prev_block.index + 1 {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadParent,
+                    format!(
+                        "Block height {} does not follow parent height {}",
+                        block.index, prev_block.index
+                    ),
+                ));
             }
         }
         if self.is_before_checkpoint(block) {
-            return Err(ConsensusError(
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::FinalityConflict,
                 "Block is before last checkpoint (possible long-range attack)".into(),
             ));
         }
 
-        let active_validators = state.get_active_validators();
-        if !active_validators.is_empty() {
-            let producer = block
-                .producer
-                .as_ref()
-                .ok_or_else(|| ConsensusError("Block has no producer".into()))?;
-
-            let validator = state
-                .get_validator(producer)
-                .ok_or_else(|| ConsensusError("Unknown block producer".into()))?;
-            if !validator.active || validator.slashed || validator.stake < self.config.min_stake {
-                return Err(ConsensusError("Producer is not an active validator".into()));
+        let validator_set = state.validator_set_for_block(block.index);
+        if !validator_set.validators.is_empty() {
+            let slot = self.clock().slot_at(block.timestamp);
+            if block.slot != slot || block.epoch != slot / crate::chain_config::EPOCH_LEN {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadTimestamp,
+                    format!(
+                        "Block slot {} (epoch {}) does not match its timestamp slot {}",
+                        block.slot, block.epoch, slot
+                    ),
+                ));
+            }
+            // A leader that misses its slot is skipped: once the slot ends the
+            // next slot's leader builds on the same parent, so slot numbers
+            // may jump but heights may not.
+            if let Some(prev_block) = chain.last().filter(|b| b.index > 0) {
+                if block.slot <= prev_block.slot {
+                    return Err(ConsensusError::rejected(
+                        BlockRejectReason::BadTimestamp,
+                        format!(
+                            "Block slot {} is not after parent slot {}",
+                            block.slot, prev_block.slot
+                        ),
+                    ));
+                }
+                let skipped = Self::skipped_slots(prev_block, block);
+                if skipped > 0 {
+                    tracing::debug!(
+                        "PoS: Block {} follows {} empty slot(s) after slot {}",
+                        block.index,
+                        skipped,
+                        prev_block.slot
+                    );
+                }
+            }
+            let producer = block.producer.as_ref().ok_or_else(|| {
+                ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Block has no producer".into(),
+                )
+            })?;
+
+            let entry = validator_set.find_validator(producer).ok_or_else(|| {
+                ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Producer is not in the validator set for this epoch".into(),
+                )
+            })?;
+            let validator = state.get_validator(producer).ok_or_else(|| {
+                ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Unknown block producer".into(),
+                )
+            })?;
+            if validator.slashed || entry.stake < self.config.min_stake {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Producer is not an active validator".into(),
+                ));
             }
 
             if validator.vrf_public_key.is_empty() {
-                return Err(ConsensusError(
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
                     "Producer has no registered VRF public key".into(),
                 ));
             }
@@ -372,13 +534,17 @@ impl ConsensusEngine for PoSEngine {
                     block.epoch,
                     block.slot,
                     &block.validator_set_hash,
+                    &state.randao_seed,
                 );
 
                 let mut output_bytes = [0u8; 32];
                 if block.vrf_output.len() == 32 {
                     output_bytes.copy_from_slice(&block.vrf_output);
                 } else {
-                    return Err(ConsensusError("Invalid VRF output length".into()));
+                    return Err(ConsensusError::rejected(
+                        BlockRejectReason::BadProducer,
+                        "Invalid VRF output length".into(),
+                    ));
                 }
 
                 if let Ok(vrf_preout) = schnorrkel::vrf::VRFPreOut::from_bytes(&output_bytes) {
@@ -391,33 +557,66 @@ impl ConsensusEngine for PoSEngine {
                             )
                             .is_err()
                         {
-                            return Err(ConsensusError("VRF proof verification failed".into()));
+                            return Err(ConsensusError::rejected(
+                                BlockRejectReason::BadProducer,
+                                "VRF proof verification failed".into(),
+                            ));
                         }
                     } else {
-                        return Err(ConsensusError("Invalid VRF proof format".into()));
+                        return Err(ConsensusError::rejected(
+                            BlockRejectReason::BadProducer,
+                            "Invalid VRF proof format".into(),
+                        ));
                     }
                 } else {
-                    return Err(ConsensusError("Invalid VRF output format".into()));
+                    return Err(ConsensusError::rejected(
+                        BlockRejectReason::BadProducer,
+                        "Invalid VRF output format".into(),
+                    ));
                 }
             } else {
-                return Err(ConsensusError("Invalid VRF public key format".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "Invalid VRF public key format".into(),
+                ));
             }
 
-            let threshold = self.calculate_vrf_threshold(validator.stake, state.get_total_stake());
+            let threshold = self.calculate_vrf_threshold(entry.stake, validator_set.total_stake);
             if !self.check_vrf_threshold(&block.vrf_output, threshold) {
-                return Err(ConsensusError(
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
                     "VRF output does not meet leadership threshold".into(),
                 ));
             }
 
-            if !block.verify_signature() {
-                return Err(ConsensusError("Invalid block signature".into()));
+            if validator.randao_commitment.is_empty() {
+                if !block.randao_reveal.is_empty() {
+                    return Err(ConsensusError::rejected(
+                        BlockRejectReason::BadProducer,
+                        "Producer has no RANDAO commitment to reveal".into(),
+                    ));
+                }
+            } else if !verify_reveal(&validator.randao_commitment, &block.randao_reveal) {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadProducer,
+                    "RANDAO reveal does not open the producer's commitment".into(),
+                ));
+            }
+
+            if !block.verify_signature(&state.forks.fork_hash(block.index)) {
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadSignature,
+                    "Invalid block signature".into(),
+                ));
             }
 
             if let Some(evidences) = &block.slashing_evidence {
                 for (i, evidence) in evidences.iter().enumerate() {
-                    if !self.verify_evidence(evidence) {
-                        return Err(ConsensusError(format!("Invalid slashing evidence #{}", i)));
+                    if !self.verify_evidence(evidence, &state.forks) {
+                        return Err(ConsensusError::rejected(
+                            BlockRejectReason::BadEvidence,
+                            format!("Invalid slashing evidence #{}", i),
+                        ));
                     }
 
                     if let Some(producer) = &evidence.header1.producer {
@@ -430,7 +629,10 @@ impl ConsensusEngine for PoSEngine {
                             println!(" Valid Slashing Evidence found for validator {}", producer);
                         }
                     } else {
-                        return Err(ConsensusError("Evidence header missing producer".into()));
+                        return Err(ConsensusError::rejected(
+                            BlockRejectReason::BadEvidence,
+                            "Evidence header missing producer".into(),
+                        ));
                     }
                 }
             }
@@ -439,18 +641,74 @@ impl ConsensusEngine for PoSEngine {
                 "PoS: Block {} validated (producer: {}, stake: {})",
                 block.index,
                 &producer[..16.min(producer.len())],
-                validator.stake
+                entry.stake
             );
         } else {
             if block.hash != block.calculate_hash() {
-                return Err(ConsensusError("Invalid block hash".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadHash,
+                    "Invalid block hash".into(),
+                ));
             }
         }
         Ok(())
     }
+    fn maintenance_transactions(
+        &self,
+        state: &AccountState,
+        fork: &ForkId,
+        signing_version: u8,
+    ) -> Vec<Transaction> {
+        let Some(keys) = &self.validator_keys else {
+            return Vec::new();
+        };
+        let address = keys.sig_key.public_key_hex();
+        let Some(validator) = state.get_validator(&address) else {
+            return Vec::new();
+        };
+        let generation = if validator.randao_commitment.is_empty() {
+            validator.randao_generation
+        } else {
+            let remaining = self
+                .randao_onion(validator.randao_generation)
+                .and_then(|o| o.remaining(&validator.randao_commitment))
+                .unwrap_or(0);
+            if remaining > RANDAO_RECOMMIT_MARGIN {
+                return Vec::new();
+            }
+            validator.randao_generation + 1
+        };
+        let Some(commitment) = self.randao_commitment(generation) else {
+            return Vec::new();
+        };
+        let mut tx = Transaction::new_randao_commit(
+            address.clone(),
+            generation,
+            commitment,
+            MIN_TX_FEE,
+            state.get_nonce(&address),
+        );
+        tx.chain_id = fork.chain_id;
+        tx.fork_hash = fork.fork_hash.clone();
+        tx.signing_version = signing_version;
+        tx.hash = tx.calculate_hash();
+        tx.sign(&keys.sig_key);
+        vec![tx]
+    }
     fn consensus_type(&self) -> &'static str {
         "PoS"
     }
+    fn requires_signer(&self) -> bool {
+        true
+    }
+    fn signer_address(&self) -> Option<String> {
+        self.validator_keys
+            .as_ref()
+            .map(|keys| keys.sig_key.public_key_hex())
+    }
+    fn slot_clock(&self) -> Option<SlotClock> {
+        Some(self.clock())
+    }
     fn info(&self) -> String {
         format!(
             "PoS (min_stake: {}, checkpoints: {})",
@@ -481,26 +739,15 @@ impl ConsensusEngine for PoSEngine {
         let producer = block
             .producer
             .as_ref()
-            .ok_or(ConsensusError("Block has no producer".into()))?;
+            .ok_or(ConsensusError::new("Block has no producer".into()))?;
         let header = BlockHeader::from_block(block);
         let signature = block.signature.clone().unwrap_or_default();
         let key = (producer.clone(), header.index);
 
-        let block_hash_bytes =
-            hex::decode(&block.hash).unwrap_or_else(|_| block.hash.as_bytes().to_vec());
-        let mut block_contrib = Sha3_256::new();
-        block_contrib.update(&block_hash_bytes);
-        let contribution: [u8; 32] = block_contrib.finalize().into();
-        if let Ok(mut seed) = self.epoch_seed.write() {
-            for (i, byte) in seed.iter_mut().enumerate() {
-                *byte ^= contribution[i];
-            }
-        }
-
         let mut seen_blocks = self
             .seen_blocks
             .write()
-            .map_err(|_| ConsensusError("Lock error on seen_blocks".into()))?;
+            .map_err(|_| ConsensusError::new("Lock error on seen_blocks".into()))?;
 
         if let Some(existing) = seen_blocks.get(&key) {
             if existing.0.hash != header.hash {
@@ -508,36 +755,38 @@ impl ConsensusEngine for PoSEngine {
                     "DOUBLE-SIGN: {} signed two blocks for slot {}!",
                     producer, header.index
                 );
+                let reporter = self
+                    .validator_keys
+                    .as_ref()
+                    .map(|keys| keys.sig_key.public_key_hex())
+                    .unwrap_or_default();
                 let evidence = SlashingEvidence::new(
                     existing.0.clone(),
                     header,
                     existing.1.clone(),
                     signature,
-                );
+                )
+                .with_reporter(reporter);
                 let mut slashing_evidence = self
                     .slashing_evidence
                     .write()
-                    .map_err(|_| ConsensusError("Lock error on slashing_evidence".into()))?;
+                    .map_err(|_| ConsensusError::new("Lock error on slashing_evidence".into()))?;
                 slashing_evidence.push(evidence);
             }
         } else {
             seen_blocks.insert(key, (header, signature));
             if block.index > 0 && block.index % self.config.epoch_length == 0 {
-                if let Ok(mut seed) = self.epoch_seed.write() {
-                    *seed = [0u8; 32];
-                }
                 let _ = self.add_checkpoint(block);
             }
         }
         Ok(())
     }
 }
-#[cfg(test)]
+#[cfg(all(test, feature = "pos"))]
 mod tests {
     use super::*;
     use crate::account::AccountState;
     use crate::crypto::{KeyPair, ValidatorKeys};
-    use crate::transaction::Transaction;
 
     fn create_stake_tx(keypair: &KeyPair, amount: u64, nonce: u64) -> Transaction {
         let mut tx = Transaction::new_stake(keypair.public_key_hex(), amount, nonce);
@@ -567,13 +816,13 @@ mod tests {
         let mut block1 = Block::new(10, "prev".into(), vec![]);
         block1.producer = Some(alice.public_key_hex());
         block1.hash = "hash1".to_string();
-        block1.sign(&alice);
+        block1.sign(&alice, "");
 
         let mut block2 = Block::new(10, "prev".into(), vec![]);
         block2.timestamp += 1000;
         block2.producer = Some(alice.public_key_hex());
         block2.hash = "hash2".to_string();
-        block2.sign(&alice);
+        block2.sign(&alice, "");
 
         engine.record_block(&block1).unwrap();
         engine.record_block(&block2).unwrap();
@@ -581,7 +830,7 @@ mod tests {
         assert_eq!(engine.slashing_evidence.read().unwrap().len(), 1);
         let evidence = engine.slashing_evidence.read().unwrap()[0].clone();
         assert_eq!(evidence.header1.index, 10);
-        assert!(engine.verify_evidence(&evidence));
+        assert!(engine.verify_evidence(&evidence, &ForkSchedule::default()));
     }
 
     #[test]
@@ -1,5 +1,6 @@
 use super::{ConsensusEngine, ConsensusError};
 use crate::account::AccountState;
+use crate::events::BlockRejectReason;
 use crate::Block;
 use std::sync::RwLock;
 #[derive(Debug, Clone)]
@@ -90,9 +91,12 @@ impl PoWEngine {
 impl ConsensusEngine for PoWEngine {
     fn prepare_block(
         &self,
-        block: &mut Block,
+        _block: &mut Block,
         _state: &AccountState,
     ) -> Result<(), ConsensusError> {
+        Ok(())
+    }
+    fn seal_block(&self, block: &mut Block, _state: &AccountState) -> Result<(), ConsensusError> {
         block.hash = block.calculate_hash();
         self.mine(block);
         Ok(())
@@ -105,24 +109,33 @@ impl ConsensusEngine for PoWEngine {
     ) -> Result<(), ConsensusError> {
         if block.index == 0 {
             if block.hash != block.calculate_hash() {
-                return Err(ConsensusError("Invalid genesis block hash".into()));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadHash,
+                    "Invalid genesis block hash".into(),
+                ));
             }
             return Ok(());
         }
         if let Some(prev_block) = chain.last() {
             if block.previous_hash != prev_block.hash {
-                return Err(ConsensusError(format!(
-                    "Previous hash mismatch. Expected: {}, Got: {}",
-                    prev_block.hash, block.previous_hash
-                )));
+                return Err(ConsensusError::rejected(
+                    BlockRejectReason::BadParent,
+                    format!(
+                        "Previous hash mismatch. Expected: {}, Got: {}",
+                        prev_block.hash, block.previous_hash
+                    ),
+                ));
             }
         }
         let calculated_hash = block.calculate_hash();
         if block.hash != calculated_hash {
-            return Err(ConsensusError(format!(
-                "Invalid block hash. Calculated: {}, Existing: {}",
-                calculated_hash, block.hash
-            )));
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::BadHash,
+                format!(
+                    "Invalid block hash. Calculated: {}, Existing: {}",
+                    calculated_hash, block.hash
+                ),
+            ));
         }
 
         if block.index > 0 && block.index % self.config.adjustment_interval == 0 {
@@ -133,11 +146,14 @@ impl ConsensusEngine for PoWEngine {
         }
 
         if !self.meets_difficulty(&block.hash) {
-            return Err(ConsensusError(format!(
-                "Invalid PoW. {} leading zeros required, hash: {}",
-                self.get_difficulty(),
-                block.hash
-            )));
+            return Err(ConsensusError::rejected(
+                BlockRejectReason::BadProofOfWork,
+                format!(
+                    "Invalid PoW. {} leading zeros required, hash: {}",
+                    self.get_difficulty(),
+                    block.hash
+                ),
+            ));
         }
         Ok(())
     }
@@ -168,7 +184,7 @@ mod tests {
         let engine = PoWEngine::new(1);
         let mut block = Block::new(1, "0".repeat(64), vec![]);
         let state = AccountState::new();
-        engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
         assert!(block.hash.starts_with("0"));
     }
     #[test]
@@ -176,7 +192,7 @@ mod tests {
         let engine = PoWEngine::new(1);
         let mut block = Block::new(1, "0".repeat(64), vec![]);
         let state = AccountState::new();
-        engine.prepare_block(&mut block, &state).unwrap();
+        engine.seal_block(&mut block, &state).unwrap();
         assert!(engine.validate_block(&block, &[], &state).is_ok());
         let mut tampered = block.clone();
         tampered.hash = "invalid_hash".to_string();
@@ -189,8 +205,8 @@ mod tests {
         let mut block1 = Block::new(1, "0".repeat(64), vec![]);
         let mut block2 = Block::new(1, "0".repeat(64), vec![]);
         let state = AccountState::new();
-        easy.prepare_block(&mut block1, &state).unwrap();
-        hard.prepare_block(&mut block2, &state).unwrap();
+        easy.seal_block(&mut block1, &state).unwrap();
+        hard.seal_block(&mut block2, &state).unwrap();
         assert!(block1.hash.starts_with("0"));
         assert!(block2.hash.starts_with("00"));
     }
@@ -1,8 +1,13 @@
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
 use ed25519_dalek::{
     Signature, Signer, SigningKey, Verifier, VerifyingKey, SECRET_KEY_LENGTH, SIGNATURE_LENGTH,
 };
+#[cfg(feature = "pq-crypto")]
+use pqcrypto_dilithium::dilithium3;
+#[cfg(feature = "pq-crypto")]
+use pqcrypto_traits::sign::{PublicKey as _, SecretKey as _};
 use rand::RngCore;
-use sha3::{Digest, Sha3_256};
+use sha3::{Digest, Sha3_256, Sha3_512};
 use std::io::{Read, Write};
 use std::path::Path;
 #[derive(Debug)]
@@ -49,10 +54,23 @@ impl ValidatorKeys {
         let vrf_key = SchnorrkelKeypair::generate_with(&mut csprng);
         Ok(ValidatorKeys { sig_key, vrf_key })
     }
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CryptoError> {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = self.sig_key.signing_key.as_bytes().to_vec();
         bytes.extend_from_slice(&self.vrf_key.to_bytes());
-        std::fs::write(path.as_ref(), bytes).map_err(|e| CryptoError::Io(e.to_string()))?;
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        if bytes.len() < 128 {
+            return Err(CryptoError::InvalidKey("Key file too short".into()));
+        }
+        let sig_key = KeyPair::from_bytes(&bytes[0..32])?;
+        let vrf_key = SchnorrkelKeypair::from_bytes(&bytes[32..128])
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        Ok(ValidatorKeys { sig_key, vrf_key })
+    }
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CryptoError> {
+        std::fs::write(path.as_ref(), self.to_bytes())
+            .map_err(|e| CryptoError::Io(e.to_string()))?;
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
@@ -63,13 +81,181 @@ impl ValidatorKeys {
     }
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CryptoError> {
         let bytes = std::fs::read(path.as_ref()).map_err(|e| CryptoError::Io(e.to_string()))?;
-        if bytes.len() < 128 {
-            return Err(CryptoError::InvalidKey("Key file too short".into()));
+        Self::from_bytes(&bytes)
+    }
+}
+
+// Finality voting key; the public key is the compressed G1 point carried in
+// `ValidatorEntry::bls_public_key`.
+#[derive(Clone)]
+pub struct BlsKeyPair {
+    secret: Scalar,
+}
+
+impl BlsKeyPair {
+    pub fn generate() -> Self {
+        let mut wide = [0u8; 64];
+        rand::rng().fill_bytes(&mut wide);
+        BlsKeyPair {
+            secret: Scalar::from_bytes_wide(&wide),
         }
-        let sig_key = KeyPair::from_bytes(&bytes[0..32])?;
-        let vrf_key = SchnorrkelKeypair::from_bytes(&bytes[32..128])
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| {
+            CryptoError::InvalidKey(format!("Expected 32 bytes, got {}", bytes.len()))
+        })?;
+        let secret: Option<Scalar> = Scalar::from_bytes(&bytes).into();
+        match secret {
+            Some(secret) if secret != Scalar::zero() => Ok(BlsKeyPair { secret }),
+            _ => Err(CryptoError::InvalidKey("Invalid BLS secret scalar".into())),
+        }
+    }
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+    pub fn public_key_bytes(&self) -> [u8; 48] {
+        G1Affine::from(G1Projective::generator() * self.secret).to_compressed()
+    }
+    // Deterministic finality key of a validator, so the node does not keep
+    // a second secret next to its signing key.
+    pub fn derive(key: &KeyPair) -> Self {
+        let mut hasher = Sha3_512::new();
+        hasher.update(b"BDLM_BLS_KEY");
+        hasher.update(key.signing_key.as_bytes());
+        let wide: [u8; 64] = hasher.finalize().into();
+        BlsKeyPair {
+            secret: Scalar::from_bytes_wide(&wide),
+        }
+    }
+    pub fn sign(&self, message: &[u8]) -> [u8; 96] {
+        G2Affine::from(hash_to_g2(message) * self.secret).to_compressed()
+    }
+}
+
+// Maps a message to G2 by try-and-increment: a counter is hashed in until
+// the digest decodes as a point on the curve, whose cofactor is then
+// cleared so it lies in the prime-order subgroup.
+fn hash_to_g2(message: &[u8]) -> G2Projective {
+    let mut counter: u32 = 0;
+    loop {
+        let mut bytes = [0u8; 96];
+        for (i, chunk) in bytes.chunks_mut(32).enumerate() {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"BDLM_BLS_H2C");
+            hasher.update(counter.to_le_bytes());
+            hasher.update([i as u8]);
+            hasher.update(message);
+            chunk.copy_from_slice(&hasher.finalize());
+        }
+        // Compressed, not infinity, sign bit from the digest; both
+        // coordinates keep 381 bits so most candidates are below the modulus.
+        bytes[0] = (bytes[0] & 0x3f) | 0x80;
+        bytes[48] &= 0x1f;
+        let point: Option<G2Affine> = G2Affine::from_compressed_unchecked(&bytes).into();
+        if let Some(point) = point {
+            let point = G2Projective::from(point).clear_cofactor();
+            if !bool::from(point.is_identity()) {
+                return point;
+            }
+        }
+        counter += 1;
+    }
+}
+
+fn bls_public_key(bytes: &[u8]) -> Result<G1Affine, CryptoError> {
+    let bytes: &[u8; 48] = bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey(format!("Expected 48 bytes, got {}", bytes.len())))?;
+    let point: Option<G1Affine> = G1Affine::from_compressed(bytes).into();
+    point
+        .filter(|p| !bool::from(p.is_identity()))
+        .ok_or_else(|| CryptoError::InvalidKey("Invalid BLS public key".into()))
+}
+
+fn bls_signature(bytes: &[u8]) -> Result<G2Affine, CryptoError> {
+    let bytes: &[u8; 96] = bytes.try_into().map_err(|_| {
+        CryptoError::Verification(format!("Expected 96 bytes, got {}", bytes.len()))
+    })?;
+    let point: Option<G2Affine> = G2Affine::from_compressed(bytes).into();
+    point.ok_or_else(|| CryptoError::Verification("Invalid BLS signature".into()))
+}
+
+pub fn aggregate_bls_signatures<'a>(
+    signatures: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<[u8; 96], CryptoError> {
+    let mut aggregate = G2Projective::identity();
+    for signature in signatures {
+        aggregate += bls_signature(signature)?;
+    }
+    Ok(G2Affine::from(aggregate).to_compressed())
+}
+
+// Checks a signature aggregated from signers who all signed `message`.
+// Rogue-key attacks are ruled out by requiring a proof of possession for
+// every key before it is used here.
+pub fn verify_bls_aggregate<'a>(
+    public_keys: impl IntoIterator<Item = &'a [u8]>,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), CryptoError> {
+    let mut aggregate = G1Projective::identity();
+    let mut count = 0;
+    for key in public_keys {
+        aggregate += bls_public_key(key)?;
+        count += 1;
+    }
+    if count == 0 {
+        return Err(CryptoError::Verification("No BLS signers".into()));
+    }
+    let signature = bls_signature(signature)?;
+    let expected = pairing(
+        &G1Affine::from(aggregate),
+        &G2Affine::from(hash_to_g2(message)),
+    );
+    if pairing(&G1Affine::generator(), &signature) != expected {
+        return Err(CryptoError::Verification(
+            "BLS signature does not verify".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Post-quantum signing key. Dilithium secret keys don't embed the public
+// key, so both halves are kept together.
+#[cfg(feature = "pq-crypto")]
+pub struct DilithiumKeyPair {
+    public: dilithium3::PublicKey,
+    secret: dilithium3::SecretKey,
+}
+
+#[cfg(feature = "pq-crypto")]
+impl DilithiumKeyPair {
+    pub fn generate() -> Self {
+        let (public, secret) = dilithium3::keypair();
+        DilithiumKeyPair { public, secret }
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let pk_len = dilithium3::public_key_bytes();
+        if bytes.len() != pk_len + dilithium3::secret_key_bytes() {
+            return Err(CryptoError::InvalidKey(format!(
+                "Expected {} bytes, got {}",
+                pk_len + dilithium3::secret_key_bytes(),
+                bytes.len()
+            )));
+        }
+        let public = dilithium3::PublicKey::from_bytes(&bytes[..pk_len])
             .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
-        Ok(ValidatorKeys { sig_key, vrf_key })
+        let secret = dilithium3::SecretKey::from_bytes(&bytes[pk_len..])
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        Ok(DilithiumKeyPair { public, secret })
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.public.as_bytes().to_vec();
+        bytes.extend_from_slice(self.secret.as_bytes());
+        bytes
+    }
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        self.public.as_bytes().to_vec()
     }
 }
 impl KeyPair {
@@ -132,6 +318,10 @@ impl KeyPair {
     pub fn public_key_hex(&self) -> String {
         hex::encode(self.public_key_bytes())
     }
+    // The clamped signing scalar, which threshold key splitting shares out.
+    pub(crate) fn secret_scalar(&self) -> curve25519_dalek::Scalar {
+        self.signing_key.to_scalar()
+    }
     pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LENGTH] {
         let signature = self.signing_key.sign(message);
         signature.to_bytes()
@@ -218,6 +408,47 @@ mod tests {
         assert!(kp.verify(message, &bad_sig).is_err());
     }
     #[test]
+    fn test_bls_and_dilithium_key_roundtrip() {
+        let bls = BlsKeyPair::generate();
+        let restored = BlsKeyPair::from_bytes(&bls.to_bytes()).unwrap();
+        assert_eq!(bls.public_key_bytes(), restored.public_key_bytes());
+        assert!(BlsKeyPair::from_bytes(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_bls_aggregate_signature() {
+        let keys: Vec<BlsKeyPair> = (0..3).map(|_| BlsKeyPair::generate()).collect();
+        let public: Vec<[u8; 48]> = keys.iter().map(|k| k.public_key_bytes()).collect();
+        let sigs: Vec<[u8; 96]> = keys.iter().map(|k| k.sign(b"checkpoint")).collect();
+        let aggregate = aggregate_bls_signatures(sigs.iter().map(|s| &s[..])).unwrap();
+        verify_bls_aggregate(public.iter().map(|k| &k[..]), b"checkpoint", &aggregate).unwrap();
+        assert!(verify_bls_aggregate(public.iter().map(|k| &k[..]), b"other", &aggregate).is_err());
+        // A signer missing from the aggregate, or a forged one, fails.
+        assert!(verify_bls_aggregate(
+            public[..2].iter().map(|k| &k[..]),
+            b"checkpoint",
+            &aggregate
+        )
+        .is_err());
+        assert!(
+            verify_bls_aggregate(public.iter().map(|k| &k[..]), b"checkpoint", &[1u8; 96]).is_err()
+        );
+
+        let validator = KeyPair::generate().unwrap();
+        assert_eq!(
+            BlsKeyPair::derive(&validator).public_key_bytes(),
+            BlsKeyPair::derive(&validator).public_key_bytes()
+        );
+
+        #[cfg(feature = "pq-crypto")]
+        {
+            let dilithium = DilithiumKeyPair::generate();
+            let restored = DilithiumKeyPair::from_bytes(&dilithium.to_bytes()).unwrap();
+            assert_eq!(dilithium.public_key_bytes(), restored.public_key_bytes());
+            assert!(DilithiumKeyPair::from_bytes(&dilithium.public_key_bytes()).is_err());
+        }
+    }
+    #[test]
     fn test_save_and_load() {
         let kp = KeyPair::generate().unwrap();
         let path = "/tmp/test_budlum_key";
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_DATA_DIR: &str = "./data";
+pub const VALIDATOR_KEY_FILE: &str = "validator.key";
+// Where the database and snapshots sat under the data dir before it was
+// split per chain.
+pub const LEGACY_DB_DIR: &str = "budlum.db";
+pub const LEGACY_SNAPSHOT_DIR: &str = "snapshots";
+// Most addresses the peer store keeps; the least recently seen go first.
+pub const MAX_KNOWN_PEERS: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    root: PathBuf,
+}
+impl DataDir {
+    pub fn new(base: impl AsRef<Path>, chain_id: u64) -> Self {
+        DataDir {
+            root: base.as_ref().join(format!("chain-{}", chain_id)),
+        }
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    pub fn db_path(&self) -> PathBuf {
+        self.root.join("db")
+    }
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.root.join("snapshots")
+    }
+    pub fn keys_dir(&self) -> PathBuf {
+        self.root.join("keys")
+    }
+    pub fn validator_key_path(&self) -> PathBuf {
+        self.keys_dir().join(VALIDATOR_KEY_FILE)
+    }
+    pub fn peers_file(&self) -> PathBuf {
+        self.root.join("peers.json")
+    }
+    pub fn admin_socket_path(&self) -> PathBuf {
+        self.root.join("admin.sock")
+    }
+    pub fn admin_token_path(&self) -> PathBuf {
+        self.root.join("admin.token")
+    }
+    pub fn admin_audit_log(&self) -> PathBuf {
+        self.root.join("admin_audit.log")
+    }
+    // Moves a database and snapshots left by the old flat layout into this
+    // chain's directory, unless it already has its own. Returns the moves
+    // made as (from, to) pairs.
+    pub fn migrate_legacy_layout(&self, move_db: bool) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+        let Some(base) = self.root.parent() else {
+            return Ok(Vec::new());
+        };
+        let mut moves = Vec::new();
+        if move_db {
+            moves.push((base.join(LEGACY_DB_DIR), self.db_path()));
+        }
+        moves.push((base.join(LEGACY_SNAPSHOT_DIR), self.snapshot_dir()));
+        let mut moved = Vec::new();
+        for (from, to) in moves {
+            if !from.is_dir() || !is_missing_or_empty(&to)? {
+                continue;
+            }
+            std::fs::create_dir_all(&self.root)?;
+            if to.exists() {
+                std::fs::remove_dir(&to)?;
+            }
+            std::fs::rename(&from, &to)?;
+            moved.push((from, to));
+        }
+        Ok(moved)
+    }
+    pub fn ensure(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(self.snapshot_dir())?;
+        std::fs::create_dir_all(self.keys_dir())?;
+        Ok(())
+    }
+}
+
+fn is_missing_or_empty(dir: &Path) -> std::io::Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+// Peer addresses in the order they were last seen, oldest first, capped at
+// `MAX_KNOWN_PEERS`.
+#[derive(Debug, Clone, Default)]
+pub struct KnownPeers {
+    addrs: VecDeque<String>,
+}
+impl KnownPeers {
+    pub fn load(path: &Path) -> Self {
+        let mut peers = KnownPeers::default();
+        for addr in load_peers(path) {
+            peers.insert(addr);
+        }
+        peers
+    }
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save_peers(path, &self.to_vec())
+    }
+    pub fn insert(&mut self, addr: String) {
+        self.remove(&addr);
+        self.addrs.push_back(addr);
+        if self.addrs.len() > MAX_KNOWN_PEERS {
+            self.addrs.pop_front();
+        }
+    }
+    pub fn remove(&mut self, addr: &str) {
+        self.addrs.retain(|a| a != addr);
+    }
+    pub fn to_vec(&self) -> Vec<String> {
+        self.addrs.iter().cloned().collect()
+    }
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+}
+
+pub fn load_peers(path: &Path) -> Vec<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            println!("Failed to parse peer store {}: {}", path.display(), e);
+            vec![]
+        }),
+        Err(_) => vec![],
+    }
+}
+
+pub fn save_peers(path: &Path, peers: &[String]) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(peers)
+        .map_err(|e| format!("Failed to serialize peers: {}", e))?;
+    std::fs::write(path, data).map_err(|e| format!("Failed to write peer store: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_is_per_chain() {
+        let mainnet = DataDir::new("/var/budlum", 1);
+        let devnet = DataDir::new("/var/budlum", 1337);
+        assert_eq!(mainnet.db_path(), PathBuf::from("/var/budlum/chain-1/db"));
+        assert_eq!(
+            devnet.snapshot_dir(),
+            PathBuf::from("/var/budlum/chain-1337/snapshots")
+        );
+        assert_eq!(
+            devnet.validator_key_path(),
+            PathBuf::from("/var/budlum/chain-1337/keys/validator.key")
+        );
+        assert_eq!(
+            devnet.peers_file(),
+            PathBuf::from("/var/budlum/chain-1337/peers.json")
+        );
+    }
+
+    #[test]
+    fn test_peer_store_roundtrip() {
+        let base = std::env::temp_dir().join(format!(
+            "budlum_datadir_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let dir = DataDir::new(&base, 1337);
+        dir.ensure().unwrap();
+        assert!(load_peers(&dir.peers_file()).is_empty());
+        let peers = vec!["/ip4/127.0.0.1/tcp/4001".to_string()];
+        save_peers(&dir.peers_file(), &peers).unwrap();
+        assert_eq!(load_peers(&dir.peers_file()), peers);
+
+        let mut known = KnownPeers::load(&dir.peers_file());
+        for port in 0..MAX_KNOWN_PEERS as u16 + 10 {
+            known.insert(format!("/ip4/10.0.0.1/tcp/{}", port));
+        }
+        known.insert(peers[0].clone());
+        assert_eq!(known.len(), MAX_KNOWN_PEERS);
+        known.save(&dir.peers_file()).unwrap();
+        let reloaded = KnownPeers::load(&dir.peers_file()).to_vec();
+        assert_eq!(reloaded.last(), Some(&peers[0]));
+        assert!(!reloaded.contains(&"/ip4/10.0.0.1/tcp/10".to_string()));
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_migrate_legacy_layout() {
+        let base = std::env::temp_dir().join(format!(
+            "budlum_legacy_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(base.join(LEGACY_DB_DIR)).unwrap();
+        std::fs::write(base.join(LEGACY_DB_DIR).join("db"), b"data").unwrap();
+        std::fs::create_dir_all(base.join(LEGACY_SNAPSHOT_DIR)).unwrap();
+        let dir = DataDir::new(&base, 1337);
+        dir.ensure().unwrap();
+
+        let moved = dir.migrate_legacy_layout(true).unwrap();
+        assert_eq!(moved.len(), 2);
+        assert!(dir.db_path().join("db").exists());
+        assert!(!base.join(LEGACY_DB_DIR).exists());
+        assert!(dir.migrate_legacy_layout(true).unwrap().is_empty());
+
+        // A chain that already has its own database keeps it.
+        std::fs::create_dir_all(base.join(LEGACY_DB_DIR)).unwrap();
+        assert!(dir.migrate_legacy_layout(true).unwrap().is_empty());
+        assert!(base.join(LEGACY_DB_DIR).exists());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
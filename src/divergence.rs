@@ -0,0 +1,213 @@
+use crate::account::{StateProof, StateRootEntry};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+// Most keys one proof request may name. Missing keys are answered with two
+// neighbouring entries, so a response carries at most twice as many proofs.
+pub const MAX_STATE_PROOF_KEYS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDivergence {
+    pub key: String,
+    pub local: Option<StateRootEntry>,
+    pub remote: Option<StateRootEntry>,
+}
+
+// Where this node's state after a block parts ways with the producer's. Only
+// proofs that hash to the root the block claims are used, so the report
+// compares against what the producer actually committed to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DivergenceReport {
+    pub height: u64,
+    pub block_hash: String,
+    pub producer: Option<String>,
+    pub claimed_root: String,
+    pub computed_root: String,
+    pub requested_keys: usize,
+    pub proven_keys: usize,
+    pub first_divergence: Option<StateDivergence>,
+}
+
+// A rejected block whose state root did not match, waiting for proofs of
+// the keys the block touched.
+#[derive(Debug, Clone)]
+pub struct PendingDivergence {
+    pub height: u64,
+    pub block_hash: String,
+    pub producer: Option<String>,
+    pub claimed_root: String,
+    pub computed_root: String,
+    pub entries: Vec<StateRootEntry>,
+    pub keys: Vec<String>,
+    pub requested: bool,
+}
+
+impl PendingDivergence {
+    pub fn diagnose(&self, proofs: &[StateProof]) -> Result<DivergenceReport, String> {
+        if proofs.len() > 2 * MAX_STATE_PROOF_KEYS {
+            return Err(format!("{} state proofs is too many", proofs.len()));
+        }
+        if let Some(bad) = proofs.iter().find(|p| !p.verify(&self.claimed_root)) {
+            return Err(format!(
+                "Proof for {} does not match the claimed root {}",
+                bad.entry.key, self.claimed_root
+            ));
+        }
+        let mut proven_keys = 0;
+        let mut first_divergence = None;
+        for key in &self.keys {
+            let Some(remote) = proven_entry(proofs, key) else {
+                continue;
+            };
+            proven_keys += 1;
+            let local = self
+                .entries
+                .binary_search_by(|e| e.key.cmp(key))
+                .ok()
+                .map(|i| self.entries[i].clone());
+            if first_divergence.is_none() && local != remote {
+                first_divergence = Some(StateDivergence {
+                    key: key.clone(),
+                    local,
+                    remote,
+                });
+            }
+        }
+        Ok(DivergenceReport {
+            height: self.height,
+            block_hash: self.block_hash.clone(),
+            producer: self.producer.clone(),
+            claimed_root: self.claimed_root.clone(),
+            computed_root: self.computed_root.clone(),
+            requested_keys: self.keys.len(),
+            proven_keys,
+            first_divergence,
+        })
+    }
+}
+
+// The producer's entry for `key` from verified proofs: `Some(None)` when
+// its neighbours are adjacent leaves, `None` when the proofs settle neither.
+fn proven_entry(proofs: &[StateProof], key: &str) -> Option<Option<StateRootEntry>> {
+    if let Some(p) = proofs.iter().find(|p| p.entry.key == key) {
+        return Some(Some(p.entry.clone()));
+    }
+    let before = proofs
+        .iter()
+        .filter(|p| p.entry.key.as_str() < key)
+        .max_by_key(|p| p.index);
+    let after = proofs
+        .iter()
+        .filter(|p| p.entry.key.as_str() > key)
+        .min_by_key(|p| p.index);
+    let absent = match (before, after) {
+        (Some(b), Some(a)) => a.index == b.index + 1,
+        (None, Some(a)) => a.index == 0,
+        (Some(b), None) => b.is_last(),
+        (None, None) => false,
+    };
+    absent.then_some(None)
+}
+
+// Walks two key-sorted entry lists together and returns every key whose
+// entry differs or exists on one side only.
+pub fn changed_keys(before: &[StateRootEntry], after: &[StateRootEntry]) -> Vec<String> {
+    let (mut b, mut a) = (0, 0);
+    let mut keys = Vec::new();
+    loop {
+        match (before.get(b), after.get(a)) {
+            (None, None) => return keys,
+            (Some(x), Some(y)) => match x.key.cmp(&y.key) {
+                Ordering::Equal => {
+                    if x != y {
+                        keys.push(x.key.clone());
+                    }
+                    b += 1;
+                    a += 1;
+                }
+                Ordering::Less => {
+                    keys.push(x.key.clone());
+                    b += 1;
+                }
+                Ordering::Greater => {
+                    keys.push(y.key.clone());
+                    a += 1;
+                }
+            },
+            (Some(x), None) => {
+                keys.push(x.key.clone());
+                b += 1;
+            }
+            (None, Some(y)) => {
+                keys.push(y.key.clone());
+                a += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountState;
+
+    fn entry(key: &str, balance: u64) -> StateRootEntry {
+        StateRootEntry {
+            key: key.into(),
+            value: balance.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let before = vec![entry("a", 1), entry("b", 2), entry("d", 4)];
+        assert!(changed_keys(&before, &before).is_empty());
+
+        let after = vec![entry("a", 1), entry("b", 3), entry("c", 9)];
+        assert_eq!(changed_keys(&before, &after), vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_diagnose_from_proofs() {
+        let mut producer = AccountState::new();
+        producer.add_balance("bob", 7);
+        producer.add_balance("carol", 1);
+        let mut local = producer.clone();
+        local.add_balance("bob", 1);
+        let keys: Vec<String> = ["account:alice", "account:bob", "account:zed"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let pending = PendingDivergence {
+            height: 5,
+            block_hash: "h".into(),
+            producer: None,
+            claimed_root: producer.calculate_state_root(),
+            computed_root: local.calculate_state_root(),
+            entries: local.state_root_entries(),
+            keys: keys.clone(),
+            requested: true,
+        };
+
+        let proofs = producer.state_proofs(&keys);
+        assert!(proofs.len() < producer.state_root_entries().len());
+        let report = pending.diagnose(&proofs).unwrap();
+        assert_eq!((report.requested_keys, report.proven_keys), (3, 3));
+        let first = report.first_divergence.unwrap();
+        assert_eq!(first.key, "account:bob");
+        assert!(first.remote.unwrap().value.contains("\"balance\":7"));
+
+        // A neighbour that is not adjacent proves nothing about the gap.
+        let gappy: Vec<StateProof> = proofs
+            .iter()
+            .filter(|p| p.entry.key != "account:carol")
+            .cloned()
+            .collect();
+        let report = pending.diagnose(&gappy).unwrap();
+        assert!(report.proven_keys < 3);
+
+        let mut forged = proofs.clone();
+        forged[0].entry.value = "0".into();
+        assert!(pending.diagnose(&forged).is_err());
+    }
+}
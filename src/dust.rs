@@ -0,0 +1,118 @@
+use crate::hash::{hash_fields, merkle_branch, merkle_root, merkle_root_from_branch};
+use serde::{Deserialize, Serialize};
+
+pub const DUST_SWEEP_KEY_PREFIX: &str = "DUST_SWEEP:";
+
+// Accounts holding less than `threshold` that nothing has touched for
+// `inactive_epochs` epochs are dropped from the account map at an epoch
+// boundary. A zero in either field turns sweeping off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustSweepConfig {
+    pub threshold: u64,
+    pub inactive_epochs: u64,
+}
+
+impl DustSweepConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0 && self.inactive_epochs > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweptAccount {
+    pub address: String,
+    pub balance: u64,
+}
+
+impl SweptAccount {
+    // The epoch is part of the leaf so an account swept, restored and swept
+    // again with the same balance can be restored a second time.
+    pub fn leaf(&self, epoch: u64) -> String {
+        hash_fields(&[
+            b"dust",
+            &epoch.to_le_bytes(),
+            self.address.as_bytes(),
+            &self.balance.to_le_bytes(),
+        ])
+    }
+}
+
+pub fn sweep_storage_key(root: &str) -> String {
+    format!("{}{}", DUST_SWEEP_KEY_PREFIX, root)
+}
+
+// Root committed to state for one epoch's sweep. Accounts are leaves in the
+// order they were swept, which is address order.
+pub fn sweep_root(epoch: u64, swept: &[SweptAccount]) -> String {
+    let leaves: Vec<String> = swept.iter().map(|a| a.leaf(epoch)).collect();
+    merkle_root(&leaves)
+}
+
+// Shows that `account` was swept in `epoch`. Anyone holding the epoch's
+// sweep list can build one, and a RestoreAccount transaction carrying it
+// puts the balance back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustProof {
+    pub epoch: u64,
+    pub account: SweptAccount,
+    pub index: u64,
+    pub siblings: Vec<String>,
+}
+
+impl DustProof {
+    pub fn build(epoch: u64, swept: &[SweptAccount], address: &str) -> Option<Self> {
+        let index = swept.iter().position(|a| a.address == address)?;
+        let leaves: Vec<String> = swept.iter().map(|a| a.leaf(epoch)).collect();
+        Some(DustProof {
+            epoch,
+            account: swept[index].clone(),
+            index: index as u64,
+            siblings: merkle_branch(&leaves, index),
+        })
+    }
+
+    pub fn leaf(&self) -> String {
+        self.account.leaf(self.epoch)
+    }
+
+    pub fn root(&self) -> String {
+        merkle_root_from_branch(&self.leaf(), self.index, &self.siblings)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(data).map_err(|e| format!("Invalid dust proof payload: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dust_proofs_match_sweep_root() {
+        let swept: Vec<SweptAccount> = (0..5)
+            .map(|i| SweptAccount {
+                address: format!("addr{}", i),
+                balance: i + 1,
+            })
+            .collect();
+        let root = sweep_root(3, &swept);
+        for account in &swept {
+            let proof = DustProof::build(3, &swept, &account.address).unwrap();
+            assert_eq!(proof.root(), root);
+            assert_eq!(DustProof::decode(&proof.encode()).unwrap(), proof);
+        }
+        assert!(DustProof::build(3, &swept, "missing").is_none());
+
+        let mut forged = DustProof::build(3, &swept, "addr2").unwrap();
+        forged.account.balance = 1_000;
+        assert_ne!(forged.root(), root);
+        assert_ne!(sweep_root(4, &swept), root);
+        assert_eq!(sweep_root(3, &swept[..1]), swept[0].leaf(3));
+        assert_eq!(sweep_root(3, &[]), "");
+    }
+}
@@ -7,6 +7,10 @@ pub const PROTOCOL_VERSION_MINOR: u32 = 0;
 
 pub const PROTOCOL_VERSION: &str = "1.0.0";
 
+pub const NODE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const NODE_COMMIT: &str = env!("BUDLUM_GIT_COMMIT");
+
 pub const NETWORK_MAGIC: [u8; 4] = [0xBD, 0x4C, 0x4D, 0x01];
 
 #[derive(Debug, Clone, PartialEq)]
@@ -161,6 +165,11 @@ mod tests {
             vrf_output: Vec::new(),
             vrf_proof: Vec::new(),
             validator_set_hash: String::new(),
+            vote_evidence: None,
+            randao_reveal: Vec::new(),
+            version: 0,
+            extensions: Vec::new(),
+            state_snapshot_hash: String::new(),
         };
         let enc = encode_block_header(&header);
         assert!(!enc.is_empty());
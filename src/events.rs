@@ -0,0 +1,293 @@
+use crate::account::TreasurySource;
+use crate::state_diff::{StateDiff, StateDiffEvent};
+use crate::Transaction;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainEvent {
+    BlockAdded {
+        height: u64,
+        hash: String,
+    },
+    Reorged {
+        depth: u64,
+        fork_height: u64,
+        old_tip: String,
+        new_tip: String,
+    },
+    Finalized {
+        height: u64,
+        hash: String,
+    },
+    PendingTransaction(Transaction),
+    LocalTransaction(Transaction),
+    EpochRewards {
+        epoch: u64,
+        minted: u64,
+        recipients: usize,
+    },
+    TreasuryCredited {
+        height: u64,
+        source: TreasurySource,
+        amount: u64,
+    },
+    Halted {
+        height: u64,
+        stalled_secs: u64,
+    },
+    Recovered {
+        height: u64,
+    },
+    ValidatorSetUnsafe {
+        height: u64,
+        epoch: u64,
+        eligible_validators: usize,
+        eligible_stake: u64,
+        quorum_stake: u64,
+    },
+    ValidatorSetRestored {
+        height: u64,
+        epoch: u64,
+    },
+    SyncProgress {
+        height: u64,
+        target: u64,
+    },
+    PeerVersions {
+        counts: BTreeMap<String, usize>,
+    },
+    BlockRejected(BlockRejection),
+}
+
+// Why a block failed import, coarse enough to tell an invalid or malicious
+// block (bad signature, forged roots) from a node that is simply behind or
+// on another fork (bad parent, finality conflict).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum BlockRejectReason {
+    BadTransaction,
+    BadEvidence,
+    FinalityConflict,
+    WrongChain,
+    BadTxRoot,
+    BadVersion,
+    BadSnapshotCommitment,
+    BadValidatorSet,
+    BadStateRoot,
+    BadSignature,
+    BadParent,
+    BadHash,
+    BadProducer,
+    BadTimestamp,
+    BadProofOfWork,
+    TooLarge,
+    ExecutionFailed,
+    Other,
+}
+
+// A failed block import. Each check that can refuse a block sets its reason
+// where it fails, so the code operators see does not depend on the wording
+// of `message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockImportError {
+    pub reason: BlockRejectReason,
+    pub message: String,
+}
+
+impl BlockImportError {
+    pub fn new(reason: BlockRejectReason, message: String) -> Self {
+        BlockImportError { reason, message }
+    }
+}
+
+impl fmt::Display for BlockImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockRejection {
+    pub height: u64,
+    pub hash: String,
+    pub producer: Option<String>,
+    pub reason: BlockRejectReason,
+    pub error: String,
+}
+
+// State diffs travel on their own channel: they are large, so blocks only
+// compute them while someone is subscribed.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+    diffs: broadcast::Sender<StateDiffEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        let (diffs, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        EventBus { sender, diffs }
+    }
+
+    pub fn subscribe_state_diffs(&self) -> broadcast::Receiver<StateDiffEvent> {
+        self.diffs.subscribe()
+    }
+
+    pub fn wants_state_diffs(&self) -> bool {
+        self.diffs.receiver_count() > 0
+    }
+
+    pub fn emit_state_diff(&self, diff: StateDiff) {
+        let _ = self.diffs.send(StateDiffEvent::Applied(Arc::new(diff)));
+    }
+
+    pub fn emit_state_revert(&self, height: u64, hash: String) {
+        let _ = self.diffs.send(StateDiffEvent::Reverted { height, hash });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: ChainEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainMetrics {
+    pub blocks_added: u64,
+    pub reorgs: u64,
+    pub max_reorg_depth: u64,
+    pub blocks_reverted: u64,
+    pub best_height: u64,
+    pub finalized_height: u64,
+    pub lagged_events: u64,
+    pub rewards_minted: u64,
+    pub treasury_credited: u64,
+    pub halts: u64,
+    pub halted: bool,
+    pub validator_set_alerts: u64,
+    pub validator_set_unsafe: bool,
+    pub sync_target_height: u64,
+    pub peer_versions: BTreeMap<String, usize>,
+    pub blocks_rejected: BTreeMap<BlockRejectReason, u64>,
+}
+
+#[cfg(feature = "metrics")]
+impl ChainMetrics {
+    pub fn record(&mut self, event: &ChainEvent) {
+        match event {
+            ChainEvent::BlockAdded { height, .. } => {
+                self.blocks_added += 1;
+                self.best_height = *height;
+            }
+            ChainEvent::Reorged { depth, .. } => {
+                self.reorgs += 1;
+                self.blocks_reverted += depth;
+                self.max_reorg_depth = self.max_reorg_depth.max(*depth);
+            }
+            ChainEvent::Finalized { height, .. } => {
+                self.finalized_height = *height;
+            }
+            ChainEvent::EpochRewards { minted, .. } => {
+                self.rewards_minted += minted;
+            }
+            ChainEvent::TreasuryCredited { amount, .. } => {
+                self.treasury_credited += amount;
+            }
+            ChainEvent::Halted { .. } => {
+                self.halts += 1;
+                self.halted = true;
+            }
+            ChainEvent::Recovered { .. } => {
+                self.halted = false;
+            }
+            ChainEvent::ValidatorSetUnsafe { .. } => {
+                self.validator_set_alerts += 1;
+                self.validator_set_unsafe = true;
+            }
+            ChainEvent::ValidatorSetRestored { .. } => {
+                self.validator_set_unsafe = false;
+            }
+            ChainEvent::SyncProgress { target, .. } => {
+                self.sync_target_height = *target;
+            }
+            ChainEvent::PeerVersions { counts } => {
+                self.peer_versions = counts.clone();
+            }
+            ChainEvent::BlockRejected(rejection) => {
+                *self.blocks_rejected.entry(rejection.reason).or_default() += 1;
+            }
+            ChainEvent::PendingTransaction(_) | ChainEvent::LocalTransaction(_) => {}
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_follow_events() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+        bus.emit(ChainEvent::BlockAdded {
+            height: 5,
+            hash: "a".into(),
+        });
+        bus.emit(ChainEvent::Reorged {
+            depth: 3,
+            fork_height: 3,
+            old_tip: "a".into(),
+            new_tip: "b".into(),
+        });
+        bus.emit(ChainEvent::Finalized {
+            height: 2,
+            hash: "c".into(),
+        });
+
+        let mut metrics = ChainMetrics::default();
+        while let Ok(event) = rx.try_recv() {
+            metrics.record(&event);
+        }
+        assert_eq!(metrics.blocks_added, 1);
+        assert_eq!(metrics.best_height, 5);
+        assert_eq!(metrics.reorgs, 1);
+        assert_eq!(metrics.max_reorg_depth, 3);
+        assert_eq!(metrics.finalized_height, 2);
+    }
+
+    #[test]
+    fn test_block_reject_reasons() {
+        use BlockRejectReason::*;
+        let mut metrics = ChainMetrics::default();
+        for _ in 0..2 {
+            metrics.record(&ChainEvent::BlockRejected(BlockRejection {
+                height: 7,
+                hash: "h".into(),
+                producer: None,
+                reason: BadSignature,
+                error: "Invalid block signature".into(),
+            }));
+        }
+        assert_eq!(metrics.blocks_rejected[&BadSignature], 2);
+    }
+}
@@ -0,0 +1,874 @@
+//! FROST threshold signing for ed25519 (RFC 9591, FROST(Ed25519, SHA-512)).
+//!
+//! A PoA authority key can be split into `n` shares so that any `threshold`
+//! of them sign together. The aggregated output is an ordinary ed25519
+//! signature under the original public key, so block verification does not
+//! change.
+use crate::crypto::KeyPair;
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::Scalar;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const CONTEXT: &[u8] = b"FROST-ED25519-SHA512-v1";
+// Block production waits on remote share holders, so they get little time.
+pub const REMOTE_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_SHARE_LINE_BYTES: u64 = 64 * 1024;
+// A share request is accepted this long after it was issued, and its nonce
+// is remembered for as long, so a captured request cannot be replayed.
+const SHARE_REQUEST_WINDOW_MS: u64 = 30_000;
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    Scalar::from_hash(hasher)
+}
+
+fn hash_bytes(label: &[u8], data: &[u8]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(CONTEXT)
+        .chain_update(label)
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    rand::rng().fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+fn identifier_scalar(identifier: u16) -> Scalar {
+    Scalar::from(identifier as u64)
+}
+
+fn decode_scalar(value: &str) -> Result<Scalar, String> {
+    let bytes: [u8; 32] = hex::decode(value)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| format!("Invalid scalar {}", value))?;
+    Option::from(Scalar::from_canonical_bytes(bytes))
+        .ok_or_else(|| format!("Non-canonical scalar {}", value))
+}
+
+fn decode_point(value: &str) -> Result<EdwardsPoint, String> {
+    let bytes = hex::decode(value).map_err(|_| format!("Invalid point {}", value))?;
+    CompressedEdwardsY::from_slice(&bytes)
+        .ok()
+        .and_then(|p| p.decompress())
+        .ok_or_else(|| format!("Invalid point {}", value))
+}
+
+fn encode_point(point: &EdwardsPoint) -> String {
+    hex::encode(point.compress().as_bytes())
+}
+
+// Public half of a split key: the group key every share signs for and each
+// participant's verifying share, used to pin a bad signature share on its
+// sender.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupKey {
+    pub public_key: String,
+    pub threshold: u16,
+    pub verifying_shares: BTreeMap<u16, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    pub identifier: u16,
+    secret: String,
+    pub group: GroupKey,
+}
+
+// Secret nonces from round one. They are consumed by `KeyShare::sign` so a
+// nonce pair can never sign twice.
+pub struct SigningNonces {
+    identifier: u16,
+    hiding: Scalar,
+    binding: Scalar,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningCommitment {
+    pub identifier: u16,
+    pub hiding: String,
+    pub binding: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureShare {
+    pub identifier: u16,
+    pub share: String,
+}
+
+// Splits an existing ed25519 key with a trusted dealer. The group public key
+// equals the original key, so an authority keeps its on-chain identity.
+pub fn split_key(
+    key: &KeyPair,
+    threshold: u16,
+    participants: u16,
+) -> Result<Vec<KeyShare>, String> {
+    if threshold == 0 || threshold > participants {
+        return Err(format!(
+            "Threshold must be between 1 and {}, got {}",
+            participants, threshold
+        ));
+    }
+    let mut coefficients = vec![key.secret_scalar()];
+    coefficients.extend((1..threshold).map(|_| random_scalar()));
+    let secrets: Vec<(u16, Scalar)> = (1..=participants)
+        .map(|id| {
+            let x = identifier_scalar(id);
+            let value = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |acc, coefficient| acc * x + coefficient);
+            (id, value)
+        })
+        .collect();
+    let group = GroupKey {
+        public_key: key.public_key_hex(),
+        threshold,
+        verifying_shares: secrets
+            .iter()
+            .map(|(id, secret)| (*id, encode_point(&(secret * ED25519_BASEPOINT_POINT))))
+            .collect(),
+    };
+    Ok(secrets
+        .into_iter()
+        .map(|(identifier, secret)| KeyShare {
+            identifier,
+            secret: hex::encode(secret.as_bytes()),
+            group: group.clone(),
+        })
+        .collect())
+}
+
+// What every participant derives from the same commitment list: the binding
+// factors, the group commitment R and the ed25519 challenge.
+struct SigningRound {
+    identifiers: Vec<u16>,
+    binding_factors: BTreeMap<u16, Scalar>,
+    commitments: BTreeMap<u16, (EdwardsPoint, EdwardsPoint)>,
+    group_commitment: EdwardsPoint,
+    challenge: Scalar,
+}
+
+impl SigningRound {
+    fn new(
+        group: &GroupKey,
+        commitments: &[SigningCommitment],
+        message: &[u8],
+    ) -> Result<Self, String> {
+        let mut sorted: Vec<&SigningCommitment> = commitments.iter().collect();
+        sorted.sort_by_key(|c| c.identifier);
+        if sorted
+            .windows(2)
+            .any(|w| w[0].identifier == w[1].identifier)
+        {
+            return Err("Duplicate signing commitment".into());
+        }
+        if sorted.len() < group.threshold as usize {
+            return Err(format!(
+                "{} signing commitments, threshold is {}",
+                sorted.len(),
+                group.threshold
+            ));
+        }
+        let mut encoded = Vec::new();
+        let mut points = BTreeMap::new();
+        for commitment in &sorted {
+            if !group.verifying_shares.contains_key(&commitment.identifier) {
+                return Err(format!("Unknown participant {}", commitment.identifier));
+            }
+            let hiding = decode_point(&commitment.hiding)?;
+            let binding = decode_point(&commitment.binding)?;
+            encoded.extend_from_slice(identifier_scalar(commitment.identifier).as_bytes());
+            encoded.extend_from_slice(hiding.compress().as_bytes());
+            encoded.extend_from_slice(binding.compress().as_bytes());
+            points.insert(commitment.identifier, (hiding, binding));
+        }
+
+        let group_public = decode_point(&group.public_key)?;
+        let mut prefix = group_public.compress().as_bytes().to_vec();
+        prefix.extend_from_slice(&hash_bytes(b"msg", message));
+        prefix.extend_from_slice(&hash_bytes(b"com", &encoded));
+        let binding_factors: BTreeMap<u16, Scalar> = points
+            .keys()
+            .map(|id| {
+                let rho =
+                    hash_to_scalar(&[CONTEXT, b"rho", &prefix, identifier_scalar(*id).as_bytes()]);
+                (*id, rho)
+            })
+            .collect();
+        let group_commitment = points
+            .iter()
+            .map(|(id, (hiding, binding))| hiding + binding * binding_factors[id])
+            .sum::<EdwardsPoint>();
+        let challenge = hash_to_scalar(&[
+            group_commitment.compress().as_bytes(),
+            group_public.compress().as_bytes(),
+            message,
+        ]);
+        Ok(SigningRound {
+            identifiers: points.keys().copied().collect(),
+            binding_factors,
+            commitments: points,
+            group_commitment,
+            challenge,
+        })
+    }
+
+    fn lagrange(&self, identifier: u16) -> Scalar {
+        let x_i = identifier_scalar(identifier);
+        let (numerator, denominator) = self
+            .identifiers
+            .iter()
+            .filter(|id| **id != identifier)
+            .fold((Scalar::ONE, Scalar::ONE), |(num, den), id| {
+                let x_j = identifier_scalar(*id);
+                (num * x_j, den * (x_j - x_i))
+            });
+        numerator * denominator.invert()
+    }
+}
+
+impl KeyShare {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let data = std::fs::read(path.as_ref())
+            .map_err(|e| format!("Cannot read {}: {}", path.as_ref().display(), e))?;
+        let share: KeyShare = serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid key share {}: {}", path.as_ref().display(), e))?;
+        share.check()?;
+        Ok(share)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap_or_default()
+    }
+
+    // The secret must match the verifying share the group recorded for it.
+    fn check(&self) -> Result<(), String> {
+        let expected = self
+            .group
+            .verifying_shares
+            .get(&self.identifier)
+            .ok_or_else(|| format!("Share {} is not part of its group", self.identifier))?;
+        if encode_point(&(decode_scalar(&self.secret)? * ED25519_BASEPOINT_POINT)) != *expected {
+            return Err(format!(
+                "Share {} does not match its verifying share",
+                self.identifier
+            ));
+        }
+        Ok(())
+    }
+
+    // Round one: fresh nonces, bound to the share's secret as RFC 9591
+    // recommends, and their public commitments.
+    pub fn commit(&self) -> Result<(SigningNonces, SigningCommitment), String> {
+        let secret = decode_scalar(&self.secret)?;
+        let nonce = || {
+            let mut random = [0u8; 32];
+            rand::rng().fill_bytes(&mut random);
+            hash_to_scalar(&[CONTEXT, b"nonce", &random, secret.as_bytes()])
+        };
+        let nonces = SigningNonces {
+            identifier: self.identifier,
+            hiding: nonce(),
+            binding: nonce(),
+        };
+        let commitment = SigningCommitment {
+            identifier: self.identifier,
+            hiding: encode_point(&(nonces.hiding * ED25519_BASEPOINT_POINT)),
+            binding: encode_point(&(nonces.binding * ED25519_BASEPOINT_POINT)),
+        };
+        Ok((nonces, commitment))
+    }
+
+    // Round two: this participant's share of the signature over `message`.
+    pub fn sign(
+        &self,
+        nonces: SigningNonces,
+        commitments: &[SigningCommitment],
+        message: &[u8],
+    ) -> Result<SignatureShare, String> {
+        if nonces.identifier != self.identifier {
+            return Err("Signing nonces belong to another participant".into());
+        }
+        let round = SigningRound::new(&self.group, commitments, message)?;
+        let (hiding, binding) = round
+            .commitments
+            .get(&self.identifier)
+            .ok_or_else(|| format!("No commitment from participant {}", self.identifier))?;
+        if *hiding != nonces.hiding * ED25519_BASEPOINT_POINT
+            || *binding != nonces.binding * ED25519_BASEPOINT_POINT
+        {
+            return Err("Commitment does not match our nonces".into());
+        }
+        let share = nonces.hiding
+            + nonces.binding * round.binding_factors[&self.identifier]
+            + round.lagrange(self.identifier) * decode_scalar(&self.secret)? * round.challenge;
+        Ok(SignatureShare {
+            identifier: self.identifier,
+            share: hex::encode(share.as_bytes()),
+        })
+    }
+}
+
+impl GroupKey {
+    // Combines the shares into an ed25519 signature. Each share is checked
+    // against its participant's verifying share first so a bad one is named.
+    pub fn aggregate(
+        &self,
+        commitments: &[SigningCommitment],
+        shares: &[SignatureShare],
+        message: &[u8],
+    ) -> Result<[u8; 64], String> {
+        let round = SigningRound::new(self, commitments, message)?;
+        let mut z = Scalar::ZERO;
+        let mut seen = Vec::new();
+        for share in shares {
+            let (hiding, binding) = round.commitments.get(&share.identifier).ok_or_else(|| {
+                format!("Share from uncommitted participant {}", share.identifier)
+            })?;
+            if seen.contains(&share.identifier) {
+                return Err(format!("Duplicate share from {}", share.identifier));
+            }
+            seen.push(share.identifier);
+            let z_i = decode_scalar(&share.share)?;
+            let public = decode_point(&self.verifying_shares[&share.identifier])?;
+            let expected = hiding
+                + binding * round.binding_factors[&share.identifier]
+                + public * (round.challenge * round.lagrange(share.identifier));
+            if z_i * ED25519_BASEPOINT_POINT != expected {
+                return Err(format!("Invalid signature share from {}", share.identifier));
+            }
+            z += z_i;
+        }
+        if seen.len() != round.identifiers.len() {
+            return Err(format!(
+                "{} of {} signature shares",
+                seen.len(),
+                round.identifiers.len()
+            ));
+        }
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(round.group_commitment.compress().as_bytes());
+        signature[32..].copy_from_slice(z.as_bytes());
+        let public_key = hex::decode(&self.public_key).map_err(|e| e.to_string())?;
+        crate::crypto::verify_signature(message, &signature, &public_key)
+            .map_err(|e| format!("Aggregated signature does not verify: {}", e))?;
+        Ok(signature)
+    }
+}
+
+// One member of a signing group as seen by the coordinator. Shares held by
+// this node are `LocalParticipant`s; a remote signer only has to answer the
+// same two rounds.
+pub trait ThresholdParticipant: Send + Sync {
+    fn identifier(&self) -> u16;
+    fn commit(&self) -> Result<SigningCommitment, String>;
+    fn sign(
+        &self,
+        commitments: &[SigningCommitment],
+        message: &[u8],
+    ) -> Result<SignatureShare, String>;
+}
+
+pub struct LocalParticipant {
+    share: KeyShare,
+    nonces: Mutex<Option<SigningNonces>>,
+}
+
+impl LocalParticipant {
+    pub fn new(share: KeyShare) -> Self {
+        LocalParticipant {
+            share,
+            nonces: Mutex::new(None),
+        }
+    }
+}
+
+impl ThresholdParticipant for LocalParticipant {
+    fn identifier(&self) -> u16 {
+        self.share.identifier
+    }
+
+    fn commit(&self) -> Result<SigningCommitment, String> {
+        let (nonces, commitment) = self.share.commit()?;
+        *self.nonces.lock().map_err(|e| e.to_string())? = Some(nonces);
+        Ok(commitment)
+    }
+
+    fn sign(
+        &self,
+        commitments: &[SigningCommitment],
+        message: &[u8],
+    ) -> Result<SignatureShare, String> {
+        let nonces = self
+            .nonces
+            .lock()
+            .map_err(|e| e.to_string())?
+            .take()
+            .ok_or("No commitment outstanding")?;
+        self.share.sign(nonces, commitments, message)
+    }
+}
+
+// Share holder on another host, reached over TCP with one JSON request per
+// line. The token both sides share never crosses the wire: every request
+// carries an HMAC under it over a fresh nonce, the issue time and the body,
+// so only the coordinator can ask the holder to sign and a sniffed request
+// cannot be replayed or altered. The channel itself is not encrypted, so
+// holders listen on loopback or a private link and are reached through a
+// tunnel (SSH, WireGuard) when they sit on other hosts.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum ShareRequest {
+    Commit,
+    Sign {
+        commitments: Vec<SigningCommitment>,
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ShareEnvelope {
+    nonce: String,
+    issued_at: u64,
+    body: String,
+    mac: String,
+}
+
+impl ShareEnvelope {
+    fn seal(token: &str, request: &ShareRequest) -> Result<Self, String> {
+        let mut nonce = [0u8; 16];
+        rand::rng().fill_bytes(&mut nonce);
+        let nonce = hex::encode(nonce);
+        let issued_at = now_ms();
+        let body = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        let mac = hex::encode(
+            share_mac(token, &nonce, issued_at, &body)
+                .finalize()
+                .into_bytes(),
+        );
+        Ok(ShareEnvelope {
+            nonce,
+            issued_at,
+            body,
+            mac,
+        })
+    }
+}
+
+fn share_mac(token: &str, nonce: &str, issued_at: u64, body: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(token.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(nonce.as_bytes());
+    mac.update(&issued_at.to_be_bytes());
+    mac.update(body.as_bytes());
+    mac
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ShareResponse {
+    Commitment(SigningCommitment),
+    Share(SignatureShare),
+    Error(String),
+}
+
+fn read_share_line(stream: impl Read) -> std::io::Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_SHARE_LINE_BYTES)).read_line(&mut line)?;
+    Ok(line)
+}
+
+// Shared secret between a coordinator and its remote share holders.
+pub fn load_token(path: impl AsRef<Path>) -> Result<String, String> {
+    let path = path.as_ref();
+    let token = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let token = token.trim();
+    if token.len() < 16 {
+        return Err(format!(
+            "{} must hold a token of at least 16 characters",
+            path.display()
+        ));
+    }
+    Ok(token.to_string())
+}
+
+pub struct RemoteParticipant {
+    identifier: u16,
+    addr: String,
+    token: String,
+}
+
+impl RemoteParticipant {
+    pub fn new(identifier: u16, addr: String, token: String) -> Self {
+        RemoteParticipant {
+            identifier,
+            addr,
+            token,
+        }
+    }
+
+    fn call(&self, request: ShareRequest) -> Result<ShareResponse, String> {
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve {}: {}", self.addr, e))?
+            .next()
+            .ok_or_else(|| format!("Cannot resolve {}", self.addr))?;
+        let mut stream = TcpStream::connect_timeout(&addr, REMOTE_TIMEOUT)
+            .map_err(|e| format!("Cannot reach {}: {}", self.addr, e))?;
+        stream
+            .set_read_timeout(Some(REMOTE_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(REMOTE_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let mut line = serde_json::to_vec(&ShareEnvelope::seal(&self.token, &request)?)
+            .map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        stream
+            .write_all(&line)
+            .map_err(|e| format!("Cannot write to {}: {}", self.addr, e))?;
+        let reply =
+            read_share_line(&stream).map_err(|e| format!("No answer from {}: {}", self.addr, e))?;
+        match serde_json::from_str(&reply)
+            .map_err(|e| format!("Invalid answer from {}: {}", self.addr, e))?
+        {
+            ShareResponse::Error(e) => Err(e),
+            response => Ok(response),
+        }
+    }
+}
+
+impl ThresholdParticipant for RemoteParticipant {
+    fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    fn commit(&self) -> Result<SigningCommitment, String> {
+        match self.call(ShareRequest::Commit)? {
+            ShareResponse::Commitment(c) if c.identifier == self.identifier => Ok(c),
+            _ => Err(format!("{} did not answer with its commitment", self.addr)),
+        }
+    }
+
+    fn sign(
+        &self,
+        commitments: &[SigningCommitment],
+        message: &[u8],
+    ) -> Result<SignatureShare, String> {
+        let request = ShareRequest::Sign {
+            commitments: commitments.to_vec(),
+            message: hex::encode(message),
+        };
+        match self.call(request)? {
+            ShareResponse::Share(s) if s.identifier == self.identifier => Ok(s),
+            _ => Err(format!("{} did not answer with its share", self.addr)),
+        }
+    }
+}
+
+// The share holder's side of `RemoteParticipant`. Connections are served
+// one at a time, which also keeps a commit and its sign in order.
+pub struct ShareServer {
+    participant: LocalParticipant,
+    token: String,
+    // Nonces of accepted requests by issue time, dropped once outside the window.
+    seen_nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl ShareServer {
+    pub fn new(share: KeyShare, token: String) -> Self {
+        ShareServer {
+            participant: LocalParticipant::new(share),
+            token,
+            seen_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn open(&self, envelope: ShareEnvelope) -> Result<ShareRequest, String> {
+        let mac = hex::decode(&envelope.mac).map_err(|_| "Invalid request signature")?;
+        share_mac(
+            &self.token,
+            &envelope.nonce,
+            envelope.issued_at,
+            &envelope.body,
+        )
+        .verify_slice(&mac)
+        .map_err(|_| "Invalid request signature")?;
+        let now = now_ms();
+        if now.abs_diff(envelope.issued_at) > SHARE_REQUEST_WINDOW_MS {
+            return Err("Request expired, check the clocks of both hosts".into());
+        }
+        let mut seen = self.seen_nonces.lock().map_err(|e| e.to_string())?;
+        seen.retain(|_, issued_at| now.abs_diff(*issued_at) <= SHARE_REQUEST_WINDOW_MS);
+        if seen.insert(envelope.nonce, envelope.issued_at).is_some() {
+            return Err("Replayed request".into());
+        }
+        serde_json::from_str(&envelope.body).map_err(|e| format!("Invalid request: {}", e))
+    }
+
+    fn handle(&self, line: &str) -> ShareResponse {
+        let request = match serde_json::from_str(line)
+            .map_err(|e| format!("Invalid request: {}", e))
+            .and_then(|envelope| self.open(envelope))
+        {
+            Ok(request) => request,
+            Err(e) => return ShareResponse::Error(e),
+        };
+        let result = match request {
+            ShareRequest::Commit => self.participant.commit().map(ShareResponse::Commitment),
+            ShareRequest::Sign {
+                commitments,
+                message,
+            } => hex::decode(&message)
+                .map_err(|e| format!("Invalid message: {}", e))
+                .and_then(|message| self.participant.sign(&commitments, &message))
+                .map(ShareResponse::Share),
+        };
+        result.unwrap_or_else(ShareResponse::Error)
+    }
+
+    pub fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        info!(
+            "Serving key share {} on {}",
+            self.participant.identifier(),
+            listener.local_addr()?
+        );
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            let result = stream
+                .set_read_timeout(Some(REMOTE_TIMEOUT))
+                .and_then(|_| stream.set_write_timeout(Some(REMOTE_TIMEOUT)))
+                .and_then(|_| read_share_line(&stream))
+                .and_then(|line| {
+                    let mut reply = serde_json::to_vec(&self.handle(&line)).unwrap_or_default();
+                    reply.push(b'\n');
+                    stream.write_all(&reply)
+                });
+            if let Err(e) = result {
+                warn!("Key share request failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Runs the two-round ceremony with the first `threshold` participants that
+// answer round one.
+pub struct ThresholdSigner {
+    group: GroupKey,
+    participants: Vec<Box<dyn ThresholdParticipant>>,
+}
+
+impl ThresholdSigner {
+    pub fn new(
+        group: GroupKey,
+        participants: Vec<Box<dyn ThresholdParticipant>>,
+    ) -> Result<Self, String> {
+        if participants.len() < group.threshold as usize {
+            return Err(format!(
+                "{} participants configured, threshold is {}",
+                participants.len(),
+                group.threshold
+            ));
+        }
+        Ok(ThresholdSigner {
+            group,
+            participants,
+        })
+    }
+
+    pub fn local(shares: Vec<KeyShare>) -> Result<Self, String> {
+        Self::with_remotes(shares, Vec::new())
+    }
+
+    // The group key comes from the local shares, so the coordinator holds at
+    // least one share itself.
+    pub fn with_remotes(
+        shares: Vec<KeyShare>,
+        remotes: Vec<RemoteParticipant>,
+    ) -> Result<Self, String> {
+        let group = shares.first().ok_or("No key shares given")?.group.clone();
+        if shares.iter().any(|s| s.group != group) {
+            return Err("Key shares belong to different groups".into());
+        }
+        let mut identifiers: Vec<u16> = shares.iter().map(|s| s.identifier).collect();
+        identifiers.extend(remotes.iter().map(|r| r.identifier));
+        for (i, id) in identifiers.iter().enumerate() {
+            if !group.verifying_shares.contains_key(id) || identifiers[..i].contains(id) {
+                return Err(format!("Participant {} is unknown or listed twice", id));
+            }
+        }
+        let mut participants: Vec<Box<dyn ThresholdParticipant>> = shares
+            .into_iter()
+            .map(|s| Box::new(LocalParticipant::new(s)) as Box<dyn ThresholdParticipant>)
+            .collect();
+        participants.extend(
+            remotes
+                .into_iter()
+                .map(|r| Box::new(r) as Box<dyn ThresholdParticipant>),
+        );
+        Self::new(group, participants)
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        self.group.public_key.clone()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<[u8; 64], String> {
+        let threshold = self.group.threshold as usize;
+        let mut signers = Vec::new();
+        let mut commitments = Vec::new();
+        for participant in &self.participants {
+            if signers.len() == threshold {
+                break;
+            }
+            match participant.commit() {
+                Ok(commitment) => {
+                    signers.push(participant);
+                    commitments.push(commitment);
+                }
+                Err(e) => warn!(
+                    "Threshold participant {} unavailable: {}",
+                    participant.identifier(),
+                    e
+                ),
+            }
+        }
+        if signers.len() < threshold {
+            return Err(format!(
+                "Only {} of {} threshold participants answered",
+                signers.len(),
+                threshold
+            ));
+        }
+        let shares = signers
+            .iter()
+            .map(|p| p.sign(&commitments, message))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.group.aggregate(&commitments, &shares, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_signature_verifies_under_original_key() {
+        let key = KeyPair::generate().unwrap();
+        assert!(split_key(&key, 4, 3).is_err());
+        let shares = split_key(&key, 2, 3).unwrap();
+        assert_eq!(shares[0].group.public_key, key.public_key_hex());
+
+        let message = b"block digest";
+        for pair in [[0, 1], [1, 2], [2, 0]] {
+            let signer =
+                ThresholdSigner::local(pair.iter().map(|i| shares[*i].clone()).collect()).unwrap();
+            let signature = signer.sign(message).unwrap();
+            assert!(key.verify(message, &signature).is_ok());
+        }
+        assert!(ThresholdSigner::local(vec![shares[0].clone()]).is_err());
+
+        let (n1, c1) = shares[0].commit().unwrap();
+        let (n2, c2) = shares[1].commit().unwrap();
+        let commitments = vec![c1, c2];
+        let s1 = shares[0].sign(n1, &commitments, message).unwrap();
+        let mut s2 = shares[1].sign(n2, &commitments, message).unwrap();
+        s2.share = s1.share.clone();
+        let err = shares[0]
+            .group
+            .aggregate(&commitments, &[s1, s2], message)
+            .unwrap_err();
+        assert!(err.contains("from 2"), "{}", err);
+
+        let mut forged = shares[0].clone();
+        forged.identifier = 2;
+        assert!(forged.check().is_err());
+    }
+
+    #[test]
+    fn test_remote_participant_signs_over_tcp() {
+        let key = KeyPair::generate().unwrap();
+        let shares = split_key(&key, 2, 3).unwrap();
+        let token = "0123456789abcdef".to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = ShareServer::new(shares[1].clone(), token.clone());
+        std::thread::spawn(move || server.serve(listener));
+
+        let bad = RemoteParticipant::new(2, addr.clone(), "wrong-token-value".into());
+        assert_eq!(bad.commit().unwrap_err(), "Invalid request signature");
+        assert!(ThresholdSigner::with_remotes(
+            vec![shares[0].clone()],
+            vec![RemoteParticipant::new(1, addr.clone(), token.clone())],
+        )
+        .is_err());
+
+        let remote = RemoteParticipant::new(2, addr, token);
+        let signer = ThresholdSigner::with_remotes(vec![shares[0].clone()], vec![remote]).unwrap();
+        let signature = signer.sign(b"block digest").unwrap();
+        assert!(key.verify(b"block digest", &signature).is_ok());
+    }
+
+    #[test]
+    fn test_share_server_rejects_replayed_and_stale_requests() {
+        let key = KeyPair::generate().unwrap();
+        let shares = split_key(&key, 2, 3).unwrap();
+        let token = "0123456789abcdef";
+        let server = ShareServer::new(shares[1].clone(), token.to_string());
+
+        let line =
+            serde_json::to_string(&ShareEnvelope::seal(token, &ShareRequest::Commit).unwrap())
+                .unwrap();
+        assert!(matches!(server.handle(&line), ShareResponse::Commitment(_)));
+        assert!(matches!(
+            server.handle(&line),
+            ShareResponse::Error(e) if e == "Replayed request"
+        ));
+
+        let mut tampered = ShareEnvelope::seal(token, &ShareRequest::Commit).unwrap();
+        tampered.body = r#"{"method":"sign","commitments":[],"message":"00"}"#.into();
+        assert!(matches!(
+            server.handle(&serde_json::to_string(&tampered).unwrap()),
+            ShareResponse::Error(e) if e == "Invalid request signature"
+        ));
+
+        let mut stale = ShareEnvelope::seal(token, &ShareRequest::Commit).unwrap();
+        stale.issued_at -= SHARE_REQUEST_WINDOW_MS + 1;
+        stale.mac = hex::encode(
+            share_mac(token, &stale.nonce, stale.issued_at, &stale.body)
+                .finalize()
+                .into_bytes(),
+        );
+        assert!(matches!(
+            server.handle(&serde_json::to_string(&stale).unwrap()),
+            ShareResponse::Error(e) if e.starts_with("Request expired")
+        ));
+    }
+}
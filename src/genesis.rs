@@ -1,6 +1,13 @@
+use crate::account::StakingRules;
 use crate::block::{Block, DEFAULT_CHAIN_ID};
+use crate::chain_config::{ChainLimits, ForkActivation, ForkSchedule};
+use crate::dust::DustSweepConfig;
+use crate::mempool::MempoolConfig;
+use crate::network::bootnodes::bootnode_peer_id;
+use crate::network::dns_seeds::validate_seed_domain;
 use crate::transaction::Transaction;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 pub const BLOCK_REWARD: u64 = 50;
 
@@ -10,7 +17,101 @@ pub const GENESIS_ALLOCATION: u64 = 1_000_000_000;
 
 pub const GENESIS_TIMESTAMP: u128 = 0;
 
+pub const STAKING_REWARD_BPS: u64 = 500;
+
+pub const EPOCHS_PER_YEAR: u64 = 164_250;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardConfig {
+    pub annual_rate_bps: u64,
+    pub epochs_per_year: u64,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        RewardConfig {
+            annual_rate_bps: STAKING_REWARD_BPS,
+            epochs_per_year: EPOCHS_PER_YEAR,
+        }
+    }
+}
+
+impl RewardConfig {
+    pub fn epoch_reward(&self, effective_stake: u64) -> u64 {
+        if self.epochs_per_year == 0 {
+            return 0;
+        }
+        (effective_stake as u128 * self.annual_rate_bps as u128
+            / 10_000
+            / self.epochs_per_year as u128) as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryConfig {
+    pub address: String,
+    pub fee_bps: u64,
+    pub reward_bps: u64,
+}
+
+impl TreasuryConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.address.is_empty() && (self.fee_bps > 0 || self.reward_bps > 0)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.fee_bps > 10_000 || self.reward_bps > 10_000 {
+            return Err("Treasury shares must be at most 10000 basis points".into());
+        }
+        if self.address.is_empty() && (self.fee_bps > 0 || self.reward_bps > 0) {
+            return Err("Treasury share configured without a treasury address".into());
+        }
+        Ok(())
+    }
+
+    pub fn share(&self, amount: u64, bps: u64) -> u64 {
+        if self.address.is_empty() {
+            return 0;
+        }
+        (amount as u128 * bps.min(10_000) as u128 / 10_000) as u64
+    }
+}
+
+// Authorities allowed to keep a halted PoA chain moving once no block has
+// been produced for `after_secs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencyQuorum {
+    pub authorities: Vec<String>,
+    pub after_secs: u64,
+}
+
+impl EmergencyQuorum {
+    pub fn is_enabled(&self) -> bool {
+        !self.authorities.is_empty() && self.after_secs > 0
+    }
+
+    // Either both fields are set or neither; a quorum without authorities
+    // or a delay would be mistaken for one that can take over.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.authorities.is_empty() && self.after_secs == 0 {
+            return Ok(());
+        }
+        if self.authorities.is_empty() {
+            return Err("Emergency quorum has a delay but no authorities".into());
+        }
+        if self.after_secs == 0 {
+            return Err("Emergency quorum needs a non-zero after_secs".into());
+        }
+        let unique: std::collections::BTreeSet<&String> = self.authorities.iter().collect();
+        if unique.len() != self.authorities.len() || unique.iter().any(|a| a.is_empty()) {
+            return Err("Emergency quorum authorities must be distinct and non-empty".into());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GenesisConfig {
     pub chain_id: u64,
 
@@ -21,6 +122,44 @@ pub struct GenesisConfig {
     pub block_reward: u64,
 
     pub base_fee: u64,
+
+    #[serde(default)]
+    pub staking_rewards: RewardConfig,
+
+    #[serde(default)]
+    pub treasury: TreasuryConfig,
+
+    #[serde(default)]
+    pub emergency_quorum: EmergencyQuorum,
+
+    #[serde(default)]
+    pub limits: ChainLimits,
+
+    // The network's default admission policy; nodes may override it.
+    #[serde(default)]
+    pub mempool: MempoolConfig,
+
+    // Sweeping decides which accounts exist, so every node of the chain
+    // must use the same settings.
+    #[serde(default)]
+    pub dust_sweep: DustSweepConfig,
+
+    // Minimum stake and validator cap, applied at epoch boundaries.
+    #[serde(default)]
+    pub staking: StakingRules,
+
+    #[serde(default)]
+    pub forks: Vec<ForkActivation>,
+
+    // Canonical peers of the network, dialed at startup alongside
+    // `--bootstrap`.
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+
+    // Domains whose TXT/SRV records list peers, used when no bootnode or
+    // stored peer is reachable.
+    #[serde(default)]
+    pub dns_seeds: Vec<String>,
 }
 
 impl Default for GenesisConfig {
@@ -31,6 +170,16 @@ impl Default for GenesisConfig {
             validators: vec![],
             block_reward: BLOCK_REWARD,
             base_fee: BASE_FEE,
+            staking_rewards: RewardConfig::default(),
+            treasury: TreasuryConfig::default(),
+            emergency_quorum: EmergencyQuorum::default(),
+            limits: ChainLimits::default(),
+            mempool: MempoolConfig::default(),
+            dust_sweep: DustSweepConfig::default(),
+            staking: StakingRules::default(),
+            forks: vec![],
+            bootnodes: vec![],
+            dns_seeds: vec![],
         }
     }
 }
@@ -43,6 +192,32 @@ impl GenesisConfig {
         }
     }
 
+    // Reads a JSON chain spec. Omitted fields keep their defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Cannot read chain spec {}: {}", path.display(), e))?;
+        let spec: GenesisConfig = serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid chain spec {}: {}", path.display(), e))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.treasury.validate()?;
+        self.emergency_quorum.validate()?;
+        self.limits.validate()?;
+        self.mempool.validate()?;
+        ForkSchedule::new(&self.forks)?;
+        for addr in &self.bootnodes {
+            bootnode_peer_id(addr)?;
+        }
+        for domain in &self.dns_seeds {
+            validate_seed_domain(domain)?;
+        }
+        Ok(())
+    }
+
     pub fn with_allocation(mut self, address: String, amount: u64) -> Self {
         self.allocations.push((address, amount));
         self
@@ -74,6 +249,11 @@ impl GenesisConfig {
             vrf_output: Vec::new(),
             vrf_proof: Vec::new(),
             validator_set_hash: String::new(),
+            vote_evidence: None,
+            randao_reveal: Vec::new(),
+            version: 0,
+            extensions: Vec::new(),
+            state_snapshot_hash: String::new(),
         };
 
         block.tx_root = block.calculate_tx_root();
@@ -92,6 +272,34 @@ mod tests {
         assert_eq!(config.chain_id, DEFAULT_CHAIN_ID);
         assert_eq!(config.block_reward, BLOCK_REWARD);
         assert_eq!(config.base_fee, BASE_FEE);
+        assert_eq!(config.staking_rewards.annual_rate_bps, STAKING_REWARD_BPS);
+    }
+
+    #[test]
+    fn test_treasury_config_validation() {
+        let treasury = TreasuryConfig {
+            address: "treasury".into(),
+            fee_bps: 2_000,
+            reward_bps: 0,
+        };
+        assert!(treasury.validate().is_ok());
+        assert_eq!(treasury.share(25, treasury.fee_bps), 5);
+        let orphan = TreasuryConfig {
+            fee_bps: 100,
+            ..Default::default()
+        };
+        assert!(orphan.validate().is_err());
+        assert_eq!(orphan.share(1_000, orphan.fee_bps), 0);
+    }
+
+    #[test]
+    fn test_epoch_reward_rate() {
+        let rewards = RewardConfig {
+            annual_rate_bps: 1000,
+            epochs_per_year: 10,
+        };
+        assert_eq!(rewards.epoch_reward(10_000), 100);
+        assert_eq!(rewards.epoch_reward(50), 0);
     }
 
     #[test]
@@ -114,4 +322,43 @@ mod tests {
         assert_eq!(config.allocations.len(), 1);
         assert_eq!(config.validators.len(), 1);
     }
+
+    #[test]
+    fn test_load_chain_spec() {
+        let peer = libp2p::PeerId::random();
+        let path = std::env::temp_dir().join(format!("budlum_spec_{}.json", peer));
+        let spec = format!(
+            r#"{{"chain_id": 9, "bootnodes": ["/dns4/boot.example/tcp/4001/p2p/{}"], "dns_seeds": ["seed.example"]}}"#,
+            peer
+        );
+        std::fs::write(&path, spec).unwrap();
+        let loaded = GenesisConfig::load(&path).unwrap();
+        assert_eq!(loaded.chain_id, 9);
+        assert_eq!(loaded.block_reward, BLOCK_REWARD);
+        assert_eq!(loaded.bootnodes.len(), 1);
+        assert_eq!(loaded.dns_seeds, vec!["seed.example"]);
+
+        std::fs::write(
+            &path,
+            r#"{"chain_id": 9, "bootnodes": ["/ip4/1.2.3.4/tcp/1"]}"#,
+        )
+        .unwrap();
+        assert!(GenesisConfig::load(&path).is_err());
+
+        for quorum in [
+            r#"{"authorities": [], "after_secs": 60}"#,
+            r#"{"authorities": ["a"], "after_secs": 0}"#,
+            r#"{"authorities": ["a", "a"], "after_secs": 60}"#,
+        ] {
+            std::fs::write(
+                &path,
+                format!(r#"{{"chain_id": 9, "emergency_quorum": {}}}"#, quorum),
+            )
+            .unwrap();
+            assert!(GenesisConfig::load(&path)
+                .unwrap_err()
+                .contains("Emergency quorum"));
+        }
+        let _ = std::fs::remove_file(path);
+    }
 }
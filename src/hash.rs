@@ -13,6 +13,68 @@ pub fn hash_fields(fields: &[&[u8]]) -> String {
     let result = hasher.finalize();
     hex::encode(result)
 }
+pub fn hash_pair(left: &str, right: &str) -> String {
+    hash_fields(&[left.as_bytes(), right.as_bytes()])
+}
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+// Root of a binary Merkle tree whose odd levels repeat their last node.
+// Empty when there are no leaves.
+pub fn merkle_root(leaves: &[String]) -> String {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.pop().unwrap_or_default()
+}
+// Sibling hashes from the leaf at `index` up to the root.
+pub fn merkle_branch(leaves: &[String], index: usize) -> Vec<String> {
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    let mut branch = Vec::new();
+    while level.len() > 1 {
+        branch.push(level.get(position ^ 1).unwrap_or(&level[position]).clone());
+        level = next_level(&level);
+        position /= 2;
+    }
+    branch
+}
+// `merkle_branch` for several leaves, building the tree only once.
+pub fn merkle_branches(leaves: &[String], indices: &[usize]) -> Vec<Vec<String>> {
+    let mut level = leaves.to_vec();
+    let mut positions = indices.to_vec();
+    let mut branches = vec![Vec::new(); indices.len()];
+    while level.len() > 1 {
+        for (branch, position) in branches.iter_mut().zip(positions.iter_mut()) {
+            branch.push(
+                level
+                    .get(*position ^ 1)
+                    .unwrap_or(&level[*position])
+                    .clone(),
+            );
+            *position /= 2;
+        }
+        level = next_level(&level);
+    }
+    branches
+}
+pub fn merkle_root_from_branch(leaf: &str, index: u64, branch: &[String]) -> String {
+    let mut current = leaf.to_string();
+    let mut position = index;
+    for sibling in branch {
+        current = if position.is_multiple_of(2) {
+            hash_pair(&current, sibling)
+        } else {
+            hash_pair(sibling, &current)
+        };
+        position /= 2;
+    }
+    current
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -25,4 +87,20 @@ mod tests {
         assert_ne!(hash1, hash3);
         assert_eq!(hash1.len(), 64);
     }
+    #[test]
+    fn test_merkle_branches() {
+        let leaves: Vec<String> = (0..5).map(|i| calculate_hash(&[i])).collect();
+        let root = merkle_root(&leaves);
+        for (i, leaf) in leaves.iter().enumerate() {
+            let branch = merkle_branch(&leaves, i);
+            assert_eq!(merkle_root_from_branch(leaf, i as u64, &branch), root);
+        }
+        let indices = [4, 0, 3];
+        let branches = merkle_branches(&leaves, &indices);
+        for (i, branch) in indices.iter().zip(&branches) {
+            assert_eq!(branch, &merkle_branch(&leaves, *i));
+        }
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&[]), "");
+    }
 }
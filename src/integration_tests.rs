@@ -49,7 +49,7 @@ mod integration_tests {
         let attacker_keypair = KeyPair::generate().unwrap();
         let mut block = Block::new(1, "0".repeat(64), vec![]);
         block.producer = Some(validator_pubkey);
-        block.sign(&attacker_keypair);
+        block.sign(&attacker_keypair, "");
 
         let result = engine.validate_block(&block, &[], &state);
         assert!(result.is_err(), "Forged signature should be rejected");
@@ -172,10 +172,10 @@ mod integration_tests {
         let keypair = KeyPair::generate().unwrap();
         let pubkey = keypair.public_key_hex();
         let mut block = Block::new(1, "0".repeat(64), vec![]);
-        block.sign(&keypair);
+        block.sign(&keypair, "");
 
         assert_eq!(block.producer.as_ref().unwrap(), &pubkey);
-        assert!(block.verify_signature());
+        assert!(block.verify_signature(""));
 
         block.transactions.push(Transaction::new(
             "attacker".to_string(),
@@ -187,7 +187,7 @@ mod integration_tests {
         block.hash = block.calculate_hash();
 
         assert!(
-            !block.verify_signature(),
+            !block.verify_signature(""),
             "Signature for old hash should fail verification"
         );
     }
@@ -240,12 +240,14 @@ mod integration_tests {
         if expected_my_slot.address == pubkey1 {
             let result = engine.prepare_block(&mut block, &state);
             assert!(result.is_ok());
+            engine.seal_block(&mut block, &state).unwrap();
             assert!(block.signature.is_some());
         }
     }
     #[test]
     fn test_finality_checkpoint_enforcement() {
-        use crate::consensus::finality::{FinalityCert, ValidatorEntry, ValidatorSetSnapshot};
+        use crate::consensus::finality::test_keys::{bls_key, sign_cert};
+        use crate::consensus::finality::{sign_pop, FinalityCert};
 
         let keys = crate::crypto::ValidatorKeys::generate().unwrap();
         let sig_key = keys.sig_key.clone();
@@ -255,12 +257,17 @@ mod integration_tests {
         let mut blockchain = Blockchain::new(consensus, None, 1337, None);
         blockchain.init_genesis_account(&pubkey);
 
-        let mut validator = crate::account::Validator::new(pubkey.clone(), 1000);
-        validator.active = true;
+        blockchain.state.add_validator(pubkey.clone(), 1000);
+        let bls = bls_key(&pubkey);
         blockchain
             .state
-            .validators
-            .insert(pubkey.clone(), validator);
+            .register_bls_key(
+                &pubkey,
+                bls.public_key_bytes().to_vec(),
+                sign_pop(&bls, 1337, &pubkey),
+                1337,
+            )
+            .unwrap();
 
         for _ in 1..=100 {
             blockchain.produce_block(pubkey.clone());
@@ -268,23 +275,18 @@ mod integration_tests {
 
         let checkpoint_block = blockchain.chain[100].clone();
 
-        let entry = ValidatorEntry {
-            address: pubkey.clone(),
-            stake: 1000,
-            bls_public_key: Vec::new(),
-            pop_signature: Vec::new(),
-        };
-        let snapshot = ValidatorSetSnapshot::new(1, vec![entry]);
-
-        let cert = FinalityCert {
+        let snapshot = blockchain.state.active_validator_snapshot(1);
+        let mut cert = FinalityCert {
             epoch: 1,
             checkpoint_height: 100,
             checkpoint_hash: checkpoint_block.hash.clone(),
-            agg_sig_bls: vec![1; 48],
+            agg_sig_bls: Vec::new(),
             bitmap: vec![0b0000_0001],
             set_hash: blockchain.get_validator_set_hash(),
+            state_root: checkpoint_block.state_root.clone(),
         };
 
+        sign_cert(&mut cert, &snapshot, 1337);
         blockchain.handle_finality_cert(cert).unwrap();
         assert_eq!(blockchain.finalized_height, 100);
         assert_eq!(blockchain.finalized_hash, checkpoint_block.hash);
@@ -292,7 +294,7 @@ mod integration_tests {
         let mut conflicting_block = Block::new(100, "wrong_prev".into(), vec![]);
         conflicting_block.hash = "conflicting_hash".into();
         conflicting_block.producer = Some(pubkey);
-        conflicting_block.sign(&sig_key);
+        conflicting_block.sign(&sig_key, "");
 
         let result = blockchain.validate_and_add_block(conflicting_block);
         assert!(result.is_err());
@@ -0,0 +1,444 @@
+use crate::snapshot::SnapshotManifest;
+use crate::transaction::TransactionType;
+use crate::{Block, Transaction};
+use serde::{Deserialize, Serialize};
+
+// Display encoding for RPC responses and CLI output. Field names are fixed
+// here rather than following the internal structs, byte fields are 0x
+// prefixed hex and u128 values are decimal strings, since JSON numbers lose
+// precision past 2^53 in most tooling. Hashes and addresses keep the plain
+// hex strings the rest of the API takes as input. Storage and the network
+// never use these types.
+
+pub fn hex_bytes(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+pub fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("expected 0x-prefixed hex, got {:?}", value))?;
+    hex::decode(digits).map_err(|e| format!("invalid hex {:?}: {}", value, e))
+}
+
+pub mod u128_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn tx_type_name(tx_type: &TransactionType) -> &'static str {
+    match tx_type {
+        TransactionType::Transfer => "transfer",
+        TransactionType::Stake => "stake",
+        TransactionType::Unstake => "unstake",
+        TransactionType::Vote => "vote",
+        TransactionType::ClaimRewards => "claim_rewards",
+        TransactionType::Unjail => "unjail",
+        TransactionType::Burn => "burn",
+        TransactionType::Approve => "approve",
+        TransactionType::TransferFrom => "transfer_from",
+        TransactionType::Batch => "batch",
+        TransactionType::Redelegate => "redelegate",
+        TransactionType::BridgeLock => "bridge_lock",
+        TransactionType::BridgeBurn => "bridge_burn",
+        TransactionType::BridgeMint => "bridge_mint",
+        TransactionType::BridgeRelease => "bridge_release",
+        TransactionType::LightClientUpdate => "light_client_update",
+        TransactionType::RestoreAccount => "restore_account",
+        TransactionType::RandaoCommit => "randao_commit",
+    }
+}
+
+pub fn tx_type_from_name(name: &str) -> Result<TransactionType, String> {
+    Ok(match name {
+        "transfer" => TransactionType::Transfer,
+        "stake" => TransactionType::Stake,
+        "unstake" => TransactionType::Unstake,
+        "vote" => TransactionType::Vote,
+        "claim_rewards" => TransactionType::ClaimRewards,
+        "unjail" => TransactionType::Unjail,
+        "burn" => TransactionType::Burn,
+        "approve" => TransactionType::Approve,
+        "transfer_from" => TransactionType::TransferFrom,
+        "batch" => TransactionType::Batch,
+        "redelegate" => TransactionType::Redelegate,
+        "bridge_lock" => TransactionType::BridgeLock,
+        "bridge_burn" => TransactionType::BridgeBurn,
+        "bridge_mint" => TransactionType::BridgeMint,
+        "bridge_release" => TransactionType::BridgeRelease,
+        "light_client_update" => TransactionType::LightClientUpdate,
+        "restore_account" => TransactionType::RestoreAccount,
+        "randao_commit" => TransactionType::RandaoCommit,
+        other => return Err(format!("unknown transaction type {:?}", other)),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionJson {
+    pub hash: String,
+    #[serde(rename = "type")]
+    pub tx_type: String,
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    pub data: String,
+    #[serde(with = "u128_string")]
+    pub timestamp: u128,
+    pub chain_id: u64,
+    pub fork_hash: String,
+    pub expires_at_height: u64,
+    #[serde(default)]
+    pub signing_version: u8,
+    pub signature: Option<String>,
+}
+
+impl From<&Transaction> for TransactionJson {
+    fn from(tx: &Transaction) -> Self {
+        TransactionJson {
+            hash: tx.hash.clone(),
+            tx_type: tx_type_name(&tx.tx_type).to_string(),
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            data: hex_bytes(&tx.data),
+            timestamp: tx.timestamp,
+            chain_id: tx.chain_id,
+            fork_hash: tx.fork_hash.clone(),
+            expires_at_height: tx.expires_at_height,
+            signing_version: tx.signing_version,
+            signature: tx.signature.as_deref().map(hex_bytes),
+        }
+    }
+}
+
+// Submitted transactions come back in the same encoding. The hash is
+// recomputed rather than trusted, so a client that hashes differently is
+// rejected here instead of producing a transaction nobody can look up.
+impl TryFrom<&TransactionJson> for Transaction {
+    type Error = String;
+
+    fn try_from(json: &TransactionJson) -> Result<Self, Self::Error> {
+        let tx = Transaction {
+            from: json.from.clone(),
+            to: json.to.clone(),
+            amount: json.amount,
+            fee: json.fee,
+            nonce: json.nonce,
+            data: parse_hex_bytes(&json.data)?,
+            timestamp: json.timestamp,
+            hash: json.hash.clone(),
+            signature: json.signature.as_deref().map(parse_hex_bytes).transpose()?,
+            chain_id: json.chain_id,
+            tx_type: tx_type_from_name(&json.tx_type)?,
+            fork_hash: json.fork_hash.clone(),
+            expires_at_height: json.expires_at_height,
+            signing_version: json.signing_version,
+        };
+        let expected = tx.calculate_hash();
+        if tx.hash != expected {
+            return Err(format!(
+                "transaction hash {} does not match contents ({})",
+                tx.hash, expected
+            ));
+        }
+        Ok(tx)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockJson {
+    pub height: u64,
+    pub hash: String,
+    pub previous_hash: String,
+    #[serde(with = "u128_string")]
+    pub timestamp: u128,
+    pub chain_id: u64,
+    pub producer: Option<String>,
+    pub signature: Option<String>,
+    pub state_root: String,
+    pub tx_root: String,
+    pub validator_set_hash: String,
+    pub state_snapshot_hash: String,
+    pub nonce: u64,
+    pub epoch: u64,
+    pub slot: u64,
+    pub vrf_output: String,
+    pub vrf_proof: String,
+    pub randao_reveal: String,
+    pub version: u8,
+    pub extensions: String,
+    pub slashing_evidence_count: usize,
+    pub vote_evidence_count: usize,
+    pub transactions: Vec<TransactionJson>,
+}
+
+impl From<&Block> for BlockJson {
+    fn from(block: &Block) -> Self {
+        BlockJson {
+            height: block.index,
+            hash: block.hash.clone(),
+            previous_hash: block.previous_hash.clone(),
+            timestamp: block.timestamp,
+            chain_id: block.chain_id,
+            producer: block.producer.clone(),
+            signature: block.signature.as_deref().map(hex_bytes),
+            state_root: block.state_root.clone(),
+            tx_root: block.tx_root.clone(),
+            validator_set_hash: block.validator_set_hash.clone(),
+            state_snapshot_hash: block.state_snapshot_hash.clone(),
+            nonce: block.nonce,
+            epoch: block.epoch,
+            slot: block.slot,
+            vrf_output: hex_bytes(&block.vrf_output),
+            vrf_proof: hex_bytes(&block.vrf_proof),
+            randao_reveal: hex_bytes(&block.randao_reveal),
+            version: block.version,
+            extensions: hex_bytes(&block.extensions),
+            slashing_evidence_count: block.slashing_evidence.as_ref().map_or(0, Vec::len),
+            vote_evidence_count: block.vote_evidence.as_ref().map_or(0, Vec::len),
+            transactions: block
+                .transactions
+                .iter()
+                .map(TransactionJson::from)
+                .collect(),
+        }
+    }
+}
+
+// Where an included transaction landed. A block is only accepted if every
+// transaction in it applies, so an included transaction always succeeded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptJson {
+    pub transaction_hash: String,
+    pub block_hash: String,
+    pub block_height: u64,
+    pub index: u64,
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub status: String,
+}
+
+impl ReceiptJson {
+    pub fn new(block: &Block, index: usize) -> Option<Self> {
+        let tx = block.transactions.get(index)?;
+        Some(ReceiptJson {
+            transaction_hash: tx.hash.clone(),
+            block_hash: block.hash.clone(),
+            block_height: block.index,
+            index: index as u64,
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            amount: tx.amount,
+            fee: tx.fee,
+            status: "success".to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifestJson {
+    pub version: u32,
+    pub height: u64,
+    pub block_hash: String,
+    pub chain_id: u64,
+    #[serde(with = "u128_string")]
+    pub created_at: u128,
+    pub finalized_height: u64,
+    pub finalized_hash: String,
+    pub account_count: u64,
+    pub chunk_hashes: Vec<String>,
+    pub chunk_root: String,
+    pub snapshot_hash: String,
+    pub base_height: Option<u64>,
+}
+
+impl From<&SnapshotManifest> for SnapshotManifestJson {
+    fn from(manifest: &SnapshotManifest) -> Self {
+        SnapshotManifestJson {
+            version: manifest.version,
+            height: manifest.height,
+            block_hash: manifest.block_hash.clone(),
+            chain_id: manifest.chain_id,
+            created_at: manifest.created_at,
+            finalized_height: manifest.finalized_height,
+            finalized_hash: manifest.finalized_hash.clone(),
+            account_count: manifest.account_count,
+            chunk_hashes: manifest.chunk_hashes.clone(),
+            chunk_root: manifest.chunk_root.clone(),
+            snapshot_hash: manifest.snapshot_hash.clone(),
+            base_height: manifest.base_height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_tx() -> Transaction {
+        let mut tx = Transaction::new_with_fee("aa".into(), "bb".into(), 5, 1, 3, vec![0xde, 0xad]);
+        tx.timestamp = u128::MAX;
+        tx.chain_id = 7;
+        tx.signature = Some(vec![1, 2]);
+        tx.hash = "txhash".into();
+        tx
+    }
+
+    #[test]
+    fn test_transaction_schema() {
+        let value = serde_json::to_value(TransactionJson::from(&sample_tx())).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "hash": "txhash",
+                "type": "transfer",
+                "from": "aa",
+                "to": "bb",
+                "amount": 5,
+                "fee": 1,
+                "nonce": 3,
+                "data": "0xdead",
+                "timestamp": "340282366920938463463374607431768211455",
+                "chain_id": 7,
+                "fork_hash": "",
+                "expires_at_height": 0,
+                "signing_version": 0,
+                "signature": "0x0102",
+            })
+        );
+        let parsed: TransactionJson = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.timestamp, u128::MAX);
+    }
+
+    #[test]
+    fn test_transaction_round_trip() {
+        let mut tx = sample_tx();
+        tx.hash = tx.calculate_hash();
+        let json = TransactionJson::from(&tx);
+        assert_eq!(Transaction::try_from(&json).unwrap(), tx);
+
+        let mut tampered = json.clone();
+        tampered.amount += 1;
+        assert!(Transaction::try_from(&tampered)
+            .unwrap_err()
+            .contains("does not match"));
+        let mut bad_type = json.clone();
+        bad_type.tx_type = "mint".into();
+        assert!(Transaction::try_from(&bad_type).is_err());
+        let mut bad_data = json;
+        bad_data.data = "dead".into();
+        assert!(Transaction::try_from(&bad_data).is_err());
+    }
+
+    #[test]
+    fn test_block_receipt_and_manifest_schema() {
+        let mut block = Block::new(4, "prev".into(), vec![sample_tx()]);
+        block.timestamp = 1_700_000_000_000;
+        block.hash = "blockhash".into();
+        block.vrf_output = vec![0xab];
+        let value = serde_json::to_value(BlockJson::from(&block)).unwrap();
+        let keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "chain_id",
+                "epoch",
+                "extensions",
+                "hash",
+                "height",
+                "nonce",
+                "previous_hash",
+                "producer",
+                "randao_reveal",
+                "signature",
+                "slashing_evidence_count",
+                "slot",
+                "state_root",
+                "state_snapshot_hash",
+                "timestamp",
+                "transactions",
+                "tx_root",
+                "validator_set_hash",
+                "version",
+                "vote_evidence_count",
+                "vrf_output",
+                "vrf_proof",
+            ]
+        );
+        assert_eq!(value["timestamp"], json!("1700000000000"));
+        assert_eq!(value["vrf_output"], json!("0xab"));
+        assert_eq!(value["randao_reveal"], json!("0x"));
+        assert_eq!(value["transactions"][0]["data"], json!("0xdead"));
+
+        assert_eq!(
+            serde_json::to_value(ReceiptJson::new(&block, 0).unwrap()).unwrap(),
+            json!({
+                "transaction_hash": "txhash",
+                "block_hash": "blockhash",
+                "block_height": 4,
+                "index": 0,
+                "from": "aa",
+                "to": "bb",
+                "amount": 5,
+                "fee": 1,
+                "status": "success",
+            })
+        );
+        assert!(ReceiptJson::new(&block, 1).is_none());
+
+        let manifest = SnapshotManifest {
+            version: 2,
+            height: 100,
+            block_hash: "bh".into(),
+            chain_id: 7,
+            created_at: 1,
+            finalized_height: 90,
+            finalized_hash: "fh".into(),
+            account_count: 3,
+            chunk_hashes: vec!["c0".into()],
+            chunk_root: "root".into(),
+            snapshot_hash: "sh".into(),
+            base_height: None,
+            chain_state: Vec::new(),
+            removed_accounts: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_value(SnapshotManifestJson::from(&manifest)).unwrap(),
+            json!({
+                "version": 2,
+                "height": 100,
+                "block_hash": "bh",
+                "chain_id": 7,
+                "created_at": "1",
+                "finalized_height": 90,
+                "finalized_hash": "fh",
+                "account_count": 3,
+                "chunk_hashes": ["c0"],
+                "chunk_root": "root",
+                "snapshot_hash": "sh",
+                "base_height": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,422 @@
+#[cfg(feature = "pq-crypto")]
+use crate::crypto::{hash_message, DilithiumKeyPair};
+use crate::crypto::{BlsKeyPair, ValidatorKeys};
+use crate::data_dir::VALIDATOR_KEY_FILE;
+use crate::frost::split_key;
+use crate::storage::{open_sealed_file, EncryptionSecret};
+use bech32::{Bech32m, Hrp};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const BLS_KEY_FILE: &str = "bls.key";
+pub const DILITHIUM_KEY_FILE: &str = "dilithium.key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyType {
+    #[value(name = "ed25519")]
+    Ed25519,
+    #[value(name = "bls")]
+    Bls,
+    #[cfg(feature = "pq-crypto")]
+    #[value(name = "dilithium")]
+    Dilithium,
+}
+
+impl KeyType {
+    pub const ALL: &'static [KeyType] = &[
+        KeyType::Ed25519,
+        KeyType::Bls,
+        #[cfg(feature = "pq-crypto")]
+        KeyType::Dilithium,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Bls => "bls",
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => "dilithium",
+        }
+    }
+
+    // The ed25519 file is the `ValidatorKeys` bundle (signing + VRF key)
+    // that `--validator-key-file` loads.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => VALIDATOR_KEY_FILE,
+            KeyType::Bls => BLS_KEY_FILE,
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => DILITHIUM_KEY_FILE,
+        }
+    }
+
+    fn hrp(&self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "budv",
+            KeyType::Bls => "budbls",
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => "buddil",
+        }
+    }
+
+    fn generate(&self) -> Vec<u8> {
+        match self {
+            KeyType::Ed25519 => ValidatorKeys::generate()
+                .expect("ed25519 key generation cannot fail")
+                .to_bytes(),
+            KeyType::Bls => BlsKeyPair::generate().to_bytes().to_vec(),
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => DilithiumKeyPair::generate().to_bytes(),
+        }
+    }
+
+    fn public_key(&self, secret: &[u8]) -> Result<Vec<u8>, String> {
+        let public = match self {
+            KeyType::Ed25519 => ValidatorKeys::from_bytes(secret)
+                .map(|keys| keys.sig_key.public_key_bytes().to_vec()),
+            KeyType::Bls => BlsKeyPair::from_bytes(secret).map(|k| k.public_key_bytes().to_vec()),
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => {
+                DilithiumKeyPair::from_bytes(secret).map(|k| k.public_key_bytes())
+            }
+        };
+        public.map_err(|e| format!("Invalid {} key: {}", self.name(), e))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicIdentity {
+    pub key_type: KeyType,
+    pub hex: String,
+    pub bech32: String,
+}
+
+impl PublicIdentity {
+    // A dilithium public key is far longer than a bech32 string may be, so
+    // its bech32 form carries the SHA3-256 fingerprint of the key instead.
+    pub fn new(key_type: KeyType, public: &[u8]) -> Result<Self, String> {
+        let hrp = Hrp::parse(key_type.hrp()).map_err(|e| e.to_string())?;
+        let payload = match key_type {
+            #[cfg(feature = "pq-crypto")]
+            KeyType::Dilithium => hash_message(public).to_vec(),
+            _ => public.to_vec(),
+        };
+        let bech32 = bech32::encode::<Bech32m>(hrp, &payload).map_err(|e| e.to_string())?;
+        Ok(PublicIdentity {
+            key_type,
+            hex: hex::encode(public),
+            bech32,
+        })
+    }
+}
+
+// Secret key files under `<data_dir>/chain-<id>/keys`, readable only by the
+// node's user and sealed when the database is encrypted.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    dir: PathBuf,
+    secret: Option<EncryptionSecret>,
+}
+
+impl Keystore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Keystore {
+            dir: dir.as_ref().to_path_buf(),
+            secret: None,
+        }
+    }
+
+    pub fn with_secret(mut self, secret: Option<EncryptionSecret>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    pub fn path(&self, key_type: KeyType) -> PathBuf {
+        self.dir.join(key_type.file_name())
+    }
+
+    pub fn previous_path(&self, key_type: KeyType) -> PathBuf {
+        self.dir.join(format!("{}.prev", key_type.file_name()))
+    }
+
+    pub fn generate(&self, key_type: KeyType, force: bool) -> Result<PublicIdentity, String> {
+        self.store(key_type, &key_type.generate(), force)
+    }
+
+    pub fn show(&self, key_type: KeyType) -> Result<Option<PublicIdentity>, String> {
+        let path = self.path(key_type);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let secret = read_secret_file(&path, self.secret.as_ref())?;
+        PublicIdentity::new(key_type, &key_type.public_key(&secret)?).map(Some)
+    }
+
+    // Exported keys are plaintext hex so they survive copy/paste between
+    // hosts, whatever encryption either side uses.
+    pub fn export(&self, key_type: KeyType, out: &Path) -> Result<PublicIdentity, String> {
+        let secret = read_secret_file(&self.path(key_type), self.secret.as_ref())?;
+        let identity = PublicIdentity::new(key_type, &key_type.public_key(&secret)?)?;
+        write_private(out, hex::encode(&*secret).as_bytes())?;
+        Ok(identity)
+    }
+
+    pub fn import(
+        &self,
+        key_type: KeyType,
+        file: &Path,
+        force: bool,
+    ) -> Result<PublicIdentity, String> {
+        let content = Zeroizing::new(
+            std::fs::read_to_string(file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?,
+        );
+        let secret = Zeroizing::new(
+            hex::decode(content.trim())
+                .map_err(|e| format!("{} is not a hex encoded key: {}", file.display(), e))?,
+        );
+        self.store(key_type, &secret, force)
+    }
+
+    // Replaces a key with a fresh one. The old key is kept as `<file>.prev`,
+    // since a validator still needs it to sign the switch to the new key,
+    // and any older `.prev` is wiped. A running node keeps signing with the
+    // key it loaded until it restarts.
+    pub fn rotate(
+        &self,
+        key_type: KeyType,
+    ) -> Result<(Option<PublicIdentity>, PublicIdentity), String> {
+        let previous = self.show(key_type)?;
+        if previous.is_some() {
+            let backup = self.previous_path(key_type);
+            if backup.exists() {
+                shred(&backup)?;
+            }
+            std::fs::rename(self.path(key_type), &backup)
+                .map_err(|e| format!("Failed to move {}: {}", backup.display(), e))?;
+        }
+        Ok((previous, self.generate(key_type, true)?))
+    }
+
+    // Splits the ed25519 signing key into FROST shares, one file per
+    // participant, to hand out to the holders of a PoA authority.
+    pub fn split(
+        &self,
+        threshold: u16,
+        shares: u16,
+        out_dir: &Path,
+    ) -> Result<(PublicIdentity, Vec<PathBuf>), String> {
+        let path = self.path(KeyType::Ed25519);
+        let secret = read_secret_file(&path, self.secret.as_ref())?;
+        let keys = ValidatorKeys::from_bytes(&secret)
+            .map_err(|e| format!("Invalid ed25519 key: {}", e))?;
+        let identity = PublicIdentity::new(KeyType::Ed25519, &keys.sig_key.public_key_bytes())?;
+        std::fs::create_dir_all(out_dir)
+            .map_err(|e| format!("Failed to create {}: {}", out_dir.display(), e))?;
+        let mut paths = Vec::new();
+        for share in split_key(&keys.sig_key, threshold, shares)? {
+            let share_path = out_dir.join(format!("share-{}.json", share.identifier));
+            write_private(&share_path, &share.to_json())?;
+            paths.push(share_path);
+        }
+        // Leaving the whole key next to its shares would defeat the split.
+        shred(&path)?;
+        Ok((identity, paths))
+    }
+
+    fn store(
+        &self,
+        key_type: KeyType,
+        secret: &[u8],
+        force: bool,
+    ) -> Result<PublicIdentity, String> {
+        let identity = PublicIdentity::new(key_type, &key_type.public_key(secret)?)?;
+        let path = self.path(key_type);
+        if path.exists() && !force {
+            return Err(format!(
+                "{} already exists (use --force to overwrite)",
+                path.display()
+            ));
+        }
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(|e| format!("Failed to restrict {}: {}", self.dir.display(), e))?;
+        }
+        write_secret_file(&path, secret, self.secret.as_ref())?;
+        Ok(identity)
+    }
+}
+
+// Reads a secret key file, opening it if it was sealed.
+pub fn read_secret_file(
+    path: &Path,
+    secret: Option<&EncryptionSecret>,
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let data = Zeroizing::new(
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?,
+    );
+    open_sealed_file(secret, &data).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+// Writes a secret key file readable only by the node's user, sealed when a
+// secret is configured.
+pub fn write_secret_file(
+    path: &Path,
+    bytes: &[u8],
+    secret: Option<&EncryptionSecret>,
+) -> Result<(), String> {
+    match secret {
+        Some(secret) => {
+            let sealed = secret
+                .seal_file(bytes)
+                .map_err(|e| format!("Failed to seal {}: {}", path.display(), e))?;
+            write_private(path, &sealed)
+        }
+        None => write_private(path, bytes),
+    }
+}
+
+// Overwrites the file in place before unlinking it.
+fn shred(path: &Path) -> Result<(), String> {
+    let len = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?
+        .len() as usize;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut file, &vec![0u8; len])
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to wipe {}: {}", path.display(), e))?;
+    std::fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut file, bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "{}_{}",
+            name,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ))
+    }
+
+    #[test]
+    fn test_generate_show_export_import() {
+        let root = temp_dir("budlum_keystore");
+        let store = Keystore::new(root.join("keys"));
+        let mut generated = vec![];
+        for &key_type in KeyType::ALL {
+            assert_eq!(store.show(key_type).unwrap(), None);
+            let identity = store.generate(key_type, false).unwrap();
+            assert!(identity.bech32.starts_with(key_type.hrp()));
+            assert_eq!(store.show(key_type).unwrap(), Some(identity.clone()));
+            assert!(store.generate(key_type, false).is_err());
+            generated.push(identity);
+        }
+        let validator = ValidatorKeys::load(store.path(KeyType::Ed25519)).unwrap();
+        assert_eq!(validator.sig_key.public_key_hex(), generated[0].hex);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.path(KeyType::Bls))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let other = Keystore::new(root.join("other"));
+        for (&key_type, identity) in KeyType::ALL.iter().zip(&generated) {
+            let exported = root.join(format!("{}.hex", key_type.name()));
+            assert_eq!(&store.export(key_type, &exported).unwrap(), identity);
+            assert_eq!(&other.import(key_type, &exported, false).unwrap(), identity);
+        }
+        let bls_hex = root.join("bls.hex");
+        assert!(other.import(KeyType::Ed25519, &bls_hex, true).is_err());
+
+        let (previous, rotated) = other.rotate(KeyType::Bls).unwrap();
+        assert_eq!(previous.as_ref(), Some(&generated[1]));
+        assert_ne!(rotated, generated[1]);
+        assert_eq!(other.show(KeyType::Bls).unwrap(), Some(rotated.clone()));
+        let (previous, _) = other.rotate(KeyType::Bls).unwrap();
+        assert_eq!(previous, Some(rotated.clone()));
+        let backup = read_secret_file(&other.previous_path(KeyType::Bls), None).unwrap();
+        assert_eq!(
+            KeyType::Bls.public_key(&backup).unwrap(),
+            hex::decode(&rotated.hex).unwrap()
+        );
+
+        assert!(store.split(4, 3, &root.join("bad")).is_err());
+        let (identity, paths) = store.split(2, 3, &root.join("shares")).unwrap();
+        assert!(!store.path(KeyType::Ed25519).exists());
+        assert_eq!(identity, generated[0]);
+        let shares = paths[1..]
+            .iter()
+            .map(crate::frost::KeyShare::load)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let signer = crate::frost::ThresholdSigner::local(shares).unwrap();
+        let signature = signer.sign(b"msg").unwrap();
+        assert!(validator.sig_key.verify(b"msg", &signature).is_ok());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_sealed_keystore() {
+        let root = temp_dir("budlum_sealed_keystore");
+        let secret = EncryptionSecret::Passphrase(Zeroizing::new("correct horse".into()));
+        let store = Keystore::new(&root).with_secret(Some(secret));
+        let identity = store.generate(KeyType::Bls, false).unwrap();
+        let raw = std::fs::read(store.path(KeyType::Bls)).unwrap();
+        assert!(crate::storage::is_sealed_file(&raw));
+        assert_eq!(store.show(KeyType::Bls).unwrap(), Some(identity.clone()));
+
+        assert!(Keystore::new(&root).show(KeyType::Bls).is_err());
+        let wrong = EncryptionSecret::Key(Zeroizing::new([7u8; 32]));
+        assert!(Keystore::new(&root)
+            .with_secret(Some(wrong))
+            .show(KeyType::Bls)
+            .is_err());
+
+        let exported = root.join("bls.hex");
+        store.export(KeyType::Bls, &exported).unwrap();
+        let plain = Keystore::new(root.join("plain"));
+        assert_eq!(
+            plain.import(KeyType::Bls, &exported, false).unwrap(),
+            identity
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,77 @@
+//! Budlum blockchain core as a library.
+//!
+//! The `budlum-core` binary is a thin wrapper around this crate; other
+//! applications can embed the same pieces:
+//!
+//! - [`Blockchain`] owns the chain, account state, mempool and storage and
+//!   validates and imports blocks.
+//! - [`ConsensusEngine`] is the pluggable block production and validation
+//!   rule set (`PoWEngine`, `PoSEngine`, `PoAEngine`, `DevEngine`). Each
+//!   engine but `DevEngine` sits behind a cargo feature (`pow-miner`,
+//!   `pos`, `poa`), as do the RPC servers (`rpc`), event counters
+//!   (`metrics`) and Dilithium keys (`pq-crypto`); all are on by default.
+//! - [`Mempool`] holds pending transactions under a [`MempoolConfig`].
+//! - [`Storage`] persists blocks and state in sled or memory.
+//! - [`Node`] runs the libp2p networking and sync around a shared chain.
+//! - [`client::Client`] talks to a node's JSON-RPC API and builds, signs and
+//!   submits transactions for applications.
+//!
+//! [`cli::NodeConfig`] assembles these the way the node binary does:
+//!
+//! ```no_run
+//! use budlum_core::cli::NodeConfig;
+//! use clap::Parser;
+//!
+//! let config = NodeConfig::parse_from(["embedded", "--db-backend", "memory"]);
+//! let consensus = config.build_consensus(None);
+//! let mut chain = config.build_blockchain(consensus).unwrap();
+//! chain.produce_block("miner".into());
+//! ```
+pub mod account;
+pub mod analytics;
+pub mod block;
+pub mod blockchain;
+pub mod bridge;
+pub mod chain_config;
+pub mod cli;
+#[cfg(feature = "rpc")]
+pub mod client;
+pub mod consensus;
+pub mod crypto;
+pub mod data_dir;
+pub mod divergence;
+pub mod dust;
+pub mod encoding;
+pub mod events;
+pub mod frost;
+pub mod genesis;
+pub mod hash;
+pub mod json;
+pub mod keystore;
+pub mod light_client;
+pub mod mempool;
+pub mod network;
+pub mod randao;
+pub mod replay;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+pub mod signing;
+pub mod slashing;
+pub mod snapshot;
+pub mod state_diff;
+pub mod storage;
+pub mod sync;
+pub mod transaction;
+pub mod validator_stats;
+
+#[cfg(all(test, feature = "pow-miner", feature = "pos", feature = "poa"))]
+mod integration_tests;
+
+pub use block::{Block, BlockHeader};
+pub use blockchain::{Blockchain, BlockchainBuilder, ReplayJob};
+pub use consensus::ConsensusEngine;
+pub use events::ChainEvent;
+pub use mempool::{Mempool, MempoolConfig};
+pub use network::{Node, NodeClient};
+pub use storage::Storage;
+pub use transaction::Transaction;
@@ -0,0 +1,408 @@
+use crate::block::BlockHeader;
+use crate::bridge::{ForeignChain, InboundKind, InboundMessage};
+use crate::consensus::finality::{is_checkpoint_height, FinalityCert, ValidatorSetSnapshot};
+use crate::transaction::{Transaction, TransactionType};
+use crate::Block;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const MAX_REMOTE_HEADERS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedHeader {
+    pub hash: String,
+    pub state_root: String,
+    pub tx_root: String,
+}
+
+// A finalized checkpoint header of the remote chain with its certificate.
+// `validator_set` is only sent when the update moves to a new epoch's set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderUpdate {
+    pub header: BlockHeader,
+    pub cert: FinalityCert,
+    pub validator_set: Option<ValidatorSetSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightClientMessage {
+    Create { chain_id: u64, update: HeaderUpdate },
+    Update { chain_id: u64, update: HeaderUpdate },
+}
+
+impl LightClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(data).map_err(|e| format!("Invalid light client payload: {}", e))
+    }
+}
+
+// Proves a transaction is in the block at `height` of a tracked remote
+// chain, using the same pairing as `Block::calculate_tx_root`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxInclusionProof {
+    pub height: u64,
+    pub tx: Transaction,
+    pub index: u64,
+    pub siblings: Vec<String>,
+}
+
+fn hash_pair(left: &str, right: &str) -> String {
+    hex::encode(crate::hash::calculate_hash(
+        format!("{}{}", left, right).as_bytes(),
+    ))
+}
+
+impl TxInclusionProof {
+    pub fn build(block: &Block, index: usize) -> Option<Self> {
+        let tx = block.transactions.get(index)?.clone();
+        let mut level: Vec<String> = block.transactions.iter().map(|t| t.hash.clone()).collect();
+        let mut position = index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            let sibling = level.get(position ^ 1).unwrap_or(&level[position]).clone();
+            siblings.push(sibling);
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            position /= 2;
+        }
+        Some(TxInclusionProof {
+            height: block.index,
+            tx,
+            index: index as u64,
+            siblings,
+        })
+    }
+
+    pub fn root(&self) -> String {
+        let mut current = self.tx.hash.clone();
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            current = if position.is_multiple_of(2) {
+                hash_pair(&current, sibling)
+            } else {
+                hash_pair(sibling, &current)
+            };
+            position /= 2;
+        }
+        current
+    }
+}
+
+// Tracks a remote Budlum chain from its finality certificates, keeping the
+// last MAX_REMOTE_HEADERS checkpoint headers to check proofs against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightClient {
+    pub chain_id: u64,
+    pub validator_set: ValidatorSetSnapshot,
+    pub latest_height: u64,
+    pub headers: BTreeMap<u64, TrustedHeader>,
+}
+
+fn check_header(
+    chain_id: u64,
+    update: &HeaderUpdate,
+    set: &ValidatorSetSnapshot,
+) -> Result<(), String> {
+    let header = &update.header;
+    if header.calculate_hash() != header.hash {
+        return Err("Remote header hash does not match its contents".into());
+    }
+    if header.chain_id != chain_id {
+        return Err(format!(
+            "Remote header is for chain {}, expected {}",
+            header.chain_id, chain_id
+        ));
+    }
+    if !is_checkpoint_height(header.index)
+        || update.cert.checkpoint_height != header.index
+        || update.cert.checkpoint_hash != header.hash
+    {
+        return Err("Finality certificate does not cover the remote header".into());
+    }
+    if set.set_hash != ValidatorSetSnapshot::compute_hash(&set.validators)
+        || header.validator_set_hash != set.set_hash
+    {
+        return Err("Validator set does not match the remote header".into());
+    }
+    update.cert.verify(set, chain_id)?;
+    update.cert.verify_state_root(&header.state_root)
+}
+
+impl LightClient {
+    // The initial set is taken on trust, like a weak subjectivity checkpoint.
+    pub fn create(chain_id: u64, update: &HeaderUpdate) -> Result<Self, String> {
+        let set = update
+            .validator_set
+            .clone()
+            .ok_or("Creating a light client needs the remote validator set")?;
+        check_header(chain_id, update, &set)?;
+        let mut client = LightClient {
+            chain_id,
+            validator_set: set,
+            latest_height: 0,
+            headers: BTreeMap::new(),
+        };
+        client.store(&update.header);
+        Ok(client)
+    }
+
+    // A new set is accepted when signers from the trusted set holding more
+    // than a third of its stake also certified the header.
+    pub fn verify_update(&self, update: &HeaderUpdate) -> Result<(), String> {
+        if update.header.index <= self.latest_height {
+            return Err(format!(
+                "Remote header {} is not above the latest tracked height {}",
+                update.header.index, self.latest_height
+            ));
+        }
+        let Some(next) = &update.validator_set else {
+            return check_header(self.chain_id, update, &self.validator_set);
+        };
+        if next.epoch <= self.validator_set.epoch {
+            return Err("Validator set update must move to a later epoch".into());
+        }
+        check_header(self.chain_id, update, next)?;
+        let carried: u64 = next
+            .validators
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| update.cert.signed_by(*idx))
+            // A trusted validator only counts under the key it was trusted
+            // with, not one the new set assigns to its address.
+            .filter_map(|(_, v)| {
+                self.validator_set
+                    .find_validator(&v.address)
+                    .filter(|trusted| trusted.bls_public_key == v.bls_public_key)
+            })
+            .map(|v| v.stake)
+            .sum();
+        if carried * 3 <= self.validator_set.total_stake {
+            return Err(format!(
+                "Trusted validators hold only {} of {} stake in the new set's certificate",
+                carried, self.validator_set.total_stake
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn apply_update(&mut self, update: &HeaderUpdate) -> Result<(), String> {
+        self.verify_update(update)?;
+        if let Some(next) = &update.validator_set {
+            self.validator_set = next.clone();
+        }
+        self.store(&update.header);
+        Ok(())
+    }
+
+    fn store(&mut self, header: &BlockHeader) {
+        self.latest_height = header.index;
+        self.headers.insert(
+            header.index,
+            TrustedHeader {
+                hash: header.hash.clone(),
+                state_root: header.state_root.clone(),
+                tx_root: header.tx_root.clone(),
+            },
+        );
+        while self.headers.len() > MAX_REMOTE_HEADERS {
+            self.headers.pop_first();
+        }
+    }
+
+    pub fn verify_inclusion(&self, proof: &TxInclusionProof) -> Result<(), String> {
+        let header = self.headers.get(&proof.height).ok_or_else(|| {
+            format!(
+                "No tracked header at height {} of chain {}",
+                proof.height, self.chain_id
+            )
+        })?;
+        if proof.tx.hash != proof.tx.calculate_hash() {
+            return Err("Proven transaction hash does not match its contents".into());
+        }
+        if proof.root() != header.tx_root {
+            return Err(format!(
+                "Transaction is not included in block {} of chain {}",
+                proof.height, self.chain_id
+            ));
+        }
+        Ok(())
+    }
+
+    // Checks that `message` restates a bridge transfer proven to be in a
+    // finalized block of this remote chain and addressed to `local_chain_id`.
+    pub fn verify_transfer(
+        &self,
+        message: &InboundMessage,
+        proof: &TxInclusionProof,
+        local_chain_id: u64,
+    ) -> Result<(), String> {
+        self.verify_inclusion(proof)?;
+        let tx = &proof.tx;
+        let kind = match tx.tx_type {
+            TransactionType::BridgeLock => InboundKind::Mint,
+            TransactionType::BridgeBurn => InboundKind::Release,
+            _ => return Err("Proven transaction is not a bridge transfer".into()),
+        };
+        let transfer = tx.bridge_transfer()?;
+        if tx.chain_id != self.chain_id
+            || transfer.destination != ForeignChain::Budlum(local_chain_id)
+        {
+            return Err("Proven transfer is not addressed to this chain".into());
+        }
+        if message.source != ForeignChain::Budlum(self.chain_id)
+            || message.source_tx != tx.hash
+            || message.nonce != tx.nonce
+            || message.recipient != transfer.recipient
+            || message.amount != tx.amount
+            || message.kind != kind
+        {
+            return Err("Bridge message does not match the proven transfer".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::BridgeTransfer;
+    use crate::chain_config::FINALITY_CHECKPOINT_INTERVAL;
+    use crate::consensus::finality::{sign_pop, test_keys};
+    use crate::crypto::aggregate_bls_signatures;
+
+    const REMOTE: u64 = 7;
+
+    fn validator_set(epoch: u64, addresses: &[&str]) -> ValidatorSetSnapshot {
+        let validators = addresses
+            .iter()
+            .map(|address| test_keys::entry(address, 100, REMOTE))
+            .collect();
+        ValidatorSetSnapshot::new(epoch, validators)
+    }
+
+    fn certified(
+        height: u64,
+        txs: Vec<Transaction>,
+        set: &ValidatorSetSnapshot,
+        bitmap: u8,
+    ) -> (Block, HeaderUpdate) {
+        let mut block = Block::new_with_chain_id(height, String::new(), txs, REMOTE);
+        block.state_root = "root".into();
+        block.validator_set_hash = set.set_hash.clone();
+        block.tx_root = block.calculate_tx_root();
+        block.hash = block.calculate_hash();
+        let mut cert = FinalityCert {
+            epoch: set.epoch,
+            checkpoint_height: height,
+            checkpoint_hash: block.hash.clone(),
+            agg_sig_bls: Vec::new(),
+            bitmap: vec![bitmap],
+            set_hash: set.set_hash.clone(),
+            state_root: block.state_root.clone(),
+        };
+        test_keys::sign_cert(&mut cert, set, REMOTE);
+        let update = HeaderUpdate {
+            header: BlockHeader::from_block(&block),
+            cert,
+            validator_set: None,
+        };
+        (block, update)
+    }
+
+    #[test]
+    fn test_light_client_tracks_headers_and_verifies_transfers() {
+        let set = validator_set(0, &["a", "b", "c"]);
+        let (_, mut genesis) = certified(FINALITY_CHECKPOINT_INTERVAL, vec![], &set, 0b011);
+        assert!(LightClient::create(REMOTE, &genesis).is_err());
+        genesis.validator_set = Some(set.clone());
+        let mut client = LightClient::create(REMOTE, &genesis).unwrap();
+        assert!(client.verify_update(&genesis).is_err());
+
+        let transfer = BridgeTransfer {
+            destination: ForeignChain::Budlum(1337),
+            recipient: "bob".into(),
+        };
+        let mut lock = Transaction::new_bridge_lock("alice".into(), &transfer, 40, 3);
+        lock.chain_id = REMOTE;
+        lock.hash = lock.calculate_hash();
+        let txs = vec![
+            Transaction::new("x".into(), "y".into(), 1, vec![]),
+            Transaction::new("x".into(), "z".into(), 2, vec![]),
+            lock,
+        ];
+        let height = 2 * FINALITY_CHECKPOINT_INTERVAL;
+        let (_, weak) = certified(height, txs.clone(), &set, 0b001);
+        assert!(client.apply_update(&weak).is_err());
+
+        // Rotating to a set where only "c" of the trusted set signed.
+        let next = validator_set(1, &["c", "d", "e"]);
+        let (block, mut rotated) = certified(height, txs, &next, 0b111);
+        rotated.validator_set = Some(next.clone());
+        assert!(client.verify_update(&rotated).is_err());
+        let (_, mut stale) = certified(height, block.transactions.clone(), &next, 0b111);
+        stale.validator_set = Some(validator_set(0, &["c", "d", "e"]));
+        assert!(client.verify_update(&stale).is_err());
+
+        // "a" and "c" carry over, but only under the keys they were trusted
+        // with: a set that rebinds them to new keys carries no trust.
+        let trusted = validator_set(1, &["a", "c", "d"]);
+        let mut rebound = trusted.validators.clone();
+        for (entry, attacker) in rebound.iter_mut().zip(["x", "y"]) {
+            let key = test_keys::bls_key(attacker);
+            entry.bls_public_key = key.public_key_bytes().to_vec();
+            entry.pop_signature = sign_pop(&key, REMOTE, &entry.address);
+        }
+        let next = ValidatorSetSnapshot::new(1, rebound);
+        let (_, mut hijacked) = certified(height, block.transactions.clone(), &next, 0b011);
+        let message = hijacked.cert.signing_message(REMOTE);
+        let shares = [
+            test_keys::bls_key("x").sign(&message),
+            test_keys::bls_key("y").sign(&message),
+        ];
+        hijacked.cert.agg_sig_bls = aggregate_bls_signatures(shares.iter().map(|s| &s[..]))
+            .unwrap()
+            .to_vec();
+        hijacked.validator_set = Some(next.clone());
+        check_header(REMOTE, &hijacked, &next).unwrap();
+        assert!(client.verify_update(&hijacked).is_err());
+
+        let next = trusted;
+        let (block, mut rotated) = certified(height, block.transactions.clone(), &next, 0b011);
+        rotated.validator_set = Some(next);
+        client.apply_update(&rotated).unwrap();
+        assert_eq!(client.latest_height, height);
+
+        let proof = TxInclusionProof::build(&block, 2).unwrap();
+        assert_eq!(proof.root(), block.tx_root);
+        let message = InboundMessage {
+            source: ForeignChain::Budlum(REMOTE),
+            source_tx: proof.tx.hash.clone(),
+            nonce: 3,
+            recipient: "bob".into(),
+            amount: 40,
+            kind: InboundKind::Mint,
+            dest_chain_id: 1337,
+        };
+        client.verify_transfer(&message, &proof, 1337).unwrap();
+        assert!(client.verify_transfer(&message, &proof, 99).is_err());
+        let inflated = InboundMessage {
+            amount: 400,
+            ..message.clone()
+        };
+        assert!(client.verify_transfer(&inflated, &proof, 1337).is_err());
+
+        let not_bridge = TxInclusionProof::build(&block, 0).unwrap();
+        client.verify_inclusion(&not_bridge).unwrap();
+        assert!(client.verify_transfer(&message, &not_bridge, 1337).is_err());
+        let mut forged = proof;
+        forged.tx.amount = 400;
+        forged.tx.hash = forged.tx.calculate_hash();
+        assert!(client.verify_inclusion(&forged).is_err());
+    }
+}
@@ -1,44 +1,548 @@
-mod account;
-mod block;
-mod blockchain;
-mod chain_config;
-mod cli;
-mod consensus;
-mod crypto;
-mod encoding;
-mod genesis;
-mod hash;
-mod mempool;
-mod network;
-mod slashing;
-mod snapshot;
-mod storage;
-mod transaction;
-
-#[cfg(test)]
-mod integration_tests;
-use block::{Block, BlockHeader};
-use blockchain::Blockchain;
-use clap::Parser;
-use cli::{ConsensusType, NodeConfig};
-use consensus::{ConsensusEngine, PoAEngine, PoSEngine, PoWEngine};
-use network::{NetworkMessage, Node};
+use budlum_core::cli::{
+    ChainCommand, Command, ConfigCommand, ConsensusType, DbCommand, KeysCommand, NodeConfig,
+    SnapshotCommand,
+};
+use budlum_core::events::ChainEvent;
+#[cfg(feature = "metrics")]
+use budlum_core::events::ChainMetrics;
+use budlum_core::json::SnapshotManifestJson;
+use budlum_core::keystore::{KeyType, PublicIdentity};
+use budlum_core::network::{NetworkMessage, Node, NodeClient};
+#[cfg(feature = "rpc")]
+use budlum_core::rpc::{AdminServer, RpcServer};
+use budlum_core::{snapshot, Blockchain, Transaction};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
-use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
-use transaction::Transaction;
+use tracing::{error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+type LogHandle = reload::Handle<LevelFilter, Registry>;
+// Without the `metrics` feature the event consumer only logs.
+#[cfg(feature = "metrics")]
+type SharedMetrics = Arc<Mutex<ChainMetrics>>;
+#[cfg(not(feature = "metrics"))]
+type SharedMetrics = ();
+
+async fn reload_runtime_config(
+    client: &NodeClient,
+    blockchain: &Arc<Mutex<Blockchain>>,
+    log_handle: &LogHandle,
+    rpc_rate_limit: &AtomicU32,
+) {
+    let runtime = match NodeConfig::load() {
+        Ok(config) => config.runtime_config(),
+        Err(e) => {
+            warn!("Configuration reload failed: {}", e);
+            return;
+        }
+    };
+    match runtime.log_level.parse::<LevelFilter>() {
+        Ok(level) => {
+            if let Err(e) = log_handle.modify(|filter| *filter = level) {
+                warn!("Failed to change log level: {}", e);
+            }
+        }
+        Err(e) => warn!("Invalid log level {}: {}", runtime.log_level, e),
+    }
+    client.set_max_peers(runtime.max_peers).await;
+    blockchain
+        .lock()
+        .unwrap()
+        .mempool
+        .set_config(runtime.mempool.clone());
+    rpc_rate_limit.store(runtime.rpc_rate_limit, Ordering::Relaxed);
+    info!(
+        "Reloaded runtime configuration: log_level={}, max_peers={}, mempool_max_size={}, rpc_rate_limit={}",
+        runtime.log_level, runtime.max_peers, runtime.mempool.max_size, runtime.rpc_rate_limit
+    );
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+fn spawn_event_consumer(
+    mut events: tokio::sync::broadcast::Receiver<ChainEvent>,
+    metrics: SharedMetrics,
+) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    match &event {
+                        ChainEvent::Reorged { depth, fork_height, old_tip, new_tip } => warn!(
+                            "Chain reorganized at height {} (depth {}): {} -> {}",
+                            fork_height, depth, old_tip, new_tip
+                        ),
+                        ChainEvent::Finalized { height, hash } => {
+                            info!("Finalized height {} ({})", height, hash)
+                        }
+                        ChainEvent::EpochRewards { epoch, minted, recipients } => {
+                            info!("Epoch {} rewards: minted {} to {} validators", epoch, minted, recipients)
+                        }
+                        ChainEvent::TreasuryCredited { height, source, amount } => {
+                            info!("Treasury credited {} from {:?} at height {}", amount, source, height)
+                        }
+                        ChainEvent::Halted { height, stalled_secs } => {
+                            error!("Chain halted at height {}: no block for {}s, resyncing from peers", height, stalled_secs)
+                        }
+                        ChainEvent::Recovered { height } => info!("Chain recovered at height {}", height),
+                        ChainEvent::ValidatorSetUnsafe { height, epoch, eligible_validators, eligible_stake, quorum_stake } => error!(
+                            "Validator set of epoch {} unsafe at height {}: {} eligible validators with stake {} (quorum {}), refusing to finalize",
+                            epoch, height, eligible_validators, eligible_stake, quorum_stake
+                        ),
+                        ChainEvent::ValidatorSetRestored { height, epoch } => {
+                            info!("Validator set of epoch {} safe again at height {}", epoch, height)
+                        }
+                        ChainEvent::BlockRejected(rejection) => warn!(
+                            "Rejected block #{} {} from {:?}: {:?} ({})",
+                            rejection.height, rejection.hash, rejection.producer, rejection.reason, rejection.error
+                        ),
+                        ChainEvent::BlockAdded { .. }
+                        | ChainEvent::PendingTransaction(_)
+                        | ChainEvent::LocalTransaction(_)
+                        | ChainEvent::SyncProgress { .. }
+                        | ChainEvent::PeerVersions { .. } => {}
+                    }
+                    #[cfg(feature = "metrics")]
+                    metrics.lock().unwrap().record(&event);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Chain event consumer lagged, skipped {} events", skipped);
+                    #[cfg(feature = "metrics")]
+                    {
+                        metrics.lock().unwrap().lagged_events += skipped;
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(feature = "rpc")]
+fn spawn_rpc_services(config: &NodeConfig, blockchain: &Arc<Mutex<Blockchain>>) -> Arc<AtomicU32> {
+    let rpc_server = RpcServer::new(blockchain.clone(), config.rpc_rate_limit);
+    let rpc_rate_limit = rpc_server.rate_limit_handle();
+    let watch = rpc_server.watch_service();
+    watch.spawn(
+        blockchain.lock().unwrap().subscribe_events(),
+        blockchain.clone(),
+    );
+    let bind = config.rpc_bind;
+    if let Some(port) = config.ws_port {
+        let events = blockchain.lock().unwrap().events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = budlum_core::rpc::ws::serve_ws(bind, port, watch, events).await {
+                warn!("WebSocket server stopped: {}", e);
+            }
+        });
+    }
+    if let Some(port) = config.rpc_port {
+        tokio::spawn(async move {
+            if let Err(e) = rpc_server.serve(bind, port).await {
+                warn!("RPC server stopped: {}", e);
+            }
+        });
+    }
+    if let Some(port) = config.grpc_port {
+        let grpc = budlum_core::rpc::GrpcService::new(blockchain.clone());
+        tokio::spawn(async move {
+            if let Err(e) = grpc.serve(bind, port).await {
+                warn!("gRPC server stopped: {}", e);
+            }
+        });
+    }
+    rpc_rate_limit
+}
+
+#[cfg(not(feature = "rpc"))]
+fn spawn_rpc_services(config: &NodeConfig, _blockchain: &Arc<Mutex<Blockchain>>) -> Arc<AtomicU32> {
+    if config.rpc_port.is_some() || config.ws_port.is_some() || config.grpc_port.is_some() {
+        warn!("RPC is not compiled into this build (enable the `rpc` feature), ignoring RPC ports");
+    }
+    Arc::new(AtomicU32::new(config.rpc_rate_limit))
+}
+
+#[cfg(feature = "rpc")]
+fn spawn_admin_service(
+    config: &NodeConfig,
+    blockchain: &Arc<Mutex<Blockchain>>,
+    client: &NodeClient,
+) {
+    if !config.admin_socket {
+        return;
+    }
+    let data_dir = config.data_dir();
+    let token = match budlum_core::rpc::admin::load_or_create_token(&data_dir.admin_token_path()) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Admin API disabled: {}", e);
+            return;
+        }
+    };
+    let mut server = AdminServer::new(blockchain.clone(), token, data_dir.admin_audit_log())
+        .with_node(client.clone());
+    match config.keystore() {
+        Ok(keystore) => server = server.with_keystore(keystore),
+        Err(e) => warn!("Key rotation over the admin API disabled: {}", e),
+    }
+    let path = data_dir.admin_socket_path();
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(path).await {
+            warn!("Admin API stopped: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "rpc"))]
+fn spawn_admin_service(
+    config: &NodeConfig,
+    _blockchain: &Arc<Mutex<Blockchain>>,
+    _client: &NodeClient,
+) {
+    if config.admin_socket {
+        warn!("The admin API is not compiled into this build (enable the `rpc` feature)");
+    }
+}
+
+#[cfg(feature = "rpc")]
+fn run_admin_command(
+    method: String,
+    params: Option<String>,
+    config: &NodeConfig,
+) -> Result<(), String> {
+    let data_dir = config.data_dir();
+    let token = std::fs::read_to_string(data_dir.admin_token_path()).map_err(|e| {
+        format!(
+            "Failed to read admin token (is --admin-socket enabled?): {}",
+            e
+        )
+    })?;
+    let params = match params {
+        Some(p) => serde_json::from_str(&p).map_err(|e| format!("Invalid params: {}", e))?,
+        None => serde_json::Value::Null,
+    };
+    let result = budlum_core::rpc::admin::call(
+        &data_dir.admin_socket_path(),
+        token.trim(),
+        &method,
+        params,
+    )?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&result).unwrap_or_default()
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "rpc"))]
+fn run_admin_command(
+    _method: String,
+    _params: Option<String>,
+    _config: &NodeConfig,
+) -> Result<(), String> {
+    Err("The admin API is not compiled into this build (enable the `rpc` feature)".into())
+}
+
+fn run_config_command(cmd: ConfigCommand) -> Result<(), String> {
+    match cmd {
+        ConfigCommand::Init { out, force } => {
+            if std::path::Path::new(&out).exists() && !force {
+                return Err(format!("{} already exists (use --force to overwrite)", out));
+            }
+            std::fs::write(&out, budlum_core::cli::DEFAULT_CONFIG_TOML)
+                .map_err(|e| format!("Failed to write {}: {}", out, e))?;
+            println!("Wrote default configuration to {}", out);
+        }
+    }
+    Ok(())
+}
+
+fn run_db_command(cmd: DbCommand, config: &NodeConfig) -> Result<(), String> {
+    let store = config
+        .open_storage()
+        .map_err(|e| format!("Failed to open storage: {}", e))?;
+    match cmd {
+        DbCommand::Stats => {
+            let stats = store
+                .stats()
+                .map_err(|e| format!("Failed to read storage stats: {}", e))?;
+            println!("Backend: {}", stats.backend);
+            println!("Size on disk: {} bytes", stats.size_on_disk);
+            for (name, keyspace) in &stats.keyspaces {
+                println!(
+                    "   {:<14} {:>10} keys {:>14} bytes",
+                    name, keyspace.keys, keyspace.bytes
+                );
+            }
+            println!("Live data: {} bytes", stats.live_bytes());
+            println!(
+                "Orphaned blocks: {} ({} bytes)",
+                stats.orphaned_blocks, stats.orphaned_bytes
+            );
+            println!("Estimated reclaimable: {} bytes", stats.reclaimable_bytes());
+        }
+        DbCommand::Compact => {
+            let removed = store
+                .compact()
+                .map_err(|e| format!("Compaction failed: {}", e))?;
+            println!("Removed {} orphaned blocks", removed);
+        }
+        DbCommand::Reindex => {
+            let report = store
+                .reindex()
+                .map_err(|e| format!("Reindex failed: {}", e))?;
+            println!(
+                "Scanned {} blocks ({} undecodable, {} off the canonical chain)",
+                report.blocks, report.undecodable, report.orphaned_blocks
+            );
+            let Some((height, hash)) = report.tip else {
+                return Err("No stored blocks to index".into());
+            };
+            println!(
+                "Indexed heights {}..={} and {} transactions, tip {}",
+                report.lowest_height.unwrap_or(height),
+                height,
+                report.transactions,
+                hash
+            );
+            if config.balance_history {
+                // The blockchain opens its own handle on the database.
+                drop(store);
+                let blockchain = config.build_blockchain(config.build_consensus(None))?;
+                let start = blockchain.rebuild_balance_history()?;
+                println!("Rebuilt balance history from height {}", start);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_identity(identity: &PublicIdentity) {
+    println!("{}:", identity.key_type.name());
+    println!("   hex:    {}", identity.hex);
+    println!("   bech32: {}", identity.bech32);
+}
+
+fn run_keys_command(cmd: KeysCommand, config: &NodeConfig) -> Result<(), String> {
+    let store = config
+        .keystore()
+        .map_err(|e| format!("Failed to open keystore: {}", e))?;
+    match cmd {
+        KeysCommand::Generate { key_type, force } => {
+            let identity = store.generate(key_type, force)?;
+            println!("Generated {}", store.path(key_type).display());
+            print_identity(&identity);
+        }
+        KeysCommand::Show { key_type } => {
+            let types = key_type.map(|t| vec![t]).unwrap_or(KeyType::ALL.to_vec());
+            for key_type in types {
+                match store.show(key_type)? {
+                    Some(identity) => print_identity(&identity),
+                    None => println!(
+                        "{}: no key at {}",
+                        key_type.name(),
+                        store.path(key_type).display()
+                    ),
+                }
+            }
+        }
+        KeysCommand::Import {
+            file,
+            key_type,
+            force,
+        } => {
+            let identity = store.import(key_type, std::path::Path::new(&file), force)?;
+            println!("Imported {} to {}", file, store.path(key_type).display());
+            print_identity(&identity);
+        }
+        KeysCommand::Export { key_type, out } => {
+            let identity = store.export(key_type, std::path::Path::new(&out))?;
+            println!("Exported {} secret key to {}", key_type.name(), out);
+            print_identity(&identity);
+        }
+        KeysCommand::Split {
+            threshold,
+            shares,
+            out_dir,
+        } => {
+            let (identity, paths) =
+                store.split(threshold, shares, std::path::Path::new(&out_dir))?;
+            println!(
+                "Split the ed25519 key into {} shares, {} needed to sign:",
+                shares, threshold
+            );
+            for path in paths {
+                println!("   {}", path.display());
+            }
+            println!("The original key file was wiped; keep the shares on separate hosts.");
+            print_identity(&identity);
+        }
+        KeysCommand::ServeShare {
+            share,
+            listen,
+            token_file,
+        } => {
+            let server = budlum_core::frost::ShareServer::new(
+                budlum_core::frost::KeyShare::load(&share)?,
+                budlum_core::frost::load_token(&token_file)?,
+            );
+            let listener = std::net::TcpListener::bind(&listen)
+                .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+            if listener.local_addr().is_ok_and(|a| !a.ip().is_loopback()) {
+                warn!(
+                    "Share requests to {} are authenticated but not encrypted; prefer loopback behind an SSH or WireGuard tunnel",
+                    listen
+                );
+            }
+            server.serve(listener).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn run_snapshot_command(cmd: SnapshotCommand, config: &NodeConfig) -> Result<(), String> {
+    let pm = config.pruning_manager();
+    match cmd {
+        SnapshotCommand::Export { height, out } => {
+            let manifest = pm.export_snapshot(height, std::path::Path::new(&out))?;
+            println!(
+                "Exported snapshot at height {} ({} accounts, chunk root {}) to {}",
+                manifest.height, manifest.account_count, manifest.chunk_root, out
+            );
+        }
+        SnapshotCommand::Import { file, state_root } => {
+            let anchor = match state_root {
+                Some(root) => snapshot::SnapshotAnchor {
+                    chain_id: config.chain_id,
+                    state_root: root,
+                    block_hash: None,
+                },
+                None => {
+                    let height = snapshot::read_export_height(std::path::Path::new(&file))?;
+                    let store = config
+                        .open_storage()
+                        .map_err(|e| format!("Failed to open storage: {}", e))?;
+                    // Only a finalized block pins the state a snapshot must match.
+                    let finalized = store
+                        .get_finalized()
+                        .map_err(|e| format!("Failed to read finalized height: {}", e))?
+                        .map_or(0, |(h, _)| h);
+                    if height > finalized {
+                        return Err(format!(
+                            "Height {} is above the local finalized height {}; pass --state-root from a finalized checkpoint",
+                            height, finalized
+                        ));
+                    }
+                    let block = store
+                        .get_block_by_height(height)
+                        .map_err(|e| format!("Failed to read block {}: {}", height, e))?
+                        .ok_or(format!(
+                            "No local block at height {}; pass --state-root from a finalized checkpoint",
+                            height
+                        ))?;
+                    snapshot::SnapshotAnchor {
+                        chain_id: config.chain_id,
+                        state_root: block.state_root,
+                        block_hash: Some(block.hash),
+                    }
+                }
+            };
+            let snap = pm.import_snapshot(std::path::Path::new(&file), &anchor)?;
+            println!(
+                "Imported snapshot at height {} ({} accounts), state root {}",
+                snap.height,
+                snap.balances.len(),
+                anchor.state_root
+            );
+        }
+        SnapshotCommand::Inspect { file } => {
+            let manifest = snapshot::read_export_file_manifest(std::path::Path::new(&file))?;
+            let json = serde_json::to_string_pretty(&SnapshotManifestJson::from(&manifest))
+                .map_err(|e| format!("Failed to encode manifest: {}", e))?;
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+fn run_chain_command(cmd: ChainCommand, blockchain: &mut Blockchain) -> Result<(), String> {
+    match cmd {
+        ChainCommand::Export { out } => {
+            let file = std::fs::File::create(&out)
+                .map_err(|e| format!("Failed to create export file: {}", e))?;
+            let count = blockchain.export_chain(&mut std::io::BufWriter::new(file))?;
+            println!("Exported {} blocks to {}", count, out);
+        }
+        ChainCommand::Import { file } => {
+            let input = std::fs::File::open(&file)
+                .map_err(|e| format!("Failed to open import file: {}", e))?;
+            let count = blockchain.import_chain(&mut std::io::BufReader::new(input))?;
+            println!(
+                "Imported {} blocks from {} (height {})",
+                count,
+                file,
+                blockchain.last_block().index
+            );
+        }
+        ChainCommand::ExportCheckpoint { out } => {
+            let bundle = blockchain.export_checkpoint()?;
+            let bytes = serde_json::to_vec(&bundle)
+                .map_err(|e| format!("Failed to encode checkpoint: {}", e))?;
+            std::fs::write(&out, bytes).map_err(|e| format!("Failed to write {}: {}", out, e))?;
+            println!(
+                "Exported checkpoint at height {} to {}",
+                bundle.cert.checkpoint_height, out
+            );
+        }
+        ChainCommand::CheckpointSync { file } => {
+            let bytes =
+                std::fs::read(&file).map_err(|e| format!("Failed to read {}: {}", file, e))?;
+            let bundle = serde_json::from_slice(&bytes)
+                .map_err(|e| format!("Invalid checkpoint bundle: {}", e))?;
+            let height = blockchain.checkpoint_sync(bundle)?;
+            println!("Synced to finalized checkpoint at height {}", height);
+        }
+    }
+    Ok(())
+}
+fn load_validator_keys(
+    config: &NodeConfig,
+    path: &str,
+) -> Result<budlum_core::crypto::ValidatorKeys, String> {
+    let secret = config.encryption_secret().map_err(|e| e.to_string())?;
+    let bytes =
+        budlum_core::keystore::read_secret_file(std::path::Path::new(path), secret.as_ref())?;
+    budlum_core::crypto::ValidatorKeys::from_bytes(&bytes).map_err(|e| e.to_string())
+}
 #[tokio::main]
 async fn main() {
-    let config = NodeConfig::parse();
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-    
+    let config = NodeConfig::load().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let level = config.log_level.parse::<LevelFilter>().unwrap_or_else(|e| {
+        eprintln!("Invalid log level {}: {}", config.log_level, e);
+        std::process::exit(1);
+    });
+    let (level_filter, log_handle) = reload::Layer::new(level);
+    tracing_subscriber::registry()
+        .with(level_filter)
+        .with(fmt::layer())
+        .try_init()
+        .expect("setting default subscriber failed");
+
     if let Some(ref path) = config.gen_key {
-        match crate::crypto::ValidatorKeys::generate() {
+        match budlum_core::crypto::ValidatorKeys::generate() {
             Ok(keys) => {
-                keys.save(path).expect("Failed to save key");
+                let secret = config
+                    .encryption_secret()
+                    .expect("Failed to read encryption key");
+                budlum_core::keystore::write_secret_file(
+                    std::path::Path::new(path),
+                    &zeroize::Zeroizing::new(keys.to_bytes()),
+                    secret.as_ref(),
+                )
+                .expect("Failed to save key");
                 println!("Validator key generated and saved to: {}", path);
                 println!("Address: {}", keys.sig_key.public_key_hex());
             }
@@ -47,82 +551,132 @@ async fn main() {
         return;
     }
 
+    // An explicit --db-path is left where it is.
+    match config
+        .data_dir()
+        .migrate_legacy_layout(config.db_path.is_none())
+    {
+        Ok(moved) => {
+            for (from, to) in moved {
+                println!("Moved {} to {}", from.display(), to.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to migrate the old data directory layout: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let early_command = match config.command.clone() {
+        Some(Command::Snapshot(cmd)) => Some(run_snapshot_command(cmd, &config)),
+        Some(Command::Db(cmd)) => Some(run_db_command(cmd, &config)),
+        Some(Command::Config(cmd)) => Some(run_config_command(cmd)),
+        Some(Command::Keys(cmd)) => Some(run_keys_command(cmd, &config)),
+        Some(Command::Admin { method, params }) => Some(run_admin_command(method, params, &config)),
+        _ => None,
+    };
+    if let Some(result) = early_command {
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let data_dir = config.data_dir();
+    if let Err(e) = data_dir.ensure() {
+        eprintln!(
+            "Failed to create data directory {}: {}",
+            data_dir.root().display(),
+            e
+        );
+        std::process::exit(1);
+    }
+    let validator_key_file = config.validator_key_path();
+    let validator_keys = match validator_key_file {
+        Some(ref path) => match load_validator_keys(&config, path) {
+            Ok(keys) => Some(keys),
+            Err(e) => {
+                eprintln!("Error: failed to load validator key {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    if let Err(e) = config.threshold_signer() {
+        eprintln!("Error: failed to load threshold shares: {}", e);
+        std::process::exit(1);
+    }
+
     println!("Budlum Node - v0.2.0 (Framework Edition)");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("Configuration:");
     println!("   Port: {}", config.port);
     println!("   Consensus: {:?}", config.consensus);
     println!("   Privacy: {:?}", config.privacy);
-    println!("   DB Path: {}", config.db_path);
+    println!("   Data Dir: {}", data_dir.root().display());
+    println!("   DB Path: {} ({:?})", config.db_path(), config.db_backend);
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    let consensus: Arc<dyn ConsensusEngine> = match config.consensus {
-        ConsensusType::PoW => {
-            println!(" PoW mode - difficulty: {}", config.difficulty);
-            Arc::new(PoWEngine::new(config.difficulty))
-        }
-        ConsensusType::PoS => {
-            println!("PoS mode - min stake: {}", config.min_stake);
-            let pos_config = crate::consensus::pos::PoSConfig {
-                min_stake: config.min_stake,
-                ..Default::default()
-            };
-            let keys = if let Some(ref path) = config.validator_key_file {
-                match crate::crypto::ValidatorKeys::load(path) {
-                    Ok(k) => Some(k),
-                    Err(e) => {
-                        println!("Failed to load validator keys from {}: {}", path, e);
-                        None
-                    }
-                }
-            } else {
-                None
-            };
-            Arc::new(PoSEngine::new(pos_config, keys))
-        }
-        ConsensusType::PoA => {
-            println!("PoA mode");
-            Arc::new(PoAEngine::new(
-                crate::consensus::poa::PoAConfig::default(),
-                None,
-            ))
-        }
-    };
-    let storage = match storage::Storage::new(&config.db_path) {
-        Ok(s) => Some(s),
+    let consensus = config.build_consensus(validator_keys.clone());
+    let blockchain = match config.build_blockchain(consensus) {
+        Ok(blockchain) => Arc::new(Mutex::new(blockchain)),
         Err(e) => {
-            println!("Failed to initialize storage: {}", e);
-            None
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
     };
 
-    let pruning_manager = snapshot::PruningManager::new(1000, 100, "./data/snapshots".to_string());
-
-    let blockchain = Arc::new(Mutex::new(Blockchain::new(
-        consensus,
-        storage,
-        config.chain_id,
-        Some(pruning_manager),
-    )));
-
-    if let Some(ref keys) = (match config.consensus {
-        ConsensusType::PoS => {
-           let mut bc = blockchain.lock().unwrap();
-           if let Some(ref v_path) = config.validator_key_file {
-               if let Ok(keys) = crate::crypto::ValidatorKeys::load(v_path) {
-                    let addr = keys.sig_key.public_key_hex();
-                    println!("Auto-bootstrapping validator: {}", addr);
-                    bc.state.add_balance(&addr, 1_000_000);
-                    let mut v = crate::account::Validator::new(addr.clone(), 100_000);
-                    v.active = true;
-                    v.vrf_public_key = keys.vrf_key.public.to_bytes().to_vec();
-                    bc.state.validators.insert(addr, v);
-                    Some(keys)
-               } else { None }
-           } else { None }
+    if let Err(e) = blockchain.lock().unwrap().verify_weak_subjectivity() {
+        eprintln!("Error: {}. Resync from a trusted peer or snapshot.", e);
+        std::process::exit(1);
+    }
+    for cp in &config.weak_subjectivity {
+        info!("Weak subjectivity checkpoint: {}", cp);
+    }
+
+    if let Some(Command::Chain(cmd)) = config.command.clone() {
+        let mut bc = blockchain.lock().unwrap();
+        if let Err(e) = run_chain_command(cmd, &mut bc) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
-        _ => None,
-    }) {
-       
+        return;
+    }
+
+    let (local_producer, observer) = {
+        let bc = blockchain.lock().unwrap();
+        (bc.local_producer(), bc.is_observer())
+    };
+    match local_producer {
+        Some(addr) => println!("   Validator key: {}", addr),
+        None if config.observer => println!(
+            "   Observer mode (--observer): validating and serving the chain, never producing blocks or admitting transactions"
+        ),
+        None if observer => println!(
+            "   Observer mode: no validator key (--validator-key), following the chain without signing blocks"
+        ),
+        None => {}
+    }
+
+    if let (ConsensusType::PoS, Some(keys)) = (config.consensus, &validator_keys) {
+        let mut bc = blockchain.lock().unwrap();
+        let addr = keys.sig_key.public_key_hex();
+        println!("Auto-bootstrapping validator: {}", addr);
+        bc.state.add_balance(&addr, 1_000_000);
+        let mut v = budlum_core::account::Validator::new(addr.clone(), 100_000);
+        v.active = true;
+        v.vrf_public_key = keys.vrf_key.public.to_bytes().to_vec();
+        v.randao_commitment = budlum_core::randao::HashOnion::from_keys(
+            keys,
+            0,
+            budlum_core::randao::RANDAO_ONION_DEPTH,
+        )
+        .commitment()
+        .to_vec();
+        let bls = budlum_core::crypto::BlsKeyPair::derive(&keys.sig_key);
+        v.bls_public_key = bls.public_key_bytes().to_vec();
+        v.bls_pop = budlum_core::consensus::finality::sign_pop(&bls, config.chain_id, &addr);
+        bc.state.validators.insert(addr, v);
     }
 
     if let ConsensusType::PoA = config.consensus {
@@ -131,7 +685,7 @@ async fn main() {
             println!("Initializing PoA validators: {:?}", validators);
             let mut bc = blockchain.lock().unwrap();
             for addr in validators {
-                let mut v = crate::account::Validator::new(addr.clone(), 0);
+                let mut v = budlum_core::account::Validator::new(addr.clone(), 0);
                 v.active = true;
                 bc.state.validators.insert(addr, v);
             }
@@ -140,7 +694,32 @@ async fn main() {
         }
     }
 
-    let mut node = Node::new(blockchain.clone()).unwrap();
+    let mut node = Node::new_with_gossip(blockchain.clone(), config.gossip.clone())
+        .unwrap()
+        .with_peer_store(data_dir.peers_file())
+        .with_max_peers(config.max_peers)
+        .with_bandwidth_limits(config.max_peer_inbound_bps, config.max_inbound_bps);
+    match config.chaos_config() {
+        Ok(Some(chaos)) => node = node.with_chaos(chaos),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if !config.bootnodes.is_empty() {
+        println!(
+            "Dialing {} bootnodes from the chain spec",
+            config.bootnodes.len()
+        );
+        node = node.with_bootnodes(&config.bootnodes).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+    if !config.dns_seeds.is_empty() {
+        node = node.with_dns_seeds(config.dns_seeds.clone());
+    }
     if let Some(ref addr) = config.bootstrap {
         if let Err(e) = node.bootstrap(addr) {
             eprintln!("Failed to bootstrap: {}", e);
@@ -151,9 +730,29 @@ async fn main() {
         node.dial(addr).expect("Failed to dial");
     }
     let client = node.get_client();
+    client.spawn_tx_relay(blockchain.lock().unwrap().subscribe_events());
     let peer_id = node.peer_id;
+    let metrics = SharedMetrics::default();
+    let rpc_rate_limit = spawn_rpc_services(&config, &blockchain);
+    spawn_admin_service(&config, &blockchain, &client);
+    spawn_event_consumer(
+        blockchain.lock().unwrap().subscribe_events(),
+        metrics.clone(),
+    );
     tokio::select! {
         _ = node.run() => {},
+        _ = async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(mut hangup) => {
+                    while hangup.recv().await.is_some() {
+                        info!("SIGHUP received, reloading configuration");
+                        reload_runtime_config(&client, &blockchain, &log_handle, &rpc_rate_limit).await;
+                    }
+                }
+                Err(e) => warn!("Failed to install SIGHUP handler: {}", e),
+            }
+            std::future::pending::<()>().await
+        } => {}
         _ = async {
             let mut stdin = tokio::io::BufReader::new(tokio::io::stdin());
             let mut line = String::new();
@@ -163,30 +762,91 @@ async fn main() {
                 line.clear();
                 use tokio::io::AsyncBufReadExt;
                 if stdin.read_line(&mut line).await.is_ok() {
-                    let cmd = line.trim();
+                    let mut words = line.split_whitespace();
+                    let cmd = words.next().unwrap_or("");
                     match cmd {
                         "tx" => {
-                            let tx = Transaction::new(
-                                peer_id.to_string(),
-                                "recipient".to_string(),
-                                10,
-                                b"demo tx".to_vec(),
-                            );
+                            // tx [to] [amount] [nonce|auto]
+                            let from = peer_id.to_string();
+                            let to = words.next().unwrap_or("recipient").to_string();
+                            let amount = match words.next().map(str::parse::<u64>) {
+                                Some(Ok(amount)) => amount,
+                                Some(Err(e)) => {
+                                    println!("Invalid amount: {}", e);
+                                    continue;
+                                }
+                                None => 10,
+                            };
+                            let nonce = match words.next() {
+                                None | Some("auto") => blockchain.lock().unwrap().get_next_nonce(&from),
+                                Some(n) => match n.parse::<u64>() {
+                                    Ok(nonce) => nonce,
+                                    Err(e) => {
+                                        println!("Invalid nonce: {}", e);
+                                        continue;
+                                    }
+                                },
+                            };
+                            let tx = Transaction::new_with_fee(from, to, amount, 0, nonce, b"demo tx".to_vec());
+                            println!("Sending tx {} with nonce {}", tx.hash, nonce);
                             client.broadcast("transactions".to_string(), NetworkMessage::Transaction(tx)).await;
                         }
                         "block" | "mine" => {
                             let mut chain = blockchain.lock().unwrap();
-                            let producer = if let Some(addr) = &config.validator_address {
-                                addr.clone()
-                            } else {
-                                peer_id.to_string()
-                            };
+                            if chain.is_observer() {
+                                println!("Observer mode: this node does not produce blocks");
+                                continue;
+                            }
+                            let producer = chain
+                                .local_producer()
+                                .or_else(|| config.validator_address.clone())
+                                .unwrap_or_else(|| peer_id.to_string());
                             chain.produce_block(producer);
                         }
+                        "pause" => {
+                            if !blockchain.lock().unwrap().pause_production() {
+                                println!("Block production is already paused");
+                            }
+                        }
+                        "resume" => {
+                            if !blockchain.lock().unwrap().resume_production() {
+                                println!("Block production is not paused");
+                            }
+                        }
                         "chain" => {
                             let chain = blockchain.lock().unwrap();
                             chain.print_info();
                         }
+                        "pending" => {
+                            let address = words
+                                .next()
+                                .map(str::to_string)
+                                .or_else(|| validator_keys.as_ref().map(|k| k.sig_key.public_key_hex()));
+                            let Some(address) = address else {
+                                println!("Usage: pending <address>");
+                                continue;
+                            };
+                            for tx in blockchain.lock().unwrap().mempool.pending_for_sender(&address) {
+                                println!("   nonce {} fee {} -> {} amount {} ({})", tx.nonce, tx.fee, tx.to, tx.amount, tx.hash);
+                            }
+                        }
+                        "cancel" => {
+                            let Some(keys) = validator_keys.as_ref() else {
+                                println!("Load a key with --validator-key-file to cancel transactions");
+                                continue;
+                            };
+                            let nonce = match words.next().map(str::parse::<u64>) {
+                                Some(Ok(nonce)) => nonce,
+                                _ => {
+                                    println!("Usage: cancel <nonce>");
+                                    continue;
+                                }
+                            };
+                            match blockchain.lock().unwrap().cancel_pending_transaction(&keys.sig_key, nonce) {
+                                Ok(tx) => println!("Replaced nonce {} with cancellation {} (fee {})", nonce, tx.hash, tx.fee),
+                                Err(e) => println!("Cancel failed: {}", e),
+                            }
+                        }
                         "peers" => {
                             client.list_peers().await;
                         }
@@ -197,14 +857,31 @@ async fn main() {
                             };
                             client.broadcast("blocks".to_string(), msg).await;
                         }
+                        "snapshot" => {
+                            client.request_snapshot(0).await;
+                        }
+                        "reload" => {
+                            reload_runtime_config(&client, &blockchain, &log_handle, &rpc_rate_limit).await;
+                        }
+                        #[cfg(feature = "metrics")]
+                        "metrics" => {
+                            println!("{:#?}", metrics.lock().unwrap());
+                        }
                         "help" => {
                             println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
                             println!("Commands:");
-                            println!("   tx    - Send demo transaction");
+                            println!("   tx [to] [amount] [nonce|auto] - Send demo transaction (nonce auto-assigned)");
                             println!("   mine  - Produce new block");
+                            println!("   pause / resume - Stop or restart block production and tx admission");
                             println!("   chain - Show blockchain info");
+                            println!("   pending [address] - List pending txs of an address (default: own key)");
+                            println!("   cancel <nonce> - Replace own pending tx with a 0-value self-transfer");
                             println!("   peers - List connected peers");
                             println!("   sync  - Request chain sync");
+                            println!("   snapshot - Download latest state snapshot");
+                            println!("   reload - Re-read runtime settings from config");
+                            #[cfg(feature = "metrics")]
+                            println!("   metrics - Show block, reorg and finality counters");
                             println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
                         }
                         _ => {}
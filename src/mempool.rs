@@ -1,7 +1,12 @@
-use crate::transaction::Transaction;
+use crate::analytics::FeePercentiles;
+use crate::transaction::{Transaction, TransactionType};
 use std::collections::{BTreeMap, HashMap, HashSet};
 
-#[derive(Debug, Clone)]
+// Admission policy for pending transactions. A chain spec sets the
+// network's defaults; a node's `[mempool]` section may override them since
+// none of these values decide block validity.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct MempoolConfig {
     pub max_size: usize,
 
@@ -12,6 +17,14 @@ pub struct MempoolConfig {
     pub tx_ttl_secs: u64,
 
     pub rbf_bump_percent: u64,
+
+    pub rebroadcast_after_blocks: u64,
+
+    pub rebroadcast_limit: usize,
+
+    pub priority_senders: Vec<String>,
+
+    pub producer_rules: Vec<ProducerRule>,
 }
 
 impl Default for MempoolConfig {
@@ -22,7 +35,59 @@ impl Default for MempoolConfig {
             min_fee: 1,
             tx_ttl_secs: 3600,
             rbf_bump_percent: 10,
+            rebroadcast_after_blocks: 5,
+            rebroadcast_limit: 16,
+            priority_senders: Vec::new(),
+            producer_rules: Vec::new(),
+        }
+    }
+}
+
+impl MempoolConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size == 0 || self.max_per_sender == 0 {
+            return Err("Mempool max_size and max_per_sender must be non-zero".into());
+        }
+        for rule in &self.producer_rules {
+            rule.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ProducerAction {
+    Include,
+    Deprioritize,
+}
+
+// An operator's rule for ordering transactions in blocks this node produces,
+// e.g. putting governance votes first. It matches on sender and/or type and
+// never changes what other producers' blocks may contain.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProducerRule {
+    #[serde(default)]
+    pub sender: Option<String>,
+    #[serde(default)]
+    pub tx_type: Option<TransactionType>,
+    pub action: ProducerAction,
+}
+
+impl ProducerRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sender.is_none() && self.tx_type.is_none() {
+            return Err("producer rule needs a sender or tx_type".into());
         }
+        Ok(())
+    }
+
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        self.sender.as_ref().is_none_or(|s| *s == tx.from)
+            && self.tx_type.as_ref().is_none_or(|t| *t == tx.tx_type)
     }
 }
 
@@ -38,10 +103,42 @@ pub enum MempoolError {
     InvalidTransaction(String),
 }
 
+pub const MAX_REBROADCAST_BACKOFF_SHIFT: u32 = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct PriorityStats {
+    pub admitted: u64,
+    pub sender_cap_bypasses: u64,
+    pub evictions: u64,
+    pub pending: usize,
+}
+
+// Transactions that left the pool without being included, by reason, so a
+// support case for a transaction that disappeared can be narrowed down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct EvictionStats {
+    pub evicted_low_fee: u64,
+    pub replaced: u64,
+    pub expired_ttl: u64,
+    pub expired_height: u64,
+    pub dropped_on_reorg: u64,
+}
+
+// A sender's pending transactions. `ready` is the run of consecutive nonces
+// starting at the sender's state nonce; `future` waits on a missing nonce
+// (or reuses one already spent) and cannot be included yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SenderQueues {
+    pub ready: Vec<Transaction>,
+    pub future: Vec<Transaction>,
+}
+
 #[derive(Debug, Clone)]
 struct PendingTx {
     tx: Transaction,
     added_at: u128,
+    next_rebroadcast: u64,
+    rebroadcasts: u32,
 }
 
 pub struct Mempool {
@@ -52,27 +149,85 @@ pub struct Mempool {
     by_sender: HashMap<String, BTreeMap<u64, String>>,
 
     by_fee: BTreeMap<u64, HashSet<String>>,
+
+    height: u64,
+
+    priority_stats: PriorityStats,
+
+    eviction_stats: EvictionStats,
 }
 
 impl Mempool {
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: MempoolConfig) {
+        self.config = config;
+    }
     pub fn new(config: MempoolConfig) -> Self {
         Mempool {
             config,
             transactions: HashMap::new(),
             by_sender: HashMap::new(),
             by_fee: BTreeMap::new(),
+            height: 0,
+            priority_stats: PriorityStats::default(),
+            eviction_stats: EvictionStats::default(),
+        }
+    }
+
+    // Whitelisted system accounts (oracles, bridges) skip fee ordering and
+    // the per-sender cap so they always make it into the next block.
+    pub fn is_priority_sender(&self, sender: &str) -> bool {
+        self.config.priority_senders.iter().any(|s| s == sender)
+    }
+
+    pub fn priority_stats(&self) -> PriorityStats {
+        PriorityStats {
+            pending: self
+                .config
+                .priority_senders
+                .iter()
+                .filter_map(|s| self.by_sender.get(s))
+                .map(|nonces| nonces.len())
+                .sum(),
+            ..self.priority_stats
         }
     }
 
+    pub fn eviction_stats(&self) -> EvictionStats {
+        self.eviction_stats
+    }
+
+    // Carries the counters over when the pool is rebuilt after a reorg.
+    pub fn restore_eviction_stats(&mut self, stats: EvictionStats) {
+        self.eviction_stats = stats;
+    }
+
+    // Counts pending transactions a reorg found invalid against the new tip.
+    pub fn record_reorg_drops(&mut self, count: usize) {
+        self.eviction_stats.dropped_on_reorg += count as u64;
+    }
+
+    pub fn set_height(&mut self, height: u64) {
+        self.height = height;
+    }
+
     pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), MempoolError> {
         if self.transactions.contains_key(&tx.hash) {
             return Err(MempoolError::DuplicateTransaction);
         }
 
-        if tx.fee < self.config.min_fee {
+        if tx.tx_type == TransactionType::Batch {
+            tx.batch_outputs()
+                .map_err(MempoolError::InvalidTransaction)?;
+        }
+
+        if tx.fee < self.config.min_fee.saturating_mul(tx.fee_units()) {
             return Err(MempoolError::FeeTooLow);
         }
 
+        let priority = self.is_priority_sender(&tx.from);
         if self.transactions.len() >= self.config.max_size {
             if !self.evict_lowest_fee(&tx) {
                 return Err(MempoolError::PoolFull);
@@ -83,17 +238,20 @@ impl Mempool {
 
         if let Some(existing_hash) = self.find_tx_by_sender_nonce(&tx.from, tx.nonce) {
             let existing = self.transactions.get(&existing_hash).unwrap();
-            let min_new_fee =
-                existing.tx.fee + (existing.tx.fee * self.config.rbf_bump_percent / 100);
-            if tx.fee < min_new_fee {
+            if tx.fee < self.bumped_fee(existing.tx.fee) {
                 return Err(MempoolError::RbfFeeTooLow);
             }
 
             self.remove_transaction(&existing_hash);
-        } else {
-            if sender_count >= self.config.max_per_sender {
+            self.eviction_stats.replaced += 1;
+        } else if sender_count >= self.config.max_per_sender {
+            if !priority {
                 return Err(MempoolError::SenderLimitReached);
             }
+            self.priority_stats.sender_cap_bypasses += 1;
+        }
+        if priority {
+            self.priority_stats.admitted += 1;
         }
 
         let now = std::time::SystemTime::now()
@@ -110,8 +268,16 @@ impl Mempool {
             .or_insert_with(HashSet::new)
             .insert(tx.hash.clone());
 
-        self.transactions
-            .insert(tx.hash.clone(), PendingTx { tx, added_at: now });
+        let next_rebroadcast = self.height + self.config.rebroadcast_after_blocks;
+        self.transactions.insert(
+            tx.hash.clone(),
+            PendingTx {
+                tx,
+                added_at: now,
+                next_rebroadcast,
+                rebroadcasts: 0,
+            },
+        );
 
         Ok(())
     }
@@ -136,17 +302,143 @@ impl Mempool {
         None
     }
 
+    pub fn pending_for_sender(&self, sender: &str) -> Vec<Transaction> {
+        self.by_sender
+            .get(sender)
+            .map(|nonces| {
+                nonces
+                    .values()
+                    .filter_map(|hash| self.get(hash).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Lowest fee a transaction must pay to replace the pending one at `nonce`.
+    pub fn replacement_fee(&self, sender: &str, nonce: u64) -> Option<u64> {
+        self.find_tx_by_sender_nonce(sender, nonce)
+            .and_then(|hash| self.get(&hash))
+            .map(|tx| self.bumped_fee(tx.fee))
+    }
+
+    fn bumped_fee(&self, fee: u64) -> u64 {
+        fee + (fee * self.config.rbf_bump_percent / 100)
+    }
+
+    pub fn queues(&self, state_nonce: impl Fn(&str) -> u64) -> BTreeMap<String, SenderQueues> {
+        self.by_sender
+            .iter()
+            .map(|(sender, nonces)| {
+                let mut queues = SenderQueues::default();
+                let mut next = state_nonce(sender);
+                for (&nonce, hash) in nonces {
+                    let Some(tx) = self.get(hash).cloned() else {
+                        continue;
+                    };
+                    if nonce == next {
+                        queues.ready.push(tx);
+                        next += 1;
+                    } else {
+                        queues.future.push(tx);
+                    }
+                }
+                (sender.clone(), queues)
+            })
+            .collect()
+    }
+
+    pub fn fee_distribution(&self) -> FeePercentiles {
+        let fees: Vec<u64> = self
+            .by_fee
+            .iter()
+            .flat_map(|(&fee, hashes)| std::iter::repeat_n(fee, hashes.len()))
+            .collect();
+        FeePercentiles::from_sorted(&fees)
+    }
+
+    pub fn fee_range(&self) -> Option<(u64, u64)> {
+        Some((
+            *self.by_fee.keys().next()?,
+            *self.by_fee.keys().next_back()?,
+        ))
+    }
+
     pub fn get_sorted_transactions(&self, limit: usize) -> Vec<Transaction> {
         let mut result = Vec::with_capacity(limit);
 
-        for (_, hashes) in self.by_fee.iter().rev() {
-            for hash in hashes {
-                if result.len() >= limit {
-                    return result;
-                }
-                if let Some(pending) = self.transactions.get(hash) {
-                    result.push(pending.tx.clone());
-                }
+        let priority = self
+            .config
+            .priority_senders
+            .iter()
+            .filter_map(|s| self.by_sender.get(s))
+            .flat_map(|nonces| nonces.values());
+        let by_fee = self
+            .by_fee
+            .iter()
+            .rev()
+            .flat_map(|(_, hashes)| hashes.iter())
+            .filter(|hash| {
+                self.transactions
+                    .get(*hash)
+                    .is_some_and(|p| !self.is_priority_sender(&p.tx.from))
+            });
+        for hash in priority.chain(by_fee) {
+            if result.len() >= limit {
+                return result;
+            }
+            if let Some(pending) = self.transactions.get(hash) {
+                result.push(pending.tx.clone());
+            }
+        }
+        result
+    }
+
+    // Candidates for a block this node produces: pool order with the first
+    // matching producer rule moving a transaction to the front or the back.
+    // The sort is stable and the assembler still only takes a sender's
+    // transactions in nonce order, so the block stays valid either way.
+    pub fn block_candidates(&self, limit: usize) -> Vec<Transaction> {
+        let rules = &self.config.producer_rules;
+        if rules.is_empty() {
+            return self.get_sorted_transactions(limit);
+        }
+        let mut txs = self.get_sorted_transactions(self.len());
+        txs.sort_by_key(|tx| match rules.iter().find(|r| r.matches(tx)) {
+            Some(rule) if rule.action == ProducerAction::Include => 0,
+            Some(_) => 2,
+            None => 1,
+        });
+        txs.truncate(limit);
+        txs
+    }
+
+    // Highest-fee transactions still pending `rebroadcast_after_blocks` after
+    // they were added. Each rebroadcast doubles the wait before the next one.
+    pub fn stuck_transactions(&mut self, height: u64) -> Vec<Transaction> {
+        let after = self.config.rebroadcast_after_blocks;
+        if after == 0 {
+            return Vec::new();
+        }
+        let due: Vec<String> = self
+            .by_fee
+            .iter()
+            .rev()
+            .flat_map(|(_, hashes)| hashes.iter())
+            .filter(|hash| {
+                self.transactions
+                    .get(*hash)
+                    .is_some_and(|p| p.next_rebroadcast <= height)
+            })
+            .take(self.config.rebroadcast_limit)
+            .cloned()
+            .collect();
+        let mut result = Vec::with_capacity(due.len());
+        for hash in due {
+            if let Some(pending) = self.transactions.get_mut(&hash) {
+                pending.rebroadcasts += 1;
+                let shift = pending.rebroadcasts.min(MAX_REBROADCAST_BACKOFF_SHIFT);
+                pending.next_rebroadcast = height + (after << shift);
+                result.push(pending.tx.clone());
             }
         }
         result
@@ -170,6 +462,23 @@ impl Mempool {
         for hash in expired {
             self.remove_transaction(&hash);
         }
+        self.eviction_stats.expired_ttl += count as u64;
+        count
+    }
+
+    // Drops transactions that can no longer be included at `height` or later.
+    pub fn remove_expired_at(&mut self, height: u64) -> usize {
+        let expired: Vec<String> = self
+            .transactions
+            .iter()
+            .filter(|(_, p)| p.tx.is_expired_at(height))
+            .map(|(h, _)| h.clone())
+            .collect();
+        let count = expired.len();
+        for hash in expired {
+            self.remove_transaction(&hash);
+        }
+        self.eviction_stats.expired_height += count as u64;
         count
     }
 
@@ -193,6 +502,19 @@ impl Mempool {
         txs
     }
 
+    pub fn next_nonce(&self, sender: &str, state_nonce: u64) -> u64 {
+        let mut next = state_nonce;
+        if let Some(nonces) = self.by_sender.get(sender) {
+            for &nonce in nonces.range(state_nonce..).map(|(n, _)| n) {
+                if nonce != next {
+                    break;
+                }
+                next += 1;
+            }
+        }
+        next
+    }
+
     fn find_tx_by_sender_nonce(&self, sender: &str, nonce: u64) -> Option<String> {
         self.by_sender
             .get(sender)
@@ -200,15 +522,28 @@ impl Mempool {
     }
 
     fn evict_lowest_fee(&mut self, new_tx: &Transaction) -> bool {
-        if let Some((&lowest_fee, hashes)) = self.by_fee.iter().next() {
-            if new_tx.fee > lowest_fee {
-                if let Some(hash) = hashes.iter().next().cloned() {
-                    self.remove_transaction(&hash);
-                    return true;
+        let priority = self.is_priority_sender(&new_tx.from);
+        let lowest = self.by_fee.iter().find_map(|(&fee, hashes)| {
+            hashes
+                .iter()
+                .find(|h| {
+                    self.transactions
+                        .get(*h)
+                        .is_some_and(|p| !self.is_priority_sender(&p.tx.from))
+                })
+                .map(|h| (fee, h.clone()))
+        });
+        match lowest {
+            Some((lowest_fee, hash)) if priority || new_tx.fee > lowest_fee => {
+                self.remove_transaction(&hash);
+                self.eviction_stats.evicted_low_fee += 1;
+                if priority {
+                    self.priority_stats.evictions += 1;
                 }
+                true
             }
+            _ => false,
         }
-        false
     }
 }
 
@@ -221,6 +556,7 @@ impl Default for Mempool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transaction::BatchOutput;
 
     fn create_test_tx(from: &str, nonce: u64, fee: u64) -> Transaction {
         let mut tx = Transaction::new(from.to_string(), "to".to_string(), 100, vec![]);
@@ -257,6 +593,32 @@ mod tests {
         assert_eq!(pool.add_transaction(tx), Err(MempoolError::FeeTooLow));
     }
 
+    #[test]
+    fn test_batch_fee_scales_with_outputs() {
+        let mut pool = Mempool::default();
+        let outputs: Vec<BatchOutput> = (0..3)
+            .map(|i| BatchOutput {
+                to: format!("payee{}", i),
+                amount: 10,
+            })
+            .collect();
+        let mut tx = Transaction::new_batch("alice".into(), &outputs, 0);
+        tx.fee = 2;
+        assert_eq!(
+            pool.add_transaction(tx.clone()),
+            Err(MempoolError::FeeTooLow)
+        );
+        tx.fee = 3;
+        pool.add_transaction(tx).unwrap();
+
+        let mut empty = Transaction::new_batch("bob".into(), &[], 0);
+        empty.fee = 10;
+        assert!(matches!(
+            pool.add_transaction(empty),
+            Err(MempoolError::InvalidTransaction(_))
+        ));
+    }
+
     #[test]
     fn test_sender_limit() {
         let config = MempoolConfig {
@@ -275,6 +637,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_priority_senders_bypass_fees_and_caps() {
+        let config = MempoolConfig {
+            max_size: 3,
+            max_per_sender: 1,
+            priority_senders: vec!["oracle".into()],
+            ..Default::default()
+        };
+        let mut pool = Mempool::new(config);
+        pool.add_transaction(create_test_tx("alice", 0, 50))
+            .unwrap();
+        pool.add_transaction(create_test_tx("oracle", 0, 1))
+            .unwrap();
+        pool.add_transaction(create_test_tx("oracle", 1, 1))
+            .unwrap();
+        assert_eq!(
+            pool.add_transaction(create_test_tx("bob", 0, 10)),
+            Err(MempoolError::PoolFull)
+        );
+        pool.add_transaction(create_test_tx("oracle", 2, 1))
+            .unwrap();
+        assert!(pool.pending_for_sender("alice").is_empty());
+        assert_eq!(
+            pool.add_transaction(create_test_tx("bob", 0, 100)),
+            Err(MempoolError::PoolFull)
+        );
+
+        let order: Vec<(String, u64)> = pool
+            .get_sorted_transactions(10)
+            .into_iter()
+            .map(|tx| (tx.from, tx.nonce))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("oracle".to_string(), 0),
+                ("oracle".to_string(), 1),
+                ("oracle".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            pool.priority_stats(),
+            PriorityStats {
+                admitted: 3,
+                sender_cap_bypasses: 2,
+                evictions: 1,
+                pending: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_next_nonce_skips_pending() {
+        let mut pool = Mempool::default();
+        assert_eq!(pool.next_nonce("alice", 3), 3);
+        pool.add_transaction(create_test_tx("alice", 3, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("alice", 4, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("alice", 6, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("alice", 1, 10))
+            .unwrap();
+        assert_eq!(pool.next_nonce("alice", 3), 5);
+        assert_eq!(pool.next_nonce("alice", 5), 5);
+        assert_eq!(pool.next_nonce("bob", 0), 0);
+    }
+
     #[test]
     fn test_sorted_by_fee() {
         let mut pool = Mempool::default();
@@ -317,4 +747,159 @@ mod tests {
         assert_eq!(pool.len(), 0);
         assert!(pool.is_empty());
     }
+
+    #[test]
+    fn test_remove_expired_at_height() {
+        let mut pool = Mempool::default();
+        let mut expiring = create_test_tx("alice", 0, 10);
+        expiring.expires_at_height = 5;
+        pool.add_transaction(expiring).unwrap();
+        pool.add_transaction(create_test_tx("bob", 0, 10)).unwrap();
+
+        assert_eq!(pool.remove_expired_at(5), 0);
+        assert_eq!(pool.remove_expired_at(6), 1);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get("tx_bob_0").is_some());
+    }
+
+    #[test]
+    fn test_pending_for_sender_and_replacement_fee() {
+        let mut pool = Mempool::default();
+        pool.add_transaction(create_test_tx("alice", 1, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("alice", 0, 20))
+            .unwrap();
+        pool.add_transaction(create_test_tx("bob", 0, 5)).unwrap();
+
+        let nonces: Vec<u64> = pool
+            .pending_for_sender("alice")
+            .iter()
+            .map(|tx| tx.nonce)
+            .collect();
+        assert_eq!(nonces, vec![0, 1]);
+        assert!(pool.pending_for_sender("carol").is_empty());
+        assert_eq!(pool.replacement_fee("alice", 0), Some(22));
+        assert_eq!(pool.replacement_fee("alice", 2), None);
+    }
+
+    #[test]
+    fn test_stuck_transactions_backoff() {
+        let config = MempoolConfig {
+            rebroadcast_after_blocks: 2,
+            rebroadcast_limit: 1,
+            ..Default::default()
+        };
+        let mut pool = Mempool::new(config);
+        pool.set_height(10);
+        pool.add_transaction(create_test_tx("alice", 0, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("bob", 0, 50)).unwrap();
+
+        assert!(pool.stuck_transactions(11).is_empty());
+        let first = pool.stuck_transactions(12);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].fee, 50);
+        let second = pool.stuck_transactions(12);
+        assert_eq!(second[0].fee, 10);
+
+        // bob's next rebroadcast waits 2 << 1 blocks
+        assert!(pool.stuck_transactions(15).is_empty());
+        assert_eq!(pool.stuck_transactions(16)[0].fee, 50);
+
+        pool.config.rebroadcast_after_blocks = 0;
+        assert!(pool.stuck_transactions(100).is_empty());
+    }
+
+    #[test]
+    fn test_producer_rules_order_block_candidates() {
+        let mut vote = create_test_tx("carol", 0, 1);
+        vote.tx_type = TransactionType::Vote;
+        let config = MempoolConfig {
+            producer_rules: vec![
+                ProducerRule {
+                    sender: None,
+                    tx_type: Some(TransactionType::Vote),
+                    action: ProducerAction::Include,
+                },
+                ProducerRule {
+                    sender: Some("spammer".into()),
+                    tx_type: None,
+                    action: ProducerAction::Deprioritize,
+                },
+            ],
+            ..Default::default()
+        };
+        let mut pool = Mempool::new(config);
+        pool.add_transaction(create_test_tx("alice", 0, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("spammer", 0, 90))
+            .unwrap();
+        pool.add_transaction(create_test_tx("bob", 0, 20)).unwrap();
+        pool.add_transaction(vote).unwrap();
+
+        let order = |txs: Vec<Transaction>| txs.into_iter().map(|tx| tx.from).collect::<Vec<_>>();
+        assert_eq!(
+            order(pool.get_sorted_transactions(10)),
+            vec!["spammer", "bob", "alice", "carol"]
+        );
+        assert_eq!(
+            order(pool.block_candidates(10)),
+            vec!["carol", "bob", "alice", "spammer"]
+        );
+        assert_eq!(order(pool.block_candidates(1)), vec!["carol"]);
+
+        let catch_all = ProducerRule {
+            sender: None,
+            tx_type: None,
+            action: ProducerAction::Include,
+        };
+        assert!(catch_all.validate().is_err());
+    }
+
+    #[test]
+    fn test_queues_and_eviction_stats() {
+        let config = MempoolConfig {
+            max_size: 6,
+            ..Default::default()
+        };
+        let mut pool = Mempool::new(config);
+        for nonce in [2, 3, 5] {
+            pool.add_transaction(create_test_tx("alice", nonce, 10))
+                .unwrap();
+        }
+        pool.add_transaction(create_test_tx("alice", 1, 10))
+            .unwrap();
+        pool.add_transaction(create_test_tx("bob", 0, 2)).unwrap();
+        let mut expiring = create_test_tx("carol", 0, 40);
+        expiring.expires_at_height = 3;
+        pool.add_transaction(expiring).unwrap();
+
+        let queues = pool.queues(|sender| if sender == "alice" { 2 } else { 0 });
+        let nonces = |txs: &[Transaction]| txs.iter().map(|tx| tx.nonce).collect::<Vec<_>>();
+        assert_eq!(nonces(&queues["alice"].ready), vec![2, 3]);
+        assert_eq!(nonces(&queues["alice"].future), vec![1, 5]);
+        assert_eq!(nonces(&queues["bob"].ready), vec![0]);
+
+        assert_eq!(pool.fee_range(), Some((2, 40)));
+        assert_eq!(pool.fee_distribution().p50, 10);
+
+        pool.add_transaction(create_test_tx("dave", 0, 30)).unwrap();
+        assert!(pool.get("tx_bob_0").is_none());
+        pool.remove_expired_at(4);
+        let mut bump = create_test_tx("alice", 5, 20);
+        bump.hash = "tx_alice_5_v2".into();
+        pool.add_transaction(bump).unwrap();
+        pool.record_reorg_drops(2);
+        assert_eq!(
+            pool.eviction_stats(),
+            EvictionStats {
+                evicted_low_fee: 1,
+                replaced: 1,
+                expired_ttl: 0,
+                expired_height: 1,
+                dropped_on_reorg: 2,
+            }
+        );
+        assert_eq!(Mempool::default().fee_range(), None);
+    }
 }
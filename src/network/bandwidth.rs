@@ -0,0 +1,135 @@
+use crate::network::protocol::MAX_MESSAGE_SIZE;
+use std::collections::HashMap;
+
+pub const BANDWIDTH_BURST_SECS: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandwidthExceeded {
+    Peer,
+    Global,
+}
+
+#[derive(Debug, Clone)]
+struct ByteBucket {
+    tokens: f64,
+    last_refill_ms: u64,
+}
+
+impl ByteBucket {
+    fn new(capacity: f64, now_ms: u64) -> Self {
+        ByteBucket {
+            tokens: capacity,
+            last_refill_ms: now_ms,
+        }
+    }
+
+    fn refill(&mut self, rate: u64, capacity: f64, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.last_refill_ms) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * rate as f64).min(capacity);
+        self.last_refill_ms = now_ms;
+    }
+}
+
+// Inbound byte budgets per peer and for the whole node. Message size limits
+// alone let a peer stream valid-sized messages without pause; these caps
+// bound the sustained rate. A limit of 0 disables that check.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    per_peer_bytes_per_sec: u64,
+    global_bytes_per_sec: u64,
+    peers: HashMap<String, ByteBucket>,
+    global: Option<ByteBucket>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(per_peer_bytes_per_sec: u64, global_bytes_per_sec: u64) -> Self {
+        BandwidthLimiter {
+            per_peer_bytes_per_sec,
+            global_bytes_per_sec,
+            peers: HashMap::new(),
+            global: None,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0, 0)
+    }
+
+    // Room for a full burst, and never less than one maximum-size message.
+    fn capacity(rate: u64) -> f64 {
+        (rate * BANDWIDTH_BURST_SECS).max(MAX_MESSAGE_SIZE as u64) as f64
+    }
+
+    // Charges `bytes` from `peer` against both budgets. A message over the
+    // peer's budget is not charged globally, so one flooding peer cannot
+    // starve the others.
+    pub fn charge(
+        &mut self,
+        peer: &str,
+        bytes: usize,
+        now_ms: u64,
+    ) -> Result<(), BandwidthExceeded> {
+        let bytes = bytes as f64;
+        if self.per_peer_bytes_per_sec > 0 {
+            let rate = self.per_peer_bytes_per_sec;
+            let capacity = Self::capacity(rate);
+            let bucket = self
+                .peers
+                .entry(peer.to_string())
+                .or_insert_with(|| ByteBucket::new(capacity, now_ms));
+            bucket.refill(rate, capacity, now_ms);
+            if bucket.tokens < bytes {
+                return Err(BandwidthExceeded::Peer);
+            }
+            bucket.tokens -= bytes;
+        }
+        if self.global_bytes_per_sec > 0 {
+            let rate = self.global_bytes_per_sec;
+            let capacity = Self::capacity(rate);
+            let bucket = self
+                .global
+                .get_or_insert_with(|| ByteBucket::new(capacity, now_ms));
+            bucket.refill(rate, capacity, now_ms);
+            if bucket.tokens < bytes {
+                return Err(BandwidthExceeded::Global);
+            }
+            bucket.tokens -= bytes;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, peer: &str) {
+        self.peers.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_and_global_caps() {
+        let burst = MAX_MESSAGE_SIZE;
+        let mut limiter = BandwidthLimiter::new(1_000_000, 3_000_000);
+        assert_eq!(limiter.charge("a", burst, 0), Ok(()));
+        assert_eq!(limiter.charge("a", 1, 0), Err(BandwidthExceeded::Peer));
+        assert_eq!(limiter.charge("b", 1, 0), Err(BandwidthExceeded::Global));
+
+        assert_eq!(limiter.charge("b", 2_000_000, 1_000), Ok(()));
+        assert_eq!(
+            limiter.charge("c", 2_000_000, 1_000),
+            Err(BandwidthExceeded::Global)
+        );
+        assert_eq!(limiter.charge("a", 500_000, 1_000), Ok(()));
+        assert_eq!(
+            limiter.charge("a", 600_000, 1_000),
+            Err(BandwidthExceeded::Peer)
+        );
+        assert_eq!(limiter.charge("c", 2_000_000, 2_000), Ok(()));
+
+        let mut open = BandwidthLimiter::unlimited();
+        for _ in 0..100 {
+            assert_eq!(open.charge("a", burst, 0), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+
+pub const BOOTNODE_MIN_BACKOFF_MS: u64 = 1_000;
+pub const BOOTNODE_MAX_BACKOFF_MS: u64 = 5 * 60 * 1000;
+
+// Bootnodes have to name their peer id so a connection can be matched back
+// to the address that was dialed.
+pub fn bootnode_peer_id(addr: &str) -> Result<String, String> {
+    let multiaddr: Multiaddr = addr
+        .parse()
+        .map_err(|e| format!("Invalid bootnode {}: {}", addr, e))?;
+    multiaddr
+        .iter()
+        .find_map(|p| match p {
+            Protocol::P2p(peer_id) => Some(peer_id.to_string()),
+            _ => None,
+        })
+        .ok_or_else(|| format!("Bootnode {} must end in /p2p/<ID>", addr))
+}
+
+#[derive(Debug, Clone)]
+struct Bootnode {
+    addr: String,
+    peer: String,
+    connected: bool,
+    backoff_ms: u64,
+    next_dial_ms: u64,
+}
+
+// Keeps the chain spec's bootnodes connected: each one that is not connected
+// is redialed with exponential backoff, reset once a connection succeeds.
+#[derive(Debug, Clone, Default)]
+pub struct BootnodeDialer {
+    nodes: Vec<Bootnode>,
+}
+
+impl BootnodeDialer {
+    pub fn new(addrs: &[String]) -> Result<Self, String> {
+        let nodes = addrs
+            .iter()
+            .map(|addr| {
+                Ok(Bootnode {
+                    addr: addr.clone(),
+                    peer: bootnode_peer_id(addr)?,
+                    connected: false,
+                    backoff_ms: BOOTNODE_MIN_BACKOFF_MS,
+                    next_dial_ms: 0,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(BootnodeDialer { nodes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Addresses to dial now. Each is scheduled for its next attempt and its
+    // backoff doubled, in case this dial fails too.
+    pub fn due(&mut self, now_ms: u64) -> Vec<String> {
+        let mut due = Vec::new();
+        for node in self
+            .nodes
+            .iter_mut()
+            .filter(|n| !n.connected && n.next_dial_ms <= now_ms)
+        {
+            node.next_dial_ms = now_ms + node.backoff_ms;
+            node.backoff_ms = (node.backoff_ms * 2).min(BOOTNODE_MAX_BACKOFF_MS);
+            due.push(node.addr.clone());
+        }
+        due
+    }
+
+    pub fn on_connected(&mut self, peer: &str) {
+        for node in self.nodes.iter_mut().filter(|n| n.peer == peer) {
+            node.connected = true;
+            node.backoff_ms = BOOTNODE_MIN_BACKOFF_MS;
+        }
+    }
+
+    pub fn on_disconnected(&mut self, peer: &str, now_ms: u64) {
+        for node in self.nodes.iter_mut().filter(|n| n.peer == peer) {
+            node.connected = false;
+            node.next_dial_ms = now_ms + node.backoff_ms;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bootnodes_redial_with_backoff() {
+        let peer = libp2p::PeerId::random().to_string();
+        let addr = format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer);
+        assert!(BootnodeDialer::new(&["/ip4/127.0.0.1/tcp/4001".to_string()]).is_err());
+        assert!(BootnodeDialer::new(&["not an address".to_string()]).is_err());
+
+        let mut dialer = BootnodeDialer::new(&[addr.clone()]).unwrap();
+        assert_eq!(dialer.due(0), vec![addr.clone()]);
+        assert!(dialer.due(999).is_empty());
+        assert_eq!(dialer.due(1_000), vec![addr.clone()]);
+        assert!(dialer.due(2_999).is_empty());
+        assert_eq!(dialer.due(3_000), vec![addr.clone()]);
+
+        dialer.on_connected(&peer);
+        assert!(dialer.due(100_000).is_empty());
+        dialer.on_disconnected(&peer, 100_000);
+        assert!(dialer.due(100_999).is_empty());
+        assert_eq!(dialer.due(101_000), vec![addr]);
+    }
+}
@@ -0,0 +1,146 @@
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::{Name, TokioAsyncResolver};
+use libp2p::Multiaddr;
+use tracing::warn;
+
+pub const DNS_SEED_REFRESH_SECS: u64 = 600;
+pub const DNS_SEED_SRV_PREFIX: &str = "_budlum._tcp.";
+
+pub fn validate_seed_domain(domain: &str) -> Result<(), String> {
+    Name::from_ascii(domain)
+        .map(|_| ())
+        .map_err(|e| format!("Invalid DNS seed {}: {}", domain, e))
+}
+
+// A TXT string may list several whitespace separated multiaddrs, optionally
+// in the libp2p `dnsaddr=` form. Anything that does not parse is skipped.
+pub fn parse_txt(record: &str) -> Vec<String> {
+    record
+        .split_whitespace()
+        .map(|entry| entry.strip_prefix("dnsaddr=").unwrap_or(entry))
+        .filter(|entry| entry.parse::<Multiaddr>().is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+// Looks up TXT records on each seed domain and SRV records on
+// `_budlum._tcp.<domain>`. SRV targets are resolved to IPs here because the
+// swarm has no DNS transport.
+pub async fn resolve_dns_seeds(domains: Vec<String>) -> Vec<String> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+        TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+    });
+    let mut addrs = Vec::new();
+    for domain in domains {
+        match resolver.txt_lookup(domain.as_str()).await {
+            Ok(records) => {
+                for txt in records.iter() {
+                    for data in txt.txt_data() {
+                        addrs.extend(parse_txt(&String::from_utf8_lossy(data)));
+                    }
+                }
+            }
+            Err(e) => warn!("DNS seed {}: no TXT records: {}", domain, e),
+        }
+        let srv_name = format!("{}{}", DNS_SEED_SRV_PREFIX, domain);
+        let Ok(records) = resolver.srv_lookup(srv_name.as_str()).await else {
+            continue;
+        };
+        for srv in records.iter() {
+            let Ok(ips) = resolver.lookup_ip(srv.target().clone()).await else {
+                continue;
+            };
+            for ip in ips.iter() {
+                let proto = if ip.is_ipv4() { "ip4" } else { "ip6" };
+                addrs.push(format!("/{}/{}/tcp/{}", proto, ip, srv.port()));
+            }
+        }
+    }
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+// Seed domains from the chain spec and the peers they last resolved to. The
+// node falls back to these when it has no peers, e.g. because every
+// Kademlia bootstrap node is unreachable.
+#[derive(Debug, Clone, Default)]
+pub struct DnsSeeds {
+    domains: Vec<String>,
+    addrs: Vec<String>,
+    refreshed_ms: Option<u64>,
+    resolving: bool,
+}
+
+impl DnsSeeds {
+    pub fn new(domains: Vec<String>) -> Self {
+        DnsSeeds {
+            domains,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    pub fn addrs(&self) -> &[String] {
+        &self.addrs
+    }
+
+    pub fn needs_refresh(&self, now_ms: u64) -> bool {
+        !self.resolving
+            && self
+                .refreshed_ms
+                .is_none_or(|at| now_ms.saturating_sub(at) >= DNS_SEED_REFRESH_SECS * 1000)
+    }
+
+    pub fn start_refresh(&mut self) -> Vec<String> {
+        self.resolving = true;
+        self.domains.clone()
+    }
+
+    // An empty answer keeps the previous list; a flaky resolver should not
+    // erase seeds that worked.
+    pub fn update(&mut self, addrs: Vec<String>, now_ms: u64) {
+        self.resolving = false;
+        self.refreshed_ms = Some(now_ms);
+        if !addrs.is_empty() {
+            self.addrs = addrs;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_txt_parsing_and_refresh() {
+        let peer = libp2p::PeerId::random();
+        let record = format!(
+            "dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/{} /ip6/::1/tcp/4001 garbage",
+            peer
+        );
+        assert_eq!(
+            parse_txt(&record),
+            vec![
+                format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", peer),
+                "/ip6/::1/tcp/4001".to_string()
+            ]
+        );
+        assert!(validate_seed_domain("seed.budlum.example").is_ok());
+        assert!(validate_seed_domain("bad domain").is_err());
+
+        let mut seeds = DnsSeeds::new(vec!["seed.budlum.example".into()]);
+        assert!(seeds.needs_refresh(0));
+        assert_eq!(seeds.start_refresh(), vec!["seed.budlum.example"]);
+        assert!(!seeds.needs_refresh(0));
+        seeds.update(vec!["/ip4/1.2.3.4/tcp/4001".into()], 1_000);
+        assert!(!seeds.needs_refresh(1_000 + DNS_SEED_REFRESH_SECS * 1000 - 1));
+        assert!(seeds.needs_refresh(1_000 + DNS_SEED_REFRESH_SECS * 1000));
+        seeds.start_refresh();
+        seeds.update(vec![], 700_000);
+        assert_eq!(seeds.addrs().len(), 1);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::network::protocol::MAX_MESSAGE_SIZE;
+use libp2p::gossipsub;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GossipConfig {
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+    pub mesh_outbound_min: usize,
+    pub gossip_lazy: usize,
+    pub heartbeat_interval_ms: u64,
+    pub flood_publish: bool,
+    pub max_transmit_size: usize,
+    pub topic_max_sizes: HashMap<String, usize>,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        GossipConfig {
+            mesh_n: 6,
+            mesh_n_low: 5,
+            mesh_n_high: 12,
+            mesh_outbound_min: 2,
+            gossip_lazy: 6,
+            heartbeat_interval_ms: 10_000,
+            flood_publish: true,
+            max_transmit_size: MAX_MESSAGE_SIZE,
+            topic_max_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl GossipConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.mesh_outbound_min <= self.mesh_n_low
+            && self.mesh_n_low <= self.mesh_n
+            && self.mesh_n <= self.mesh_n_high)
+        {
+            return Err(
+                "Gossip mesh sizes must satisfy mesh_outbound_min <= mesh_n_low <= mesh_n <= mesh_n_high"
+                    .into(),
+            );
+        }
+        if self.mesh_outbound_min * 2 > self.mesh_n {
+            return Err("Gossip mesh_outbound_min must be at most half of mesh_n".into());
+        }
+        if self.heartbeat_interval_ms == 0 {
+            return Err("Gossip heartbeat_interval_ms must be positive".into());
+        }
+        if self.max_transmit_size == 0 || self.max_transmit_size > MAX_MESSAGE_SIZE {
+            return Err(format!(
+                "Gossip max_transmit_size must be within 1..={}",
+                MAX_MESSAGE_SIZE
+            ));
+        }
+        if let Some((topic, _)) = self
+            .topic_max_sizes
+            .iter()
+            .find(|(_, size)| **size == 0 || **size > self.max_transmit_size)
+        {
+            return Err(format!(
+                "Max message size for topic {} must be within 1..={}",
+                topic, self.max_transmit_size
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn max_message_size(&self, topic: &str) -> usize {
+        self.topic_max_sizes
+            .get(topic)
+            .copied()
+            .unwrap_or(self.max_transmit_size)
+    }
+
+    // Messages are only forwarded once the node reports them valid, so
+    // invalid blocks and transactions stop at the first honest hop.
+    pub fn build(&self) -> Result<gossipsub::Config, String> {
+        self.validate()?;
+        let message_id_fn = |message: &gossipsub::Message| {
+            let mut s = DefaultHasher::new();
+            message.data.hash(&mut s);
+            gossipsub::MessageId::from(s.finish().to_string())
+        };
+        gossipsub::ConfigBuilder::default()
+            .heartbeat_interval(Duration::from_millis(self.heartbeat_interval_ms))
+            .validation_mode(gossipsub::ValidationMode::Strict)
+            .validate_messages()
+            .message_id_fn(message_id_fn)
+            .mesh_n(self.mesh_n)
+            .mesh_n_low(self.mesh_n_low)
+            .mesh_n_high(self.mesh_n_high)
+            .mesh_outbound_min(self.mesh_outbound_min)
+            .gossip_lazy(self.gossip_lazy)
+            .flood_publish(self.flood_publish)
+            .max_transmit_size(self.max_transmit_size)
+            .build()
+            .map_err(|e| format!("Invalid gossipsub config: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gossip_config_builds_and_validates() {
+        let config = GossipConfig::default();
+        let built = config.build().unwrap();
+        assert!(built.validate_messages());
+        assert_eq!(built.mesh_n(), 6);
+        assert!(built.flood_publish());
+
+        let mut sized = config.clone();
+        sized.topic_max_sizes.insert("transactions".into(), 1024);
+        assert_eq!(sized.max_message_size("transactions"), 1024);
+        assert_eq!(sized.max_message_size("blocks"), MAX_MESSAGE_SIZE);
+        assert!(sized.build().is_ok());
+
+        sized
+            .topic_max_sizes
+            .insert("blocks".into(), MAX_MESSAGE_SIZE + 1);
+        assert!(sized.validate().is_err());
+
+        let mut bad_mesh = config;
+        bad_mesh.mesh_n_low = 10;
+        assert!(bad_mesh.build().is_err());
+    }
+}
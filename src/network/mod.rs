@@ -1,6 +1,14 @@
+pub mod bandwidth;
+pub mod bootnodes;
+pub mod dns_seeds;
+pub mod gossip;
 mod node;
+pub mod outbound;
 pub mod peer_manager;
 mod protocol;
-pub use node::Node;
-pub use protocol::NetworkMessage;
+pub use node::{Node, NodeClient, DEFAULT_MAX_PEERS};
+pub use protocol::{MessagePriority, NetworkMessage, MAX_BLOCK_SIZE as MAX_MESSAGE_BLOCK_SIZE};
 pub mod proto_conversions;
+pub mod range_sync;
+pub mod snapshot_providers;
+pub mod time_sync;
@@ -1,17 +1,19 @@
-use crate::network::protocol::NetworkMessage;
+use crate::network::protocol::{MessagePriority, NetworkMessage};
 use libp2p::{
     futures::StreamExt,
     gossipsub, identify, identity,
     kad::{
-        store::MemoryStore, Behaviour as Kademlia, Config as KademliaConfig, Event as KademliaEvent,
+        store::MemoryStore, Behaviour as Kademlia, Config as KademliaConfig,
+        Event as KademliaEvent, GetProvidersOk, Mode as KademliaMode, QueryId, QueryResult,
     },
     mdns, noise, ping,
+    request_response::{self, ProtocolSupport},
     swarm::{NetworkBehaviour, SwarmEvent},
     tcp, yamux, Multiaddr, PeerId, Swarm,
 };
-use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::error::Error;
-use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::time::Duration;
 use tracing::{info, warn};
 #[derive(NetworkBehaviour)]
@@ -21,8 +23,23 @@ pub struct BudlumBehaviour {
     mdns: mdns::tokio::Behaviour,
     gossipsub: gossipsub::Behaviour,
     kad: Kademlia<MemoryStore>,
+    snapshot: request_response::Behaviour<SnapshotCodec>,
 }
+use crate::data_dir::KnownPeers;
+use crate::events::ChainEvent;
+use crate::network::bandwidth::{BandwidthExceeded, BandwidthLimiter};
+use crate::network::bootnodes::BootnodeDialer;
+use crate::network::dns_seeds::{resolve_dns_seeds, DnsSeeds};
+use crate::network::gossip::GossipConfig;
+use crate::network::outbound::{ChaosConfig, OutboundQueue};
 use crate::network::peer_manager::PeerManager;
+use crate::network::range_sync::RangeDownloader;
+use crate::network::snapshot_providers::{
+    candidate_heights, snapshot_provider_key, ProviderSearch, SnapshotCodec, SNAPSHOT_ADVERTISE_SECS,
+    SNAPSHOT_PROTOCOL,
+};
+use crate::network::time_sync::PeerClock;
+use crate::snapshot::{SnapshotDownload, MAX_CHUNKS_PER_REQUEST, SNAPSHOT_CHUNK_SIZE};
 use crate::Blockchain;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
@@ -30,7 +47,20 @@ pub enum NodeCommand {
     Subscribe(String),
     Broadcast(String, NetworkMessage),
     ListPeers,
+    RequestSnapshot(u64),
+    SetMaxPeers(usize),
+    DnsSeedsResolved(Vec<String>),
+    AddPeer(String),
+    RemovePeer(String),
 }
+pub const DEFAULT_MAX_PEERS: usize = 50;
+pub const OUTBOUND_FLUSH_MS: u64 = 50;
+pub const HALT_CHECK_SECS: u64 = 10;
+pub const SYNC_REPORT_SECS: u64 = 15;
+pub const RANGE_CHECK_SECS: u64 = 2;
+pub const BOOTNODE_CHECK_SECS: u64 = 1;
+pub const DNS_SEED_CHECK_SECS: u64 = 30;
+pub const REBROADCAST_CHECK_SECS: u64 = 30;
 #[derive(Clone)]
 pub struct NodeClient {
     sender: mpsc::Sender<NodeCommand>,
@@ -46,7 +76,40 @@ impl NodeClient {
     pub async fn list_peers(&self) {
         let _ = self.sender.send(NodeCommand::ListPeers).await;
     }
+    pub async fn request_snapshot(&self, height: u64) {
+        let _ = self.sender.send(NodeCommand::RequestSnapshot(height)).await;
+    }
+    pub async fn set_max_peers(&self, max_peers: usize) {
+        let _ = self.sender.send(NodeCommand::SetMaxPeers(max_peers)).await;
+    }
+    pub async fn add_peer(&self, addr: String) {
+        let _ = self.sender.send(NodeCommand::AddPeer(addr)).await;
+    }
+    pub async fn remove_peer(&self, addr: String) {
+        let _ = self.sender.send(NodeCommand::RemovePeer(addr)).await;
+    }
+    pub fn spawn_tx_relay(&self, mut events: tokio::sync::broadcast::Receiver<ChainEvent>) {
+        let client = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(ChainEvent::LocalTransaction(tx)) => {
+                        info!("Broadcasting local transaction {}", tx.hash);
+                        client
+                            .broadcast("transactions".to_string(), NetworkMessage::Transaction(tx))
+                            .await;
+                    }
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Transaction relay lagged, skipped {} chain events", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
 }
+#[cfg(feature = "pow-miner")]
 #[tokio::test]
 async fn test_node_creation() {
     use crate::consensus::PoWEngine;
@@ -55,6 +118,40 @@ async fn test_node_creation() {
     let node = Node::new(blockchain);
     assert!(node.is_ok());
 }
+#[cfg(feature = "pow-miner")]
+#[tokio::test]
+async fn test_local_transactions_are_broadcast() {
+    use crate::consensus::PoWEngine;
+    use crate::crypto::KeyPair;
+    let consensus = std::sync::Arc::new(PoWEngine::new(0));
+    let mut chain = Blockchain::new(consensus, None, 1337, None);
+    let kp = KeyPair::generate().unwrap();
+    chain.init_genesis_account(&kp.public_key_hex());
+    let (sender, mut commands) = mpsc::channel(8);
+    let client = NodeClient {
+        sender,
+        peer_id: PeerId::random(),
+    };
+    client.spawn_tx_relay(chain.subscribe_events());
+
+    let mut remote =
+        crate::Transaction::new_with_fee(kp.public_key_hex(), "bob".into(), 1, 1, 0, vec![]);
+    remote.sign(&kp);
+    chain.add_transaction(remote).unwrap();
+    let mut local =
+        crate::Transaction::new_with_fee(kp.public_key_hex(), "bob".into(), 1, 1, 1, vec![]);
+    local.sign(&kp);
+    chain.submit_local_transaction(local.clone()).unwrap();
+
+    match tokio::time::timeout(Duration::from_secs(1), commands.recv()).await {
+        Ok(Some(NodeCommand::Broadcast(topic, NetworkMessage::Transaction(tx)))) => {
+            assert_eq!(topic, "transactions");
+            assert_eq!(tx.hash, local.hash);
+        }
+        _ => panic!("local transaction was not broadcast"),
+    }
+    assert!(commands.try_recv().is_err());
+}
 pub struct Node {
     swarm: Swarm<BudlumBehaviour>,
     command_rx: mpsc::Receiver<NodeCommand>,
@@ -63,24 +160,41 @@ pub struct Node {
     pub blockchain: Arc<Mutex<Blockchain>>,
     pub peer_manager: Arc<Mutex<PeerManager>>,
     pub bootstrap_peers: Vec<String>,
+    snapshot_download: Option<SnapshotDownload>,
+    peer_store: Option<PathBuf>,
+    known_peers: KnownPeers,
+    max_peers: usize,
+    outbound: OutboundQueue,
+    peer_clock: PeerClock,
+    range_sync: Option<RangeDownloader>,
+    gossip: GossipConfig,
+    bandwidth: BandwidthLimiter,
+    bootnodes: BootnodeDialer,
+    dns_seeds: DnsSeeds,
+    advertised_snapshot: Option<u64>,
+    snapshot_queries: HashMap<QueryId, u64>,
+    snapshot_search: ProviderSearch,
+    snapshot_source: Option<PeerId>,
+}
+
+fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 impl Node {
     pub fn new(blockchain: Arc<Mutex<Blockchain>>) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_gossip(blockchain, GossipConfig::default())
+    }
+    pub fn new_with_gossip(
+        blockchain: Arc<Mutex<Blockchain>>,
+        gossip: GossipConfig,
+    ) -> Result<Self, Box<dyn Error>> {
         let local_key = identity::Keypair::generate_ed25519();
         let peer_id = PeerId::from(local_key.public());
         info!("Node ID: {}", peer_id);
-        let message_id_fn = |message: &gossipsub::Message| {
-            let mut s = DefaultHasher::new();
-            message.data.hash(&mut s);
-            gossipsub::MessageId::from(s.finish().to_string())
-        };
-        let gossipsub_config = gossipsub::ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(10))
-            .validation_mode(gossipsub::ValidationMode::Strict)
-            .message_id_fn(message_id_fn)
-            .max_transmit_size(crate::network::protocol::MAX_MESSAGE_SIZE)
-            .build()
-            .map_err(|msg| std::io::Error::new(std::io::ErrorKind::Other, msg))?;
+        let gossipsub_config = gossip.build()?;
         let gossipsub = gossipsub::Behaviour::new(
             gossipsub::MessageAuthenticity::Signed(local_key.clone()),
             gossipsub_config,
@@ -99,12 +213,20 @@ impl Node {
                 )?;
                 let kad_store = MemoryStore::new(key.public().to_peer_id());
                 let kad_config = KademliaConfig::default();
-                let kademlia =
+                let mut kademlia =
                     Kademlia::with_config(key.public().to_peer_id(), kad_store, kad_config);
+                // Nodes answer DHT queries so provider lookups work without
+                // a confirmed external address.
+                kademlia.set_mode(Some(KademliaMode::Server));
                 let identify = identify::Behaviour::new(identify::Config::new(
                     "/budlum/1.0.0".to_string(),
                     key.public(),
                 ));
+                let snapshot = request_response::Behaviour::new(
+                    [(SNAPSHOT_PROTOCOL, ProtocolSupport::Full)],
+                    request_response::Config::default()
+                        .with_request_timeout(Duration::from_secs(30)),
+                );
                 Ok(BudlumBehaviour {
                     ping: ping::Behaviour::new(
                         ping::Config::new().with_interval(Duration::from_secs(15)),
@@ -113,6 +235,7 @@ impl Node {
                     mdns,
                     gossipsub,
                     kad: kademlia,
+                    snapshot,
                 })
             })?
             .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(60)))
@@ -127,8 +250,72 @@ impl Node {
             blockchain,
             peer_manager,
             bootstrap_peers: Vec::new(),
+            snapshot_download: None,
+            peer_store: None,
+            known_peers: KnownPeers::default(),
+            max_peers: DEFAULT_MAX_PEERS,
+            outbound: OutboundQueue::new(),
+            peer_clock: PeerClock::new(),
+            range_sync: None,
+            gossip,
+            bandwidth: BandwidthLimiter::unlimited(),
+            bootnodes: BootnodeDialer::default(),
+            dns_seeds: DnsSeeds::default(),
+            advertised_snapshot: None,
+            snapshot_queries: HashMap::new(),
+            snapshot_search: ProviderSearch::default(),
+            snapshot_source: None,
         })
     }
+    pub fn with_chaos(mut self, chaos: ChaosConfig) -> Self {
+        warn!("Chaos mode enabled for outbound messages: {:?}", chaos);
+        self.outbound = OutboundQueue::new().with_chaos(chaos);
+        self
+    }
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+    pub fn with_bandwidth_limits(
+        mut self,
+        per_peer_bytes_per_sec: u64,
+        global_bytes_per_sec: u64,
+    ) -> Self {
+        self.bandwidth = BandwidthLimiter::new(per_peer_bytes_per_sec, global_bytes_per_sec);
+        self
+    }
+    // Bootnodes are dialed directly until connected and also seed Kademlia.
+    pub fn with_bootnodes(mut self, bootnodes: &[String]) -> Result<Self, String> {
+        self.bootnodes = BootnodeDialer::new(bootnodes)?;
+        self.bootstrap_peers.extend(bootnodes.iter().cloned());
+        Ok(self)
+    }
+    pub fn with_dns_seeds(mut self, domains: Vec<String>) -> Self {
+        self.dns_seeds = DnsSeeds::new(domains);
+        self
+    }
+    fn dial_dns_seeds(&mut self) {
+        for addr in self.dns_seeds.addrs().to_vec() {
+            if addr.contains("/p2p/") {
+                let _ = self.bootstrap(&addr);
+            }
+            if let Err(e) = self.dial(&addr) {
+                warn!("Failed to dial DNS seed peer {}: {}", addr, e);
+            }
+        }
+    }
+    pub fn with_peer_store(mut self, path: PathBuf) -> Self {
+        self.known_peers = KnownPeers::load(&path);
+        self.peer_store = Some(path);
+        self
+    }
+    fn save_peer_store(&self) {
+        if let Some(ref path) = self.peer_store {
+            if let Err(e) = self.known_peers.save(path) {
+                warn!("{}", e);
+            }
+        }
+    }
     pub fn new_with_bootstrap(
         blockchain: Arc<Mutex<Blockchain>>,
         bootstrap_peers: Vec<String>,
@@ -172,6 +359,518 @@ impl Node {
         self.swarm.behaviour_mut().kad.bootstrap()?;
         Ok(())
     }
+    fn report_validation(
+        &mut self,
+        id: &gossipsub::MessageId,
+        source: &PeerId,
+        acceptance: gossipsub::MessageAcceptance,
+    ) {
+        if let Err(e) = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(id, source, acceptance)
+        {
+            tracing::debug!("Failed to report validation of {}: {:?}", id, e);
+        }
+    }
+    // Asks the chosen provider directly, or every peer over gossip while
+    // no provider is known.
+    fn request_snapshot_chunks(&mut self) {
+        if let Some(ref download) = self.snapshot_download {
+            let from_index = download.next_missing_index();
+            let msg = NetworkMessage::GetStateSnapshot {
+                height: download.height,
+                from_index,
+                target: String::new(),
+            };
+            info!(
+                "Requesting snapshot chunks from index {} (height: {}, source: {:?})",
+                from_index, download.height, self.snapshot_source
+            );
+            match self.snapshot_source {
+                Some(provider) => {
+                    self.swarm
+                        .behaviour_mut()
+                        .snapshot
+                        .send_request(&provider, msg);
+                }
+                None => {
+                    let topic = gossipsub::IdentTopic::new("blocks");
+                    self.outbound.push(topic, &msg);
+                }
+            }
+        }
+    }
+    // The answer to a snapshot request: the state root at the snapshot
+    // height on a first request, then one batch of chunks from `from_index`.
+    // Peers over the blob rate limit get nothing.
+    fn snapshot_response(
+        &self,
+        peer_id: &PeerId,
+        height: u64,
+        from_index: u32,
+    ) -> Vec<NetworkMessage> {
+        if !self
+            .peer_manager
+            .lock()
+            .unwrap_or_else(|e| {
+                tracing::error!("PeerManager lock poisoned: {}", e);
+                std::process::exit(1);
+            })
+            .check_blob_rate_limit(peer_id)
+        {
+            warn!(
+                "Peer {} exceeded blob rate limit. Ignoring GetStateSnapshot.",
+                peer_id
+            );
+            return Vec::new();
+        }
+        info!(
+            "GetStateSnapshot request from {} (height: {}, from chunk: {})",
+            peer_id, height, from_index
+        );
+        let chain = self.blockchain.lock().unwrap_or_else(|e| {
+            tracing::error!("Blockchain lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        let snapshot = chain
+            .pruning_manager
+            .as_ref()
+            .and_then(|pm| {
+                match pm.load_committed_snapshot(|h| chain.committed_snapshot_hash(h)) {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        warn!("Not serving snapshot: {}", e);
+                        None
+                    }
+                }
+            })
+            .filter(|s| height == 0 || s.height == height);
+        let response_height = snapshot.as_ref().map(|s| s.height).unwrap_or(height);
+        let state_root = chain
+            .storage
+            .as_ref()
+            .and_then(|store| store.get_state_root(response_height).ok().flatten())
+            .unwrap_or_default();
+        drop(chain);
+
+        let mut messages = Vec::new();
+        if from_index == 0 || snapshot.is_none() {
+            let ok = snapshot.is_some() || !state_root.is_empty();
+            messages.push(NetworkMessage::StateSnapshotResponse {
+                height: response_height,
+                state_root,
+                ok,
+            });
+        }
+        if let Some(snapshot) = snapshot {
+            let chunks: Vec<NetworkMessage> = snapshot
+                .to_chunks(SNAPSHOT_CHUNK_SIZE)
+                .into_iter()
+                .skip(from_index as usize)
+                .take(MAX_CHUNKS_PER_REQUEST as usize)
+                .map(|chunk| NetworkMessage::SnapshotChunk {
+                    height: snapshot.height,
+                    index: chunk.index,
+                    total: chunk.total,
+                    data: chunk.data,
+                    chunk_hash: chunk.hash,
+                })
+                .collect();
+            info!(
+                "Sending {} snapshot chunks (height {}) to {}",
+                chunks.len(),
+                snapshot.height,
+                peer_id
+            );
+            messages.extend(chunks);
+        }
+        messages
+    }
+    // Adds one chunk to the running download and finishes it once all
+    // chunks are in. Returns whether the chunk was accepted.
+    fn on_snapshot_chunk(
+        &mut self,
+        peer_id: PeerId,
+        height: u64,
+        index: u32,
+        total: u32,
+        data: Vec<u8>,
+        chunk_hash: String,
+    ) -> bool {
+        info!(
+            "SnapshotChunk from {}: height={}, {}/{}, {} bytes",
+            peer_id,
+            height,
+            index,
+            total,
+            data.len()
+        );
+        let Some(ref mut download) = self.snapshot_download else {
+            return false;
+        };
+        if let Err(e) = download.add_chunk(height, index, total, &chunk_hash, data) {
+            warn!("Rejected snapshot chunk {} from {}: {}", index, peer_id, e);
+            self.report_snapshot_peer(&peer_id, false);
+            return false;
+        }
+        if download.is_complete() {
+            let result = download.assemble();
+            self.snapshot_download = None;
+            self.snapshot_source = None;
+            match result {
+                Ok(snapshot) => {
+                    info!(
+                        "Snapshot download complete: height={}, {} accounts",
+                        snapshot.height,
+                        snapshot.balances.len()
+                    );
+                    let chain = self.blockchain.lock().unwrap_or_else(|e| {
+                        tracing::error!("Blockchain lock poisoned: {}", e);
+                        std::process::exit(1);
+                    });
+                    // Only a state root from a verified finality certificate
+                    // vouches for a snapshot.
+                    if chain.finalized_state_root(snapshot.height).is_none() {
+                        warn!(
+                            "No finality certificate for snapshot height {}; snapshot from {} rejected",
+                            snapshot.height, peer_id
+                        );
+                        return true;
+                    }
+                    if let Err(e) = chain
+                        .verify_finalized_snapshot(&snapshot)
+                        .and_then(|_| chain.verify_snapshot_commitment(&snapshot))
+                    {
+                        warn!("Downloaded snapshot from {} rejected: {}", peer_id, e);
+                        drop(chain);
+                        self.report_snapshot_peer(&peer_id, false);
+                        return true;
+                    }
+                    if let Some(ref pm) = chain.pruning_manager {
+                        if let Err(e) = pm.save_snapshot(&snapshot) {
+                            warn!("Failed to store downloaded snapshot: {}", e);
+                        }
+                    }
+                }
+                Err(e) => warn!("Downloaded snapshot rejected: {}", e),
+            }
+        } else if self.snapshot_source.is_none()
+            && (index + 1).is_multiple_of(MAX_CHUNKS_PER_REQUEST)
+            && download.next_missing_index() > index
+        {
+            self.request_snapshot_chunks();
+        }
+        self.report_snapshot_peer(&peer_id, true);
+        true
+    }
+    fn report_snapshot_peer(&self, peer_id: &PeerId, good: bool) {
+        let mut pm = self.peer_manager.lock().unwrap_or_else(|e| {
+            tracing::error!("PeerManager lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        if good {
+            pm.report_good_behavior(peer_id);
+        } else {
+            pm.report_bad_behavior(peer_id);
+        }
+    }
+    // Chunks from the chosen provider's direct response. The next batch is
+    // requested from the same provider; one that sends nothing usable is
+    // dropped for the next best.
+    fn on_snapshot_response(&mut self, peer_id: PeerId, messages: Vec<NetworkMessage>) {
+        if self.snapshot_source != Some(peer_id) {
+            return;
+        }
+        let mut accepted = 0;
+        for msg in messages {
+            match msg {
+                NetworkMessage::SnapshotChunk {
+                    height,
+                    index,
+                    total,
+                    data,
+                    chunk_hash,
+                } => {
+                    accepted +=
+                        self.on_snapshot_chunk(peer_id, height, index, total, data, chunk_hash)
+                            as usize;
+                }
+                NetworkMessage::StateSnapshotResponse { height, ok, .. } if !ok => {
+                    warn!("Snapshot {} is unavailable at {}", height, peer_id);
+                }
+                _ => {}
+            }
+        }
+        if accepted == 0 {
+            self.drop_snapshot_provider(&peer_id);
+        } else if self.snapshot_download.is_some() && self.snapshot_source == Some(peer_id) {
+            self.request_snapshot_chunks();
+        }
+    }
+    fn drop_snapshot_provider(&mut self, peer_id: &PeerId) {
+        if self.snapshot_source != Some(*peer_id) {
+            return;
+        }
+        self.snapshot_search.reject(peer_id);
+        self.snapshot_source = None;
+        self.select_snapshot_provider();
+    }
+    // Registers this node as a provider of its latest committed snapshot,
+    // replacing the record for the previous one.
+    fn advertise_snapshot(&mut self) {
+        let (chain_id, latest) = {
+            let chain = self.blockchain.lock().unwrap_or_else(|e| {
+                tracing::error!("Blockchain lock poisoned: {}", e);
+                std::process::exit(1);
+            });
+            let latest = chain
+                .pruning_manager
+                .as_ref()
+                .and_then(|pm| pm.snapshot_heights().ok())
+                .and_then(|heights| {
+                    heights
+                        .into_iter()
+                        .filter(|h| chain.committed_snapshot_hash(*h).is_some())
+                        .max()
+                });
+            (chain.chain_id, latest)
+        };
+        if latest == self.advertised_snapshot {
+            return;
+        }
+        let kad = &mut self.swarm.behaviour_mut().kad;
+        if let Some(old) = self.advertised_snapshot.take() {
+            kad.stop_providing(&snapshot_provider_key(chain_id, old));
+        }
+        if let Some(height) = latest {
+            match kad.start_providing(snapshot_provider_key(chain_id, height)) {
+                Ok(_) => {
+                    info!("Advertising snapshot at height {} via Kademlia", height);
+                    self.advertised_snapshot = Some(height);
+                }
+                Err(e) => warn!("Failed to advertise snapshot {}: {}", height, e),
+            }
+        }
+    }
+    // Looks up providers for the requested snapshot, or for the latest
+    // snapshot boundaries below the best peer height when `height` is 0.
+    // Only boundaries covered by a finality certificate are candidates,
+    // since no other snapshot can be verified once downloaded. Without any
+    // candidate the request is broadcast as before.
+    fn find_snapshot_providers(&mut self, height: u64) {
+        let (chain_id, heights) = {
+            let chain = self.blockchain.lock().unwrap_or_else(|e| {
+                tracing::error!("Blockchain lock poisoned: {}", e);
+                std::process::exit(1);
+            });
+            let heights = if height > 0 {
+                vec![height]
+            } else {
+                let interval = chain
+                    .pruning_manager
+                    .as_ref()
+                    .map(|pm| pm.snapshot_interval)
+                    .unwrap_or(crate::cli::PruningConfig::default().snapshot_interval);
+                candidate_heights(chain.sync.best_peer_height, interval)
+                    .into_iter()
+                    .filter(|h| chain.finalized_state_root(*h).is_some())
+                    .collect()
+            };
+            (chain.chain_id, heights)
+        };
+        self.snapshot_source = None;
+        self.snapshot_queries.clear();
+        self.snapshot_search = ProviderSearch::new(&heights);
+        if heights.is_empty() {
+            self.request_snapshot_chunks();
+            return;
+        }
+        for height in heights {
+            let query = self
+                .swarm
+                .behaviour_mut()
+                .kad
+                .get_providers(snapshot_provider_key(chain_id, height));
+            self.snapshot_queries.insert(query, height);
+        }
+    }
+    fn on_snapshot_providers(&mut self, query: QueryId, providers: Vec<PeerId>, last: bool) {
+        let Some(height) = self.snapshot_queries.get(&query).copied() else {
+            return;
+        };
+        if last {
+            self.snapshot_queries.remove(&query);
+        }
+        let providers = providers
+            .into_iter()
+            .filter(|p| *p != self.peer_id)
+            .collect();
+        self.snapshot_search.on_providers(height, providers, last);
+        self.select_snapshot_provider();
+    }
+    // Points the download at the best provider found so far, or asks every
+    // peer once all lookups came back without a usable one.
+    fn select_snapshot_provider(&mut self) {
+        if self.snapshot_download.is_none() || self.snapshot_source.is_some() {
+            return;
+        }
+        match self.snapshot_search.best() {
+            Some((height, provider)) => {
+                info!("Snapshot {} is provided by {}", height, provider);
+                self.snapshot_source = Some(provider);
+                if self
+                    .snapshot_download
+                    .as_ref()
+                    .is_some_and(|d| d.height != height)
+                {
+                    self.snapshot_download = Some(SnapshotDownload::new(height));
+                }
+                self.request_snapshot_chunks();
+            }
+            None if self.snapshot_search.is_exhausted() => {
+                warn!("No snapshot providers found, asking all peers");
+                self.request_snapshot_chunks();
+            }
+            None => {}
+        }
+    }
+    fn schedule_ranges(&mut self) {
+        let Some(download) = self.range_sync.as_mut() else {
+            return;
+        };
+        let mut pm = self.peer_manager.lock().unwrap_or_else(|e| {
+            tracing::error!("PeerManager lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        let candidates: Vec<PeerId> = self
+            .swarm
+            .connected_peers()
+            .filter(|peer| pm.is_handshaked(peer) && !pm.is_banned(peer))
+            .copied()
+            .collect();
+        let peers: Vec<String> = pm
+            .rank_sync_peers(candidates)
+            .iter()
+            .map(|peer| peer.to_string())
+            .collect();
+        drop(pm);
+        for request in download.assign(&peers, unix_time_ms() as u128) {
+            info!(
+                "Requesting blocks {}..{} from {}",
+                request.from, request.to, request.peer
+            );
+            let msg = NetworkMessage::GetBlocksRange {
+                from: request.from,
+                to: request.to,
+                target: request.peer,
+            };
+            self.outbound
+                .push(gossipsub::IdentTopic::new("blocks"), &msg);
+        }
+    }
+    fn import_ready_ranges(&mut self) {
+        let Some(download) = self.range_sync.as_mut() else {
+            return;
+        };
+        let mut chain = self.blockchain.lock().unwrap_or_else(|e| {
+            tracing::error!("Blockchain lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        let mut bad_peers = Vec::new();
+        while let Some(ready) = download.next_ready() {
+            let (peer, blocks) = match ready {
+                Ok(range) => range,
+                Err(peer) => {
+                    warn!("Range from {} does not link to the imported chain", peer);
+                    bad_peers.push(peer);
+                    continue;
+                }
+            };
+            if let Some(e) = blocks
+                .into_iter()
+                .find_map(|block| chain.validate_and_add_block(block).err())
+            {
+                warn!("Rejected synced block from {}: {}", peer, e);
+                bad_peers.push(peer);
+                download.restart(chain.last_block());
+                break;
+            }
+        }
+        let complete = download.is_complete();
+        drop(chain);
+        let mut pm = self.peer_manager.lock().unwrap_or_else(|e| {
+            tracing::error!("PeerManager lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        for peer in bad_peers.iter().filter_map(|p| p.parse::<PeerId>().ok()) {
+            pm.report_invalid_block(&peer);
+        }
+        drop(pm);
+        if complete {
+            info!("Range sync complete");
+            self.range_sync = None;
+        } else {
+            self.schedule_ranges();
+        }
+    }
+    fn record_peer_time(&mut self, peer_id: &PeerId, timestamp_ms: u64) {
+        self.peer_clock
+            .record(&peer_id.to_string(), timestamp_ms, unix_time_ms());
+        if self.peer_clock.is_drifting() {
+            warn!(
+                "Local clock drifts {} ms from the median of {} peers, check NTP",
+                self.peer_clock.median_offset().unwrap_or(0),
+                self.peer_clock.sample_count()
+            );
+        }
+        let adjustment = self.peer_clock.adjustment();
+        let mut chain = self.blockchain.lock().unwrap_or_else(|e| {
+            tracing::error!("Blockchain lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        if chain.clock_offset() != adjustment {
+            info!("Adjusting validation clock by {} ms", adjustment);
+            chain.set_clock_offset(adjustment);
+        }
+    }
+    fn record_peer_version(&mut self, peer_id: &PeerId, version: &str, commit: &str) {
+        let mut chain = self.blockchain.lock().unwrap_or_else(|e| {
+            tracing::error!("Blockchain lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        chain
+            .peer_versions
+            .record(&peer_id.to_string(), version, commit);
+        let counts = chain.peer_versions.counts();
+        chain.events.emit(ChainEvent::PeerVersions { counts });
+    }
+    fn forget_peer_version(&mut self, peer_id: &PeerId) {
+        let mut chain = self.blockchain.lock().unwrap_or_else(|e| {
+            tracing::error!("Blockchain lock poisoned: {}", e);
+            std::process::exit(1);
+        });
+        if chain.peer_versions.remove(&peer_id.to_string()) {
+            let counts = chain.peer_versions.counts();
+            chain.events.emit(ChainEvent::PeerVersions { counts });
+        }
+    }
+    fn flush_outbound(&mut self) {
+        for out in self.outbound.next_batch() {
+            if let Err(e) = self
+                .swarm
+                .behaviour_mut()
+                .gossipsub
+                .publish(out.topic.clone(), out.data)
+            {
+                warn!(
+                    "Failed to publish {:?} message to {}: {}",
+                    out.priority, out.topic, e
+                );
+            }
+        }
+    }
     pub async fn run(&mut self) {
         info!("Node running...");
         for addr in self.bootstrap_peers.clone() {
@@ -179,11 +878,55 @@ impl Node {
                 warn!("Bootstrap dial failed for {}: {}", addr, e);
             }
         }
+        for addr in self.known_peers.to_vec() {
+            if let Err(e) = self.dial(&addr) {
+                warn!("Failed to dial stored peer {}: {}", addr, e);
+            }
+        }
         let mut gc_interval = tokio::time::interval(Duration::from_secs(60));
         let mut discovery_interval = tokio::time::interval(Duration::from_secs(300));
+        let mut outbound_interval = tokio::time::interval(Duration::from_millis(OUTBOUND_FLUSH_MS));
+        let mut halt_interval = tokio::time::interval(Duration::from_secs(HALT_CHECK_SECS));
+        let mut sync_interval = tokio::time::interval(Duration::from_secs(SYNC_REPORT_SECS));
+        let mut range_interval = tokio::time::interval(Duration::from_secs(RANGE_CHECK_SECS));
+        let mut bootnode_interval = tokio::time::interval(Duration::from_secs(BOOTNODE_CHECK_SECS));
+        let mut dns_seed_interval = tokio::time::interval(Duration::from_secs(DNS_SEED_CHECK_SECS));
+        let mut snapshot_advertise_interval =
+            tokio::time::interval(Duration::from_secs(SNAPSHOT_ADVERTISE_SECS));
+        let mut rebroadcast_interval =
+            tokio::time::interval(Duration::from_secs(REBROADCAST_CHECK_SECS));
+        let slot_clock = {
+            let chain = self.blockchain.lock().unwrap_or_else(|e| {
+                tracing::error!("Blockchain lock poisoned: {}", e);
+                std::process::exit(1);
+            });
+            if chain.is_observer() {
+                None
+            } else {
+                chain.consensus.slot_clock()
+            }
+        };
+        if let Some(clock) = slot_clock {
+            info!("Producing blocks at {} ms slot boundaries", clock.slot_ms);
+        }
 
         loop {
+            self.flush_outbound();
+            let slot_wait = slot_clock
+                .map(|clock| clock.until_next_slot(unix_time_ms() as u128))
+                .unwrap_or(u64::MAX);
             tokio::select! {
+                _ = outbound_interval.tick(), if !self.outbound.is_empty() => {}
+                _ = tokio::time::sleep(Duration::from_millis(slot_wait)), if slot_clock.is_some() => {
+                    let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                    let produced = chain.produce_slot_block();
+                    drop(chain);
+                    if let Some(block) = produced {
+                        info!("Produced block {} for slot {}", block.index, block.slot);
+                        let topic = gossipsub::IdentTopic::new("blocks");
+                        self.outbound.push(topic, &NetworkMessage::Block(block));
+                    }
+                }
                 _ = gc_interval.tick() => {
                     let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
                     let removed = chain.mempool.cleanup_expired();
@@ -194,6 +937,90 @@ impl Node {
 
                     let mut pm = self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); });
                     pm.cleanup_expired_bans();
+                    drop(pm);
+
+                    self.request_snapshot_chunks();
+                    self.save_peer_store();
+                    if let Some(stats) = self.outbound.chaos_stats() {
+                        info!("Chaos: dropped={}, duplicated={}, delayed={}", stats.dropped, stats.duplicated, stats.delayed);
+                    }
+                }
+                _ = rebroadcast_interval.tick() => {
+                    let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                    let height = chain.last_block().index;
+                    let stuck = chain.mempool.stuck_transactions(height);
+                    drop(chain);
+                    if !stuck.is_empty() {
+                        info!("Rebroadcasting {} stuck transactions", stuck.len());
+                    }
+                    for tx in stuck {
+                        self.outbound.push(gossipsub::IdentTopic::new("transactions"), &NetworkMessage::Transaction(tx));
+                    }
+                }
+                _ = range_interval.tick(), if self.range_sync.is_some() => {
+                    let expired = self.range_sync.as_mut().map(|d| d.expire(unix_time_ms() as u128)).unwrap_or_default();
+                    let mut pm = self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); });
+                    for peer in expired.iter().filter_map(|p| p.parse::<PeerId>().ok()) {
+                        warn!("Block range request to {} timed out, re-requesting", peer);
+                        pm.report_bad_behavior(&peer);
+                    }
+                    drop(pm);
+                    self.schedule_ranges();
+                }
+                _ = sync_interval.tick() => {
+                    let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                    let now = unix_time_ms() as u128;
+                    let height = chain.last_block().index;
+                    chain.sync.record_progress(height, now);
+                    let report = chain.sync.report(now);
+                    if report.remaining > 0 {
+                        info!("Sync {:?}: {}/{} ({} remaining, {:.1} blocks/s, ETA {})",
+                            report.status, report.current_height, report.best_peer_height, report.remaining,
+                            report.blocks_per_sec, report.eta_secs.map(|s| format!("{}s", s)).unwrap_or_else(|| "unknown".into()));
+                        chain.events.emit(ChainEvent::SyncProgress { height, target: report.best_peer_height });
+                    }
+                }
+                _ = halt_interval.tick() => {
+                    let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                    let now = chain.network_time_ms();
+                    if chain.check_halt(now) {
+                        let locator = chain.block_locator();
+                        drop(chain);
+                        warn!("Chain halted, resyncing from peers");
+                        let topic = gossipsub::IdentTopic::new("blocks");
+                        let msg = NetworkMessage::GetHeaders { locator, limit: 2000 };
+                        self.outbound.push(topic, &msg);
+                        if self.swarm.connected_peers().count() == 0 {
+                            for addr in self.bootstrap_peers.clone() {
+                                if let Err(e) = self.bootstrap(&addr) {
+                                    warn!("Recovery bootstrap failed for {}: {}", addr, e);
+                                }
+                            }
+                        }
+                    }
+                }
+                _ = bootnode_interval.tick(), if !self.bootnodes.is_empty() => {
+                    for addr in self.bootnodes.due(unix_time_ms()) {
+                        if let Err(e) = self.dial(&addr) {
+                            warn!("Failed to dial bootnode {}: {}", addr, e);
+                        }
+                    }
+                }
+                _ = dns_seed_interval.tick(), if !self.dns_seeds.is_empty() => {
+                    if self.dns_seeds.needs_refresh(unix_time_ms()) {
+                        let domains = self.dns_seeds.start_refresh();
+                        let sender = self.command_tx.clone();
+                        tokio::spawn(async move {
+                            let addrs = resolve_dns_seeds(domains).await;
+                            let _ = sender.send(NodeCommand::DnsSeedsResolved(addrs)).await;
+                        });
+                    } else if self.swarm.connected_peers().count() == 0 {
+                        info!("No peers connected, falling back to DNS seeds");
+                        self.dial_dns_seeds();
+                    }
+                }
+                _ = snapshot_advertise_interval.tick() => {
+                    self.advertise_snapshot();
                 }
                 _ = discovery_interval.tick() => {
                     info!("Running periodic peer discovery...");
@@ -216,12 +1043,8 @@ impl Node {
                             }
                             NodeCommand::Broadcast(topic, msg) => {
                                 let topic = gossipsub::IdentTopic::new(topic);
-                                let data = msg.to_bytes();
-                                if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic.clone(), data) {
-                                    warn!("Failed to publish: {}", e);
-                                } else {
-                                    info!("Broadcasted to {}: {:?}", topic, msg);
-                                }
+                                info!("Broadcasting to {}: {:?}", topic, msg);
+                                self.outbound.push(topic, &msg);
                             }
                             NodeCommand::ListPeers => {
                                 let peers: Vec<_> = self.swarm.behaviour().gossipsub.all_peers().collect();
@@ -230,6 +1053,44 @@ impl Node {
                                     info!(" - {}", peer);
                                 }
                             }
+                            NodeCommand::RequestSnapshot(height) => {
+                                self.snapshot_download = Some(SnapshotDownload::new(height));
+                                self.find_snapshot_providers(height);
+                            }
+                            NodeCommand::DnsSeedsResolved(addrs) => {
+                                info!("DNS seeds resolved to {} peer addresses", addrs.len());
+                                self.dns_seeds.update(addrs, unix_time_ms());
+                                if self.swarm.connected_peers().count() == 0 {
+                                    self.dial_dns_seeds();
+                                }
+                            }
+                            NodeCommand::SetMaxPeers(max_peers) => {
+                                info!("Peer limit changed from {} to {}", self.max_peers, max_peers);
+                                self.max_peers = max_peers;
+                            }
+                            NodeCommand::AddPeer(addr) => {
+                                match self.dial(&addr) {
+                                    Ok(()) => {
+                                        self.known_peers.insert(addr);
+                                        self.save_peer_store();
+                                    }
+                                    Err(e) => warn!("Failed to dial added peer {}: {}", addr, e),
+                                }
+                            }
+                            NodeCommand::RemovePeer(addr) => {
+                                self.known_peers.remove(&addr);
+                                self.save_peer_store();
+                                let peer = addr.parse::<Multiaddr>().ok().and_then(|ma| {
+                                    ma.iter().find_map(|p| match p {
+                                        libp2p::multiaddr::Protocol::P2p(id) => Some(id),
+                                        _ => None,
+                                    })
+                                });
+                                if let Some(peer) = peer {
+                                    let _ = self.swarm.disconnect_peer_id(peer);
+                                }
+                                info!("Removed peer {}", addr);
+                            }
                         }
                     }
                 }
@@ -238,8 +1099,17 @@ impl Node {
                         SwarmEvent::NewListenAddr { address, .. } => {
                             info!("Listening on {}", address);
                         }
-                        SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
                             info!("Connected to {}", peer_id);
+                            self.bootnodes.on_connected(&peer_id.to_string());
+                            if self.swarm.connected_peers().count() > self.max_peers {
+                                warn!("Peer limit ({}) reached, disconnecting {}", self.max_peers, peer_id);
+                                let _ = self.swarm.disconnect_peer_id(peer_id);
+                                continue;
+                            }
+                            if endpoint.is_dialer() {
+                                self.known_peers.insert(endpoint.get_remote_address().to_string());
+                            }
                             let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
 
                             let handshake = NetworkMessage::Handshake {
@@ -249,17 +1119,19 @@ impl Node {
                                 best_height: chain.chain.len() as u64,
                                 validator_set_hash: chain.get_validator_set_hash(),
                                 supported_schemes: vec!["ED25519".to_string(), "BLS".to_string(), "DILITHIUM".to_string()],
+                                timestamp_ms: unix_time_ms(),
+                                fork_hash: chain.fork_id().fork_hash,
+                                node_version: crate::encoding::NODE_VERSION.to_string(),
+                                node_commit: crate::encoding::NODE_COMMIT.to_string(),
                             };
 
                             info!("DEBUG: Connected to {}, Chain length: {}, sending Handshake", peer_id, chain.chain.len());
 
                             let topic = gossipsub::IdentTopic::new("blocks");
-                            if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, handshake.to_bytes()) {
-                                warn!("Failed to send Handshake: {}", e);
-                            }
+                            self.outbound.push(topic, &handshake);
 
                             if chain.chain.len() == 1 {
-                                let locator = vec![chain.chain.last().unwrap().hash.clone()];
+                                let locator = chain.block_locator();
                                 drop(chain);
                                 info!("New connection, requesting headers...");
                                 let topic = gossipsub::IdentTopic::new("blocks");
@@ -267,15 +1139,24 @@ impl Node {
                                     locator,
                                     limit: 2000,
                                 };
-                                if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, msg.to_bytes()) {
-                                    warn!("Failed to request headers: {}", e);
-                                }
+                                self.outbound.push(topic, &msg);
+                            } else {
+                                drop(chain);
                             }
+
+                            self.request_snapshot_chunks();
                         }
                         SwarmEvent::ConnectionClosed { peer_id, .. } => {
                             warn!("Disconnected from {}", peer_id);
+                            self.peer_clock.remove(&peer_id.to_string());
+                            self.forget_peer_version(&peer_id);
+                            self.bandwidth.remove(&peer_id.to_string());
+                            self.bootnodes.on_disconnected(&peer_id.to_string(), unix_time_ms());
                         }
                         SwarmEvent::Behaviour(BudlumBehaviourEvent::Ping(event)) => {
+                            if let Ok(rtt) = event.result {
+                                self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).record_ping(&event.peer, rtt);
+                            }
                         }
                         SwarmEvent::Behaviour(BudlumBehaviourEvent::Mdns(event)) => {
                             match event {
@@ -301,17 +1182,41 @@ impl Node {
                             message_id: id,
                             message,
                         })) => {
+                            let origin = message.source.unwrap_or(peer_id);
 
                             if self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).is_banned(&peer_id) {
                                 warn!("Ignoring message from banned peer {}", peer_id);
+                                self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
                                 continue;
                             }
 
                             if !self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).check_rate_limit(&peer_id) {
                                 warn!("Rate limit exceeded for peer {}", peer_id);
+                                self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
+                                continue;
+                            }
+
+                            let max_size = self.gossip.max_message_size(message.topic.as_str());
+                            if message.data.len() > max_size {
+                                warn!("Message from {} on {} exceeds topic limit ({} > {} bytes)", peer_id, message.topic, message.data.len(), max_size);
+                                self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Reject);
+                                self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_oversized_message(&peer_id);
                                 continue;
                             }
 
+                            // Over the global budget only consensus traffic is still
+                            // processed; a peer over its own budget is dropped outright.
+                            let saturated = match self.bandwidth.charge(&peer_id.to_string(), message.data.len(), unix_time_ms()) {
+                                Ok(()) => false,
+                                Err(BandwidthExceeded::Global) => true,
+                                Err(BandwidthExceeded::Peer) => {
+                                    warn!("Peer {} exceeds its inbound bandwidth cap, dropping message", peer_id);
+                                    self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
+                                    self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_oversized_message(&peer_id);
+                                    continue;
+                                }
+                            };
+
                             info!("Received from {}: id={}", peer_id, id);
                             match NetworkMessage::from_bytes_validated(&message.data) {
                                 Ok(msg) => {
@@ -322,51 +1227,88 @@ impl Node {
 
                                     if !is_handshake_msg && !self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).is_handshaked(&peer_id) {
                                         warn!("Peer {} sent {:?} before completing handshake, dropping.", peer_id, msg);
+                                        self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
 
                                         self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_invalid_tx(&peer_id);
                                         continue;
                                     }
 
+                                    if saturated && msg.priority() != MessagePriority::Consensus {
+                                        warn!("Inbound bandwidth cap reached, deferring {:?} traffic from {}", msg.priority(), peer_id);
+                                        self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
+                                        continue;
+                                    }
+
+                                    // Blocks and transactions are forwarded only after they
+                                    // pass local validation; everything else is relayed as is.
+                                    if !matches!(msg, NetworkMessage::Block(_) | NetworkMessage::Transaction(_)) {
+                                        self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Accept);
+                                    }
+
                                     match msg {
                                         NetworkMessage::Block(block) => {
                                         if let Err(e) = NetworkMessage::validate_block_size(&block) {
                                             warn!("Received oversized block from {}: {:?}", peer_id, e);
+                                            self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Reject);
                                             self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_oversized_message(&peer_id);
                                             continue;
                                         }
                                         info!("BLOCK: #{} Hash: {}...", block.index, &block.hash[..8]);
                                         let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
-                                        if block.index == chain.chain.len() as u64 {
+                                        let acceptance = if block.index == chain.chain.len() as u64 {
                                             match chain.validate_and_add_block(block.clone()) {
                                                 Ok(_) => {
                                                     info!("Added block #{} to local chain", block.index);
-                                                    self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_good_behavior(&peer_id);
+                                                    self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_valid_block(&peer_id);
+                                                    gossipsub::MessageAcceptance::Accept
                                                 }
                                                 Err(e) => {
                                                     warn!("Block validation failed: {}", e);
                                                     self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_invalid_block(&peer_id);
+                                                    gossipsub::MessageAcceptance::Reject
                                                 }
                                             }
+                                        } else {
+                                            gossipsub::MessageAcceptance::Ignore
+                                        };
+                                        let proof_request = chain.take_state_proof_request();
+                                        drop(chain);
+                                        if let Some((height, block_hash, keys)) = proof_request {
+                                            // The relaying peer accepted the block, so its tip holds the producer's state.
+                                            let topic = gossipsub::IdentTopic::new("blocks");
+                                            let target = peer_id.to_string();
+                                            self.outbound.push(topic, &NetworkMessage::GetStateProofs { height, block_hash, keys, target });
                                         }
+                                        self.report_validation(&id, &peer_id, acceptance);
                                     }
                                     NetworkMessage::Transaction(tx) => {
                                         if let Err(e) = NetworkMessage::validate_tx_size(&tx) {
                                             warn!("Received oversized transaction from {}: {:?}", peer_id, e);
+                                            self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Reject);
                                             self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_oversized_message(&peer_id);
                                             continue;
                                         }
                                         info!("TX: {}->{} Amount: {}",
                                             &tx.from[..8], &tx.to[..8], tx.amount);
                                         let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
-                                        match chain.add_transaction(tx) {
+                                        if !chain.accepts_transactions() {
+                                            drop(chain);
+                                            self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Ignore);
+                                            continue;
+                                        }
+                                        let acceptance = match chain.add_transaction(tx) {
                                             Ok(_) => {
                                                 self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_good_behavior(&peer_id);
+                                                gossipsub::MessageAcceptance::Accept
                                             }
                                             Err(e) => {
                                                 warn!("Failed to add transaction: {}", e);
                                                 self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_invalid_tx(&peer_id);
+                                                gossipsub::MessageAcceptance::Reject
                                             }
-                                        }
+                                        };
+                                        drop(chain);
+                                        self.report_validation(&id, &peer_id, acceptance);
                                     }
 
                                     NetworkMessage::GetHeaders { locator, limit } => {
@@ -374,11 +1316,8 @@ impl Node {
                                             peer_id, locator.len(), limit);
                                         let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
 
-                                        let start_idx = locator.iter()
-                                            .find_map(|hash| {
-                                                chain.chain.iter().position(|b| &b.hash == hash)
-                                            })
-                                            .map(|i| i + 1)
+                                        let start_idx = chain.find_locator_fork(&locator)
+                                            .map(|i| i as usize + 1)
                                             .unwrap_or(0);
 
                                         let end_idx = (start_idx + limit as usize).min(chain.chain.len());
@@ -390,7 +1329,7 @@ impl Node {
                                         info!("Sending {} headers to {}", headers.len(), peer_id);
                                         let response = NetworkMessage::Headers(headers);
                                         let topic = gossipsub::IdentTopic::new("blocks");
-                                        let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                        self.outbound.push(topic, &response);
                                     }
 
                                     NetworkMessage::Headers(headers) => {
@@ -399,16 +1338,21 @@ impl Node {
                                             continue;
                                         }
                                         if !headers.is_empty() {
-                                            let from = headers[0].index;
                                             let to = headers.last().unwrap().index;
-                                            let req = NetworkMessage::GetBlocksRange { from, to };
-                                            let topic = gossipsub::IdentTopic::new("blocks");
-                                            let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, req.to_bytes());
+                                            let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                                            chain.sync.observe_peer_height(&origin.to_string(), to, unix_time_ms() as u128);
+                                            let download = self.range_sync.get_or_insert_with(|| RangeDownloader::new(chain.last_block()));
+                                            download.extend_target(to + 1);
+                                            drop(chain);
+                                            self.schedule_ranges();
                                         }
                                         self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_good_behavior(&peer_id);
                                     }
 
-                                    NetworkMessage::GetBlocksRange { from, to } => {
+                                    NetworkMessage::GetBlocksRange { from, to, target } => {
+                                        if !target.is_empty() && target != self.peer_id.to_string() {
+                                            continue;
+                                        }
                                         info!("GetBlocksRange request from {} ({}..{})", peer_id, from, to);
                                         let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
 
@@ -418,11 +1362,11 @@ impl Node {
                                         let to_idx = to_idx.min(from_idx + max_blocks);
 
                                         if from_idx < chain.chain.len() {
-                                            let blocks: Vec<_> = chain.chain[from_idx..to_idx].to_vec();
+                                            let blocks = chain.blocks_between(from_idx, to_idx);
                                             info!("Sending {} blocks to {}", blocks.len(), peer_id);
                                             let response = NetworkMessage::Blocks(blocks);
                                             let topic = gossipsub::IdentTopic::new("blocks");
-                                            let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                            self.outbound.push(topic, &response);
                                         }
                                     }
 
@@ -431,6 +1375,26 @@ impl Node {
                                             self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_invalid_block(&peer_id);
                                             continue;
                                         }
+                                        if let Some(download) = self.range_sync.as_mut() {
+                                            let sent_ms = blocks.first().and_then(|b| download.sent_ms(b.index, &origin.to_string()));
+                                            match download.on_blocks(&origin.to_string(), blocks.clone()) {
+                                                Ok(true) => {
+                                                    if let Some(sent_ms) = sent_ms {
+                                                        let elapsed_ms = (unix_time_ms() as u128).saturating_sub(sent_ms);
+                                                        self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).record_transfer(&origin, message.data.len(), elapsed_ms);
+                                                    }
+                                                    self.import_ready_ranges();
+                                                    continue;
+                                                }
+                                                Ok(false) => {}
+                                                Err(e) => {
+                                                    warn!("{}", e);
+                                                    self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_invalid_block(&origin);
+                                                    self.schedule_ranges();
+                                                    continue;
+                                                }
+                                            }
+                                        }
                                         let mut chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
                                         if !blocks.is_empty() {
                                             let start_idx = blocks[0].index as usize;
@@ -450,40 +1414,25 @@ impl Node {
                                     }
 
                                     NetworkMessage::NewTip { height, hash } => {
+                                        self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).sync.observe_peer_height(&origin.to_string(), height, unix_time_ms() as u128);
                                         let our_height = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).chain.len() as u64;
                                         if height > our_height {
                                             let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
-                                            let mut locator = Vec::new();
-                                            let mut step = 1;
-                                            let mut current = chain.chain.len().saturating_sub(1);
-                                            while current > 0 && locator.len() < 10 {
-                                                locator.push(chain.chain[current].hash.clone());
-                                                current = current.saturating_sub(step);
-                                                step *= 2;
-                                            }
-                                            if locator.is_empty() && !chain.chain.is_empty() {
-                                                locator.push(chain.chain[0].hash.clone());
-                                            }
+                                            let locator = chain.block_locator();
                                             let req = NetworkMessage::GetHeaders { locator, limit: 500 };
                                             let topic = gossipsub::IdentTopic::new("blocks");
-                                            let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, req.to_bytes());
+                                            self.outbound.push(topic, &req);
                                         }
                                     }
 
-                                    NetworkMessage::GetStateSnapshot { height } => {
-                                        info!("GetStateSnapshot request from {} (height: {})", peer_id, height);
-                                        let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
-                                        let (state_root, ok) = if let Some(ref store) = chain.storage {
-                                            match store.get_state_root(height) {
-                                                Ok(Some(root)) => (root, true),
-                                                _ => (String::new(), false),
-                                            }
-                                        } else {
-                                            (String::new(), false)
-                                        };
-                                        let response = NetworkMessage::StateSnapshotResponse { height, state_root, ok };
+                                    NetworkMessage::GetStateSnapshot { height, from_index, target } => {
+                                        if !target.is_empty() && target != self.peer_id.to_string() {
+                                            continue;
+                                        }
                                         let topic = gossipsub::IdentTopic::new("blocks");
-                                        let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                        for msg in self.snapshot_response(&peer_id, height, from_index) {
+                                            self.outbound.push(topic.clone(), &msg);
+                                        }
                                     }
 
                                     NetworkMessage::StateSnapshotResponse { height, state_root, ok } => {
@@ -517,7 +1466,7 @@ impl Node {
                                         info!("Sending {} blocks by height to {}", blocks.len(), peer_id);
                                         let response = NetworkMessage::BlocksByHeight(blocks);
                                         let topic = gossipsub::IdentTopic::new("blocks");
-                                        let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                        self.outbound.push(topic, &response);
                                     }
 
                                     NetworkMessage::BlocksByHeight(blocks) => {
@@ -540,22 +1489,28 @@ impl Node {
                                         self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_good_behavior(&peer_id);
                                     }
 
-                                    NetworkMessage::SnapshotChunk { height, index, total, data } => {
-                                        info!("SnapshotChunk from {}: height={}, {}/{}, {} bytes",
-                                            peer_id, height, index, total, data.len());
-
+                                    NetworkMessage::SnapshotChunk { height, index, total, data, chunk_hash } => {
+                                        self.on_snapshot_chunk(peer_id, height, index, total, data, chunk_hash);
                                     }
 
-                                    NetworkMessage::Handshake { version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes } => {
+                                    NetworkMessage::Handshake { version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes, timestamp_ms, fork_hash, node_version, node_commit } => {
                                         let my_chain_id = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).chain_id;
                                         if chain_id != my_chain_id {
                                             warn!("Peer {} has wrong chain_id {} (expected {}). Banning.", peer_id, chain_id, my_chain_id);
                                             self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).ban_peer(&peer_id);
                                             continue;
                                         }
-                                        info!("Handshake from {}: v{}.{}, chain={}, height={}, val_set={}, schemes={:?}",
-                                            peer_id, version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes);
+                                        if let Err(e) = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).check_peer_fork(best_height, &fork_hash) {
+                                            warn!("Peer {} is on another fork: {}. Banning.", peer_id, e);
+                                            self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).ban_peer(&peer_id);
+                                            continue;
+                                        }
+                                        info!("Handshake from {}: v{}.{}, node={} ({}), chain={}, height={}, val_set={}, schemes={:?}",
+                                            peer_id, version_major, version_minor, node_version, node_commit, chain_id, best_height, validator_set_hash, supported_schemes);
                                         self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).set_handshaked(&peer_id, true);
+                                        self.record_peer_time(&peer_id, timestamp_ms);
+                                        self.record_peer_version(&peer_id, &node_version, &node_commit);
+                                        self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).sync.observe_peer_height(&peer_id.to_string(), best_height.saturating_sub(1), unix_time_ms() as u128);
 
                                         let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
                                         let response = NetworkMessage::HandshakeAck {
@@ -565,47 +1520,98 @@ impl Node {
                                             best_height: chain.chain.len() as u64,
                                             validator_set_hash: chain.get_validator_set_hash(),
                                             supported_schemes: vec!["ED25519".to_string(), "BLS".to_string(), "DILITHIUM".to_string()],
+                                            timestamp_ms: unix_time_ms(),
+                                            fork_hash: chain.fork_id().fork_hash,
+                                            node_version: crate::encoding::NODE_VERSION.to_string(),
+                                            node_commit: crate::encoding::NODE_COMMIT.to_string(),
                                         };
                                         let topic = gossipsub::IdentTopic::new("blocks");
-                                        let data = response.to_bytes();
-                                        if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                                            warn!("Failed to send HandshakeAck: {}", e);
-                                        }
+                                        self.outbound.push(topic, &response);
                                     }
 
-                                    NetworkMessage::HandshakeAck { version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes } => {
+                                    NetworkMessage::HandshakeAck { version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes, timestamp_ms, fork_hash, node_version, node_commit } => {
                                         let my_chain_id = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).chain_id;
                                         if chain_id != my_chain_id {
                                             warn!("Peer {} Ack with wrong chain_id {} (expected {}). Banning.", peer_id, chain_id, my_chain_id);
                                             self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).ban_peer(&peer_id);
                                             continue;
                                         }
-                                        info!("HandshakeAck from {}: v{}.{}, chain={}, height={}, val_set={}, schemes={:?}",
-                                            peer_id, version_major, version_minor, chain_id, best_height, validator_set_hash, supported_schemes);
+                                        if let Err(e) = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).check_peer_fork(best_height, &fork_hash) {
+                                            warn!("Peer {} Ack from another fork: {}. Banning.", peer_id, e);
+                                            self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).ban_peer(&peer_id);
+                                            continue;
+                                        }
+                                        info!("HandshakeAck from {}: v{}.{}, node={} ({}), chain={}, height={}, val_set={}, schemes={:?}",
+                                            peer_id, version_major, version_minor, node_version, node_commit, chain_id, best_height, validator_set_hash, supported_schemes);
                                         let mut pm = self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); });
                                         pm.set_handshaked(&peer_id, true);
                                         pm.report_good_behavior(&peer_id);
+                                        drop(pm);
+                                        self.record_peer_time(&peer_id, timestamp_ms);
+                                        self.record_peer_version(&peer_id, &node_version, &node_commit);
+                                        self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).sync.observe_peer_height(&peer_id.to_string(), best_height.saturating_sub(1), unix_time_ms() as u128);
                                     }
 
-                                    NetworkMessage::Prevote { epoch, checkpoint_height, checkpoint_hash, voter_id, .. } => {
+                                    NetworkMessage::Prevote { epoch, checkpoint_height, checkpoint_hash, voter_id, sig_bls, signature } => {
                                         if !self.peer_manager.lock().unwrap().check_vote_rate_limit(&peer_id) {
                                             warn!("Peer {} exceeded vote rate limit. Ignoring Prevote.", peer_id);
                                             continue;
                                         }
+                                        let vote = crate::consensus::finality::Prevote {
+                                            epoch,
+                                            checkpoint_height,
+                                            checkpoint_hash: checkpoint_hash.clone(),
+                                            voter_id: voter_id.clone(),
+                                            sig_bls,
+                                            signature,
+                                        };
+                                        let (voters, fork) = {
+                                            let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                                            (chain.state.active_validator_snapshot(epoch), chain.fork_id_at(checkpoint_height))
+                                        };
+                                        if let Err(e) = vote.authenticate(&voters, &fork) {
+                                            warn!("Dropping Prevote forwarded by {}: {}", peer_id, e);
+                                            self.peer_manager.lock().unwrap().report_bad_behavior(&peer_id);
+                                            continue;
+                                        }
                                         info!("Prevote from {}: epoch={}, height={}, hash={}..., voter={}",
                                             peer_id, epoch, checkpoint_height, &checkpoint_hash[..16.min(checkpoint_hash.len())], voter_id);
+                                        if let Err(e) = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).add_prevote(vote) {
+                                            tracing::debug!("Prevote from {} not tallied: {}", voter_id, e);
+                                        }
                                     }
 
-                                    NetworkMessage::Precommit { epoch, checkpoint_height, checkpoint_hash, voter_id, .. } => {
+                                    NetworkMessage::Precommit { epoch, checkpoint_height, checkpoint_hash, voter_id, sig_bls, signature, state_root } => {
                                         if !self.peer_manager.lock().unwrap().check_vote_rate_limit(&peer_id) {
                                             warn!("Peer {} exceeded vote rate limit. Ignoring Precommit.", peer_id);
                                             continue;
                                         }
+                                        let vote = crate::consensus::finality::Precommit {
+                                            epoch,
+                                            checkpoint_height,
+                                            checkpoint_hash: checkpoint_hash.clone(),
+                                            voter_id: voter_id.clone(),
+                                            sig_bls,
+                                            signature,
+                                            state_root,
+                                        };
+                                        let (voters, fork) = {
+                                            let chain = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); });
+                                            (chain.state.active_validator_snapshot(epoch), chain.fork_id_at(checkpoint_height))
+                                        };
+                                        if let Err(e) = vote.authenticate(&voters, &fork) {
+                                            warn!("Dropping Precommit forwarded by {}: {}", peer_id, e);
+                                            self.peer_manager.lock().unwrap().report_bad_behavior(&peer_id);
+                                            continue;
+                                        }
                                         info!("Precommit from {}: epoch={}, height={}, hash={}..., voter={}",
                                             peer_id, epoch, checkpoint_height, &checkpoint_hash[..16.min(checkpoint_hash.len())], voter_id);
+                                        if let Err(e) = self.blockchain.lock().unwrap_or_else(|e| { tracing::error!("Blockchain lock poisoned: {}", e); std::process::exit(1); }).add_precommit(vote) {
+                                            tracing::debug!("Precommit from {} not tallied: {}", voter_id, e);
+                                        }
                                     }
 
-                                    NetworkMessage::FinalityCert { epoch, checkpoint_height, checkpoint_hash, agg_sig_bls, bitmap, set_hash } => {
+                                    NetworkMessage::FinalityCert { epoch, checkpoint_height, checkpoint_hash, agg_sig_bls, bitmap, set_hash, state_root } => {
                                         if !self.peer_manager.lock().unwrap().check_vote_rate_limit(&peer_id) {
                                             warn!("Peer {} exceeded vote rate limit. Ignoring FinalityCert.", peer_id);
                                             continue;
@@ -620,6 +1626,7 @@ impl Node {
                                             agg_sig_bls,
                                             bitmap,
                                             set_hash,
+                                            state_root,
                                         };
 
                                         let mut chain = self.blockchain.lock().unwrap();
@@ -648,7 +1655,7 @@ impl Node {
                                                     found: true,
                                                 };
                                                 let topic = gossipsub::IdentTopic::new("blocks");
-                                                let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                                self.outbound.push(topic, &response);
                                             } else {
                                                 let response = NetworkMessage::QcBlobResponse {
                                                     epoch,
@@ -658,11 +1665,30 @@ impl Node {
                                                     found: false,
                                                 };
                                                 let topic = gossipsub::IdentTopic::new("blocks");
-                                                let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, response.to_bytes());
+                                                self.outbound.push(topic, &response);
                                             }
                                         }
                                     }
 
+                                    NetworkMessage::GetStateProofs { height, block_hash, keys, target } => {
+                                        if target != self.peer_id.to_string() || !self.peer_manager.lock().unwrap().check_blob_rate_limit(&peer_id) {
+                                            continue;
+                                        }
+                                        let proofs = self.blockchain.lock().unwrap().state_proofs_for(height, &block_hash, &keys);
+                                        if let Some(proofs) = proofs {
+                                            info!("Serving {} state proofs for block {} to {}", proofs.len(), height, peer_id);
+                                            let topic = gossipsub::IdentTopic::new("blocks");
+                                            self.outbound.push(topic, &NetworkMessage::StateProofs { height, block_hash, proofs });
+                                        }
+                                    }
+
+                                    NetworkMessage::StateProofs { height, block_hash, proofs } => {
+                                        let mut chain = self.blockchain.lock().unwrap();
+                                        if let Err(e) = chain.diagnose_state_root(height, &block_hash, &proofs) {
+                                            info!("Ignoring state proofs from {}: {}", peer_id, e);
+                                        }
+                                    }
+
                                     NetworkMessage::QcBlobResponse { epoch, checkpoint_height, found, .. } => {
                                         if !self.peer_manager.lock().unwrap().check_blob_rate_limit(&peer_id) {
                                             warn!("Peer {} exceeded blob rate limit. Ignoring QcBlobResponse.", peer_id);
@@ -679,6 +1705,7 @@ impl Node {
                                 }
                                 Err(e) => {
                                     warn!("Computed invalid message from {}: {:?}", peer_id, e);
+                                    self.report_validation(&id, &peer_id, gossipsub::MessageAcceptance::Reject);
 
                                     self.peer_manager.lock().unwrap_or_else(|e| { tracing::error!("PeerManager lock poisoned: {}", e); std::process::exit(1); }).report_oversized_message(&peer_id);
                                 }
@@ -697,6 +1724,35 @@ impl Node {
                                 KademliaEvent::RoutingUpdated { peer, .. } => {
                                     info!("Kademlia: Routing updated for peer {}", peer);
                                 }
+                                KademliaEvent::OutboundQueryProgressed { id, result: QueryResult::GetProviders(result), step, .. } => {
+                                    let providers = match result {
+                                        Ok(GetProvidersOk::FoundProviders { providers, .. }) => providers.into_iter().collect(),
+                                        Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => Vec::new(),
+                                        Err(e) => {
+                                            warn!("Snapshot provider lookup failed: {:?}", e);
+                                            Vec::new()
+                                        }
+                                    };
+                                    self.on_snapshot_providers(id, providers, step.last);
+                                }
+                                _ => {}
+                            }
+                        }
+                        SwarmEvent::Behaviour(BudlumBehaviourEvent::Snapshot(event)) => {
+                            match event {
+                                request_response::Event::Message { peer, message: request_response::Message::Request { request: NetworkMessage::GetStateSnapshot { height, from_index, .. }, channel, .. } } => {
+                                    let response = self.snapshot_response(&peer, height, from_index);
+                                    if self.swarm.behaviour_mut().snapshot.send_response(channel, response).is_err() {
+                                        warn!("Snapshot response to {} was not sent", peer);
+                                    }
+                                }
+                                request_response::Event::Message { peer, message: request_response::Message::Response { response, .. } } => {
+                                    self.on_snapshot_response(peer, response);
+                                }
+                                request_response::Event::OutboundFailure { peer, error, .. } => {
+                                    warn!("Snapshot request to {} failed: {}", peer, error);
+                                    self.drop_snapshot_provider(&peer);
+                                }
                                 _ => {}
                             }
                         }
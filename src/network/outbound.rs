@@ -0,0 +1,262 @@
+use crate::network::protocol::{MessagePriority, NetworkMessage};
+use libp2p::gossipsub::IdentTopic;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const MAX_BULK_PER_FLUSH: usize = 8;
+pub const MAX_QUEUED_BULK: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    pub drop_probability: f64,
+    pub duplicate_probability: f64,
+    pub delay_probability: f64,
+    pub max_delay_ms: u64,
+}
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            drop_probability: 0.05,
+            duplicate_probability: 0.05,
+            delay_probability: 0.2,
+            max_delay_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChaosAction {
+    Deliver,
+    Drop,
+    Duplicate,
+    Delay(Duration),
+}
+
+impl ChaosConfig {
+    pub fn decide(&self, rng: &mut impl Rng) -> ChaosAction {
+        let roll: f64 = rng.random();
+        let mut threshold = self.drop_probability;
+        if roll < threshold {
+            return ChaosAction::Drop;
+        }
+        threshold += self.duplicate_probability;
+        if roll < threshold {
+            return ChaosAction::Duplicate;
+        }
+        threshold += self.delay_probability;
+        if roll < threshold {
+            let delay = rng.random_range(0..=self.max_delay_ms);
+            return ChaosAction::Delay(Duration::from_millis(delay));
+        }
+        ChaosAction::Deliver
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosStats {
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub delayed: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboundMessage {
+    pub topic: IdentTopic,
+    pub priority: MessagePriority,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct OutboundQueue {
+    consensus: VecDeque<OutboundMessage>,
+    normal: VecDeque<OutboundMessage>,
+    bulk: VecDeque<OutboundMessage>,
+    dropped_bulk: u64,
+    chaos: Option<ChaosConfig>,
+    chaos_stats: ChaosStats,
+    delayed: Vec<(Instant, OutboundMessage)>,
+}
+
+impl OutboundQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    pub fn push(&mut self, topic: IdentTopic, msg: &NetworkMessage) {
+        let priority = msg.priority();
+        let entry = OutboundMessage {
+            topic,
+            priority,
+            data: msg.to_bytes(),
+        };
+        match priority {
+            MessagePriority::Consensus => self.consensus.push_back(entry),
+            MessagePriority::Normal => self.normal.push_back(entry),
+            MessagePriority::Bulk => {
+                if self.bulk.len() >= MAX_QUEUED_BULK {
+                    self.bulk.pop_front();
+                    self.dropped_bulk += 1;
+                }
+                self.bulk.push_back(entry);
+            }
+        }
+    }
+
+    pub fn next_batch(&mut self) -> Vec<OutboundMessage> {
+        let mut batch: Vec<OutboundMessage> = self.consensus.drain(..).collect();
+        batch.extend(self.normal.drain(..));
+        let bulk = self.bulk.len().min(MAX_BULK_PER_FLUSH);
+        batch.extend(self.bulk.drain(..bulk));
+        match self.chaos.clone() {
+            Some(chaos) => self.apply_chaos(&chaos, batch, Instant::now()),
+            None => batch,
+        }
+    }
+
+    fn apply_chaos(
+        &mut self,
+        chaos: &ChaosConfig,
+        batch: Vec<OutboundMessage>,
+        now: Instant,
+    ) -> Vec<OutboundMessage> {
+        let mut rng = rand::rng();
+        let mut out = Vec::with_capacity(batch.len());
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(release_at, _)| *release_at <= now);
+        self.delayed = waiting;
+        out.extend(ready.into_iter().map(|(_, msg)| msg));
+        for msg in batch {
+            match chaos.decide(&mut rng) {
+                ChaosAction::Deliver => out.push(msg),
+                ChaosAction::Drop => self.chaos_stats.dropped += 1,
+                ChaosAction::Duplicate => {
+                    self.chaos_stats.duplicated += 1;
+                    out.push(msg.clone());
+                    out.push(msg);
+                }
+                ChaosAction::Delay(delay) => {
+                    self.chaos_stats.delayed += 1;
+                    self.delayed.push((now + delay, msg));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn chaos_stats(&self) -> Option<&ChaosStats> {
+        self.chaos.as_ref().map(|_| &self.chaos_stats)
+    }
+
+    pub fn len(&self) -> usize {
+        self.consensus.len() + self.normal.len() + self.bulk.len() + self.delayed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn pending_bulk(&self) -> usize {
+        self.bulk.len()
+    }
+
+    pub fn dropped_bulk(&self) -> u64 {
+        self.dropped_bulk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consensus_messages_preempt_bulk() {
+        let topic = IdentTopic::new("blocks");
+        let mut queue = OutboundQueue::new();
+        for index in 0..(MAX_BULK_PER_FLUSH as u32 + 4) {
+            let chunk = NetworkMessage::SnapshotChunk {
+                height: 10,
+                index,
+                total: 100,
+                data: vec![0u8; 64],
+                chunk_hash: String::new(),
+            };
+            queue.push(topic.clone(), &chunk);
+        }
+        queue.push(
+            topic.clone(),
+            &NetworkMessage::Prevote {
+                epoch: 1,
+                checkpoint_height: 100,
+                checkpoint_hash: "aa".into(),
+                voter_id: "v".into(),
+                sig_bls: vec![],
+                signature: vec![],
+            },
+        );
+        queue.push(
+            topic.clone(),
+            &NetworkMessage::GetBlocksRange {
+                from: 1,
+                to: 5,
+                target: String::new(),
+            },
+        );
+
+        let batch = queue.next_batch();
+        assert_eq!(batch[0].priority, MessagePriority::Consensus);
+        assert_eq!(batch[1].priority, MessagePriority::Normal);
+        assert_eq!(batch.len(), 2 + MAX_BULK_PER_FLUSH);
+        assert_eq!(queue.pending_bulk(), 4);
+
+        let rest = queue.next_batch();
+        assert_eq!(rest.len(), 4);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_chaos_drops_and_duplicates() {
+        let topic = IdentTopic::new("blocks");
+        let tip = NetworkMessage::NewTip {
+            height: 1,
+            hash: "aa".into(),
+        };
+
+        let mut lossy = OutboundQueue::new().with_chaos(ChaosConfig {
+            drop_probability: 1.0,
+            duplicate_probability: 0.0,
+            delay_probability: 0.0,
+            max_delay_ms: 0,
+        });
+        lossy.push(topic.clone(), &tip);
+        assert!(lossy.next_batch().is_empty());
+        assert_eq!(lossy.chaos_stats().unwrap().dropped, 1);
+
+        let mut noisy = OutboundQueue::new().with_chaos(ChaosConfig {
+            drop_probability: 0.0,
+            duplicate_probability: 1.0,
+            delay_probability: 0.0,
+            max_delay_ms: 0,
+        });
+        noisy.push(topic.clone(), &tip);
+        assert_eq!(noisy.next_batch().len(), 2);
+
+        let mut slow = OutboundQueue::new().with_chaos(ChaosConfig {
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+            delay_probability: 1.0,
+            max_delay_ms: 0,
+        });
+        slow.push(topic, &tip);
+        assert!(slow.next_batch().is_empty());
+        assert!(!slow.is_empty());
+        assert_eq!(slow.next_batch().len(), 1);
+        assert!(OutboundQueue::new().chaos_stats().is_none());
+    }
+}
@@ -11,9 +11,49 @@ pub const MAX_SCORE: i32 = 100;
 pub const MIN_SCORE: i32 = -99;
 pub const MAX_MSG_BURST: f64 = 20.0;
 pub const MSG_REFILL_RATE: f64 = 5.0;
+// Each relayed valid block forgives this share of outstanding block offenses.
+pub const VALID_BLOCK_APPEAL: f64 = 0.2;
+pub const LINK_SMOOTHING: f64 = 0.3;
+// Every Nth sync peer ranking moves one peer in good standing to the front
+// whose throughput was never measured or was measured too long ago, so it
+// gets a chance to show it is (still) fast.
+pub const SYNC_PROBE_INTERVAL: u64 = 8;
+pub const SYNC_MEASUREMENT_TTL: Duration = Duration::from_secs(120);
+
+// Offenses are tracked per class and decay with their own half-life, so a
+// burst of oversized messages is forgotten long before an invalid block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffenseClass {
+    Block,
+    Protocol,
+    Transaction,
+    Spam,
+}
+
+impl OffenseClass {
+    pub const ALL: [OffenseClass; 4] = [
+        OffenseClass::Block,
+        OffenseClass::Protocol,
+        OffenseClass::Transaction,
+        OffenseClass::Spam,
+    ];
+
+    pub fn half_life(&self) -> Duration {
+        match self {
+            OffenseClass::Block => Duration::from_secs(6 * 3600),
+            OffenseClass::Protocol => Duration::from_secs(3600),
+            OffenseClass::Transaction => Duration::from_secs(1800),
+            OffenseClass::Spam => Duration::from_secs(600),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PeerScore {
     pub score: i32,
+    credit: f64,
+    offenses: [f64; 4],
+    decayed_at: Instant,
     pub banned_until: Option<Instant>,
     pub invalid_blocks: u32,
     pub invalid_txs: u32,
@@ -24,11 +64,17 @@ pub struct PeerScore {
     pub vote_tokens: f64,
     pub blob_tokens: f64,
     pub handshaked: bool,
+    pub rtt_ms: Option<f64>,
+    pub bytes_per_sec: Option<f64>,
+    measured_at: Option<Instant>,
 }
 impl Default for PeerScore {
     fn default() -> Self {
         PeerScore {
             score: 0,
+            credit: 0.0,
+            offenses: [0.0; 4],
+            decayed_at: Instant::now(),
             banned_until: None,
             invalid_blocks: 0,
             invalid_txs: 0,
@@ -39,6 +85,9 @@ impl Default for PeerScore {
             vote_tokens: 10.0,
             blob_tokens: 5.0,
             handshaked: false,
+            rtt_ms: None,
+            bytes_per_sec: None,
+            measured_at: None,
         }
     }
 }
@@ -74,6 +123,45 @@ impl PeerScore {
             false
         }
     }
+    pub fn offense(&self, class: OffenseClass) -> f64 {
+        self.offenses[class as usize]
+    }
+    fn decay(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.decayed_at).as_secs_f64();
+        for class in OffenseClass::ALL {
+            self.offenses[class as usize] *= 0.5f64.powf(elapsed / class.half_life().as_secs_f64());
+        }
+        self.decayed_at = now;
+        self.update_score();
+    }
+    fn update_score(&mut self) {
+        let offenses: f64 = self.offenses.iter().sum();
+        self.score = ((self.credit - offenses).round() as i32).min(MAX_SCORE);
+    }
+    fn penalize(&mut self, class: OffenseClass, penalty: i32) {
+        self.decay(Instant::now());
+        self.offenses[class as usize] += penalty.unsigned_abs() as f64;
+        self.update_score();
+        self.last_seen = Some(Instant::now());
+    }
+    // Soft penalties stop at MIN_SCORE, so on their own they deprioritize a
+    // peer without banning it.
+    fn penalize_soft(&mut self, class: OffenseClass, penalty: i32) {
+        self.decay(Instant::now());
+        let room = (self.score - MIN_SCORE).max(0);
+        self.penalize(class, penalty.abs().min(room));
+    }
+    fn reward(&mut self) {
+        self.decay(Instant::now());
+        self.credit = (self.credit + GOOD_BEHAVIOR_REWARD as f64).min(MAX_SCORE as f64);
+        self.update_score();
+        self.last_seen = Some(Instant::now());
+    }
+    fn reset(&mut self) {
+        self.credit = 0.0;
+        self.offenses = [0.0; 4];
+        self.score = 0;
+    }
     pub fn ban_remaining(&self) -> Option<Duration> {
         self.banned_until.and_then(|until| {
             let now = Instant::now();
@@ -85,13 +173,22 @@ impl PeerScore {
         })
     }
 }
+fn smooth(previous: Option<f64>, sample: f64) -> f64 {
+    match previous {
+        Some(value) => value * (1.0 - LINK_SMOOTHING) + sample * LINK_SMOOTHING,
+        None => sample,
+    }
+}
+
 pub struct PeerManager {
     peers: HashMap<PeerId, PeerScore>,
+    rankings: u64,
 }
 impl PeerManager {
     pub fn new() -> Self {
         PeerManager {
             peers: HashMap::new(),
+            rankings: 0,
         }
     }
     fn get_or_create(&mut self, peer_id: &PeerId) -> &mut PeerScore {
@@ -100,7 +197,7 @@ impl PeerManager {
     pub fn check_rate_limit(&mut self, peer_id: &PeerId) -> bool {
         let score = self.get_or_create(peer_id);
         if !score.consume_token() {
-            score.score = (score.score + OVERSIZED_MESSAGE_PENALTY).max(MIN_SCORE);
+            score.penalize_soft(OffenseClass::Spam, OVERSIZED_MESSAGE_PENALTY);
             if score.score <= BAN_THRESHOLD {
                 let until = Instant::now() + BAN_DURATION;
                 score.banned_until = Some(until);
@@ -117,7 +214,7 @@ impl PeerManager {
             score.vote_tokens -= 1.0;
             true
         } else {
-            score.score = (score.score - 1).max(MIN_SCORE);
+            score.penalize_soft(OffenseClass::Spam, 1);
             false
         }
     }
@@ -129,15 +226,14 @@ impl PeerManager {
             score.blob_tokens -= 1.0;
             true
         } else {
-            score.score = (score.score - 5).max(MIN_SCORE);
+            score.penalize_soft(OffenseClass::Spam, 5);
             false
         }
     }
     pub fn report_invalid_block(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
         score.invalid_blocks += 1;
-        score.score = score.score + INVALID_BLOCK_PENALTY;
-        score.last_seen = Some(Instant::now());
+        score.penalize(OffenseClass::Block, INVALID_BLOCK_PENALTY);
         if score.score <= BAN_THRESHOLD {
             self.ban_peer(peer_id);
         }
@@ -145,24 +241,21 @@ impl PeerManager {
     pub fn report_invalid_tx(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
         score.invalid_txs += 1;
-        score.score = score.score + INVALID_TX_PENALTY;
-        score.last_seen = Some(Instant::now());
+        score.penalize(OffenseClass::Transaction, INVALID_TX_PENALTY);
         if score.score <= BAN_THRESHOLD {
             self.ban_peer(peer_id);
         }
     }
     pub fn report_oversized_message(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
-        score.score = score.score + OVERSIZED_MESSAGE_PENALTY;
-        score.last_seen = Some(Instant::now());
+        score.penalize(OffenseClass::Spam, OVERSIZED_MESSAGE_PENALTY);
         if score.score <= BAN_THRESHOLD {
             self.ban_peer(peer_id);
         }
     }
     pub fn report_bad_behavior(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
-        score.score = (score.score - 10).max(MIN_SCORE);
-        score.last_seen = Some(Instant::now());
+        score.penalize_soft(OffenseClass::Protocol, 10);
         if score.score <= BAN_THRESHOLD {
             self.ban_peer(peer_id);
         }
@@ -170,8 +263,75 @@ impl PeerManager {
     pub fn report_good_behavior(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
         score.valid_contributions += 1;
-        score.score = (score.score + GOOD_BEHAVIOR_REWARD).min(MAX_SCORE);
-        score.last_seen = Some(Instant::now());
+        score.reward();
+    }
+    // A relayed block that made it onto our chain also works off block
+    // offenses, which otherwise take hours to decay.
+    pub fn report_valid_block(&mut self, peer_id: &PeerId) {
+        let score = self.get_or_create(peer_id);
+        score.valid_contributions += 1;
+        score.decay(Instant::now());
+        score.offenses[OffenseClass::Block as usize] *= 1.0 - VALID_BLOCK_APPEAL;
+        score.reward();
+    }
+    pub fn record_ping(&mut self, peer_id: &PeerId, rtt: Duration) {
+        let score = self.get_or_create(peer_id);
+        score.rtt_ms = Some(smooth(score.rtt_ms, rtt.as_secs_f64() * 1000.0));
+    }
+    pub fn record_transfer(&mut self, peer_id: &PeerId, bytes: usize, elapsed_ms: u128) {
+        let rate = bytes as f64 * 1000.0 / elapsed_ms.max(1) as f64;
+        let score = self.get_or_create(peer_id);
+        score.bytes_per_sec = Some(smooth(score.bytes_per_sec, rate));
+        score.measured_at = Some(Instant::now());
+    }
+    // Orders sync candidates best first: peers in good standing before
+    // penalized ones, then by measured throughput and ping time. Unmeasured
+    // peers sort last until a probe gives them a turn.
+    pub fn rank_sync_peers(&mut self, mut peers: Vec<PeerId>) -> Vec<PeerId> {
+        let key = |peer: &PeerId| {
+            let info = self.peers.get(peer);
+            let penalized = info.map(|s| s.score < 0).unwrap_or(false);
+            let throughput = info.and_then(|s| s.bytes_per_sec).unwrap_or(0.0) as u64;
+            let rtt = info
+                .and_then(|s| s.rtt_ms)
+                .map(|ms| ms as u64)
+                .unwrap_or(u64::MAX);
+            (
+                penalized,
+                std::cmp::Reverse(throughput),
+                rtt,
+                peer.to_string(),
+            )
+        };
+        peers.sort_by_cached_key(key);
+        self.rankings += 1;
+        if self.rankings.is_multiple_of(SYNC_PROBE_INTERVAL) {
+            if let Some(i) = self.probe_candidate(&peers) {
+                let peer = peers.remove(i);
+                peers.insert(0, peer);
+            }
+        }
+        peers
+    }
+    // Unmeasured peers first, then the one measured longest ago.
+    fn probe_candidate(&self, ranked: &[PeerId]) -> Option<usize> {
+        let now = Instant::now();
+        ranked
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(i, peer)| {
+                let info = self.peers.get(peer);
+                if info.is_some_and(|s| s.score < 0) {
+                    return None;
+                }
+                match info.and_then(|s| s.measured_at) {
+                    Some(at) if now.duration_since(at) < SYNC_MEASUREMENT_TTL => None,
+                    measured_at => Some((i, measured_at)),
+                }
+            })
+            .min_by_key(|(_, measured_at)| *measured_at)
+            .map(|(i, _)| i)
     }
     pub fn ban_peer(&mut self, peer_id: &PeerId) {
         let score = self.get_or_create(peer_id);
@@ -203,7 +363,7 @@ impl PeerManager {
     pub fn unban_peer(&mut self, peer_id: &PeerId) {
         if let Some(score) = self.peers.get_mut(peer_id) {
             score.banned_until = None;
-            score.score = 0;
+            score.reset();
         }
     }
     pub fn cleanup_expired_bans(&mut self) {
@@ -212,9 +372,10 @@ impl PeerManager {
             if let Some(until) = score.banned_until {
                 if now >= until {
                     score.banned_until = None;
-                    score.score = 0;
+                    score.reset();
                 }
             }
+            score.decay(now);
         }
     }
     pub fn get_banned_peers(&self) -> Vec<PeerId> {
@@ -280,4 +441,80 @@ mod tests {
         }
         assert_eq!(manager.get_score(&peer), MAX_SCORE);
     }
+    #[test]
+    fn test_offense_classes_decay_and_appeal() {
+        let mut manager = PeerManager::new();
+        let peer = test_peer_id();
+        manager.report_invalid_block(&peer);
+        manager.report_oversized_message(&peer);
+        manager.report_oversized_message(&peer);
+        manager.report_oversized_message(&peer);
+        manager.report_oversized_message(&peer);
+        assert_eq!(manager.get_score(&peer), -22);
+
+        let score = manager.peers.get_mut(&peer).unwrap();
+        score.decay(Instant::now() + Duration::from_secs(3600));
+        assert!(score.offense(OffenseClass::Spam) < 0.5);
+        assert!(score.offense(OffenseClass::Block) > 8.5);
+        assert_eq!(score.score, -9);
+
+        for _ in 0..10 {
+            manager.report_valid_block(&peer);
+        }
+        let score = manager.get_peer_info(&peer).unwrap();
+        assert!(score.offense(OffenseClass::Block) < 1.5);
+        assert!(manager.get_score(&peer) > 0);
+
+        let spammer = test_peer_id();
+        for _ in 0..50 {
+            manager.report_bad_behavior(&spammer);
+        }
+        assert_eq!(manager.get_score(&spammer), MIN_SCORE);
+        assert!(!manager.is_banned(&spammer));
+    }
+    #[test]
+    fn test_sync_peers_ranked_by_speed_with_probes() {
+        let mut manager = PeerManager::new();
+        let (fast, slow, unknown, bad) = (
+            test_peer_id(),
+            test_peer_id(),
+            test_peer_id(),
+            test_peer_id(),
+        );
+        manager.record_transfer(&fast, 1_000_000, 1_000);
+        manager.record_transfer(&slow, 1_000_000, 1_000);
+        manager.record_transfer(&slow, 0, 1_000);
+        manager.record_ping(&slow, Duration::from_millis(40));
+        manager.record_transfer(&bad, 5_000_000, 1_000);
+        manager.report_invalid_block(&bad);
+        assert_eq!(
+            manager.get_peer_info(&slow).unwrap().bytes_per_sec,
+            Some(700_000.0)
+        );
+
+        let peers = vec![unknown, bad, slow, fast];
+        let expected = vec![fast, slow, unknown, bad];
+        for _ in 1..SYNC_PROBE_INTERVAL {
+            assert_eq!(manager.rank_sync_peers(peers.clone()), expected);
+        }
+        let probed = manager.rank_sync_peers(peers.clone());
+        assert_eq!(probed, vec![unknown, fast, slow, bad]);
+
+        // Once every peer has a fresh measurement there is nothing to probe
+        // until one goes stale.
+        manager.record_transfer(&unknown, 1_000, 1_000);
+        let expected = vec![fast, slow, unknown, bad];
+        for _ in 0..SYNC_PROBE_INTERVAL {
+            assert_eq!(manager.rank_sync_peers(peers.clone()), expected);
+        }
+        manager.peers.get_mut(&slow).unwrap().measured_at =
+            Instant::now().checked_sub(SYNC_MEASUREMENT_TTL);
+        for _ in 1..SYNC_PROBE_INTERVAL {
+            manager.rank_sync_peers(peers.clone());
+        }
+        assert_eq!(
+            manager.rank_sync_peers(peers),
+            vec![slow, fast, unknown, bad]
+        );
+    }
 }
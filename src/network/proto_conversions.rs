@@ -1,5 +1,7 @@
+use crate::account::{StateProof, StateRootEntry};
 use crate::consensus::pos::SlashingEvidence;
 use crate::network::protocol::NetworkMessage;
+use crate::slashing::SlashingEvidence as VoteEvidence;
 use crate::{Block, BlockHeader, Transaction};
 use prost::Message;
 
@@ -20,6 +22,9 @@ impl From<&Transaction> for pb::ProtoTransaction {
             hash: tx.hash.clone(),
             signature: tx.signature.clone().unwrap_or_default(),
             chain_id: tx.chain_id,
+            fork_hash: tx.fork_hash.clone(),
+            expires_at_height: tx.expires_at_height,
+            signing_version: tx.signing_version as u32,
             tx_type: match tx.tx_type {
                 crate::transaction::TransactionType::Transfer => {
                     pb::ProtoTransactionType::Transfer as i32
@@ -31,6 +36,46 @@ impl From<&Transaction> for pb::ProtoTransaction {
                     pb::ProtoTransactionType::Unstake as i32
                 }
                 crate::transaction::TransactionType::Vote => pb::ProtoTransactionType::Vote as i32,
+                crate::transaction::TransactionType::ClaimRewards => {
+                    pb::ProtoTransactionType::ClaimRewards as i32
+                }
+                crate::transaction::TransactionType::Unjail => {
+                    pb::ProtoTransactionType::Unjail as i32
+                }
+                crate::transaction::TransactionType::Burn => pb::ProtoTransactionType::Burn as i32,
+                crate::transaction::TransactionType::Approve => {
+                    pb::ProtoTransactionType::Approve as i32
+                }
+                crate::transaction::TransactionType::TransferFrom => {
+                    pb::ProtoTransactionType::TransferFrom as i32
+                }
+                crate::transaction::TransactionType::Batch => {
+                    pb::ProtoTransactionType::Batch as i32
+                }
+                crate::transaction::TransactionType::Redelegate => {
+                    pb::ProtoTransactionType::Redelegate as i32
+                }
+                crate::transaction::TransactionType::BridgeLock => {
+                    pb::ProtoTransactionType::BridgeLock as i32
+                }
+                crate::transaction::TransactionType::BridgeBurn => {
+                    pb::ProtoTransactionType::BridgeBurn as i32
+                }
+                crate::transaction::TransactionType::BridgeMint => {
+                    pb::ProtoTransactionType::BridgeMint as i32
+                }
+                crate::transaction::TransactionType::BridgeRelease => {
+                    pb::ProtoTransactionType::BridgeRelease as i32
+                }
+                crate::transaction::TransactionType::LightClientUpdate => {
+                    pb::ProtoTransactionType::LightClientUpdate as i32
+                }
+                crate::transaction::TransactionType::RestoreAccount => {
+                    pb::ProtoTransactionType::RestoreAccount as i32
+                }
+                crate::transaction::TransactionType::RandaoCommit => {
+                    pb::ProtoTransactionType::RandaoCommit as i32
+                }
             },
         }
     }
@@ -53,6 +98,40 @@ impl TryFrom<pb::ProtoTransaction> for Transaction {
             Ok(pb::ProtoTransactionType::Stake) => crate::transaction::TransactionType::Stake,
             Ok(pb::ProtoTransactionType::Unstake) => crate::transaction::TransactionType::Unstake,
             Ok(pb::ProtoTransactionType::Vote) => crate::transaction::TransactionType::Vote,
+            Ok(pb::ProtoTransactionType::ClaimRewards) => {
+                crate::transaction::TransactionType::ClaimRewards
+            }
+            Ok(pb::ProtoTransactionType::Unjail) => crate::transaction::TransactionType::Unjail,
+            Ok(pb::ProtoTransactionType::Burn) => crate::transaction::TransactionType::Burn,
+            Ok(pb::ProtoTransactionType::Approve) => crate::transaction::TransactionType::Approve,
+            Ok(pb::ProtoTransactionType::TransferFrom) => {
+                crate::transaction::TransactionType::TransferFrom
+            }
+            Ok(pb::ProtoTransactionType::Batch) => crate::transaction::TransactionType::Batch,
+            Ok(pb::ProtoTransactionType::Redelegate) => {
+                crate::transaction::TransactionType::Redelegate
+            }
+            Ok(pb::ProtoTransactionType::BridgeLock) => {
+                crate::transaction::TransactionType::BridgeLock
+            }
+            Ok(pb::ProtoTransactionType::BridgeBurn) => {
+                crate::transaction::TransactionType::BridgeBurn
+            }
+            Ok(pb::ProtoTransactionType::BridgeMint) => {
+                crate::transaction::TransactionType::BridgeMint
+            }
+            Ok(pb::ProtoTransactionType::BridgeRelease) => {
+                crate::transaction::TransactionType::BridgeRelease
+            }
+            Ok(pb::ProtoTransactionType::LightClientUpdate) => {
+                crate::transaction::TransactionType::LightClientUpdate
+            }
+            Ok(pb::ProtoTransactionType::RestoreAccount) => {
+                crate::transaction::TransactionType::RestoreAccount
+            }
+            Ok(pb::ProtoTransactionType::RandaoCommit) => {
+                crate::transaction::TransactionType::RandaoCommit
+            }
             Err(_) => return Err("Invalid transaction type in proto payload".into()),
         };
 
@@ -68,6 +147,10 @@ impl TryFrom<pb::ProtoTransaction> for Transaction {
             signature,
             chain_id: proto.chain_id,
             tx_type,
+            fork_hash: proto.fork_hash,
+            expires_at_height: proto.expires_at_height,
+            signing_version: u8::try_from(proto.signing_version)
+                .map_err(|_| "Invalid signing version in proto payload".to_string())?,
         })
     }
 }
@@ -79,6 +162,7 @@ impl From<&SlashingEvidence> for pb::ProtoSlashingEvidence {
             header2: Some(pb::ProtoBlockHeader::from(&ev.header2)),
             signature1: ev.signature1.clone(),
             signature2: ev.signature2.clone(),
+            reporter: ev.reporter.clone(),
         }
     }
 }
@@ -93,6 +177,35 @@ impl TryFrom<pb::ProtoSlashingEvidence> for SlashingEvidence {
             header2: BlockHeader::try_from(header2)?,
             signature1: proto.signature1,
             signature2: proto.signature2,
+            reporter: proto.reporter,
+        })
+    }
+}
+
+impl From<&StateProof> for pb::ProtoStateProof {
+    fn from(proof: &StateProof) -> Self {
+        pb::ProtoStateProof {
+            entry: Some(pb::ProtoStateEntry {
+                key: proof.entry.key.clone(),
+                value: proof.entry.value.clone(),
+            }),
+            index: proof.index,
+            branch: proof.branch.clone(),
+        }
+    }
+}
+
+impl TryFrom<pb::ProtoStateProof> for StateProof {
+    type Error = String;
+    fn try_from(proto: pb::ProtoStateProof) -> Result<Self, Self::Error> {
+        let entry = proto.entry.ok_or("Missing entry in proto state proof")?;
+        Ok(StateProof {
+            entry: StateRootEntry {
+                key: entry.key,
+                value: entry.value,
+            },
+            index: proto.index,
+            branch: proto.branch,
         })
     }
 }
@@ -121,10 +234,31 @@ impl From<&BlockHeader> for pb::ProtoBlockHeader {
             vrf_output: header.vrf_output.clone(),
             vrf_proof: header.vrf_proof.clone(),
             validator_set_hash: header.validator_set_hash.clone(),
+            vote_evidence: encode_vote_evidence(&header.vote_evidence),
+            randao_reveal: header.randao_reveal.clone(),
+            version: header.version as u32,
+            extensions: header.extensions.clone(),
+            state_snapshot_hash: header.state_snapshot_hash.clone(),
         }
     }
 }
 
+fn encode_vote_evidence(evidence: &Option<Vec<VoteEvidence>>) -> Vec<u8> {
+    evidence
+        .as_ref()
+        .map(|e| bincode::serialize(e).unwrap_or_default())
+        .unwrap_or_default()
+}
+
+fn decode_vote_evidence(bytes: &[u8]) -> Result<Option<Vec<VoteEvidence>>, String> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    bincode::deserialize(bytes)
+        .map(Some)
+        .map_err(|e| format!("Invalid vote evidence: {}", e))
+}
+
 impl TryFrom<pb::ProtoBlockHeader> for BlockHeader {
     type Error = String;
     fn try_from(proto: pb::ProtoBlockHeader) -> Result<Self, Self::Error> {
@@ -162,10 +296,21 @@ impl TryFrom<pb::ProtoBlockHeader> for BlockHeader {
             vrf_output: proto.vrf_output,
             vrf_proof: proto.vrf_proof,
             validator_set_hash: proto.validator_set_hash,
+            vote_evidence: decode_vote_evidence(&proto.vote_evidence)?,
+            randao_reveal: proto.randao_reveal,
+            version: decode_block_version(proto.version)?,
+            extensions: proto.extensions,
+            state_snapshot_hash: proto.state_snapshot_hash,
         })
     }
 }
 
+// Any version that fits the byte decodes, so a block from a newer minor
+// reaches validation, which decides whether this node can follow it.
+fn decode_block_version(version: u32) -> Result<u8, String> {
+    u8::try_from(version).map_err(|_| format!("Invalid block version {}", version))
+}
+
 impl From<&Block> for pb::ProtoBlock {
     fn from(block: &Block) -> Self {
         pb::ProtoBlock {
@@ -196,6 +341,11 @@ impl From<&Block> for pb::ProtoBlock {
             vrf_output: block.vrf_output.clone(),
             vrf_proof: block.vrf_proof.clone(),
             validator_set_hash: block.validator_set_hash.clone(),
+            vote_evidence: encode_vote_evidence(&block.vote_evidence),
+            randao_reveal: block.randao_reveal.clone(),
+            version: block.version as u32,
+            extensions: block.extensions.clone(),
+            state_snapshot_hash: block.state_snapshot_hash.clone(),
         }
     }
 }
@@ -251,6 +401,11 @@ impl TryFrom<pb::ProtoBlock> for Block {
             vrf_output: proto.vrf_output,
             vrf_proof: proto.vrf_proof,
             validator_set_hash: proto.validator_set_hash,
+            vote_evidence: decode_vote_evidence(&proto.vote_evidence)?,
+            randao_reveal: proto.randao_reveal,
+            version: decode_block_version(proto.version)?,
+            extensions: proto.extensions,
+            state_snapshot_hash: proto.state_snapshot_hash,
         })
     }
 }
@@ -265,6 +420,10 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 best_height,
                 validator_set_hash,
                 supported_schemes,
+                timestamp_ms,
+                fork_hash,
+                node_version,
+                node_commit,
             } => pb::proto_network_message::Payload::Handshake(pb::ProtoHandshake {
                 version_major: *version_major,
                 version_minor: *version_minor,
@@ -272,6 +431,10 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 best_height: *best_height,
                 validator_set_hash: validator_set_hash.clone(),
                 supported_schemes: supported_schemes.clone(),
+                timestamp_ms: *timestamp_ms,
+                fork_hash: fork_hash.clone(),
+                node_version: node_version.clone(),
+                node_commit: node_commit.clone(),
             }),
             NetworkMessage::HandshakeAck {
                 version_major,
@@ -280,6 +443,10 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 best_height,
                 validator_set_hash,
                 supported_schemes,
+                timestamp_ms,
+                fork_hash,
+                node_version,
+                node_commit,
             } => pb::proto_network_message::Payload::HandshakeAck(pb::ProtoHandshakeAck {
                 version_major: *version_major,
                 version_minor: *version_minor,
@@ -287,6 +454,10 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 best_height: *best_height,
                 validator_set_hash: validator_set_hash.clone(),
                 supported_schemes: supported_schemes.clone(),
+                timestamp_ms: *timestamp_ms,
+                fork_hash: fork_hash.clone(),
+                node_version: node_version.clone(),
+                node_commit: node_commit.clone(),
             }),
             NetworkMessage::Block(block) => {
                 pb::proto_network_message::Payload::Block(pb::ProtoBlock::from(block))
@@ -305,10 +476,11 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                     headers: headers.iter().map(pb::ProtoBlockHeader::from).collect(),
                 })
             }
-            NetworkMessage::GetBlocksRange { from, to } => {
+            NetworkMessage::GetBlocksRange { from, to, target } => {
                 pb::proto_network_message::Payload::GetBlocksRange(pb::ProtoGetBlocksRange {
                     from_index: *from,
                     to_index: *to,
+                    target: target.clone(),
                 })
             }
             NetworkMessage::Blocks(blocks) => {
@@ -347,21 +519,27 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                     hash: hash.clone(),
                 })
             }
-            NetworkMessage::GetStateSnapshot { height } => {
-                pb::proto_network_message::Payload::GetStateSnapshot(pb::ProtoGetStateSnapshot {
-                    height: *height,
-                })
-            }
+            NetworkMessage::GetStateSnapshot {
+                height,
+                from_index,
+                target,
+            } => pb::proto_network_message::Payload::GetStateSnapshot(pb::ProtoGetStateSnapshot {
+                height: *height,
+                from_index: *from_index,
+                target: target.clone(),
+            }),
             NetworkMessage::SnapshotChunk {
                 height,
                 index,
                 total,
                 data,
+                chunk_hash,
             } => pb::proto_network_message::Payload::SnapshotChunk(pb::ProtoSnapshotChunk {
                 height: *height,
                 index: *index,
                 total: *total,
                 data: data.clone(),
+                chunk_hash: chunk_hash.clone(),
             }),
             NetworkMessage::Prevote {
                 epoch,
@@ -369,12 +547,14 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 checkpoint_hash,
                 voter_id,
                 sig_bls,
+                signature,
             } => pb::proto_network_message::Payload::Prevote(pb::ProtoPrevote {
                 epoch: *epoch,
                 checkpoint_height: *checkpoint_height,
                 checkpoint_hash: checkpoint_hash.clone(),
                 voter_id: voter_id.clone(),
                 sig_bls: sig_bls.clone(),
+                signature: signature.clone(),
             }),
             NetworkMessage::Precommit {
                 epoch,
@@ -382,12 +562,16 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 checkpoint_hash,
                 voter_id,
                 sig_bls,
+                signature,
+                state_root,
             } => pb::proto_network_message::Payload::Precommit(pb::ProtoPrecommit {
                 epoch: *epoch,
                 checkpoint_height: *checkpoint_height,
                 checkpoint_hash: checkpoint_hash.clone(),
                 voter_id: voter_id.clone(),
                 sig_bls: sig_bls.clone(),
+                signature: signature.clone(),
+                state_root: state_root.clone(),
             }),
             NetworkMessage::FinalityCert {
                 epoch,
@@ -396,6 +580,7 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 agg_sig_bls,
                 bitmap,
                 set_hash,
+                state_root,
             } => pb::proto_network_message::Payload::FinalityCert(pb::ProtoFinalityCert {
                 epoch: *epoch,
                 checkpoint_height: *checkpoint_height,
@@ -403,6 +588,7 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 agg_sig_bls: agg_sig_bls.clone(),
                 bitmap: bitmap.clone(),
                 set_hash: set_hash.clone(),
+                state_root: state_root.clone(),
             }),
             NetworkMessage::GetQcBlob {
                 epoch,
@@ -424,6 +610,26 @@ impl From<&NetworkMessage> for pb::ProtoNetworkMessage {
                 blob_data: blob_data.clone(),
                 found: *found,
             }),
+            NetworkMessage::GetStateProofs {
+                height,
+                block_hash,
+                keys,
+                target,
+            } => pb::proto_network_message::Payload::GetStateProofs(pb::ProtoGetStateProofs {
+                height: *height,
+                block_hash: block_hash.clone(),
+                keys: keys.clone(),
+                target: target.clone(),
+            }),
+            NetworkMessage::StateProofs {
+                height,
+                block_hash,
+                proofs,
+            } => pb::proto_network_message::Payload::StateProofs(pb::ProtoStateProofs {
+                height: *height,
+                block_hash: block_hash.clone(),
+                proofs: proofs.iter().map(pb::ProtoStateProof::from).collect(),
+            }),
         };
 
         pb::ProtoNetworkMessage {
@@ -446,6 +652,10 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                 best_height: h.best_height,
                 validator_set_hash: h.validator_set_hash,
                 supported_schemes: h.supported_schemes,
+                timestamp_ms: h.timestamp_ms,
+                fork_hash: h.fork_hash,
+                node_version: h.node_version,
+                node_commit: h.node_commit,
             }),
             pb::proto_network_message::Payload::HandshakeAck(h) => {
                 Ok(NetworkMessage::HandshakeAck {
@@ -455,6 +665,10 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                     best_height: h.best_height,
                     validator_set_hash: h.validator_set_hash,
                     supported_schemes: h.supported_schemes,
+                    timestamp_ms: h.timestamp_ms,
+                    fork_hash: h.fork_hash,
+                    node_version: h.node_version,
+                    node_commit: h.node_commit,
                 })
             }
             pb::proto_network_message::Payload::Block(b) => {
@@ -478,6 +692,7 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                 Ok(NetworkMessage::GetBlocksRange {
                     from: r.from_index,
                     to: r.to_index,
+                    target: r.target,
                 })
             }
             pb::proto_network_message::Payload::Blocks(b) => {
@@ -512,7 +727,11 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                 hash: t.hash,
             }),
             pb::proto_network_message::Payload::GetStateSnapshot(s) => {
-                Ok(NetworkMessage::GetStateSnapshot { height: s.height })
+                Ok(NetworkMessage::GetStateSnapshot {
+                    height: s.height,
+                    from_index: s.from_index,
+                    target: s.target,
+                })
             }
             pb::proto_network_message::Payload::SnapshotChunk(c) => {
                 Ok(NetworkMessage::SnapshotChunk {
@@ -520,6 +739,7 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                     index: c.index,
                     total: c.total,
                     data: c.data,
+                    chunk_hash: c.chunk_hash,
                 })
             }
             pb::proto_network_message::Payload::Prevote(v) => Ok(NetworkMessage::Prevote {
@@ -528,6 +748,7 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                 checkpoint_hash: v.checkpoint_hash,
                 voter_id: v.voter_id,
                 sig_bls: v.sig_bls,
+                signature: v.signature,
             }),
             pb::proto_network_message::Payload::Precommit(v) => Ok(NetworkMessage::Precommit {
                 epoch: v.epoch,
@@ -535,6 +756,8 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                 checkpoint_hash: v.checkpoint_hash,
                 voter_id: v.voter_id,
                 sig_bls: v.sig_bls,
+                signature: v.signature,
+                state_root: v.state_root,
             }),
             pb::proto_network_message::Payload::FinalityCert(f) => {
                 Ok(NetworkMessage::FinalityCert {
@@ -544,6 +767,7 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                     agg_sig_bls: f.agg_sig_bls,
                     bitmap: f.bitmap,
                     set_hash: f.set_hash,
+                    state_root: f.state_root,
                 })
             }
             pb::proto_network_message::Payload::GetQcBlob(q) => Ok(NetworkMessage::GetQcBlob {
@@ -559,6 +783,23 @@ impl TryFrom<pb::ProtoNetworkMessage> for NetworkMessage {
                     found: q.found,
                 })
             }
+            pb::proto_network_message::Payload::GetStateProofs(q) => {
+                Ok(NetworkMessage::GetStateProofs {
+                    height: q.height,
+                    block_hash: q.block_hash,
+                    keys: q.keys,
+                    target: q.target,
+                })
+            }
+            pb::proto_network_message::Payload::StateProofs(q) => Ok(NetworkMessage::StateProofs {
+                height: q.height,
+                block_hash: q.block_hash,
+                proofs: q
+                    .proofs
+                    .into_iter()
+                    .map(StateProof::try_from)
+                    .collect::<Result<_, _>>()?,
+            }),
         }
     }
 }
@@ -603,13 +844,20 @@ mod tests {
         let mut block = Block::new(10, "PREV_HASH".to_string(), vec![tx]);
         block.state_root = "STATE_ROOT".to_string();
         block.tx_root = "TX_ROOT".to_string();
-        block.sign(&keypair);
+        block.version = 0x1f;
+        block.extensions = vec![1, 2, 3];
+        block.state_snapshot_hash = "SNAPSHOT_HASH".to_string();
+        block.sign(&keypair, "");
 
         let proto_block = pb::ProtoBlock::from(&block);
 
         let decoded_block = Block::try_from(proto_block).expect("Failed to decode proto block");
 
         assert_eq!(block, decoded_block);
+
+        let mut oversized = pb::ProtoBlock::from(&block);
+        oversized.version = 256;
+        assert!(Block::try_from(oversized).is_err());
     }
 
     #[test]
@@ -1,3 +1,4 @@
+use crate::account::StateProof;
 use crate::encoding::PROTOCOL_VERSION_MAJOR;
 use crate::{Block, BlockHeader, Transaction};
 use serde::{Deserialize, Serialize};
@@ -17,6 +18,13 @@ pub enum MessageError {
 
 pub const MAX_SNAP_BATCH: u64 = 256;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessagePriority {
+    Consensus,
+    Normal,
+    Bulk,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NetworkMessage {
     Handshake {
@@ -26,6 +34,14 @@ pub enum NetworkMessage {
         best_height: u64,
         validator_set_hash: String,
         supported_schemes: Vec<String>,
+        #[serde(default)]
+        timestamp_ms: u64,
+        #[serde(default)]
+        fork_hash: String,
+        #[serde(default)]
+        node_version: String,
+        #[serde(default)]
+        node_commit: String,
     },
     HandshakeAck {
         version_major: u32,
@@ -34,6 +50,14 @@ pub enum NetworkMessage {
         best_height: u64,
         validator_set_hash: String,
         supported_schemes: Vec<String>,
+        #[serde(default)]
+        timestamp_ms: u64,
+        #[serde(default)]
+        fork_hash: String,
+        #[serde(default)]
+        node_version: String,
+        #[serde(default)]
+        node_commit: String,
     },
 
     Block(Block),
@@ -49,6 +73,8 @@ pub enum NetworkMessage {
     GetBlocksRange {
         from: u64,
         to: u64,
+        #[serde(default)]
+        target: String,
     },
 
     Blocks(Vec<Block>),
@@ -73,6 +99,9 @@ pub enum NetworkMessage {
 
     GetStateSnapshot {
         height: u64,
+        from_index: u32,
+        #[serde(default)]
+        target: String,
     },
 
     SnapshotChunk {
@@ -80,6 +109,7 @@ pub enum NetworkMessage {
         index: u32,
         total: u32,
         data: Vec<u8>,
+        chunk_hash: String,
     },
 
     Prevote {
@@ -88,6 +118,8 @@ pub enum NetworkMessage {
         checkpoint_hash: String,
         voter_id: String,
         sig_bls: Vec<u8>,
+        #[serde(default)]
+        signature: Vec<u8>,
     },
 
     Precommit {
@@ -96,6 +128,10 @@ pub enum NetworkMessage {
         checkpoint_hash: String,
         voter_id: String,
         sig_bls: Vec<u8>,
+        #[serde(default)]
+        signature: Vec<u8>,
+        #[serde(default)]
+        state_root: String,
     },
 
     FinalityCert {
@@ -105,6 +141,8 @@ pub enum NetworkMessage {
         agg_sig_bls: Vec<u8>,
         bitmap: Vec<u8>,
         set_hash: String,
+        #[serde(default)]
+        state_root: String,
     },
 
     GetQcBlob {
@@ -119,8 +157,45 @@ pub enum NetworkMessage {
         blob_data: Vec<u8>,
         found: bool,
     },
+
+    // Merkle proofs of a few state root keys, for diagnosing a state root
+    // mismatch. Only `target` answers, and only while that block is its tip.
+    GetStateProofs {
+        height: u64,
+        block_hash: String,
+        keys: Vec<String>,
+        #[serde(default)]
+        target: String,
+    },
+
+    StateProofs {
+        height: u64,
+        block_hash: String,
+        proofs: Vec<StateProof>,
+    },
 }
 impl NetworkMessage {
+    pub fn priority(&self) -> MessagePriority {
+        match self {
+            NetworkMessage::Handshake { .. }
+            | NetworkMessage::HandshakeAck { .. }
+            | NetworkMessage::NewTip { .. }
+            | NetworkMessage::Prevote { .. }
+            | NetworkMessage::Precommit { .. }
+            | NetworkMessage::FinalityCert { .. } => MessagePriority::Consensus,
+            NetworkMessage::Headers(_)
+            | NetworkMessage::Blocks(_)
+            | NetworkMessage::BlocksByHeight(_)
+            | NetworkMessage::SnapshotChunk { .. }
+            | NetworkMessage::QcBlobResponse { .. }
+            | NetworkMessage::StateProofs { .. } => MessagePriority::Bulk,
+            NetworkMessage::Block(block) if block.slashing_evidence.is_some() => {
+                MessagePriority::Consensus
+            }
+            _ => MessagePriority::Normal,
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         use prost::Message;
         let proto_msg = crate::network::proto_conversions::pb::ProtoNetworkMessage::from(self);
@@ -0,0 +1,296 @@
+use crate::Block;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+pub const RANGE_SIZE: u64 = 128;
+pub const RANGE_TIMEOUT_MS: u128 = 10_000;
+pub const MAX_RANGES_PER_PEER: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeRequest {
+    pub from: u64,
+    pub to: u64,
+    pub peer: String,
+}
+
+#[derive(Debug, Clone)]
+struct InFlight {
+    to: u64,
+    peer: String,
+    sent_ms: u128,
+}
+
+#[derive(Debug, Clone)]
+struct Retry {
+    from: u64,
+    to: u64,
+    avoid: String,
+}
+
+// Splits [next, target) into disjoint `from..to` ranges (exclusive `to`, as
+// served by GetBlocksRange), spreads them over peers and hands completed
+// ranges back strictly in height order.
+#[derive(Debug, Clone)]
+pub struct RangeDownloader {
+    next_height: u64,
+    target: u64,
+    import_height: u64,
+    import_hash: String,
+    in_flight: BTreeMap<u64, InFlight>,
+    retries: VecDeque<Retry>,
+    completed: BTreeMap<u64, (String, Vec<Block>)>,
+}
+
+impl RangeDownloader {
+    pub fn new(tip: &Block) -> Self {
+        RangeDownloader {
+            next_height: tip.index + 1,
+            target: tip.index + 1,
+            import_height: tip.index + 1,
+            import_hash: tip.hash.clone(),
+            in_flight: BTreeMap::new(),
+            retries: VecDeque::new(),
+            completed: BTreeMap::new(),
+        }
+    }
+
+    pub fn extend_target(&mut self, target: u64) {
+        self.target = self.target.max(target);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.import_height >= self.target
+    }
+
+    // Hands out retried ranges first, never back to the peer that failed
+    // them, then fresh ranges up to the target. `peers` is in preference
+    // order: among equally loaded peers the earlier one gets the range.
+    pub fn assign(&mut self, peers: &[String], now_ms: u128) -> Vec<RangeRequest> {
+        let rank: HashMap<&str, usize> = peers
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.as_str(), i))
+            .collect();
+        let mut load: HashMap<&str, usize> = peers.iter().map(|p| (p.as_str(), 0)).collect();
+        for request in self.in_flight.values() {
+            if let Some(count) = load.get_mut(request.peer.as_str()) {
+                *count += 1;
+            }
+        }
+        let mut requests = Vec::new();
+        loop {
+            let retry = self.retries.front().cloned();
+            let (from, to) = match &retry {
+                Some(r) => (r.from, r.to),
+                None if self.next_height < self.target => (
+                    self.next_height,
+                    (self.next_height + RANGE_SIZE).min(self.target),
+                ),
+                None => break,
+            };
+            let avoid = retry.as_ref().map(|r| r.avoid.as_str()).unwrap_or("");
+            let peer = load
+                .iter()
+                .filter(|(peer, count)| **count < MAX_RANGES_PER_PEER && **peer != avoid)
+                .min_by_key(|(peer, count)| (**count, rank[**peer]))
+                .map(|(peer, _)| peer.to_string());
+            let Some(peer) = peer else { break };
+            *load.get_mut(peer.as_str()).unwrap() += 1;
+            if retry.is_some() {
+                self.retries.pop_front();
+            } else {
+                self.next_height = to;
+            }
+            self.in_flight.insert(
+                from,
+                InFlight {
+                    to,
+                    peer: peer.clone(),
+                    sent_ms: now_ms,
+                },
+            );
+            requests.push(RangeRequest { from, to, peer });
+        }
+        requests
+    }
+
+    pub fn sent_ms(&self, from: u64, peer: &str) -> Option<u128> {
+        self.in_flight
+            .get(&from)
+            .filter(|request| request.peer == peer)
+            .map(|request| request.sent_ms)
+    }
+
+    // Returns false when the blocks don't answer any range requested from
+    // this peer, so the caller can fall back to its regular handling. A
+    // contiguous prefix of the range is accepted.
+    pub fn on_blocks(&mut self, peer: &str, blocks: Vec<Block>) -> Result<bool, String> {
+        let Some(from) = blocks.first().map(|b| b.index) else {
+            return Ok(false);
+        };
+        match self.in_flight.get(&from) {
+            Some(request) if request.peer == peer => {}
+            _ => return Ok(false),
+        }
+        let request = self.in_flight.remove(&from).unwrap();
+        let len = blocks.len() as u64;
+        let contiguous = len <= request.to - from
+            && blocks
+                .windows(2)
+                .all(|w| w[1].index == w[0].index + 1 && w[1].previous_hash == w[0].hash);
+        if !contiguous {
+            self.retries.push_back(Retry {
+                from,
+                to: request.to,
+                avoid: request.peer,
+            });
+            return Err(format!(
+                "Range {}..{} from {} is not contiguous",
+                from, request.to, peer
+            ));
+        }
+        // A peer may serve fewer blocks than asked for, e.g. when it caps
+        // its response; the rest is requested again from any peer.
+        if from + len < request.to {
+            self.retries.push_back(Retry {
+                from: from + len,
+                to: request.to,
+                avoid: String::new(),
+            });
+        }
+        self.completed.insert(from, (request.peer, blocks));
+        Ok(true)
+    }
+
+    pub fn expire(&mut self, now_ms: u128) -> Vec<String> {
+        let expired: Vec<u64> = self
+            .in_flight
+            .iter()
+            .filter(|(_, r)| now_ms.saturating_sub(r.sent_ms) >= RANGE_TIMEOUT_MS)
+            .map(|(from, _)| *from)
+            .collect();
+        let mut peers = Vec::new();
+        for from in expired {
+            let request = self.in_flight.remove(&from).unwrap();
+            peers.push(request.peer.clone());
+            self.retries.push_back(Retry {
+                from,
+                to: request.to,
+                avoid: request.peer,
+            });
+        }
+        peers
+    }
+
+    // Pops completed ranges that extend the imported prefix. A range that
+    // doesn't link to it is re-requested and its peer reported in the error.
+    pub fn next_ready(&mut self) -> Option<Result<(String, Vec<Block>), String>> {
+        let (peer, blocks) = self.completed.remove(&self.import_height)?;
+        if blocks[0].previous_hash != self.import_hash {
+            self.retries.push_back(Retry {
+                from: self.import_height,
+                to: self.import_height + blocks.len() as u64,
+                avoid: peer.clone(),
+            });
+            return Some(Err(peer));
+        }
+        let last = blocks.last().unwrap();
+        self.import_height = last.index + 1;
+        self.import_hash = last.hash.clone();
+        Some(Ok((peer, blocks)))
+    }
+
+    // Drops everything past a block the chain refused and schedules the
+    // download again from the current tip.
+    pub fn restart(&mut self, tip: &Block) {
+        let target = self.target;
+        *self = RangeDownloader::new(tip);
+        self.target = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(len: u64) -> Vec<Block> {
+        let mut blocks = vec![Block::new(0, "0".repeat(64), vec![])];
+        for i in 1..len {
+            let prev = blocks.last().unwrap().hash.clone();
+            blocks.push(Block::new(i, prev, vec![]));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_ranges_spread_retry_and_import_in_order() {
+        let blocks = chain(400);
+        let mut dl = RangeDownloader::new(&blocks[0]);
+        dl.extend_target(400);
+        let peers = vec!["a".to_string(), "b".to_string()];
+        let requests = dl.assign(&peers, 0);
+        assert_eq!(requests.len(), 4);
+        assert_eq!((requests[0].from, requests[0].to), (1, 129));
+        assert_eq!(requests[3].to, 400);
+        assert_ne!(requests[0].peer, requests[1].peer);
+        assert_eq!(requests[0].peer, "a");
+        assert_eq!(dl.sent_ms(1, "a"), Some(0));
+        assert_eq!(dl.sent_ms(1, "b"), None);
+
+        let r1 = requests[1].clone();
+        let mut tampered = blocks[r1.from as usize..r1.to as usize].to_vec();
+        tampered.remove(5);
+        assert!(dl.on_blocks(&r1.peer, tampered).is_err());
+        assert!(!dl
+            .on_blocks("c", blocks[r1.from as usize..r1.to as usize].to_vec())
+            .unwrap());
+
+        for r in &requests[2..] {
+            let range = blocks[r.from as usize..r.to as usize].to_vec();
+            assert!(dl.on_blocks(&r.peer, range).unwrap());
+        }
+        assert_eq!(dl.expire(RANGE_TIMEOUT_MS), vec![requests[0].peer.clone()]);
+
+        let retried = dl.assign(&peers, RANGE_TIMEOUT_MS);
+        assert_eq!(retried.len(), 2);
+        for request in &retried {
+            let failed = if request.from == r1.from {
+                &r1.peer
+            } else {
+                &requests[0].peer
+            };
+            assert_ne!(&request.peer, failed);
+            let range = blocks[request.from as usize..request.to as usize].to_vec();
+            assert!(dl.on_blocks(&request.peer, range).unwrap());
+        }
+        let mut imported = vec![];
+        while let Some(ready) = dl.next_ready() {
+            imported.extend(ready.unwrap().1);
+        }
+        assert_eq!(imported.len(), 399);
+        assert!(imported.windows(2).all(|w| w[1].previous_hash == w[0].hash));
+        assert!(dl.is_complete());
+    }
+
+    #[test]
+    fn test_short_range_is_accepted_and_rest_requested() {
+        let blocks = chain(129);
+        let mut dl = RangeDownloader::new(&blocks[0]);
+        dl.extend_target(129);
+        let peers = vec!["a".to_string()];
+        let request = dl.assign(&peers, 0).remove(0);
+        assert_eq!((request.from, request.to), (1, 129));
+
+        assert!(dl.on_blocks("a", blocks[1..50].to_vec()).unwrap());
+        let rest = dl.assign(&peers, 0);
+        assert_eq!((rest[0].from, rest[0].to), (50, 129));
+        assert_eq!(rest[0].peer, "a");
+        assert!(dl.on_blocks("a", blocks[50..129].to_vec()).unwrap());
+
+        let mut imported = vec![];
+        while let Some(ready) = dl.next_ready() {
+            imported.extend(ready.unwrap().1);
+        }
+        assert_eq!(imported.len(), 128);
+        assert!(dl.is_complete());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::network::protocol::{NetworkMessage, MAX_MESSAGE_SIZE};
+use crate::snapshot::{snapshot_period, MAX_CHUNKS_PER_REQUEST};
+use async_trait::async_trait;
+use libp2p::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::kad::RecordKey;
+use libp2p::request_response;
+use libp2p::{PeerId, StreamProtocol};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+
+pub const SNAPSHOT_ADVERTISE_SECS: u64 = 60;
+pub const SNAPSHOT_PROVIDER_CANDIDATES: u64 = 4;
+pub const SNAPSHOT_PROTOCOL: StreamProtocol = StreamProtocol::new("/budlum/snapshot/1");
+
+// Kademlia key under which nodes holding the snapshot at `height` of chain
+// `chain_id` register as providers.
+pub fn snapshot_provider_key(chain_id: u64, height: u64) -> RecordKey {
+    RecordKey::new(&format!("/budlum/{}/snapshot/{}", chain_id, height))
+}
+
+// Heights to look up when asking for the latest snapshot: the most recent
+// snapshot boundaries at or below the best height peers have advertised.
+pub fn candidate_heights(best_peer_height: u64, snapshot_interval: u64) -> Vec<u64> {
+    if snapshot_interval == 0 {
+        return Vec::new();
+    }
+    let period = snapshot_period(snapshot_interval);
+    let latest = (best_peer_height + 1) / period;
+    (0..SNAPSHOT_PROVIDER_CANDIDATES)
+        .filter_map(|back| latest.checked_sub(back))
+        .filter(|n| *n > 0)
+        .map(|n| n * period - 1)
+        .collect()
+}
+
+// Collects provider lookups for the candidate snapshot heights and picks
+// the highest height someone provides. A lower height is only chosen once
+// every lookup above it has finished without a provider, so a fast answer
+// for an old snapshot doesn't win over a newer one.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSearch {
+    pending: BTreeSet<u64>,
+    found: BTreeMap<u64, Vec<PeerId>>,
+    failed: HashSet<PeerId>,
+}
+
+impl ProviderSearch {
+    pub fn new(heights: &[u64]) -> Self {
+        ProviderSearch {
+            pending: heights.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn on_providers(&mut self, height: u64, providers: Vec<PeerId>, last: bool) {
+        if last {
+            self.pending.remove(&height);
+        }
+        let found = self.found.entry(height).or_default();
+        for provider in providers {
+            if !found.contains(&provider) {
+                found.push(provider);
+            }
+        }
+    }
+
+    // A provider that failed to serve is not picked again.
+    pub fn reject(&mut self, provider: &PeerId) {
+        self.failed.insert(*provider);
+    }
+
+    pub fn best(&self) -> Option<(u64, PeerId)> {
+        for (height, providers) in self.found.iter().rev() {
+            if self.pending.range(height + 1..).next().is_some() {
+                return None;
+            }
+            if let Some(provider) = providers.iter().find(|p| !self.failed.contains(*p)) {
+                return Some((*height, *provider));
+            }
+        }
+        None
+    }
+
+    // Every lookup finished and no usable provider is left.
+    pub fn is_exhausted(&self) -> bool {
+        self.pending.is_empty() && self.best().is_none()
+    }
+}
+
+// Snapshot chunks are fetched from the chosen provider over a direct
+// request-response stream rather than gossip. A request is one
+// `GetStateSnapshot` and a response the messages a gossip request would
+// have produced, both in the gossip encoding with a length prefix.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotCodec;
+
+async fn read_message<T: AsyncRead + Unpin + Send>(
+    io: &mut T,
+) -> io::Result<Option<NetworkMessage>> {
+    let mut len = [0u8; 4];
+    match io.read_exact(&mut len).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot message of {} bytes is too large", len),
+        ));
+    }
+    let mut bytes = vec![0u8; len];
+    io.read_exact(&mut bytes).await?;
+    NetworkMessage::from_bytes(&bytes)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<T: AsyncWrite + Unpin + Send>(
+    io: &mut T,
+    msg: &NetworkMessage,
+) -> io::Result<()> {
+    let bytes = msg.to_bytes();
+    io.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    io.write_all(&bytes).await
+}
+
+#[async_trait]
+impl request_response::Codec for SnapshotCodec {
+    type Protocol = StreamProtocol;
+    type Request = NetworkMessage;
+    type Response = Vec<NetworkMessage>;
+
+    async fn read_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<NetworkMessage>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        match read_message(io).await? {
+            Some(msg @ NetworkMessage::GetStateSnapshot { .. }) => Ok(msg),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a snapshot request",
+            )),
+        }
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<Vec<NetworkMessage>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        // One state root answer plus at most one batch of chunks.
+        let mut messages = Vec::new();
+        while let Some(msg) = read_message(io).await? {
+            if messages.len() > MAX_CHUNKS_PER_REQUEST as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many snapshot messages",
+                ));
+            }
+            messages.push(msg);
+        }
+        Ok(messages)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        req: NetworkMessage,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &req).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        res: Vec<NetworkMessage>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        for msg in &res {
+            write_message(io, msg).await?;
+        }
+        io.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_keys_and_candidates() {
+        assert_eq!(
+            snapshot_provider_key(1337, 200),
+            RecordKey::new(&"/budlum/1337/snapshot/200")
+        );
+        assert_ne!(
+            snapshot_provider_key(1337, 200),
+            snapshot_provider_key(1, 200)
+        );
+        assert_eq!(candidate_heights(1_050, 100), vec![1_023, 895, 767, 639]);
+        assert_eq!(candidate_heights(255, 100), vec![255, 127]);
+        assert_eq!(candidate_heights(250, 64), vec![191, 127, 63]);
+        assert!(candidate_heights(126, 100).is_empty());
+        assert!(candidate_heights(1_000, 0).is_empty());
+    }
+
+    #[test]
+    fn test_provider_search_prefers_highest_height() {
+        let (old, new) = (PeerId::random(), PeerId::random());
+        let mut search = ProviderSearch::new(&[1_023, 895, 767]);
+        search.on_providers(767, vec![old], true);
+        assert_eq!(search.best(), None);
+        search.on_providers(1_023, vec![], false);
+        search.on_providers(895, vec![new], false);
+        assert_eq!(search.best(), None);
+        search.on_providers(1_023, vec![], true);
+        assert_eq!(search.best(), Some((895, new)));
+
+        // 895 may still turn up another provider before falling back.
+        search.reject(&new);
+        assert_eq!(search.best(), None);
+        assert!(!search.is_exhausted());
+        search.on_providers(895, vec![], true);
+        assert_eq!(search.best(), Some((767, old)));
+        search.reject(&old);
+        assert!(search.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_codec_round_trip() {
+        use request_response::Codec;
+        let mut codec = SnapshotCodec;
+        let request = NetworkMessage::GetStateSnapshot {
+            height: 255,
+            from_index: 4,
+            target: String::new(),
+        };
+        let mut buf = Vec::new();
+        codec
+            .write_request(&SNAPSHOT_PROTOCOL, &mut buf, request.clone())
+            .await
+            .unwrap();
+        let read = codec
+            .read_request(&SNAPSHOT_PROTOCOL, &mut buf.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(read.to_bytes(), request.to_bytes());
+
+        let chunk = NetworkMessage::SnapshotChunk {
+            height: 255,
+            index: 4,
+            total: 9,
+            data: vec![7; 32],
+            chunk_hash: "h".into(),
+        };
+        let mut buf = Vec::new();
+        codec
+            .write_response(&SNAPSHOT_PROTOCOL, &mut buf, vec![chunk.clone(); 2])
+            .await
+            .unwrap();
+        let read = codec
+            .read_response(&SNAPSHOT_PROTOCOL, &mut buf.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].to_bytes(), chunk.to_bytes());
+
+        let mut buf = Vec::new();
+        let too_many = vec![chunk; MAX_CHUNKS_PER_REQUEST as usize + 2];
+        codec
+            .write_response(&SNAPSHOT_PROTOCOL, &mut buf, too_many)
+            .await
+            .unwrap();
+        assert!(codec
+            .read_response(&SNAPSHOT_PROTOCOL, &mut buf.as_slice())
+            .await
+            .is_err());
+        assert!(codec
+            .read_request(&SNAPSHOT_PROTOCOL, &mut &buf[..])
+            .await
+            .is_err());
+    }
+}
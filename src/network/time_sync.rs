@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+pub const MIN_TIME_SAMPLES: usize = 3;
+pub const MAX_TIME_SAMPLES: usize = 64;
+pub const CLOCK_DRIFT_WARN_MS: i64 = 5_000;
+// Peers can shift the validation clock by at most a third of the window for
+// future block timestamps, so a Sybil majority cannot move it far.
+pub const MAX_CLOCK_ADJUST_MS: i64 = (crate::consensus::MAX_FUTURE_BLOCK_TIME_MS / 3) as i64;
+
+// Offsets between the clocks of handshaked peers and our own, one sample per
+// peer so a single chatty peer cannot drag the median.
+#[derive(Debug, Default)]
+pub struct PeerClock {
+    offsets: HashMap<String, i64>,
+}
+
+impl PeerClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, peer: &str, remote_ms: u64, local_ms: u64) {
+        if remote_ms == 0 {
+            return;
+        }
+        if self.offsets.len() >= MAX_TIME_SAMPLES && !self.offsets.contains_key(peer) {
+            return;
+        }
+        let offset =
+            (remote_ms as i128 - local_ms as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        self.offsets.insert(peer.to_string(), offset);
+    }
+
+    pub fn remove(&mut self, peer: &str) {
+        self.offsets.remove(peer);
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn median_offset(&self) -> Option<i64> {
+        if self.offsets.len() < MIN_TIME_SAMPLES {
+            return None;
+        }
+        let mut offsets: Vec<i64> = self.offsets.values().copied().collect();
+        offsets.sort_unstable();
+        let mid = offsets.len() / 2;
+        if offsets.len().is_multiple_of(2) {
+            // In i128, as two extreme offsets overflow an i64 sum.
+            Some(((offsets[mid - 1] as i128 + offsets[mid] as i128) / 2) as i64)
+        } else {
+            Some(offsets[mid])
+        }
+    }
+
+    pub fn adjustment(&self) -> i64 {
+        self.median_offset()
+            .unwrap_or(0)
+            .clamp(-MAX_CLOCK_ADJUST_MS, MAX_CLOCK_ADJUST_MS)
+    }
+
+    pub fn is_drifting(&self) -> bool {
+        self.median_offset()
+            .is_some_and(|offset| offset.abs() > CLOCK_DRIFT_WARN_MS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median_offset_ignores_outlier() {
+        let mut clock = PeerClock::new();
+        clock.record("a", 10_000, 1_000);
+        clock.record("b", 10_500, 1_000);
+        assert_eq!(clock.median_offset(), None);
+        assert_eq!(clock.adjustment(), 0);
+
+        clock.record("c", 1_000_000_000, 1_000);
+        assert_eq!(clock.median_offset(), Some(9_500));
+        assert!(clock.is_drifting());
+
+        clock.record("c", 1_000, 1_000);
+        clock.record("d", 1_200, 1_000);
+        assert_eq!(clock.median_offset(), Some(4_600));
+        assert!(!clock.is_drifting());
+
+        clock.remove("a");
+        clock.remove("b");
+        assert_eq!(clock.median_offset(), None);
+
+        for peer in ["x", "y", "z"] {
+            clock.record(peer, 10_000_000, 1_000);
+        }
+        assert_eq!(clock.adjustment(), MAX_CLOCK_ADJUST_MS);
+        assert_eq!(MAX_CLOCK_ADJUST_MS, 5_000);
+
+        let mut hostile = PeerClock::new();
+        for peer in ["a", "b", "c", "d"] {
+            hostile.record(peer, u64::MAX, 1);
+        }
+        assert_eq!(hostile.median_offset(), Some(i64::MAX));
+        assert_eq!(hostile.adjustment(), MAX_CLOCK_ADJUST_MS);
+    }
+}
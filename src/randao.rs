@@ -0,0 +1,95 @@
+use crate::crypto::ValidatorKeys;
+use sha3::{Digest, Sha3_256};
+
+pub const RANDAO_ONION_DEPTH: usize = 1 << 16;
+
+pub fn randao_hash(value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"BDLM_RANDAO");
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+pub fn verify_reveal(commitment: &[u8], reveal: &[u8]) -> bool {
+    !commitment.is_empty() && randao_hash(reveal).as_slice() == commitment
+}
+
+// Folds one validator's reveal into the running mix. Every reveal is fixed
+// in advance by the producer's commitment, so the only choice a producer
+// has is whether to publish its block at all.
+pub fn mix_reveal(mix: &[u8; 32], reveal: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(mix);
+    hasher.update(reveal);
+    hasher.finalize().into()
+}
+
+// A validator's chain of reveals. Layer 0 is derived from the VRF secret
+// and each layer is the hash of the one before; the last layer is the
+// commitment registered on the validator. Blocks reveal the layers from the
+// end backwards, so every reveal hashes to the previous one, and a fresh
+// commitment, from the onion of the next generation, is registered with a
+// RandaoCommit transaction before RANDAO_ONION_DEPTH blocks run out.
+pub struct HashOnion {
+    layers: Vec<[u8; 32]>,
+}
+
+impl HashOnion {
+    pub fn new(secret: &[u8], depth: usize) -> Self {
+        let mut layers = Vec::with_capacity(depth.max(1));
+        layers.push(randao_hash(secret));
+        while layers.len() < depth {
+            let next = randao_hash(&layers[layers.len() - 1]);
+            layers.push(next);
+        }
+        HashOnion { layers }
+    }
+
+    pub fn from_keys(keys: &ValidatorKeys, generation: u64, depth: usize) -> Self {
+        let mut secret = keys.vrf_key.secret.to_bytes().to_vec();
+        secret.extend_from_slice(&generation.to_le_bytes());
+        Self::new(&secret, depth)
+    }
+
+    pub fn commitment(&self) -> [u8; 32] {
+        self.layers[self.layers.len() - 1]
+    }
+
+    // The reveal that follows `revealed`, the validator's current
+    // commitment. None once the onion is used up or if `revealed` is not
+    // from this onion.
+    pub fn reveal_after(&self, revealed: &[u8]) -> Option<[u8; 32]> {
+        let position = self.layers.iter().rposition(|l| l.as_slice() == revealed)?;
+        position.checked_sub(1).map(|p| self.layers[p])
+    }
+
+    // How many reveals are left after `revealed`.
+    pub fn remaining(&self, revealed: &[u8]) -> Option<usize> {
+        self.layers.iter().rposition(|l| l.as_slice() == revealed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_onion_reveals_in_order() {
+        let onion = HashOnion::new(b"secret", 4);
+        let mut commitment = onion.commitment().to_vec();
+        for _ in 0..3 {
+            let reveal = onion.reveal_after(&commitment).unwrap();
+            assert!(verify_reveal(&commitment, &reveal));
+            assert!(!verify_reveal(&reveal, &commitment));
+            commitment = reveal.to_vec();
+        }
+        assert_eq!(onion.remaining(&commitment), Some(0));
+        assert!(onion.reveal_after(&commitment).is_none());
+        assert_eq!(onion.remaining(&onion.commitment()), Some(3));
+        assert!(onion.reveal_after(b"foreign").is_none());
+        assert!(!verify_reveal(&[], b"anything"));
+
+        let mix = mix_reveal(&[0u8; 32], b"a");
+        assert_ne!(mix_reveal(&mix, b"b"), mix_reveal(&mix, b"c"));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::account::Account;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceDelta {
+    pub address: String,
+    pub before: u64,
+    pub after: u64,
+    pub delta: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransactionTrace {
+    pub index: usize,
+    pub hash: String,
+    pub from: String,
+    pub to: String,
+    // Why the transaction would be rejected at this point in the block. It is
+    // skipped, so later traces run against the state without it.
+    pub error: Option<String>,
+    pub balance_deltas: Vec<BalanceDelta>,
+}
+
+// A block re-executed against the state before it, one transaction at a
+// time. `block_deltas` holds what the block itself changed after its
+// transactions, such as fee payouts and the treasury cut.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockTrace {
+    pub height: u64,
+    pub hash: String,
+    pub producer: Option<String>,
+    pub transactions: Vec<TransactionTrace>,
+    pub block_deltas: Vec<BalanceDelta>,
+    pub error: Option<String>,
+    pub claimed_state_root: String,
+    pub computed_state_root: Option<String>,
+    pub state_root_matches: bool,
+}
+
+pub fn balance_deltas(
+    before: &HashMap<String, Account>,
+    after: &HashMap<String, Account>,
+) -> Vec<BalanceDelta> {
+    let balance = |accounts: &HashMap<String, Account>, address: &str| {
+        accounts.get(address).map_or(0, |a| a.balance)
+    };
+    let addresses: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let (before, after) = (balance(before, address), balance(after, address));
+            (before != after).then(|| BalanceDelta {
+                address: address.clone(),
+                before,
+                after,
+                delta: after as i128 - before as i128,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,488 @@
+use super::{RpcError, RpcHandler, RpcRequest, RpcResponse, INVALID_REQUEST, PARSE_ERROR};
+use crate::keystore::{KeyType, Keystore, PublicIdentity};
+use crate::network::NodeClient;
+use crate::Blockchain;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tracing::{info, warn};
+
+// Admin API for operators orchestrating validators, served on a unix socket
+// in the data directory instead of the public RPC port. Only the node's user
+// can open the socket, and every request must also carry the token from the
+// token file, so an operator tool reaches a node through the socket (or an
+// SSH forward of it) rather than over the network. Requests are newline
+// separated JSON-RPC objects with an extra `token` field; every one of them,
+// authorized or not, is appended to the audit log.
+
+pub const UNAUTHORIZED: i64 = -32001;
+pub const ADMIN_TOKEN_BYTES: usize = 32;
+const MAX_ADMIN_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct AdminRequest {
+    #[serde(default)]
+    token: String,
+    #[serde(flatten)]
+    request: RpcRequest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub params: Value,
+    pub authorized: bool,
+    pub error: Option<String>,
+}
+
+// Reads the token at `path`, generating one readable only by the node's
+// user if the file does not exist yet.
+pub fn load_or_create_token(path: &Path) -> Result<String, String> {
+    if path.exists() {
+        let token = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read admin token {}: {}", path.display(), e))?;
+        return Ok(token.trim().to_string());
+    }
+    let mut bytes = [0u8; ADMIN_TOKEN_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .map_err(|e| format!("Failed to write admin token {}: {}", path.display(), e))?;
+    Ok(token)
+}
+
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[derive(Clone)]
+pub struct AdminServer {
+    handler: RpcHandler,
+    token: String,
+    audit_log: PathBuf,
+    audit_lock: Arc<Mutex<()>>,
+    node: Option<NodeClient>,
+    keystore: Option<Keystore>,
+}
+
+impl AdminServer {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, token: String, audit_log: PathBuf) -> Self {
+        AdminServer {
+            handler: RpcHandler::new(blockchain).with_admin(true),
+            token,
+            audit_log,
+            audit_lock: Arc::new(Mutex::new(())),
+            node: None,
+            keystore: None,
+        }
+    }
+
+    // Needed for the peer list methods, which act on the running network.
+    pub fn with_node(mut self, node: NodeClient) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    // Needed for `admin_rotate_key`.
+    pub fn with_keystore(mut self, keystore: Keystore) -> Self {
+        self.keystore = Some(keystore);
+        self
+    }
+
+    pub async fn serve(self, path: PathBuf) -> std::io::Result<()> {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        // The socket is bound inside a directory only the node's user can
+        // enter and moved into place once restricted, so no other user can
+        // connect between `bind` and `chmod`.
+        let private_dir = path.with_extension(format!("{}.tmp", std::process::id()));
+        if private_dir.exists() {
+            fs::remove_dir_all(&private_dir)?;
+        }
+        DirBuilder::new().mode(0o700).create(&private_dir)?;
+        let bound = private_dir.join("admin.sock");
+        let listener = UnixListener::bind(&bound).and_then(|listener| {
+            fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&bound, &path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_dir_all(&private_dir);
+        let listener = listener?;
+        info!("Admin API listening on {}", path.display());
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    warn!("Admin connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: tokio::net::UnixStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = tokio::io::BufReader::new(reader);
+        let mut buf = Vec::new();
+        while let Some(fits) =
+            read_line_bounded(&mut reader, &mut buf, MAX_ADMIN_LINE_BYTES).await?
+        {
+            let response = match std::str::from_utf8(&buf).map(str::trim) {
+                _ if !fits => RpcResponse::from_result(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, "request too large")),
+                ),
+                Ok("") => continue,
+                Ok(line) => self.handle_line(line).await,
+                Err(_) => RpcResponse::from_result(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, "request is not valid UTF-8")),
+                ),
+            };
+            let mut payload = serde_json::to_vec(&response).unwrap_or_default();
+            payload.push(b'\n');
+            writer.write_all(&payload).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_line(&self, line: &str) -> RpcResponse {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(e) => {
+                return RpcResponse::from_result(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                )
+            }
+        };
+        let AdminRequest { token, request } = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                return RpcResponse::from_result(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                )
+            }
+        };
+        let authorized = tokens_match(&token, &self.token);
+        let result = if authorized {
+            self.dispatch(&request).await
+        } else {
+            Err(RpcError::new(UNAUTHORIZED, "invalid admin token"))
+        };
+        self.audit(AuditRecord {
+            timestamp_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            method: request.method.clone(),
+            params: request.params.clone(),
+            authorized,
+            error: result.as_ref().err().map(|e| e.message.clone()),
+        });
+        RpcResponse::from_result(request.id, result)
+    }
+
+    async fn dispatch(&self, request: &RpcRequest) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "admin_add_peer" | "admin_remove_peer" => {
+                let addr = match &request.params {
+                    Value::Array(items) => items.first(),
+                    Value::Object(map) => map.get("addr"),
+                    _ => None,
+                }
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::invalid_params("missing addr"))?
+                .to_string();
+                let node = self.node.as_ref().ok_or_else(|| {
+                    RpcError::new(super::SERVER_ERROR, "networking is not running")
+                })?;
+                if request.method == "admin_add_peer" {
+                    node.add_peer(addr).await;
+                } else {
+                    node.remove_peer(addr).await;
+                }
+                Ok(json!(true))
+            }
+            "admin_rotate_key" => {
+                let key_type = match &request.params {
+                    Value::Array(items) => items.first(),
+                    Value::Object(map) => map.get("key_type"),
+                    _ => None,
+                }
+                .and_then(Value::as_str)
+                .unwrap_or(KeyType::Ed25519.name());
+                let key_type = KeyType::ALL
+                    .iter()
+                    .copied()
+                    .find(|k| k.name() == key_type)
+                    .ok_or_else(|| {
+                        RpcError::invalid_params(format!("unknown key type {}", key_type))
+                    })?;
+                let keystore = self.keystore.as_ref().ok_or_else(|| {
+                    RpcError::new(super::SERVER_ERROR, "keystore is not available")
+                })?;
+                let (previous, current) = keystore
+                    .rotate(key_type)
+                    .map_err(|e| RpcError::new(super::SERVER_ERROR, e))?;
+                let identity = |id: &PublicIdentity| json!({"hex": id.hex, "bech32": id.bech32});
+                Ok(json!({
+                    "key_type": key_type.name(),
+                    "previous": previous.as_ref().map(identity),
+                    "current": identity(&current),
+                    "restart_required": true,
+                }))
+            }
+            _ => {
+                let response = self.handler.handle(request.clone());
+                match response.error {
+                    Some(e) => Err(e),
+                    None => Ok(response.result.unwrap_or(Value::Null)),
+                }
+            }
+        }
+    }
+
+    fn audit(&self, record: AuditRecord) {
+        info!(
+            "Admin request {} (authorized={}, error={:?})",
+            record.method, record.authorized, record.error
+        );
+        let _guard = self.audit_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let line = serde_json::to_string(&record).unwrap_or_default();
+        let written = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(&self.audit_log)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = written {
+            warn!(
+                "Failed to write admin audit log {}: {}",
+                self.audit_log.display(),
+                e
+            );
+        }
+    }
+}
+
+// Sends one request to a node's admin socket and returns its result.
+pub fn call(socket: &Path, token: &str, method: &str, params: Value) -> Result<Value, String> {
+    let mut stream = UnixStream::connect(socket)
+        .map_err(|e| format!("Failed to connect to {}: {}", socket.display(), e))?;
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "token": token,
+        "method": method,
+        "params": params,
+    });
+    writeln!(stream, "{}", request).map_err(|e| format!("Failed to send request: {}", e))?;
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    let response: RpcResponse =
+        serde_json::from_str(&line).map_err(|e| format!("Invalid response: {}", e))?;
+    match response.error {
+        Some(e) => Err(format!("{} ({})", e.message, e.code)),
+        None => Ok(response.result.unwrap_or(Value::Null)),
+    }
+}
+
+// Reads one line into `buf` without holding more than `max` bytes of it.
+// A longer line is skipped up to its newline and reported as `Some(false)`;
+// `None` means the peer closed the connection.
+async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> std::io::Result<Option<bool>> {
+    buf.clear();
+    if (&mut *reader)
+        .take(max as u64 + 1)
+        .read_until(b'\n', buf)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+        return Ok(Some(true));
+    }
+    if buf.len() <= max {
+        return Ok(Some(true));
+    }
+    loop {
+        let chunk = reader.fill_buf().await?;
+        if chunk.is_empty() {
+            break;
+        }
+        match chunk.iter().position(|b| *b == b'\n') {
+            Some(i) => {
+                reader.consume(i + 1);
+                break;
+            }
+            None => {
+                let n = chunk.len();
+                reader.consume(n);
+            }
+        }
+    }
+    Ok(Some(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::PoWEngine;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("budlum_{}_{}", name, nanos))
+    }
+
+    fn audit_records(path: &Path) -> Vec<AuditRecord> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_admin_requests_are_authenticated_and_audited() {
+        let token_path = temp_path("admin_token");
+        let token = load_or_create_token(&token_path).unwrap();
+        assert_eq!(token.len(), ADMIN_TOKEN_BYTES * 2);
+        assert_eq!(load_or_create_token(&token_path).unwrap(), token);
+        let mode = fs::metadata(&token_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let bc = Arc::new(Mutex::new(Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            None,
+            1337,
+            None,
+        )));
+        let audit_log = temp_path("admin_audit");
+        let server = AdminServer::new(bc.clone(), token.clone(), audit_log.clone());
+
+        let forged = json!({"token": "00", "id": 1, "method": "admin_pause_production"});
+        let response = server.handle_line(&forged.to_string()).await;
+        assert_eq!(response.error.unwrap().code, UNAUTHORIZED);
+        assert!(!bc.lock().unwrap().is_production_paused());
+
+        let pause = json!({"token": token, "id": 2, "method": "admin_pause_production"});
+        let response = server.handle_line(&pause.to_string()).await;
+        assert_eq!(response.result, Some(json!(true)));
+        assert!(bc.lock().unwrap().is_production_paused());
+
+        let add_peer = json!({"token": token, "id": 3, "method": "admin_add_peer", "params": ["/ip4/10.0.0.2/tcp/4001"]});
+        let response = server.handle_line(&add_peer.to_string()).await;
+        assert!(response.error.is_some());
+
+        let keys_dir = temp_path("admin_keys");
+        let server = server.with_keystore(Keystore::new(&keys_dir));
+        let rotate =
+            json!({"token": token, "id": 4, "method": "admin_rotate_key", "params": ["bls"]});
+        let first = server
+            .handle_line(&rotate.to_string())
+            .await
+            .result
+            .unwrap();
+        assert_eq!(first["previous"], Value::Null);
+        let second = server
+            .handle_line(&rotate.to_string())
+            .await
+            .result
+            .unwrap();
+        assert_eq!(second["previous"], first["current"]);
+        assert_ne!(second["current"], first["current"]);
+        let bad_type = json!({"token": token, "id": 5, "method": "admin_rotate_key", "params": {"key_type": "rsa"}});
+        let response = server.handle_line(&bad_type.to_string()).await;
+        assert!(response.error.is_some());
+
+        let records = audit_records(&audit_log);
+        assert_eq!(records.len(), 6);
+        assert_eq!(
+            (records[0].authorized, records[0].method.as_str()),
+            (false, "admin_pause_production")
+        );
+        assert!(records[1].authorized && records[1].error.is_none());
+        assert_eq!(records[2].params, json!(["/ip4/10.0.0.2/tcp/4001"]));
+        assert!(records[2].error.is_some());
+
+        let _ = fs::remove_file(&token_path);
+        let _ = fs::remove_file(&audit_log);
+        let _ = fs::remove_dir_all(&keys_dir);
+    }
+
+    #[tokio::test]
+    async fn test_admin_socket_is_owner_only() {
+        let dir = temp_path("admin_socket");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("admin.sock");
+        let bc = Arc::new(Mutex::new(Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            None,
+            1337,
+            None,
+        )));
+        let server = AdminServer::new(bc, "token".into(), dir.join("audit.log"));
+        let handle = tokio::spawn(server.serve(path.clone()));
+        while !path.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        handle.abort();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_read_line_bounded_skips_long_lines() {
+        let input = format!("{}\n{{}}\r\n{}\ntail", "x".repeat(20), "y".repeat(8));
+        let mut reader = input.as_bytes();
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        while let Some(fits) = read_line_bounded(&mut reader, &mut buf, 8).await.unwrap() {
+            assert!(buf.len() <= 9);
+            lines.push((fits, String::from_utf8(buf.clone()).unwrap()));
+        }
+        assert_eq!(
+            lines,
+            vec![
+                (false, "x".repeat(9)),
+                (true, "{}\r".to_string()),
+                (true, "y".repeat(8)),
+                (true, "tail".to_string()),
+            ]
+        );
+    }
+}
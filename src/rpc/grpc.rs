@@ -0,0 +1,237 @@
+use super::http::{MAX_REQUEST_BYTES, READ_TIMEOUT};
+use crate::events::ChainEvent;
+use crate::network::proto_conversions::pb::{ProtoBlock, ProtoTransaction};
+use crate::{Blockchain, Transaction};
+use futures::{Stream, StreamExt};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+pub mod pb {
+    tonic::include_proto!("budlum.rpc");
+}
+
+use pb::get_block_request::Selector;
+use pb::node_rpc_server::{NodeRpc, NodeRpcServer};
+
+pub type BlockStream = Pin<Box<dyn Stream<Item = Result<ProtoBlock, Status>> + Send>>;
+
+const BACKFILL_PAGE_BLOCKS: u64 = 64;
+
+#[derive(Clone)]
+pub struct GrpcService {
+    blockchain: Arc<Mutex<Blockchain>>,
+}
+
+impl GrpcService {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>) -> Self {
+        GrpcService { blockchain }
+    }
+
+    pub async fn serve(self, bind: IpAddr, port: u16) -> Result<(), tonic::transport::Error> {
+        let addr = SocketAddr::new(bind, port);
+        info!("gRPC listening on {}", addr);
+        tonic::transport::Server::builder()
+            .timeout(READ_TIMEOUT)
+            .add_service(NodeRpcServer::new(self).max_decoding_message_size(MAX_REQUEST_BYTES))
+            .serve(addr)
+            .await
+    }
+
+    fn chain(&self) -> Result<std::sync::MutexGuard<'_, Blockchain>, Status> {
+        self.blockchain
+            .lock()
+            .map_err(|e| Status::internal(format!("blockchain lock poisoned: {}", e)))
+    }
+}
+
+#[tonic::async_trait]
+impl NodeRpc for GrpcService {
+    async fn get_block(
+        &self,
+        request: Request<pb::GetBlockRequest>,
+    ) -> Result<Response<ProtoBlock>, Status> {
+        let chain = self.chain()?;
+        let block = match request.into_inner().selector {
+            Some(Selector::Height(height)) => chain.get_block_by_height(height),
+            Some(Selector::Hash(hash)) => chain.get_block_by_hash(&hash),
+            None => return Err(Status::invalid_argument("height or hash is required")),
+        };
+        block
+            .map(|block| Response::new(ProtoBlock::from(&block)))
+            .ok_or_else(|| Status::not_found("unknown block"))
+    }
+
+    async fn submit_transaction(
+        &self,
+        request: Request<pb::SubmitTransactionRequest>,
+    ) -> Result<Response<pb::SubmitTransactionResponse>, Status> {
+        let proto: ProtoTransaction = request
+            .into_inner()
+            .transaction
+            .ok_or_else(|| Status::invalid_argument("transaction is required"))?;
+        let tx = Transaction::try_from(proto).map_err(Status::invalid_argument)?;
+        let hash = tx.hash.clone();
+        self.chain()?
+            .submit_local_transaction(tx)
+            .map_err(Status::failed_precondition)?;
+        Ok(Response::new(pb::SubmitTransactionResponse { hash }))
+    }
+
+    type StreamBlocksStream = BlockStream;
+
+    async fn stream_blocks(
+        &self,
+        request: Request<pb::StreamBlocksRequest>,
+    ) -> Result<Response<Self::StreamBlocksStream>, Status> {
+        let from_height = request.into_inner().from_height;
+        let (tip, events) = {
+            let chain = self.chain()?;
+            (chain.last_block().index, chain.subscribe_events())
+        };
+        // Blocks up to the tip are read a page at a time, so a backfill from
+        // genesis neither holds the chain lock nor sits in memory at once.
+        let backfill = futures::stream::unfold(
+            (self.blockchain.clone(), from_height),
+            move |(blockchain, next)| async move {
+                if next > tip {
+                    return None;
+                }
+                let end = tip.min(next + BACKFILL_PAGE_BLOCKS - 1);
+                let page: Vec<Result<ProtoBlock, Status>> = {
+                    let chain = blockchain.lock().unwrap_or_else(|e| e.into_inner());
+                    (next..=end)
+                        .filter_map(|height| chain.get_block_by_height(height))
+                        .map(|block| Ok(ProtoBlock::from(&block)))
+                        .collect()
+                };
+                Some((futures::stream::iter(page), (blockchain, end + 1)))
+            },
+        )
+        .flatten();
+        let next_height = from_height.max(tip + 1);
+        let live = futures::stream::unfold(
+            (events, self.blockchain.clone(), next_height),
+            |(mut events, blockchain, next_height)| async move {
+                loop {
+                    let hash = match events.recv().await {
+                        Ok(ChainEvent::BlockAdded { height, hash }) if height >= next_height => {
+                            hash
+                        }
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("gRPC block stream lagged, skipped {} chain events", skipped);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    };
+                    let block = blockchain
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get_block_by_hash(&hash);
+                    if let Some(block) = block {
+                        let next = block.index + 1;
+                        return Some((
+                            Ok(ProtoBlock::from(&block)),
+                            (events, blockchain, next_height.max(next)),
+                        ));
+                    }
+                }
+            },
+        );
+        let stream = backfill.chain(live);
+        Ok(Response::new(Box::pin(stream) as Self::StreamBlocksStream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::PoWEngine;
+    use crate::crypto::KeyPair;
+
+    #[tokio::test]
+    async fn test_grpc_get_submit_and_stream() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        bc.produce_block("miner".into());
+        let kp = KeyPair::generate().unwrap();
+        bc.init_genesis_account(&kp.public_key_hex());
+        let blockchain = Arc::new(Mutex::new(bc));
+        let service = GrpcService::new(blockchain.clone());
+
+        let by_height = service
+            .get_block(Request::new(pb::GetBlockRequest {
+                selector: Some(Selector::Height(1)),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let by_hash = service
+            .get_block(Request::new(pb::GetBlockRequest {
+                selector: Some(Selector::Hash(by_height.hash.clone())),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(by_height, by_hash);
+        let missing = service
+            .get_block(Request::new(pb::GetBlockRequest {
+                selector: Some(Selector::Height(99)),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+
+        let mut tx = Transaction::new_with_fee(kp.public_key_hex(), "bob".into(), 5, 1, 0, vec![]);
+        tx.sign(&kp);
+        let submitted = service
+            .submit_transaction(Request::new(pb::SubmitTransactionRequest {
+                transaction: Some(ProtoTransaction::from(&tx)),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(submitted.hash, tx.hash);
+        let rejected = service
+            .submit_transaction(Request::new(pb::SubmitTransactionRequest {
+                transaction: None,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(rejected.code(), tonic::Code::InvalidArgument);
+
+        let mut stream = service
+            .stream_blocks(Request::new(pb::StreamBlocksRequest { from_height: 1 }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stream.next().await.unwrap().unwrap().index, 1);
+        blockchain.lock().unwrap().produce_block("miner".into());
+        let live = stream.next().await.unwrap().unwrap();
+        assert_eq!(live.index, 2);
+        assert_eq!(live.transactions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_stream_backfills_in_pages() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        while bc.last_block().index < BACKFILL_PAGE_BLOCKS + 5 {
+            bc.produce_block("miner".into());
+        }
+        let service = GrpcService::new(Arc::new(Mutex::new(bc)));
+        let stream = service
+            .stream_blocks(Request::new(pb::StreamBlocksRequest { from_height: 2 }))
+            .await
+            .unwrap()
+            .into_inner();
+        let heights: Vec<u64> = stream
+            .take(BACKFILL_PAGE_BLOCKS as usize + 4)
+            .map(|block| block.unwrap().index)
+            .collect()
+            .await;
+        assert_eq!(heights, (2..=BACKFILL_PAGE_BLOCKS + 5).collect::<Vec<_>>());
+    }
+}
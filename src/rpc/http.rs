@@ -0,0 +1,257 @@
+use super::{
+    RpcError, RpcHandler, RpcRequest, RpcResponse, INVALID_REQUEST, PARSE_ERROR, RATE_LIMITED,
+};
+use crate::Blockchain;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+pub const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+// A client has this long to send its whole request, so slow senders cannot
+// hold connections open.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADER_LINES: usize = 64;
+const MAX_HEADER_LINE_BYTES: u64 = 8 * 1024;
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Request counts per client address for the current one-second window.
+// Expired windows are swept at most once per window, so the map only holds
+// addresses seen in the last two seconds however many clients come and go.
+struct RateWindows {
+    counts: HashMap<IpAddr, (Instant, u32)>,
+    swept_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct RpcServer {
+    handler: RpcHandler,
+    rate_limit: Arc<AtomicU32>,
+    windows: Arc<Mutex<RateWindows>>,
+}
+
+impl RpcServer {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, rate_limit_per_sec: u32) -> Self {
+        RpcServer {
+            handler: RpcHandler::new(blockchain),
+            rate_limit: Arc::new(AtomicU32::new(rate_limit_per_sec)),
+            windows: Arc::new(Mutex::new(RateWindows {
+                counts: HashMap::new(),
+                swept_at: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn watch_service(&self) -> super::WatchService {
+        self.handler.watch_service()
+    }
+
+    pub fn rate_limit_handle(&self) -> Arc<AtomicU32> {
+        self.rate_limit.clone()
+    }
+
+    pub async fn serve(self, bind: IpAddr, port: u16) -> std::io::Result<()> {
+        let listener = TcpListener::bind((bind, port)).await?;
+        info!("RPC listening on {}:{}", bind, port);
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream, addr.ip()).await {
+                    warn!("RPC connection from {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    fn allow(&self, ip: IpAddr) -> bool {
+        self.allow_at(ip, Instant::now())
+    }
+
+    fn allow_at(&self, ip: IpAddr, now: Instant) -> bool {
+        let limit = self.rate_limit.load(Ordering::Relaxed);
+        if limit == 0 {
+            return true;
+        }
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(windows.swept_at) >= RATE_WINDOW {
+            windows
+                .counts
+                .retain(|_, (start, _)| now.duration_since(*start) < RATE_WINDOW);
+            windows.swept_at = now;
+        }
+        let entry = windows.counts.entry(ip).or_insert((now, 0));
+        if now.duration_since(entry.0) >= RATE_WINDOW {
+            *entry = (now, 0);
+        }
+        entry.1 += 1;
+        entry.1 <= limit
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, ip: IpAddr) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let body = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader)).await {
+            Ok(Ok(Ok(body))) => body,
+            Ok(Ok(Err(status))) => return write_http(reader.get_mut(), status, b"").await,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return write_http(reader.get_mut(), "408 Request Timeout", b"").await,
+        };
+
+        let response = if self.allow(ip) {
+            self.handle_body(&body)
+        } else {
+            RpcResponse::from_result(
+                Value::Null,
+                Err(RpcError::new(RATE_LIMITED, "rate limit exceeded")),
+            )
+        };
+        let payload = serde_json::to_vec(&response).unwrap_or_default();
+        write_http(reader.get_mut(), "200 OK", &payload).await
+    }
+
+    fn handle_body(&self, body: &[u8]) -> RpcResponse {
+        let value: Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => {
+                return RpcResponse::from_result(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                )
+            }
+        };
+        match serde_json::from_value::<RpcRequest>(value) {
+            Ok(request) => self.handler.handle(request),
+            Err(e) => RpcResponse::from_result(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+            ),
+        }
+    }
+}
+
+// Reads one POST request and returns its body, or the status to answer
+// with when the request is not acceptable.
+async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Result<Vec<u8>, &'static str>> {
+    let mut content_length = 0usize;
+    let mut line = String::new();
+    if !read_header_line(reader, &mut line).await? {
+        return Ok(Err("431 Request Header Fields Too Large"));
+    }
+    if !line.starts_with("POST ") {
+        return Ok(Err("405 Method Not Allowed"));
+    }
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if !read_header_line(reader, &mut line).await? {
+            return Ok(Err("431 Request Header Fields Too Large"));
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_REQUEST_BYTES {
+        return Ok(Err("413 Payload Too Large"));
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok(Ok(body))
+}
+
+// False when the line does not end within MAX_HEADER_LINE_BYTES.
+async fn read_header_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+) -> std::io::Result<bool> {
+    let read = reader.take(MAX_HEADER_LINE_BYTES).read_line(line).await?;
+    Ok(read == 0 || line.ends_with('\n'))
+}
+
+async fn write_http(stream: &mut TcpStream, status: &str, body: &[u8]) -> std::io::Result<()> {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(raw: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let mut reader = BufReader::new(raw);
+        read_request(&mut reader).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_request_bounds() {
+        let body = read(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}").await;
+        assert_eq!(body.unwrap(), b"{}");
+        assert_eq!(
+            read(b"GET / HTTP/1.1\r\n\r\n").await,
+            Err("405 Method Not Allowed")
+        );
+
+        let long_header = format!(
+            "POST / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_HEADER_LINE_BYTES as usize)
+        );
+        assert_eq!(
+            read(long_header.as_bytes()).await,
+            Err("431 Request Header Fields Too Large")
+        );
+        let endless = vec![b'P'; 2 * MAX_HEADER_LINE_BYTES as usize];
+        assert_eq!(
+            read(&endless).await,
+            Err("431 Request Header Fields Too Large")
+        );
+        let oversized = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_REQUEST_BYTES + 1
+        );
+        assert_eq!(
+            read(oversized.as_bytes()).await,
+            Err("413 Payload Too Large")
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_windows_are_evicted() {
+        let chain = Blockchain::new(
+            Arc::new(crate::consensus::DevEngine::default()),
+            None,
+            1337,
+            None,
+        );
+        let server = RpcServer::new(Arc::new(Mutex::new(chain)), 2);
+        let start = Instant::now();
+        let ip = |i: u32| IpAddr::from(std::net::Ipv4Addr::from(i));
+
+        assert!(server.allow_at(ip(1), start));
+        assert!(server.allow_at(ip(1), start));
+        assert!(!server.allow_at(ip(1), start));
+        for i in 2..1000 {
+            assert!(server.allow_at(ip(i), start));
+        }
+        assert_eq!(server.windows.lock().unwrap().counts.len(), 999);
+
+        let later = start + RATE_WINDOW;
+        assert!(server.allow_at(ip(1), later));
+        assert_eq!(server.windows.lock().unwrap().counts.len(), 1);
+    }
+}
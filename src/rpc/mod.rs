@@ -0,0 +1,761 @@
+pub mod admin;
+pub mod grpc;
+mod http;
+pub mod watch;
+pub mod ws;
+
+use crate::account::MIN_TX_FEE;
+use crate::analytics::ANALYTICS_WINDOW;
+use crate::json::{u128_string, BlockJson, ReceiptJson, TransactionJson};
+use crate::{Block, Blockchain, Transaction};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+pub use admin::AdminServer;
+pub use grpc::GrpcService;
+pub use http::RpcServer;
+pub use watch::WatchService;
+
+pub const DEFAULT_PAGE_SIZE: u64 = 20;
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_ERROR: i64 = -32000;
+pub const RATE_LIMITED: i64 = -32005;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcRequest {
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn from_result(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(e) => (None, Some(e)),
+        };
+        RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+struct Params<'a>(&'a Value);
+
+impl Params<'_> {
+    fn get<T: DeserializeOwned>(&self, index: usize, name: &str) -> Result<Option<T>, RpcError> {
+        let value = match self.0 {
+            Value::Array(items) => items.get(index),
+            Value::Object(map) => map.get(name),
+            Value::Null => None,
+            _ => {
+                return Err(RpcError::invalid_params(
+                    "params must be an array or object",
+                ))
+            }
+        };
+        match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => serde_json::from_value(v.clone())
+                .map(Some)
+                .map_err(|e| RpcError::invalid_params(format!("invalid {}: {}", name, e))),
+        }
+    }
+
+    fn require<T: DeserializeOwned>(&self, index: usize, name: &str) -> Result<T, RpcError> {
+        self.get(index, name)?
+            .ok_or_else(|| RpcError::invalid_params(format!("missing {}", name)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSummary {
+    pub index: u64,
+    pub hash: String,
+    pub previous_hash: String,
+    #[serde(with = "u128_string")]
+    pub timestamp: u128,
+    pub producer: Option<String>,
+    pub state_root: String,
+    pub tx_count: usize,
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        BlockSummary {
+            index: block.index,
+            hash: block.hash.clone(),
+            previous_hash: block.previous_hash.clone(),
+            timestamp: block.timestamp,
+            producer: block.producer.clone(),
+            state_root: block.state_root.clone(),
+            tx_count: block.transactions.len(),
+        }
+    }
+}
+
+// Fees per fee unit; a batch pays them once per output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub min_fee: u64,
+    pub suggested_fee: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub page_size: u64,
+    pub total: u64,
+}
+
+fn page_size(params: &Params, index: usize) -> Result<u64, RpcError> {
+    let size = params
+        .get::<u64>(index, "page_size")?
+        .unwrap_or(DEFAULT_PAGE_SIZE);
+    if size == 0 || size > MAX_PAGE_SIZE {
+        return Err(RpcError::invalid_params(format!(
+            "page_size must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    Ok(size)
+}
+
+fn stats_window(params: &Params) -> Result<usize, RpcError> {
+    let blocks = params
+        .get::<usize>(0, "blocks")?
+        .unwrap_or(DEFAULT_PAGE_SIZE as usize);
+    if blocks == 0 || blocks > ANALYTICS_WINDOW {
+        return Err(RpcError::invalid_params(format!(
+            "blocks must be between 1 and {}",
+            ANALYTICS_WINDOW
+        )));
+    }
+    Ok(blocks)
+}
+
+#[derive(Clone)]
+pub struct RpcHandler {
+    blockchain: Arc<Mutex<Blockchain>>,
+    watch: WatchService,
+    admin: bool,
+}
+
+impl RpcHandler {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>) -> Self {
+        RpcHandler {
+            blockchain,
+            watch: WatchService::new(),
+            admin: false,
+        }
+    }
+
+    // `admin_*` methods change how the node runs and `debug_*` methods can
+    // replay large parts of the chain, so both are only served by the
+    // token-authenticated admin socket.
+    pub(crate) fn with_admin(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
+    }
+
+    pub fn watch_service(&self) -> WatchService {
+        self.watch.clone()
+    }
+
+    pub fn handle(&self, request: RpcRequest) -> RpcResponse {
+        let result = self.dispatch(&request.method, Params(&request.params));
+        RpcResponse::from_result(request.id, result)
+    }
+
+    fn dispatch(&self, method: &str, params: Params) -> Result<Value, RpcError> {
+        if (method.starts_with("admin_") || method.starts_with("debug_")) && !self.admin {
+            return Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("admin methods are disabled: {}", method),
+            ));
+        }
+        match method {
+            "get_blocks" => self.get_blocks(params),
+            "get_block" => {
+                let height: u64 = params.require(0, "height")?;
+                let block = self.chain()?.get_block_by_height(height);
+                Ok(json!(block.as_ref().map(BlockJson::from)))
+            }
+            "get_transaction_receipt" => {
+                let hash: String = params.require(0, "hash")?;
+                let chain = self.chain()?;
+                let receipt = chain
+                    .find_transaction(&hash)
+                    .and_then(|(block, index)| ReceiptJson::new(&block, index));
+                Ok(json!(receipt))
+            }
+            "get_transaction_confirmation" => {
+                let hash: String = params.require(0, "hash")?;
+                Ok(json!(self.chain()?.tx_confirmation(&hash)))
+            }
+            "get_block_transactions" => self.get_block_transactions(params),
+            "send_transaction" => {
+                let json: TransactionJson = params.require(0, "transaction")?;
+                let tx = Transaction::try_from(&json).map_err(RpcError::invalid_params)?;
+                let hash = tx.hash.clone();
+                self.chain()?
+                    .submit_local_transaction(tx)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+                Ok(json!(hash))
+            }
+            "get_fee_estimate" => {
+                let chain = self.chain()?;
+                let min_fee = chain.mempool.config().min_fee.max(MIN_TX_FEE);
+                let median = chain
+                    .analytics()
+                    .summary(DEFAULT_PAGE_SIZE as usize)
+                    .map_or(0, |stats| stats.fees.p50);
+                Ok(json!(FeeEstimate {
+                    min_fee,
+                    suggested_fee: median.max(min_fee),
+                }))
+            }
+            "get_next_nonce" => {
+                let address: String = params.require(0, "address")?;
+                Ok(json!(self.chain()?.get_next_nonce(&address)))
+            }
+            "get_balance_at" => {
+                let address: String = params.require(0, "address")?;
+                let height: u64 = params.require(1, "height")?;
+                let balance = self
+                    .chain()?
+                    .balance_at(&address, height)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+                Ok(json!(balance))
+            }
+            "get_pending_transactions" => {
+                let address: String = params.require(0, "address")?;
+                let pending: Vec<TransactionJson> = self
+                    .chain()?
+                    .mempool
+                    .pending_for_sender(&address)
+                    .iter()
+                    .map(TransactionJson::from)
+                    .collect();
+                Ok(json!(pending))
+            }
+            "get_mempool_stats" => {
+                let chain = self.chain()?;
+                Ok(json!({
+                    "size": chain.mempool.len(),
+                    "priority": chain.mempool.priority_stats(),
+                }))
+            }
+            "txpool_status" => {
+                let chain = self.chain()?;
+                let queues = chain.mempool.queues(|sender| chain.state.get_nonce(sender));
+                let (ready, future) = queues.values().fold((0, 0), |(ready, future), q| {
+                    (ready + q.ready.len(), future + q.future.len())
+                });
+                let (min, max) = chain.mempool.fee_range().unwrap_or_default();
+                Ok(json!({
+                    "size": chain.mempool.len(),
+                    "senders": queues.len(),
+                    "ready": ready,
+                    "future": future,
+                    "fees": {
+                        "min": min,
+                        "max": max,
+                        "percentiles": chain.mempool.fee_distribution(),
+                    },
+                    "evictions": chain.mempool.eviction_stats(),
+                }))
+            }
+            "txpool_content" => {
+                let address: Option<String> = params.get(0, "address")?;
+                let chain = self.chain()?;
+                let (mut ready, mut future) = (serde_json::Map::new(), serde_json::Map::new());
+                for (sender, queues) in chain.mempool.queues(|sender| chain.state.get_nonce(sender))
+                {
+                    if address.as_ref().is_some_and(|a| *a != sender) {
+                        continue;
+                    }
+                    let encode = |txs: &[Transaction]| {
+                        json!(txs.iter().map(TransactionJson::from).collect::<Vec<_>>())
+                    };
+                    if !queues.ready.is_empty() {
+                        ready.insert(sender.clone(), encode(&queues.ready));
+                    }
+                    if !queues.future.is_empty() {
+                        future.insert(sender, encode(&queues.future));
+                    }
+                }
+                Ok(json!({"ready": ready, "future": future}))
+            }
+            "get_block_stats" => {
+                let blocks = stats_window(&params)?;
+                Ok(json!(self.chain()?.analytics().recent(blocks)))
+            }
+            "get_chain_stats" => {
+                let blocks = stats_window(&params)?;
+                Ok(json!(self.chain()?.analytics().summary(blocks)))
+            }
+            "get_total_supply" => {
+                let chain = self.chain()?;
+                Ok(json!({
+                    "minted": chain.state.total_minted,
+                    "burned": chain.state.total_burned,
+                    "total_supply": chain.state.total_supply(),
+                }))
+            }
+            "get_light_client" => {
+                let chain_id: u64 = params.require(0, "chain_id")?;
+                Ok(json!(self.chain()?.state.light_clients.get(&chain_id)))
+            }
+            "get_dust_proof" => {
+                let address: String = params.require(0, "address")?;
+                Ok(json!(self.chain()?.dust_proof(&address)))
+            }
+            "get_validator_info" => {
+                let address: String = params.require(0, "address")?;
+                Ok(json!(self.chain()?.state.validator_info(&address)))
+            }
+            "get_randomness" => {
+                let chain = self.chain()?;
+                Ok(json!({
+                    "epoch": chain.state.epoch_index,
+                    "seed": hex::encode(chain.state.randao_seed),
+                    "mix": hex::encode(chain.state.randao_mix),
+                }))
+            }
+            "get_fork_id" => {
+                let chain = self.chain()?;
+                let height = chain.chain.len().saturating_sub(1) as u64;
+                Ok(json!({
+                    "fork_id": chain.fork_id(),
+                    "next_fork": chain.forks().next_fork(height),
+                    "tx_signing_version": chain.forks().tx_signing_version(height),
+                }))
+            }
+            "get_chain_health" => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                Ok(json!(self.chain()?.health(now)))
+            }
+            "get_finality_status" => Ok(json!(self.chain()?.finality_status())),
+            "get_sync_status" => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                Ok(json!(self.chain()?.sync.report(now)))
+            }
+            "peer_versions" => {
+                let chain = self.chain()?;
+                Ok(json!({
+                    "local": {
+                        "version": crate::encoding::NODE_VERSION,
+                        "commit": crate::encoding::NODE_COMMIT,
+                    },
+                    "peers": chain.peer_versions.peers(),
+                    "counts": chain.peer_versions.counts(),
+                }))
+            }
+            "watch_address" => {
+                let address: String = params.require(0, "address")?;
+                let added = self
+                    .watch
+                    .watch(address)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+                Ok(json!(added))
+            }
+            "unwatch_address" => {
+                let address: String = params.require(0, "address")?;
+                Ok(json!(self.watch.unwatch(&address)))
+            }
+            "get_watched_addresses" => Ok(json!(self.watch.list())),
+            "debug_replay_block" => {
+                let height: u64 = params.require(0, "height")?;
+                // Only copying the inputs happens under the chain lock.
+                let job = self
+                    .chain()?
+                    .replay_job(height)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+                let trace = job.run().map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+                Ok(json!(trace))
+            }
+            "debug_state_divergence" => Ok(json!(self.chain()?.last_divergence())),
+            "admin_pause_production" => Ok(json!(self.chain()?.pause_production())),
+            "admin_resume_production" => Ok(json!(self.chain()?.resume_production())),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("method not found: {}", method),
+            )),
+        }
+    }
+
+    fn chain(&self) -> Result<std::sync::MutexGuard<'_, Blockchain>, RpcError> {
+        self.blockchain
+            .lock()
+            .map_err(|e| RpcError::new(SERVER_ERROR, format!("blockchain lock poisoned: {}", e)))
+    }
+
+    fn get_blocks(&self, params: Params) -> Result<Value, RpcError> {
+        let page = params.get::<u64>(0, "page")?.unwrap_or(0);
+        let page_size = page_size(&params, 1)?;
+        let reverse = params.get::<bool>(2, "reverse")?.unwrap_or(false);
+
+        let chain = self.chain()?;
+        let total = chain.last_block().index + 1;
+        let start = page.saturating_mul(page_size);
+        let end = start.saturating_add(page_size).min(total);
+        let mut items = Vec::new();
+        for offset in start..end {
+            let height = if reverse { total - 1 - offset } else { offset };
+            let block = chain.get_block_by_height(height).ok_or_else(|| {
+                RpcError::new(SERVER_ERROR, format!("block {} missing from index", height))
+            })?;
+            items.push(BlockSummary::from(&block));
+        }
+        Ok(json!(Page {
+            items,
+            page,
+            page_size,
+            total,
+        }))
+    }
+
+    fn get_block_transactions(&self, params: Params) -> Result<Value, RpcError> {
+        let hash: String = params.require(0, "hash")?;
+        let page = params.get::<u64>(1, "page")?.unwrap_or(0);
+        let page_size = page_size(&params, 2)?;
+
+        let block = self
+            .chain()?
+            .get_block_by_hash(&hash)
+            .ok_or_else(|| RpcError::new(SERVER_ERROR, format!("unknown block {}", hash)))?;
+        let total = block.transactions.len() as u64;
+        let items: Vec<TransactionJson> = block
+            .transactions
+            .iter()
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .map(TransactionJson::from)
+            .collect();
+        Ok(json!(Page {
+            items,
+            page,
+            page_size,
+            total,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::PoWEngine;
+    use crate::crypto::KeyPair;
+
+    fn call(handler: &RpcHandler, method: &str, params: Value) -> Result<Value, RpcError> {
+        let response = handler.handle(RpcRequest {
+            id: json!(1),
+            method: method.to_string(),
+            params,
+        });
+        match response.error {
+            Some(e) => Err(e),
+            None => Ok(response.result.unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_block_pagination() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        for amount in 1..=3 {
+            let kp = KeyPair::generate().unwrap();
+            bc.init_genesis_account(&kp.public_key_hex());
+            let mut tx =
+                Transaction::new_with_fee(kp.public_key_hex(), "bob".into(), amount, 1, 0, vec![]);
+            tx.sign(&kp);
+            bc.add_transaction(tx).unwrap();
+        }
+        for _ in 0..4 {
+            bc.produce_block("miner".into());
+        }
+        let block_hash = bc.chain[1].hash.clone();
+        let bc_block_txs: Vec<String> = bc.chain[1]
+            .transactions
+            .iter()
+            .map(|tx| tx.hash.clone())
+            .collect();
+        let handler = RpcHandler::new(Arc::new(Mutex::new(bc)));
+
+        let page: Page<BlockSummary> = serde_json::from_value(
+            call(&handler, "get_blocks", json!({"page": 1, "page_size": 2})).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.items.iter().map(|b| b.index).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+
+        let newest: Page<BlockSummary> =
+            serde_json::from_value(call(&handler, "get_blocks", json!([0, 2, true])).unwrap())
+                .unwrap();
+        assert_eq!(
+            newest.items.iter().map(|b| b.index).collect::<Vec<_>>(),
+            vec![4, 3]
+        );
+
+        let txs: Page<TransactionJson> = serde_json::from_value(
+            call(
+                &handler,
+                "get_block_transactions",
+                json!({"hash": block_hash, "page": 1, "page_size": 2}),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(txs.total, 3);
+        assert_eq!(txs.items.len(), 1);
+        assert_eq!(txs.items[0].hash, bc_block_txs[2]);
+        assert_eq!(txs.items[0].tx_type, "transfer");
+
+        let block = call(&handler, "get_block", json!([1])).unwrap();
+        assert_eq!(block["hash"], json!(block_hash));
+        assert!(block["timestamp"].is_string());
+        assert_eq!(block["transactions"].as_array().unwrap().len(), 3);
+        assert!(call(&handler, "get_block", json!([99])).unwrap().is_null());
+        let receipt = call(
+            &handler,
+            "get_transaction_receipt",
+            json!([bc_block_txs[1]]),
+        )
+        .unwrap();
+        assert_eq!(receipt["block_height"], json!(1));
+        assert_eq!(receipt["index"], json!(1));
+        assert_eq!(receipt["status"], json!("success"));
+        assert!(call(&handler, "get_transaction_receipt", json!(["nope"]))
+            .unwrap()
+            .is_null());
+        let confirmation = call(
+            &handler,
+            "get_transaction_confirmation",
+            json!({"hash": bc_block_txs[1]}),
+        )
+        .unwrap();
+        assert_eq!(confirmation["status"], json!("included"));
+        assert_eq!(confirmation["block_height"], json!(1));
+        assert!(
+            call(&handler, "get_transaction_confirmation", json!(["nope"]))
+                .unwrap()
+                .is_null()
+        );
+
+        let stats = call(&handler, "get_block_stats", json!([2])).unwrap();
+        assert_eq!(stats.as_array().unwrap().len(), 2);
+        assert_eq!(stats[1]["height"], json!(4));
+        let summary = call(&handler, "get_chain_stats", json!({"blocks": 4})).unwrap();
+        assert_eq!(summary["from_height"], json!(1));
+        assert_eq!(summary["tx_count"], json!(3));
+        assert_eq!(summary["fees"]["p50"], json!(1));
+        let err = call(&handler, "get_chain_stats", json!([0])).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let err = call(&handler, "get_balance_at", json!(["bob", 1])).unwrap_err();
+        assert_eq!(err.code, SERVER_ERROR);
+        let client = call(&handler, "get_light_client", json!({"chain_id": 7})).unwrap();
+        assert!(client.is_null());
+        let fork = call(&handler, "get_fork_id", Value::Null).unwrap();
+        assert_eq!(fork["fork_id"]["fork_hash"], json!(""));
+        assert!(fork["next_fork"].is_null());
+
+        handler
+            .blockchain
+            .lock()
+            .unwrap()
+            .peer_versions
+            .record("peer-a", "0.2.0", "abc123");
+        let versions = call(&handler, "peer_versions", Value::Null).unwrap();
+        assert_eq!(
+            versions["local"]["version"],
+            json!(crate::encoding::NODE_VERSION)
+        );
+        assert_eq!(versions["peers"][0]["peer"], json!("peer-a"));
+        assert_eq!(versions["counts"]["0.2.0 (abc123)"], json!(1));
+
+        let err = call(&handler, "get_blocks", json!({"page_size": 1000})).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let err = call(&handler, "no_such_method", Value::Null).unwrap_err();
+        assert_eq!(err.code, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_next_nonce_counts_pending() {
+        let mut bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let kp = KeyPair::generate().unwrap();
+        let sender = kp.public_key_hex();
+        bc.init_genesis_account(&sender);
+        for nonce in 0..2 {
+            let mut tx =
+                Transaction::new_with_fee(sender.clone(), "bob".into(), 1, 1, nonce, vec![]);
+            tx.sign(&kp);
+            bc.mempool.add_transaction(tx).unwrap();
+        }
+        let handler = RpcHandler::new(Arc::new(Mutex::new(bc)));
+        assert_eq!(
+            call(&handler, "get_next_nonce", json!([sender])).unwrap(),
+            json!(2)
+        );
+        let pending: Vec<TransactionJson> = serde_json::from_value(
+            call(&handler, "get_pending_transactions", json!([sender])).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            pending.iter().map(|tx| tx.nonce).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        let stats = call(&handler, "get_mempool_stats", Value::Null).unwrap();
+        assert_eq!(stats["size"], json!(2));
+        assert_eq!(stats["priority"]["admitted"], json!(0));
+        let status = call(&handler, "txpool_status", Value::Null).unwrap();
+        assert_eq!(
+            (status["ready"].clone(), status["future"].clone()),
+            (json!(2), json!(0))
+        );
+        assert_eq!(status["fees"]["percentiles"]["p50"], json!(1));
+        assert_eq!(status["evictions"]["replaced"], json!(0));
+        let content = call(&handler, "txpool_content", json!([sender])).unwrap();
+        assert_eq!(content["ready"][&sender][1]["nonce"], json!(1));
+        assert_eq!(content["future"], json!({}));
+        let content = call(&handler, "txpool_content", json!({"address": "carol"})).unwrap();
+        assert_eq!(content["ready"], json!({}));
+        assert_eq!(
+            call(&handler, "get_next_nonce", json!({"address": "carol"})).unwrap(),
+            json!(0)
+        );
+        assert_eq!(
+            call(&handler, "get_total_supply", Value::Null).unwrap(),
+            json!({"minted": 1_000_000_000u64, "burned": 0, "total_supply": 1_000_000_000u64})
+        );
+        let health = call(&handler, "get_chain_health", Value::Null).unwrap();
+        assert_eq!(health["height"], json!(0));
+        assert_eq!(health["halted"], json!(false));
+        let sync = call(&handler, "get_sync_status", Value::Null).unwrap();
+        assert_eq!(sync["status"], json!("synced"));
+        let finality = call(&handler, "get_finality_status", Value::Null).unwrap();
+        assert_eq!(finality["finalized"]["height"], json!(0));
+        assert_eq!(finality["justified"], finality["finalized"]);
+        assert_eq!(finality["epochs"], json!([]));
+    }
+
+    #[test]
+    fn test_admin_production_controls() {
+        let bc = Arc::new(Mutex::new(Blockchain::new(
+            Arc::new(PoWEngine::new(0)),
+            None,
+            1337,
+            None,
+        )));
+        let public = RpcHandler::new(bc.clone());
+        assert_eq!(
+            call(&public, "admin_pause_production", Value::Null)
+                .unwrap_err()
+                .code,
+            METHOD_NOT_FOUND
+        );
+        assert!(!bc.lock().unwrap().is_production_paused());
+        assert_eq!(
+            call(&public, "debug_replay_block", json!([1]))
+                .unwrap_err()
+                .code,
+            METHOD_NOT_FOUND
+        );
+
+        let admin = RpcHandler::new(bc.clone()).with_admin(true);
+        assert_eq!(
+            call(&admin, "admin_pause_production", Value::Null).unwrap(),
+            json!(true)
+        );
+        assert_eq!(
+            call(&admin, "admin_pause_production", Value::Null).unwrap(),
+            json!(false)
+        );
+        let health = call(&public, "get_chain_health", Value::Null).unwrap();
+        assert_eq!(health["production_paused"], json!(true));
+        assert_eq!(
+            call(&admin, "admin_resume_production", Value::Null).unwrap(),
+            json!(true)
+        );
+        assert!(!bc.lock().unwrap().is_production_paused());
+
+        bc.lock().unwrap().produce_block("miner".into());
+        let trace = call(&admin, "debug_replay_block", json!([1])).unwrap();
+        assert_eq!(trace["state_root_matches"], json!(true));
+        assert_eq!(trace["transactions"], json!([]));
+    }
+
+    #[test]
+    fn test_watch_address_methods() {
+        let bc = Blockchain::new(Arc::new(PoWEngine::new(0)), None, 1337, None);
+        let handler = RpcHandler::new(Arc::new(Mutex::new(bc)));
+        assert_eq!(
+            call(&handler, "watch_address", json!(["bob"])).unwrap(),
+            json!(true)
+        );
+        assert_eq!(
+            call(&handler, "get_watched_addresses", Value::Null).unwrap(),
+            json!(["bob"])
+        );
+        assert_eq!(
+            call(&handler, "unwatch_address", json!({"address": "bob"})).unwrap(),
+            json!(true)
+        );
+        assert_eq!(
+            call(&handler, "watch_address", Value::Null)
+                .unwrap_err()
+                .code,
+            INVALID_PARAMS
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crate::events::ChainEvent;
+use crate::{Block, Blockchain, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+pub const MAX_WATCHED_ADDRESSES: usize = 10_000;
+pub const NOTIFICATION_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityStatus {
+    Pending,
+    Confirmed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressNotification {
+    pub address: String,
+    pub tx_hash: String,
+    pub status: ActivityStatus,
+    pub block_height: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct WatchService {
+    watched: Arc<Mutex<BTreeSet<String>>>,
+    notifications: broadcast::Sender<AddressNotification>,
+}
+
+impl WatchService {
+    pub fn new() -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        WatchService {
+            watched: Arc::new(Mutex::new(BTreeSet::new())),
+            notifications,
+        }
+    }
+
+    fn watched(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.watched.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn watch(&self, address: String) -> Result<bool, String> {
+        let mut watched = self.watched();
+        if watched.len() >= MAX_WATCHED_ADDRESSES && !watched.contains(&address) {
+            return Err(format!(
+                "watch list is full ({} addresses)",
+                MAX_WATCHED_ADDRESSES
+            ));
+        }
+        Ok(watched.insert(address))
+    }
+
+    pub fn unwatch(&self, address: &str) -> bool {
+        self.watched().remove(address)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.watched().iter().cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AddressNotification> {
+        self.notifications.subscribe()
+    }
+
+    fn notify_tx(&self, tx: &Transaction, status: ActivityStatus, block_height: Option<u64>) {
+        let matches: Vec<String> = {
+            let watched = self.watched();
+            if watched.is_empty() {
+                return;
+            }
+            [&tx.from, &tx.to]
+                .into_iter()
+                .filter(|addr| watched.contains(*addr))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        for address in matches {
+            let _ = self.notifications.send(AddressNotification {
+                address,
+                tx_hash: tx.hash.clone(),
+                status,
+                block_height,
+            });
+        }
+    }
+
+    pub fn notify_pending(&self, tx: &Transaction) {
+        self.notify_tx(tx, ActivityStatus::Pending, None);
+    }
+
+    pub fn notify_block(&self, block: &Block) {
+        for tx in &block.transactions {
+            self.notify_tx(tx, ActivityStatus::Confirmed, Some(block.index));
+        }
+    }
+
+    pub fn spawn(
+        &self,
+        mut events: broadcast::Receiver<ChainEvent>,
+        blockchain: Arc<Mutex<Blockchain>>,
+    ) {
+        let watch = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Address watcher lagged, skipped {} chain events", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                match event {
+                    ChainEvent::PendingTransaction(tx) => watch.notify_pending(&tx),
+                    ChainEvent::BlockAdded { hash, .. } => {
+                        let block = blockchain
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .get_block_by_hash(&hash);
+                        if let Some(block) = block {
+                            watch.notify_block(&block);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+}
+
+impl Default for WatchService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watched_address_notifications() {
+        let watch = WatchService::new();
+        let mut rx = watch.subscribe();
+        assert!(watch.watch("bob".into()).unwrap());
+        assert!(!watch.watch("bob".into()).unwrap());
+
+        let tx = Transaction::new("alice".into(), "bob".into(), 5, vec![]);
+        let other = Transaction::new("alice".into(), "carol".into(), 5, vec![]);
+        watch.notify_pending(&tx);
+        watch.notify_pending(&other);
+        let mut block = Block::new(7, "0".repeat(64), vec![tx.clone(), other]);
+        block.hash = block.calculate_hash();
+        watch.notify_block(&block);
+
+        let pending = rx.try_recv().unwrap();
+        assert_eq!(pending.address, "bob");
+        assert_eq!(pending.status, ActivityStatus::Pending);
+        let confirmed = rx.try_recv().unwrap();
+        assert_eq!(confirmed.tx_hash, tx.hash);
+        assert_eq!(confirmed.status, ActivityStatus::Confirmed);
+        assert_eq!(confirmed.block_height, Some(7));
+        assert!(rx.try_recv().is_err());
+
+        assert!(watch.unwatch("bob"));
+        assert!(watch.list().is_empty());
+    }
+}
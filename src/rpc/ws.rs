@@ -0,0 +1,154 @@
+use super::http::{MAX_REQUEST_BYTES, READ_TIMEOUT};
+use super::watch::WatchService;
+use crate::events::{BlockRejection, ChainEvent, EventBus};
+use crate::state_diff::StateDiffEvent;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+pub async fn serve_ws(
+    bind: IpAddr,
+    port: u16,
+    watch: WatchService,
+    events: EventBus,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind((bind, port)).await?;
+    info!("WebSocket notifications listening on {}:{}", bind, port);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let watch = watch.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = forward_notifications(stream, watch, events).await {
+                warn!("WebSocket connection from {} closed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn next_diff(
+    diffs: &mut Option<broadcast::Receiver<StateDiffEvent>>,
+) -> Result<StateDiffEvent, broadcast::error::RecvError> {
+    match diffs {
+        Some(diffs) => diffs.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_rejection(
+    events: &mut Option<broadcast::Receiver<ChainEvent>>,
+) -> Result<BlockRejection, broadcast::error::RecvError> {
+    let Some(events) = events else {
+        return std::future::pending().await;
+    };
+    loop {
+        if let ChainEvent::BlockRejected(rejection) = events.recv().await? {
+            return Ok(rejection);
+        }
+    }
+}
+
+// State diffs are opt-in per connection: a client sends
+// `{"method": "subscribe_state_diffs"}` and then receives a `state_diff`
+// frame for every imported block and a `state_diff_reverted` frame for every
+// block a reorg removed. A client too slow to keep up gets
+// `state_diffs_lagged` instead of the diffs it missed and must resync.
+// `subscribe_block_rejections` likewise turns on a `block_rejected` frame
+// for every block that fails import.
+async fn forward_notifications(
+    stream: TcpStream,
+    watch: WatchService,
+    events: EventBus,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    // Clients only send short subscribe requests.
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_REQUEST_BYTES))
+        .max_frame_size(Some(MAX_REQUEST_BYTES));
+    let handshake = tokio_tungstenite::accept_async_with_config(stream, Some(config));
+    let ws = tokio::time::timeout(READ_TIMEOUT, handshake)
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    let (mut sink, mut incoming) = ws.split();
+    let mut notifications = watch.subscribe();
+    let mut diffs = None;
+    let mut rejections = None;
+    loop {
+        tokio::select! {
+            notification = notifications.recv() => match notification {
+                Ok(notification) => {
+                    let frame = json!({
+                        "jsonrpc": "2.0",
+                        "method": "address_activity",
+                        "params": notification,
+                    });
+                    sink.send(Message::Text(frame.to_string().into())).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket subscriber lagged, skipped {} notifications", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            diff = next_diff(&mut diffs) => {
+                let (method, params) = match diff {
+                    Ok(StateDiffEvent::Applied(diff)) => ("state_diff", json!(*diff)),
+                    Ok(StateDiffEvent::Reverted { height, hash }) => {
+                        ("state_diff_reverted", json!({"height": height, "hash": hash}))
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("State diff subscriber lagged, skipped {} diffs", skipped);
+                        ("state_diffs_lagged", json!({"skipped": skipped}))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        diffs = None;
+                        continue;
+                    }
+                };
+                let frame = json!({"jsonrpc": "2.0", "method": method, "params": params});
+                sink.send(Message::Text(frame.to_string().into())).await?;
+            }
+            rejection = next_rejection(&mut rejections) => match rejection {
+                Ok(rejection) => {
+                    let frame = json!({
+                        "jsonrpc": "2.0",
+                        "method": "block_rejected",
+                        "params": rejection,
+                    });
+                    sink.send(Message::Text(frame.to_string().into())).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Block rejection subscriber lagged, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => rejections = None,
+            },
+            frame = incoming.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    let request: Value = serde_json::from_str(&text).unwrap_or_default();
+                    let subscribed = match request["method"].as_str() {
+                        Some("subscribe_state_diffs") => {
+                            diffs.get_or_insert_with(|| events.subscribe_state_diffs());
+                            true
+                        }
+                        Some("subscribe_block_rejections") => {
+                            rejections.get_or_insert_with(|| events.subscribe());
+                            true
+                        }
+                        _ => false,
+                    };
+                    if subscribed {
+                        let reply = json!({"jsonrpc": "2.0", "id": request["id"], "result": true});
+                        sink.send(Message::Text(reply.to_string().into())).await?;
+                    }
+                }
+                Some(Ok(Message::Ping(payload))) => sink.send(Message::Pong(payload)).await?,
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+}
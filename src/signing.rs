@@ -0,0 +1,75 @@
+use crate::chain_config::ForkId;
+use sha3::{Digest, Sha3_256};
+
+// What a signature is for. Each kind gets its own context string, so a
+// signature made for one message type never verifies as another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningContext {
+    Block,
+    Prevote,
+    Precommit,
+    BlsPop,
+    Finality,
+    Transaction,
+}
+
+impl SigningContext {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigningContext::Block => "BDLM_BLOCK",
+            SigningContext::Prevote => "BDLM_PREVOTE",
+            SigningContext::Precommit => "BDLM_PRECOMMIT",
+            SigningContext::BlsPop => "BDLM_BLS_POP",
+            SigningContext::Finality => "BDLM_FINALITY",
+            SigningContext::Transaction => "BDLM_TX",
+        }
+    }
+}
+
+// The digest that is actually signed: the context, chain id and fork hash
+// followed by the message-specific payload. A signature therefore only
+// verifies for the same kind of message on the same chain and fork.
+pub fn signing_digest(context: SigningContext, fork: &ForkId, payload: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"BDLM_SIGN_V1");
+    for field in [
+        context.as_str().as_bytes(),
+        &fork.chain_id.to_le_bytes(),
+        fork.fork_hash.as_bytes(),
+    ] {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    hasher.update(payload);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_depends_on_context_chain_and_fork() {
+        let fork = |chain_id, fork_hash: &str| ForkId {
+            chain_id,
+            fork_hash: fork_hash.into(),
+        };
+        let base = signing_digest(SigningContext::Prevote, &fork(1, ""), b"payload");
+        assert_eq!(
+            base,
+            signing_digest(SigningContext::Prevote, &fork(1, ""), b"payload")
+        );
+        assert_ne!(
+            base,
+            signing_digest(SigningContext::Precommit, &fork(1, ""), b"payload")
+        );
+        assert_ne!(
+            base,
+            signing_digest(SigningContext::Prevote, &fork(42, ""), b"payload")
+        );
+        assert_ne!(
+            base,
+            signing_digest(SigningContext::Prevote, &fork(1, "ab"), b"payload")
+        );
+    }
+}
@@ -7,7 +7,7 @@ pub enum SlashingType {
     Downtime,
     InvalidBlock,
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SlashingEvidence {
     pub offense_type: SlashingType,
     pub validator: String,
@@ -22,6 +22,10 @@ pub struct SlashingEvidence {
     pub vrf_output_2: Option<Vec<u8>>,
     pub checkpoint_hash_1: Option<String>,
     pub checkpoint_hash_2: Option<String>,
+    #[serde(default)]
+    pub state_root_1: Option<String>,
+    #[serde(default)]
+    pub state_root_2: Option<String>,
     pub timestamp: u128,
     pub reporter: String,
 }
@@ -53,6 +57,8 @@ impl SlashingEvidence {
             vrf_output_2: None,
             checkpoint_hash_1: None,
             checkpoint_hash_2: None,
+            state_root_1: None,
+            state_root_2: None,
             timestamp,
             reporter,
         }
@@ -88,6 +94,8 @@ impl SlashingEvidence {
             vrf_output_2: Some(vrf_output_2),
             checkpoint_hash_1: None,
             checkpoint_hash_2: None,
+            state_root_1: None,
+            state_root_2: None,
             timestamp,
             reporter,
         }
@@ -111,6 +119,8 @@ impl SlashingEvidence {
             vrf_output_2: None,
             checkpoint_hash_1: None,
             checkpoint_hash_2: None,
+            state_root_1: None,
+            state_root_2: None,
             timestamp,
             reporter,
         }
@@ -143,6 +153,8 @@ impl SlashingEvidence {
             vrf_output_2: None,
             checkpoint_hash_1: Some(checkpoint_hash_1),
             checkpoint_hash_2: Some(checkpoint_hash_2),
+            state_root_1: None,
+            state_root_2: None,
             timestamp,
             reporter,
         }
@@ -216,6 +228,11 @@ impl SlashingEvidence {
         Ok(())
     }
 
+    // One double-vote offence per validator and checkpoint.
+    pub fn key(&self) -> String {
+        format!("vote:{}:{}:{}", self.validator, self.epoch, self.height)
+    }
+
     pub fn slash_amount(&self, stake: u64) -> u64 {
         match self.offense_type {
             SlashingType::DoubleSign => stake,
@@ -330,6 +347,8 @@ mod tests {
             vrf_output_2: None,
             checkpoint_hash_1: None,
             checkpoint_hash_2: None,
+            state_root_1: None,
+            state_root_2: None,
             timestamp: 0,
             reporter: "reporter".to_string(),
         };
@@ -1,6 +1,49 @@
-use crate::account::AccountState;
+use crate::account::{AccountState, StateRootEntry};
+use crate::blockchain::EPOCH_LENGTH;
+use crate::hash::hash_fields;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha3::{Digest, Sha3_256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOT_CHUNK_SIZE: usize = 256 * 1024;
+pub const MAX_CHUNKS_PER_REQUEST: u32 = 4;
+
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 4;
+pub const ACCOUNTS_PER_CHUNK: usize = 10_000;
+pub const SNAPSHOT_ZSTD_LEVEL: i32 = 3;
+pub const DELTAS_PER_FULL_SNAPSHOT: u64 = 4;
+pub const SNAPSHOT_EXPORT_MAGIC: &[u8; 8] = b"BDLMSNP3";
+// Snapshot files may come from anywhere, so the lengths they declare are
+// checked against these before anything is allocated.
+pub const MAX_SNAPSHOT_MANIFEST_BYTES: usize = 64 * 1024 * 1024;
+pub const MAX_SNAPSHOT_CHUNK_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotChunkData {
+    pub index: u32,
+    pub total: u32,
+    pub hash: String,
+    pub data: Vec<u8>,
+}
+
+// Epoch start blocks commit to the state they build on, so snapshots are
+// taken on the last block of an epoch and the interval is rounded up to
+// whole epochs.
+pub fn snapshot_period(snapshot_interval: u64) -> u64 {
+    snapshot_interval.div_ceil(EPOCH_LENGTH).max(1) * EPOCH_LENGTH
+}
+
+pub fn chunk_hash(height: u64, index: u32, data: &[u8]) -> String {
+    hash_fields(&[
+        b"BDLM_SNAP_CHUNK_V1",
+        &height.to_le_bytes(),
+        &index.to_le_bytes(),
+        data,
+    ])
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateSnapshot {
     pub height: u64,
@@ -9,6 +52,13 @@ pub struct StateSnapshot {
     pub created_at: u128,
     pub balances: HashMap<String, u64>,
     pub nonces: HashMap<String, u64>,
+    // Stake locked by each account that locked any, by validator.
+    #[serde(default)]
+    pub locked: HashMap<String, BTreeMap<String, u64>>,
+    // The state root entries other than accounts: validators, allowances
+    // and the chain-wide records.
+    #[serde(default)]
+    pub chain_state: Vec<StateRootEntry>,
     pub finalized_height: u64,
     pub finalized_hash: String,
     pub snapshot_hash: String,
@@ -28,6 +78,12 @@ impl StateSnapshot {
             .as_millis();
         let balances = account_state.get_all_balances();
         let nonces = account_state.get_all_nonces();
+        let locked = account_state.get_all_locked();
+        let chain_state = account_state
+            .state_root_entries()
+            .into_iter()
+            .filter(|e| !e.key.starts_with("account:"))
+            .collect();
         let mut snapshot = StateSnapshot {
             height,
             block_hash,
@@ -35,6 +91,8 @@ impl StateSnapshot {
             created_at,
             balances,
             nonces,
+            locked,
+            chain_state,
             finalized_height,
             finalized_hash,
             snapshot_hash: String::new(),
@@ -43,8 +101,22 @@ impl StateSnapshot {
         snapshot
     }
     fn calculate_hash(&self) -> String {
-        use sha3::{Digest, Sha3_256};
         let mut hasher = Sha3_256::new();
+        self.hash_state(&mut hasher);
+        hasher.update(self.finalized_height.to_le_bytes());
+        hasher.update(self.finalized_hash.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+    // The hash epoch start blocks commit to. It leaves out when the snapshot
+    // was taken and the taking node's view of finality, so every node
+    // computes the same one for the same state.
+    pub fn commitment(&self) -> String {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"BDLM_STATE_SNAPSHOT_V1");
+        self.hash_state(&mut hasher);
+        hex::encode(hasher.finalize())
+    }
+    fn hash_state(&self, hasher: &mut Sha3_256) {
         hasher.update(self.height.to_le_bytes());
         hasher.update(self.block_hash.as_bytes());
         hasher.update(self.chain_id.to_le_bytes());
@@ -60,9 +132,25 @@ impl StateSnapshot {
             hasher.update(key.as_bytes());
             hasher.update(self.nonces[key].to_le_bytes());
         }
-        hasher.update(self.finalized_height.to_le_bytes());
-        hasher.update(self.finalized_hash.as_bytes());
-        hex::encode(hasher.finalize())
+        // Left out when empty so snapshots without stake keep their hash.
+        if !self.locked.is_empty() {
+            hasher.update(b"LOCKED");
+            let mut locked_keys: Vec<_> = self.locked.keys().collect();
+            locked_keys.sort();
+            for key in locked_keys {
+                hasher.update(key.as_bytes());
+                for (validator, amount) in &self.locked[key] {
+                    hasher.update(validator.as_bytes());
+                    hasher.update(amount.to_le_bytes());
+                }
+            }
+        }
+        if !self.chain_state.is_empty() {
+            hasher.update(b"CHAIN_STATE");
+            for entry in &self.chain_state {
+                hasher.update(entry.leaf().as_bytes());
+            }
+        }
     }
     pub fn verify(&self) -> bool {
         self.snapshot_hash == self.calculate_hash()
@@ -76,12 +164,474 @@ impl StateSnapshot {
     pub fn size(&self) -> usize {
         self.to_bytes().len()
     }
+    pub fn to_account_state(&self) -> Result<AccountState, String> {
+        let mut state = AccountState::new();
+        for (addr, balance) in &self.balances {
+            state.get_or_create(addr).balance = *balance;
+        }
+        for (addr, nonce) in &self.nonces {
+            state.get_or_create(addr).nonce = *nonce;
+        }
+        for (addr, locked) in &self.locked {
+            state.get_or_create(addr).locked = locked.clone();
+        }
+        state.restore_entries(&self.chain_state)?;
+        Ok(state)
+    }
+    pub fn state_root(&self) -> Result<String, String> {
+        Ok(self.to_account_state()?.calculate_state_root())
+    }
+    pub fn to_chunks(&self, chunk_size: usize) -> Vec<SnapshotChunkData> {
+        let bytes = self.to_bytes();
+        let total = bytes.len().div_ceil(chunk_size).max(1) as u32;
+        if bytes.is_empty() {
+            return vec![SnapshotChunkData {
+                index: 0,
+                total,
+                hash: chunk_hash(self.height, 0, &[]),
+                data: Vec::new(),
+            }];
+        }
+        bytes
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(i, data)| SnapshotChunkData {
+                index: i as u32,
+                total,
+                hash: chunk_hash(self.height, i as u32, data),
+                data: data.to_vec(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotDownload {
+    pub height: u64,
+    pub total: Option<u32>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+}
+impl SnapshotDownload {
+    pub fn new(height: u64) -> Self {
+        SnapshotDownload {
+            height,
+            total: None,
+            chunks: BTreeMap::new(),
+        }
+    }
+    pub fn add_chunk(
+        &mut self,
+        height: u64,
+        index: u32,
+        total: u32,
+        hash: &str,
+        data: Vec<u8>,
+    ) -> Result<(), String> {
+        if self.height == 0 && self.chunks.is_empty() {
+            self.height = height;
+        }
+        if height != self.height {
+            return Err(format!(
+                "Chunk for height {} does not match download height {}",
+                height, self.height
+            ));
+        }
+        if total == 0 || index >= total {
+            return Err(format!(
+                "Chunk index {} out of range (total {})",
+                index, total
+            ));
+        }
+        match self.total {
+            Some(t) if t != total => {
+                return Err(format!("Chunk total changed from {} to {}", t, total));
+            }
+            _ => self.total = Some(total),
+        }
+        if data.len() > SNAPSHOT_CHUNK_SIZE {
+            return Err(format!(
+                "Chunk {} is {} bytes, over the {} byte chunk size",
+                index,
+                data.len(),
+                SNAPSHOT_CHUNK_SIZE
+            ));
+        }
+        if chunk_hash(height, index, &data) != hash {
+            return Err(format!("Chunk {} hash mismatch", index));
+        }
+        self.chunks.insert(index, data);
+        Ok(())
+    }
+    pub fn next_missing_index(&self) -> u32 {
+        let mut next = 0;
+        while self.chunks.contains_key(&next) {
+            next += 1;
+        }
+        next
+    }
+    pub fn received(&self) -> usize {
+        self.chunks.len()
+    }
+    pub fn is_complete(&self) -> bool {
+        match self.total {
+            Some(total) => self.next_missing_index() >= total,
+            None => false,
+        }
+    }
+    pub fn assemble(&self) -> Result<StateSnapshot, String> {
+        if !self.is_complete() {
+            return Err(format!(
+                "Snapshot download incomplete: {}/{} chunks",
+                self.chunks.len(),
+                self.total.unwrap_or(0)
+            ));
+        }
+        let data: Vec<u8> = self.chunks.values().flatten().copied().collect();
+        let snapshot = StateSnapshot::from_bytes(&data)?;
+        if snapshot.height != self.height || !snapshot.verify() {
+            return Err("Snapshot integrity check failed".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SnapshotAccount {
+    pub address: String,
+    pub balance: u64,
+    pub nonce: u64,
+    pub locked: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub height: u64,
+    pub block_hash: String,
+    pub chain_id: u64,
+    pub created_at: u128,
+    pub finalized_height: u64,
+    pub finalized_hash: String,
+    pub account_count: u64,
+    pub chunk_hashes: Vec<String>,
+    pub chunk_root: String,
+    pub snapshot_hash: String,
+    #[serde(default)]
+    pub base_height: Option<u64>,
+    // Carried whole, in deltas too, since it is small next to the accounts.
+    #[serde(default)]
+    pub chain_state: Vec<StateRootEntry>,
+    // Tombstones: accounts in the base that are gone at this height, such
+    // as swept dust. Always empty in a full snapshot.
+    #[serde(default)]
+    pub removed_accounts: Vec<String>,
+}
+impl SnapshotManifest {
+    pub fn from_snapshot(snapshot: &StateSnapshot) -> Self {
+        SnapshotManifest {
+            version: SNAPSHOT_FORMAT_VERSION,
+            height: snapshot.height,
+            block_hash: snapshot.block_hash.clone(),
+            chain_id: snapshot.chain_id,
+            created_at: snapshot.created_at,
+            finalized_height: snapshot.finalized_height,
+            finalized_hash: snapshot.finalized_hash.clone(),
+            account_count: 0,
+            chunk_hashes: Vec::new(),
+            chunk_root: String::new(),
+            snapshot_hash: snapshot.snapshot_hash.clone(),
+            base_height: None,
+            chain_state: snapshot.chain_state.clone(),
+            removed_accounts: Vec::new(),
+        }
+    }
+    pub fn is_delta(&self) -> bool {
+        self.base_height.is_some()
+    }
+    pub fn verify_chunk_root(&self) -> bool {
+        self.chunk_root == merkle_root(&self.chunk_hashes)
+    }
+}
+
+fn read_export_manifest(input: &mut impl Read) -> Result<(SnapshotManifest, Vec<u8>), String> {
+    let mut magic = [0u8; 8];
+    input
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Failed to read import file: {}", e))?;
+    if &magic != SNAPSHOT_EXPORT_MAGIC {
+        return Err("Not a Budlum snapshot export file".to_string());
+    }
+    let mut len_bytes = [0u8; 4];
+    input
+        .read_exact(&mut len_bytes)
+        .map_err(|e| format!("Failed to read import file: {}", e))?;
+    let manifest_bytes = read_bounded(
+        input,
+        u32::from_le_bytes(len_bytes) as usize,
+        MAX_SNAPSHOT_MANIFEST_BYTES,
+    )
+    .map_err(|e| format!("Failed to read manifest: {}", e))?;
+    let manifest = serde_json::from_slice(&manifest_bytes)
+        .map_err(|e| format!("Failed to parse manifest: {}", e))?;
+    Ok((manifest, manifest_bytes))
+}
+// Reads a `len` byte record, growing the buffer only as data arrives.
+pub fn read_bounded(input: &mut impl Read, len: usize, max: usize) -> Result<Vec<u8>, String> {
+    if len > max {
+        return Err(format!(
+            "record of {} bytes exceeds the {} byte limit",
+            len, max
+        ));
+    }
+    let mut buf = Vec::new();
+    input
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .map_err(|e| e.to_string())?;
+    if buf.len() != len {
+        return Err(format!("truncated after {} of {} bytes", buf.len(), len));
+    }
+    Ok(buf)
+}
+// Stops inflating once the output passes the chunk limit.
+fn decompress_chunk(compressed: &[u8]) -> Result<Vec<u8>, String> {
+    let mut raw = Vec::new();
+    zstd::stream::read::Decoder::new(compressed)
+        .and_then(|decoder| {
+            decoder
+                .take(MAX_SNAPSHOT_CHUNK_BYTES as u64 + 1)
+                .read_to_end(&mut raw)
+        })
+        .map_err(|e| format!("Failed to decompress snapshot chunk: {}", e))?;
+    if raw.len() > MAX_SNAPSHOT_CHUNK_BYTES {
+        return Err(format!(
+            "Snapshot chunk inflates past {} bytes",
+            MAX_SNAPSHOT_CHUNK_BYTES
+        ));
+    }
+    Ok(raw)
+}
+pub fn read_export_file_manifest(file: &Path) -> Result<SnapshotManifest, String> {
+    let mut input = File::open(file).map_err(|e| format!("Failed to open import file: {}", e))?;
+    Ok(read_export_manifest(&mut input)?.0)
+}
+pub fn read_export_height(file: &Path) -> Result<u64, String> {
+    Ok(read_export_file_manifest(file)?.height)
+}
+pub fn merkle_root(hashes: &[String]) -> String {
+    if hashes.is_empty() {
+        return "0".repeat(64);
+    }
+    crate::hash::merkle_root(hashes)
+}
+
+pub fn snapshot_chunks_path(dir: &Path, height: u64) -> PathBuf {
+    dir.join(format!("snapshot_{}.chunks", height))
+}
+
+pub fn snapshot_manifest_path(dir: &Path, height: u64) -> PathBuf {
+    dir.join(format!("snapshot_{}.manifest.json", height))
+}
+
+pub struct SnapshotWriter {
+    out: BufWriter<File>,
+    manifest: SnapshotManifest,
+    manifest_path: PathBuf,
+    pending: Vec<SnapshotAccount>,
+}
+impl SnapshotWriter {
+    pub fn create(dir: &Path, manifest: SnapshotManifest) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create snapshot dir: {}", e))?;
+        let file = File::create(snapshot_chunks_path(dir, manifest.height))
+            .map_err(|e| format!("Failed to create snapshot file: {}", e))?;
+        Ok(SnapshotWriter {
+            out: BufWriter::new(file),
+            manifest_path: snapshot_manifest_path(dir, manifest.height),
+            manifest: SnapshotManifest {
+                account_count: 0,
+                chunk_hashes: Vec::new(),
+                chunk_root: String::new(),
+                ..manifest
+            },
+            pending: Vec::with_capacity(ACCOUNTS_PER_CHUNK),
+        })
+    }
+    pub fn write_account(&mut self, account: SnapshotAccount) -> Result<(), String> {
+        self.pending.push(account);
+        self.manifest.account_count += 1;
+        if self.pending.len() >= ACCOUNTS_PER_CHUNK {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+    fn flush_chunk(&mut self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let raw = bincode::serialize(&self.pending)
+            .map_err(|e| format!("Failed to encode snapshot chunk: {}", e))?;
+        let compressed = zstd::encode_all(raw.as_slice(), SNAPSHOT_ZSTD_LEVEL)
+            .map_err(|e| format!("Failed to compress snapshot chunk: {}", e))?;
+        let index = self.manifest.chunk_hashes.len() as u32;
+        self.manifest
+            .chunk_hashes
+            .push(chunk_hash(self.manifest.height, index, &compressed));
+        self.out
+            .write_all(&(compressed.len() as u32).to_le_bytes())
+            .and_then(|_| self.out.write_all(&compressed))
+            .map_err(|e| format!("Failed to write snapshot chunk: {}", e))?;
+        self.pending.clear();
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<SnapshotManifest, String> {
+        self.flush_chunk()?;
+        self.out
+            .flush()
+            .map_err(|e| format!("Failed to flush snapshot file: {}", e))?;
+        self.manifest.chunk_root = merkle_root(&self.manifest.chunk_hashes);
+        let data = serde_json::to_string_pretty(&self.manifest)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        fs::write(&self.manifest_path, data)
+            .map_err(|e| format!("Failed to write manifest: {}", e))?;
+        Ok(self.manifest)
+    }
+}
+
+pub struct SnapshotReader {
+    input: BufReader<File>,
+    manifest: SnapshotManifest,
+    next_index: usize,
 }
+impl SnapshotReader {
+    pub fn open(dir: &Path, height: u64) -> Result<Self, String> {
+        let data = fs::read_to_string(snapshot_manifest_path(dir, height))
+            .map_err(|e| format!("Failed to read manifest: {}", e))?;
+        let manifest: SnapshotManifest =
+            serde_json::from_str(&data).map_err(|e| format!("Failed to parse manifest: {}", e))?;
+        if manifest.version != SNAPSHOT_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported snapshot version {} (expected {})",
+                manifest.version, SNAPSHOT_FORMAT_VERSION
+            ));
+        }
+        if !manifest.verify_chunk_root() {
+            return Err("Snapshot manifest chunk root mismatch".to_string());
+        }
+        let file = File::open(snapshot_chunks_path(dir, height))
+            .map_err(|e| format!("Failed to open snapshot file: {}", e))?;
+        Ok(SnapshotReader {
+            input: BufReader::new(file),
+            manifest,
+            next_index: 0,
+        })
+    }
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<SnapshotAccount>>, String> {
+        let Some(expected) = self.manifest.chunk_hashes.get(self.next_index) else {
+            return Ok(None);
+        };
+        let mut len_bytes = [0u8; 4];
+        self.input
+            .read_exact(&mut len_bytes)
+            .map_err(|e| format!("Truncated snapshot file: {}", e))?;
+        let compressed = read_bounded(
+            &mut self.input,
+            u32::from_le_bytes(len_bytes) as usize,
+            MAX_SNAPSHOT_CHUNK_BYTES,
+        )
+        .map_err(|e| format!("Bad snapshot chunk {}: {}", self.next_index, e))?;
+        if &chunk_hash(self.manifest.height, self.next_index as u32, &compressed) != expected {
+            return Err(format!("Snapshot chunk {} hash mismatch", self.next_index));
+        }
+        let raw = decompress_chunk(&compressed)?;
+        let accounts: Vec<SnapshotAccount> = bincode::deserialize(&raw)
+            .map_err(|e| format!("Failed to decode snapshot chunk: {}", e))?;
+        self.next_index += 1;
+        Ok(Some(accounts))
+    }
+    pub fn read_snapshot(self) -> Result<StateSnapshot, String> {
+        if let Some(base) = self.manifest.base_height {
+            return Err(format!(
+                "Snapshot at height {} is a delta over {}; load it with its base",
+                self.manifest.height, base
+            ));
+        }
+        self.read_over(None)
+    }
+    pub fn read_over(mut self, base: Option<StateSnapshot>) -> Result<StateSnapshot, String> {
+        if let Some(ref b) = base {
+            if Some(b.height) != self.manifest.base_height {
+                return Err(format!(
+                    "Delta snapshot expects base {:?}, got {}",
+                    self.manifest.base_height, b.height
+                ));
+            }
+        }
+        let (mut balances, mut nonces, mut locked) = match base {
+            Some(b) => (b.balances, b.nonces, b.locked),
+            None => (HashMap::new(), HashMap::new(), HashMap::new()),
+        };
+        for address in &self.manifest.removed_accounts {
+            balances.remove(address);
+            nonces.remove(address);
+            locked.remove(address);
+        }
+        let mut read = 0u64;
+        while let Some(accounts) = self.next_chunk()? {
+            for account in accounts {
+                balances.insert(account.address.clone(), account.balance);
+                nonces.insert(account.address.clone(), account.nonce);
+                if account.locked.is_empty() {
+                    locked.remove(&account.address);
+                } else {
+                    locked.insert(account.address, account.locked);
+                }
+                read += 1;
+            }
+        }
+        if read != self.manifest.account_count {
+            return Err(format!(
+                "Snapshot account count mismatch: manifest {}, read {}",
+                self.manifest.account_count, read
+            ));
+        }
+        let manifest = self.manifest;
+        let snapshot = StateSnapshot {
+            height: manifest.height,
+            block_hash: manifest.block_hash,
+            chain_id: manifest.chain_id,
+            created_at: manifest.created_at,
+            balances,
+            nonces,
+            locked,
+            chain_state: manifest.chain_state,
+            finalized_height: manifest.finalized_height,
+            finalized_hash: manifest.finalized_hash,
+            snapshot_hash: manifest.snapshot_hash,
+        };
+        if !snapshot.verify() {
+            return Err("Snapshot integrity check failed".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+
+// What an imported snapshot has to match. The block hash is known when the
+// state root comes from a finalized local block rather than the operator.
+#[derive(Debug, Clone)]
+pub struct SnapshotAnchor {
+    pub chain_id: u64,
+    pub state_root: String,
+    pub block_hash: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct PruningManager {
     pub min_blocks_to_keep: u64,
     pub snapshot_interval: u64,
     pub snapshot_dir: String,
+    pub deltas_per_full: u64,
 }
 impl PruningManager {
     pub fn new(min_blocks: u64, snapshot_interval: u64, snapshot_dir: String) -> Self {
@@ -89,10 +639,15 @@ impl PruningManager {
             min_blocks_to_keep: min_blocks,
             snapshot_interval,
             snapshot_dir,
+            deltas_per_full: DELTAS_PER_FULL_SNAPSHOT,
         }
     }
+    pub fn with_deltas_per_full(mut self, deltas_per_full: u64) -> Self {
+        self.deltas_per_full = deltas_per_full;
+        self
+    }
     pub fn should_create_snapshot(&self, height: u64) -> bool {
-        height > 0 && height % self.snapshot_interval == 0
+        (height + 1).is_multiple_of(snapshot_period(self.snapshot_interval))
     }
     pub fn get_prunable_blocks(
         &self,
@@ -114,54 +669,274 @@ impl PruningManager {
         (1..safe_prune_up_to).collect()
     }
     pub fn save_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), String> {
-        use std::fs;
-        use std::path::Path;
+        let base = match self.delta_base(snapshot.height)? {
+            Some(base_height) => Some(self.load_snapshot(base_height)?),
+            None => None,
+        };
+        let manifest = self.write_snapshot(snapshot, base.as_ref())?;
+        if manifest.is_delta() {
+            println!(
+                "Delta snapshot saved at height {} over {} ({} changed accounts)",
+                snapshot.height,
+                manifest.base_height.unwrap_or(0),
+                manifest.account_count
+            );
+        } else {
+            println!(
+                "Snapshot saved: {} ({} accounts, {} chunks)",
+                snapshot_chunks_path(Path::new(&self.snapshot_dir), snapshot.height).display(),
+                manifest.account_count,
+                manifest.chunk_hashes.len()
+            );
+            self.remove_obsolete_deltas(snapshot.height)?;
+        }
+        Ok(())
+    }
+    fn write_snapshot(
+        &self,
+        snapshot: &StateSnapshot,
+        base: Option<&StateSnapshot>,
+    ) -> Result<SnapshotManifest, String> {
         let dir = Path::new(&self.snapshot_dir);
-        if !dir.exists() {
-            fs::create_dir_all(dir).map_err(|e| format!("Failed to create snapshot dir: {}", e))?;
+        let mut manifest = SnapshotManifest::from_snapshot(snapshot);
+        manifest.base_height = base.map(|b| b.height);
+        if let Some(b) = base {
+            manifest.removed_accounts = b
+                .balances
+                .keys()
+                .filter(|address| !snapshot.balances.contains_key(*address))
+                .cloned()
+                .collect();
+            manifest.removed_accounts.sort();
+        }
+        let mut writer = SnapshotWriter::create(dir, manifest)?;
+        let mut addresses: Vec<&String> = snapshot.balances.keys().collect();
+        addresses.sort();
+        for address in addresses {
+            let balance = snapshot.balances[address];
+            let nonce = snapshot.nonces.get(address).copied().unwrap_or(0);
+            let locked = snapshot.locked.get(address).cloned().unwrap_or_default();
+            if let Some(b) = base {
+                let unchanged = b.balances.get(address) == Some(&balance)
+                    && b.nonces.get(address).copied().unwrap_or(0) == nonce
+                    && b.locked.get(address).cloned().unwrap_or_default() == locked;
+                if unchanged {
+                    continue;
+                }
+            }
+            writer.write_account(SnapshotAccount {
+                address: address.clone(),
+                balance,
+                nonce,
+                locked,
+            })?;
+        }
+        writer.finish()
+    }
+    fn read_manifest(&self, height: u64) -> Result<SnapshotManifest, String> {
+        let path = snapshot_manifest_path(Path::new(&self.snapshot_dir), height);
+        let data =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read manifest: {}", e))?;
+        serde_json::from_str(&data).map_err(|e| format!("Failed to parse manifest: {}", e))
+    }
+    fn delta_base(&self, height: u64) -> Result<Option<u64>, String> {
+        if self.deltas_per_full == 0 {
+            return Ok(None);
+        }
+        let mut deltas = 0;
+        for h in self.snapshot_heights()?.into_iter().rev() {
+            if h >= height {
+                continue;
+            }
+            let manifest = self.read_manifest(h)?;
+            match manifest.base_height {
+                Some(_) => deltas += 1,
+                None => {
+                    return Ok((deltas < self.deltas_per_full).then_some(h));
+                }
+            }
+        }
+        Ok(None)
+    }
+    fn remove_obsolete_deltas(&self, full_height: u64) -> Result<(), String> {
+        let dir = Path::new(&self.snapshot_dir);
+        for h in self.snapshot_heights()? {
+            if h >= full_height || !self.read_manifest(h)?.is_delta() {
+                continue;
+            }
+            let _ = fs::remove_file(snapshot_chunks_path(dir, h));
+            let _ = fs::remove_file(snapshot_manifest_path(dir, h));
         }
-        let filename = format!("snapshot_{}.json", snapshot.height);
-        let path = dir.join(filename);
-        let data = serde_json::to_string_pretty(snapshot)
-            .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
-        fs::write(&path, data).map_err(|e| format!("Failed to write snapshot: {}", e))?;
+        Ok(())
+    }
+    pub fn compact(&self) -> Result<Option<u64>, String> {
+        let Some(latest) = self.snapshot_heights()?.last().copied() else {
+            return Ok(None);
+        };
+        if !self.read_manifest(latest)?.is_delta() {
+            return Ok(None);
+        }
+        let snapshot = self.load_snapshot(latest)?;
+        self.write_snapshot(&snapshot, None)?;
+        self.remove_obsolete_deltas(latest)?;
         println!(
-            "Snapshot saved: {} ({} accounts)",
-            path.display(),
-            snapshot.balances.len()
+            "Compacted snapshot deltas into full snapshot at height {}",
+            latest
         );
-        Ok(())
+        Ok(Some(latest))
     }
-    pub fn load_latest_snapshot(&self) -> Result<Option<StateSnapshot>, String> {
-        use std::fs;
-        use std::path::Path;
+    pub fn snapshot_heights(&self) -> Result<Vec<u64>, String> {
         let dir = Path::new(&self.snapshot_dir);
         if !dir.exists() {
-            return Ok(None);
+            return Ok(Vec::new());
         }
-        let mut snapshots: Vec<_> = fs::read_dir(dir)
+        let mut heights: Vec<u64> = fs::read_dir(dir)
             .map_err(|e| format!("Failed to read snapshot dir: {}", e))?
             .filter_map(|entry| entry.ok())
-            .filter(|entry| {
-                entry
-                    .path()
-                    .extension()
-                    .map(|e| e == "json")
-                    .unwrap_or(false)
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_prefix("snapshot_")
+                    .and_then(|rest| rest.strip_suffix(".manifest.json"))
+                    .and_then(|h| h.parse().ok())
             })
             .collect();
-        if snapshots.is_empty() {
-            return Ok(None);
+        heights.sort_unstable();
+        Ok(heights)
+    }
+    pub fn load_snapshot(&self, height: u64) -> Result<StateSnapshot, String> {
+        let reader = SnapshotReader::open(Path::new(&self.snapshot_dir), height)?;
+        match reader.manifest().base_height {
+            Some(base_height) => {
+                let base = self.load_snapshot(base_height)?;
+                reader.read_over(Some(base))
+            }
+            None => reader.read_over(None),
         }
-        snapshots.sort_by(|a, b| b.path().cmp(&a.path()));
-        let latest_path = snapshots[0].path();
-        let data = fs::read_to_string(&latest_path)
-            .map_err(|e| format!("Failed to read snapshot: {}", e))?;
-        let snapshot: StateSnapshot =
-            serde_json::from_str(&data).map_err(|e| format!("Failed to parse snapshot: {}", e))?;
-        if !snapshot.verify() {
-            return Err("Snapshot integrity check failed".to_string());
+    }
+    pub fn export_snapshot(
+        &self,
+        height: Option<u64>,
+        out: &Path,
+    ) -> Result<SnapshotManifest, String> {
+        let height = match height {
+            Some(h) => h,
+            None => self
+                .snapshot_heights()?
+                .last()
+                .copied()
+                .ok_or("No snapshots available to export")?,
+        };
+        let snapshot = self.load_snapshot(height)?;
+        let staging = Path::new(&self.snapshot_dir).join(format!("export_{}", height));
+        let staging_manager = PruningManager::new(
+            self.min_blocks_to_keep,
+            self.snapshot_interval,
+            staging.to_string_lossy().to_string(),
+        )
+        .with_deltas_per_full(0);
+        let result = staging_manager
+            .write_snapshot(&snapshot, None)
+            .and_then(|manifest| {
+                let manifest_bytes = serde_json::to_vec(&manifest)
+                    .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+                let mut chunks = File::open(snapshot_chunks_path(&staging, height))
+                    .map_err(|e| format!("Failed to open snapshot file: {}", e))?;
+                let mut file = BufWriter::new(
+                    File::create(out)
+                        .map_err(|e| format!("Failed to create export file: {}", e))?,
+                );
+                file.write_all(SNAPSHOT_EXPORT_MAGIC)
+                    .and_then(|_| file.write_all(&(manifest_bytes.len() as u32).to_le_bytes()))
+                    .and_then(|_| file.write_all(&manifest_bytes))
+                    .and_then(|_| std::io::copy(&mut chunks, &mut file).map(|_| ()))
+                    .and_then(|_| file.flush())
+                    .map_err(|e| format!("Failed to write export file: {}", e))?;
+                Ok(manifest)
+            });
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+    pub fn import_snapshot(
+        &self,
+        file: &Path,
+        anchor: &SnapshotAnchor,
+    ) -> Result<StateSnapshot, String> {
+        let mut input = BufReader::new(
+            File::open(file).map_err(|e| format!("Failed to open import file: {}", e))?,
+        );
+        let (manifest, manifest_bytes) = read_export_manifest(&mut input)?;
+        if manifest.is_delta() {
+            return Err("Delta snapshots cannot be imported".to_string());
+        }
+        if manifest.chain_id != anchor.chain_id {
+            return Err(format!(
+                "Snapshot is for chain {}, expected {}",
+                manifest.chain_id, anchor.chain_id
+            ));
+        }
+        if let Some(hash) = &anchor.block_hash {
+            if &manifest.block_hash != hash {
+                return Err(format!(
+                    "Snapshot block {} at height {} is not the finalized block {}",
+                    manifest.block_hash, manifest.height, hash
+                ));
+            }
+        }
+
+        let dir = Path::new(&self.snapshot_dir);
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create snapshot dir: {}", e))?;
+        let height = manifest.height;
+        let result = (|| {
+            let mut chunks = File::create(snapshot_chunks_path(dir, height))
+                .map_err(|e| format!("Failed to create snapshot file: {}", e))?;
+            std::io::copy(&mut input, &mut chunks)
+                .map_err(|e| format!("Failed to write snapshot file: {}", e))?;
+            fs::write(snapshot_manifest_path(dir, height), &manifest_bytes)
+                .map_err(|e| format!("Failed to write manifest: {}", e))?;
+            let snapshot = SnapshotReader::open(dir, height)?.read_snapshot()?;
+            let state_root = snapshot.state_root()?;
+            if state_root != anchor.state_root {
+                return Err(format!(
+                    "State root mismatch at height {}: snapshot {}, expected {}",
+                    height, state_root, anchor.state_root
+                ));
+            }
+            Ok(snapshot)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(snapshot_chunks_path(dir, height));
+            let _ = fs::remove_file(snapshot_manifest_path(dir, height));
         }
+        result
+    }
+    // The latest snapshot whose commitment `committed` returns, i.e. whose
+    // epoch start block is on chain. Snapshots the chain has not committed
+    // to are never served, and one that differs from its commitment is an
+    // error.
+    pub fn load_committed_snapshot(
+        &self,
+        committed: impl Fn(u64) -> Option<String>,
+    ) -> Result<Option<StateSnapshot>, String> {
+        for height in self.snapshot_heights()?.into_iter().rev() {
+            let Some(commitment) = committed(height) else {
+                continue;
+            };
+            let snapshot = self.load_snapshot(height)?;
+            if snapshot.commitment() != commitment {
+                return Err(format!(
+                    "Snapshot at height {} does not match the hash committed on chain",
+                    height
+                ));
+            }
+            return Ok(Some(snapshot));
+        }
+        Ok(None)
+    }
+    pub fn load_latest_snapshot(&self) -> Result<Option<StateSnapshot>, String> {
+        let Some(height) = self.snapshot_heights()?.last().copied() else {
+            return Ok(None);
+        };
+        let snapshot = self.load_snapshot(height)?;
         println!("Loaded snapshot at height {}", snapshot.height);
         Ok(Some(snapshot))
     }
@@ -212,7 +987,317 @@ mod tests {
         let manager = PruningManager::new(100, 1000, "./snapshots".to_string());
         assert!(!manager.should_create_snapshot(0));
         assert!(!manager.should_create_snapshot(500));
-        assert!(manager.should_create_snapshot(1000));
-        assert!(manager.should_create_snapshot(2000));
+        assert!(!manager.should_create_snapshot(1000));
+        assert!(manager.should_create_snapshot(1023));
+        assert!(manager.should_create_snapshot(2047));
+        let manager = PruningManager::new(100, 64, "./snapshots".to_string());
+        assert!(manager.should_create_snapshot(63));
+        assert!(!manager.should_create_snapshot(64));
+    }
+    fn sample_snapshot() -> StateSnapshot {
+        let mut account_state = AccountState::new();
+        for i in 0..200u64 {
+            account_state.add_balance(&format!("addr{}", i), i + 1);
+        }
+        StateSnapshot::from_state(
+            100,
+            "blockhash".to_string(),
+            1337,
+            &account_state,
+            0,
+            "genhash".to_string(),
+        )
+    }
+    #[test]
+    fn test_snapshot_chunk_roundtrip() {
+        let snapshot = sample_snapshot();
+        let chunks = snapshot.to_chunks(512);
+        assert!(chunks.len() > 1);
+        let mut download = SnapshotDownload::new(0);
+        for c in chunks.iter().rev() {
+            download
+                .add_chunk(100, c.index, c.total, &c.hash, c.data.clone())
+                .unwrap();
+        }
+        assert!(download.is_complete());
+        let restored = download.assemble().unwrap();
+        assert_eq!(restored.snapshot_hash, snapshot.snapshot_hash);
+        assert_eq!(restored.balances, snapshot.balances);
+    }
+    #[test]
+    fn test_snapshot_chunk_tampered_rejected() {
+        let snapshot = sample_snapshot();
+        let mut chunks = snapshot.to_chunks(512);
+        chunks[0].data[0] ^= 0xff;
+        let mut download = SnapshotDownload::new(100);
+        let c = &chunks[0];
+        assert!(download
+            .add_chunk(100, c.index, c.total, &c.hash, c.data.clone())
+            .is_err());
+        assert_eq!(download.received(), 0);
+    }
+    #[test]
+    fn test_snapshot_download_resume_index() {
+        let snapshot = sample_snapshot();
+        let chunks = snapshot.to_chunks(512);
+        let mut download = SnapshotDownload::new(100);
+        for c in chunks.iter().take(3) {
+            download
+                .add_chunk(100, c.index, c.total, &c.hash, c.data.clone())
+                .unwrap();
+        }
+        assert_eq!(download.next_missing_index(), 3);
+        assert!(!download.is_complete());
+        assert!(download.assemble().is_err());
+        for c in chunks.iter().skip(3) {
+            download
+                .add_chunk(100, c.index, c.total, &c.hash, c.data.clone())
+                .unwrap();
+        }
+        assert!(download.assemble().is_ok());
+    }
+    fn temp_snapshot_dir(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("budlum_{}_{}", name, nanos))
+    }
+    #[test]
+    fn test_snapshot_v2_roundtrip() {
+        let dir = temp_snapshot_dir("snap_v2");
+        let mut account_state = AccountState::new();
+        for i in 0..(ACCOUNTS_PER_CHUNK as u64 + 50) {
+            account_state.add_balance(&format!("addr{}", i), i + 1);
+        }
+        account_state.validators.insert(
+            "val".into(),
+            crate::account::Validator::new("val".into(), 0),
+        );
+        let snapshot = StateSnapshot::from_state(
+            10,
+            "hash".to_string(),
+            1337,
+            &account_state,
+            0,
+            "genhash".to_string(),
+        );
+        let manager =
+            PruningManager::new(100, 10, dir.to_string_lossy().to_string()).with_deltas_per_full(0);
+        manager.save_snapshot(&snapshot).unwrap();
+
+        let reader = SnapshotReader::open(&dir, 10).unwrap();
+        assert_eq!(reader.manifest().chunk_hashes.len(), 2);
+        assert!(reader.manifest().verify_chunk_root());
+
+        let loaded = manager.load_latest_snapshot().unwrap().unwrap();
+        assert_eq!(loaded.snapshot_hash, snapshot.snapshot_hash);
+        assert_eq!(loaded.balances, snapshot.balances);
+        assert_eq!(
+            loaded.state_root().unwrap(),
+            account_state.calculate_state_root()
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_snapshot_v2_detects_corruption() {
+        let dir = temp_snapshot_dir("snap_v2_corrupt");
+        let snapshot = sample_snapshot();
+        let manager = PruningManager::new(100, 10, dir.to_string_lossy().to_string());
+        manager.save_snapshot(&snapshot).unwrap();
+
+        let path = snapshot_chunks_path(&dir, snapshot.height);
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        fs::write(&path, data).unwrap();
+        assert!(manager.load_snapshot(snapshot.height).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_latest_snapshot_uses_numeric_height() {
+        let dir = temp_snapshot_dir("snap_latest");
+        let manager = PruningManager::new(100, 10, dir.to_string_lossy().to_string());
+        for height in [200u64, 1000] {
+            let snapshot = StateSnapshot::from_state(
+                height,
+                "hash".to_string(),
+                1337,
+                &AccountState::new(),
+                0,
+                "genhash".to_string(),
+            );
+            manager.save_snapshot(&snapshot).unwrap();
+        }
+        assert_eq!(manager.snapshot_heights().unwrap(), vec![200, 1000]);
+        assert!(manager.read_manifest(1000).unwrap().is_delta());
+        assert_eq!(
+            manager.load_latest_snapshot().unwrap().unwrap().height,
+            1000
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_serves_only_committed_snapshots() {
+        let dir = temp_snapshot_dir("snap_committed");
+        let manager = PruningManager::new(100, 32, dir.to_string_lossy().to_string());
+        let mut account_state = AccountState::new();
+        let mut commitments = HashMap::new();
+        for height in [31u64, 63] {
+            account_state.add_balance("alice", height);
+            let snapshot = StateSnapshot::from_state(
+                height,
+                format!("hash{}", height),
+                1337,
+                &account_state,
+                height,
+                "final".to_string(),
+            );
+            commitments.insert(height, snapshot.commitment());
+            manager.save_snapshot(&snapshot).unwrap();
+        }
+        let none = |_: u64| None;
+        assert!(manager.load_committed_snapshot(none).unwrap().is_none());
+        let only_31 = |h: u64| (h == 31).then(|| commitments[&31].clone());
+        let served = manager.load_committed_snapshot(only_31).unwrap().unwrap();
+        assert_eq!(served.height, 31);
+        let all = |h: u64| commitments.get(&h).cloned();
+        let served = manager.load_committed_snapshot(all).unwrap().unwrap();
+        assert_eq!(served.height, 63);
+        let forged = |h: u64| (h == 63).then(|| commitments[&31].clone());
+        assert!(manager.load_committed_snapshot(forged).is_err());
+
+        let later_view = StateSnapshot::from_state(
+            63,
+            "hash63".to_string(),
+            1337,
+            &account_state,
+            0,
+            String::new(),
+        );
+        assert_eq!(later_view.commitment(), commitments[&63]);
+        assert_ne!(later_view.snapshot_hash, served.snapshot_hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_delta_snapshots_and_compaction() {
+        let dir = temp_snapshot_dir("snap_delta");
+        let manager =
+            PruningManager::new(100, 10, dir.to_string_lossy().to_string()).with_deltas_per_full(2);
+        let mut state = AccountState::new();
+        for i in 0..100u64 {
+            state.add_balance(&format!("addr{}", i), 1000);
+        }
+        let take = |state: &AccountState, height: u64| {
+            StateSnapshot::from_state(
+                height,
+                format!("hash{}", height),
+                1337,
+                state,
+                0,
+                "genhash".to_string(),
+            )
+        };
+        manager.save_snapshot(&take(&state, 10)).unwrap();
+        state.add_balance("addr1", 5);
+        state.add_balance("new_addr", 7);
+        state.add_validator("addr4".into(), 500);
+        manager.save_snapshot(&take(&state, 20)).unwrap();
+
+        let delta = manager.read_manifest(20).unwrap();
+        assert_eq!(delta.base_height, Some(10));
+        assert_eq!(delta.account_count, 3);
+        let loaded = manager.load_snapshot(20).unwrap();
+        assert_eq!(loaded.balances.get("addr1"), Some(&1005));
+        assert_eq!(loaded.balances.get("new_addr"), Some(&7));
+        assert_eq!(loaded.balances.len(), 101);
+        assert_eq!(
+            loaded
+                .to_account_state()
+                .unwrap()
+                .locked_stake("addr4", "addr4"),
+            500
+        );
+
+        state.add_balance("addr2", 1);
+        manager.save_snapshot(&take(&state, 30)).unwrap();
+        assert_eq!(manager.read_manifest(30).unwrap().base_height, Some(10));
+
+        assert_eq!(manager.compact().unwrap(), Some(30));
+        assert!(!manager.read_manifest(30).unwrap().is_delta());
+        assert_eq!(manager.snapshot_heights().unwrap(), vec![10, 30]);
+        let compacted = manager.load_latest_snapshot().unwrap().unwrap();
+        assert_eq!(compacted.balances.get("addr2"), Some(&1001));
+
+        state.add_balance("addr3", 1);
+        manager.save_snapshot(&take(&state, 40)).unwrap();
+        assert_eq!(manager.read_manifest(40).unwrap().base_height, Some(30));
+
+        // A swept account leaves a tombstone in the next delta.
+        state.accounts.remove("addr5");
+        manager.save_snapshot(&take(&state, 50)).unwrap();
+        let delta = manager.read_manifest(50).unwrap();
+        assert_eq!(delta.base_height, Some(30));
+        assert_eq!(delta.removed_accounts, vec!["addr5".to_string()]);
+        let loaded = manager.load_snapshot(50).unwrap();
+        assert!(!loaded.balances.contains_key("addr5"));
+        assert!(!loaded.nonces.contains_key("addr5"));
+        assert_eq!(loaded.state_root(), Ok(state.calculate_state_root()));
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_snapshot_export_import() {
+        let src_dir = temp_snapshot_dir("snap_export_src");
+        let dst_dir = temp_snapshot_dir("snap_export_dst");
+        let source = PruningManager::new(100, 10, src_dir.to_string_lossy().to_string());
+        let target = PruningManager::new(100, 10, dst_dir.to_string_lossy().to_string());
+        let mut state = AccountState::new();
+        state.add_balance("alice", 100);
+        let base = StateSnapshot::from_state(10, "h10".into(), 1337, &state, 0, "g".into());
+        source.save_snapshot(&base).unwrap();
+        state.add_balance("bob", 50);
+        let snapshot = StateSnapshot::from_state(20, "h20".into(), 1337, &state, 0, "g".into());
+        source.save_snapshot(&snapshot).unwrap();
+
+        let file = src_dir.join("export.bin");
+        let manifest = source.export_snapshot(None, &file).unwrap();
+        assert_eq!(manifest.height, 20);
+        assert!(!manifest.is_delta());
+
+        let anchor = |chain_id, state_root: &str, block_hash: &str| SnapshotAnchor {
+            chain_id,
+            state_root: state_root.into(),
+            block_hash: Some(block_hash.into()),
+        };
+        let root = state.calculate_state_root();
+        assert!(target
+            .import_snapshot(&file, &anchor(1337, "deadbeef", "h20"))
+            .is_err());
+        assert!(target
+            .import_snapshot(&file, &anchor(1, &root, "h20"))
+            .is_err());
+        assert!(target
+            .import_snapshot(&file, &anchor(1337, &root, "h19"))
+            .is_err());
+        assert!(target.snapshot_heights().unwrap().is_empty());
+
+        let imported = target
+            .import_snapshot(&file, &anchor(1337, &root, "h20"))
+            .unwrap();
+        assert_eq!(imported.balances, snapshot.balances);
+        assert_eq!(target.snapshot_heights().unwrap(), vec![20]);
+
+        // A record length past the limit is refused before it is allocated.
+        let mut data = fs::read(&file).unwrap();
+        data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let huge = src_dir.join("huge.bin");
+        fs::write(&huge, &data).unwrap();
+        assert!(read_export_file_manifest(&huge)
+            .unwrap_err()
+            .contains("exceeds"));
+        let bomb = zstd::encode_all(&vec![0u8; MAX_SNAPSHOT_CHUNK_BYTES + 1][..], 3).unwrap();
+        assert!(decompress_chunk(&bomb).unwrap_err().contains("inflates"));
+        let _ = fs::remove_dir_all(&src_dir);
+        let _ = fs::remove_dir_all(&dst_dir);
     }
 }
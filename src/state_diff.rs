@@ -0,0 +1,193 @@
+use crate::account::{Account, AccountState, Validator};
+use crate::slashing::SlashingType;
+use crate::Block;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountChange {
+    pub address: String,
+    pub before: Option<Account>,
+    pub after: Option<Account>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidatorChange {
+    pub address: String,
+    pub before: Option<Validator>,
+    pub after: Option<Validator>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlashRecord {
+    pub validator: String,
+    pub offense: SlashingType,
+    pub stake_before: u64,
+    pub stake_after: u64,
+}
+
+// Everything a block changed in account state, so indexers can follow the
+// chain without re-executing it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDiff {
+    pub height: u64,
+    pub hash: String,
+    pub accounts: Vec<AccountChange>,
+    pub validators: Vec<ValidatorChange>,
+    pub slashes: Vec<SlashRecord>,
+}
+
+// What a state diff subscriber receives: each block's diff, and word that
+// a reorg took a block it already saw off the chain.
+#[derive(Debug, Clone)]
+pub enum StateDiffEvent {
+    Applied(Arc<StateDiff>),
+    Reverted { height: u64, hash: String },
+}
+
+// The validator set before a block is applied. Accounts are not copied:
+// the state records the prior value of each account the block writes.
+#[derive(Debug, Clone, Default)]
+pub struct StateView {
+    validators: HashMap<String, Validator>,
+}
+
+impl StateView {
+    pub fn capture(state: &AccountState) -> Self {
+        StateView {
+            validators: state.validators.clone(),
+        }
+    }
+}
+
+fn changed<T: Clone + PartialEq>(
+    before: &HashMap<String, T>,
+    after: &HashMap<String, T>,
+) -> Vec<(String, Option<T>, Option<T>)> {
+    let addresses: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    addresses
+        .into_iter()
+        .filter(|address| before.get(*address) != after.get(*address))
+        .map(|address| {
+            (
+                address.clone(),
+                before.get(address).cloned(),
+                after.get(address).cloned(),
+            )
+        })
+        .collect()
+}
+
+impl StateDiff {
+    // `after` must be the state the block was applied to, so its touched
+    // accounts are the block's.
+    pub fn compute(block: &Block, before: &StateView, after: &AccountState) -> Self {
+        let touched: BTreeMap<&String, &Option<Account>> =
+            after.touched_accounts().iter().collect();
+        let accounts = touched
+            .into_iter()
+            .filter_map(|(address, before)| {
+                let after = after.accounts.get(address).cloned();
+                (*before != after).then(|| AccountChange {
+                    address: address.clone(),
+                    before: before.clone(),
+                    after,
+                })
+            })
+            .collect();
+        let validators = changed(&before.validators, &after.validators)
+            .into_iter()
+            .map(|(address, before, after)| ValidatorChange {
+                address,
+                before,
+                after,
+            })
+            .collect();
+
+        let stake = |validators: &HashMap<String, Validator>, address: &str| {
+            validators.get(address).map(|v| v.stake).unwrap_or(0)
+        };
+        let offenders = block
+            .slashing_evidence
+            .iter()
+            .flatten()
+            .filter_map(|e| e.header1.producer.clone())
+            .map(|validator| (validator, SlashingType::DoubleSign))
+            .chain(
+                block
+                    .vote_evidence
+                    .iter()
+                    .flatten()
+                    .map(|e| (e.validator.clone(), e.offense_type.clone())),
+            );
+        let slashes = offenders
+            .map(|(validator, offense)| SlashRecord {
+                stake_before: stake(&before.validators, &validator),
+                stake_after: stake(&after.validators, &validator),
+                validator,
+                offense,
+            })
+            .collect();
+
+        StateDiff {
+            height: block.index,
+            hash: block.hash.clone(),
+            accounts,
+            validators,
+            slashes,
+        }
+    }
+
+    // Balance of every changed account after the block (0 if removed).
+    pub fn balances(&self) -> Vec<(String, u64)> {
+        self.accounts
+            .iter()
+            .map(|change| {
+                let balance = change.after.as_ref().map(|a| a.balance).unwrap_or(0);
+                (change.address.clone(), balance)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_diff_lists_changed_accounts_and_validators() {
+        let mut state = AccountState::new();
+        state.add_balance("alice", 100);
+        state.add_balance("carol", 5);
+        state.add_validator("val".into(), 5_000);
+        let before = StateView::capture(&state);
+        state.clear_touched();
+
+        state.add_balance("alice", 20);
+        state.add_balance("bob", 7);
+        state.validators.get_mut("val").unwrap().stake = 4_500;
+        let block = Block::new(1, String::new(), vec![]);
+        let diff = StateDiff::compute(&block, &before, &state);
+
+        assert_eq!(diff.height, 1);
+        let addresses: Vec<&str> = diff.accounts.iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(addresses, vec!["alice", "bob"]);
+        assert_eq!(diff.accounts[0].before.as_ref().unwrap().balance, 100);
+        assert_eq!(diff.accounts[0].after.as_ref().unwrap().balance, 120);
+        assert!(diff.accounts[1].before.is_none());
+        assert_eq!(
+            diff.balances(),
+            vec![("alice".to_string(), 120), ("bob".to_string(), 7)]
+        );
+        assert_eq!(diff.validators.len(), 1);
+        assert_eq!(diff.validators[0].after.as_ref().unwrap().stake, 4_500);
+        // Written back to its old value, so not a change.
+        state.clear_touched();
+        state.add_balance("carol", 1);
+        state.get_or_create("carol").balance = 5;
+        let diff = StateDiff::compute(&block, &StateView::capture(&state), &state);
+        assert!(diff.accounts.is_empty());
+        assert!(diff.slashes.is_empty());
+    }
+}
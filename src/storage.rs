@@ -1,36 +1,164 @@
+use crate::block::BlockHeader;
 use crate::Block;
-use sled::Db;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
 use std::str::from_utf8;
-#[derive(Clone, Debug)]
-pub struct Storage {
-    db: Db,
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+pub const STORAGE_COMPACTION_INTERVAL: u64 = 1000;
+pub const STORAGE_KEY_ROUNDS: u32 = 100_000;
+const ENCRYPTION_SALT_KEY: &[u8] = b"ENCRYPTION_SALT";
+const ENCRYPTION_CHECK_KEY: &[u8] = b"ENCRYPTION_CHECK";
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+const SEALED_FILE_MAGIC: &[u8] = b"BUDSEAL1";
+const BALANCE_HISTORY_FORMAT_KEY: &[u8] = b"BALANCE_HISTORY_FORMAT";
+const BLOCK_KEY_FORMAT_KEY: &[u8] = b"BLOCK_KEY_FORMAT";
+
+fn invalid_data(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
 }
-impl Storage {
-    pub fn new(path: &str) -> std::io::Result<Self> {
-        let db = sled::open(path)?;
-        Ok(Storage { db })
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyspaceStats {
+    pub keys: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageStats {
+    pub backend: &'static str,
+    pub size_on_disk: u64,
+    pub keyspaces: BTreeMap<String, KeyspaceStats>,
+    pub orphaned_blocks: u64,
+    pub orphaned_bytes: u64,
+}
+impl StorageStats {
+    pub fn live_bytes(&self) -> u64 {
+        self.keyspaces.values().map(|k| k.bytes).sum()
+    }
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size_on_disk.saturating_sub(self.live_bytes()) + self.orphaned_bytes
+    }
+}
+
+fn keyspace_of(key: &[u8]) -> String {
+    let key = String::from_utf8_lossy(key);
+    match key.split_once(':') {
+        Some((prefix, _)) => prefix.to_string(),
+        None => "META".to_string(),
     }
-    pub fn insert_block(&self, block: &Block) -> std::io::Result<()> {
-        let key = block.hash.clone();
+}
+
+fn block_key(hash: &str) -> Vec<u8> {
+    format!("BLOCK:{}", hash).into_bytes()
+}
+
+// Only the height is needed to tell whether a stored block can still be
+// reorged to.
+#[derive(serde::Deserialize)]
+struct BlockHeight {
+    index: u64,
+}
+
+// One entry per address and height, with the height big-endian so an
+// address's entries sort by height and a lookup is a single range scan.
+fn balance_history_key(address: &str, height: u64) -> Vec<u8> {
+    let mut key = format!("BALANCE_HISTORY:{}:", address).into_bytes();
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
+
+pub trait ChainStore: Send + Sync + std::fmt::Debug {
+    fn backend_name(&self) -> &'static str;
+    fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>>;
+    fn put(&self, key: &[u8], value: &[u8]) -> std::io::Result<()>;
+    fn remove(&self, key: &[u8]) -> std::io::Result<()>;
+    fn flush(&self) -> std::io::Result<()>;
+    fn for_each_entry(&self, f: &mut dyn FnMut(&[u8], &[u8])) -> std::io::Result<()>;
+    // The entry with the greatest key in `start..=end`.
+    fn last_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>>;
+    fn size_on_disk(&self) -> std::io::Result<u64> {
+        Ok(0)
+    }
+    fn sync(&self) -> std::io::Result<()> {
+        self.flush()
+    }
+
+    fn insert_block(&self, block: &Block) -> std::io::Result<()> {
         let val = serde_json::to_vec(block)?;
-        self.db.insert(key, val)?;
+        self.put(&block_key(&block.hash), &val)?;
         let height_key = format!("HEIGHT:{}", block.index);
-        self.db
-            .insert(height_key.as_bytes(), block.hash.as_bytes())?;
-        self.db.flush()?;
+        self.put(height_key.as_bytes(), block.hash.as_bytes())?;
+        self.index_transactions(block)?;
+        self.sync()?;
         Ok(())
     }
-    pub fn get_block(&self, hash: &str) -> std::io::Result<Option<Block>> {
-        if let Some(val) = self.db.get(hash)? {
+    // Transaction hash -> "height:block_hash:index". Blocks off the
+    // canonical chain may have overwritten an entry, so lookups check it
+    // against the height index.
+    fn index_transactions(&self, block: &Block) -> std::io::Result<()> {
+        for (i, tx) in block.transactions.iter().enumerate() {
+            let key = format!("TX:{}", tx.hash);
+            let value = format!("{}:{}:{}", block.index, block.hash, i);
+            self.put(key.as_bytes(), value.as_bytes())?;
+        }
+        Ok(())
+    }
+    fn get_transaction_location(&self, hash: &str) -> std::io::Result<Option<(Block, usize)>> {
+        let key = format!("TX:{}", hash);
+        let Some(val) = self.get(key.as_bytes())? else {
+            return Ok(None);
+        };
+        let text = from_utf8(&val).map_err(|_| invalid_data("transaction index is not utf-8"))?;
+        let mut parts = text.splitn(3, ':');
+        let (Some(height), Some(block_hash), Some(index)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_data("malformed transaction index entry"));
+        };
+        let height: u64 = height
+            .parse()
+            .map_err(|_| invalid_data("malformed transaction index height"))?;
+        let index: usize = index
+            .parse()
+            .map_err(|_| invalid_data("malformed transaction index position"))?;
+        let height_key = format!("HEIGHT:{}", height);
+        if self.get(height_key.as_bytes())?.as_deref() != Some(block_hash.as_bytes()) {
+            return Ok(None);
+        }
+        Ok(self
+            .get_block(block_hash)?
+            .filter(|block| {
+                block
+                    .transactions
+                    .get(index)
+                    .is_some_and(|tx| tx.hash == hash)
+            })
+            .map(|block| (block, index)))
+    }
+    fn get_block(&self, hash: &str) -> std::io::Result<Option<Block>> {
+        if let Some(val) = self.get(&block_key(hash))? {
             let block: Block = serde_json::from_slice(&val)?;
             Ok(Some(block))
         } else {
             Ok(None)
         }
     }
-    pub fn get_block_by_height(&self, height: u64) -> std::io::Result<Option<Block>> {
+    fn get_header(&self, hash: &str) -> std::io::Result<Option<BlockHeader>> {
+        Ok(self.get_block(hash)?.map(|b| BlockHeader::from_block(&b)))
+    }
+    fn get_block_by_height(&self, height: u64) -> std::io::Result<Option<Block>> {
         let height_key = format!("HEIGHT:{}", height);
-        if let Some(hash_bytes) = self.db.get(height_key.as_bytes())? {
+        if let Some(hash_bytes) = self.get(height_key.as_bytes())? {
             let hash = from_utf8(&hash_bytes)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
                 .to_string();
@@ -39,8 +167,8 @@ impl Storage {
             Ok(None)
         }
     }
-    pub fn get_canonical_height(&self) -> std::io::Result<u64> {
-        if let Some(val) = self.db.get("CANONICAL_HEIGHT")? {
+    fn get_canonical_height(&self) -> std::io::Result<u64> {
+        if let Some(val) = self.get(b"CANONICAL_HEIGHT")? {
             let s = from_utf8(&val)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
             Ok(s.parse().unwrap_or(0))
@@ -49,82 +177,105 @@ impl Storage {
         }
     }
 
-    pub fn delete_block(&self, height: u64) -> std::io::Result<()> {
+    fn delete_block(&self, height: u64) -> std::io::Result<()> {
         let key = format!("HEIGHT:{}", height);
-        if let Some(hash_val) = self.db.get(key.as_bytes())? {
-            self.db.remove(&hash_val)?;
-            self.db.remove(key.as_bytes())?;
+        if let Some(hash_val) = self.get(key.as_bytes())? {
+            let hash = String::from_utf8_lossy(&hash_val).into_owned();
+            if let Some(block) = self.get_block(&hash)? {
+                for tx in &block.transactions {
+                    self.remove(format!("TX:{}", tx.hash).as_bytes())?;
+                }
+            }
+            self.remove(&block_key(&hash))?;
+            self.remove(key.as_bytes())?;
             let state_root_key = format!("STATE_ROOT:{}", height);
-            self.db.remove(state_root_key.as_bytes())?;
+            self.remove(state_root_key.as_bytes())?;
             let cert_key = format!("FINALITY_CERT:{}", height);
-            self.db.remove(cert_key.as_bytes())?;
+            self.remove(cert_key.as_bytes())?;
             let qc_key = format!("QC_BLOB:{}", height);
-            self.db.remove(qc_key.as_bytes())?;
-            self.db.flush()?;
+            self.remove(qc_key.as_bytes())?;
+            self.sync()?;
         }
         Ok(())
     }
-    pub fn save_qc_blob(
+    fn save_qc_blob(
         &self,
         height: u64,
         blob: &crate::consensus::qc::QcBlob,
     ) -> std::io::Result<()> {
         let key = format!("QC_BLOB:{}", height);
         let val = serde_json::to_vec(blob)?;
-        self.db.insert(key.as_bytes(), val)?;
-        self.db.flush()?;
+        self.put(key.as_bytes(), &val)?;
+        self.sync()?;
         Ok(())
     }
-    pub fn get_qc_blob(
-        &self,
-        height: u64,
-    ) -> std::io::Result<Option<crate::consensus::qc::QcBlob>> {
+    fn get_qc_blob(&self, height: u64) -> std::io::Result<Option<crate::consensus::qc::QcBlob>> {
         let key = format!("QC_BLOB:{}", height);
-        if let Some(val) = self.db.get(key.as_bytes())? {
+        if let Some(val) = self.get(key.as_bytes())? {
             let blob = serde_json::from_slice(&val)?;
             Ok(Some(blob))
         } else {
             Ok(None)
         }
     }
-    pub fn save_finality_cert(
+    fn save_finality_cert(
         &self,
         height: u64,
         cert: &crate::consensus::finality::FinalityCert,
     ) -> std::io::Result<()> {
         let key = format!("FINALITY_CERT:{}", height);
         let val = serde_json::to_vec(cert)?;
-        self.db.insert(key.as_bytes(), val)?;
-        self.db.flush()?;
+        self.put(key.as_bytes(), &val)?;
+        self.sync()?;
         Ok(())
     }
-    pub fn get_finality_cert(
+    fn get_finality_cert(
         &self,
         height: u64,
     ) -> std::io::Result<Option<crate::consensus::finality::FinalityCert>> {
         let key = format!("FINALITY_CERT:{}", height);
-        if let Some(val) = self.db.get(key.as_bytes())? {
+        if let Some(val) = self.get(key.as_bytes())? {
             let cert = serde_json::from_slice(&val)?;
             Ok(Some(cert))
         } else {
             Ok(None)
         }
     }
-    pub fn save_canonical_height(&self, height: u64) -> std::io::Result<()> {
-        self.db
-            .insert("CANONICAL_HEIGHT", height.to_string().as_bytes())?;
-        self.db.flush()?;
+    fn save_canonical_height(&self, height: u64) -> std::io::Result<()> {
+        self.put(b"CANONICAL_HEIGHT", height.to_string().as_bytes())?;
+        self.sync()?;
+        Ok(())
+    }
+    fn save_finalized(&self, height: u64, hash: &str) -> std::io::Result<()> {
+        let value = format!("{}:{}", height, hash);
+        self.put(b"FINALIZED", value.as_bytes())?;
+        self.flush()?;
         Ok(())
     }
-    pub fn save_state_root(&self, height: u64, state_root: &str) -> std::io::Result<()> {
+    fn get_finalized(&self) -> std::io::Result<Option<(u64, String)>> {
+        let Some(val) = self.get(b"FINALIZED")? else {
+            return Ok(None);
+        };
+        let invalid =
+            |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+        let text = from_utf8(&val).map_err(|_| invalid("finalized marker is not utf-8"))?;
+        let (height, hash) = text
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed finalized marker"))?;
+        let height = height
+            .parse::<u64>()
+            .map_err(|_| invalid("malformed finalized height"))?;
+        Ok(Some((height, hash.to_string())))
+    }
+    fn save_state_root(&self, height: u64, state_root: &str) -> std::io::Result<()> {
         let key = format!("STATE_ROOT:{}", height);
-        self.db.insert(key.as_bytes(), state_root.as_bytes())?;
-        self.db.flush()?;
+        self.put(key.as_bytes(), state_root.as_bytes())?;
+        self.sync()?;
         Ok(())
     }
-    pub fn get_state_root(&self, height: u64) -> std::io::Result<Option<String>> {
+    fn get_state_root(&self, height: u64) -> std::io::Result<Option<String>> {
         let key = format!("STATE_ROOT:{}", height);
-        if let Some(val) = self.db.get(key.as_bytes())? {
+        if let Some(val) = self.get(key.as_bytes())? {
             let root = from_utf8(&val)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
                 .to_string();
@@ -133,20 +284,20 @@ impl Storage {
             Ok(None)
         }
     }
-    pub fn save_last_hash(&self, hash: &str) -> std::io::Result<()> {
-        self.db.insert("LAST", hash.as_bytes())?;
-        self.db.flush()?;
+    fn save_last_hash(&self, hash: &str) -> std::io::Result<()> {
+        self.put(b"LAST", hash.as_bytes())?;
+        self.sync()?;
         Ok(())
     }
-    pub fn get_last_hash(&self) -> std::io::Result<Option<String>> {
-        if let Some(val) = self.db.get("LAST")? {
+    fn get_last_hash(&self) -> std::io::Result<Option<String>> {
+        if let Some(val) = self.get(b"LAST")? {
             let hash = from_utf8(&val).unwrap().to_string();
             Ok(Some(hash))
         } else {
             Ok(None)
         }
     }
-    pub fn load_chain(&self) -> std::io::Result<Vec<Block>> {
+    fn load_chain(&self) -> std::io::Result<Vec<Block>> {
         let mut chain = Vec::new();
         if let Some(mut current_hash) = self.get_last_hash()? {
             while let Ok(Some(block)) = self.get_block(&current_hash) {
@@ -160,7 +311,1092 @@ impl Storage {
         chain.reverse();
         Ok(chain)
     }
-    pub fn db(&self) -> &Db {
-        &self.db
+    // Balance history is stored as one entry per address and height, plus
+    // the list of addresses changed at each height so a reorg can roll the
+    // entries back.
+    fn save_balance_changes(&self, height: u64, balances: &[(String, u64)]) -> std::io::Result<()> {
+        for (address, balance) in balances {
+            self.put(
+                &balance_history_key(address, height),
+                balance.to_string().as_bytes(),
+            )?;
+        }
+        let addresses: Vec<&String> = balances.iter().map(|(address, _)| address).collect();
+        let key = format!("BALANCE_CHANGES:{}", height);
+        self.put(key.as_bytes(), &serde_json::to_vec(&addresses)?)?;
+        self.sync()
+    }
+    fn get_balance_at(&self, address: &str, height: u64) -> std::io::Result<Option<u64>> {
+        let start = balance_history_key(address, 0);
+        let end = balance_history_key(address, height);
+        let Some((_, val)) = self.last_in_range(&start, &end)? else {
+            return Ok(None);
+        };
+        from_utf8(&val)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Some)
+            .ok_or_else(|| invalid_data("malformed balance history entry"))
+    }
+    fn revert_balance_history(&self, from_height: u64, to_height: u64) -> std::io::Result<()> {
+        for height in from_height..=to_height {
+            let changes_key = format!("BALANCE_CHANGES:{}", height);
+            let Some(val) = self.get(changes_key.as_bytes())? else {
+                continue;
+            };
+            let addresses: Vec<String> = serde_json::from_slice(&val)?;
+            for address in addresses {
+                self.remove(&balance_history_key(&address, height))?;
+            }
+            self.remove(changes_key.as_bytes())?;
+        }
+        self.sync()
+    }
+    // Older databases kept each address's history as one JSON list under
+    // `BALANCE_HISTORY:{address}`. Rewrites those once into per-height
+    // entries and returns how many addresses were converted.
+    fn migrate_balance_history(&self) -> std::io::Result<u64> {
+        if self.get(BALANCE_HISTORY_FORMAT_KEY)?.is_some() {
+            return Ok(0);
+        }
+        let mut legacy = Vec::new();
+        self.for_each_entry(&mut |key, value| {
+            if let Some(address) = key.strip_prefix(b"BALANCE_HISTORY:") {
+                if !address.contains(&b':') {
+                    legacy.push((address.to_vec(), value.to_vec()));
+                }
+            }
+        })?;
+        for (address, value) in &legacy {
+            let address = from_utf8(address)
+                .map_err(|_| invalid_data("balance history address is not utf-8"))?;
+            let history: Vec<(u64, u64)> = serde_json::from_slice(value)?;
+            for (height, balance) in history {
+                self.put(
+                    &balance_history_key(address, height),
+                    balance.to_string().as_bytes(),
+                )?;
+            }
+            self.remove(format!("BALANCE_HISTORY:{}", address).as_bytes())?;
+        }
+        self.put(BALANCE_HISTORY_FORMAT_KEY, b"2")?;
+        self.sync()?;
+        Ok(legacy.len() as u64)
+    }
+    fn has_balance_changes(&self, height: u64) -> std::io::Result<bool> {
+        let key = format!("BALANCE_CHANGES:{}", height);
+        Ok(self.get(key.as_bytes())?.is_some())
+    }
+    fn save_balance_history_start(&self, height: u64) -> std::io::Result<()> {
+        self.put(b"BALANCE_HISTORY_START", height.to_string().as_bytes())?;
+        self.sync()
+    }
+    fn get_balance_history_start(&self) -> std::io::Result<Option<u64>> {
+        let Some(val) = self.get(b"BALANCE_HISTORY_START")? else {
+            return Ok(None);
+        };
+        from_utf8(&val)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "malformed balance history start",
+                )
+            })
+    }
+    fn clear_balance_history(&self) -> std::io::Result<()> {
+        let mut keys = vec![b"BALANCE_HISTORY_START".to_vec()];
+        self.for_each_entry(&mut |key, _| {
+            if matches!(
+                keyspace_of(key).as_str(),
+                "BALANCE_HISTORY" | "BALANCE_CHANGES"
+            ) {
+                keys.push(key.to_vec());
+            }
+        })?;
+        for key in keys {
+            self.remove(&key)?;
+        }
+        self.sync()
+    }
+    // Moves bodies of blocks below `height` to a cold tier, if the store has
+    // one, and returns how many were moved.
+    fn archive_before(&self, _height: u64) -> std::io::Result<u64> {
+        Ok(0)
+    }
+    fn put_meta(&self, key: &str, value: &[u8]) -> std::io::Result<()> {
+        self.put(key.as_bytes(), value)?;
+        self.sync()
+    }
+    fn get_meta(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        self.get(key.as_bytes())
+    }
+    fn stats(&self) -> std::io::Result<StorageStats> {
+        let mut stats = StorageStats {
+            backend: self.backend_name(),
+            size_on_disk: self.size_on_disk()?,
+            ..Default::default()
+        };
+        let mut indexed = std::collections::HashSet::new();
+        let mut blocks = Vec::new();
+        self.for_each_entry(&mut |key, value| {
+            let keyspace = keyspace_of(key);
+            let entry = stats.keyspaces.entry(keyspace.clone()).or_default();
+            entry.keys += 1;
+            entry.bytes += (key.len() + value.len()) as u64;
+            match keyspace.as_str() {
+                "HEIGHT" => {
+                    indexed.insert(value.to_vec());
+                }
+                "BLOCK" => blocks.push((
+                    key[b"BLOCK:".len()..].to_vec(),
+                    (key.len() + value.len()) as u64,
+                )),
+                _ => {}
+            }
+        })?;
+        for (hash, size) in blocks {
+            if !indexed.contains(&hash) {
+                stats.orphaned_blocks += 1;
+                stats.orphaned_bytes += size;
+            }
+        }
+        Ok(stats)
+    }
+    // Removes block bodies off the canonical chain at or below the
+    // finalized height. Side branches above it are kept, since a reorg may
+    // still switch to them.
+    fn compact(&self) -> std::io::Result<u64> {
+        let Some((finalized, _)) = self.get_finalized()? else {
+            return Ok(0);
+        };
+        let mut indexed = std::collections::HashSet::new();
+        let mut blocks = Vec::new();
+        self.for_each_entry(&mut |key, value| {
+            if key.starts_with(b"HEIGHT:") {
+                indexed.insert(value.to_vec());
+            } else if let Some(hash) = key.strip_prefix(b"BLOCK:") {
+                let height = serde_json::from_slice::<BlockHeight>(value).map(|b| b.index);
+                if height.is_ok_and(|h| h <= finalized) {
+                    blocks.push(hash.to_vec());
+                }
+            }
+        })?;
+        let mut removed = 0;
+        for hash in blocks {
+            if !indexed.contains(&hash) {
+                self.remove(&[b"BLOCK:".as_slice(), &hash].concat())?;
+                removed += 1;
+            }
+        }
+        self.flush()?;
+        Ok(removed)
+    }
+    // Older databases kept block bodies under their bare hash. Moves each
+    // one whose body hashes back to its key under `BLOCK:` and returns how
+    // many were moved.
+    fn migrate_block_keys(&self) -> std::io::Result<u64> {
+        if self.get(BLOCK_KEY_FORMAT_KEY)?.is_some() {
+            return Ok(0);
+        }
+        let mut legacy = Vec::new();
+        self.for_each_entry(&mut |key, value| {
+            if key.len() != 64 || !key.iter().all(u8::is_ascii_hexdigit) {
+                return;
+            }
+            if let Ok(block) = serde_json::from_slice::<Block>(value) {
+                if block.hash.as_bytes() == key {
+                    legacy.push((block.hash, value.to_vec()));
+                }
+            }
+        })?;
+        for (hash, value) in &legacy {
+            self.put(&block_key(hash), value)?;
+            self.remove(hash.as_bytes())?;
+        }
+        self.put(BLOCK_KEY_FORMAT_KEY, b"2")?;
+        self.sync()?;
+        Ok(legacy.len() as u64)
+    }
+    // Rebuilds the height, state root and transaction indexes and the
+    // canonical markers from the stored block bodies alone. The tip is the
+    // `LAST` block if its body survived, otherwise the highest stored block.
+    // The chain is followed back through parent hashes until a parent is
+    // missing, so pruned databases reindex down to their oldest block.
+    fn reindex(&self) -> std::io::Result<ReindexReport> {
+        let mut report = ReindexReport::default();
+        let mut blocks: HashMap<String, Block> = HashMap::new();
+        let mut stale_keys = Vec::new();
+        self.for_each_entry(&mut |key, value| match keyspace_of(key).as_str() {
+            "BLOCK" => match serde_json::from_slice::<Block>(value) {
+                Ok(block) if block_key(&block.hash) == key => {
+                    blocks.insert(block.hash.clone(), block);
+                }
+                _ => report.undecodable += 1,
+            },
+            "HEIGHT" | "STATE_ROOT" | "TX" => stale_keys.push(key.to_vec()),
+            _ => {}
+        })?;
+        report.blocks = blocks.len() as u64;
+
+        let last = self
+            .get_last_hash()
+            .ok()
+            .flatten()
+            .filter(|hash| blocks.contains_key(hash));
+        let tip = last.or_else(|| {
+            blocks
+                .values()
+                .max_by(|a, b| a.index.cmp(&b.index).then_with(|| b.hash.cmp(&a.hash)))
+                .map(|block| block.hash.clone())
+        });
+        let mut canonical = Vec::new();
+        let mut current = tip.as_deref().and_then(|hash| blocks.get(hash));
+        while let Some(block) = current {
+            canonical.push(block);
+            current = match block.index.checked_sub(1) {
+                Some(parent_height) => blocks
+                    .get(&block.previous_hash)
+                    .filter(|parent| parent.index == parent_height),
+                None => None,
+            };
+        }
+        canonical.reverse();
+        if let (Some((height, hash)), Some(first)) = (self.get_finalized()?, canonical.first()) {
+            let finalized = height
+                .checked_sub(first.index)
+                .and_then(|offset| canonical.get(offset as usize));
+            if finalized.is_some_and(|block| block.hash != hash) {
+                return Err(invalid_data(format!(
+                    "stored blocks do not lead to the finalized block {} at height {}",
+                    hash, height
+                )));
+            }
+        }
+
+        for key in &stale_keys {
+            self.remove(key)?;
+        }
+        for block in &canonical {
+            let height_key = format!("HEIGHT:{}", block.index);
+            self.put(height_key.as_bytes(), block.hash.as_bytes())?;
+            if !block.state_root.is_empty() {
+                let state_root_key = format!("STATE_ROOT:{}", block.index);
+                self.put(state_root_key.as_bytes(), block.state_root.as_bytes())?;
+            }
+            self.index_transactions(block)?;
+            report.transactions += block.transactions.len() as u64;
+        }
+        if let Some(tip) = canonical.last() {
+            self.put(b"CANONICAL_HEIGHT", tip.index.to_string().as_bytes())?;
+            self.put(b"LAST", tip.hash.as_bytes())?;
+            report.lowest_height = canonical.first().map(|block| block.index);
+            report.tip = Some((tip.index, tip.hash.clone()));
+        }
+        report.orphaned_blocks = report.blocks - canonical.len() as u64;
+        self.flush()?;
+        Ok(report)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReindexReport {
+    pub blocks: u64,
+    pub undecodable: u64,
+    pub orphaned_blocks: u64,
+    pub transactions: u64,
+    pub lowest_height: Option<u64>,
+    pub tip: Option<(u64, String)>,
+}
+
+#[derive(Debug)]
+pub struct SledStore {
+    db: sled::Db,
+    sync_interval_ms: u64,
+}
+impl SledStore {
+    pub fn open(path: &str, sync_interval_ms: u64) -> std::io::Result<Self> {
+        let db = sled::Config::new()
+            .path(path)
+            .flush_every_ms(if sync_interval_ms > 0 {
+                Some(sync_interval_ms)
+            } else {
+                None
+            })
+            .open()?;
+        Ok(SledStore {
+            db,
+            sync_interval_ms,
+        })
+    }
+}
+impl ChainStore for SledStore {
+    fn backend_name(&self) -> &'static str {
+        "sled"
+    }
+    fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?.map(|v| v.to_vec()))
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+    fn remove(&self, key: &[u8]) -> std::io::Result<()> {
+        self.db.remove(key)?;
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+    fn for_each_entry(&self, f: &mut dyn FnMut(&[u8], &[u8])) -> std::io::Result<()> {
+        for entry in self.db.iter() {
+            let (key, value) = entry?;
+            f(&key, &value);
+        }
+        Ok(())
+    }
+    fn last_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match self.db.range(start..=end).next_back() {
+            Some(entry) => {
+                let (key, value) = entry?;
+                Ok(Some((key.to_vec(), value.to_vec())))
+            }
+            None => Ok(None),
+        }
+    }
+    fn size_on_disk(&self) -> std::io::Result<u64> {
+        Ok(self.db.size_on_disk()?)
+    }
+    fn sync(&self) -> std::io::Result<()> {
+        if self.sync_interval_ms == 0 {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+impl MemoryStore {
+    fn entries(&self) -> std::sync::MutexGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl ChainStore for MemoryStore {
+    fn backend_name(&self) -> &'static str {
+        "memory"
+    }
+    fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.entries().get(key).cloned())
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        self.entries().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+    fn remove(&self, key: &[u8]) -> std::io::Result<()> {
+        self.entries().remove(key);
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn for_each_entry(&self, f: &mut dyn FnMut(&[u8], &[u8])) -> std::io::Result<()> {
+        for (key, value) in self.entries().iter() {
+            f(key, value);
+        }
+        Ok(())
+    }
+    fn last_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .entries()
+            .range(start.to_vec()..=end.to_vec())
+            .next_back()
+            .map(|(key, value)| (key.clone(), value.clone())))
+    }
+}
+
+// Keeps recent data in `hot` and moves old block bodies to `cold`. Reads
+// fall through to `cold`, so callers never see where a block lives. Height
+// index entries, state roots and certificates stay hot.
+#[derive(Debug)]
+pub struct TieredStore {
+    hot: Arc<dyn ChainStore>,
+    cold: Arc<dyn ChainStore>,
+}
+impl TieredStore {
+    const ARCHIVED_KEY: &'static [u8] = b"COLD_ARCHIVED_HEIGHT";
+
+    fn archived_height(&self) -> std::io::Result<u64> {
+        Ok(self
+            .hot
+            .get(Self::ARCHIVED_KEY)?
+            .and_then(|val| from_utf8(&val).ok()?.parse().ok())
+            .unwrap_or(0))
+    }
+}
+impl ChainStore for TieredStore {
+    fn backend_name(&self) -> &'static str {
+        "tiered"
+    }
+    fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        match self.hot.get(key)? {
+            Some(val) => Ok(Some(val)),
+            None => self.cold.get(key),
+        }
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        self.hot.put(key, value)
+    }
+    fn remove(&self, key: &[u8]) -> std::io::Result<()> {
+        self.hot.remove(key)?;
+        self.cold.remove(key)
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        self.hot.flush()?;
+        self.cold.flush()
+    }
+    fn for_each_entry(&self, f: &mut dyn FnMut(&[u8], &[u8])) -> std::io::Result<()> {
+        self.hot.for_each_entry(f)?;
+        self.cold.for_each_entry(f)
+    }
+    fn last_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let hot = self.hot.last_in_range(start, end)?;
+        let cold = self.cold.last_in_range(start, end)?;
+        Ok(match (hot, cold) {
+            (Some(h), Some(c)) if c.0 > h.0 => Some(c),
+            (Some(h), _) => Some(h),
+            (None, cold) => cold,
+        })
+    }
+    fn size_on_disk(&self) -> std::io::Result<u64> {
+        Ok(self.hot.size_on_disk()? + self.cold.size_on_disk()?)
+    }
+    fn sync(&self) -> std::io::Result<()> {
+        self.hot.sync()?;
+        self.cold.sync()
+    }
+    fn archive_before(&self, height: u64) -> std::io::Result<u64> {
+        let start = self.archived_height()?;
+        if height <= start {
+            return Ok(0);
+        }
+        let mut moved = Vec::new();
+        for h in start..height {
+            let height_key = format!("HEIGHT:{}", h);
+            let Some(hash) = self.hot.get(height_key.as_bytes())? else {
+                continue;
+            };
+            let key = block_key(&String::from_utf8_lossy(&hash));
+            if let Some(body) = self.hot.get(&key)? {
+                self.cold.put(&key, &body)?;
+                moved.push(key);
+            }
+        }
+        // Bodies leave the hot tier only once the cold copy is durable.
+        self.cold.flush()?;
+        for key in &moved {
+            self.hot.remove(key)?;
+        }
+        self.hot
+            .put(Self::ARCHIVED_KEY, height.to_string().as_bytes())?;
+        self.hot.sync()?;
+        Ok(moved.len() as u64)
+    }
+}
+
+// Seals every value with AES-256-GCM under a random nonce, using the
+// entry's key as associated data so ciphertexts cannot be moved between
+// keys. Keys stay in plaintext so height lookups and stats keep working.
+pub struct EncryptedStore {
+    inner: Arc<dyn ChainStore>,
+    cipher: Aes256Gcm,
+}
+impl std::fmt::Debug for EncryptedStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedStore")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+impl EncryptedStore {
+    pub fn new(inner: Storage, key: &[u8; 32]) -> std::io::Result<Self> {
+        let store = EncryptedStore {
+            inner: inner.store,
+            cipher: Aes256Gcm::new(key.into()),
+        };
+        match store.inner.get(ENCRYPTION_CHECK_KEY)? {
+            Some(sealed) => {
+                store
+                    .open(ENCRYPTION_CHECK_KEY, &sealed)
+                    .map_err(|_| invalid_data("wrong storage encryption key"))?;
+            }
+            None => {
+                if store.inner.get(b"LAST")?.is_some() {
+                    return Err(invalid_data(
+                        "database already holds unencrypted data; encrypt a fresh database",
+                    ));
+                }
+                let check = store.seal(ENCRYPTION_CHECK_KEY, b"budlum")?;
+                store.inner.put(ENCRYPTION_CHECK_KEY, &check)?;
+                store.inner.flush()?;
+            }
+        }
+        Ok(store)
+    }
+
+    fn seal(&self, key: &[u8], value: &[u8]) -> std::io::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value,
+                    aad: key,
+                },
+            )
+            .map_err(|_| invalid_data("encryption failed"))?;
+        let mut out = nonce.to_vec();
+        out.extend(sealed);
+        Ok(out)
+    }
+
+    fn open(&self, key: &[u8], sealed: &[u8]) -> std::io::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(invalid_data("encrypted value is truncated"));
+        }
+        let (nonce, msg) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg, aad: key })
+            .map_err(|_| {
+                invalid_data(format!(
+                    "cannot decrypt value for key {}",
+                    String::from_utf8_lossy(key)
+                ))
+            })
+    }
+}
+impl ChainStore for EncryptedStore {
+    fn backend_name(&self) -> &'static str {
+        self.inner.backend_name()
+    }
+    fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        self.inner
+            .get(key)?
+            .map(|sealed| self.open(key, &sealed))
+            .transpose()
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        self.inner.put(key, &self.seal(key, value)?)
+    }
+    fn remove(&self, key: &[u8]) -> std::io::Result<()> {
+        self.inner.remove(key)
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+    fn for_each_entry(&self, f: &mut dyn FnMut(&[u8], &[u8])) -> std::io::Result<()> {
+        let mut failure = None;
+        self.inner.for_each_entry(&mut |key, sealed| {
+            if failure.is_some() || key == ENCRYPTION_SALT_KEY || key == ENCRYPTION_CHECK_KEY {
+                return;
+            }
+            match self.open(key, sealed) {
+                Ok(value) => f(key, &value),
+                Err(e) => failure = Some(e),
+            }
+        })?;
+        failure.map_or(Ok(()), Err)
+    }
+    fn last_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match self.inner.last_in_range(start, end)? {
+            Some((key, sealed)) => {
+                let value = self.open(&key, &sealed)?;
+                Ok(Some((key, value)))
+            }
+            None => Ok(None),
+        }
+    }
+    fn size_on_disk(&self) -> std::io::Result<u64> {
+        self.inner.size_on_disk()
+    }
+    fn sync(&self) -> std::io::Result<()> {
+        self.inner.sync()
+    }
+    fn archive_before(&self, height: u64) -> std::io::Result<u64> {
+        self.inner.archive_before(height)
+    }
+}
+
+// Stretches an operator passphrase with PBKDF2-SHA256 and a random
+// per-database salt, stored in plaintext on first use.
+pub fn derive_storage_key(store: &Storage, passphrase: &str) -> std::io::Result<[u8; 32]> {
+    if passphrase.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "storage passphrase is empty",
+        ));
+    }
+    let salt = match store.get(ENCRYPTION_SALT_KEY)? {
+        Some(salt) => salt,
+        None => {
+            let mut salt = vec![0u8; SALT_LEN];
+            rand::rng().fill_bytes(&mut salt);
+            store.put(ENCRYPTION_SALT_KEY, &salt)?;
+            store.flush()?;
+            salt
+        }
+    };
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), &salt, STORAGE_KEY_ROUNDS, &mut key);
+    Ok(key)
+}
+
+// External KMS hook: runs `command` through the shell and reads the
+// 32-byte key as hex from its stdout.
+pub fn storage_key_from_command(command: &str) -> std::io::Result<[u8; 32]> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "storage key command exited with {}",
+            output.status
+        )));
+    }
+    let key = hex::decode(String::from_utf8_lossy(&output.stdout).trim())
+        .map_err(|e| invalid_data(format!("storage key command output is not hex: {}", e)))?;
+    key.try_into()
+        .map_err(|_| invalid_data("storage key command must print a 32-byte key"))
+}
+
+// Where the encryption key comes from. Files outside the database, such as
+// keystore secrets, are sealed with it too.
+#[derive(Clone)]
+pub enum EncryptionSecret {
+    Passphrase(Zeroizing<String>),
+    Key(Zeroizing<[u8; 32]>),
+}
+impl std::fmt::Debug for EncryptionSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptionSecret::Passphrase(_) => f.write_str("Passphrase(..)"),
+            EncryptionSecret::Key(_) => f.write_str("Key(..)"),
+        }
+    }
+}
+impl EncryptionSecret {
+    pub fn storage_key(&self, store: &Storage) -> std::io::Result<[u8; 32]> {
+        match self {
+            EncryptionSecret::Passphrase(passphrase) => derive_storage_key(store, passphrase),
+            EncryptionSecret::Key(key) => Ok(**key),
+        }
+    }
+
+    // A sealed file carries its own salt, so a passphrase is stretched per
+    // file rather than against the database salt.
+    fn file_cipher(&self, salt: &[u8]) -> Aes256Gcm {
+        match self {
+            EncryptionSecret::Passphrase(passphrase) => {
+                let mut key = Zeroizing::new([0u8; 32]);
+                pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+                    passphrase.as_bytes(),
+                    salt,
+                    STORAGE_KEY_ROUNDS,
+                    key.as_mut(),
+                );
+                Aes256Gcm::new(key.as_ref().into())
+            }
+            EncryptionSecret::Key(key) => Aes256Gcm::new(key.as_ref().into()),
+        }
+    }
+
+    // MAGIC || salt || nonce || ciphertext.
+    pub fn seal_file(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = SEALED_FILE_MAGIC.to_vec();
+        let mut salt = [0u8; SALT_LEN];
+        rand::rng().fill_bytes(&mut salt);
+        out.extend_from_slice(&salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        out.extend_from_slice(&nonce);
+        let sealed = self
+            .file_cipher(&salt)
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: data,
+                    aad: SEALED_FILE_MAGIC,
+                },
+            )
+            .map_err(|_| invalid_data("encryption failed"))?;
+        out.extend(sealed);
+        Ok(out)
+    }
+}
+
+pub fn is_sealed_file(data: &[u8]) -> bool {
+    data.starts_with(SEALED_FILE_MAGIC)
+}
+
+// Opens a file written by `EncryptionSecret::seal_file`. Files without the
+// header predate encryption and are returned as they are.
+pub fn open_sealed_file(
+    secret: Option<&EncryptionSecret>,
+    data: &[u8],
+) -> std::io::Result<Zeroizing<Vec<u8>>> {
+    let Some(rest) = data.strip_prefix(SEALED_FILE_MAGIC) else {
+        return Ok(Zeroizing::new(data.to_vec()));
+    };
+    let secret = secret.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "file is encrypted; set db_passphrase_file or db_key_command",
+        )
+    })?;
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return Err(invalid_data("encrypted file is truncated"));
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, msg) = rest.split_at(NONCE_LEN);
+    secret
+        .file_cipher(salt)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg,
+                aad: SEALED_FILE_MAGIC,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| invalid_data("wrong encryption key for file"))
+}
+
+#[derive(Clone, Debug)]
+pub struct Storage {
+    store: Arc<dyn ChainStore>,
+}
+impl Storage {
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        Storage::open_sled(path, 0)
+    }
+    pub fn open_sled(path: &str, sync_interval_ms: u64) -> std::io::Result<Self> {
+        Ok(Storage::from_store(SledStore::open(
+            path,
+            sync_interval_ms,
+        )?))
+    }
+    pub fn memory() -> Self {
+        Storage::from_store(MemoryStore::default())
+    }
+    pub fn from_store(store: impl ChainStore + 'static) -> Self {
+        Storage {
+            store: Arc::new(store),
+        }
+    }
+    // Seals every value written through `inner` with AES-256-GCM under `key`.
+    pub fn encrypted(inner: Storage, key: &[u8; 32]) -> std::io::Result<Self> {
+        Ok(Storage::from_store(EncryptedStore::new(inner, key)?))
+    }
+    pub fn is_encrypted(&self) -> std::io::Result<bool> {
+        Ok(self.get(ENCRYPTION_CHECK_KEY)?.is_some())
+    }
+    // `cold` can be any backend, e.g. sled on a slower disk or an adapter
+    // over object storage implementing `ChainStore`.
+    pub fn tiered(hot: Storage, cold: Storage) -> Self {
+        Storage::from_store(TieredStore {
+            hot: hot.store,
+            cold: cold.store,
+        })
+    }
+}
+impl Deref for Storage {
+    type Target = dyn ChainStore;
+    fn deref(&self) -> &Self::Target {
+        self.store.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::GenesisConfig;
+
+    fn exercise_store(storage: &Storage) {
+        let genesis = GenesisConfig::new(1337).build_genesis_block();
+        storage.insert_block(&genesis).unwrap();
+        storage.save_last_hash(&genesis.hash).unwrap();
+        storage.save_state_root(0, &genesis.state_root).unwrap();
+        storage.save_canonical_height(0).unwrap();
+        storage.put_meta("ACCOUNT_STATE", b"{}").unwrap();
+
+        assert_eq!(
+            storage.get_block_by_height(0).unwrap().unwrap().hash,
+            genesis.hash
+        );
+        assert_eq!(storage.get_header(&genesis.hash).unwrap().unwrap().index, 0);
+        assert_eq!(storage.load_chain().unwrap().len(), 1);
+        assert_eq!(
+            storage.get_state_root(0).unwrap(),
+            Some(genesis.state_root.clone())
+        );
+        assert_eq!(
+            storage.get_meta("ACCOUNT_STATE").unwrap(),
+            Some(b"{}".to_vec())
+        );
+
+        storage.delete_block(0).unwrap();
+        assert!(storage.get_block(&genesis.hash).unwrap().is_none());
+        assert!(storage.get_state_root(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        let storage = Storage::memory();
+        assert_eq!(storage.backend_name(), "memory");
+        exercise_store(&storage);
+    }
+
+    #[test]
+    fn test_stats_and_compaction_remove_orphaned_blocks() {
+        let storage = Storage::memory();
+        let genesis = GenesisConfig::new(1337).build_genesis_block();
+        let mut orphan = genesis.clone();
+        orphan.hash = "ab".repeat(32);
+        storage.insert_block(&orphan).unwrap();
+        storage.insert_block(&genesis).unwrap();
+        storage.save_last_hash(&genesis.hash).unwrap();
+        let mut fork = Block::new(1, genesis.hash.clone(), vec![]);
+        fork.hash = "cd".repeat(32);
+        storage.insert_block(&fork).unwrap();
+        storage.remove(b"HEIGHT:1").unwrap();
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.keyspaces["BLOCK"].keys, 3);
+        assert_eq!(stats.keyspaces["HEIGHT"].keys, 1);
+        assert_eq!(stats.orphaned_blocks, 2);
+        assert!(stats.reclaimable_bytes() > 0);
+
+        // Nothing is final yet, so every branch may still be needed.
+        assert_eq!(storage.compact().unwrap(), 0);
+        storage.save_finalized(0, &genesis.hash).unwrap();
+        assert_eq!(storage.compact().unwrap(), 1);
+        assert!(storage.get_block(&orphan.hash).unwrap().is_none());
+        assert!(storage.get_block(&genesis.hash).unwrap().is_some());
+        assert!(storage.get_block(&fork.hash).unwrap().is_some());
+        assert_eq!(storage.stats().unwrap().orphaned_blocks, 1);
+    }
+
+    #[test]
+    fn test_migrate_block_keys() {
+        let storage = Storage::memory();
+        let genesis = GenesisConfig::new(1337).build_genesis_block();
+        storage
+            .put(
+                genesis.hash.as_bytes(),
+                &serde_json::to_vec(&genesis).unwrap(),
+            )
+            .unwrap();
+        storage.put(&[b'a'; 64], b"not a block").unwrap();
+        assert!(storage.get_block(&genesis.hash).unwrap().is_none());
+
+        assert_eq!(storage.migrate_block_keys().unwrap(), 1);
+        assert!(storage.get_block(&genesis.hash).unwrap().is_some());
+        assert!(storage.get(genesis.hash.as_bytes()).unwrap().is_none());
+        assert!(storage.get(&[b'a'; 64]).unwrap().is_some());
+        assert_eq!(storage.migrate_block_keys().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_indexes_from_blocks() {
+        let storage = Storage::memory();
+        let genesis = GenesisConfig::new(1337).build_genesis_block();
+        let tx = crate::Transaction::new("alice".into(), "bob".into(), 5, vec![]);
+        let block = Block::new(1, genesis.hash.clone(), vec![tx.clone()]);
+        let mut orphan = Block::new(1, genesis.hash.clone(), vec![]);
+        orphan.hash = "f".repeat(64);
+        for b in [&genesis, &orphan, &block] {
+            storage.insert_block(b).unwrap();
+        }
+        let (found, index) = storage.get_transaction_location(&tx.hash).unwrap().unwrap();
+        assert_eq!((found.hash.as_str(), index), (block.hash.as_str(), 0));
+
+        storage.put(b"LAST", b"missing").unwrap();
+        storage.put(b"HEIGHT:1", orphan.hash.as_bytes()).unwrap();
+        storage.put(b"HEIGHT:7", b"stale").unwrap();
+        storage.remove(b"HEIGHT:0").unwrap();
+        storage
+            .remove(format!("TX:{}", tx.hash).as_bytes())
+            .unwrap();
+        assert!(storage
+            .get_transaction_location(&tx.hash)
+            .unwrap()
+            .is_none());
+
+        let report = storage.reindex().unwrap();
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.orphaned_blocks, 1);
+        assert_eq!(report.lowest_height, Some(0));
+        assert_eq!(report.tip, Some((1, block.hash.clone())));
+        assert_eq!(storage.get_last_hash().unwrap(), Some(block.hash.clone()));
+        assert_eq!(storage.get_canonical_height().unwrap(), 1);
+        assert_eq!(
+            storage.get_block_by_height(0).unwrap().unwrap().hash,
+            genesis.hash
+        );
+        assert_eq!(
+            storage.get(b"HEIGHT:1").unwrap(),
+            Some(block.hash.clone().into_bytes())
+        );
+        assert!(storage.get(b"HEIGHT:7").unwrap().is_none());
+        assert!(storage
+            .get_transaction_location(&tx.hash)
+            .unwrap()
+            .is_some());
+        assert_eq!(storage.load_chain().unwrap().len(), 2);
+
+        storage.save_finalized(1, &orphan.hash).unwrap();
+        assert!(storage.reindex().is_err());
+    }
+
+    #[test]
+    fn test_balance_history_lookup_and_revert() {
+        let storage = Storage::memory();
+        assert_eq!(storage.get_balance_history_start().unwrap(), None);
+        storage.save_balance_history_start(0).unwrap();
+        storage
+            .save_balance_changes(0, &[("alice".into(), 100)])
+            .unwrap();
+        storage
+            .save_balance_changes(3, &[("alice".into(), 60), ("bob".into(), 40)])
+            .unwrap();
+        storage
+            .save_balance_changes(5, &[("bob".into(), 0)])
+            .unwrap();
+
+        assert_eq!(storage.get_balance_at("alice", 2).unwrap(), Some(100));
+        assert_eq!(storage.get_balance_at("alice", 9).unwrap(), Some(60));
+        assert_eq!(storage.get_balance_at("bob", 2).unwrap(), None);
+        assert_eq!(storage.get_balance_at("bob", 4).unwrap(), Some(40));
+        assert_eq!(storage.get_balance_at("bob", 5).unwrap(), Some(0));
+
+        storage.revert_balance_history(3, 5).unwrap();
+        assert_eq!(storage.get_balance_at("alice", 9).unwrap(), Some(100));
+        assert_eq!(storage.get_balance_at("bob", 9).unwrap(), None);
+        assert_eq!(storage.get_balance_history_start().unwrap(), Some(0));
+
+        let legacy = Storage::memory();
+        legacy
+            .put(b"BALANCE_HISTORY:carol", b"[[2,10],[7,30]]")
+            .unwrap();
+        assert_eq!(legacy.migrate_balance_history().unwrap(), 1);
+        assert_eq!(legacy.get(b"BALANCE_HISTORY:carol").unwrap(), None);
+        assert_eq!(legacy.get_balance_at("carol", 1).unwrap(), None);
+        assert_eq!(legacy.get_balance_at("carol", 6).unwrap(), Some(10));
+        assert_eq!(legacy.get_balance_at("carol", 7).unwrap(), Some(30));
+        assert_eq!(legacy.migrate_balance_history().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tiered_store_archives_old_blocks() {
+        let hot = Storage::memory();
+        let cold = Storage::memory();
+        let storage = Storage::tiered(hot.clone(), cold.clone());
+        let mut blocks = vec![GenesisConfig::new(1337).build_genesis_block()];
+        for index in 1..4 {
+            blocks.push(Block::new(
+                index,
+                blocks[index as usize - 1].hash.clone(),
+                vec![],
+            ));
+        }
+        for block in &blocks {
+            storage.insert_block(block).unwrap();
+        }
+        storage.save_last_hash(&blocks[3].hash).unwrap();
+
+        assert_eq!(storage.archive_before(2).unwrap(), 2);
+        assert_eq!(storage.archive_before(2).unwrap(), 0);
+        assert!(hot.get_block(&blocks[1].hash).unwrap().is_none());
+        assert!(cold.get_block(&blocks[1].hash).unwrap().is_some());
+        assert!(hot.get_block(&blocks[2].hash).unwrap().is_some());
+        assert_eq!(
+            storage.get_block_by_height(1).unwrap().unwrap().hash,
+            blocks[1].hash
+        );
+        assert_eq!(storage.load_chain().unwrap().len(), 4);
+        assert_eq!(storage.stats().unwrap().orphaned_blocks, 0);
+
+        storage.delete_block(0).unwrap();
+        assert!(cold.get_block(&blocks[0].hash).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_encrypted_store_round_trip() {
+        let raw = Storage::memory();
+        let key = derive_storage_key(&raw, "correct horse").unwrap();
+        assert_eq!(derive_storage_key(&raw, "correct horse").unwrap(), key);
+        assert_ne!(
+            derive_storage_key(&Storage::memory(), "correct horse").unwrap(),
+            key
+        );
+
+        let storage = Storage::encrypted(raw.clone(), &key).unwrap();
+        assert!(storage.is_encrypted().unwrap());
+        exercise_store(&storage);
+        let genesis = GenesisConfig::new(1337).build_genesis_block();
+        storage.insert_block(&genesis).unwrap();
+        let sealed = raw.get(&block_key(&genesis.hash)).unwrap().unwrap();
+        assert!(serde_json::from_slice::<Block>(&sealed).is_err());
+        assert_eq!(
+            storage.get_block(&genesis.hash).unwrap().unwrap().hash,
+            genesis.hash
+        );
+        assert_eq!(storage.stats().unwrap().keyspaces["BLOCK"].keys, 1);
+
+        assert!(Storage::encrypted(raw.clone(), &[7u8; 32]).is_err());
+        assert!(Storage::encrypted(raw, &key).is_ok());
+
+        let plain = Storage::memory();
+        plain.save_last_hash(&genesis.hash).unwrap();
+        assert!(Storage::encrypted(plain, &key).is_err());
+    }
+
+    #[test]
+    fn test_sled_backend() {
+        let path = std::env::temp_dir().join(format!(
+            "budlum_store_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let storage = Storage::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(storage.backend_name(), "sled");
+        exercise_store(&storage);
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&path);
     }
 }
@@ -0,0 +1,253 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const SYNC_STALL_SECS: u64 = 60;
+const RATE_SMOOTHING: f64 = 0.3;
+// A peer's height claim older than this no longer counts.
+pub const PEER_HEIGHT_TTL_MS: u128 = 5 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    Synced,
+    Syncing,
+    Stalled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SyncReport {
+    pub status: SyncStatus,
+    pub current_height: u64,
+    pub best_peer_height: u64,
+    pub remaining: u64,
+    pub blocks_per_sec: f64,
+    pub eta_secs: Option<u64>,
+}
+
+// Local height against the height peers advertise, with a smoothed import
+// rate so operators can tell catching up from stalled. Claims are
+// unauthenticated, so the target is the lower median of each peer's latest
+// claim and claims expire after `PEER_HEIGHT_TTL_MS`.
+#[derive(Debug, Clone, Default)]
+pub struct SyncState {
+    pub current_height: u64,
+    pub best_peer_height: u64,
+    pub blocks_per_sec: f64,
+    last_sample_ms: u128,
+    last_progress_ms: u128,
+    peer_heights: BTreeMap<String, (u64, u128)>,
+}
+
+impl SyncState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe_peer_height(&mut self, peer: &str, height: u64, now_ms: u128) {
+        self.peer_heights.insert(peer.to_string(), (height, now_ms));
+        self.refresh_peer_height(now_ms);
+    }
+
+    fn refresh_peer_height(&mut self, now_ms: u128) {
+        self.peer_heights
+            .retain(|_, (_, seen_ms)| now_ms.saturating_sub(*seen_ms) < PEER_HEIGHT_TTL_MS);
+        let mut heights: Vec<u64> = self.peer_heights.values().map(|(h, _)| *h).collect();
+        heights.sort_unstable();
+        self.best_peer_height = match heights.len() {
+            0 => 0,
+            n => heights[(n - 1) / 2],
+        };
+    }
+
+    pub fn record_progress(&mut self, height: u64, now_ms: u128) {
+        self.refresh_peer_height(now_ms);
+        if self.last_sample_ms == 0 {
+            self.current_height = height;
+            self.last_sample_ms = now_ms;
+            self.last_progress_ms = now_ms;
+            return;
+        }
+        let elapsed_ms = now_ms.saturating_sub(self.last_sample_ms);
+        if elapsed_ms == 0 {
+            return;
+        }
+        let imported = height.saturating_sub(self.current_height);
+        let rate = imported as f64 * 1000.0 / elapsed_ms as f64;
+        self.blocks_per_sec = if self.blocks_per_sec == 0.0 {
+            rate
+        } else {
+            self.blocks_per_sec * (1.0 - RATE_SMOOTHING) + rate * RATE_SMOOTHING
+        };
+        if height != self.current_height {
+            self.last_progress_ms = now_ms;
+        }
+        self.current_height = height;
+        self.last_sample_ms = now_ms;
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.best_peer_height.saturating_sub(self.current_height)
+    }
+
+    pub fn eta_secs(&self) -> Option<u64> {
+        if self.remaining() == 0 {
+            return Some(0);
+        }
+        if self.blocks_per_sec <= 0.0 {
+            return None;
+        }
+        Some((self.remaining() as f64 / self.blocks_per_sec).ceil() as u64)
+    }
+
+    pub fn status(&self, now_ms: u128) -> SyncStatus {
+        if self.remaining() == 0 {
+            SyncStatus::Synced
+        } else if now_ms.saturating_sub(self.last_progress_ms) >= SYNC_STALL_SECS as u128 * 1000 {
+            SyncStatus::Stalled
+        } else {
+            SyncStatus::Syncing
+        }
+    }
+
+    pub fn report(&self, now_ms: u128) -> SyncReport {
+        SyncReport {
+            status: self.status(now_ms),
+            current_height: self.current_height,
+            best_peer_height: self.best_peer_height,
+            remaining: self.remaining(),
+            blocks_per_sec: self.blocks_per_sec,
+            eta_secs: self.eta_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerVersion {
+    pub peer: String,
+    pub version: String,
+    pub commit: String,
+}
+
+// Software version and commit each handshaked peer reported, so operators
+// can watch an upgrade roll out across the validator set before a fork
+// activates. Peers too old to report one are counted as "unknown".
+#[derive(Debug, Clone, Default)]
+pub struct PeerVersions {
+    peers: BTreeMap<String, (String, String)>,
+}
+
+impl PeerVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, peer: &str, version: &str, commit: &str) {
+        let known = |s: &str| {
+            if s.is_empty() {
+                "unknown".to_string()
+            } else {
+                s.to_string()
+            }
+        };
+        self.peers
+            .insert(peer.to_string(), (known(version), known(commit)));
+    }
+
+    pub fn remove(&mut self, peer: &str) -> bool {
+        self.peers.remove(peer).is_some()
+    }
+
+    pub fn peers(&self) -> Vec<PeerVersion> {
+        self.peers
+            .iter()
+            .map(|(peer, (version, commit))| PeerVersion {
+                peer: peer.clone(),
+                version: version.clone(),
+                commit: commit.clone(),
+            })
+            .collect()
+    }
+
+    // Connected peers per "version (commit)".
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for (version, commit) in self.peers.values() {
+            *counts
+                .entry(format!("{} ({})", version, commit))
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_rate_eta_and_stall() {
+        let mut sync = SyncState::new();
+        sync.record_progress(100, 1_000);
+        sync.observe_peer_height("a", 1_100, 1_000);
+        assert_eq!(sync.best_peer_height, 1_100);
+        assert_eq!(sync.eta_secs(), None);
+
+        sync.record_progress(300, 11_000);
+        assert_eq!(sync.blocks_per_sec, 20.0);
+        assert_eq!(sync.remaining(), 800);
+        assert_eq!(sync.eta_secs(), Some(40));
+        assert_eq!(sync.status(11_000), SyncStatus::Syncing);
+
+        sync.record_progress(300, 21_000);
+        assert_eq!(sync.blocks_per_sec, 14.0);
+        assert_eq!(sync.status(71_000), SyncStatus::Stalled);
+
+        sync.record_progress(1_100, 72_000);
+        let report = sync.report(72_000);
+        assert_eq!(report.status, SyncStatus::Synced);
+        assert_eq!(report.eta_secs, Some(0));
+    }
+
+    #[test]
+    fn test_peer_height_is_decaying_median() {
+        let mut sync = SyncState::new();
+        sync.observe_peer_height("a", 100, 0);
+        sync.observe_peer_height("b", 110, 0);
+        sync.observe_peer_height("liar", u64::MAX, 0);
+        assert_eq!(sync.best_peer_height, 110);
+
+        // A later claim replaces the peer's earlier one.
+        sync.observe_peer_height("b", 90, 1_000);
+        assert_eq!(sync.best_peer_height, 100);
+
+        sync.observe_peer_height("c", 120, PEER_HEIGHT_TTL_MS);
+        assert_eq!(sync.best_peer_height, 90);
+        sync.record_progress(50, PEER_HEIGHT_TTL_MS + 1_000);
+        assert_eq!(sync.best_peer_height, 120);
+        sync.record_progress(50, 2 * PEER_HEIGHT_TTL_MS + 1_000);
+        assert_eq!(sync.best_peer_height, 0);
+        assert_eq!(
+            sync.status(2 * PEER_HEIGHT_TTL_MS + 1_000),
+            SyncStatus::Synced
+        );
+    }
+
+    #[test]
+    fn test_peer_versions_count_rollout() {
+        let mut versions = PeerVersions::new();
+        versions.record("a", "0.2.0", "abc123");
+        versions.record("b", "0.2.0", "abc123");
+        versions.record("c", "", "");
+        versions.record("c", "0.1.0", "def456");
+        assert_eq!(versions.peers().len(), 3);
+        let counts = versions.counts();
+        assert_eq!(counts["0.2.0 (abc123)"], 2);
+        assert_eq!(counts["0.1.0 (def456)"], 1);
+
+        assert!(versions.remove("a"));
+        assert!(!versions.remove("a"));
+        versions.record("d", "", "");
+        assert_eq!(versions.counts()["unknown (unknown)"], 1);
+        assert_eq!(versions.counts()["0.2.0 (abc123)"], 1);
+    }
+}
@@ -1,9 +1,19 @@
+use crate::bridge::{BridgeTransfer, InboundKind, SignedInbound};
+use crate::chain_config::ForkId;
 use crate::crypto::{verify_signature, KeyPair};
+use crate::dust::DustProof;
 use crate::hash::calculate_hash;
+use crate::light_client::LightClientMessage;
+use crate::signing::{signing_digest, SigningContext};
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 
 pub const DEFAULT_CHAIN_ID: u64 = 1337;
+pub const MAX_BATCH_OUTPUTS: usize = 256;
+// Signing hash layouts. 0 concatenates the fields as they are, 1 hashes them
+// under a domain tag with every variable-length field length-prefixed.
+pub const TX_SIGNING_LEGACY: u8 = 0;
+pub const TX_SIGNING_V2: u8 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TransactionType {
@@ -11,6 +21,47 @@ pub enum TransactionType {
     Stake,
     Unstake,
     Vote,
+    ClaimRewards,
+    Unjail,
+    Burn,
+    Approve,
+    TransferFrom,
+    Batch,
+    Redelegate,
+    BridgeLock,
+    BridgeBurn,
+    BridgeMint,
+    BridgeRelease,
+    LightClientUpdate,
+    RestoreAccount,
+    RandaoCommit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BatchOutput {
+    pub to: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "action", content = "validator", rename_all = "snake_case")]
+pub enum AuthorityVote {
+    Add(String),
+    Remove(String),
+}
+
+impl AuthorityVote {
+    pub fn target(&self) -> &str {
+        match self {
+            AuthorityVote::Add(address) | AuthorityVote::Remove(address) => address,
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(data).map_err(|e| format!("Invalid authority vote payload: {}", e))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -26,6 +77,17 @@ pub struct Transaction {
     pub signature: Option<Vec<u8>>,
     pub chain_id: u64,
     pub tx_type: TransactionType,
+    // Fork hash of the branch the transaction was signed for; empty before
+    // the chain's first hard fork.
+    #[serde(default)]
+    pub fork_hash: String,
+    // Last block height the transaction may be included at; 0 never expires.
+    #[serde(default)]
+    pub expires_at_height: u64,
+    // Layout of the signing hash; which one a block accepts is set by the
+    // `tx_signing_v2` fork.
+    #[serde(default)]
+    pub signing_version: u8,
 }
 impl Transaction {
     pub fn new(from: String, to: String, amount: u64, data: Vec<u8>) -> Self {
@@ -54,6 +116,349 @@ impl Transaction {
         )
     }
 
+    pub fn new_vote(from: String, vote: &AuthorityVote, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            vote.target().to_string(),
+            0,
+            0,
+            nonce,
+            vote.encode(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::Vote,
+        )
+    }
+
+    // Zero-value self-transfer that replaces a pending tx at the same nonce.
+    pub fn new_cancellation(from: String, nonce: u64, fee: u64, chain_id: u64) -> Self {
+        Self::new_with_chain_id(
+            from.clone(),
+            from,
+            0,
+            fee,
+            nonce,
+            vec![],
+            chain_id,
+            TransactionType::Transfer,
+        )
+    }
+
+    pub fn new_claim_rewards(from: String, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            0,
+            0,
+            nonce,
+            vec![],
+            DEFAULT_CHAIN_ID,
+            TransactionType::ClaimRewards,
+        )
+    }
+
+    pub fn new_unjail(from: String, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            0,
+            0,
+            nonce,
+            vec![],
+            DEFAULT_CHAIN_ID,
+            TransactionType::Unjail,
+        )
+    }
+
+    pub fn new_burn(from: String, amount: u64, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            amount,
+            0,
+            nonce,
+            vec![],
+            DEFAULT_CHAIN_ID,
+            TransactionType::Burn,
+        )
+    }
+
+    pub fn new_approve(owner: String, spender: String, allowance: u64, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            owner,
+            spender,
+            allowance,
+            0,
+            nonce,
+            vec![],
+            DEFAULT_CHAIN_ID,
+            TransactionType::Approve,
+        )
+    }
+
+    pub fn new_transfer_from(
+        spender: String,
+        owner: &str,
+        to: String,
+        amount: u64,
+        nonce: u64,
+    ) -> Self {
+        Self::new_with_chain_id(
+            spender,
+            to,
+            amount,
+            0,
+            nonce,
+            owner.as_bytes().to_vec(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::TransferFrom,
+        )
+    }
+
+    pub fn new_batch(from: String, outputs: &[BatchOutput], nonce: u64) -> Self {
+        let amount = outputs
+            .iter()
+            .map(|o| o.amount)
+            .fold(0u64, u64::saturating_add);
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            amount,
+            0,
+            nonce,
+            serde_json::to_vec(outputs).unwrap_or_default(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::Batch,
+        )
+    }
+
+    pub fn batch_outputs(&self) -> Result<Vec<BatchOutput>, String> {
+        let outputs: Vec<BatchOutput> = serde_json::from_slice(&self.data)
+            .map_err(|e| format!("Invalid batch outputs: {}", e))?;
+        if outputs.is_empty() || outputs.len() > MAX_BATCH_OUTPUTS {
+            return Err(format!(
+                "Batch must have between 1 and {} outputs, got {}",
+                MAX_BATCH_OUTPUTS,
+                outputs.len()
+            ));
+        }
+        let mut total = 0u64;
+        for output in &outputs {
+            if output.to.is_empty() || output.amount == 0 {
+                return Err("Batch output needs a recipient and a non-zero amount".into());
+            }
+            total = total
+                .checked_add(output.amount)
+                .ok_or("Batch total overflows")?;
+        }
+        if total != self.amount {
+            return Err(format!(
+                "Batch amount {} does not match outputs total {}",
+                self.amount, total
+            ));
+        }
+        Ok(outputs)
+    }
+
+    // Batches pay the minimum fee once per output.
+    pub fn fee_units(&self) -> u64 {
+        match self.tx_type {
+            TransactionType::Batch => self.batch_outputs().map_or(1, |o| o.len() as u64),
+            _ => 1,
+        }
+    }
+
+    pub fn new_redelegate(
+        from: String,
+        source: &str,
+        destination: String,
+        amount: u64,
+        nonce: u64,
+    ) -> Self {
+        let data = if source == from {
+            vec![]
+        } else {
+            source.as_bytes().to_vec()
+        };
+        Self::new_with_chain_id(
+            from,
+            destination,
+            amount,
+            0,
+            nonce,
+            data,
+            DEFAULT_CHAIN_ID,
+            TransactionType::Redelegate,
+        )
+    }
+
+    // Redelegations move the sender's own stake unless `data` names the
+    // validator currently holding their delegation.
+    pub fn redelegate_source(&self) -> Result<String, String> {
+        if self.data.is_empty() {
+            return Ok(self.from.clone());
+        }
+        String::from_utf8(self.data.clone())
+            .map_err(|_| "Redelegate TX data must hold the source validator".to_string())
+    }
+
+    pub fn transfer_from_owner(&self) -> Result<String, String> {
+        match String::from_utf8(self.data.clone()) {
+            Ok(owner) if !owner.is_empty() => Ok(owner),
+            _ => Err("TransferFrom TX data must hold the owner address".into()),
+        }
+    }
+
+    // Locked coins stay in BRIDGE_ESCROW until a release message returns them.
+    pub fn new_bridge_lock(
+        from: String,
+        transfer: &BridgeTransfer,
+        amount: u64,
+        nonce: u64,
+    ) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            amount,
+            0,
+            nonce,
+            transfer.encode(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::BridgeLock,
+        )
+    }
+
+    // Burns wrapped coins so the source chain releases the originals.
+    pub fn new_bridge_burn(
+        from: String,
+        transfer: &BridgeTransfer,
+        amount: u64,
+        nonce: u64,
+    ) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            amount,
+            0,
+            nonce,
+            transfer.encode(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::BridgeBurn,
+        )
+    }
+
+    pub fn new_bridge_inbound(relayer: String, inbound: &SignedInbound, nonce: u64) -> Self {
+        let tx_type = match inbound.message.kind {
+            InboundKind::Mint => TransactionType::BridgeMint,
+            InboundKind::Release => TransactionType::BridgeRelease,
+        };
+        Self::new_with_chain_id(
+            relayer,
+            inbound.message.recipient.clone(),
+            inbound.message.amount,
+            0,
+            nonce,
+            inbound.encode(),
+            inbound.message.dest_chain_id,
+            tx_type,
+        )
+    }
+
+    pub fn bridge_transfer(&self) -> Result<BridgeTransfer, String> {
+        BridgeTransfer::decode(&self.data)
+    }
+
+    // The embedded message must match what the transaction moves, and only
+    // its relayer may submit it. Attestations are checked against state.
+    pub fn bridge_inbound(&self) -> Result<SignedInbound, String> {
+        let inbound = SignedInbound::decode(&self.data)?;
+        let message = &inbound.message;
+        let kind = match self.tx_type {
+            TransactionType::BridgeMint => InboundKind::Mint,
+            _ => InboundKind::Release,
+        };
+        if message.kind != kind
+            || message.recipient != self.to
+            || message.amount != self.amount
+            || message.dest_chain_id != self.chain_id
+        {
+            return Err("Bridge message does not match the transaction".into());
+        }
+        if inbound.relayer != self.from {
+            return Err("Bridge message must be submitted by its relayer".into());
+        }
+        if self.to.is_empty() || self.amount == 0 {
+            return Err("Bridge message needs a recipient and a non-zero amount".into());
+        }
+        Ok(inbound)
+    }
+
+    pub fn new_light_client(from: String, message: &LightClientMessage, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            0,
+            0,
+            nonce,
+            message.encode(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::LightClientUpdate,
+        )
+    }
+
+    pub fn light_client_message(&self) -> Result<LightClientMessage, String> {
+        LightClientMessage::decode(&self.data)
+    }
+
+    // Anyone may pay to restore a swept account; the balance goes back to
+    // the address named in the proof.
+    pub fn new_restore_account(from: String, proof: &DustProof, nonce: u64) -> Self {
+        Self::new_with_chain_id(
+            from,
+            proof.account.address.clone(),
+            0,
+            0,
+            nonce,
+            proof.encode(),
+            DEFAULT_CHAIN_ID,
+            TransactionType::RestoreAccount,
+        )
+    }
+
+    pub fn dust_proof(&self) -> Result<DustProof, String> {
+        DustProof::decode(&self.data)
+    }
+
+    // Registers the commitment of a validator's next hash onion. The
+    // generation picks the onion, so it must grow with every re-commit.
+    pub fn new_randao_commit(
+        from: String,
+        generation: u64,
+        commitment: [u8; 32],
+        fee: u64,
+        nonce: u64,
+    ) -> Self {
+        let mut data = generation.to_le_bytes().to_vec();
+        data.extend_from_slice(&commitment);
+        Self::new_with_chain_id(
+            from,
+            String::new(),
+            0,
+            fee,
+            nonce,
+            data,
+            DEFAULT_CHAIN_ID,
+            TransactionType::RandaoCommit,
+        )
+    }
+
+    pub fn randao_commit(&self) -> Result<(u64, [u8; 32]), String> {
+        if self.data.len() != 40 {
+            return Err("RANDAO commit needs an 8-byte generation and a 32-byte commitment".into());
+        }
+        let generation = u64::from_le_bytes(self.data[..8].try_into().unwrap());
+        Ok((generation, self.data[8..].try_into().unwrap()))
+    }
+
     pub fn new_with_chain_id(
         from: String,
         to: String,
@@ -80,10 +485,26 @@ impl Transaction {
             signature: None,
             chain_id,
             tx_type,
+            fork_hash: String::new(),
+            expires_at_height: 0,
+            signing_version: TX_SIGNING_LEGACY,
         };
         tx.hash = tx.calculate_hash();
         tx
     }
+    pub fn with_fork_hash(mut self, fork_hash: String) -> Self {
+        self.fork_hash = fork_hash;
+        self.hash = self.calculate_hash();
+        self
+    }
+    pub fn with_expiry(mut self, expires_at_height: u64) -> Self {
+        self.expires_at_height = expires_at_height;
+        self.hash = self.calculate_hash();
+        self
+    }
+    pub fn is_expired_at(&self, height: u64) -> bool {
+        self.expires_at_height != 0 && height > self.expires_at_height
+    }
     pub fn new_with_fee(
         from: String,
         to: String,
@@ -116,39 +537,90 @@ impl Transaction {
             signature: None,
             chain_id: DEFAULT_CHAIN_ID,
             tx_type: TransactionType::Transfer,
+            fork_hash: String::new(),
+            expires_at_height: 0,
+            signing_version: TX_SIGNING_LEGACY,
         }
     }
-    pub fn signing_hash(&self) -> [u8; 32] {
-        let mut hasher = Sha3_256::new();
-        hasher.update(b"BDLM_TX_V2");
-        hasher.update(self.from.as_bytes());
-        hasher.update(self.to.as_bytes());
-        hasher.update(self.amount.to_le_bytes());
-        hasher.update(self.fee.to_le_bytes());
-        hasher.update(self.nonce.to_le_bytes());
-        hasher.update(&self.data);
-        hasher.update(self.timestamp.to_le_bytes());
-        hasher.update(self.chain_id.to_le_bytes());
-
-        let type_byte = match self.tx_type {
+    pub fn fork_id(&self) -> ForkId {
+        ForkId {
+            chain_id: self.chain_id,
+            fork_hash: self.fork_hash.clone(),
+        }
+    }
+    fn type_byte(&self) -> u8 {
+        match self.tx_type {
             TransactionType::Transfer => 0,
             TransactionType::Stake => 1,
             TransactionType::Unstake => 2,
             TransactionType::Vote => 3,
-        };
-        hasher.update(&[type_byte]);
+            TransactionType::ClaimRewards => 4,
+            TransactionType::Unjail => 5,
+            TransactionType::Burn => 6,
+            TransactionType::Approve => 7,
+            TransactionType::TransferFrom => 8,
+            TransactionType::Batch => 9,
+            TransactionType::Redelegate => 10,
+            TransactionType::BridgeLock => 11,
+            TransactionType::BridgeBurn => 12,
+            TransactionType::BridgeMint => 13,
+            TransactionType::BridgeRelease => 14,
+            TransactionType::LightClientUpdate => 15,
+            TransactionType::RestoreAccount => 16,
+            TransactionType::RandaoCommit => 17,
+        }
+    }
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        if self.signing_version == TX_SIGNING_LEGACY {
+            hasher.update(self.from.as_bytes());
+            hasher.update(self.to.as_bytes());
+            hasher.update(self.amount.to_le_bytes());
+            hasher.update(self.fee.to_le_bytes());
+            hasher.update(self.nonce.to_le_bytes());
+            hasher.update(&self.data);
+            hasher.update(self.timestamp.to_le_bytes());
+            hasher.update([self.type_byte()]);
+            // Only hashed when set, so transactions without an expiry keep
+            // their signatures.
+            if self.expires_at_height != 0 {
+                hasher.update(self.expires_at_height.to_le_bytes());
+            }
+        } else {
+            // Length prefixes keep bytes from moving between fields, and the
+            // expiry is always hashed.
+            hasher.update(b"BDLM_TX_V2");
+            hasher.update([self.signing_version]);
+            for field in [self.from.as_bytes(), self.to.as_bytes(), &self.data] {
+                hasher.update((field.len() as u64).to_le_bytes());
+                hasher.update(field);
+            }
+            hasher.update(self.amount.to_le_bytes());
+            hasher.update(self.fee.to_le_bytes());
+            hasher.update(self.nonce.to_le_bytes());
+            hasher.update(self.timestamp.to_le_bytes());
+            hasher.update([self.type_byte()]);
+            hasher.update(self.expires_at_height.to_le_bytes());
+        }
 
-        hasher.finalize().into()
+        signing_digest(
+            SigningContext::Transaction,
+            &self.fork_id(),
+            &hasher.finalize(),
+        )
     }
     pub fn calculate_hash(&self) -> String {
-        let type_byte = match self.tx_type {
-            TransactionType::Transfer => 0,
-            TransactionType::Stake => 1,
-            TransactionType::Unstake => 2,
-            TransactionType::Vote => 3,
+        let type_byte = self.type_byte();
+        let expiry = match self.expires_at_height {
+            0 => String::new(),
+            height => format!("expires{}", height),
+        };
+        let signing = match self.signing_version {
+            TX_SIGNING_LEGACY => String::new(),
+            version => format!("signing{}", version),
         };
         let data = format!(
-            "{}{}{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}{}{}{}{}",
             self.from,
             self.to,
             self.amount,
@@ -156,7 +628,10 @@ impl Transaction {
             self.nonce,
             hex::encode(&self.data),
             self.timestamp,
-            type_byte
+            type_byte,
+            self.fork_hash,
+            expiry,
+            signing
         );
         calculate_hash(data.as_bytes())
     }
@@ -191,6 +666,10 @@ impl Transaction {
                 return false;
             }
         };
+        if self.signing_version > TX_SIGNING_V2 {
+            println!("Unknown TX signing version {}", self.signing_version);
+            return false;
+        }
         let public_key = match hex::decode(&self.from) {
             Ok(pk) => pk,
             Err(e) => {
@@ -235,7 +714,120 @@ impl Transaction {
                 }
             }
             TransactionType::Unstake => {}
-            TransactionType::Vote => {}
+            TransactionType::Vote => match AuthorityVote::decode(&self.data) {
+                Ok(vote) if vote.target() == self.to && self.amount == 0 => {}
+                Ok(_) => {
+                    println!("Vote TX target must match 'to' and carry no amount");
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
+            TransactionType::ClaimRewards => {
+                if self.amount != 0 {
+                    println!("ClaimRewards TX must not carry an amount");
+                    return false;
+                }
+            }
+            TransactionType::Unjail => {
+                if self.amount != 0 {
+                    println!("Unjail TX must not carry an amount");
+                    return false;
+                }
+            }
+            TransactionType::Burn => {
+                if self.amount == 0 || !self.to.is_empty() {
+                    println!("Burn TX needs an amount and no 'to' address");
+                    return false;
+                }
+            }
+            TransactionType::Approve => {
+                if self.to.is_empty() || self.to == self.from {
+                    println!("Approve TX needs a spender other than the owner");
+                    return false;
+                }
+            }
+            TransactionType::TransferFrom => match self.transfer_from_owner() {
+                Ok(owner) if owner != self.from && !self.to.is_empty() && self.amount > 0 => {}
+                Ok(_) => {
+                    println!(
+                        "TransferFrom TX needs an amount, a recipient and a third-party owner"
+                    );
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
+            TransactionType::Batch => {
+                if let Err(e) = self.batch_outputs() {
+                    println!("{}", e);
+                    return false;
+                }
+            }
+            TransactionType::Redelegate => match self.redelegate_source() {
+                Ok(source) if source != self.to && !self.to.is_empty() && self.amount > 0 => {}
+                Ok(_) => {
+                    println!("Redelegate TX needs an amount and a different destination");
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
+            TransactionType::BridgeLock | TransactionType::BridgeBurn => {
+                if self.amount == 0 || !self.to.is_empty() {
+                    println!("Bridge transfer TX needs an amount and no 'to' address");
+                    return false;
+                }
+                if let Err(e) = self.bridge_transfer() {
+                    println!("{}", e);
+                    return false;
+                }
+            }
+            TransactionType::BridgeMint | TransactionType::BridgeRelease => {
+                if let Err(e) = self.bridge_inbound() {
+                    println!("{}", e);
+                    return false;
+                }
+            }
+            TransactionType::LightClientUpdate => match self.light_client_message() {
+                Ok(_) if self.amount == 0 => {}
+                Ok(_) => {
+                    println!("LightClientUpdate TX must not carry an amount");
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
+            TransactionType::RestoreAccount => match self.dust_proof() {
+                Ok(proof) if self.amount == 0 && proof.account.address == self.to => {}
+                Ok(_) => {
+                    println!("RestoreAccount TX needs no amount and the proof's address as 'to'");
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
+            TransactionType::RandaoCommit => match self.randao_commit() {
+                Ok(_) if self.amount == 0 && self.to.is_empty() => {}
+                Ok(_) => {
+                    println!("RandaoCommit TX needs no amount and no 'to' address");
+                    return false;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    return false;
+                }
+            },
         }
         true
     }
@@ -243,7 +835,18 @@ impl Transaction {
         serde_json::to_vec(self).unwrap_or_default()
     }
     pub fn total_cost(&self) -> u64 {
-        self.amount.saturating_add(self.fee)
+        match self.tx_type {
+            // The amount is an allowance, comes out of the owner's balance, or
+            // moves bonded stake rather than spendable coins. Inbound bridge
+            // amounts are credited to the recipient, not paid by the relayer.
+            TransactionType::Unstake
+            | TransactionType::Approve
+            | TransactionType::TransferFrom
+            | TransactionType::Redelegate
+            | TransactionType::BridgeMint
+            | TransactionType::BridgeRelease => self.fee,
+            _ => self.amount.saturating_add(self.fee),
+        }
     }
 }
 #[cfg(test)]
@@ -291,4 +894,59 @@ mod tests {
         assert!(tx.verify());
         assert!(tx.is_valid());
     }
+    #[test]
+    fn test_expiry_is_signed() {
+        let keypair = KeyPair::generate().unwrap();
+        let plain =
+            Transaction::new_with_fee(keypair.public_key_hex(), "bob".into(), 5, 1, 0, vec![]);
+        let mut tx = plain.clone().with_expiry(10);
+        assert_ne!(tx.hash, plain.hash);
+        assert_eq!(plain.clone().with_expiry(0).hash, plain.hash);
+        assert!(!plain.is_expired_at(u64::MAX));
+        assert!(!tx.is_expired_at(10));
+        assert!(tx.is_expired_at(11));
+
+        tx.sign(&keypair);
+        assert!(tx.verify());
+        tx.expires_at_height = 20;
+        tx.hash = tx.calculate_hash();
+        assert!(!tx.verify());
+    }
+    #[test]
+    fn test_v2_signing_hash_fixes_field_boundaries() {
+        let expiring = Transaction::new_with_fee("alice".into(), "bob".into(), 5, 1, 0, vec![7])
+            .with_expiry(0x0102);
+        // A non-expiring transaction whose `data`, `timestamp` and type byte
+        // end in the expiring one's trailing bytes.
+        let tail = [
+            &expiring.data[..],
+            &expiring.timestamp.to_le_bytes(),
+            &[expiring.type_byte()],
+            &expiring.expires_at_height.to_le_bytes(),
+        ]
+        .concat();
+        let mut shifted = expiring.clone();
+        shifted.expires_at_height = 0;
+        shifted.data = tail[..9].to_vec();
+        shifted.timestamp = u128::from_le_bytes(tail[9..25].try_into().unwrap());
+        assert_eq!(tail[25], 0);
+        assert_eq!(expiring.signing_hash(), shifted.signing_hash());
+
+        let mut v2 = expiring.clone();
+        v2.signing_version = TX_SIGNING_V2;
+        let mut v2_shifted = shifted.clone();
+        v2_shifted.signing_version = TX_SIGNING_V2;
+        assert_ne!(v2.signing_hash(), v2_shifted.signing_hash());
+        assert_ne!(v2.signing_hash(), expiring.signing_hash());
+        assert_ne!(v2.calculate_hash(), expiring.calculate_hash());
+
+        let keypair = KeyPair::generate().unwrap();
+        let mut tx =
+            Transaction::new_with_fee(keypair.public_key_hex(), "bob".into(), 5, 1, 0, vec![]);
+        tx.signing_version = TX_SIGNING_V2;
+        tx.sign(&keypair);
+        assert!(tx.verify());
+        tx.signing_version = TX_SIGNING_V2 + 1;
+        assert!(!tx.verify());
+    }
 }
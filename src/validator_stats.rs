@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+// Expected proposals are accumulated in millionths of a block so every
+// block's stake-weighted share can be added without rounding it away.
+pub const EXPECTED_BLOCK_UNITS: u64 = 1_000_000;
+
+fn ratio(part: f64, whole: f64) -> f64 {
+    if whole <= 0.0 {
+        0.0
+    } else {
+        part / whole
+    }
+}
+
+// Proposal and slash counts are derived from blocks and replay with them.
+// Checkpoint votes come from finality certificates this node accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorPerformance {
+    pub blocks_proposed: u64,
+    pub expected_units: u64,
+    pub checkpoints_signed: u64,
+    pub checkpoints_eligible: u64,
+    pub slashes: u64,
+}
+
+impl ValidatorPerformance {
+    pub fn blocks_expected(&self) -> f64 {
+        self.expected_units as f64 / EXPECTED_BLOCK_UNITS as f64
+    }
+
+    // Above 1.0 when the validator proposed more than its stake share.
+    pub fn proposal_rate(&self) -> f64 {
+        ratio(self.blocks_proposed as f64, self.blocks_expected())
+    }
+
+    pub fn uptime(&self) -> f64 {
+        ratio(
+            self.checkpoints_signed as f64,
+            self.checkpoints_eligible as f64,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidatorInfo {
+    pub address: String,
+    pub stake: u64,
+    pub delegated_stake: u64,
+    pub active: bool,
+    pub jailed: bool,
+    pub tombstoned: bool,
+    pub slashed: bool,
+    pub missed_slots: u64,
+    pub accrued_rewards: u64,
+    pub last_proposed_block: Option<u64>,
+    pub blocks_proposed: u64,
+    pub blocks_expected: f64,
+    pub proposal_rate: f64,
+    pub votes_signed: u64,
+    pub votes_expected: u64,
+    pub uptime: f64,
+    pub slashes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_performance_rates() {
+        let idle = ValidatorPerformance::default();
+        assert_eq!((idle.proposal_rate(), idle.uptime()), (0.0, 0.0));
+
+        let perf = ValidatorPerformance {
+            blocks_proposed: 3,
+            expected_units: 4 * EXPECTED_BLOCK_UNITS,
+            checkpoints_signed: 9,
+            checkpoints_eligible: 10,
+            slashes: 0,
+        };
+        assert_eq!(perf.blocks_expected(), 4.0);
+        assert_eq!(perf.proposal_rate(), 0.75);
+        assert_eq!(perf.uptime(), 0.9);
+    }
+}
@@ -0,0 +1,27 @@
+// The dev engine is built in every feature set, so this also runs under
+// `--no-default-features`.
+use budlum_core::consensus::DevEngine;
+use budlum_core::crypto::KeyPair;
+use budlum_core::{Blockchain, ChainEvent, Storage, Transaction};
+use std::sync::Arc;
+
+#[test]
+fn test_embedded_chain_through_public_api() {
+    let consensus = Arc::new(DevEngine::default());
+    let mut chain = Blockchain::new(consensus, Some(Storage::memory()), 1337, None);
+    let mut events = chain.subscribe_events();
+
+    let sender = KeyPair::generate().unwrap();
+    chain.init_genesis_account(&sender.public_key_hex());
+    let mut tx = Transaction::new_with_fee(sender.public_key_hex(), "bob".into(), 25, 1, 0, vec![]);
+    tx.sign(&sender);
+    // The dev engine seals a block as soon as a transaction arrives.
+    chain.add_transaction(tx).unwrap();
+
+    assert_eq!(chain.last_block().index, 1);
+    assert_eq!(chain.state.get_balance("bob"), 25);
+    let received: Vec<ChainEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+    assert!(received
+        .iter()
+        .any(|e| matches!(e, ChainEvent::BlockAdded { .. })));
+}